@@ -12,6 +12,17 @@ some built-in functionality.
 
 */
 
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+mod integer;
+mod json;
+mod rational;
+
+pub use integer::{parse_integer, Integer};
+pub use json::Json;
+pub use rational::Rational;
+
 // Define the type alias BigInteger based on whether the bigint feature is enabled
 #[cfg(feature = "bigint")]
 use num_bigint::BigInt;
@@ -49,3 +60,50 @@ pub fn resolve(atom: IString) -> String {
   atom.to_string()
 }
 
+
+/// Errors arising from exact arithmetic on Sail numbers.
+#[derive(Clone, Eq, PartialEq)]
+pub enum ArithmeticError {
+  /// The exact result does not fit in the active numeric backend.
+  Overflow,
+  DivisionByZero,
+  /// The operation is undefined for its argument, e.g. the square root of a negative number.
+  Domain,
+  MalformedLiteral(String),
+}
+
+impl ArithmeticError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ArithmeticError::Overflow => {
+        write!(f, "arithmetic overflow")
+      }
+
+      ArithmeticError::DivisionByZero => {
+        write!(f, "division by zero")
+      }
+
+      ArithmeticError::Domain => {
+        write!(f, "argument outside the domain of the operation")
+      }
+
+      ArithmeticError::MalformedLiteral(text) => {
+        write!(f, "malformed number literal `{}`", text)
+      }
+    }
+  }
+}
+
+impl Debug for ArithmeticError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for ArithmeticError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for ArithmeticError {}
@@ -0,0 +1,328 @@
+/*!
+
+A common API for Sail integers over both numeric backends.
+
+Sail's `int` is unbounded. With the `bigint` feature `BigInteger` is a `num_bigint::BigInt` and matches Sail exactly.
+Without it, `BigInteger` is an `i64`, and the `Integer` implementation below checks every operation, reporting
+`ArithmeticError::Overflow` where the bare machine operations would wrap or panic. Code written against the `Integer`
+trait therefore behaves identically in both configurations, up to the point where the `i64` backend runs out of range
+and says so.
+
+*/
+
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+#[cfg(feature = "bigint")]
+use num_bigint::{BigInt, Sign};
+
+use crate::abstractions::{ArithmeticError, BigInteger};
+
+/// Checked arithmetic on Sail integers. Methods are prefixed `try_` so they never collide with inherent methods of the
+/// backing type.
+pub trait Integer: Clone + Eq + Ord + Hash + Debug + Display + Sized {
+  fn from_i64(value: i64) -> Self;
+
+  /// Narrows to an `i64`, if the value fits.
+  fn to_i64(&self) -> Option<i64>;
+
+  fn is_zero(&self) -> bool;
+
+  fn is_negative(&self) -> bool;
+
+  /// Parses an optionally negative integer in the given radix, which must be between 2 and 36.
+  fn parse_radix(text: &str, radix: u32) -> Result<Self, ArithmeticError>;
+
+  fn try_add(&self, other: &Self) -> Result<Self, ArithmeticError>;
+
+  fn try_sub(&self, other: &Self) -> Result<Self, ArithmeticError>;
+
+  fn try_mul(&self, other: &Self) -> Result<Self, ArithmeticError>;
+
+  fn try_neg(&self) -> Result<Self, ArithmeticError>;
+
+  /// Sail's `tdiv_int`: division rounding toward zero.
+  fn try_tdiv(&self, other: &Self) -> Result<Self, ArithmeticError>;
+
+  /// Sail's `tmod_int`: the remainder of `try_tdiv`, with the sign of the dividend.
+  fn try_tmod(&self, other: &Self) -> Result<Self, ArithmeticError>;
+
+  /// Sail's `pow`.
+  fn try_pow(&self, exponent: u32) -> Result<Self, ArithmeticError>;
+
+  /// Sail's `shl_int`.
+  fn try_shl(&self, shift: u32) -> Result<Self, ArithmeticError>;
+
+  /// Sail's `shr_int`, an arithmetic shift rounding toward negative infinity.
+  fn try_shr(&self, shift: u32) -> Result<Self, ArithmeticError>;
+
+  // region Provided methods
+
+  /// Parses a decimal Sail integer literal.
+  fn parse(text: &str) -> Result<Self, ArithmeticError> {
+    Self::parse_radix(text, 10)
+  }
+
+  /// Sail's `abs_int`.
+  fn try_abs(&self) -> Result<Self, ArithmeticError> {
+    match self.is_negative() {
+      true  => self.try_neg(),
+      false => Ok(self.clone()),
+    }
+  }
+
+  /// Sail's `ediv_int`: Euclidean division, whose remainder is never negative.
+  fn try_ediv(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    let quotient = self.try_tdiv(other)?;
+    match self.try_tmod(other)?.is_negative() {
+      true if other.is_negative() => quotient.try_add(&Self::from_i64(1)),
+      true                        => quotient.try_sub(&Self::from_i64(1)),
+      false                       => Ok(quotient),
+    }
+  }
+
+  /// Sail's `emod_int`: the non-negative remainder of `try_ediv`.
+  fn try_emod(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    let remainder = self.try_tmod(other)?;
+    match remainder.is_negative() {
+      true  => remainder.try_add(&other.try_abs()?),
+      false => Ok(remainder),
+    }
+  }
+
+  /// Sail's `pow2`.
+  fn try_pow2(exponent: u32) -> Result<Self, ArithmeticError> {
+    Self::from_i64(1).try_shl(exponent)
+  }
+
+  /// Narrows to a `u32`, as needed for shift amounts, exponents and vector widths.
+  fn try_to_u32(&self) -> Result<u32, ArithmeticError> {
+    self.to_i64()
+        .and_then(|value| u32::try_from(value).ok())
+        .ok_or(ArithmeticError::Overflow)
+  }
+
+  /// Narrows to a `usize`, as needed for indexing.
+  fn try_to_usize(&self) -> Result<usize, ArithmeticError> {
+    self.to_i64()
+        .and_then(|value| usize::try_from(value).ok())
+        .ok_or(ArithmeticError::Overflow)
+  }
+
+  // endregion
+}
+
+/// Validates the shape of an integer literal, returning the sign and the digits. A radix outside `2..=36`, which
+/// `char::is_digit` and the parsers would panic on, makes every literal malformed.
+fn split_literal(text: &str, radix: u32) -> Result<(bool, &str), ArithmeticError> {
+  if !(2..=36).contains(&radix) {
+    return Err(ArithmeticError::MalformedLiteral(text.to_string()));
+  }
+  let (negative, digits) = match text.strip_prefix('-') {
+    Some(rest) => (true, rest),
+    None       => (false, text),
+  };
+  if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+    return Err(ArithmeticError::MalformedLiteral(text.to_string()));
+  }
+  Ok((negative, digits))
+}
+
+impl Integer for i64 {
+  fn from_i64(value: i64) -> Self {
+    value
+  }
+
+  fn to_i64(&self) -> Option<i64> {
+    Some(*self)
+  }
+
+  fn is_zero(&self) -> bool {
+    *self == 0
+  }
+
+  fn is_negative(&self) -> bool {
+    *self < 0
+  }
+
+  fn parse_radix(text: &str, radix: u32) -> Result<Self, ArithmeticError> {
+    split_literal(text, radix)?;
+    // The shape is valid, so the only way `from_str_radix` can fail now is by leaving the range of `i64`.
+    i64::from_str_radix(text, radix).map_err(|_| ArithmeticError::Overflow)
+  }
+
+  fn try_add(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    self.checked_add(*other).ok_or(ArithmeticError::Overflow)
+  }
+
+  fn try_sub(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    self.checked_sub(*other).ok_or(ArithmeticError::Overflow)
+  }
+
+  fn try_mul(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    self.checked_mul(*other).ok_or(ArithmeticError::Overflow)
+  }
+
+  fn try_neg(&self) -> Result<Self, ArithmeticError> {
+    self.checked_neg().ok_or(ArithmeticError::Overflow)
+  }
+
+  fn try_tdiv(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    if *other == 0 {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    self.checked_div(*other).ok_or(ArithmeticError::Overflow)
+  }
+
+  fn try_tmod(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    if *other == 0 {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    // `i64::MIN % -1` overflows in the machine instruction, but the mathematical result is simply zero.
+    Ok(self.checked_rem(*other).unwrap_or(0))
+  }
+
+  fn try_pow(&self, exponent: u32) -> Result<Self, ArithmeticError> {
+    self.checked_pow(exponent).ok_or(ArithmeticError::Overflow)
+  }
+
+  fn try_shl(&self, shift: u32) -> Result<Self, ArithmeticError> {
+    if *self == 0 {
+      return Ok(0);
+    }
+    // The shift is lossless exactly when shifting back recovers the original value.
+    match self.checked_shl(shift) {
+      Some(shifted) if shifted >> shift == *self => Ok(shifted),
+      _ => Err(ArithmeticError::Overflow),
+    }
+  }
+
+  fn try_shr(&self, shift: u32) -> Result<Self, ArithmeticError> {
+    // Shifting out every bit leaves only the sign.
+    Ok(self.checked_shr(shift).unwrap_or(if *self < 0 { -1 } else { 0 }))
+  }
+}
+
+#[cfg(feature = "bigint")]
+impl Integer for BigInt {
+  fn from_i64(value: i64) -> Self {
+    BigInt::from(value)
+  }
+
+  fn to_i64(&self) -> Option<i64> {
+    i64::try_from(self).ok()
+  }
+
+  fn is_zero(&self) -> bool {
+    self.sign() == Sign::NoSign
+  }
+
+  fn is_negative(&self) -> bool {
+    self.sign() == Sign::Minus
+  }
+
+  fn parse_radix(text: &str, radix: u32) -> Result<Self, ArithmeticError> {
+    let (negative, digits) = split_literal(text, radix)?;
+    let magnitude = BigInt::parse_bytes(digits.as_bytes(), radix)
+        .ok_or_else(|| ArithmeticError::MalformedLiteral(text.to_string()))?;
+    Ok(if negative { -magnitude } else { magnitude })
+  }
+
+  fn try_add(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    Ok(self + other)
+  }
+
+  fn try_sub(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    Ok(self - other)
+  }
+
+  fn try_mul(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    Ok(self * other)
+  }
+
+  fn try_neg(&self) -> Result<Self, ArithmeticError> {
+    Ok(-self)
+  }
+
+  fn try_tdiv(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    if other.is_zero() {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    Ok(self / other)
+  }
+
+  fn try_tmod(&self, other: &Self) -> Result<Self, ArithmeticError> {
+    if other.is_zero() {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    Ok(self % other)
+  }
+
+  fn try_pow(&self, exponent: u32) -> Result<Self, ArithmeticError> {
+    Ok(self.pow(exponent))
+  }
+
+  fn try_shl(&self, shift: u32) -> Result<Self, ArithmeticError> {
+    Ok(self << shift as usize)
+  }
+
+  fn try_shr(&self, shift: u32) -> Result<Self, ArithmeticError> {
+    // `BigInt`'s right shift rounds toward negative infinity, matching the two's complement behavior of `i64`.
+    Ok(self >> shift as usize)
+  }
+}
+
+/// Parses a decimal integer literal into the active backend's `BigInteger`.
+pub fn parse_integer(text: &str) -> Result<BigInteger, ArithmeticError> {
+  BigInteger::parse(text)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn int(value: i64) -> BigInteger {
+    BigInteger::from_i64(value)
+  }
+
+  #[test]
+  fn division_follows_sail_conventions() {
+    assert_eq!(int(-7).try_tdiv(&int(2)), Ok(int(-3)));
+    assert_eq!(int(-7).try_tmod(&int(2)), Ok(int(-1)));
+    assert_eq!(int(-7).try_ediv(&int(2)), Ok(int(-4)));
+    assert_eq!(int(-7).try_emod(&int(2)), Ok(int(1)));
+    assert_eq!(int(-7).try_ediv(&int(-2)), Ok(int(4)));
+    assert_eq!(int(-7).try_emod(&int(-2)), Ok(int(1)));
+    assert_eq!(int(1).try_tdiv(&int(0)), Err(ArithmeticError::DivisionByZero));
+  }
+
+  #[test]
+  fn shifts_and_powers() {
+    assert_eq!(int(-5).try_shr(1), Ok(int(-3)));
+    assert_eq!(int(3).try_shl(4), Ok(int(48)));
+    assert_eq!(BigInteger::try_pow2(10), Ok(int(1024)));
+    assert_eq!(int(-2).try_pow(3), Ok(int(-8)));
+  }
+
+  #[test]
+  fn parses_literals() {
+    assert_eq!(parse_integer("-42"), Ok(int(-42)));
+    assert_eq!(BigInteger::parse_radix("ff", 16), Ok(int(255)));
+    assert!(parse_integer("4_2").is_err());
+    assert!(parse_integer("-").is_err());
+    assert_eq!(BigInteger::parse_radix("1", 1), Err(ArithmeticError::MalformedLiteral("1".to_string())));
+    assert_eq!(BigInteger::parse_radix("1", 37), Err(ArithmeticError::MalformedLiteral("1".to_string())));
+    assert_eq!(BigInteger::parse_radix("z", 36), Ok(int(35)));
+  }
+
+  #[cfg(not(feature = "bigint"))]
+  #[test]
+  fn i64_backend_reports_overflow() {
+    assert_eq!(i64::MAX.try_add(&1), Err(ArithmeticError::Overflow));
+    assert_eq!(i64::MIN.try_neg(), Err(ArithmeticError::Overflow));
+    assert_eq!(i64::try_pow2(63), Err(ArithmeticError::Overflow));
+    assert_eq!(3i64.try_shl(62), Err(ArithmeticError::Overflow));
+    assert_eq!(parse_integer("9223372036854775808"), Err(ArithmeticError::Overflow));
+    assert_eq!(i64::MIN.try_tmod(&-1), Ok(0));
+  }
+}
@@ -0,0 +1,342 @@
+/*!
+
+A small JSON value type, enough for the debug adapter protocol and for traces, with a parser and a compact writer.
+
+Numbers are kept as integers when they are written as integers, so that values up to `i64::MAX` survive a round trip
+exactly. Objects keep their members in the order they were written or inserted.
+
+*/
+
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Integer(i64),
+  Float(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  /// An object with the given members.
+  pub fn object<K: Into<String>>(members: impl IntoIterator<Item = (K, Json)>) -> Json {
+    Json::Object(members.into_iter().map(|(key, value)| (key.into(), value)).collect())
+  }
+
+  /// The member `key` of an object.
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+      _ => None,
+    }
+  }
+
+  /// Sets the member `key` of an object, replacing any it has.
+  pub fn insert(&mut self, key: &str, value: Json) {
+    if let Json::Object(members) = self {
+      match members.iter_mut().find(|(name, _)| name == key) {
+        Some((_, current)) => *current = value,
+        None => members.push((key.to_string(), value)),
+      }
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::String(text) => Some(text),
+      _ => None,
+    }
+  }
+
+  pub fn as_i64(&self) -> Option<i64> {
+    match self {
+      Json::Integer(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Json::Bool(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&[Json]> {
+    match self {
+      Json::Array(elements) => Some(elements),
+      _ => None,
+    }
+  }
+
+  /// Parses a complete JSON text.
+  pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { text: text.as_bytes(), position: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    match parser.position == parser.text.len() {
+      true => Ok(value),
+      false => Err(parser.error("trailing characters")),
+    }
+  }
+}
+
+impl From<&str> for Json {
+  fn from(text: &str) -> Self {
+    Json::String(text.to_string())
+  }
+}
+
+impl From<String> for Json {
+  fn from(text: String) -> Self {
+    Json::String(text)
+  }
+}
+
+impl From<bool> for Json {
+  fn from(value: bool) -> Self {
+    Json::Bool(value)
+  }
+}
+
+impl From<i64> for Json {
+  fn from(value: i64) -> Self {
+    Json::Integer(value)
+  }
+}
+
+impl From<usize> for Json {
+  fn from(value: usize) -> Self {
+    Json::Integer(value as i64)
+  }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+  fn from(elements: Vec<T>) -> Self {
+    Json::Array(elements.into_iter().map(Into::into).collect())
+  }
+}
+
+/// Writes the value compactly, on one line.
+impl Display for Json {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Json::Null => write!(f, "null"),
+      Json::Bool(value) => write!(f, "{}", value),
+      Json::Integer(value) => write!(f, "{}", value),
+      Json::Float(value) if value.is_finite() => write!(f, "{}", value),
+      Json::Float(_) => write!(f, "null"),
+      Json::String(text) => write_string(f, text),
+      Json::Array(elements) => {
+        write!(f, "[")?;
+        for (index, element) in elements.iter().enumerate() {
+          if index > 0 {
+            write!(f, ",")?;
+          }
+          write!(f, "{}", element)?;
+        }
+        write!(f, "]")
+      }
+      Json::Object(members) => {
+        write!(f, "{{")?;
+        for (index, (key, value)) in members.iter().enumerate() {
+          if index > 0 {
+            write!(f, ",")?;
+          }
+          write_string(f, key)?;
+          write!(f, ":{}", value)?;
+        }
+        write!(f, "}}")
+      }
+    }
+  }
+}
+
+fn write_string(f: &mut Formatter<'_>, text: &str) -> std::fmt::Result {
+  write!(f, "\"")?;
+  for character in text.chars() {
+    match character {
+      '"' => write!(f, "\\\"")?,
+      '\\' => write!(f, "\\\\")?,
+      '\n' => write!(f, "\\n")?,
+      '\r' => write!(f, "\\r")?,
+      '\t' => write!(f, "\\t")?,
+      character if (character as u32) < 0x20 => write!(f, "\\u{:04x}", character as u32)?,
+      character => write!(f, "{}", character)?,
+    }
+  }
+  write!(f, "\"")
+}
+
+struct Parser<'t> {
+  text    : &'t [u8],
+  position: usize,
+}
+
+impl Parser<'_> {
+  fn error(&self, message: &str) -> String {
+    format!("{} at offset {}", message, self.position)
+  }
+
+  fn whitespace(&mut self) {
+    while self.text.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+      self.position += 1;
+    }
+  }
+
+  fn expect(&mut self, expected: &str) -> Result<(), String> {
+    match self.text[self.position..].starts_with(expected.as_bytes()) {
+      true => {
+        self.position += expected.len();
+        Ok(())
+      }
+      false => Err(self.error(&format!("expected `{}`", expected))),
+    }
+  }
+
+  fn value(&mut self) -> Result<Json, String> {
+    self.whitespace();
+    match self.text.get(self.position) {
+      Some(b'n') => self.expect("null").map(|_| Json::Null),
+      Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+      Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+      Some(b'"') => self.string().map(Json::String),
+      Some(b'[') => {
+        self.position += 1;
+        let mut elements = Vec::new();
+        self.whitespace();
+        if self.text.get(self.position) == Some(&b']') {
+          self.position += 1;
+          return Ok(Json::Array(elements));
+        }
+        loop {
+          elements.push(self.value()?);
+          self.whitespace();
+          match self.text.get(self.position) {
+            Some(b',') => self.position += 1,
+            Some(b']') => {
+              self.position += 1;
+              return Ok(Json::Array(elements));
+            }
+            _ => return Err(self.error("expected `,` or `]`")),
+          }
+        }
+      }
+      Some(b'{') => {
+        self.position += 1;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.text.get(self.position) == Some(&b'}') {
+          self.position += 1;
+          return Ok(Json::Object(members));
+        }
+        loop {
+          self.whitespace();
+          let key = self.string()?;
+          self.whitespace();
+          self.expect(":")?;
+          members.push((key, self.value()?));
+          self.whitespace();
+          match self.text.get(self.position) {
+            Some(b',') => self.position += 1,
+            Some(b'}') => {
+              self.position += 1;
+              return Ok(Json::Object(members));
+            }
+            _ => return Err(self.error("expected `,` or `}`")),
+          }
+        }
+      }
+      Some(b'-' | b'0'..=b'9') => self.number(),
+      _ => Err(self.error("expected a value")),
+    }
+  }
+
+  fn number(&mut self) -> Result<Json, String> {
+    let start = self.position;
+    while self.text.get(self.position).is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+      self.position += 1;
+    }
+    let text = std::str::from_utf8(&self.text[start..self.position]).map_err(|_| self.error("malformed number"))?;
+    if let Ok(value) = text.parse::<i64>() {
+      return Ok(Json::Integer(value));
+    }
+    text.parse::<f64>().map(Json::Float).map_err(|_| self.error("malformed number"))
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    self.expect("\"")?;
+    let mut bytes = Vec::new();
+    loop {
+      let Some(&byte) = self.text.get(self.position) else {
+        return Err(self.error("unterminated string"));
+      };
+      self.position += 1;
+      match byte {
+        b'"' => break,
+        b'\\' => {
+          let Some(&escape) = self.text.get(self.position) else {
+            return Err(self.error("unterminated string"));
+          };
+          self.position += 1;
+          let character = match escape {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => self.unicode_escape()?,
+            _ => return Err(self.error("unknown escape")),
+          };
+          bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        byte => bytes.push(byte),
+      }
+    }
+    String::from_utf8(bytes).map_err(|_| self.error("a string that is not UTF-8"))
+  }
+
+  /// The character after `\u`, which may be the first of a surrogate pair.
+  fn unicode_escape(&mut self) -> Result<char, String> {
+    let first = self.hex4()?;
+    let code = match first {
+      0xd800..=0xdbff => {
+        self.expect("\\u")?;
+        let second = self.hex4()?;
+        0x10000 + ((first - 0xd800) << 10) + (second.wrapping_sub(0xdc00) & 0x3ff)
+      }
+      _ => first,
+    };
+    char::from_u32(code).ok_or_else(|| self.error("invalid character"))
+  }
+
+  fn hex4(&mut self) -> Result<u32, String> {
+    let digits = self.text.get(self.position..self.position + 4).ok_or_else(|| self.error("short escape"))?;
+    let value = std::str::from_utf8(digits).ok().and_then(|digits| u32::from_str_radix(digits, 16).ok());
+    self.position += 4;
+    value.ok_or_else(|| self.error("malformed escape"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips() {
+    let text = r#"{"seq":1,"type":"request","arguments":{"lines":[3,-4],"path":"a \"b\"\n","x":1.5,"ok":true,"none":null}}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(value.get("seq").and_then(Json::as_i64), Some(1));
+    assert_eq!(value.get("arguments").and_then(|arguments| arguments.get("path")).and_then(Json::as_str), Some("a \"b\"\n"));
+    assert_eq!(value.to_string(), text);
+    assert_eq!(Json::parse(r#""\u00e9\ud83d\ude00""#).unwrap(), Json::from("é😀"));
+    assert!(Json::parse("[1,").is_err());
+    assert!(Json::parse("{} x").is_err());
+  }
+}
@@ -0,0 +1,504 @@
+/*!
+
+Exact rational numbers for Sail's `real` type.
+
+Sail's `real` is not floating point; it is exact rational arithmetic. With the `bigint` feature enabled a `Rational` is
+a `num_rational::BigRational` and every operation except division by zero succeeds. Without it, a `Rational` is a
+reduced pair of `i64`s, and any operation whose exact result does not fit reports `ArithmeticError::Overflow` instead of
+rounding or wrapping. Both backends expose the same API, so callers never need to know which one is active.
+
+The methods named after Sail builtins (`to_real`, `floor`, `ceil`, `sqrt_real`, ...) implement the corresponding
+functions of Sail's `real.sail` library.
+
+*/
+
+#[cfg(not(feature = "bigint"))]
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[cfg(feature = "bigint")]
+use num_bigint::{BigInt, Sign};
+#[cfg(feature = "bigint")]
+use num_rational::BigRational;
+
+use crate::abstractions::{ArithmeticError, BigInteger};
+
+/// Number of bits of precision `sqrt_real` computes. The `i64` backend has much less headroom.
+#[cfg(feature = "bigint")]
+pub const SQRT_PRECISION_BITS: u32 = 64;
+#[cfg(not(feature = "bigint"))]
+pub const SQRT_PRECISION_BITS: u32 = 30;
+
+/// An exact rational number, always kept in lowest terms with a positive denominator.
+#[cfg(feature = "bigint")]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rational(BigRational);
+
+/// An exact rational number, always kept in lowest terms with a positive denominator.
+#[cfg(not(feature = "bigint"))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rational {
+  numerator  : i64,
+  denominator: i64,
+}
+
+// region Backend: BigRational
+
+#[cfg(feature = "bigint")]
+impl Rational {
+  pub fn new(numerator: BigInteger, denominator: BigInteger) -> Result<Rational, ArithmeticError> {
+    if denominator.sign() == Sign::NoSign {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    Ok(Rational(BigRational::new(numerator, denominator)))
+  }
+
+  /// Sail's `to_real`.
+  pub fn from_integer(value: BigInteger) -> Rational {
+    Rational(BigRational::from_integer(value))
+  }
+
+  pub fn numerator(&self) -> BigInteger {
+    self.0.numer().clone()
+  }
+
+  pub fn denominator(&self) -> BigInteger {
+    self.0.denom().clone()
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.0.numer().sign() == Sign::NoSign
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.0.numer().sign() == Sign::Minus
+  }
+
+  pub fn is_integer(&self) -> bool {
+    self.0.is_integer()
+  }
+
+  /// Sail's `add_real`.
+  pub fn checked_add(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    Ok(Rational(&self.0 + &other.0))
+  }
+
+  /// Sail's `sub_real`.
+  pub fn checked_sub(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    Ok(Rational(&self.0 - &other.0))
+  }
+
+  /// Sail's `mult_real`.
+  pub fn checked_mul(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    Ok(Rational(&self.0 * &other.0))
+  }
+
+  /// Sail's `div_real`.
+  pub fn checked_div(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    if other.is_zero() {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    Ok(Rational(&self.0 / &other.0))
+  }
+
+  /// Sail's `neg_real`.
+  pub fn checked_neg(&self) -> Result<Rational, ArithmeticError> {
+    Ok(Rational(-&self.0))
+  }
+
+  /// Sail's `floor`.
+  pub fn floor(&self) -> BigInteger {
+    self.0.floor().to_integer()
+  }
+
+  /// Sail's `ceil`.
+  pub fn ceil(&self) -> BigInteger {
+    self.0.ceil().to_integer()
+  }
+
+  /// Sail's `sqrt_real`, truncated to `precision_bits` fractional bits.
+  pub fn sqrt(&self, precision_bits: u32) -> Result<Rational, ArithmeticError> {
+    if self.is_negative() {
+      return Err(ArithmeticError::Domain);
+    }
+    let shift = 2 * precision_bits as usize;
+    let scaled = (self.0.numer() << shift) / self.0.denom();
+    let root = scaled.sqrt();
+    Rational::new(root, BigInt::from(1) << precision_bits as usize)
+  }
+
+  /// Builds `digits / 10^scale`, negated if `negative`. The digits have already been validated.
+  fn from_decimal_digits(negative: bool, digits: &str, scale: usize) -> Result<Rational, ArithmeticError> {
+    let magnitude = BigInt::parse_bytes(digits.as_bytes(), 10)
+        .ok_or_else(|| ArithmeticError::MalformedLiteral(digits.to_string()))?;
+    let numerator = if negative { -magnitude } else { magnitude };
+    Rational::new(numerator, BigInt::from(10).pow(scale as u32))
+  }
+}
+
+// endregion
+
+// region Backend: checked i64
+
+#[cfg(not(feature = "bigint"))]
+impl Rational {
+  pub fn new(numerator: BigInteger, denominator: BigInteger) -> Result<Rational, ArithmeticError> {
+    Rational::reduced(numerator as i128, denominator as i128)
+  }
+
+  /// Sail's `to_real`.
+  pub fn from_integer(value: BigInteger) -> Rational {
+    Rational { numerator: value, denominator: 1 }
+  }
+
+  pub fn numerator(&self) -> BigInteger {
+    self.numerator
+  }
+
+  pub fn denominator(&self) -> BigInteger {
+    self.denominator
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.numerator == 0
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.numerator < 0
+  }
+
+  pub fn is_integer(&self) -> bool {
+    self.denominator == 1
+  }
+
+  /// Sail's `add_real`.
+  pub fn checked_add(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    let (a, b, c, d) = self.wide(other);
+    Rational::reduced(a * d + c * b, b * d)
+  }
+
+  /// Sail's `sub_real`.
+  pub fn checked_sub(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    let (a, b, c, d) = self.wide(other);
+    Rational::reduced(a * d - c * b, b * d)
+  }
+
+  /// Sail's `mult_real`.
+  pub fn checked_mul(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    let (a, b, c, d) = self.wide(other);
+    Rational::reduced(a * c, b * d)
+  }
+
+  /// Sail's `div_real`.
+  pub fn checked_div(&self, other: &Rational) -> Result<Rational, ArithmeticError> {
+    let (a, b, c, d) = self.wide(other);
+    Rational::reduced(a * d, b * c)
+  }
+
+  /// Sail's `neg_real`.
+  pub fn checked_neg(&self) -> Result<Rational, ArithmeticError> {
+    Rational::reduced(-(self.numerator as i128), self.denominator as i128)
+  }
+
+  /// Sail's `floor`.
+  pub fn floor(&self) -> BigInteger {
+    // The denominator is positive, so Euclidean division rounds toward negative infinity, and the quotient of an
+    // `i64` by a positive `i64` always fits.
+    self.numerator.div_euclid(self.denominator)
+  }
+
+  /// Sail's `ceil`.
+  pub fn ceil(&self) -> BigInteger {
+    let quotient = (self.numerator as i128).div_euclid(self.denominator as i128);
+    let remainder = (self.numerator as i128).rem_euclid(self.denominator as i128);
+    (quotient + (remainder != 0) as i128) as i64
+  }
+
+  /// Sail's `sqrt_real`, truncated to `precision_bits` fractional bits.
+  pub fn sqrt(&self, precision_bits: u32) -> Result<Rational, ArithmeticError> {
+    if self.is_negative() {
+      return Err(ArithmeticError::Domain);
+    }
+    let shift = 2 * precision_bits;
+    let numerator = self.numerator as u128;
+    // Keep one bit of headroom so the shifted numerator cannot lose high bits.
+    if shift >= 127 || numerator.leading_zeros() <= shift {
+      return Err(ArithmeticError::Overflow);
+    }
+    let root = integer_sqrt((numerator << shift) / self.denominator as u128);
+    Rational::reduced(root as i128, 1i128 << precision_bits)
+  }
+
+  /// Builds `digits / 10^scale`, negated if `negative`. The digits have already been validated.
+  fn from_decimal_digits(negative: bool, digits: &str, scale: usize) -> Result<Rational, ArithmeticError> {
+    // Leading zeros are common in fractional parts and carry no magnitude, and trailing ones only scale it, so both
+    // go before either part is computed; `1.50000000000000000000000000000000000000` is as small as `1.5`.
+    let trimmed = digits.trim_start_matches('0');
+    if trimmed.is_empty() {
+      return Ok(Rational::zero());
+    }
+    let significant = trimmed.trim_end_matches('0');
+    let dropped = (trimmed.len() - significant.len()).min(scale);
+    let mut magnitude: i128 = trimmed[..trimmed.len() - dropped].parse().map_err(|_| ArithmeticError::Overflow)?;
+    // The denominator is `2^twos * 5^fives`; dividing common factors out first keeps it as small as it can be.
+    let (mut twos, mut fives) = (scale - dropped, scale - dropped);
+    while twos > 0 && magnitude % 2 == 0 {
+      magnitude /= 2;
+      twos -= 1;
+    }
+    while fives > 0 && magnitude % 5 == 0 {
+      magnitude /= 5;
+      fives -= 1;
+    }
+    let power =
+      |base: i128, exponent: usize| u32::try_from(exponent).ok().and_then(|exponent| base.checked_pow(exponent));
+    let denominator = power(2, twos)
+        .zip(power(5, fives))
+        .and_then(|(twos, fives)| twos.checked_mul(fives))
+        .ok_or(ArithmeticError::Overflow)?;
+    Rational::reduced(if negative { -magnitude } else { magnitude }, denominator)
+  }
+
+  /// Widens both operands to `i128`, in which every product of two `i64` values is exact.
+  fn wide(&self, other: &Rational) -> (i128, i128, i128, i128) {
+    (
+      self.numerator as i128,
+      self.denominator as i128,
+      other.numerator as i128,
+      other.denominator as i128,
+    )
+  }
+
+  /// Normalizes `numerator / denominator` and narrows it back to `i64`, reporting overflow if it does not fit.
+  fn reduced(mut numerator: i128, mut denominator: i128) -> Result<Rational, ArithmeticError> {
+    if denominator == 0 {
+      return Err(ArithmeticError::DivisionByZero);
+    }
+    if denominator < 0 {
+      numerator = -numerator;
+      denominator = -denominator;
+    }
+    let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs()) as i128;
+    if divisor > 1 {
+      numerator /= divisor;
+      denominator /= divisor;
+    }
+    match (i64::try_from(numerator), i64::try_from(denominator)) {
+      (Ok(numerator), Ok(denominator)) => Ok(Rational { numerator, denominator }),
+      _ => Err(ArithmeticError::Overflow),
+    }
+  }
+}
+
+#[cfg(not(feature = "bigint"))]
+impl PartialOrd for Rational {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+#[cfg(not(feature = "bigint"))]
+impl Ord for Rational {
+  fn cmp(&self, other: &Self) -> Ordering {
+    // Denominators are positive, so cross-multiplying preserves the order.
+    let (a, b, c, d) = self.wide(other);
+    (a * d).cmp(&(c * b))
+  }
+}
+
+#[cfg(not(feature = "bigint"))]
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+  while b != 0 {
+    (a, b) = (b, a % b);
+  }
+  a.max(1)
+}
+
+#[cfg(not(feature = "bigint"))]
+fn integer_sqrt(n: u128) -> u128 {
+  if n < 2 {
+    return n;
+  }
+  // Newton's method from an overestimate decreases monotonically to the floor of the root.
+  let mut x = 1u128 << ((128 - n.leading_zeros()) / 2 + 1);
+  loop {
+    let y = (x + n / x) / 2;
+    if y >= x {
+      return x;
+    }
+    x = y;
+  }
+}
+
+// endregion
+
+// region Backend-independent API
+
+impl Rational {
+  pub fn zero() -> Rational {
+    Rational::from_integer(0.into())
+  }
+
+  pub fn one() -> Rational {
+    Rational::from_integer(1.into())
+  }
+
+  /// Parses a Sail real literal such as `3.25` or `-0.5`. A bare integer is also accepted.
+  pub fn parse(text: &str) -> Result<Rational, ArithmeticError> {
+    let malformed = || ArithmeticError::MalformedLiteral(text.to_string());
+    let (negative, unsigned) = match text.strip_prefix('-') {
+      Some(rest) => (true, rest),
+      None       => (false, text),
+    };
+    let (whole, fraction) = match unsigned.split_once('.') {
+      Some((whole, fraction)) => (whole, fraction),
+      None                    => (unsigned, ""),
+    };
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction)
+        || (unsigned.contains('.') && fraction.is_empty())
+    {
+      return Err(malformed());
+    }
+    Rational::from_decimal_digits(negative, &format!("{}{}", whole, fraction), fraction.len())
+  }
+
+  /// Sail's `abs_real`.
+  pub fn abs(&self) -> Result<Rational, ArithmeticError> {
+    match self.is_negative() {
+      true  => self.checked_neg(),
+      false => Ok(self.clone()),
+    }
+  }
+
+  /// Sail's `real_power`. Negative exponents take the reciprocal.
+  pub fn pow(&self, exponent: i64) -> Result<Rational, ArithmeticError> {
+    let mut base = match exponent < 0 {
+      true  => Rational::one().checked_div(self)?,
+      false => self.clone(),
+    };
+    let mut remaining = exponent.unsigned_abs();
+    let mut result = Rational::one();
+    while remaining > 0 {
+      if remaining & 1 == 1 {
+        result = result.checked_mul(&base)?;
+      }
+      remaining >>= 1;
+      if remaining > 0 {
+        base = base.checked_mul(&base)?;
+      }
+    }
+    Ok(result)
+  }
+
+  /// Sail's `sqrt_real` at the default precision for the active backend.
+  pub fn sqrt_real(&self) -> Result<Rational, ArithmeticError> {
+    self.sqrt(SQRT_PRECISION_BITS)
+  }
+
+  /// Sail's `eq_real`.
+  pub fn eq_real(&self, other: &Rational) -> bool {
+    self == other
+  }
+
+  /// Sail's `lt_real`.
+  pub fn lt_real(&self, other: &Rational) -> bool {
+    self < other
+  }
+
+  /// Sail's `gt_real`.
+  pub fn gt_real(&self, other: &Rational) -> bool {
+    self > other
+  }
+
+  /// Sail's `lteq_real`.
+  pub fn lteq_real(&self, other: &Rational) -> bool {
+    self <= other
+  }
+
+  /// Sail's `gteq_real`.
+  pub fn gteq_real(&self, other: &Rational) -> bool {
+    self >= other
+  }
+}
+
+impl FromStr for Rational {
+  type Err = ArithmeticError;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    Rational::parse(text)
+  }
+}
+
+impl Display for Rational {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self.is_integer() {
+      true  => write!(f, "{}", self.numerator()),
+      false => write!(f, "{}/{}", self.numerator(), self.denominator()),
+    }
+  }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn real(text: &str) -> Rational {
+    Rational::parse(text).unwrap()
+  }
+
+  #[test]
+  fn parses_sail_real_literals() {
+    assert_eq!(real("3.25"), Rational::new(13.into(), 4.into()).unwrap());
+    assert_eq!(real("-0.5"), Rational::new((-1).into(), 2.into()).unwrap());
+    assert_eq!(real("0.000"), Rational::zero());
+    assert_eq!(real("7"), Rational::from_integer(7.into()));
+    assert!(Rational::parse("1.").is_err());
+    assert!(Rational::parse(".5").is_err());
+    assert!(Rational::parse("1.2.3").is_err());
+  }
+
+  #[test]
+  fn long_literals_reduce_before_scaling() {
+    assert_eq!(real(&format!("0.{}", "0".repeat(40))), Rational::zero());
+    assert_eq!(real(&format!("1.5{}", "0".repeat(40))), real("1.5"));
+    assert_eq!(real(&format!("-2{}.{}", "0".repeat(3), "0".repeat(40))), Rational::from_integer((-2000).into()));
+    assert_eq!(real(&format!("0.{:039}", 5u128.pow(39))), real("2").pow(-39).unwrap());
+  }
+
+  #[test]
+  fn floor_and_ceil_round_toward_infinities() {
+    assert_eq!(real("2.5").floor(), 2.into());
+    assert_eq!(real("2.5").ceil(), 3.into());
+    assert_eq!(real("-2.5").floor(), (-3).into());
+    assert_eq!(real("-2.5").ceil(), (-2).into());
+    assert_eq!(real("4").ceil(), 4.into());
+  }
+
+  #[test]
+  fn arithmetic_is_exact() {
+    let third = Rational::one().checked_div(&Rational::from_integer(3.into())).unwrap();
+    let sum = third.checked_add(&third).unwrap().checked_add(&third).unwrap();
+    assert_eq!(sum, Rational::one());
+    assert_eq!(real("0.5").pow(-3).unwrap(), Rational::from_integer(8.into()));
+    assert!(real("0.1").lt_real(&real("0.25")));
+    assert_eq!(Rational::one().checked_div(&Rational::zero()), Err(ArithmeticError::DivisionByZero));
+  }
+
+  #[test]
+  fn square_roots_of_perfect_squares_are_exact() {
+    assert_eq!(real("6.25").sqrt_real().unwrap(), real("2.5"));
+    assert_eq!(real("-1").sqrt_real(), Err(ArithmeticError::Domain));
+  }
+
+  #[cfg(not(feature = "bigint"))]
+  #[test]
+  fn i64_backend_reports_overflow() {
+    let big = Rational::from_integer(i64::MAX);
+    assert_eq!(big.checked_add(&Rational::one()), Err(ArithmeticError::Overflow));
+    assert_eq!(real("2").pow(64), Err(ArithmeticError::Overflow));
+  }
+}
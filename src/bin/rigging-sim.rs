@@ -0,0 +1,11 @@
+//! The `rigging-sim` instruction-level simulator. See `rigging::simulator::cli`.
+//!
+//! This crate does not have a Sail front end yet, so the binary stops before loading anything. A build with one passes
+//! its parser to `cli::main`.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+  eprintln!("rigging-sim: this build has no Sail front end, so it cannot load a model");
+  ExitCode::FAILURE
+}
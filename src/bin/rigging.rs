@@ -0,0 +1,39 @@
+//! The `rigging` command, whose subcommands are the crate's interactive tools. See `rigging::repl::cli` and
+//! `rigging::simulator::trace`.
+//!
+//! This crate does not have a Sail front end yet, so `repl` stops before loading anything. A build with one passes its
+//! parser to `repl::cli::main`.
+
+use std::process::ExitCode;
+
+use rigging::simulator::trace;
+
+const USAGE: &str = "\
+usage: rigging <command> [<arguments>...]
+
+commands:
+  repl        start an interactive session with a model
+  trace-diff  report where two simulation traces first differ";
+
+fn main() -> ExitCode {
+  let mut arguments = std::env::args().skip(1);
+  match arguments.next().as_deref() {
+    Some("repl") => {
+      eprintln!("rigging repl: this build has no Sail front end, so it cannot parse Sail");
+      ExitCode::FAILURE
+    }
+    Some("trace-diff") => trace::diff_main(arguments),
+    Some("--help" | "-h" | "help") => {
+      println!("{}", USAGE);
+      ExitCode::SUCCESS
+    }
+    Some(command) => {
+      eprintln!("rigging: unknown command {}\n{}", command, USAGE);
+      ExitCode::FAILURE
+    }
+    None => {
+      eprintln!("{}", USAGE);
+      ExitCode::FAILURE
+    }
+  }
+}
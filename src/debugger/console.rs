@@ -0,0 +1,213 @@
+/*!
+
+A command-line front end to the debugger, in the manner of GDB.
+
+```text
+continue, c          run to the next breakpoint
+step, s              run to the next line, entering calls
+next, n              run to the next line of this call
+finish               run until this call returns
+break, b <place>     stop at <file>:<line>, or on entry to a function
+delete, d <n>        remove breakpoint <n>
+info breakpoints     list the breakpoints
+backtrace, bt        list the calls in progress
+up, down             select the caller, or the callee, of the selected call
+locals               list the selected call's local variables
+registers            list the registers
+print, p <name>      show a variable, a top-level let or a register, with any .fields
+list, l              show the source around the selected call's line
+quit, q              stop the program
+```
+
+An empty line repeats the last command.
+
+*/
+
+use std::io::{BufRead, Write};
+
+use crate::debugger::{stopped_by_debugger, Breakpoints, Frontend, Place, Reason, Resume, Session, Sources};
+use crate::runtime::RuntimeError;
+
+pub const HELP: &str = "\
+continue, c          run to the next breakpoint
+step, s              run to the next line, entering calls
+next, n              run to the next line of this call
+finish               run until this call returns
+break, b <place>     stop at <file>:<line>, or on entry to a function
+delete, d <n>        remove breakpoint <n>
+info breakpoints     list the breakpoints
+backtrace, bt        list the calls in progress
+up, down             select the caller, or the callee, of the selected call
+locals               list the selected call's local variables
+registers            list the registers
+print, p <name>      show a variable, a top-level let or a register, with any .fields
+list, l              show the source around the selected call's line
+quit, q              stop the program";
+
+pub struct Console<I: BufRead, O: Write> {
+  input   : I,
+  output  : O,
+  /// The call commands apply to, counting out from the innermost
+  selected: usize,
+  last    : String,
+}
+
+impl Console<std::io::StdinLock<'static>, std::io::Stderr> {
+  /// A console reading commands from standard input and writing to standard error, leaving standard output to the
+  /// program.
+  pub fn stdio() -> Self {
+    Console::new(std::io::stdin().lock(), std::io::stderr())
+  }
+}
+
+impl<I: BufRead, O: Write> Console<I, O> {
+  pub fn new(input: I, output: O) -> Self {
+    Console { input, output, selected: 0, last: String::new() }
+  }
+
+  /// The place of a breakpoint, written `<file>:<line>` or as a function's name.
+  pub fn parse_place(text: &str) -> Result<Place, String> {
+    match text.rsplit_once(':') {
+      Some((file, line)) => {
+        let line = line.parse().map_err(|_| format!("{} is not a line number", line))?;
+        Ok(Place::Line(file.to_string(), line))
+      }
+      None if text.is_empty() => Err("break needs a place".to_string()),
+      None => Ok(Place::Function(text.to_string())),
+    }
+  }
+
+  /// Runs `command`, returning how to go on if it resumes the program.
+  fn command(&mut self, session: &mut Session<'_>, command: &str) -> std::io::Result<Option<Resume>> {
+    let (name, argument) = command.split_once(' ').map(|(name, rest)| (name, rest.trim())).unwrap_or((command, ""));
+    let frames = session.frames();
+    match name {
+      "continue" | "c" => return Ok(Some(Resume::Continue)),
+      "step" | "s" => return Ok(Some(Resume::StepInto)),
+      "next" | "n" => return Ok(Some(Resume::StepOver)),
+      "finish" => return Ok(Some(Resume::StepOut)),
+
+      "break" | "b" => match Self::parse_place(argument) {
+        Ok(place) => {
+          if let Place::Line(file, _) = &place {
+            if session.sources.find(file).is_none() {
+              writeln!(self.output, "warning: no source file {} is loaded", file)?;
+            }
+          }
+          let id = session.breakpoints.add(place.clone());
+          writeln!(self.output, "breakpoint {} at {}", id, place)?;
+        }
+        Err(message) => writeln!(self.output, "{}", message)?,
+      },
+      "delete" | "d" => match argument.parse() {
+        Ok(id) if session.breakpoints.remove(id) => {}
+        _ => writeln!(self.output, "no breakpoint {}", argument)?,
+      },
+      "info" if argument == "breakpoints" => {
+        for breakpoint in session.breakpoints.iter() {
+          writeln!(self.output, "{:<4} {} (hit {} times)", breakpoint.id, breakpoint.place, breakpoint.hits)?;
+        }
+      }
+
+      "backtrace" | "bt" => {
+        for (index, frame) in frames.iter().enumerate() {
+          let marker = if index == self.selected { '*' } else { ' ' };
+          let line = frame.line.as_ref().map(ToString::to_string).unwrap_or_else(|| "?".to_string());
+          writeln!(self.output, "{}#{:<3} {} at {}", marker, index, frame.function.as_deref().unwrap_or("<top>"), line)?;
+        }
+      }
+      "up" if self.selected + 1 < frames.len() => self.selected += 1,
+      "down" if self.selected > 0 => self.selected -= 1,
+      "up" | "down" => writeln!(self.output, "no such call")?,
+      "locals" | "info" if name == "locals" || argument == "locals" => {
+        for variable in session.locals(self.selected) {
+          writeln!(self.output, "{}", variable)?;
+        }
+      }
+      "registers" | "info" if name == "registers" || argument == "registers" => {
+        for register in session.registers() {
+          writeln!(self.output, "{}", register)?;
+        }
+      }
+      "print" | "p" => match session.lookup(self.selected, argument) {
+        Ok(variable) => writeln!(self.output, "{}", variable)?,
+        Err(message) => writeln!(self.output, "{}", message)?,
+      },
+      "list" | "l" => match frames.get(self.selected).and_then(|frame| frame.line.clone()) {
+        Some(line) => {
+          let count = line.file.find_line(line.file.span.high()) + 1;
+          for number in line.line.saturating_sub(5).max(1)..=(line.line + 5).min(count) {
+            let marker = if number == line.line { "=>" } else { "  " };
+            writeln!(self.output, "{} {:>5} {}", marker, number, line.file.source_line(number - 1))?;
+          }
+        }
+        None => writeln!(self.output, "no source for this call")?,
+      },
+
+      "help" | "h" => writeln!(self.output, "{}", HELP)?,
+      _ => writeln!(self.output, "unknown command {}; try help", command)?,
+    }
+    Ok(None)
+  }
+}
+
+impl<I: BufRead, O: Write> Frontend for Console<I, O> {
+  /// Stops before the first statement, so that breakpoints can be set.
+  fn start(&mut self, _sources: &Sources, _breakpoints: &mut Breakpoints) -> Result<bool, RuntimeError> {
+    Ok(true)
+  }
+
+  fn stopped(&mut self, session: &mut Session<'_>, reason: Reason) -> Result<Resume, RuntimeError> {
+    let io = |error: std::io::Error| RuntimeError::Failure(format!("debugger console: {}", error));
+    self.selected = 0;
+    let frames = session.frames();
+    if let Reason::Breakpoint(id) = reason {
+      write!(self.output, "breakpoint {}, ", id).map_err(io)?;
+    }
+    if let Some(frame) = frames.first() {
+      let function = frame.function.as_deref().unwrap_or("<top>");
+      match &frame.line {
+        Some(line) => writeln!(self.output, "{} at {}\n{:>5} {}", function, line, line.line, line.text()),
+        None => writeln!(self.output, "{}", function),
+      }
+      .map_err(io)?;
+    }
+
+    loop {
+      write!(self.output, "(rigging) ").and_then(|_| self.output.flush()).map_err(io)?;
+      let mut line = String::new();
+      if self.input.read_line(&mut line).map_err(io)? == 0 {
+        return Err(stopped_by_debugger());
+      }
+      let mut command = line.trim().to_string();
+      if command.is_empty() {
+        command = self.last.clone();
+      }
+      self.last = command.clone();
+      if matches!(command.as_str(), "quit" | "q") {
+        return Err(stopped_by_debugger());
+      }
+      if let Some(resume) = self.command(session, &command).map_err(io)? {
+        return Ok(resume);
+      }
+    }
+  }
+
+  fn finished(&mut self, exit_code: i64) {
+    let _ = writeln!(self.output, "the program finished with exit code {}", exit_code);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_places() {
+    type Stdio = Console<std::io::Empty, std::io::Sink>;
+    assert_eq!(Stdio::parse_place("model/riscv.sail:42"), Ok(Place::Line("model/riscv.sail".to_string(), 42)));
+    assert_eq!(Stdio::parse_place("execute"), Ok(Place::Function("execute".to_string())));
+    assert!(Stdio::parse_place("a.sail:x").is_err());
+    assert!(Stdio::parse_place("").is_err());
+  }
+}
@@ -0,0 +1,369 @@
+/*!
+
+A front end to the debugger speaking the Debug Adapter Protocol, through which editors such as VS Code drive debuggers.
+
+Each message is a JSON object preceded by a `Content-Length` header. The editor's requests are served in two phases.
+Before the program runs, `initialize`, `launch` or `attach`, `setBreakpoints` and `setFunctionBreakpoints` set it up,
+until `configurationDone`; a `launch` with `stopOnEntry` stops before the first statement. While the program is
+stopped, `threads`, `stackTrace`, `scopes`, `variables` and `evaluate` inspect it, and `continue`, `next`, `stepIn` and
+`stepOut` resume it. `disconnect` or `terminate` stops it.
+
+The program is a single thread, and each call in progress a frame, numbered from the innermost. Every frame has a scope
+of local variables and one of registers. Values are shown as Sail would print them, without structure to expand.
+
+*/
+
+use std::io::{BufRead, Write};
+
+use crate::abstractions::Json;
+use crate::debugger::{stopped_by_debugger, Breakpoints, Frontend, Place, Reason, Resume, Session, Sources, Variable};
+use crate::runtime::RuntimeError;
+
+/// The `variablesReference` of the registers. That of the local variables of frame `n` is `n + LOCALS`.
+const REGISTERS: i64 = 1;
+const LOCALS   : i64 = 2;
+
+/// The program's only thread.
+const THREAD: i64 = 1;
+
+/// The protocol's framing of messages over a pair of streams.
+pub struct Transport<R: BufRead, W: Write> {
+  reader: R,
+  writer: W,
+  seq   : i64,
+}
+
+impl<R: BufRead, W: Write> Transport<R, W> {
+  pub fn new(reader: R, writer: W) -> Self {
+    Transport { reader, writer, seq: 0 }
+  }
+
+  /// The next message, or `None` at the end of the input.
+  pub fn receive(&mut self) -> std::io::Result<Option<Json>> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut length = None;
+    loop {
+      let mut header = String::new();
+      if self.reader.read_line(&mut header)? == 0 {
+        return Ok(None);
+      }
+      let header = header.trim();
+      if header.is_empty() {
+        if length.is_some() {
+          break;
+        }
+        continue;
+      }
+      if let Some((name, value)) = header.split_once(':') {
+        if name.trim().eq_ignore_ascii_case("Content-Length") {
+          length = Some(value.trim().parse::<usize>().map_err(|_| invalid(format!("bad header `{}`", header)))?);
+        }
+      }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    self.reader.read_exact(&mut body)?;
+    let text = String::from_utf8(body).map_err(|_| invalid("a message that is not UTF-8".to_string()))?;
+    Json::parse(&text).map(Some).map_err(invalid)
+  }
+
+  /// Sends `message`, numbering it.
+  pub fn send(&mut self, mut message: Json) -> std::io::Result<()> {
+    self.seq += 1;
+    message.insert("seq", Json::Integer(self.seq));
+    let text = message.to_string();
+    write!(self.writer, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+    self.writer.flush()
+  }
+
+  pub fn respond(&mut self, request: &Json, body: Json) -> std::io::Result<()> {
+    self.send(Json::object([
+      ("type", Json::from("response")),
+      ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+      ("success", Json::from(true)),
+      ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+      ("body", body),
+    ]))
+  }
+
+  pub fn fail(&mut self, request: &Json, message: &str) -> std::io::Result<()> {
+    self.send(Json::object([
+      ("type", Json::from("response")),
+      ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+      ("success", Json::from(false)),
+      ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+      ("message", Json::from(message)),
+    ]))
+  }
+
+  pub fn event(&mut self, event: &str, body: Json) -> std::io::Result<()> {
+    self.send(Json::object([("type", Json::from("event")), ("event", Json::from(event)), ("body", body)]))
+  }
+}
+
+pub struct DebugAdapter<R: BufRead, W: Write> {
+  transport: Transport<R, W>,
+}
+
+impl DebugAdapter<std::io::StdinLock<'static>, std::io::Stdout> {
+  /// An adapter speaking over standard input and output, which the program must then leave alone.
+  pub fn stdio() -> Self {
+    DebugAdapter::new(Transport::new(std::io::stdin().lock(), std::io::stdout()))
+  }
+}
+
+impl<R: BufRead, W: Write> DebugAdapter<R, W> {
+  pub fn new(transport: Transport<R, W>) -> Self {
+    DebugAdapter { transport }
+  }
+
+  /// The next request, failing at the end of the input.
+  fn request(&mut self) -> Result<Json, RuntimeError> {
+    match self.transport.receive().map_err(failure)? {
+      Some(request) => Ok(request),
+      None => Err(stopped_by_debugger()),
+    }
+  }
+
+  /// Serves the breakpoint requests, returning whether `request` was one.
+  fn breakpoints(&mut self, request: &Json, sources: &Sources, breakpoints: &mut Breakpoints) -> std::io::Result<bool> {
+    let arguments = request.get("arguments").cloned().unwrap_or(Json::object::<&str>([]));
+    match request.get("command").and_then(Json::as_str) {
+      Some("setBreakpoints") => {
+        let Some(path) = arguments.get("source").and_then(|source| source.get("path")).and_then(Json::as_str) else {
+          self.transport.fail(request, "a source needs a path")?;
+          return Ok(true);
+        };
+        breakpoints.retain(|breakpoint| !matches!(&breakpoint.place, Place::Line(file, _) if file == path));
+        let verified = sources.find(path).is_some();
+        let mut set = Vec::new();
+        for line in arguments.get("breakpoints").and_then(Json::as_array).unwrap_or_default() {
+          let Some(number) = line.get("line").and_then(Json::as_i64).filter(|number| *number > 0) else { continue };
+          let id = breakpoints.add(Place::Line(path.to_string(), number as usize));
+          set.push(Json::object([("id", Json::from(id)), ("verified", Json::from(verified)), ("line", Json::from(number))]));
+        }
+        self.transport.respond(request, Json::object([("breakpoints", Json::Array(set))]))?;
+        Ok(true)
+      }
+      Some("setFunctionBreakpoints") => {
+        breakpoints.retain(|breakpoint| !matches!(breakpoint.place, Place::Function(_)));
+        let mut set = Vec::new();
+        for function in arguments.get("breakpoints").and_then(Json::as_array).unwrap_or_default() {
+          let Some(name) = function.get("name").and_then(Json::as_str) else { continue };
+          let id = breakpoints.add(Place::Function(name.to_string()));
+          set.push(Json::object([("id", Json::from(id)), ("verified", Json::from(true))]));
+        }
+        self.transport.respond(request, Json::object([("breakpoints", Json::Array(set))]))?;
+        Ok(true)
+      }
+      _ => Ok(false),
+    }
+  }
+
+  /// Serves a request while the program is stopped, returning how to go on if it resumes the program.
+  fn serve(&mut self, request: &Json, session: &mut Session<'_>) -> Result<Option<Resume>, RuntimeError> {
+    if self.breakpoints(request, session.sources, session.breakpoints).map_err(failure)? {
+      return Ok(None);
+    }
+    let arguments = request.get("arguments").cloned().unwrap_or(Json::object::<&str>([]));
+    let resume = match request.get("command").and_then(Json::as_str).unwrap_or_default() {
+      "continue" => Some(Resume::Continue),
+      "next" => Some(Resume::StepOver),
+      "stepIn" => Some(Resume::StepInto),
+      "stepOut" => Some(Resume::StepOut),
+      "disconnect" | "terminate" => {
+        self.transport.respond(request, Json::Null).map_err(failure)?;
+        return Err(stopped_by_debugger());
+      }
+      _ => None,
+    };
+    if let Some(resume) = resume {
+      let body = Json::object([("allThreadsContinued", Json::from(true))]);
+      self.transport.respond(request, body).map_err(failure)?;
+      return Ok(Some(resume));
+    }
+
+    let body = match request.get("command").and_then(Json::as_str).unwrap_or_default() {
+      "threads" => {
+        let thread = Json::object([("id", Json::Integer(THREAD)), ("name", Json::from("main"))]);
+        Json::object([("threads", Json::Array(vec![thread]))])
+      }
+      "stackTrace" => {
+        let frames: Vec<Json> = session
+            .frames()
+            .into_iter()
+            .enumerate()
+            .map(|(index, frame)| {
+              let mut json = Json::object([
+                ("id", Json::from(index)),
+                ("name", Json::from(frame.function.unwrap_or_else(|| "<top>".to_string()))),
+                ("line", Json::from(frame.line.as_ref().map_or(0, |line| line.line))),
+                ("column", Json::from(frame.line.as_ref().map_or(0, |line| line.column))),
+              ]);
+              if let Some(line) = &frame.line {
+                let name = line.file.name();
+                let short = name.rsplit('/').next().unwrap_or(name);
+                json.insert("source", Json::object([("name", Json::from(short)), ("path", Json::from(name))]));
+              }
+              json
+            })
+            .collect();
+        Json::object([("totalFrames", Json::from(frames.len())), ("stackFrames", Json::Array(frames))])
+      }
+      "scopes" => {
+        let frame = arguments.get("frameId").and_then(Json::as_i64).unwrap_or(0);
+        let scope = |name: &str, reference: i64| {
+          Json::object([
+            ("name", Json::from(name)),
+            ("variablesReference", Json::Integer(reference)),
+            ("expensive", Json::from(false)),
+          ])
+        };
+        Json::object([("scopes", Json::Array(vec![scope("Locals", frame + LOCALS), scope("Registers", REGISTERS)]))])
+      }
+      "variables" => {
+        let variables = match arguments.get("variablesReference").and_then(Json::as_i64).unwrap_or(0) {
+          REGISTERS => session.registers(),
+          reference if reference >= LOCALS => session.locals((reference - LOCALS) as usize),
+          _ => Vec::new(),
+        };
+        Json::object([("variables", Json::Array(variables.iter().map(variable).collect()))])
+      }
+      "evaluate" => {
+        let frame = arguments.get("frameId").and_then(Json::as_i64).unwrap_or(0) as usize;
+        let expression = arguments.get("expression").and_then(Json::as_str).unwrap_or_default();
+        match session.lookup(frame, expression) {
+          Ok(found) => Json::object([
+            ("result", Json::from(found.value.to_string())),
+            ("type", Json::from(found.typ)),
+            ("variablesReference", Json::Integer(0)),
+          ]),
+          Err(message) => {
+            self.transport.fail(request, &message).map_err(failure)?;
+            return Ok(None);
+          }
+        }
+      }
+      "pause" => Json::Null,
+      command => {
+        self.transport.fail(request, &format!("{} is not supported", command)).map_err(failure)?;
+        return Ok(None);
+      }
+    };
+    self.transport.respond(request, body).map_err(failure)?;
+    Ok(None)
+  }
+}
+
+impl<R: BufRead, W: Write> Frontend for DebugAdapter<R, W> {
+  /// Serves requests until `configurationDone`.
+  fn start(&mut self, sources: &Sources, breakpoints: &mut Breakpoints) -> Result<bool, RuntimeError> {
+    let mut stop_on_entry = false;
+    loop {
+      let request = self.request()?;
+      if self.breakpoints(&request, sources, breakpoints).map_err(failure)? {
+        continue;
+      }
+      let transport = &mut self.transport;
+      match request.get("command").and_then(Json::as_str).unwrap_or_default() {
+        "initialize" => {
+          let capabilities = Json::object([
+            ("supportsConfigurationDoneRequest", Json::from(true)),
+            ("supportsFunctionBreakpoints", Json::from(true)),
+            ("supportsEvaluateForHovers", Json::from(true)),
+            ("supportsTerminateRequest", Json::from(true)),
+          ]);
+          transport.respond(&request, capabilities).and_then(|_| transport.event("initialized", Json::Null))
+        }
+        "launch" | "attach" => {
+          let arguments = request.get("arguments");
+          stop_on_entry = arguments.and_then(|arguments| arguments.get("stopOnEntry")).and_then(Json::as_bool) == Some(true);
+          transport.respond(&request, Json::Null)
+        }
+        "threads" => {
+          let thread = Json::object([("id", Json::Integer(THREAD)), ("name", Json::from("main"))]);
+          transport.respond(&request, Json::object([("threads", Json::Array(vec![thread]))]))
+        }
+        "configurationDone" => {
+          transport.respond(&request, Json::Null).map_err(failure)?;
+          return Ok(stop_on_entry);
+        }
+        "disconnect" | "terminate" => {
+          transport.respond(&request, Json::Null).map_err(failure)?;
+          return Err(stopped_by_debugger());
+        }
+        command => transport.fail(&request, &format!("{} is not supported before the program starts", command)),
+      }
+      .map_err(failure)?;
+    }
+  }
+
+  fn stopped(&mut self, session: &mut Session<'_>, reason: Reason) -> Result<Resume, RuntimeError> {
+    let (reason, breakpoint) = match reason {
+      Reason::Entry => ("entry", None),
+      Reason::Breakpoint(id) => ("breakpoint", Some(id)),
+      Reason::Step => ("step", None),
+    };
+    let mut body = Json::object([
+      ("reason", Json::from(reason)),
+      ("threadId", Json::Integer(THREAD)),
+      ("allThreadsStopped", Json::from(true)),
+    ]);
+    if let Some(id) = breakpoint {
+      body.insert("hitBreakpointIds", Json::from(vec![id]));
+    }
+    self.transport.event("stopped", body).map_err(failure)?;
+    loop {
+      let request = self.request()?;
+      if let Some(resume) = self.serve(&request, session)? {
+        return Ok(resume);
+      }
+    }
+  }
+
+  /// Reports the exit code and that the session is over, then answers the editor's requests until it disconnects.
+  fn finished(&mut self, exit_code: i64) {
+    let _ = self.transport.event("exited", Json::object([("exitCode", Json::Integer(exit_code))]));
+    let _ = self.transport.event("terminated", Json::Null);
+    while let Ok(Some(request)) = self.transport.receive() {
+      let disconnect = matches!(request.get("command").and_then(Json::as_str), Some("disconnect" | "terminate"));
+      let _ = self.transport.respond(&request, Json::Null);
+      if disconnect {
+        break;
+      }
+    }
+  }
+}
+
+fn variable(variable: &Variable) -> Json {
+  Json::object([
+    ("name", Json::from(variable.name.as_str())),
+    ("value", Json::from(variable.value.to_string())),
+    ("type", Json::from(variable.typ.as_str())),
+    ("variablesReference", Json::Integer(0)),
+  ])
+}
+
+fn failure(error: std::io::Error) -> RuntimeError {
+  RuntimeError::Failure(format!("debug adapter connection: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn frames_messages() {
+    let body = r#"{"seq":1,"type":"request","x":"\u00e9"}"#;
+    let input = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let mut transport = Transport::new(input.as_bytes(), Vec::new());
+    let request = transport.receive().unwrap().unwrap();
+    assert_eq!(request.get("x").and_then(Json::as_str), Some("é"));
+    assert_eq!(transport.receive().unwrap(), None);
+
+    transport.respond(&request, Json::Null).unwrap();
+    let output = String::from_utf8(transport.writer).unwrap();
+    let (header, body) = output.split_once("\r\n\r\n").unwrap();
+    assert_eq!(header, format!("Content-Length: {}", body.len()));
+    assert_eq!(body, r#"{"type":"response","request_seq":1,"success":true,"command":null,"body":null,"seq":1}"#);
+  }
+}
@@ -0,0 +1,669 @@
+/*!
+
+A source-level debugger for Sail programs running on the interpreter.
+
+`SourceDebugger` is the interpreter's `Debugger`. Before each statement it decides whether the program should stop
+there: because it has just started and was asked to stop on entry, because a step has finished, or because of a
+breakpoint, either at a line of a source file or on entry to a function. Lines are found from the statements'
+`SourceLocation::Span`s through the program's `Sources`. A statement on the same line as the one before it in the same
+call does not stop the program again, so that a line holding several statements is a single stop.
+
+When the program stops, the debugger hands a `Session` to its `Frontend`, which may list the calls in progress, their
+local variables with the types the type checker gave them, and the registers, and which says how to go on:
+
+ * `Continue`, to the next breakpoint,
+ * `StepInto`, to the next line run, in this call or one it makes,
+ * `StepOver`, to the next line of this call or its caller, or
+ * `StepOut`, to the next line of the caller.
+
+Two front ends are provided: `console`, a command line like GDB's, and `dap`, the Debug Adapter Protocol that editors
+such as VS Code speak, over a pair of streams.
+
+*/
+
+pub mod console;
+pub mod dap;
+
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use codemap::{CodeMap, File};
+
+use crate::driver::source_span;
+use crate::interpreter::{Debugger, Environment, Interpreter, Pause, StackFrame};
+use crate::parser::ast::Definitions;
+use crate::parser::location::SourceLocation;
+use crate::passes::typecheck::{Type, Typing};
+use crate::runtime::{RuntimeError, Value};
+
+// region Sources
+
+/// The source files of a program, to find the lines its statements are on.
+#[derive(Clone, Default)]
+pub struct Sources {
+  /// In the order of their spans
+  files: Vec<Arc<File>>,
+}
+
+/// A line of a source file, counting from one.
+#[derive(Clone)]
+pub struct Line {
+  pub file  : Arc<File>,
+  pub line  : usize,
+  pub column: usize,
+}
+
+impl Line {
+  /// The text of the line.
+  pub fn text(&self) -> &str {
+    self.file.source_line(self.line - 1)
+  }
+}
+
+impl Display for Line {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}:{}", self.file.name(), self.line)
+  }
+}
+
+impl Sources {
+  /// The files of `code_map` that `definitions` come from.
+  pub fn of(code_map: &CodeMap, definitions: &Definitions) -> Self {
+    let mut sources = Sources::default();
+    for (_, file) in &definitions.0 {
+      for definition in file {
+        if let Some(span) = source_span(&definition.location) {
+          sources.add(code_map.find_file(span.low()).clone());
+        }
+      }
+    }
+    sources
+  }
+
+  /// Adds `file`, unless it is already known.
+  pub fn add(&mut self, file: Arc<File>) {
+    if let Err(index) = self.files.binary_search_by_key(&file.span.low(), |known| known.span.low()) {
+      self.files.insert(index, file);
+    }
+  }
+
+  /// The file named `name`, or whose name ends with `name` as a path, or which `name` ends with as a path.
+  pub fn find(&self, name: &str) -> Option<&Arc<File>> {
+    self.files.iter().find(|file| same_file(file.name(), name))
+  }
+
+  /// The line `location` starts on, if it is in a known file.
+  pub fn line(&self, location: &SourceLocation) -> Option<Line> {
+    let position = source_span(location)?.low();
+    let index = self.files.partition_point(|file| file.span.high() < position);
+    let file = self.files.get(index).filter(|file| file.span.low() <= position)?;
+    let at = file.find_line_col(position);
+    Some(Line { file: file.clone(), line: at.line + 1, column: at.column + 1 })
+  }
+}
+
+/// Whether two names refer to the same file, one being possibly a longer path than the other.
+fn same_file(a: &str, b: &str) -> bool {
+  a == b || a.ends_with(&format!("/{}", b)) || b.ends_with(&format!("/{}", a))
+}
+
+// endregion
+
+// region Breakpoints
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Place {
+  /// A line of a file, counting from one
+  Line(String, usize),
+  /// The entry to a function
+  Function(String),
+}
+
+impl Display for Place {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Place::Line(file, line) => write!(f, "{}:{}", file, line),
+      Place::Function(function) => write!(f, "{}", function),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+  pub id   : usize,
+  pub place: Place,
+  /// How many times the program has stopped here
+  pub hits : usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+  list: Vec<Breakpoint>,
+  next: usize,
+}
+
+impl Breakpoints {
+  /// Adds a breakpoint, returning its number.
+  pub fn add(&mut self, place: Place) -> usize {
+    self.next += 1;
+    self.list.push(Breakpoint { id: self.next, place, hits: 0 });
+    self.next
+  }
+
+  /// Removes the breakpoint numbered `id`, returning whether there was one.
+  pub fn remove(&mut self, id: usize) -> bool {
+    let before = self.list.len();
+    self.list.retain(|breakpoint| breakpoint.id != id);
+    self.list.len() != before
+  }
+
+  /// Removes the breakpoints for which `f` is false.
+  pub fn retain(&mut self, f: impl Fn(&Breakpoint) -> bool) {
+    self.list.retain(f);
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+    self.list.iter()
+  }
+
+  /// The breakpoint at `line`, or on entry to `function` if the program is `entering` it.
+  fn hit(&mut self, line: &Line, function: &str, entering: bool) -> Option<&mut Breakpoint> {
+    self.list.iter_mut().find(|breakpoint| match &breakpoint.place {
+      Place::Line(file, number) => *number == line.line && same_file(line.file.name(), file),
+      Place::Function(name) => entering && name == function,
+    })
+  }
+}
+
+// endregion
+
+// region Inspection
+
+/// A variable or register, with its value and type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+  pub name : String,
+  pub typ  : String,
+  pub value: Value,
+}
+
+impl Display for Variable {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} : {} = {}", self.name, self.typ, self.value)
+  }
+}
+
+/// A call in progress, as a front end shows it.
+#[derive(Clone)]
+pub struct Frame {
+  /// The function called, or `None` outside any function
+  pub function: Option<String>,
+  /// The line running
+  pub line    : Option<Line>,
+}
+
+/// A stopped program, for a front end to inspect.
+pub struct Session<'s> {
+  pub interpreter: &'s mut Interpreter,
+  pub environment: &'s mut dyn Environment,
+  pub sources    : &'s Sources,
+  pub breakpoints: &'s mut Breakpoints,
+}
+
+impl Session<'_> {
+  /// The calls in progress, innermost first.
+  pub fn frames(&self) -> Vec<Frame> {
+    self.interpreter
+        .stack()
+        .iter()
+        .map(|frame| Frame {
+          function: (!frame.function.is_empty()).then(|| frame.function.to_string()),
+          line    : self.sources.line(frame.position),
+        })
+        .collect()
+  }
+
+  /// The local variables of the call `frame` frames out from the innermost.
+  pub fn locals(&self, frame: usize) -> Vec<Variable> {
+    let stack = self.interpreter.stack();
+    let Some(frame) = stack.get(frame) else { return Vec::new() };
+    let typing = self.interpreter.typing();
+    frame
+        .locals()
+        .into_iter()
+        .map(|(name, value, typ)| Variable {
+          name : name.to_string(),
+          typ  : typ.map(Type::to_string).unwrap_or_else(|| value_type(value, typing)),
+          value: value.clone(),
+        })
+        .collect()
+  }
+
+  /// The program's registers, by name.
+  pub fn registers(&mut self) -> Vec<Variable> {
+    let mut registers: Vec<(String, Type)> =
+      self.interpreter.typing().environment.registers.iter().map(|(name, typ)| (name.clone(), typ.clone())).collect();
+    registers.sort_by(|(a, _), (b, _)| a.cmp(b));
+    registers
+        .into_iter()
+        .filter_map(|(name, typ)| {
+          let value = self.environment.read_register(&name).ok()?;
+          Some(Variable { name, typ: typ.to_string(), value })
+        })
+        .collect()
+  }
+
+  /// The value of `path`, a local variable of the call `frame`, a top-level `let` or a register, followed by any
+  /// number of `.field`s of records and bitfields.
+  pub fn lookup(&mut self, frame: usize, path: &str) -> Result<Variable, String> {
+    let mut parts = path.split('.').map(str::trim);
+    let name = parts.next().unwrap_or_default();
+    let (mut value, mut typ) = self.variable(frame, name).ok_or_else(|| format!("nothing is called `{}`", name))?;
+    let typing = self.interpreter.typing();
+    for field in parts {
+      (value, typ) = field_of(&value, &typ, field, typing).ok_or_else(|| format!("no field `{}` in `{}`", field, path))?;
+    }
+    let typ = match typ {
+      Type::Any => value_type(&value, typing),
+      typ => typ.to_string(),
+    };
+    Ok(Variable { name: path.to_string(), typ, value })
+  }
+
+  fn variable(&mut self, frame: usize, name: &str) -> Option<(Value, Type)> {
+    let stack = self.interpreter.stack();
+    if let Some(value) = stack.get(frame).and_then(|frame: &StackFrame| frame.local(name)) {
+      let typ = stack[frame].local_type(name).cloned().unwrap_or(Type::Any);
+      return Some((value.clone(), typ));
+    }
+    let environment = &self.interpreter.typing().environment;
+    if let Some(value) = self.interpreter.global(name) {
+      return Some((value.clone(), environment.values.get(name).cloned().unwrap_or(Type::Any)));
+    }
+    let typ = environment.registers.get(name).cloned()?;
+    Some((self.environment.read_register(name).ok()?, typ))
+  }
+}
+
+/// The field `field` of a record or a bitfield, and its type.
+fn field_of(value: &Value, typ: &Type, field: &str, typing: &Typing) -> Option<(Value, Type)> {
+  let environment = &typing.environment;
+  let name = match typ {
+    Type::Application(name, _) => Some(name.as_str()),
+    _ => None,
+  };
+  if let Some(bitfield) = name.and_then(|name| environment.bitfields.get(name)) {
+    let (ranges, _) = bitfield.field(field)?;
+    let mut result = Value::Bitvector(crate::runtime::Bits::zeros(0));
+    for (high, low) in ranges {
+      result = result.append(&value.subrange(high, low, environment.increasing).ok()?).ok()?;
+    }
+    return Some((result, Type::Any));
+  }
+  let field_type = name
+      .and_then(|name| environment.records.get(name))
+      .and_then(|record| record.fields.iter().find(|(name, _)| name == field))
+      .map(|(_, typ)| typ.clone())
+      .unwrap_or(Type::Any);
+  Some((value.field(field)?.clone(), field_type))
+}
+
+/// The type of a value without a recorded one, as far as the value tells it.
+pub fn value_type(value: &Value, typing: &Typing) -> String {
+  let environment = &typing.environment;
+  match value {
+    Value::Unit => "unit".to_string(),
+    Value::Bool(_) => "bool".to_string(),
+    Value::Bit(_) => "bit".to_string(),
+    Value::Integer(_) => "int".to_string(),
+    Value::Real(_) => "real".to_string(),
+    Value::String(_) => "string".to_string(),
+    Value::Bitvector(bits) => format!("bits({})", bits.len()),
+    Value::Vector(elements) => {
+      let element = elements.first().map(|element| value_type(element, typing)).unwrap_or_else(|| "_".to_string());
+      format!("vector({}, {})", elements.len(), element)
+    }
+    Value::List(elements) => {
+      let element = elements.first().map(|element| value_type(element, typing)).unwrap_or_else(|| "_".to_string());
+      format!("list({})", element)
+    }
+    Value::Tuple(elements) => {
+      format!("({})", elements.iter().map(|element| value_type(element, typing)).collect::<Vec<_>>().join(", "))
+    }
+    Value::Record(fields) => environment
+        .records
+        .iter()
+        .find(|(_, record)| {
+          record.fields.len() == fields.len() && record.fields.iter().zip(fields).all(|((a, _), (b, _))| a == b)
+        })
+        .map(|(name, _)| name.clone())
+        .unwrap_or_else(|| "struct".to_string()),
+    Value::Member(member) => environment.enum_members.get(member).cloned().unwrap_or_else(|| "enum".to_string()),
+    Value::Constructor(constructor, _) => environment
+        .constructors
+        .get(constructor)
+        .map(|constructor| constructor.result.to_string())
+        .unwrap_or_else(|| "union".to_string()),
+    Value::Reference(register) => environment
+        .registers
+        .get(register)
+        .map(|typ| format!("register({})", typ))
+        .unwrap_or_else(|| "register".to_string()),
+  }
+}
+
+// endregion
+
+// region The debugger
+
+/// How a front end asks for the program to go on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+  Continue,
+  StepInto,
+  StepOver,
+  StepOut,
+}
+
+/// Why the program stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+  /// The program is about to run its first statement.
+  Entry,
+  /// At the breakpoint with this number
+  Breakpoint(usize),
+  Step,
+}
+
+/// A user interface to the debugger.
+pub trait Frontend {
+  /// Called before the first statement, with the breakpoints to set up, returning whether to stop there.
+  fn start(&mut self, sources: &Sources, breakpoints: &mut Breakpoints) -> Result<bool, RuntimeError>;
+
+  /// Called when the program stops, returning how to go on. An error stops the program.
+  fn stopped(&mut self, session: &mut Session<'_>, reason: Reason) -> Result<Resume, RuntimeError>;
+
+  /// Called once the program has finished, with its exit code.
+  fn finished(&mut self, _exit_code: i64) {}
+}
+
+/// The call depth and line of a statement.
+type Position = (usize, Option<(String, usize)>);
+
+pub struct SourceDebugger<F: Frontend> {
+  sources      : Sources,
+  breakpoints  : Breakpoints,
+  frontend     : F,
+  started      : bool,
+  /// How the front end last asked to go on, and where the program was then
+  resume       : (Resume, Position),
+  /// Where the last statement was
+  last         : Option<Position>,
+}
+
+impl<F: Frontend> SourceDebugger<F> {
+  /// A debugger for a program from `sources`.
+  pub fn new(sources: Sources, frontend: F) -> Self {
+    SourceDebugger {
+      sources,
+      breakpoints: Breakpoints::default(),
+      frontend,
+      started: false,
+      resume : (Resume::Continue, (0, None)),
+      last   : None,
+    }
+  }
+
+  /// Whether the step the front end asked for ends at `here`.
+  fn stepped(&self, here: &Position) -> bool {
+    let (resume, (depth, line)) = &self.resume;
+    match resume {
+      Resume::Continue => false,
+      Resume::StepInto => here.0 != *depth || here.1 != *line,
+      Resume::StepOver => here.0 < *depth || (here.0 == *depth && here.1 != *line),
+      Resume::StepOut => here.0 < *depth,
+    }
+  }
+}
+
+impl<F: Frontend> Debugger for SourceDebugger<F> {
+  fn statement(&mut self, pause: Pause<'_>) -> Result<(), RuntimeError> {
+    // Statements the sources do not cover, such as generated ones, are never stopped at.
+    let Some(line) = self.sources.line(pause.location) else { return Ok(()) };
+    let stack = pause.interpreter.stack();
+    let function = stack.first().map(|frame| frame.function.to_string()).unwrap_or_default();
+    let here: Position = (stack.len(), Some((line.file.name().to_string(), line.line)));
+    let same_line = self.last.as_ref() == Some(&here);
+    self.last = Some(here.clone());
+
+    let reason = if !self.started {
+      self.started = true;
+      self.frontend.start(&self.sources, &mut self.breakpoints)?.then_some(Reason::Entry)
+    } else {
+      None
+    };
+    let reason = reason
+        .or_else(|| self.stepped(&here).then_some(Reason::Step))
+        .or_else(|| {
+          let entering = pause.entering && !same_line;
+          let breakpoint = self.breakpoints.hit(&line, &function, entering).filter(|_| !same_line || entering)?;
+          breakpoint.hits += 1;
+          Some(Reason::Breakpoint(breakpoint.id))
+        });
+    let Some(reason) = reason else { return Ok(()) };
+
+    let mut session = Session {
+      interpreter: pause.interpreter,
+      environment: pause.environment,
+      sources    : &self.sources,
+      breakpoints: &mut self.breakpoints,
+    };
+    let resume = self.frontend.stopped(&mut session, reason)?;
+    self.resume = (resume, here);
+    Ok(())
+  }
+
+  fn finished(&mut self, exit_code: i64) {
+    self.frontend.finished(exit_code);
+  }
+}
+
+// endregion
+
+/// The error with which a front end stops the program.
+pub fn stopped_by_debugger() -> RuntimeError {
+  RuntimeError::Failure("stopped by the debugger".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::collections::VecDeque;
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::parser::ast::LocatedExpression;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+  use crate::runtime::SimpleEnvironment;
+
+  /// The program the tests debug, as the lines its statements are given are written:
+  ///
+  /// ```text
+  /// 1  function inc(x) = {
+  /// 2    y = add_int(x, 1);
+  /// 3    y
+  /// 4  }
+  /// 5  function main() = {
+  /// 6    a = 1; b = inc(a);
+  /// 7    c = inc(b);
+  /// 8    c
+  /// 9  }
+  /// ```
+  const SOURCE: &str = "function inc(x) = {\n  y = add_int(x, 1);\n  y\n}\n\
+                        function main() = {\n  a = 1; b = inc(a);\n  c = inc(b);\n  c\n}\n";
+
+  /// `expression`, placed at the start of `line` of `file`.
+  fn on(file: &File, line: usize, mut expression: LocatedExpression) -> LocatedExpression {
+    let SourceLocation::Unique(id, _) = expression.location else { unreachable!() };
+    expression.location = SourceLocation::Unique(id, Box::new(SourceLocation::Span(file.line_span(line - 1))));
+    expression
+  }
+
+  /// A function and a line in it.
+  type At = (Option<String>, Option<usize>);
+
+  /// Why the program stopped, the function and line it stopped at, its local variables and what was looked up.
+  #[derive(Debug, PartialEq, Eq)]
+  struct Stop {
+    reason : Reason,
+    at     : At,
+    locals : Vec<String>,
+    lookups: Vec<Result<String, String>>,
+  }
+
+  /// A front end that sets breakpoints, goes on as it is told to and writes down where the program stops.
+  #[derive(Default)]
+  struct Script {
+    stop_on_entry: bool,
+    breakpoints  : Vec<Place>,
+    resumes      : VecDeque<Resume>,
+    /// What to look up at each stop, as frames out from the innermost and paths
+    lookups      : Vec<(usize, &'static str)>,
+    stops        : Rc<RefCell<Vec<Stop>>>,
+  }
+
+  impl Frontend for Script {
+    fn start(&mut self, _sources: &Sources, breakpoints: &mut Breakpoints) -> Result<bool, RuntimeError> {
+      for place in self.breakpoints.drain(..) {
+        breakpoints.add(place);
+      }
+      Ok(self.stop_on_entry)
+    }
+
+    fn stopped(&mut self, session: &mut Session<'_>, reason: Reason) -> Result<Resume, RuntimeError> {
+      let frame = session.frames().into_iter().next().unwrap();
+      let locals = session.locals(0).iter().map(|local| format!("{} = {}", local.name, local.value)).collect();
+      let lookups = self
+          .lookups
+          .iter()
+          .map(|(frame, path)| session.lookup(*frame, path).map(|variable| variable.to_string()))
+          .collect();
+      let at = (frame.function, frame.line.map(|line| line.line));
+      self.stops.borrow_mut().push(Stop { reason, at, locals, lookups });
+      Ok(self.resumes.pop_front().unwrap_or(Resume::Continue))
+    }
+  }
+
+  /// Runs `main` under `script`, returning where it stopped.
+  fn debug(script: Script) -> Vec<Stop> {
+    let mut code_map = CodeMap::new();
+    let file = code_map.add_file("model/test.sail".to_string(), SOURCE.to_string());
+    let program = vec![
+      extern_val("add_int", "add_int", function_type(vec![typ("int"), typ("int")], typ("int"))),
+      register("R", typ("int"), Some(number(5))),
+      val("inc", function_type(vec![typ("int")], typ("int"))),
+      function("inc", vec![pattern("x")], block(vec![
+        on(&file, 2, assign(var("y"), call("add_int", vec![var("x"), number(1)]))),
+        on(&file, 3, var("y")),
+      ])),
+      val("main", function_type(vec![typ("unit")], typ("int"))),
+      function("main", vec![], block(vec![
+        on(&file, 6, assign(var("a"), number(1))),
+        on(&file, 6, assign(var("b"), call("inc", vec![var("a")]))),
+        on(&file, 7, assign(var("c"), call("inc", vec![var("b")]))),
+        on(&file, 8, var("c")),
+      ])),
+    ];
+    let definitions = definitions(program);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty());
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = Interpreter::new(&definitions, typing);
+    interpreter.initialise(&mut environment).unwrap();
+
+    let mut sources = Sources::default();
+    sources.add(file);
+    let stops = script.stops.clone();
+    interpreter.set_debugger(Some(Box::new(SourceDebugger::new(sources, script))));
+    let result = interpreter.call("main", vec![Value::Unit], &mut environment).unwrap();
+    assert_eq!(result.to_string(), "3");
+    stops.take()
+  }
+
+  fn at(function: &str, line: usize) -> At {
+    (Some(function.to_string()), Some(line))
+  }
+
+  fn strings(list: &[&str]) -> Vec<String> {
+    list.iter().map(|item| item.to_string()).collect()
+  }
+
+  fn summary(stops: &[Stop]) -> Vec<(Reason, At)> {
+    stops.iter().map(|stop| (stop.reason, stop.at.clone())).collect()
+  }
+
+  #[test]
+  fn stops_at_line_and_function_breakpoints() {
+    let stops = debug(Script {
+      breakpoints: vec![Place::Line("test.sail".to_string(), 3), Place::Function("inc".to_string())],
+      ..Script::default()
+    });
+    assert_eq!(summary(&stops), vec![
+      (Reason::Breakpoint(2), at("inc", 2)),
+      (Reason::Breakpoint(1), at("inc", 3)),
+      (Reason::Breakpoint(2), at("inc", 2)),
+      (Reason::Breakpoint(1), at("inc", 3)),
+    ]);
+    assert_eq!(stops[1].locals, strings(&["x = 1", "y = 2"]));
+    assert_eq!(stops[3].locals, strings(&["x = 2", "y = 3"]));
+  }
+
+  #[test]
+  fn stops_once_on_a_line_of_several_statements() {
+    let stops = debug(Script {
+      breakpoints: vec![Place::Line("model/test.sail".to_string(), 6), Place::Line("test.sail".to_string(), 7)],
+      ..Script::default()
+    });
+    assert_eq!(summary(&stops), vec![(Reason::Breakpoint(1), at("main", 6)), (Reason::Breakpoint(2), at("main", 7))]);
+    assert_eq!(stops[0].locals, Vec::<String>::new());
+    assert_eq!(stops[1].locals, strings(&["a = 1", "b = 2"]));
+  }
+
+  #[test]
+  fn steps_into_over_and_out_of_calls() {
+    let stops = debug(Script {
+      stop_on_entry: true,
+      resumes: VecDeque::from([Resume::StepOver, Resume::StepInto, Resume::StepInto, Resume::StepOut]),
+      ..Script::default()
+    });
+    assert_eq!(summary(&stops), vec![
+      (Reason::Entry, at("main", 6)),
+      // Over the rest of line 6, call and all.
+      (Reason::Step, at("main", 7)),
+      (Reason::Step, at("inc", 2)),
+      (Reason::Step, at("inc", 3)),
+      // Out of `inc` to the line after the call.
+      (Reason::Step, at("main", 8)),
+    ]);
+    assert_eq!(stops[4].locals, strings(&["a = 1", "b = 2", "c = 3"]));
+  }
+
+  #[test]
+  fn looks_up_locals_of_any_frame_and_registers() {
+    let stops = debug(Script {
+      breakpoints: vec![Place::Line("test.sail".to_string(), 3)],
+      lookups    : vec![(0, "y"), (1, "a"), (0, "R"), (0, "a"), (0, "missing")],
+      ..Script::default()
+    });
+    let first = &stops[0];
+    assert_eq!(first.at, at("inc", 3));
+    assert_eq!(first.lookups, vec![
+      Ok("y : {'#n. int('#n)} = 2".to_string()),
+      Ok("a : {'#n. int('#n)} = 1".to_string()),
+      Ok("R : {'#n. int('#n)} = 5".to_string()),
+      Err("nothing is called `a`".to_string()),
+      Err("nothing is called `missing`".to_string()),
+    ]);
+  }
+}
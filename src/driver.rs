@@ -0,0 +1,234 @@
+/*!
+
+Taking a loaded project to a program the interpreter can run.
+
+The tools built on the interpreter all start the same way: load the model's files (see `crate::project`), collect its
+scattered definitions, derive the functions Sail provides for bitfields and mappings, resolve its names, check its kinds
+and types, infer its effects, and check its pattern matches and termination measures. `check_model` runs those passes in
+that order and gathers what they report as `Diagnostic`s, located messages that no longer depend on which pass found
+them, so that a tool can print them uniformly with `describe_location`. Name resolution assumes the definitions are
+complete, type checking that the names resolve, and the passes after it that the types check, so each runs only if those
+before it found nothing fatal. As in Sail, a match that is not exhaustive is not fatal: the interpreter reports the
+failure if it happens. `check_more` runs the passes over definitions added to a checked model, as an interactive
+tool does.
+
+*/
+
+use std::fmt::Display;
+
+use codemap::CodeMap;
+
+use crate::parser::ast::Definitions;
+use crate::parser::location::{Located, SourceLocation};
+use crate::passes::bitfields::derive_bitfield_accessors;
+use crate::passes::effects::{infer_more_effects, EffectSummary};
+use crate::passes::mappings::invert_mappings;
+use crate::passes::patterns::check_patterns;
+use crate::passes::resolve::{resolve_more_names, resolve_names, SymbolTable};
+use crate::passes::scattered::collect_scattered;
+use crate::passes::termination::check_termination;
+use crate::passes::typecheck::{check_more_types, check_types, Typing};
+use crate::project::LoadedProject;
+
+/// A problem found while loading or checking a model. A model with fatal diagnostics cannot be run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub location: SourceLocation,
+  pub message : String,
+  pub fatal   : bool,
+}
+
+impl Diagnostic {
+  pub fn from_error<E: Display>(error: Located<E>, fatal: bool) -> Self {
+    Diagnostic { location: error.location, message: error.value.to_string(), fatal }
+  }
+
+  /// The diagnostic as `file:line:column: message`, or just the message if it has no place in the source.
+  pub fn describe(&self, code_map: &CodeMap) -> String {
+    match describe_location(code_map, &self.location) {
+      Some(location) => format!("{}: {}", location, self.message),
+      None => self.message.clone(),
+    }
+  }
+}
+
+/// A checked program, ready to be run.
+pub struct Model {
+  pub code_map   : CodeMap,
+  pub definitions: Definitions,
+  pub symbols    : SymbolTable,
+  pub typing     : Typing,
+  pub effects    : EffectSummary,
+}
+
+/// Runs the passes a loaded project needs before it can be run, returning the model and everything found along the
+/// way, including the project's own diagnostics.
+pub fn check_model(project: LoadedProject) -> (Model, Vec<Diagnostic>) {
+  let mut diagnostics: Vec<Diagnostic> = project
+      .diagnostics
+      .into_iter()
+      .map(|error| {
+        let fatal = error.is_fatal();
+        Diagnostic::from_error(error, fatal)
+      })
+      .collect();
+
+  let definitions = prepare(project.definitions, &mut diagnostics);
+  let mut model = Model {
+    code_map: project.code_map,
+    definitions,
+    symbols : SymbolTable::default(),
+    typing  : Typing::default(),
+    effects : EffectSummary::default(),
+  };
+  if is_fatal(&diagnostics) {
+    return (model, diagnostics);
+  }
+
+  let (symbols, errors) = resolve_names(&model.definitions);
+  model.symbols = symbols;
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  if is_fatal(&diagnostics) {
+    return (model, diagnostics);
+  }
+
+  let (typing, errors) = check_types(&model.definitions);
+  model.typing = typing;
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  if is_fatal(&diagnostics) {
+    return (model, diagnostics);
+  }
+  check_typed(&mut model.effects, &model.typing, &model.definitions, &mut diagnostics);
+  (model, diagnostics)
+}
+
+/// Runs the same passes over more definitions, following the model `symbols`, `typing` and `effects` describe, adding
+/// what the passes learn to them. Scattered definitions must be complete within `definitions`.
+pub fn check_more(
+  symbols    : &mut SymbolTable,
+  typing     : &mut Typing,
+  effects    : &mut EffectSummary,
+  definitions: Definitions,
+) -> (Definitions, Vec<Diagnostic>) {
+  let mut diagnostics = Vec::new();
+  let definitions = prepare(definitions, &mut diagnostics);
+  if is_fatal(&diagnostics) {
+    return (definitions, diagnostics);
+  }
+
+  let errors = resolve_more_names(symbols, &definitions);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  if is_fatal(&diagnostics) {
+    return (definitions, diagnostics);
+  }
+
+  let errors = check_more_types(typing, &definitions, None);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  if is_fatal(&diagnostics) {
+    return (definitions, diagnostics);
+  }
+  check_typed(effects, typing, &definitions, &mut diagnostics);
+  (definitions, diagnostics)
+}
+
+/// The passes before type checking.
+fn prepare(definitions: Definitions, diagnostics: &mut Vec<Diagnostic>) -> Definitions {
+  let (definitions, errors) = collect_scattered(definitions);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  let definitions = derive_bitfield_accessors(definitions);
+  let (definitions, errors) = invert_mappings(definitions);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  definitions
+}
+
+/// The passes after type checking, over definitions that checked without errors, adding their effects to `effects`.
+fn check_typed(effects: &mut EffectSummary, typing: &Typing, definitions: &Definitions, diagnostics: &mut Vec<Diagnostic>) {
+  let errors = infer_more_effects(effects, definitions);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+  let errors = check_patterns(definitions, typing);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, false)));
+  let errors = check_termination(definitions, typing, None);
+  diagnostics.extend(errors.into_iter().map(|error| Diagnostic::from_error(error, true)));
+}
+
+fn is_fatal(diagnostics: &[Diagnostic]) -> bool {
+  diagnostics.iter().any(|diagnostic| diagnostic.fatal)
+}
+
+/// The span in the source a location refers to, looking through generated and tagged locations.
+pub fn source_span(location: &SourceLocation) -> Option<codemap::Span> {
+  match location {
+    SourceLocation::Span(span) => Some(*span),
+    SourceLocation::Unique(_, location)
+    | SourceLocation::Generated(location)
+    | SourceLocation::Hint(_, _, location) => source_span(location),
+    SourceLocation::Unknown => None,
+  }
+}
+
+/// A location as `file:line:column`, counting from one.
+pub fn describe_location(code_map: &CodeMap, location: &SourceLocation) -> Option<String> {
+  source_span(location).map(|span| code_map.look_up_pos(span.low()).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::ast::{Literal, LocatedDefinition};
+  use crate::parser::testing::*;
+
+  fn check(program: Vec<LocatedDefinition>) -> (Model, Vec<(String, bool)>) {
+    let project = LoadedProject { code_map: CodeMap::new(), definitions: definitions(program), diagnostics: Vec::new(), modules: None };
+    let (model, diagnostics) = check_model(project);
+    (model, diagnostics.into_iter().map(|diagnostic| (diagnostic.message, diagnostic.fatal)).collect())
+  }
+
+  #[test]
+  fn stops_at_names_that_do_not_resolve() {
+    let (model, diagnostics) = check(vec![
+      val("f", function_type(vec![typ("int")], typ("int"))),
+      function("f", vec![pattern("n")], call("g", vec![var("n")])),
+    ]);
+    // The type checker would report `g` as well.
+    assert_eq!(diagnostics, vec![("undefined identifier `g`".to_string(), true)]);
+    assert!(model.typing.environment.functions.is_empty());
+  }
+
+  #[test]
+  fn reports_measures_that_do_not_decrease() {
+    let (_, diagnostics) = check(vec![
+      val("f", function_type(vec![typ("int")], typ("int"))),
+      measured_function("f", vec![pattern("n")], (pattern("n"), var("n")), call("f", vec![var("n")])),
+    ]);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].0.starts_with("cannot prove that the termination measure decreases"));
+    assert!(diagnostics[0].1);
+  }
+
+  #[test]
+  fn incomplete_matches_are_not_fatal() {
+    let (model, diagnostics) = check(vec![
+      val("f", function_type(vec![typ("bool")], typ("int"))),
+      function("f", vec![pattern("b")], matching(var("b"), vec![arm(literal_pattern(Literal::True), number(1))])),
+    ]);
+    assert_eq!(diagnostics, vec![("pattern match is not exhaustive; for example, `false` is not matched".to_string(), false)]);
+    assert!(model.typing.environment.functions.contains_key("f"));
+  }
+
+  #[test]
+  fn more_definitions_follow_the_model() {
+    let (mut model, _) = check(vec![val("f", function_type(vec![typ("int")], typ("int")))]);
+    let mut more = |program| {
+      let (_, diagnostics) = check_more(&mut model.symbols, &mut model.typing, &mut model.effects, definitions(program));
+      diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>()
+    };
+    assert_eq!(more(vec![function("f", vec![pattern("n")], var("n"))]), Vec::<String>::new());
+    assert_eq!(more(vec![value(pattern("x"), call("g", vec![number(1)]))]), vec!["undefined identifier `g`".to_string()]);
+    let recursive = more(vec![
+      val("h", function_type(vec![typ("int")], typ("int"))),
+      measured_function("h", vec![pattern("n")], (pattern("n"), var("n")), call("h", vec![call("f", vec![var("n")])])),
+    ]);
+    assert_eq!(recursive.len(), 1);
+    assert!(recursive[0].starts_with("cannot prove that the termination measure decreases"));
+  }
+}
@@ -0,0 +1,1161 @@
+/*!
+
+Evaluation of expressions and calls.
+
+*/
+
+use std::collections::HashMap;
+
+use crate::abstractions::{BigInteger, Integer, Rational};
+use crate::interpreter::{Environment, Frame, Interpreter, Outcome, Pause, RuntimeError, Unwind, BACKENDS, MAXIMUM_CALL_DEPTH};
+use crate::parser::ast::*;
+use crate::parser::ast_util::{expression_to_lvalue, resolve_expression_infix, strip_function_clause};
+use crate::parser::location::SourceLocation;
+use crate::passes::typecheck::{Constraint, NumericExpression, Substitution, Type, TypeArgument};
+use crate::runtime::{Bits, Primitive, Value};
+
+/// The type inside any existential.
+pub(super) fn unpack(typ: Type) -> Type {
+  match typ {
+    Type::Existential(_, _, inner) => unpack(*inner),
+    typ => typ,
+  }
+}
+
+/// The number of bits or elements in `high..low`, whichever way round they are.
+fn range_width(high: &BigInteger, low: &BigInteger) -> Result<usize, RuntimeError> {
+  Ok(high.try_sub(low)?.try_abs()?.try_to_usize()? + 1)
+}
+
+impl Interpreter {
+  // region Utilities
+
+  /// The type the checker recorded at `location`.
+  pub(super) fn type_at(&self, location: &SourceLocation) -> Type {
+    self.typing.types.get(location).cloned().unwrap_or(Type::Any)
+  }
+
+  pub(super) fn lookup(&self, name: &str) -> Option<&Value> {
+    self.frame.locals.iter().rev().find(|(local, _)| local == name).map(|(_, value)| value)
+  }
+
+  /// Runs `f`, then forgets the locals and type variables it introduced.
+  pub(super) fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+    let locals = self.frame.locals.len();
+    let type_variables = self.frame.type_variables.len();
+    let bitfields = self.frame.bitfields.len();
+    let types = self.frame.types.len();
+    let result = f(self);
+    self.frame.locals.truncate(locals);
+    self.frame.type_variables.truncate(type_variables);
+    self.frame.bitfields.truncate(bitfields);
+    self.frame.types.truncate(types);
+    result
+  }
+
+  /// Brings what matching a pattern bound into scope.
+  pub(super) fn enter(&mut self, bound: Frame) {
+    self.frame.locals.extend(bound.locals);
+    self.frame.type_variables.extend(bound.type_variables);
+    self.frame.bitfields.extend(bound.bitfields);
+    self.frame.types.extend(bound.types);
+  }
+
+  /// Hands the program to the debugger, if one is attached, before the statement at `location`.
+  pub(super) fn pause(&mut self, location: &SourceLocation, environment: &mut dyn Environment) -> Outcome<()> {
+    let Some(mut debugger) = self.debugger.take() else { return Ok(()) };
+    let entering = self.frame.position == SourceLocation::Unknown;
+    self.frame.position = location.clone();
+    let result = debugger.statement(Pause { interpreter: self, environment, location, entering });
+    self.debugger.get_or_insert(debugger);
+    result.map_err(|error| self.error(location, error))
+  }
+
+  /// The body of a clause, an arm or a `let`, which is a statement of its own unless it is a block of them.
+  fn body(&mut self, body: &LocatedExpression, environment: &mut dyn Environment) -> Outcome<Value> {
+    if !matches!(body.value, Expression::Block(_)) {
+      self.pause(&body.location, environment)?;
+    }
+    self.expression(body, environment)
+  }
+
+  pub(super) fn malformed(&self, location: &SourceLocation, what: &str) -> Unwind {
+    self.error(location, RuntimeError::Malformed(what.to_string()))
+  }
+
+  /// The value of `n`, given the type variables in scope.
+  pub(super) fn evaluate_numeric(&self, n: &NumericExpression) -> Result<BigInteger, RuntimeError> {
+    if let Some(value) = n.as_constant() {
+      return Ok(value);
+    }
+    // Later bindings shadow earlier ones.
+    let substitution: Substitution = self
+        .frame
+        .type_variables
+        .iter()
+        .map(|(variable, value)| (variable.clone(), TypeArgument::Integer(NumericExpression::Constant(value.clone()))))
+        .collect();
+    let n = n.substitute(&substitution);
+    if let Some(value) = n.as_constant() {
+      return Ok(value);
+    }
+    let mut variables = std::collections::BTreeSet::new();
+    n.free_variables(&mut variables);
+    match variables.into_iter().next() {
+      Some(variable) => Err(RuntimeError::UnknownTypeVariable(variable)),
+      None => Err(RuntimeError::Arithmetic(crate::abstractions::ArithmeticError::Overflow)),
+    }
+  }
+
+  /// The truth of `constraint`, given the type variables in scope.
+  pub(super) fn evaluate_constraint(&self, constraint: &Constraint) -> Result<bool, RuntimeError> {
+    let compare = |left: &NumericExpression, right: &NumericExpression| -> Result<std::cmp::Ordering, RuntimeError> {
+      Ok(self.evaluate_numeric(left)?.cmp(&self.evaluate_numeric(right)?))
+    };
+    let value = match constraint {
+      Constraint::True => true,
+      Constraint::False => false,
+      Constraint::Variable(variable) => return Err(RuntimeError::UnknownTypeVariable(variable.clone())),
+      Constraint::Equal(left, right) => compare(left, right)?.is_eq(),
+      Constraint::NotEqual(left, right) => compare(left, right)?.is_ne(),
+      Constraint::LessEqual(left, right) => compare(left, right)?.is_le(),
+      Constraint::Less(left, right) => compare(left, right)?.is_lt(),
+      Constraint::GreaterEqual(left, right) => compare(left, right)?.is_ge(),
+      Constraint::Greater(left, right) => compare(left, right)?.is_gt(),
+      Constraint::Set(n, values) => values.contains(&self.evaluate_numeric(n)?),
+      Constraint::And(left, right) => self.evaluate_constraint(left)? && self.evaluate_constraint(right)?,
+      Constraint::Or(left, right) => self.evaluate_constraint(left)? || self.evaluate_constraint(right)?,
+      Constraint::Not(inner) => !self.evaluate_constraint(inner)?,
+    };
+    Ok(value)
+  }
+
+  fn length_of(&self, n: &NumericExpression) -> Result<usize, RuntimeError> {
+    Ok(self.evaluate_numeric(n)?.try_to_usize()?)
+  }
+
+  /// A value of type `typ` for `undefined`.
+  pub(super) fn undefined(&self, typ: &Type) -> Result<Value, RuntimeError> {
+    let value = match typ {
+      Type::Unit
+      | Type::Any
+      | Type::Function(..)
+      | Type::Bidirectional(..)
+      | Type::Variable(_) => Value::Unit,
+      Type::Bit => Value::Bit(false),
+      Type::String => Value::String(String::new()),
+      Type::Real => Value::Real(Rational::zero()),
+      Type::Atom(n) => Value::Integer(self.evaluate_numeric(n).unwrap_or(BigInteger::from_i64(0))),
+      Type::AtomBool(p) => Value::Bool(self.evaluate_constraint(p).unwrap_or(false)),
+      Type::Bitvector(n) => Value::Bitvector(Bits::zeros(self.length_of(n)?)),
+      Type::Vector(n, element) => Value::Vector(vec![self.undefined(element)?; self.length_of(n)?]),
+      Type::List(_) => Value::List(Vec::new()),
+      Type::Register(inner) => self.undefined(inner)?,
+      Type::Tuple(elements) => {
+        Value::Tuple(elements.iter().map(|element| self.undefined(element)).collect::<Result<_, _>>()?)
+      }
+      Type::Existential(_, _, inner) => self.undefined(inner)?,
+      Type::Application(name, arguments) => {
+        let environment = &self.typing.environment;
+        if let Some(members) = environment.enums.get(name) {
+          return members.first().map(|member| Value::Member(member.clone())).ok_or(RuntimeError::Unsupported(format!("`undefined` of the empty enum `{}`", name)));
+        }
+        if let Some(bitfield) = environment.bitfields.get(name) {
+          return Ok(Value::Bitvector(Bits::zeros(self.length_of(&bitfield.width)?)));
+        }
+        if let Some(record) = environment.records.get(name) {
+          let substitution: Substitution =
+              record.variables.iter().map(|(variable, _)| variable.clone()).zip(arguments.iter().cloned()).collect();
+          let mut fields = Vec::new();
+          for (field, typ) in &record.fields {
+            fields.push((field.clone(), self.undefined(&typ.substitute(&substitution))?));
+          }
+          return Ok(Value::Record(fields));
+        }
+        if let Some(variant) = environment.variants.get(name) {
+          let substitution: Substitution =
+              variant.variables.iter().map(|(variable, _)| variable.clone()).zip(arguments.iter().cloned()).collect();
+          let constructor = variant.constructors.first().ok_or(RuntimeError::Unsupported(format!("`undefined` of the empty union `{}`", name)))?;
+          let payload = match environment.constructors.get(constructor).map(|function| function.arguments.as_slice()) {
+            Some([payload]) => self.undefined(&payload.substitute(&substitution))?,
+            _ => Value::Unit,
+          };
+          return Ok(Value::Constructor(constructor.clone(), Box::new(payload)));
+        }
+        return Err(RuntimeError::Unsupported(format!("`undefined` of type `{}`", name)));
+      }
+    };
+    Ok(value)
+  }
+
+  pub(super) fn literal(&self, literal: &Literal, location: &SourceLocation) -> Outcome<Value> {
+    let value = match literal {
+      Literal::Unit => Value::Unit,
+      Literal::Zero => Value::Bit(false),
+      Literal::One => Value::Bit(true),
+      Literal::True => Value::Bool(true),
+      Literal::False => Value::Bool(false),
+      Literal::Number(value) => Value::Integer(value.clone()),
+      Literal::Binary(digits) => Value::Bitvector(Bits::parse_binary(digits).ok_or_else(|| self.malformed(location, "a binary literal"))?),
+      Literal::Hexadecimal(digits) => {
+        Value::Bitvector(Bits::parse_hexadecimal(digits).ok_or_else(|| self.malformed(location, "a hexadecimal literal"))?)
+      }
+      Literal::String(value) => Value::String(value.clone()),
+      Literal::Real(value) => Value::Real(Rational::parse(value).map_err(|error| self.error(location, error.into()))?),
+      Literal::Undefined => self.undefined(&self.type_at(location)).map_err(|error| self.error(location, error))?,
+    };
+    Ok(value)
+  }
+
+  fn boolean(&mut self, expression: &LocatedExpression, environment: &mut dyn Environment) -> Outcome<bool> {
+    let value = self.expression(expression, environment)?;
+    value.as_bool().ok_or_else(|| self.malformed(&expression.location, "a boolean"))
+  }
+
+  fn integer(&mut self, expression: &LocatedExpression, environment: &mut dyn Environment) -> Outcome<BigInteger> {
+    let value = self.expression(expression, environment)?;
+    value.as_integer().cloned().ok_or_else(|| self.malformed(&expression.location, "an integer"))
+  }
+
+  // endregion
+
+  // region Vectors
+
+  fn access(&self, vector: &Value, index: &BigInteger, location: &SourceLocation) -> Outcome<Value> {
+    vector.access(index, self.typing.environment.increasing).map_err(|error| self.error(location, error))
+  }
+
+  fn subrange(&self, vector: &Value, high: &BigInteger, low: &BigInteger, location: &SourceLocation) -> Outcome<Value> {
+    vector.subrange(high, low, self.typing.environment.increasing).map_err(|error| self.error(location, error))
+  }
+
+  fn update(&self, vector: Value, index: &BigInteger, value: Value, location: &SourceLocation) -> Outcome<Value> {
+    vector.update(index, value, self.typing.environment.increasing).map_err(|error| self.error(location, error))
+  }
+
+  fn update_subrange(&self, vector: Value, high: &BigInteger, low: &BigInteger, value: Value, location: &SourceLocation) -> Outcome<Value> {
+    vector.update_subrange(high, low, value, self.typing.environment.increasing).map_err(|error| self.error(location, error))
+  }
+
+  fn append(&self, left: &Value, right: &Value, location: &SourceLocation) -> Outcome<Value> {
+    left.append(right).map_err(|error| self.error(location, error))
+  }
+
+  /// Splits `value` into consecutive slices of the given widths, the first the most significant.
+  pub(super) fn split(&self, value: &Value, widths: &[usize], location: &SourceLocation) -> Outcome<Vec<Value>> {
+    let mut position: usize = widths.iter().sum();
+    let mut slices = Vec::new();
+    for width in widths {
+      position -= width;
+      let slice = match value {
+        Value::Bitvector(bits) if bits.len() >= position + width => Value::Bitvector(bits.slice(position, *width)),
+        Value::Vector(elements) if elements.len() >= position + width => Value::Vector(elements[position..position + width].to_vec()),
+        _ => return Err(self.malformed(location, "a vector long enough for its parts")),
+      };
+      slices.push(slice);
+    }
+    Ok(slices)
+  }
+
+  /// The ranges of the field `field` of a bitfield type, most significant first.
+  fn bitfield_ranges(&self, typ: &Type, field: &str) -> Option<Vec<(BigInteger, BigInteger)>> {
+    match unpack(typ.clone()) {
+      Type::Application(name, _) => {
+        let (ranges, _) = self.typing.environment.bitfields.get(&name)?.field(field)?;
+        Some(ranges.to_vec())
+      }
+      _ => None,
+    }
+  }
+
+  /// A field of a bitfield, the concatenation of its ranges.
+  fn read_bitfield(&self, value: &Value, ranges: &[(BigInteger, BigInteger)], location: &SourceLocation) -> Outcome<Value> {
+    let mut result = Value::Bitvector(Bits::zeros(0));
+    for (high, low) in ranges {
+      let slice = self.subrange(value, high, low, location)?;
+      result = self.append(&result, &slice, location)?;
+    }
+    Ok(result)
+  }
+
+  /// `current` with the bits of a bitfield's field, spread over `ranges`, replaced by `value`.
+  fn update_bitfield(&self, current: Value, ranges: &[(BigInteger, BigInteger)], value: &Value, location: &SourceLocation) -> Outcome<Value> {
+    let widths = ranges
+        .iter()
+        .map(|(high, low)| range_width(high, low))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| self.error(location, error))?;
+    let pieces = self.split(value, &widths, location)?;
+    let mut current = current;
+    for ((high, low), piece) in ranges.iter().zip(pieces) {
+      current = self.update_subrange(current, high, low, piece, location)?;
+    }
+    Ok(current)
+  }
+
+  // endregion
+
+  // region Expressions
+
+  pub(super) fn expression(&mut self, expression: &LocatedExpression, environment: &mut dyn Environment) -> Outcome<Value> {
+    let location = &expression.location;
+    match &expression.value {
+      Expression::Block(statements) => self.scoped(|this| this.block(statements, environment)),
+
+      Expression::Identifier(identifier) => self.identifier(identifier, environment),
+
+      Expression::Reference(identifier) => Ok(Value::Reference(identifier.name().to_string())),
+
+      Expression::Dereference(inner) => match self.expression(inner, environment)? {
+        Value::Reference(name) => environment.read_register(&name).map_err(|error| self.error(location, error)),
+        _ => Err(self.malformed(location, "a register reference")),
+      },
+
+      Expression::Literal(literal) => self.literal(&literal.value, location),
+
+      Expression::Typed(_, inner)
+      | Expression::Attribute(_, _, inner)
+      | Expression::InternalAssume(_, inner) => self.expression(inner, environment),
+
+      Expression::Application(function, arguments) => match (self.resolved(function, location), arguments.as_slice()) {
+        ("and_bool", [left, right]) => self.lazy_boolean(true, left, right, environment),
+        ("or_bool", [left, right]) => self.lazy_boolean(false, left, right, environment),
+        _ => {
+          let arguments: Vec<&LocatedExpression> = arguments.iter().collect();
+          self.application(function, &arguments, location, environment)
+        }
+      },
+
+      Expression::InfixApplication(left, operator, right) => match self.resolved(operator, location) {
+        "and_bool" => self.lazy_boolean(true, left, right, environment),
+        "or_bool" => self.lazy_boolean(false, left, right, environment),
+        _ => self.application(operator, &[left, right], location, environment),
+      },
+
+      Expression::Infix(tokens) => {
+        let tree = match self.infix.get(location) {
+          Some(tree) => tree.clone(),
+          None => {
+            let tree = resolve_expression_infix(tokens, &self.typing.environment.fixities)
+                .ok_or_else(|| self.malformed(location, "a well-formed infix expression"))?;
+            let tree = std::rc::Rc::new(tree);
+            self.infix.insert(location.clone(), tree.clone());
+            tree
+          }
+        };
+        self.expression(&tree, environment)
+      }
+
+      Expression::Tuple(elements) => Ok(Value::Tuple(self.expressions(elements, environment)?)),
+
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        let branch = match self.boolean(condition, environment)? {
+          true => then_expr,
+          false => else_expr,
+        };
+        self.scoped(|this| this.expression(branch, environment))
+      }
+
+      Expression::Loop(loop_type, _, condition, body) => {
+        loop {
+          if let LoopType::While = loop_type {
+            if !self.scoped(|this| this.boolean(condition, environment))? {
+              break;
+            }
+          }
+          self.scoped(|this| this.expression(body, environment))?;
+          if let LoopType::Until = loop_type {
+            if self.scoped(|this| this.boolean(condition, environment))? {
+              break;
+            }
+          }
+        }
+        Ok(Value::Unit)
+      }
+
+      Expression::For { identifier, start, end, step, typ, body } => {
+        let start = self.integer(start, environment)?;
+        let end = self.integer(end, environment)?;
+        let step = self.integer(step, environment)?;
+        let increasing = !matches!(typ.value, AbstractType::Decreasing);
+        let mut index = start;
+        while (increasing && index <= end) || (!increasing && index >= end) {
+          self.scoped(|this| {
+            this.frame.locals.push((identifier.name().to_string(), Value::Integer(index.clone())));
+            this.expression(body, environment)
+          })?;
+          index = match increasing {
+            true => index.try_add(&step),
+            false => index.try_sub(&step),
+          }
+          .map_err(|error| self.error(location, error.into()))?;
+        }
+        Ok(Value::Unit)
+      }
+
+      Expression::Vector(elements) => {
+        let mut values = self.expressions(elements, environment)?;
+        values.reverse();
+        let is_bits = matches!(unpack(self.type_at(location)), Type::Bitvector(_))
+            || (!values.is_empty() && values.iter().all(|value| matches!(value, Value::Bit(_))));
+        match is_bits {
+          true => {
+            let mut bits = Bits::zeros(values.len());
+            for (position, value) in values.iter().enumerate() {
+              bits.set(position, matches!(value, Value::Bit(true)));
+            }
+            Ok(Value::Bitvector(bits))
+          }
+          false => Ok(Value::Vector(values)),
+        }
+      }
+
+      Expression::VectorAccess(vector, index) => {
+        let vector = self.expression(vector, environment)?;
+        let index = self.integer(index, environment)?;
+        self.access(&vector, &index, location)
+      }
+
+      Expression::VectorSubrange(vector, high, low) => {
+        let vector = self.expression(vector, environment)?;
+        let high = self.integer(high, environment)?;
+        let low = self.integer(low, environment)?;
+        self.subrange(&vector, &high, &low, location)
+      }
+
+      Expression::VectorUpdate(vector, index, value) => {
+        let vector = self.expression(vector, environment)?;
+        let index = self.integer(index, environment)?;
+        let value = self.expression(value, environment)?;
+        self.update(vector, &index, value, location)
+      }
+
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        let vector = self.expression(vector, environment)?;
+        let high = self.integer(high, environment)?;
+        let low = self.integer(low, environment)?;
+        let value = self.expression(value, environment)?;
+        self.update_subrange(vector, &high, &low, value, location)
+      }
+
+      Expression::VectorAppend(left, right) => {
+        let left = self.expression(left, environment)?;
+        let right = self.expression(right, environment)?;
+        self.append(&left, &right, location)
+      }
+
+      Expression::List(elements) => Ok(Value::List(self.expressions(elements, environment)?)),
+
+      Expression::Cons(head, tail) => {
+        let head = self.expression(head, environment)?;
+        match self.expression(tail, environment)? {
+          Value::List(mut elements) => {
+            elements.insert(0, head);
+            Ok(Value::List(elements))
+          }
+          _ => Err(self.malformed(location, "a list")),
+        }
+      }
+
+      Expression::Struct(fields) => {
+        let mut values = self.field_values(fields, environment)?;
+        // Fields are kept in the order the struct declares them.
+        if let Type::Application(name, _) = unpack(self.type_at(location)) {
+          if let Some(record) = self.typing.environment.records.get(&name) {
+            let order = |field: &String| record.fields.iter().position(|(name, _)| name == field);
+            values.sort_by_key(|(field, _)| order(field));
+          }
+        }
+        Ok(Value::Record(values))
+      }
+
+      Expression::StructUpdate(record, fields) => {
+        let typ = self.type_at(&record.location);
+        let mut current = self.expression(record, environment)?;
+        for (field, value) in self.field_values(fields, environment)? {
+          current = self.update_field(current, &typ, &field, value, location)?;
+        }
+        Ok(current)
+      }
+
+      Expression::Field(record, field) => {
+        let typ = self.type_at(&record.location);
+        let record = self.expression(record, environment)?;
+        self.read_field(&record, &typ, field.name(), location)
+      }
+
+      Expression::Match(scrutinee, arms) => {
+        let value = self.expression(scrutinee, environment)?;
+        for arm in arms {
+          if let Some(result) = self.arm(arm, &value, environment)? {
+            return Ok(result);
+          }
+        }
+        Err(self.error(location, RuntimeError::MatchFailure))
+      }
+
+      Expression::Try(body, arms) => match self.scoped(|this| this.expression(body, environment)) {
+        Err(Unwind::Throw(exception)) => {
+          for arm in arms {
+            if let Some(result) = self.arm(arm, &exception, environment)? {
+              return Ok(result);
+            }
+          }
+          Err(Unwind::Throw(exception))
+        }
+        result => result,
+      },
+
+      Expression::Let(binding, body) => {
+        let LetBinding::ValueBinding(pattern, value) = &binding.value;
+        self.let_binding(pattern, value, body, location, environment)
+      }
+
+      Expression::InternalPlet(pattern, value, body) => self.let_binding(pattern, value, body, location, environment),
+
+      Expression::Variable(target, value, body) => {
+        let Some(name) = assigned_name(target) else {
+          return Err(self.malformed(&target.location, "a variable name"));
+        };
+        let value = self.expression(value, environment)?;
+        self.scoped(|this| {
+          this.declare(name, &target.location, value);
+          this.expression(body, environment)
+        })
+      }
+
+      Expression::Assign(target, value) => {
+        let value = self.expression(value, environment)?;
+        self.assign(target, value, location, environment)?;
+        Ok(Value::Unit)
+      }
+
+      Expression::Sizeof(_) => match unpack(self.type_at(location)) {
+        Type::Atom(n) => Ok(Value::Integer(self.evaluate_numeric(&n).map_err(|error| self.error(location, error))?)),
+        _ => Err(self.error(location, RuntimeError::Unsupported("`sizeof` of an unknown integer".to_string()))),
+      },
+
+      Expression::Constraint(_) => match unpack(self.type_at(location)) {
+        Type::AtomBool(p) => Ok(Value::Bool(self.evaluate_constraint(&p).map_err(|error| self.error(location, error))?)),
+        _ => Err(self.error(location, RuntimeError::Unsupported("`constraint` of an unknown boolean".to_string()))),
+      },
+
+      Expression::Exit(inner) => {
+        self.expression(inner, environment)?;
+        Err(self.error(location, RuntimeError::Exit))
+      }
+
+      Expression::Throw(inner) => Err(Unwind::Throw(self.expression(inner, environment)?)),
+
+      Expression::Return(inner)
+      | Expression::InternalReturn(inner) => Err(Unwind::Return(self.expression(inner, environment)?)),
+
+      Expression::Assert(condition, message) => {
+        if self.boolean(condition, environment)? {
+          return Ok(Value::Unit);
+        }
+        let message = match self.expression(message, environment)? {
+          Value::String(message) => message,
+          other => other.to_string(),
+        };
+        Err(self.error(location, RuntimeError::AssertionFailed(message)))
+      }
+    }
+  }
+
+  fn expressions(&mut self, expressions: &[LocatedExpression], environment: &mut dyn Environment) -> Outcome<Vec<Value>> {
+    expressions.iter().map(|expression| self.expression(expression, environment)).collect()
+  }
+
+  /// The statements of a block. An assignment to an undeclared name declares a mutable variable for the rest.
+  fn block(&mut self, statements: &[LocatedExpression], environment: &mut dyn Environment) -> Outcome<Value> {
+    let mut result = Value::Unit;
+    for statement in statements {
+      self.pause(&statement.location, environment)?;
+      if let Expression::Assign(target, value) = &statement.value {
+        if let Some(name) = self.undeclared(target) {
+          let value = self.expression(value, environment)?;
+          self.declare(name, &target.location, value);
+          result = Value::Unit;
+          continue;
+        }
+        if let Expression::Tuple(targets) = &target.value {
+          for target in targets {
+            if let Some(name) = self.undeclared(target) {
+              // The assignment below gives it its value.
+              self.frame.locals.push((name.to_string(), Value::Unit));
+            }
+          }
+        }
+      }
+      result = self.expression(statement, environment)?;
+    }
+    Ok(result)
+  }
+
+  /// Declares a local variable whose type was recorded at `location`.
+  fn declare(&mut self, name: &str, location: &SourceLocation, value: Value) {
+    let mut frame = std::mem::take(&mut self.frame);
+    self.bind(&mut frame, name, &self.type_at(location), value);
+    self.frame = frame;
+  }
+
+  /// An assignment target that names nothing yet, and so declares a variable.
+  fn undeclared<'e>(&self, target: &'e LocatedExpression) -> Option<&'e str> {
+    let name = assigned_name(target)?;
+    let environment = &self.typing.environment;
+    match self.lookup(name).is_some() || environment.registers.contains_key(name) || environment.values.contains_key(name) {
+      true => None,
+      false => Some(name),
+    }
+  }
+
+  fn identifier(&mut self, identifier: &LocatedIdentifier, environment: &mut dyn Environment) -> Outcome<Value> {
+    let name = identifier.name();
+    if let Some(value) = self.lookup(name) {
+      return Ok(value.clone());
+    }
+    if self.typing.environment.enum_members.contains_key(name) {
+      return Ok(Value::Member(name.to_string()));
+    }
+    if self.typing.environment.registers.contains_key(name) {
+      return environment.read_register(name).map_err(|error| self.error(&identifier.location, error));
+    }
+    match self.globals.get(name) {
+      Some(value) => Ok(value.clone()),
+      None => Err(self.error(&identifier.location, RuntimeError::UnboundVariable(name.to_string()))),
+    }
+  }
+
+  /// `left & right` or `left | right`, which only evaluates `right` if it must.
+  fn lazy_boolean(&mut self, and: bool, left: &LocatedExpression, right: &LocatedExpression, environment: &mut dyn Environment) -> Outcome<Value> {
+    let left = self.boolean(left, environment)?;
+    if left != and {
+      return Ok(Value::Bool(left));
+    }
+    let right = self.scoped(|this| this.boolean(right, environment))?;
+    Ok(Value::Bool(right))
+  }
+
+  /// The fields of a `struct` expression or update, each written `field = value`.
+  fn field_values(&mut self, fields: &[LocatedExpression], environment: &mut dyn Environment) -> Outcome<Vec<(String, Value)>> {
+    let mut values = Vec::new();
+    for field in fields {
+      let Expression::Assign(name, value) = &field.value else {
+        return Err(self.malformed(&field.location, "`field = value`"));
+      };
+      let Expression::Identifier(name) = &name.value else {
+        return Err(self.malformed(&field.location, "`field = value`"));
+      };
+      let value = self.expression(value, environment)?;
+      values.push((name.name().to_string(), value));
+    }
+    Ok(values)
+  }
+
+  /// Reads `field` of `record`, a value of type `typ`. A bitfield's field is a slice of it.
+  fn read_field(&self, record: &Value, typ: &Type, field: &str, location: &SourceLocation) -> Outcome<Value> {
+    if let Some(ranges) = self.bitfield_ranges(typ, field) {
+      return self.read_bitfield(record, &ranges, location);
+    }
+    record.field(field).cloned().ok_or_else(|| self.malformed(location, &format!("a struct with a field `{}`", field)))
+  }
+
+  fn update_field(&self, record: Value, typ: &Type, field: &str, value: Value, location: &SourceLocation) -> Outcome<Value> {
+    if let Some(ranges) = self.bitfield_ranges(typ, field) {
+      return self.update_bitfield(record, &ranges, &value, location);
+    }
+    match record {
+      Value::Record(mut fields) => match fields.iter_mut().find(|(name, _)| name == field) {
+        Some((_, current)) => {
+          *current = value;
+          Ok(Value::Record(fields))
+        }
+        None => Err(self.malformed(location, &format!("a struct with a field `{}`", field))),
+      },
+      _ => Err(self.malformed(location, "a struct")),
+    }
+  }
+
+  /// `let pattern = value in body`.
+  fn let_binding(
+    &mut self,
+    pattern    : &LocatedPattern,
+    value      : &LocatedExpression,
+    body       : &LocatedExpression,
+    location   : &SourceLocation,
+    environment: &mut dyn Environment,
+  ) -> Outcome<Value> {
+    let value = self.expression(value, environment)?;
+    self.scoped(|this| {
+      let mut bound = Frame::default();
+      if !this.matches(pattern, &value, &mut bound, environment)? {
+        return Err(this.error(location, RuntimeError::MatchFailure));
+      }
+      this.enter(bound);
+      this.body(body, environment)
+    })
+  }
+
+  /// The result of `arm` if `value` matches its pattern and guard.
+  pub(super) fn arm(&mut self, arm: &LocatedPatternExpression, value: &Value, environment: &mut dyn Environment) -> Outcome<Option<Value>> {
+    let (pattern, guard, body) = match &arm.value {
+      PatternExpression::Pattern(pattern, body) => (pattern, None, body),
+      PatternExpression::PatternWhen(pattern, guard, body) => (pattern, Some(guard), body),
+    };
+    self.scoped(|this| {
+      let mut bound = Frame::default();
+      if !this.matches(pattern, value, &mut bound, environment)? {
+        return Ok(None);
+      }
+      this.enter(bound);
+      if let Some(guard) = guard {
+        if !this.boolean(guard, environment)? {
+          return Ok(None);
+        }
+      }
+      this.body(body, environment).map(Some)
+    })
+  }
+
+  // endregion
+
+  // region Assignments
+
+  fn assign(&mut self, target: &LocatedExpression, value: Value, location: &SourceLocation, environment: &mut dyn Environment) -> Outcome<()> {
+    match &target.value {
+      Expression::Typed(_, inner) => self.assign(inner, value, location, environment),
+
+      Expression::Tuple(targets) => match value {
+        Value::Tuple(values) if values.len() == targets.len() => {
+          for (target, value) in targets.iter().zip(values) {
+            self.assign(target, value, location, environment)?;
+          }
+          Ok(())
+        }
+        _ => Err(self.malformed(location, &format!("a tuple of {} values", targets.len()))),
+      },
+
+      _ => {
+        let Some(lvalue) = expression_to_lvalue(target) else {
+          return Err(self.malformed(&target.location, "something assignable"));
+        };
+        if let LValueExpression::Memory(setter, arguments) = &lvalue.value {
+          // `f(x) = v` calls `f` with `v` as well.
+          let name = self.typing.calls.get(location).map(|call| call.function.clone()).unwrap_or_else(|| setter.name().to_string());
+          let mut values = self.expressions(arguments, environment)?;
+          values.push(value);
+          self.call_function(&name, values, &HashMap::new(), location, environment)?;
+          return Ok(());
+        }
+        self.write(&lvalue, value, environment)
+      }
+    }
+  }
+
+  /// The type of the value `lvalue` holds, as far as assignments to the fields of bitfields need it.
+  fn lvalue_type(&self, lvalue: &LocatedLValueExpression) -> Type {
+    match &lvalue.value {
+      LValueExpression::Identifier(identifier) => {
+        let name = identifier.name();
+        if self.lookup(name).is_some() {
+          return match self.frame.bitfields.iter().rev().find(|(local, _)| local == name) {
+            Some((_, bitfield)) => Type::Application(bitfield.clone(), Vec::new()),
+            None => Type::Any,
+          };
+        }
+        self.typing.environment.registers.get(name).cloned().unwrap_or(Type::Any)
+      }
+      LValueExpression::Vector(vector, _) => match unpack(self.lvalue_type(vector)) {
+        Type::Vector(_, element) => *element,
+        _ => Type::Any,
+      },
+      LValueExpression::Field(record, field) => {
+        let Type::Application(name, arguments) = unpack(self.lvalue_type(record)) else {
+          return Type::Any;
+        };
+        let Some(record) = self.typing.environment.records.get(&name) else {
+          return Type::Any;
+        };
+        let substitution: Substitution =
+            record.variables.iter().map(|(variable, _)| variable.clone()).zip(arguments.iter().cloned()).collect();
+        match record.fields.iter().find(|(name, _)| name == field.name()) {
+          Some((_, typ)) => typ.substitute(&substitution),
+          None => Type::Any,
+        }
+      }
+      LValueExpression::Memory(..)
+      | LValueExpression::VectorRange(..)
+      | LValueExpression::VectorConcat(_) => Type::Any,
+    }
+  }
+
+  /// The current value of `lvalue`.
+  fn read(&mut self, lvalue: &LocatedLValueExpression, environment: &mut dyn Environment) -> Outcome<Value> {
+    let location = &lvalue.location;
+    match &lvalue.value {
+      LValueExpression::Identifier(identifier) => self.identifier(identifier, environment),
+      LValueExpression::Vector(vector, index) => {
+        let vector = self.read(vector, environment)?;
+        let index = self.integer(index, environment)?;
+        self.access(&vector, &index, location)
+      }
+      LValueExpression::VectorRange(vector, high, low) => {
+        let vector = self.read(vector, environment)?;
+        let high = self.integer(high, environment)?;
+        let low = self.integer(low, environment)?;
+        self.subrange(&vector, &high, &low, location)
+      }
+      LValueExpression::VectorConcat(parts) => {
+        let mut whole = Value::Bitvector(Bits::zeros(0));
+        for part in parts {
+          let part = self.read(part, environment)?;
+          whole = self.append(&whole, &part, location)?;
+        }
+        Ok(whole)
+      }
+      LValueExpression::Field(record, field) => {
+        let typ = self.lvalue_type(record);
+        let record = self.read(record, environment)?;
+        self.read_field(&record, &typ, field.name(), location)
+      }
+      LValueExpression::Memory(..) => Err(self.malformed(location, "something assignable")),
+    }
+  }
+
+  /// Writes `value` to `lvalue`. Writing to part of a value reads the whole, updates it, and writes it back.
+  fn write(&mut self, lvalue: &LocatedLValueExpression, value: Value, environment: &mut dyn Environment) -> Outcome<()> {
+    let location = &lvalue.location;
+    match &lvalue.value {
+      LValueExpression::Identifier(identifier) => {
+        let name = identifier.name();
+        if let Some((_, current)) = self.frame.locals.iter_mut().rev().find(|(local, _)| local == name) {
+          *current = value;
+          return Ok(());
+        }
+        if self.typing.environment.registers.contains_key(name) {
+          return environment.write_register(name, value).map_err(|error| self.error(location, error));
+        }
+        Err(self.error(location, RuntimeError::UnboundVariable(name.to_string())))
+      }
+
+      LValueExpression::Vector(vector, index) => {
+        let current = self.read(vector, environment)?;
+        let index = self.integer(index, environment)?;
+        let updated = self.update(current, &index, value, location)?;
+        self.write(vector, updated, environment)
+      }
+
+      LValueExpression::VectorRange(vector, high, low) => {
+        let current = self.read(vector, environment)?;
+        let high = self.integer(high, environment)?;
+        let low = self.integer(low, environment)?;
+        let updated = self.update_subrange(current, &high, &low, value, location)?;
+        self.write(vector, updated, environment)
+      }
+
+      LValueExpression::Field(record, field) => {
+        let typ = self.lvalue_type(record);
+        let current = self.read(record, environment)?;
+        let updated = self.update_field(current, &typ, field.name(), value, location)?;
+        self.write(record, updated, environment)
+      }
+
+      LValueExpression::VectorConcat(parts) => {
+        // Each part keeps its current length.
+        let mut widths = Vec::new();
+        for part in parts {
+          match self.read(part, environment)? {
+            Value::Bitvector(bits) => widths.push(bits.len()),
+            Value::Vector(elements) => widths.push(elements.len()),
+            _ => return Err(self.malformed(&part.location, "a vector")),
+          }
+        }
+        let slices = self.split(&value, &widths, location)?;
+        for (part, slice) in parts.iter().zip(slices) {
+          self.write(part, slice, environment)?;
+        }
+        Ok(())
+      }
+
+      LValueExpression::Memory(..) => Err(self.malformed(location, "something assignable")),
+    }
+  }
+
+  // endregion
+
+  // region Calls
+
+  /// The function the type checker resolved the call of `function` at `location` to.
+  fn resolved<'a>(&'a self, function: &'a LocatedIdentifier, location: &SourceLocation) -> &'a str {
+    self.typing.calls.get(location).map_or(function.name(), |call| call.function.as_str())
+  }
+
+  /// A call of `function`, which the type checker has resolved to a particular function.
+  fn application(
+    &mut self,
+    function   : &LocatedIdentifier,
+    arguments  : &[&LocatedExpression],
+    location   : &SourceLocation,
+    environment: &mut dyn Environment,
+  ) -> Outcome<Value> {
+    let call = self.typing.calls.get(location);
+    let name = call.map(|call| call.function.clone()).unwrap_or_else(|| function.name().to_string());
+    let mut values = Vec::new();
+    let mut instantiation = HashMap::new();
+    if let Some(call) = call {
+      for n in &call.implicit {
+        values.push(Value::Integer(self.evaluate_numeric(n).map_err(|error| self.error(location, error))?));
+      }
+      // Type variables instantiated with what is unknown here may still be found from the arguments.
+      for (variable, argument) in &call.instantiation {
+        if let TypeArgument::Integer(n) = argument {
+          if let Ok(value) = self.evaluate_numeric(n) {
+            instantiation.insert(variable.clone(), value);
+          }
+        }
+      }
+    }
+
+    let mut explicit = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+      explicit.push(self.expression(argument, environment)?);
+    }
+
+    // A function of a single tuple or of unit may be called with several values or none.
+    let parameters = self.typing.environment.functions.get(&name).map(|function| function.arguments.as_slice()).unwrap_or_default();
+    match (parameters, explicit.len()) {
+      ([Type::Unit], 0) => explicit.push(Value::Unit),
+      ([_], count) if count > 1 => explicit = vec![Value::Tuple(explicit)],
+      _ => {}
+    }
+    values.extend(explicit);
+    self.call_function(&name, values, &instantiation, location, environment)
+  }
+
+  /// The implementation registered for the extern function `name`, if it has one.
+  fn implementation(&self, name: &str) -> Option<Primitive> {
+    let bindings = self.bindings.get(name)?;
+    BACKENDS.iter().find_map(|backend| {
+      bindings
+          .iter()
+          .filter(|(binding, _)| binding == backend)
+          .find_map(|(_, implementation)| self.primitives.get(implementation).cloned())
+    })
+  }
+
+  /// Calls `name`, a union constructor, an extern function or a function of the program, in that order of preference.
+  /// `instantiation` gives the values of the function's type variables the caller knows.
+  pub(super) fn call_function(
+    &mut self,
+    name         : &str,
+    mut arguments: Vec<Value>,
+    instantiation: &HashMap<String, BigInteger>,
+    location     : &SourceLocation,
+    environment  : &mut dyn Environment,
+  ) -> Outcome<Value> {
+    if self.typing.environment.constructors.contains_key(name) {
+      let payload = match arguments.len() {
+        0 => Value::Unit,
+        1 => arguments.remove(0),
+        _ => Value::Tuple(arguments),
+      };
+      return Ok(Value::Constructor(name.to_string(), Box::new(payload)));
+    }
+    if !self.recording.is_empty() && self.recording.contains(name) {
+      self.recorded.push((name.to_string(), arguments.clone()));
+    }
+
+    let implementation = match self.implementation(name) {
+      Some(implementation) => Some(implementation),
+      None if self.functions.contains_key(name) => None,
+      None => self.primitives.get(name).cloned(),
+    };
+    if let Some(implementation) = implementation {
+      return implementation(&arguments, environment).map_err(|error| self.error(location, error));
+    }
+
+    let Some(function) = self.functions.get(name).cloned() else {
+      let error = match self.bindings.contains_key(name) {
+        true => RuntimeError::MissingExtern(name.to_string()),
+        false => RuntimeError::UnknownFunction(name.to_string()),
+      };
+      return Err(self.error(location, error));
+    };
+
+    if self.depth >= MAXIMUM_CALL_DEPTH {
+      return Err(self.error(location, RuntimeError::TooDeep));
+    }
+
+    let mut frame = Frame { function: name.to_string(), call: location.clone(), ..Frame::default() };
+    frame.type_variables.extend(instantiation.iter().map(|(variable, value)| (variable.clone(), value.clone())));
+    if let Some(function_type) = self.typing.environment.functions.get(name) {
+      if function_type.arguments.len() == arguments.len() {
+        for (typ, value) in function_type.arguments.iter().zip(&arguments) {
+          self.bind_type(&mut frame, typ, value);
+        }
+      }
+    }
+    let argument = match arguments.len() {
+      0 => Value::Unit,
+      1 => arguments.remove(0),
+      _ => Value::Tuple(arguments),
+    };
+
+    let caller = std::mem::replace(&mut self.frame, frame);
+    self.callers.push(caller);
+    self.depth += 1;
+    let FunctionDefinition::Function(_, _, _, clauses) = &function.value;
+    let mut result = Err(self.error(location, RuntimeError::MatchFailure));
+    for clause in clauses {
+      let (_, arm) = strip_function_clause(clause);
+      match self.arm(arm, &argument, environment) {
+        Ok(None) => continue,
+        Ok(Some(value)) => result = Ok(value),
+        Err(Unwind::Return(value)) => result = Ok(value),
+        Err(unwind) => result = Err(unwind),
+      }
+      break;
+    }
+    self.depth -= 1;
+    self.frame = self.callers.pop().expect("every call pushes its caller's frame");
+    result
+  }
+
+  // endregion
+}
+
+/// The name an assignment target or `var` declares, if it is an identifier, possibly annotated.
+fn assigned_name(target: &LocatedExpression) -> Option<&str> {
+  match &target.value {
+    Expression::Identifier(identifier) => Some(identifier.name()),
+    Expression::Typed(_, inner) => match &inner.value {
+      Expression::Identifier(identifier) => Some(identifier.name()),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::interpreter::tests::{arithmetic, byte, int, interpreter};
+  use crate::parser::ast::LocatedDefinition;
+  use crate::parser::testing::*;
+  use crate::runtime::{SimpleEnvironment, Value};
+
+  /// A union `exception` with the one constructor `E : int`.
+  fn exception() -> LocatedDefinition {
+    union("exception", vec![("E", typ("int"))])
+  }
+
+  #[test]
+  fn registers_keep_what_is_written() {
+    let mut program = arithmetic();
+    program.extend([
+      register("R", typ("int"), Some(number(1))),
+      val("bump", function_type(vec![typ("unit")], typ("int"))),
+      function("bump", vec![], block(vec![assign(var("R"), call("add_int", vec![var("R"), number(1)])), var("R")])),
+    ]);
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("bump", vec![Value::Unit], &mut environment).unwrap(), int(2));
+    assert_eq!(interpreter.call("bump", vec![Value::Unit], &mut environment).unwrap(), int(3));
+    assert_eq!(environment.registers["R"], int(3));
+  }
+
+  #[test]
+  fn memory_reads_what_was_written() {
+    let program = vec![
+      extern_val("read_mem", "read_mem", function_type(vec![typ("unit"), typ("int"), bits(8), typ("int")], bits(8))),
+      extern_val("write_mem", "write_mem", function_type(vec![typ("unit"), typ("int"), bits(8), typ("int"), bits(8)], typ("bool"))),
+      val("store", function_type(vec![bits(8)], bits(8))),
+      function(
+        "store",
+        vec![pattern("x")],
+        let_in(
+          wildcard(),
+          call("write_mem", vec![unit(), number(8), hex("0x10"), number(1), var("x")]),
+          call("read_mem", vec![unit(), number(8), hex("0x10"), number(1)]),
+        ),
+      ),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("store", vec![byte(0xAB)], &mut environment).unwrap(), byte(0xAB));
+    assert_eq!(environment.memory[&0x10], 0xAB);
+  }
+
+  #[test]
+  fn exceptions_are_caught_by_a_matching_arm() {
+    let program = vec![
+      exception(),
+      val("f", function_type(vec![typ("bool")], typ("int"))),
+      function(
+        "f",
+        vec![pattern("b")],
+        try_catch(
+          if_then_else(var("b"), throw(call("E", vec![number(3)])), number(1)),
+          vec![arm(constructor_pattern("E", vec![pattern("n")]), var("n"))],
+        ),
+      ),
+      val("g", function_type(vec![typ("unit")], typ("int"))),
+      function("g", vec![], throw(call("E", vec![number(4)]))),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("f", vec![Value::Bool(true)], &mut environment).unwrap(), int(3));
+    assert_eq!(interpreter.call("f", vec![Value::Bool(false)], &mut environment).unwrap(), int(1));
+    assert!(interpreter.call("g", vec![Value::Unit], &mut environment).is_err());
+  }
+
+  #[test]
+  fn loops_run_until_their_bounds() {
+    let counted = var_in(
+      var("i"),
+      number(0),
+      block(vec![
+        while_loop(call("lt_int", vec![var("i"), var("n")]), assign(var("i"), call("add_int", vec![var("i"), number(1)]))),
+        var("i"),
+      ]),
+    );
+    let summed = var_in(
+      var("total"),
+      number(0),
+      block(vec![
+        for_loop("k", number(1), var("n"), assign(var("total"), call("add_int", vec![var("total"), var("k")]))),
+        var("total"),
+      ]),
+    );
+    let mut program = arithmetic();
+    program.extend([
+      val("count", function_type(vec![typ("int")], typ("int"))),
+      function("count", vec![pattern("n")], counted),
+      val("sum", function_type(vec![typ("int")], typ("int"))),
+      function("sum", vec![pattern("n")], summed),
+    ]);
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("count", vec![int(4)], &mut environment).unwrap(), int(4));
+    assert_eq!(interpreter.call("count", vec![int(-1)], &mut environment).unwrap(), int(0));
+    assert_eq!(interpreter.call("sum", vec![int(3)], &mut environment).unwrap(), int(6));
+    assert_eq!(interpreter.call("sum", vec![int(0)], &mut environment).unwrap(), int(0));
+  }
+
+  #[test]
+  fn return_leaves_the_function() {
+    let program = vec![
+      val("f", function_type(vec![typ("bool")], typ("int"))),
+      function("f", vec![pattern("b")], block(vec![if_then_else(var("b"), early_return(number(1)), unit()), number(2)])),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("f", vec![Value::Bool(true)], &mut environment).unwrap(), int(1));
+    assert_eq!(interpreter.call("f", vec![Value::Bool(false)], &mut environment).unwrap(), int(2));
+  }
+}
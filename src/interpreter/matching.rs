@@ -0,0 +1,359 @@
+/*!
+
+Matching values against patterns.
+
+A pattern binds its variables into a `Frame` of its own, which the caller brings into scope only if the whole pattern
+matches, together with the type variables the pattern's types give the values of.
+
+*/
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::interpreter::evaluate::unpack;
+use crate::interpreter::{Environment, Frame, Interpreter, Outcome};
+use crate::parser::ast::*;
+use crate::passes::typecheck::{NumericExpression, Type};
+use crate::runtime::Value;
+
+impl Interpreter {
+  /// Whether `value` matches `pattern`, binding the pattern's variables into `frame`.
+  pub(super) fn matches(
+    &mut self,
+    pattern    : &LocatedPattern,
+    value      : &Value,
+    frame      : &mut Frame,
+    environment: &mut dyn Environment,
+  ) -> Outcome<bool> {
+    let location = &pattern.location;
+    match &pattern.value {
+      Pattern::Literal(literal) => match &literal.value {
+        Literal::Undefined => Ok(true),
+        literal => Ok(self.literal(literal, location)? == *value),
+      },
+
+      Pattern::Wildcard => Ok(true),
+
+      Pattern::Typed(abstract_type, inner)
+      | Pattern::Variable(inner, abstract_type) => {
+        if !self.matches(inner, value, frame, environment)? {
+          return Ok(false);
+        }
+        self.bind_annotation(frame, abstract_type, value);
+        Ok(true)
+      }
+
+      Pattern::Identifier(identifier) => {
+        let name = identifier.name();
+        if self.is_constant(name) {
+          return Ok(match value {
+            Value::Member(member) => member == name,
+            Value::Constructor(constructor, _) => constructor == name,
+            _ => false,
+          });
+        }
+        self.bind(frame, name, &self.type_at(location), value.clone());
+        Ok(true)
+      }
+
+      Pattern::Constructor(constructor, arguments) => {
+        let Value::Constructor(name, payload) = value else {
+          return Ok(false);
+        };
+        if name != constructor.name() {
+          return Ok(false);
+        }
+        match (arguments.as_slice(), &**payload) {
+          ([], _) => Ok(true),
+          ([argument], payload) => self.matches(argument, payload, frame, environment),
+          (arguments, Value::Tuple(elements)) => self.matches_all(arguments, elements, frame, environment),
+          _ => Ok(false),
+        }
+      }
+
+      Pattern::Vector(elements) => {
+        // Elements are written most significant first.
+        let values: Vec<Value> = match value {
+          Value::Bitvector(bits) => (0..bits.len()).rev().map(|position| Value::Bit(bits.get(position))).collect(),
+          Value::Vector(values) => values.iter().rev().cloned().collect(),
+          _ => return Ok(false),
+        };
+        self.matches_all(elements, &values, frame, environment)
+      }
+
+      Pattern::VectorConcat(parts) => {
+        let length = match value {
+          Value::Bitvector(bits) => bits.len(),
+          Value::Vector(elements) => elements.len(),
+          _ => return Ok(false),
+        };
+        let Some(widths) = self.part_widths(parts, length) else {
+          return Err(self.malformed(location, "a concatenation pattern with at most one part of unknown length"));
+        };
+        if widths.iter().sum::<usize>() != length {
+          return Ok(false);
+        }
+        let slices = self.split(value, &widths, location)?;
+        self.matches_all(parts, &slices, frame, environment)
+      }
+
+      Pattern::VectorSubrange(identifier, _, _) => {
+        self.bind(frame, identifier.name(), &Type::Any, value.clone());
+        Ok(true)
+      }
+
+      Pattern::Tuple(elements) => match (elements.as_slice(), value) {
+        ([element], value) => self.matches(element, value, frame, environment),
+        (elements, Value::Tuple(values)) => self.matches_all(elements, values, frame, environment),
+        _ => Ok(false),
+      },
+
+      Pattern::List(elements) => match value {
+        Value::List(values) => self.matches_all(elements, values, frame, environment),
+        _ => Ok(false),
+      },
+
+      Pattern::Cons(head, tail) => match value {
+        Value::List(values) if !values.is_empty() => {
+          Ok(self.matches(head, &values[0], frame, environment)?
+              && self.matches(tail, &Value::List(values[1..].to_vec()), frame, environment)?)
+        }
+        _ => Ok(false),
+      },
+
+      Pattern::StringAppend(parts) => match value {
+        Value::String(text) => self.matches_string(parts, text, frame, environment),
+        _ => Ok(false),
+      },
+
+      Pattern::Struct(fields) => {
+        for field in fields {
+          let FieldPattern::Field(name, pattern) = &field.value else {
+            continue;
+          };
+          let Some(value) = value.field(name.name()) else {
+            return Ok(false);
+          };
+          if !self.matches(pattern, value, frame, environment)? {
+            return Ok(false);
+          }
+        }
+        Ok(true)
+      }
+
+      Pattern::Attribute(_, _, inner) => self.matches(inner, value, frame, environment),
+    }
+  }
+
+  fn matches_all(
+    &mut self,
+    patterns   : &[LocatedPattern],
+    values     : &[Value],
+    frame      : &mut Frame,
+    environment: &mut dyn Environment,
+  ) -> Outcome<bool> {
+    if patterns.len() != values.len() {
+      return Ok(false);
+    }
+    for (pattern, value) in patterns.iter().zip(values) {
+      if !self.matches(pattern, value, frame, environment)? {
+        return Ok(false);
+      }
+    }
+    Ok(true)
+  }
+
+  /// Matches `text` against the concatenation `parts`, trying every way of dividing it between them.
+  fn matches_string(
+    &mut self,
+    parts      : &[LocatedPattern],
+    text       : &str,
+    frame      : &mut Frame,
+    environment: &mut dyn Environment,
+  ) -> Outcome<bool> {
+    let Some((first, rest)) = parts.split_first() else {
+      return Ok(text.is_empty());
+    };
+    if rest.is_empty() {
+      return self.matches(first, &Value::String(text.to_string()), frame, environment);
+    }
+    for (boundary, _) in text.char_indices().chain([(text.len(), ' ')]) {
+      let mut attempt = frame.clone();
+      if self.matches(first, &Value::String(text[..boundary].to_string()), &mut attempt, environment)?
+          && self.matches_string(rest, &text[boundary..], &mut attempt, environment)?
+      {
+        *frame = attempt;
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+
+  /// The widths of the parts of a concatenation pattern matching a vector of `length` elements, at most one of which
+  /// may be unknown and is what the others leave.
+  fn part_widths(&self, parts: &[LocatedPattern], length: usize) -> Option<Vec<usize>> {
+    let widths: Vec<Option<usize>> = parts.iter().map(|part| self.pattern_width(part)).collect();
+    let known: usize = widths.iter().flatten().sum();
+    match widths.iter().filter(|width| width.is_none()).count() {
+      0 => Some(widths.into_iter().flatten().collect()),
+      1 => Some(widths.into_iter().map(|width| width.unwrap_or(length.saturating_sub(known))).collect()),
+      _ => None,
+    }
+  }
+
+  /// The length of the vectors `pattern` matches, if it is known.
+  fn pattern_width(&self, pattern: &LocatedPattern) -> Option<usize> {
+    match &pattern.value {
+      Pattern::VectorSubrange(_, high, low) => {
+        let width = high.try_sub(low).ok()?.try_abs().ok()?.try_add(&BigInteger::from_i64(1)).ok()?;
+        return width.try_to_usize().ok();
+      }
+      Pattern::Literal(literal) => match &literal.value {
+        Literal::Binary(digits) => return Some(digits.trim_start_matches("0b").chars().filter(|c| *c != '_').count()),
+        Literal::Hexadecimal(digits) => return Some(digits.trim_start_matches("0x").chars().filter(|c| *c != '_').count() * 4),
+        _ => {}
+      },
+      Pattern::Vector(elements) => return Some(elements.len()),
+      _ => {}
+    }
+    match unpack(self.type_at(&pattern.location)) {
+      Type::Bitvector(n) | Type::Vector(n, _) => self.evaluate_numeric(&n).ok()?.try_to_usize().ok(),
+      _ => None,
+    }
+  }
+
+  /// Enum members and union constructors, which are not variables where they appear in patterns.
+  fn is_constant(&self, name: &str) -> bool {
+    let environment = &self.typing.environment;
+    self.lookup(name).is_none() && (environment.enum_members.contains_key(name) || environment.constructors.contains_key(name))
+  }
+
+  /// Binds `name` to `value`, of type `typ`, along with what its type says about type variables and bitfields.
+  pub(super) fn bind(&self, frame: &mut Frame, name: &str, typ: &Type, value: Value) {
+    self.bind_type(frame, typ, &value);
+    if let Type::Application(bitfield, _) = unpack(typ.clone()) {
+      if self.typing.environment.bitfields.contains_key(&bitfield) {
+        frame.bitfields.push((name.to_string(), bitfield));
+      }
+    }
+    if self.debugger.is_some() {
+      frame.types.push((name.to_string(), typ.clone()));
+    }
+    frame.locals.push((name.to_string(), value));
+  }
+
+  /// Binds the type variable a value of type `int('n)`, `bits('n)` or `vector('n, T)` gives the value of.
+  pub(super) fn bind_type(&self, frame: &mut Frame, typ: &Type, value: &Value) {
+    let binding = match (unpack(typ.clone()), value) {
+      (Type::Atom(NumericExpression::Variable(variable)), Value::Integer(value)) => (variable, value.clone()),
+      (Type::Bitvector(NumericExpression::Variable(variable)), Value::Bitvector(bits)) => {
+        (variable, BigInteger::from_i64(bits.len() as i64))
+      }
+      (Type::Vector(NumericExpression::Variable(variable), _), Value::Vector(elements)) => {
+        (variable, BigInteger::from_i64(elements.len() as i64))
+      }
+      _ => return,
+    };
+    frame.type_variables.push(binding);
+  }
+
+  /// Binds the type variable an annotation such as `x : bits('n)` or `x as int('n)` introduces.
+  fn bind_annotation(&self, frame: &mut Frame, abstract_type: &LocatedAbstractType, value: &Value) {
+    let variable = match &abstract_type.value {
+      AbstractType::Parenthesized(inner) => return self.bind_annotation(frame, inner, value),
+      AbstractType::Variable(variable) => variable,
+      AbstractType::TypeConstructorApplication(constructor, arguments) => match (constructor.name(), arguments.first().map(|argument| &argument.value)) {
+        ("atom" | "int" | "bits" | "bitvector" | "vector", Some(AbstractType::Variable(variable))) => variable,
+        _ => return,
+      },
+      _ => return,
+    };
+    let length = match value {
+      Value::Integer(value) => value.clone(),
+      Value::Bitvector(bits) => BigInteger::from_i64(bits.len() as i64),
+      Value::Vector(elements) => BigInteger::from_i64(elements.len() as i64),
+      _ => return,
+    };
+    frame.type_variables.push((variable.value.0.clone(), length));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::interpreter::tests::{arithmetic, int, interpreter};
+  use crate::parser::ast::Literal;
+  use crate::parser::testing::*;
+  use crate::runtime::{SimpleEnvironment, Value};
+
+  #[test]
+  fn arms_are_tried_in_order() {
+    let program = vec![
+      val("f", function_type(vec![typ("int"), typ("bool")], typ("int"))),
+      function(
+        "f",
+        vec![pattern("x"), pattern("b")],
+        matching(tuple(vec![var("x"), var("b")]), vec![
+          arm(tuple_pattern(vec![number_pattern(0), wildcard()]), number(10)),
+          arm(tuple_pattern(vec![pattern("n"), literal_pattern(Literal::True)]), var("n")),
+          arm(wildcard(), number(20)),
+        ]),
+      ),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("f", vec![int(0), Value::Bool(true)], &mut environment).unwrap(), int(10));
+    assert_eq!(interpreter.call("f", vec![int(5), Value::Bool(true)], &mut environment).unwrap(), int(5));
+    assert_eq!(interpreter.call("f", vec![int(5), Value::Bool(false)], &mut environment).unwrap(), int(20));
+  }
+
+  #[test]
+  fn constructors_bind_their_arguments() {
+    let mut program = arithmetic();
+    program.extend([
+      union("U", vec![("A", typ("int")), ("B", typ("bool"))]),
+      val("g", function_type(vec![typ("U")], typ("int"))),
+      function("g", vec![pattern("u")], matching(var("u"), vec![
+        arm(constructor_pattern("A", vec![pattern("n")]), var("n")),
+        arm(constructor_pattern("B", vec![pattern("b")]), if_then_else(var("b"), number(1), number(0))),
+      ])),
+      val("h", function_type(vec![typ("bool")], typ("int"))),
+      function("h", vec![pattern("b")], call("add_int", vec![
+        call("g", vec![call("A", vec![number(5)])]),
+        call("g", vec![call("B", vec![var("b")])]),
+      ])),
+    ]);
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("h", vec![Value::Bool(true)], &mut environment).unwrap(), int(6));
+    assert_eq!(interpreter.call("h", vec![Value::Bool(false)], &mut environment).unwrap(), int(5));
+  }
+
+  #[test]
+  fn false_guards_fall_through() {
+    let program = vec![
+      val("f", function_type(vec![typ("int"), typ("bool")], typ("int"))),
+      function("f", vec![pattern("x"), pattern("b")], matching(var("x"), vec![
+        guarded_arm(pattern("n"), var("b"), var("n")),
+        arm(wildcard(), number(0)),
+      ])),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("f", vec![int(7), Value::Bool(true)], &mut environment).unwrap(), int(7));
+    assert_eq!(interpreter.call("f", vec![int(7), Value::Bool(false)], &mut environment).unwrap(), int(0));
+  }
+
+  #[test]
+  fn unmatched_values_are_errors() {
+    let program = vec![
+      val("f", function_type(vec![typ("int")], typ("int"))),
+      function("f", vec![pattern("x")], matching(var("x"), vec![arm(number_pattern(0), number(1))])),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("f", vec![int(0)], &mut environment).unwrap(), int(1));
+    assert!(interpreter.call("f", vec![int(2)], &mut environment).is_err());
+  }
+}
@@ -0,0 +1,474 @@
+/*!
+
+A tree-walking interpreter for type-checked Sail programs.
+
+The interpreter runs the AST directly, consulting what the type checker learned about it (see `Typing`): the function
+each call resolved to, so that overloads and mapping directions need no work at run time, the values of implicit
+arguments, and the types needed for `undefined`, `sizeof` and bitvector literals. The program must have been checked
+without errors, and its mappings replaced by their functions (see `passes::mappings`).
+
+Everything outside the program goes through two extension points:
+
+ * an `Environment` holds the machine state, registers and memory, which `SimpleEnvironment` keeps in maps and a
+   `RegisterFile` from `register_file` keeps as the program declares them, and
+ * extern functions, those whose `val` specification binds them to an implementation, are `Primitives` looked up by
+   the name of their implementation. Sail's standard primitives are there from the start, and `define_extern` adds
+   others. The binding for the `interpreter` backend is preferred, then the one for all backends, `_`, then those for
+   `c` and `ocaml`, whose names Sail's libraries keep consistent.
+
+Type variables are values at run time where the program needs them, as in `sizeof('n)`. A function's are found from
+its arguments, an argument of type `int('n)` giving `'n` and one of type `bits('n)` its length, and from what the call
+instantiates them with, as are those of patterns such as `x as int('n)` and of local variables.
+
+A `Debugger` attached with `set_debugger` is consulted before every statement, and may inspect the calls in progress,
+their local variables and the machine state while the program waits. For lighter observation, `record_calls` keeps the
+arguments of every call to the functions named, as a tracer does to see which instruction `execute` was given.
+
+`undefined` is zero, `false`, the first member of an enum or the first constructor of a union, as the interpreter is
+free to choose.
+
+*/
+
+mod evaluate;
+mod matching;
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::ast_util::{function_name, strip_definition};
+use crate::parser::location::{Located, SourceLocation};
+use crate::passes::typecheck::{Type, Typing};
+use crate::interpreter::evaluate::unpack;
+use crate::runtime::{Primitives, RegisterDeclaration, RegisterFile, Value};
+
+pub use crate::runtime::{Environment, LocatedRuntimeError, RuntimeError, SimpleEnvironment};
+
+/// How deeply calls may nest before the interpreter gives up, well before the Rust stack would run out.
+pub const MAXIMUM_CALL_DEPTH: usize = 1_000;
+
+/// The backends whose extern bindings the interpreter uses, in order of preference.
+const BACKENDS: [&str; 4] = ["interpreter", "_", "c", "ocaml"];
+
+
+/// How evaluation stops short of producing a value.
+enum Unwind {
+  Return(Value),
+  Throw(Value),
+  Error(LocatedRuntimeError),
+}
+
+type Outcome<T> = Result<T, Unwind>;
+
+/// The variables of the function running.
+#[derive(Clone, Default)]
+struct Frame {
+  locals        : Vec<(String, Value)>,
+  type_variables: Vec<(String, BigInteger)>,
+  /// The locals holding bitfields, with the bitfields' names, which assignments to their fields need
+  bitfields     : Vec<(String, String)>,
+  /// The types of locals bound by patterns, kept only while a debugger is attached
+  types         : Vec<(String, Type)>,
+  /// The function running, empty outside any function
+  function      : String,
+  /// Where the function was called from
+  call          : SourceLocation,
+  /// The statement running, kept only while a debugger is attached
+  position      : SourceLocation,
+}
+
+/// Something watching a program run, such as a source-level debugger.
+pub trait Debugger {
+  /// Called before each statement runs. The debugger is detached from the interpreter meanwhile, so that it may use
+  /// the interpreter to inspect the program. An error stops the program.
+  fn statement(&mut self, pause: Pause<'_>) -> Result<(), RuntimeError>;
+
+  /// Called by whoever ran the program once it has finished, with its exit code.
+  fn finished(&mut self, _exit_code: i64) {}
+}
+
+/// A program waiting for a debugger before a statement.
+pub struct Pause<'p> {
+  pub interpreter: &'p mut Interpreter,
+  pub environment: &'p mut dyn Environment,
+  pub location   : &'p SourceLocation,
+  /// Whether the statement is the first of the innermost call
+  pub entering   : bool,
+}
+
+/// A call in progress, as a debugger sees it.
+pub struct StackFrame<'i> {
+  /// The function called, empty outside any function
+  pub function: &'i str,
+  /// Where it was called from
+  pub call    : &'i SourceLocation,
+  /// The statement running, which for a caller is the one making the call
+  pub position: &'i SourceLocation,
+  frame       : &'i Frame,
+}
+
+impl StackFrame<'_> {
+  /// The local variables in scope, in the order they were bound, without those shadowed, and their types where the
+  /// type checker recorded them.
+  pub fn locals(&self) -> Vec<(&str, &Value, Option<&Type>)> {
+    let mut locals: Vec<(&str, &Value, Option<&Type>)> = Vec::new();
+    for (index, (name, value)) in self.frame.locals.iter().enumerate() {
+      if self.frame.locals[index + 1..].iter().all(|(later, _)| later != name) {
+        locals.push((name, value, self.local_type(name)));
+      }
+    }
+    locals
+  }
+
+  pub fn local(&self, name: &str) -> Option<&Value> {
+    self.frame.locals.iter().rev().find(|(local, _)| local == name).map(|(_, value)| value)
+  }
+
+  pub fn local_type(&self, name: &str) -> Option<&Type> {
+    self.frame.types.iter().rev().find(|(local, _)| local == name).map(|(_, typ)| typ)
+  }
+}
+
+pub struct Interpreter {
+  typing     : Typing,
+  functions  : HashMap<String, Rc<LocatedFunctionDefinition>>,
+  /// The extern bindings of each function that has them
+  bindings   : HashMap<String, Vec<(String, String)>>,
+  primitives : Primitives,
+  /// Register declarations and top-level `let`s, in program order
+  state      : Vec<Rc<LocatedDefinition>>,
+  globals    : HashMap<String, Value>,
+  /// Infix expressions resolved into trees, by location
+  infix      : HashMap<SourceLocation, Rc<LocatedExpression>>,
+  frame      : Frame,
+  /// The frames of the calls the current one was made from, outermost first
+  callers    : Vec<Frame>,
+  depth      : usize,
+  debugger   : Option<Box<dyn Debugger>>,
+  /// The functions whose calls are recorded
+  recording  : HashSet<String>,
+  /// Their calls since `take_recorded_calls`, with their arguments
+  recorded   : Vec<(String, Vec<Value>)>,
+}
+
+impl Interpreter {
+  /// An interpreter for `definitions`, which `typing` is the result of checking.
+  pub fn new(definitions: &Definitions, typing: Typing) -> Self {
+    let mut interpreter = Interpreter {
+      typing,
+      functions: HashMap::new(),
+      bindings : HashMap::new(),
+      primitives: Primitives::standard(),
+      state    : Vec::new(),
+      globals  : HashMap::new(),
+      infix    : HashMap::new(),
+      frame    : Frame::default(),
+      callers  : Vec::new(),
+      depth    : 0,
+      debugger : None,
+      recording: HashSet::new(),
+      recorded : Vec::new(),
+    };
+    interpreter.add_definitions(definitions);
+    interpreter
+  }
+
+  /// Adds the functions, externs, registers and values of `definitions`, replacing any of the same names.
+  pub fn add_definitions(&mut self, definitions: &Definitions) {
+    for (_, file) in definitions.0.iter() {
+      for definition in file {
+        self.add_definition(definition);
+      }
+    }
+  }
+
+  fn add_definition(&mut self, definition: &LocatedDefinition) {
+    let (definition, _) = strip_definition(definition);
+    match &definition.value {
+      Definition::FunctionDefinition(function) => self.add_function(function),
+
+      Definition::InternalMutRec(functions) => {
+        for function in functions {
+          self.add_function(function);
+        }
+      }
+
+      Definition::ValueSpec(specification) => {
+        if let ValueSpecification::ValueSpec(_, name, Some(bindings)) = &specification.value {
+          self.bindings.insert(name.name().to_string(), bindings.bindings.clone());
+        }
+      }
+
+      Definition::OutcomeSpec(_, definitions) => {
+        for definition in definitions {
+          self.add_definition(definition);
+        }
+      }
+
+      Definition::Register(_)
+      | Definition::ValueDefinition(_) => self.state.push(Rc::new(definition.clone())),
+
+      _ => {}
+    }
+  }
+
+  fn add_function(&mut self, function: &LocatedFunctionDefinition) {
+    if let Some(name) = function_name(&function.value) {
+      self.functions.insert(name.name().to_string(), Rc::new(function.clone()));
+    }
+  }
+
+  /// Registers the implementation of extern functions bound to `name`, replacing any primitive of that name.
+  pub fn define_extern(
+    &mut self,
+    name          : &str,
+    implementation: impl Fn(&[Value], &mut dyn Environment) -> Result<Value, RuntimeError> + 'static,
+  ) {
+    self.primitives.define(name, implementation);
+  }
+
+  /// Adds `primitives`, replacing any of the same names.
+  pub fn add_primitives(&mut self, primitives: &Primitives) {
+    self.primitives.extend(primitives);
+  }
+
+  /// The implementations of extern functions.
+  pub fn primitives(&self) -> &Primitives {
+    &self.primitives
+  }
+
+  /// What the type checker learned about the program.
+  pub fn typing(&self) -> &Typing {
+    &self.typing
+  }
+
+  /// What the type checker learned about the program, for adding to as more of it is checked.
+  pub fn typing_mut(&mut self) -> &mut Typing {
+    &mut self.typing
+  }
+
+  /// Attaches `debugger`, or detaches the one attached with `None`, returning the one attached before.
+  pub fn set_debugger(&mut self, debugger: Option<Box<dyn Debugger>>) -> Option<Box<dyn Debugger>> {
+    std::mem::replace(&mut self.debugger, debugger)
+  }
+
+  /// Records the arguments of every call to the functions `names` from now on, replacing any named before.
+  pub fn record_calls(&mut self, names: impl IntoIterator<Item = String>) {
+    self.recording = names.into_iter().collect();
+  }
+
+  /// The calls recorded since this was last called, in the order they were made.
+  pub fn take_recorded_calls(&mut self) -> Vec<(String, Vec<Value>)> {
+    std::mem::take(&mut self.recorded)
+  }
+
+  /// The calls in progress, innermost first.
+  pub fn stack(&self) -> Vec<StackFrame<'_>> {
+    let callers = self.callers.iter().rev().filter(|frame| !frame.function.is_empty());
+    std::iter::once(&self.frame)
+        .chain(callers)
+        .map(|frame| StackFrame { function: &frame.function, call: &frame.call, position: &frame.position, frame })
+        .collect()
+  }
+
+  /// The value of a top-level `let`, once `initialise` has computed it.
+  pub fn global(&self, name: &str) -> Option<&Value> {
+    self.globals.get(name)
+  }
+
+  /// Computes the top-level `let`s and writes every register's initial value, or an undefined value of its type if it
+  /// has none, to `environment`, in program order.
+  pub fn initialise(&mut self, environment: &mut dyn Environment) -> Result<(), LocatedRuntimeError> {
+    self.initialise_state(self.state.clone(), environment)
+  }
+
+  /// Adds `definitions`, as `add_definitions` does, and computes the top-level `let`s and registers' initial values
+  /// among them, leaving those of earlier definitions as they are. The definitions' types must have been added to the
+  /// interpreter's `Typing` first (see `typing_mut`).
+  pub fn extend(&mut self, definitions: &Definitions, environment: &mut dyn Environment) -> Result<(), LocatedRuntimeError> {
+    let known = self.state.len();
+    self.add_definitions(definitions);
+    self.initialise_state(self.state[known..].to_vec(), environment)
+  }
+
+  fn initialise_state(
+    &mut self,
+    state      : Vec<Rc<LocatedDefinition>>,
+    environment: &mut dyn Environment,
+  ) -> Result<(), LocatedRuntimeError> {
+    for definition in state {
+      match &definition.value {
+        Definition::Register(declaration) => {
+          let DeclarationSpecification::Register(_, name, initial) = &declaration.value;
+          let value = match initial {
+            Some(initial) => self.run(|this| this.expression(initial, environment))?,
+            None => {
+              let typ = self.typing.environment.registers.get(name.name()).cloned().unwrap_or(Type::Any);
+              self.undefined(&typ).map_err(|error| Located { location: declaration.location.clone(), value: error })?
+            }
+          };
+          environment.write_register(name.name(), value).map_err(|error| Located { location: name.location.clone(), value: error })?;
+        }
+
+        Definition::ValueDefinition(binding) => {
+          let LetBinding::ValueBinding(pattern, value) = &binding.value;
+          self.run(|this| {
+            let value = this.expression(value, environment)?;
+            let mut bound = Frame::default();
+            if !this.matches(pattern, &value, &mut bound, environment)? {
+              return Err(this.error(&binding.location, RuntimeError::MatchFailure));
+            }
+            for (name, value) in bound.locals {
+              this.globals.insert(name, value);
+            }
+            Ok(Value::Unit)
+          })?;
+        }
+
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  /// A register file holding the program's registers, with the initial values `initialise` gives them.
+  pub fn register_file(&mut self) -> Result<RegisterFile, LocatedRuntimeError> {
+    let mut registers = RegisterFile::new(self.typing.environment.increasing);
+    for definition in &self.state {
+      if let Definition::Register(declaration) = &definition.value {
+        let DeclarationSpecification::Register(_, name, _) = &declaration.value;
+        registers.declare(self.register_declaration(name.name()), Value::Unit);
+      }
+    }
+    self.initialise(&mut registers)?;
+    registers.record_initial_values();
+    Ok(registers)
+  }
+
+  /// What a register file needs to know of the register `name`'s type.
+  fn register_declaration(&self, name: &str) -> RegisterDeclaration {
+    let typ = self.typing.environment.registers.get(name).cloned().unwrap_or(Type::Any);
+    let mut declaration = RegisterDeclaration { name: name.to_string(), ..RegisterDeclaration::default() };
+    match unpack(typ) {
+      Type::Bitvector(n) => declaration.width = self.evaluate_numeric(&n).ok().and_then(|width| width.try_to_usize().ok()),
+      Type::Application(bitfield, _) => {
+        if let Some(definition) = self.typing.environment.bitfields.get(&bitfield) {
+          declaration.width = self.evaluate_numeric(&definition.width).ok().and_then(|width| width.try_to_usize().ok());
+          declaration.fields = definition.fields.clone();
+          declaration.bitfield = Some(bitfield);
+        }
+      }
+      _ => {}
+    }
+    declaration
+  }
+
+  /// Calls the function `name` with `arguments`, which for a function of several parameters are its parameters, and
+  /// include any implicit ones.
+  pub fn call(
+    &mut self,
+    name       : &str,
+    arguments  : Vec<Value>,
+    environment: &mut dyn Environment,
+  ) -> Result<Value, LocatedRuntimeError> {
+    self.run(|this| this.call_function(name, arguments, &HashMap::new(), &SourceLocation::Unknown, environment))
+  }
+
+  /// Evaluates an expression outside any function, with `locals` in scope. The expression's types must be in the
+  /// `Typing` the interpreter was made with.
+  pub fn evaluate(
+    &mut self,
+    expression : &LocatedExpression,
+    locals     : Vec<(String, Value)>,
+    environment: &mut dyn Environment,
+  ) -> Result<Value, LocatedRuntimeError> {
+    let saved = std::mem::replace(&mut self.frame, Frame { locals, ..Frame::default() });
+    let result = self.run(|this| this.expression(expression, environment));
+    self.frame = saved;
+    result
+  }
+
+  /// Runs `f`, turning an exception that escapes it into an error and a `return` into its value.
+  fn run(&mut self, f: impl FnOnce(&mut Self) -> Outcome<Value>) -> Result<Value, LocatedRuntimeError> {
+    let depth = self.depth;
+    let result = f(self);
+    self.depth = depth;
+    match result {
+      Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+      Err(Unwind::Throw(value)) => Err(Located { location: SourceLocation::Unknown, value: RuntimeError::UncaughtException(value) }),
+      Err(Unwind::Error(error)) => Err(error),
+    }
+  }
+
+  fn error(&self, location: &SourceLocation, error: RuntimeError) -> Unwind {
+    Unwind::Error(Located { location: location.clone(), value: error })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+  use crate::runtime::Bits;
+
+  /// An interpreter for `program`, which must check without errors, with its state initialised in `environment`.
+  pub(super) fn interpreter(program: Vec<LocatedDefinition>, environment: &mut SimpleEnvironment) -> Interpreter {
+    let definitions = definitions(program);
+    let (typing, errors) = check_types(&definitions);
+    let messages: Vec<String> = errors.iter().map(|error| error.value.to_string()).collect();
+    assert_eq!(messages, Vec::<String>::new());
+    let mut interpreter = Interpreter::new(&definitions, typing);
+    interpreter.initialise(environment).unwrap();
+    interpreter
+  }
+
+  pub(super) fn byte(value: u64) -> Value {
+    Value::Bitvector(Bits::from_u64(value, 8))
+  }
+
+  pub(super) fn int(value: i64) -> Value {
+    Value::Integer(BigInteger::from_i64(value))
+  }
+
+  /// `add_int` and `lt_int`, which the tests call by name rather than through operators.
+  pub(super) fn arithmetic() -> Vec<LocatedDefinition> {
+    vec![
+      extern_val("add_int", "add_int", function_type(vec![typ("int"), typ("int")], typ("int"))),
+      extern_val("lt_int", "lt_int", function_type(vec![typ("int"), typ("int")], typ("bool"))),
+    ]
+  }
+
+  #[test]
+  fn bitvector_and_calls_the_resolved_overload() {
+    let program = vec![
+      extern_val("and_bool", "and_bool", function_type(vec![typ("bool"), typ("bool")], typ("bool"))),
+      extern_val("and_vec", "and_vec", function_type(vec![bits(8), bits(8)], bits(8))),
+      overload("&", &["and_bool", "and_vec"]),
+      val("f", function_type(vec![bits(8)], bits(8))),
+      function("f", vec![pattern("x")], infix(var("x"), "&", hex("0x0F"))),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+    assert_eq!(interpreter.call("f", vec![byte(0xAB)], &mut environment).unwrap(), byte(0x0B));
+  }
+
+  #[test]
+  fn boolean_and_skips_its_right_operand() {
+    let program = vec![
+      register("R", typ("int"), Some(number(0))),
+      val("touch", function_type(vec![typ("unit")], typ("bool"))),
+      function("touch", vec![], block(vec![assign(var("R"), number(1)), boolean(true)])),
+      val("g", function_type(vec![typ("bool")], typ("bool"))),
+      function("g", vec![pattern("a")], infix(var("a"), "&", call("touch", vec![]))),
+    ];
+    let mut environment = SimpleEnvironment::default();
+    let mut interpreter = interpreter(program, &mut environment);
+
+    assert_eq!(interpreter.call("g", vec![Value::Bool(false)], &mut environment).unwrap(), Value::Bool(false));
+    assert_eq!(environment.registers["R"], int(0));
+    assert_eq!(interpreter.call("g", vec![Value::Bool(true)], &mut environment).unwrap(), Value::Bool(true));
+    assert_eq!(environment.registers["R"], int(1));
+  }
+}
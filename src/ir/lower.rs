@@ -0,0 +1,1398 @@
+/*!
+
+Lowering of a type-checked program to the core IR.
+
+Lowering is a single walk over the AST that relies on the type checker's results (see `Typing`): the type recorded for
+each expression gives the types of temporaries and of `undefined`, and the call recorded for each application says which
+function an overloaded name or a mapping stands for and what any implicit arguments left out are. An expression is
+lowered either to an `Expr` computing its value or, where an operand is needed, to an `Atom`, binding whatever must be
+computed first to temporaries named `t#1`, `t#2`, and so on, which cannot clash with names in the program.
+
+Patterns the IR has no form for are matched in two steps. A vector concatenation such as `0b00 @ x @ y : bits(4)` binds
+the whole value to a temporary, and the arm's guard slices it and matches each slice against its part of the pattern,
+as does the arm's body to bind the variables the parts contain. The widths of all but one part must be known.
+
+The program is assumed to have been checked without errors, and mappings replaced by their functions (see
+`passes::mappings`). What cannot be lowered is reported and replaced with something of the right shape.
+
+*/
+
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::ir::{Arm, Atom, Constant, Expr, Extern, Function, LoopKind, Name, Operation, Primitive, Program, Register, Value};
+use crate::ir::Pattern as Core;
+use crate::parser::ast::*;
+use crate::parser::ast_util::{expression_to_lvalue, pattern_identifiers, resolve_expression_infix, strip_definition, strip_function_clause};
+use crate::parser::location::{Located, SourceLocation};
+use crate::passes::typecheck::{NumericExpression, Substitution, Type, Typing};
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum LowerError {
+  /// A construct the core IR has no equivalent for.
+  Unsupported(String),
+  /// The left of an assignment is not something that can be assigned to.
+  NotAssignable,
+}
+
+pub type LocatedLowerError = Located<LowerError>;
+
+impl LowerError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LowerError::Unsupported(what) => {
+        write!(f, "{} cannot be lowered to the core language", what)
+      }
+
+      LowerError::NotAssignable => {
+        write!(f, "this cannot be assigned to")
+      }
+    }
+  }
+}
+
+impl Debug for LowerError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for LowerError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for LowerError {}
+
+/// Lowers a type-checked program to the core IR.
+pub fn lower(definitions: &Definitions, typing: &Typing) -> (Program, Vec<LocatedLowerError>) {
+  let mut lowerer = Lowerer { typing, scope: Scope::default(), fresh: 0, errors: Vec::new() };
+  let mut program = Program { environment: typing.environment.clone(), ..Program::default() };
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      lowerer.definition(definition, &mut program);
+    }
+  }
+  (program, lowerer.errors)
+}
+
+/// Temporaries to bind, in order, before an expression that uses them.
+type Bindings = Vec<(Name, Type, Expr)>;
+
+/// Wraps `body` in `let`s for `bindings`.
+fn wrap(bindings: Bindings, body: Expr) -> Expr {
+  bindings
+      .into_iter()
+      .rev()
+      .fold(body, |body, (name, typ, value)| Expr::Let(name, typ, Box::new(value), Box::new(body)))
+}
+
+/// Sequences `exprs`, the value of the whole being that of the last.
+fn sequence_all(exprs: Vec<Expr>) -> Expr {
+  let mut exprs = exprs.into_iter().rev();
+  let last = exprs.next().unwrap_or(Expr::unit());
+  exprs.fold(last, |rest, expr| Expr::sequence(expr, rest))
+}
+
+/// Names invented by lowering, which are scoped so that they can be moved outwards.
+fn is_temporary(name: &str) -> bool {
+  name.starts_with("t#")
+}
+
+fn integer(value: i64) -> Atom {
+  Atom::Constant(Constant::Integer(BigInteger::from_i64(value)))
+}
+
+fn boolean(value: bool) -> Expr {
+  Expr::atom(Atom::Constant(Constant::Bool(value)))
+}
+
+/// The constant a literal stands for, which `undefined` is not.
+fn constant(literal: &Literal) -> Option<Constant> {
+  let constant = match literal {
+    Literal::Unit => Constant::Unit,
+    Literal::Zero => Constant::Bit(false),
+    Literal::One => Constant::Bit(true),
+    Literal::True => Constant::Bool(true),
+    Literal::False => Constant::Bool(false),
+    Literal::Number(value) => Constant::Integer(value.clone()),
+    Literal::Binary(digits) => {
+      Constant::Bitvector(digits.trim_start_matches("0b").chars().filter(|c| *c != '_').collect())
+    }
+    Literal::Hexadecimal(digits) => {
+      let mut bits = String::new();
+      for digit in digits.trim_start_matches("0x").chars().filter(|c| *c != '_') {
+        bits.push_str(&format!("{:04b}", digit.to_digit(16)?));
+      }
+      Constant::Bitvector(bits)
+    }
+    Literal::String(value) => Constant::String(value.clone()),
+    Literal::Real(value) => Constant::Real(value.clone()),
+    Literal::Undefined => return None,
+  };
+  Some(constant)
+}
+
+/// The type inside any existential.
+fn unpack(typ: Type) -> Type {
+  match typ {
+    Type::Existential(_, _, inner) => unpack(*inner),
+    typ => typ,
+  }
+}
+
+/// The width of a bitvector type, if it is a constant.
+fn known_width(typ: &Type) -> Option<BigInteger> {
+  match unpack(typ.clone()) {
+    Type::Bitvector(n) | Type::Vector(n, _) => n.simplify().as_constant(),
+    _ => None,
+  }
+}
+
+/// Where the value of a type variable can be found at run time.
+#[derive(Clone, Debug)]
+enum TypeSource {
+  /// A local of type `atom('n)`
+  Value(Name),
+  /// A local of type `bits('n)` or `vector('n, T)`
+  Length(Name),
+}
+
+#[derive(Clone, Default)]
+struct Scope {
+  locals        : HashMap<Name, Type>,
+  type_variables: HashMap<String, TypeSource>,
+}
+
+/// Part of a pattern left to be matched after the pattern itself: the values computed by `bindings`, each matched against
+/// a part of the original pattern.
+#[derive(Clone)]
+struct Test<'p> {
+  bindings: Bindings,
+  parts   : Vec<(Atom, &'p LocatedPattern)>,
+}
+
+/// One step of matching the tests of an arm.
+#[derive(Clone)]
+enum Step<'p> {
+  Let(Bindings),
+  Match(Atom, &'p LocatedPattern),
+}
+
+fn steps(tests: Vec<Test>) -> Vec<Step> {
+  let mut steps = Vec::new();
+  for test in tests {
+    steps.push(Step::Let(test.bindings));
+    steps.extend(test.parts.into_iter().map(|(atom, pattern)| Step::Match(atom, pattern)));
+  }
+  steps
+}
+
+struct Lowerer<'a> {
+  typing: &'a Typing,
+  scope : Scope,
+  fresh : usize,
+  errors: Vec<LocatedLowerError>,
+}
+
+impl<'a> Lowerer<'a> {
+  // region Utilities
+
+  fn error(&mut self, location: &SourceLocation, error: LowerError) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  fn temporary(&mut self) -> Name {
+    self.fresh += 1;
+    format!("t#{}", self.fresh)
+  }
+
+  fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+    let saved = self.scope.clone();
+    let result = f(self);
+    self.scope = saved;
+    result
+  }
+
+  /// The type the checker recorded at `location`.
+  fn type_at(&self, location: &SourceLocation) -> Type {
+    self.typing.types.get(location).cloned().unwrap_or(Type::Any)
+  }
+
+  fn declare(&mut self, name: &str, typ: Type) {
+    self.scope.type_variables.retain(|_, source| !matches!(source, TypeSource::Value(local) | TypeSource::Length(local) if local == name));
+    match unpack(typ.clone()) {
+      Type::Atom(NumericExpression::Variable(variable)) => {
+        self.scope.type_variables.entry(variable).or_insert(TypeSource::Value(name.to_string()));
+      }
+      Type::Bitvector(NumericExpression::Variable(variable)) | Type::Vector(NumericExpression::Variable(variable), _) => {
+        self.scope.type_variables.entry(variable).or_insert(TypeSource::Length(name.to_string()));
+      }
+      _ => {}
+    }
+    self.scope.locals.insert(name.to_string(), typ);
+  }
+
+  /// Binds `expr` to a temporary unless it is already an atom. `let`s of temporaries it starts with are moved outwards.
+  fn bind(&mut self, bindings: &mut Bindings, typ: Type, expr: Expr) -> Atom {
+    let mut expr = expr;
+    loop {
+      match expr {
+        Expr::Operation(Operation::Atom(atom)) => return atom,
+        Expr::Let(name, typ, value, body) if is_temporary(&name) => {
+          bindings.push((name, typ, *value));
+          expr = *body;
+        }
+        _ => break,
+      }
+    }
+    let name = self.temporary();
+    bindings.push((name.clone(), typ, expr));
+    Atom::Local(name)
+  }
+
+  fn bind_operation(&mut self, bindings: &mut Bindings, typ: Type, operation: Operation) -> Atom {
+    self.bind(bindings, typ, Expr::Operation(operation))
+  }
+
+  /// `a + b` or `a - b`, computed now if both are constants.
+  fn arithmetic(&mut self, bindings: &mut Bindings, primitive: Primitive, a: Atom, b: Atom) -> Atom {
+    if let (Atom::Constant(Constant::Integer(x)), Atom::Constant(Constant::Integer(y))) = (&a, &b) {
+      let result = match primitive {
+        Primitive::Add => x.try_add(y),
+        _ => x.try_sub(y),
+      };
+      if let Ok(result) = result {
+        return Atom::Constant(Constant::Integer(result));
+      }
+    }
+    self.bind_operation(bindings, Type::int(), Operation::Primitive(primitive, vec![a, b]))
+  }
+
+  /// An atom for the value of `n`, binding the type variables it mentions to the values they stand for.
+  fn sizeof(&mut self, bindings: &mut Bindings, n: &NumericExpression) -> Atom {
+    let n = n.clone().simplify();
+    if let Some(value) = n.as_constant() {
+      return Atom::Constant(Constant::Integer(value));
+    }
+    let mut variables = BTreeSet::new();
+    n.free_variables(&mut variables);
+    self.bind_type_variables(bindings, &variables);
+    self.bind_operation(bindings, Type::Atom(n.clone()), Operation::Sizeof(n))
+  }
+
+  fn bind_type_variables(&mut self, bindings: &mut Bindings, variables: &BTreeSet<String>) {
+    for variable in variables {
+      let Some(source) = self.scope.type_variables.get(variable).cloned() else {
+        continue;
+      };
+      let value = match source {
+        TypeSource::Value(local) => Operation::Atom(Atom::Local(local)),
+        TypeSource::Length(local) => Operation::Primitive(Primitive::Length, vec![Atom::Local(local)]),
+      };
+      let typ = Type::Atom(NumericExpression::Variable(variable.clone()));
+      bindings.push((variable.clone(), typ, Expr::Operation(value)));
+    }
+  }
+
+  /// Bitfield types keep their fields' ranges.
+  fn bitfield_ranges(&self, typ: &Type, field: &str) -> Option<Vec<(BigInteger, BigInteger)>> {
+    match unpack(typ.clone()) {
+      Type::Application(name, _) => {
+        let (ranges, _) = self.typing.environment.bitfields.get(&name)?.field(field)?;
+        Some(ranges.to_vec())
+      }
+      _ => None,
+    }
+  }
+
+  fn field_type(&self, typ: &Type, field: &str) -> Type {
+    let Type::Application(name, arguments) = unpack(typ.clone()) else {
+      return Type::Any;
+    };
+    if let Some(record) = self.typing.environment.records.get(&name) {
+      let substitution: Substitution =
+          record.variables.iter().map(|(variable, _)| variable.clone()).zip(arguments.iter().cloned()).collect();
+      if let Some((_, field_type)) = record.fields.iter().find(|(name, _)| name == field) {
+        return field_type.substitute(&substitution);
+      }
+    }
+    if let Some((_, width)) = self.typing.environment.bitfields.get(&name).and_then(|bitfield| bitfield.field(field)) {
+      return Type::Bitvector(NumericExpression::Constant(width));
+    }
+    Type::Any
+  }
+
+  /// Splits the bitvector `value` into consecutive slices of the given widths, first to last in the default order. At
+  /// most one width may be unknown, and is what the others leave.
+  fn slices(&mut self, bindings: &mut Bindings, value: Atom, widths: &[Option<BigInteger>]) -> Option<Vec<Atom>> {
+    let known = widths.iter().flatten().try_fold(BigInteger::from_i64(0), |sum, width| sum.try_add(width).ok())?;
+    let unknown = widths.iter().filter(|width| width.is_none()).count();
+    let widths: Vec<Atom> = match unknown {
+      0 => widths.iter().flatten().map(|width| Atom::Constant(Constant::Integer(width.clone()))).collect(),
+      1 => {
+        let length = self.bind_operation(bindings, Type::int(), Operation::Primitive(Primitive::Length, vec![value.clone()]));
+        let rest = self.arithmetic(bindings, Primitive::Subtract, length, Atom::Constant(Constant::Integer(known)));
+        widths
+            .iter()
+            .map(|width| match width {
+              Some(width) => Atom::Constant(Constant::Integer(width.clone())),
+              None => rest.clone(),
+            })
+            .collect()
+      }
+      _ => return None,
+    };
+
+    let increasing = self.typing.environment.increasing;
+    let mut position = match increasing {
+      true => integer(0),
+      false => {
+        let total = widths.iter().cloned().reduce(|sum, width| self.arithmetic(bindings, Primitive::Add, sum, width));
+        let total = total.unwrap_or(integer(0));
+        self.arithmetic(bindings, Primitive::Subtract, total, integer(1))
+      }
+    };
+    let mut slices = Vec::new();
+    for width in widths {
+      let last = self.arithmetic(bindings, Primitive::Subtract, width, integer(1));
+      let (from, to, next) = match increasing {
+        true => {
+          let to = self.arithmetic(bindings, Primitive::Add, position.clone(), last);
+          let next = self.arithmetic(bindings, Primitive::Add, to.clone(), integer(1));
+          (position, to, next)
+        }
+        false => {
+          let to = self.arithmetic(bindings, Primitive::Subtract, position.clone(), last);
+          let next = self.arithmetic(bindings, Primitive::Subtract, to.clone(), integer(1));
+          (position, to, next)
+        }
+      };
+      let slice = Operation::Primitive(Primitive::Subrange, vec![value.clone(), from, to]);
+      slices.push(self.bind_operation(bindings, Type::Any, slice));
+      position = next;
+    }
+    Some(slices)
+  }
+
+  // endregion
+
+  // region Definitions
+
+  fn definition(&mut self, definition: &LocatedDefinition, program: &mut Program) {
+    let (definition, _) = strip_definition(definition);
+    self.scope = Scope::default();
+    match &definition.value {
+      Definition::FunctionDefinition(function) => {
+        if let Some(function) = self.function(function) {
+          program.functions.push(function);
+        }
+      }
+
+      Definition::InternalMutRec(functions) => {
+        for function in functions {
+          if let Some(function) = self.function(function) {
+            program.functions.push(function);
+          }
+        }
+      }
+
+      Definition::ValueDefinition(binding) => {
+        let LetBinding::ValueBinding(pattern, value) = &binding.value;
+        let names: Vec<(Name, Type)> = pattern_identifiers(pattern)
+            .into_iter()
+            .filter_map(|identifier| {
+              let typ = self.typing.environment.values.get(identifier.name())?;
+              Some((identifier.name().to_string(), typ.clone()))
+            })
+            .collect();
+        let result: Vec<Atom> = names.iter().map(|(name, _)| Atom::Local(name.clone())).collect();
+        let body = self.let_binding(pattern, value, &mut |_| match result.as_slice() {
+          [single] => Expr::atom(single.clone()),
+          _ => Expr::Operation(Operation::Tuple(result.clone())),
+        });
+        program.values.push(Value { names, body });
+      }
+
+      Definition::Register(declaration) => {
+        let DeclarationSpecification::Register(_, name, initial) = &declaration.value;
+        let Some(typ) = self.typing.environment.registers.get(name.name()) else {
+          return;
+        };
+        let initial = initial.as_ref().map(|initial| self.expression(initial));
+        program.registers.push(Register { name: name.name().to_string(), typ: typ.clone(), initial });
+      }
+
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(_, name, Some(bindings)) = &specification.value else {
+          return;
+        };
+        program.externs.push(Extern {
+          name    : name.name().to_string(),
+          bindings: bindings.bindings.clone(),
+          is_pure : bindings.is_pure,
+        });
+      }
+
+      Definition::OutcomeSpec(_, definitions) => {
+        for definition in definitions {
+          self.definition(definition, program);
+        }
+      }
+
+      _ => {}
+    }
+  }
+
+  fn function(&mut self, definition: &LocatedFunctionDefinition) -> Option<Function> {
+    let FunctionDefinition::Function(_, _, _, clauses) = &definition.value;
+    let (name, _) = strip_function_clause(clauses.first()?);
+    let function_type = self.typing.environment.functions.get(name.name())?.clone();
+    self.scope = Scope::default();
+
+    // A single clause binding each argument to a name, as most are, takes its parameters' names from the pattern.
+    let named = match clauses.as_slice() {
+      [clause] => match &strip_function_clause(clause).1.value {
+        PatternExpression::Pattern(pattern, _) => self.parameter_names(pattern, function_type.arguments.len()),
+        PatternExpression::PatternWhen(..) => None,
+      },
+      _ => None,
+    };
+    let names = named.clone().unwrap_or_else(|| (0..function_type.arguments.len()).map(|index| format!("arg#{}", index)).collect());
+    let parameters: Vec<(Name, Type)> = names.into_iter().zip(function_type.arguments.iter().cloned()).collect();
+    for (name, typ) in &parameters {
+      self.declare(name, typ.clone());
+    }
+
+    let body = match named {
+      Some(_) => match &strip_function_clause(&clauses[0]).1.value {
+        PatternExpression::Pattern(_, body) | PatternExpression::PatternWhen(_, _, body) => self.expression(body),
+      },
+      None => {
+        let mut bindings = Vec::new();
+        let scrutinee = match parameters.as_slice() {
+          [(name, _)] => Atom::Local(name.clone()),
+          _ => {
+            let elements = parameters.iter().map(|(name, _)| Atom::Local(name.clone())).collect();
+            let typ = Type::Tuple(function_type.arguments.clone());
+            self.bind_operation(&mut bindings, typ, Operation::Tuple(elements))
+          }
+        };
+        let arms = clauses.iter().map(|clause| self.arm(strip_function_clause(clause).1)).collect();
+        wrap(bindings, Expr::Match(scrutinee, arms))
+      }
+    };
+    Some(Function { name: name.name().to_string(), parameters, result: function_type.result.clone(), body })
+  }
+
+  /// The names of a function's `count` parameters if `pattern` just names them.
+  fn parameter_names(&self, pattern: &LocatedPattern, count: usize) -> Option<Vec<Name>> {
+    let elements = match &self.strip_pattern(pattern).value {
+      Pattern::Tuple(elements) if count > 1 && elements.len() == count => elements.iter().collect(),
+      _ if count == 1 => vec![pattern],
+      _ => return None,
+    };
+    elements
+        .into_iter()
+        .enumerate()
+        .map(|(index, element)| match &self.strip_pattern(element).value {
+          Pattern::Identifier(identifier) if !self.is_constant(identifier.name()) => Some(identifier.name().to_string()),
+          Pattern::Wildcard => Some(format!("arg#{}", index)),
+          _ => None,
+        })
+        .collect()
+  }
+
+  /// A pattern without type annotations or attributes around it.
+  fn strip_pattern<'p>(&self, pattern: &'p LocatedPattern) -> &'p LocatedPattern {
+    match &pattern.value {
+      Pattern::Typed(_, inner) | Pattern::Attribute(_, _, inner) => self.strip_pattern(inner),
+      _ => pattern,
+    }
+  }
+
+  /// Enum members and union constructors, which are not variables where they appear in patterns.
+  fn is_constant(&self, name: &str) -> bool {
+    !self.scope.locals.contains_key(name)
+        && (self.typing.environment.enum_members.contains_key(name) || self.typing.environment.constructors.contains_key(name))
+  }
+
+  // endregion
+
+  // region Expressions
+
+  /// An atom for the value of `expression`, binding what must be computed first.
+  fn atom(&mut self, bindings: &mut Bindings, expression: &LocatedExpression) -> Atom {
+    match &expression.value {
+      Expression::Literal(literal) => {
+        if let Some(constant) = constant(&literal.value) {
+          return Atom::Constant(constant);
+        }
+      }
+      Expression::Identifier(identifier) => {
+        if let Operation::Atom(atom) = self.identifier(identifier) {
+          return atom;
+        }
+      }
+      Expression::Typed(_, inner) | Expression::Attribute(_, _, inner) => return self.atom(bindings, inner),
+      _ => {}
+    }
+    let expr = self.expression(expression);
+    self.bind(bindings, self.type_at(&expression.location), expr)
+  }
+
+  fn atoms(&mut self, bindings: &mut Bindings, expressions: &[LocatedExpression]) -> Vec<Atom> {
+    expressions.iter().map(|expression| self.atom(bindings, expression)).collect()
+  }
+
+  /// An operation whose operands are the values of `expressions`.
+  fn operation(&mut self, expressions: &[&LocatedExpression], f: impl FnOnce(Vec<Atom>) -> Operation) -> Expr {
+    let mut bindings = Vec::new();
+    let atoms = expressions.iter().map(|expression| self.atom(&mut bindings, expression)).collect();
+    wrap(bindings, Expr::Operation(f(atoms)))
+  }
+
+  fn identifier(&self, identifier: &LocatedIdentifier) -> Operation {
+    let name = identifier.name();
+    let environment = &self.typing.environment;
+    if self.scope.locals.contains_key(name) {
+      Operation::Atom(Atom::Local(name.to_string()))
+    } else if environment.enum_members.contains_key(name) {
+      Operation::Atom(Atom::Constant(Constant::Member(name.to_string())))
+    } else if environment.registers.contains_key(name) {
+      Operation::ReadRegister(name.to_string())
+    } else {
+      Operation::Atom(Atom::Global(name.to_string()))
+    }
+  }
+
+  fn expression(&mut self, expression: &LocatedExpression) -> Expr {
+    let location = &expression.location;
+    match &expression.value {
+      Expression::Block(statements) => self.scoped(|this| this.block(statements)),
+
+      Expression::Identifier(identifier) => Expr::Operation(self.identifier(identifier)),
+
+      Expression::Reference(identifier) => Expr::Operation(Operation::Reference(identifier.name().to_string())),
+
+      Expression::Dereference(inner) => self.operation(&[inner], |atoms| Operation::Dereference(atoms[0].clone())),
+
+      Expression::Literal(literal) => match constant(&literal.value) {
+        Some(constant) => Expr::atom(Atom::Constant(constant)),
+        None => Expr::Operation(Operation::Undefined(self.type_at(location))),
+      },
+
+      Expression::Typed(_, inner)
+      | Expression::Attribute(_, _, inner)
+      | Expression::InternalAssume(_, inner) => self.expression(inner),
+
+      Expression::Application(function, arguments) => match (self.resolved(function, location), arguments.as_slice()) {
+        ("and_bool", [left, right]) => self.lazy_boolean(true, left, right),
+        ("or_bool", [left, right]) => self.lazy_boolean(false, left, right),
+        _ => self.application(function, arguments, location),
+      },
+
+      Expression::InfixApplication(left, operator, right) => match self.resolved(operator, location) {
+        "and_bool" => self.lazy_boolean(true, left, right),
+        "or_bool" => self.lazy_boolean(false, left, right),
+        _ => self.application(operator, &[(**left).clone(), (**right).clone()], location),
+      },
+
+      Expression::Infix(tokens) => match resolve_expression_infix(tokens, &self.typing.environment.fixities) {
+        Some(tree) => self.expression(&tree),
+        None => {
+          self.error(location, LowerError::Unsupported("a malformed infix expression".to_string()));
+          Expr::unit()
+        }
+      },
+
+      Expression::Tuple(elements) => {
+        let mut bindings = Vec::new();
+        let atoms = self.atoms(&mut bindings, elements);
+        wrap(bindings, Expr::Operation(Operation::Tuple(atoms)))
+      }
+
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        let mut bindings = Vec::new();
+        let condition = self.atom(&mut bindings, condition);
+        let then_expr = self.scoped(|this| this.expression(then_expr));
+        let else_expr = self.scoped(|this| this.expression(else_expr));
+        wrap(bindings, Expr::If(condition, Box::new(then_expr), Box::new(else_expr)))
+      }
+
+      Expression::Loop(loop_type, _, condition, body) => {
+        let kind = match loop_type {
+          LoopType::While => LoopKind::While,
+          LoopType::Until => LoopKind::Until,
+        };
+        let condition = self.scoped(|this| this.expression(condition));
+        let body = self.scoped(|this| this.expression(body));
+        Expr::Loop(kind, Box::new(condition), Box::new(body))
+      }
+
+      Expression::For { identifier, start, end, step, typ, body } => {
+        let mut bindings = Vec::new();
+        let start = self.atom(&mut bindings, start);
+        let end = self.atom(&mut bindings, end);
+        let step = self.atom(&mut bindings, step);
+        let increasing = !matches!(typ.value, AbstractType::Decreasing);
+        let variable = identifier.name().to_string();
+        let body = self.scoped(|this| {
+          this.declare(&variable, Type::int());
+          this.expression(body)
+        });
+        wrap(bindings, Expr::For { variable, start, end, step, increasing, body: Box::new(body) })
+      }
+
+      Expression::Vector(elements) => {
+        let mut bindings = Vec::new();
+        let atoms = self.atoms(&mut bindings, elements);
+        wrap(bindings, Expr::Operation(Operation::Vector(atoms)))
+      }
+
+      Expression::VectorAccess(vector, index) => {
+        self.operation(&[vector, index], |atoms| Operation::Primitive(Primitive::Access, atoms))
+      }
+
+      Expression::VectorSubrange(vector, high, low) => {
+        self.operation(&[vector, high, low], |atoms| Operation::Primitive(Primitive::Subrange, atoms))
+      }
+
+      Expression::VectorUpdate(vector, index, value) => {
+        self.operation(&[vector, index, value], |atoms| Operation::Primitive(Primitive::Update, atoms))
+      }
+
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        self.operation(&[vector, high, low, value], |atoms| Operation::Primitive(Primitive::UpdateSubrange, atoms))
+      }
+
+      Expression::VectorAppend(left, right) => {
+        self.operation(&[left, right], |atoms| Operation::Primitive(Primitive::Append, atoms))
+      }
+
+      Expression::List(elements) => {
+        let mut bindings = Vec::new();
+        let atoms = self.atoms(&mut bindings, elements);
+        wrap(bindings, Expr::Operation(Operation::List(atoms)))
+      }
+
+      Expression::Cons(head, tail) => {
+        self.operation(&[head, tail], |atoms| Operation::Cons(atoms[0].clone(), atoms[1].clone()))
+      }
+
+      Expression::Struct(fields) => {
+        let mut bindings = Vec::new();
+        let fields = self.field_values(&mut bindings, fields);
+        wrap(bindings, Expr::Operation(Operation::Record(fields)))
+      }
+
+      Expression::StructUpdate(record, fields) => {
+        let mut bindings = Vec::new();
+        let record = self.atom(&mut bindings, record);
+        let fields = self.field_values(&mut bindings, fields);
+        wrap(bindings, Expr::Operation(Operation::RecordUpdate(record, fields)))
+      }
+
+      Expression::Field(record, field) => {
+        let mut bindings = Vec::new();
+        let typ = self.type_at(&record.location);
+        let record = self.atom(&mut bindings, record);
+        let field = self.field(&mut bindings, record, &typ, field.name());
+        wrap(bindings, Expr::Operation(field))
+      }
+
+      Expression::Match(scrutinee, arms) => {
+        let mut bindings = Vec::new();
+        let scrutinee = self.atom(&mut bindings, scrutinee);
+        let arms = arms.iter().map(|arm| self.arm(arm)).collect();
+        wrap(bindings, Expr::Match(scrutinee, arms))
+      }
+
+      Expression::Try(body, arms) => {
+        let body = self.scoped(|this| this.expression(body));
+        let arms = arms.iter().map(|arm| self.arm(arm)).collect();
+        Expr::Try(Box::new(body), arms)
+      }
+
+      Expression::Let(binding, body) => {
+        let LetBinding::ValueBinding(pattern, value) = &binding.value;
+        self.scoped(|this| this.let_binding(pattern, value, &mut |this| this.expression(body)))
+      }
+
+      Expression::InternalPlet(pattern, value, body) => {
+        self.scoped(|this| this.let_binding(pattern, value, &mut |this| this.expression(body)))
+      }
+
+      Expression::Variable(target, value, body) => self.scoped(|this| {
+        let (name, typ) = this.assigned_name(target).unwrap_or_else(|| {
+          this.error(&target.location, LowerError::NotAssignable);
+          ("_".to_string(), Type::Any)
+        });
+        let value = this.expression(value);
+        this.declare(&name, typ.clone());
+        let body = this.expression(body);
+        Expr::Var(name, typ, Box::new(value), Box::new(body))
+      }),
+
+      Expression::Assign(target, value) => self.assign(target, value, location),
+
+      Expression::Sizeof(_) => match unpack(self.type_at(location)) {
+        Type::Atom(n) => {
+          let mut bindings = Vec::new();
+          let value = self.sizeof(&mut bindings, &n);
+          wrap(bindings, Expr::atom(value))
+        }
+        _ => {
+          self.error(location, LowerError::Unsupported("`sizeof` of an unknown integer".to_string()));
+          Expr::Operation(Operation::Undefined(Type::int()))
+        }
+      },
+
+      Expression::Constraint(_) => match unpack(self.type_at(location)) {
+        Type::AtomBool(p) => {
+          let mut bindings = Vec::new();
+          let mut variables = BTreeSet::new();
+          p.free_variables(&mut variables);
+          self.bind_type_variables(&mut bindings, &variables);
+          wrap(bindings, Expr::Operation(Operation::Constraint(p)))
+        }
+        _ => {
+          self.error(location, LowerError::Unsupported("`constraint` of an unknown boolean".to_string()));
+          Expr::Operation(Operation::Undefined(Type::bool()))
+        }
+      },
+
+      Expression::Exit(inner) => {
+        let mut bindings = Vec::new();
+        let value = self.atom(&mut bindings, inner);
+        wrap(bindings, Expr::Exit(value))
+      }
+
+      Expression::Throw(inner) => {
+        let mut bindings = Vec::new();
+        let value = self.atom(&mut bindings, inner);
+        wrap(bindings, Expr::Throw(value))
+      }
+
+      Expression::Return(inner)
+      | Expression::InternalReturn(inner) => {
+        let mut bindings = Vec::new();
+        let value = self.atom(&mut bindings, inner);
+        wrap(bindings, Expr::Return(value))
+      }
+
+      Expression::Assert(condition, message) => {
+        let mut bindings = Vec::new();
+        let condition = self.atom(&mut bindings, condition);
+        let message = self.atom(&mut bindings, message);
+        wrap(bindings, Expr::Assert(condition, message))
+      }
+    }
+  }
+
+  /// The statements of a block. An assignment to an undeclared name declares a mutable variable for the rest.
+  fn block(&mut self, statements: &[LocatedExpression]) -> Expr {
+    let Some((first, rest)) = statements.split_first() else {
+      return Expr::unit();
+    };
+    if let Expression::Assign(target, value) = &first.value {
+      if let Some((name, typ)) = self.undeclared(target) {
+        let value = self.expression(value);
+        self.declare(&name, typ.clone());
+        let body = self.block(rest);
+        return Expr::Var(name, typ, Box::new(value), Box::new(body));
+      }
+      if let Expression::Tuple(targets) = &target.value {
+        let declared: Vec<(Name, Type)> = targets.iter().filter_map(|target| self.undeclared(target)).collect();
+        if !declared.is_empty() {
+          for (name, typ) in &declared {
+            self.declare(name, typ.clone());
+          }
+          let assignment = self.assign(target, value, &first.location);
+          let body = Expr::sequence(assignment, self.block(rest));
+          return declared.into_iter().rev().fold(body, |body, (name, typ)| {
+            let undefined = Expr::Operation(Operation::Undefined(typ.clone()));
+            Expr::Var(name, typ, Box::new(undefined), Box::new(body))
+          });
+        }
+      }
+    }
+    let first = self.expression(first);
+    match rest.is_empty() {
+      true => first,
+      false => Expr::sequence(first, self.block(rest)),
+    }
+  }
+
+  /// The name and type of an assignment target that is an identifier, possibly annotated.
+  fn assigned_name(&self, target: &LocatedExpression) -> Option<(Name, Type)> {
+    match &target.value {
+      Expression::Identifier(identifier) => Some((identifier.name().to_string(), self.type_at(&target.location))),
+      Expression::Typed(_, inner) => match &inner.value {
+        Expression::Identifier(identifier) => Some((identifier.name().to_string(), self.type_at(&target.location))),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  /// An assignment target that names nothing yet, and so declares a variable.
+  fn undeclared(&self, target: &LocatedExpression) -> Option<(Name, Type)> {
+    let (name, typ) = self.assigned_name(target)?;
+    let environment = &self.typing.environment;
+    match self.scope.locals.contains_key(&name) || environment.registers.contains_key(&name) || environment.values.contains_key(&name) {
+      true => None,
+      false => Some((name, typ)),
+    }
+  }
+
+  /// `left & right` or `left | right`, which only evaluates `right` if it must.
+  fn lazy_boolean(&mut self, and: bool, left: &LocatedExpression, right: &LocatedExpression) -> Expr {
+    let mut bindings = Vec::new();
+    let left = self.atom(&mut bindings, left);
+    let right = self.scoped(|this| this.expression(right));
+    let expr = match and {
+      true => Expr::If(left, Box::new(right), Box::new(boolean(false))),
+      false => Expr::If(left, Box::new(boolean(true)), Box::new(right)),
+    };
+    wrap(bindings, expr)
+  }
+
+  /// The function the type checker resolved the call of `function` at `location` to.
+  fn resolved<'f>(&'f self, function: &'f LocatedIdentifier, location: &SourceLocation) -> &'f str {
+    self.typing.calls.get(location).map_or(function.name(), |call| call.function.as_str())
+  }
+
+  /// A call of `function`, which the type checker has resolved to a particular function.
+  fn application(&mut self, function: &LocatedIdentifier, arguments: &[LocatedExpression], location: &SourceLocation) -> Expr {
+    let call = self.typing.calls.get(location);
+    let name = call.map(|call| call.function.clone()).unwrap_or_else(|| function.name().to_string());
+    let mut bindings = Vec::new();
+    let implicit = call.map(|call| call.implicit.clone()).unwrap_or_default();
+    let mut atoms: Vec<Atom> = implicit.iter().map(|n| self.sizeof(&mut bindings, n)).collect();
+    let mut values = self.atoms(&mut bindings, arguments);
+
+    let environment = &self.typing.environment;
+    if environment.constructors.contains_key(&name) {
+      let payload = match values.len() {
+        0 => Atom::Constant(Constant::Unit),
+        1 => values.remove(0),
+        _ => self.bind_operation(&mut bindings, Type::Any, Operation::Tuple(values)),
+      };
+      return wrap(bindings, Expr::Operation(Operation::Construct(name, payload)));
+    }
+
+    // A function of a single tuple or of unit may be called with several values or none.
+    let parameters = environment.functions.get(&name).map(|function| function.arguments.clone()).unwrap_or_default();
+    match (parameters.as_slice(), values.len()) {
+      ([Type::Unit], 0) => values.push(Atom::Constant(Constant::Unit)),
+      ([parameter], count) if count > 1 => {
+        let tuple = self.bind_operation(&mut bindings, parameter.clone(), Operation::Tuple(values));
+        values = vec![tuple];
+      }
+      _ => {}
+    }
+    atoms.extend(values);
+    wrap(bindings, Expr::Operation(Operation::Call(name, atoms)))
+  }
+
+  /// The fields of a `struct` expression or update, each written `field = value`.
+  fn field_values(&mut self, bindings: &mut Bindings, fields: &[LocatedExpression]) -> Vec<(Name, Atom)> {
+    let mut values = Vec::new();
+    for field in fields {
+      match &field.value {
+        Expression::Assign(name, value) => match &name.value {
+          Expression::Identifier(name) => {
+            let value = self.atom(bindings, value);
+            values.push((name.name().to_string(), value));
+          }
+          _ => self.error(&field.location, LowerError::NotAssignable),
+        },
+        _ => self.error(&field.location, LowerError::NotAssignable),
+      }
+    }
+    values
+  }
+
+  /// Reads `field` of `record`, a value of type `typ`. A bitfield's field is a slice of it.
+  fn field(&mut self, bindings: &mut Bindings, record: Atom, typ: &Type, field: &str) -> Operation {
+    let Some(ranges) = self.bitfield_ranges(typ, field) else {
+      return Operation::Field(record, field.to_string());
+    };
+    let mut slices = ranges.into_iter().map(|(high, low)| {
+      let high = Atom::Constant(Constant::Integer(high));
+      let low = Atom::Constant(Constant::Integer(low));
+      Operation::Primitive(Primitive::Subrange, vec![record.clone(), high, low])
+    });
+    let first = slices.next().unwrap_or(Operation::Vector(Vec::new()));
+    slices.fold(first, |whole, slice| {
+      let whole = self.bind_operation(bindings, Type::Any, whole);
+      let slice = self.bind_operation(bindings, Type::Any, slice);
+      Operation::Primitive(Primitive::Append, vec![whole, slice])
+    })
+  }
+
+  // endregion
+
+  // region Assignments
+
+  fn assign(&mut self, target: &LocatedExpression, value: &LocatedExpression, location: &SourceLocation) -> Expr {
+    let mut bindings = Vec::new();
+    let value = self.atom(&mut bindings, value);
+    let assignment = self.assign_atom(target, value, location);
+    wrap(bindings, assignment)
+  }
+
+  fn assign_atom(&mut self, target: &LocatedExpression, value: Atom, location: &SourceLocation) -> Expr {
+    match &target.value {
+      Expression::Typed(_, inner) => self.assign_atom(inner, value, location),
+
+      Expression::Tuple(targets) => {
+        let names: Vec<Name> = targets.iter().map(|_| self.temporary()).collect();
+        let assignments = targets
+            .iter()
+            .zip(&names)
+            .map(|(target, name)| self.assign_atom(target, Atom::Local(name.clone()), location))
+            .collect();
+        let pattern = Core::Tuple(names.into_iter().map(Core::Bind).collect());
+        Expr::Match(value, vec![Arm { pattern, guard: None, body: sequence_all(assignments) }])
+      }
+
+      _ => {
+        let Some(lvalue) = expression_to_lvalue(target) else {
+          self.error(&target.location, LowerError::NotAssignable);
+          return Expr::unit();
+        };
+        if let LValueExpression::Memory(setter, arguments) = &lvalue.value {
+          // `f(x) = v` calls `f` with `v` as well.
+          let name = self.typing.calls.get(location).map(|call| call.function.clone()).unwrap_or_else(|| setter.name().to_string());
+          let mut bindings = Vec::new();
+          let mut atoms = self.atoms(&mut bindings, arguments);
+          atoms.push(value);
+          return wrap(bindings, Expr::Operation(Operation::Call(name, atoms)));
+        }
+        self.write(&lvalue, value)
+      }
+    }
+  }
+
+  /// The type of the value `lvalue` holds.
+  fn lvalue_type(&self, lvalue: &LocatedLValueExpression) -> Type {
+    match &lvalue.value {
+      LValueExpression::Identifier(identifier) => {
+        let name = identifier.name();
+        self.scope
+            .locals
+            .get(name)
+            .or_else(|| self.typing.environment.registers.get(name))
+            .cloned()
+            .unwrap_or(Type::Any)
+      }
+      LValueExpression::Vector(vector, _) => match unpack(self.lvalue_type(vector)) {
+        Type::Bitvector(_) => Type::Bit,
+        Type::Vector(_, element) => *element,
+        _ => Type::Any,
+      },
+      LValueExpression::VectorRange(vector, high, low) => {
+        let width = match (&high.value, &low.value) {
+          (Expression::Literal(high), Expression::Literal(low)) => match (&high.value, &low.value) {
+            (Literal::Number(high), Literal::Number(low)) => {
+              high.try_sub(low).and_then(|difference| difference.try_abs()).and_then(|difference| difference.try_add(&BigInteger::from_i64(1))).ok()
+            }
+            _ => None,
+          },
+          _ => None,
+        };
+        match (unpack(self.lvalue_type(vector)), width) {
+          (Type::Bitvector(_), Some(width)) => Type::Bitvector(NumericExpression::Constant(width)),
+          (Type::Vector(_, element), Some(width)) => Type::Vector(NumericExpression::Constant(width), element),
+          _ => Type::Any,
+        }
+      }
+      LValueExpression::Field(record, field) => self.field_type(&self.lvalue_type(record), field.name()),
+      LValueExpression::Memory(..)
+      | LValueExpression::VectorConcat(_) => Type::Any,
+    }
+  }
+
+  /// An atom for the current value of `lvalue`.
+  fn read(&mut self, bindings: &mut Bindings, lvalue: &LocatedLValueExpression) -> Atom {
+    let typ = self.lvalue_type(lvalue);
+    let operation = match &lvalue.value {
+      LValueExpression::Identifier(identifier) => self.identifier(identifier),
+      LValueExpression::Vector(vector, index) => {
+        let vector = self.read(bindings, vector);
+        let index = self.atom(bindings, index);
+        Operation::Primitive(Primitive::Access, vec![vector, index])
+      }
+      LValueExpression::VectorRange(vector, high, low) => {
+        let vector = self.read(bindings, vector);
+        let high = self.atom(bindings, high);
+        let low = self.atom(bindings, low);
+        Operation::Primitive(Primitive::Subrange, vec![vector, high, low])
+      }
+      LValueExpression::VectorConcat(parts) => {
+        let mut parts = parts.iter();
+        let first = match parts.next() {
+          Some(part) => self.read(bindings, part),
+          None => Atom::Constant(Constant::Bitvector(String::new())),
+        };
+        let whole = parts.fold(first, |whole, part| {
+          let part = self.read(bindings, part);
+          self.bind_operation(bindings, Type::Any, Operation::Primitive(Primitive::Append, vec![whole, part]))
+        });
+        Operation::Atom(whole)
+      }
+      LValueExpression::Field(record, field) => {
+        let record_type = self.lvalue_type(record);
+        let record = self.read(bindings, record);
+        self.field(bindings, record, &record_type, field.name())
+      }
+      LValueExpression::Memory(..) => {
+        self.error(&lvalue.location, LowerError::NotAssignable);
+        Operation::Undefined(Type::Any)
+      }
+    };
+    self.bind_operation(bindings, typ, operation)
+  }
+
+  /// Writes `value` to `lvalue`. Writing to part of a value reads the whole, updates it, and writes it back.
+  fn write(&mut self, lvalue: &LocatedLValueExpression, value: Atom) -> Expr {
+    let mut bindings = Vec::new();
+    let write = match &lvalue.value {
+      LValueExpression::Identifier(identifier) => {
+        let name = identifier.name().to_string();
+        if self.scope.locals.contains_key(&name) {
+          Expr::Assign(name, value)
+        } else if self.typing.environment.registers.contains_key(&name) {
+          Expr::WriteRegister(name, value)
+        } else {
+          self.error(&lvalue.location, LowerError::NotAssignable);
+          Expr::unit()
+        }
+      }
+
+      LValueExpression::Vector(vector, index) => {
+        let current = self.read(&mut bindings, vector);
+        let index = self.atom(&mut bindings, index);
+        let update = Operation::Primitive(Primitive::Update, vec![current, index, value]);
+        let updated = self.bind_operation(&mut bindings, self.lvalue_type(vector), update);
+        self.write(vector, updated)
+      }
+
+      LValueExpression::VectorRange(vector, high, low) => {
+        let current = self.read(&mut bindings, vector);
+        let high = self.atom(&mut bindings, high);
+        let low = self.atom(&mut bindings, low);
+        let update = Operation::Primitive(Primitive::UpdateSubrange, vec![current, high, low, value]);
+        let updated = self.bind_operation(&mut bindings, self.lvalue_type(vector), update);
+        self.write(vector, updated)
+      }
+
+      LValueExpression::Field(record, field) => {
+        let typ = self.lvalue_type(record);
+        let current = self.read(&mut bindings, record);
+        let updated = match self.bitfield_ranges(&typ, field.name()) {
+          Some(ranges) => self.update_bitfield(&mut bindings, current, &ranges, value),
+          None => {
+            let update = Operation::RecordUpdate(current, vec![(field.name().to_string(), value)]);
+            self.bind_operation(&mut bindings, typ, update)
+          }
+        };
+        self.write(record, updated)
+      }
+
+      LValueExpression::VectorConcat(parts) => {
+        let widths: Vec<Option<BigInteger>> = parts.iter().map(|part| known_width(&self.lvalue_type(part))).collect();
+        match self.slices(&mut bindings, value, &widths) {
+          Some(slices) => {
+            let writes = parts.iter().zip(slices).map(|(part, slice)| self.write(part, slice)).collect();
+            sequence_all(writes)
+          }
+          None => {
+            self.error(&lvalue.location, LowerError::Unsupported("assignment to a concatenation of several vectors of unknown length".to_string()));
+            Expr::unit()
+          }
+        }
+      }
+
+      LValueExpression::Memory(..) => {
+        self.error(&lvalue.location, LowerError::NotAssignable);
+        Expr::unit()
+      }
+    };
+    wrap(bindings, write)
+  }
+
+  /// `current` with the bits of a bitfield's field, spread over `ranges`, replaced by `value`.
+  fn update_bitfield(&mut self, bindings: &mut Bindings, current: Atom, ranges: &[(BigInteger, BigInteger)], value: Atom) -> Atom {
+    let widths: Vec<Option<BigInteger>> = ranges
+        .iter()
+        .map(|(high, low)| high.try_sub(low).and_then(|difference| difference.try_abs()).and_then(|difference| difference.try_add(&BigInteger::from_i64(1))).ok())
+        .collect();
+    let pieces = match ranges.len() {
+      1 => vec![value],
+      _ => self.slices(bindings, value, &widths).unwrap_or_default(),
+    };
+    let mut current = current;
+    for ((high, low), piece) in ranges.iter().zip(pieces) {
+      let high = Atom::Constant(Constant::Integer(high.clone()));
+      let low = Atom::Constant(Constant::Integer(low.clone()));
+      let update = Operation::Primitive(Primitive::UpdateSubrange, vec![current, high, low, piece]);
+      current = self.bind_operation(bindings, Type::Any, update);
+    }
+    current
+  }
+
+  // endregion
+
+  // region Patterns
+
+  /// `let pattern = value in body`, where `body` is lowered with the pattern's variables in scope.
+  fn let_binding(&mut self, pattern: &LocatedPattern, value: &LocatedExpression, body: &mut dyn FnMut(&mut Self) -> Expr) -> Expr {
+    if let Pattern::Identifier(identifier) = &self.strip_pattern(pattern).value {
+      if !self.is_constant(identifier.name()) {
+        let name = identifier.name().to_string();
+        let typ = self.type_at(&pattern.location);
+        let value = self.expression(value);
+        self.declare(&name, typ.clone());
+        let body = body(self);
+        return Expr::Let(name, typ, Box::new(value), Box::new(body));
+      }
+    }
+    let mut bindings = Vec::new();
+    let value = self.atom(&mut bindings, value);
+    let arm = self.lower_arm(pattern, None, body);
+    wrap(bindings, Expr::Match(value, vec![arm]))
+  }
+
+  fn arm(&mut self, arm: &LocatedPatternExpression) -> Arm {
+    match &arm.value {
+      PatternExpression::Pattern(pattern, body) => self.lower_arm(pattern, None, &mut |this| this.expression(body)),
+      PatternExpression::PatternWhen(pattern, guard, body) => {
+        self.lower_arm(pattern, Some(guard), &mut |this| this.expression(body))
+      }
+    }
+  }
+
+  /// An arm matching `pattern`. Parts of the pattern the IR cannot match directly are matched by the guard, and again
+  /// by the body to bind their variables.
+  fn lower_arm(&mut self, pattern: &LocatedPattern, guard: Option<&LocatedExpression>, body: &mut dyn FnMut(&mut Self) -> Expr) -> Arm {
+    self.scoped(|this| {
+      let mut tests = Vec::new();
+      let pattern = this.pattern(pattern, &mut tests);
+      if tests.is_empty() {
+        let guard = guard.map(|guard| this.expression(guard));
+        let body = body(this);
+        return Arm { pattern, guard, body };
+      }
+      let steps = steps(tests);
+      let guard = this.scoped(|this| {
+        this.matching(steps.clone(), Some(&boolean(false)), &mut |this| match guard {
+          Some(guard) => this.expression(guard),
+          None => boolean(true),
+        })
+      });
+      let body = this.matching(steps, None, body);
+      Arm { pattern, guard: Some(guard), body }
+    })
+  }
+
+  /// Runs `steps`, each matching a value against part of a pattern, and then `inner`. Where a part does not match, the
+  /// result is `fallback`.
+  fn matching(&mut self, mut steps: Vec<Step>, fallback: Option<&Expr>, inner: &mut dyn FnMut(&mut Self) -> Expr) -> Expr {
+    if steps.is_empty() {
+      return inner(self);
+    }
+    match steps.remove(0) {
+      Step::Let(bindings) => {
+        let body = self.matching(steps, fallback, inner);
+        wrap(bindings, body)
+      }
+      Step::Match(value, pattern) => {
+        let mut tests = Vec::new();
+        let pattern = self.pattern(pattern, &mut tests);
+        let mut next = self::steps(tests);
+        next.extend(steps);
+        let body = self.matching(next, fallback, inner);
+        let mut arms = vec![Arm { pattern, guard: None, body }];
+        if let Some(fallback) = fallback {
+          arms.push(Arm { pattern: Core::Wildcard, guard: None, body: fallback.clone() });
+        }
+        Expr::Match(value, arms)
+      }
+    }
+  }
+
+  /// Lowers `pattern`, declaring the variables it binds. Parts it cannot match are left in `tests`.
+  fn pattern<'p>(&mut self, pattern: &'p LocatedPattern, tests: &mut Vec<Test<'p>>) -> Core {
+    match &pattern.value {
+      Pattern::Literal(literal) => match constant(&literal.value) {
+        Some(constant) => Core::Constant(constant),
+        None => Core::Wildcard,
+      },
+
+      Pattern::Wildcard => Core::Wildcard,
+
+      Pattern::Typed(abstract_type, inner)
+      | Pattern::Variable(inner, abstract_type) => {
+        let core = self.pattern(inner, tests);
+        if let Core::Bind(name) = &core {
+          self.bind_annotation(abstract_type, name);
+        }
+        core
+      }
+
+      Pattern::Identifier(identifier) => {
+        let name = identifier.name();
+        if self.is_constant(name) {
+          return match self.typing.environment.enum_members.contains_key(name) {
+            true => Core::Constant(Constant::Member(name.to_string())),
+            false => Core::Constructor(name.to_string(), Box::new(Core::Wildcard)),
+          };
+        }
+        self.declare(name, self.type_at(&pattern.location));
+        Core::Bind(name.to_string())
+      }
+
+      Pattern::Constructor(constructor, arguments) => {
+        let payload = match arguments.as_slice() {
+          [] => Core::Wildcard,
+          [argument] => self.pattern(argument, tests),
+          arguments => Core::Tuple(arguments.iter().map(|argument| self.pattern(argument, tests)).collect()),
+        };
+        Core::Constructor(constructor.name().to_string(), Box::new(payload))
+      }
+
+      Pattern::Vector(elements) => Core::Vector(elements.iter().map(|element| self.pattern(element, tests)).collect()),
+
+      Pattern::VectorConcat(parts) => {
+        let name = self.temporary();
+        let mut bindings = Vec::new();
+        let widths: Vec<Option<BigInteger>> = parts.iter().map(|part| known_width(&self.type_at(&part.location))).collect();
+        match self.slices(&mut bindings, Atom::Local(name.clone()), &widths) {
+          Some(slices) => tests.push(Test { bindings, parts: slices.into_iter().zip(parts.iter()).collect() }),
+          None => self.error(&pattern.location, LowerError::Unsupported("a concatenation pattern with several parts of unknown length".to_string())),
+        }
+        Core::Bind(name)
+      }
+
+      Pattern::VectorSubrange(identifier, high, low) => {
+        let width = high.try_sub(low).and_then(|difference| difference.try_abs()).and_then(|difference| difference.try_add(&BigInteger::from_i64(1)));
+        let typ = match width {
+          Ok(width) => Type::Bitvector(NumericExpression::Constant(width)),
+          Err(_) => Type::Any,
+        };
+        self.declare(identifier.name(), typ);
+        Core::Bind(identifier.name().to_string())
+      }
+
+      Pattern::Tuple(elements) => match elements.as_slice() {
+        [element] => self.pattern(element, tests),
+        elements => Core::Tuple(elements.iter().map(|element| self.pattern(element, tests)).collect()),
+      },
+
+      Pattern::List(elements) => {
+        let elements: Vec<Core> = elements.iter().map(|element| self.pattern(element, tests)).collect();
+        elements.into_iter().rev().fold(Core::Nil, |tail, head| Core::Cons(Box::new(head), Box::new(tail)))
+      }
+
+      Pattern::Cons(head, tail) => {
+        let head = self.pattern(head, tests);
+        let tail = self.pattern(tail, tests);
+        Core::Cons(Box::new(head), Box::new(tail))
+      }
+
+      Pattern::StringAppend(_) => {
+        self.error(&pattern.location, LowerError::Unsupported("a string append pattern".to_string()));
+        Core::Wildcard
+      }
+
+      Pattern::Struct(fields) => Core::Record(
+        fields
+            .iter()
+            .filter_map(|field| match &field.value {
+              FieldPattern::Field(name, pattern) => Some((name.name().to_string(), self.pattern(pattern, tests))),
+              FieldPattern::Wildcard => None,
+            })
+            .collect(),
+      ),
+
+      Pattern::Attribute(_, _, inner) => self.pattern(inner, tests),
+    }
+  }
+
+  /// Makes the type variables an annotation such as `x : bits('n)` or `x as int('n)` introduces available from `name`.
+  fn bind_annotation(&mut self, abstract_type: &LocatedAbstractType, name: &str) {
+    let source = match &abstract_type.value {
+      AbstractType::Parenthesized(inner) => return self.bind_annotation(inner, name),
+      AbstractType::Variable(variable) => Some((variable, TypeSource::Value(name.to_string()))),
+      AbstractType::TypeConstructorApplication(constructor, arguments) => match (constructor.name(), arguments.first().map(|argument| &argument.value)) {
+        ("atom" | "int", Some(AbstractType::Variable(variable))) => Some((variable, TypeSource::Value(name.to_string()))),
+        ("bits" | "bitvector" | "vector", Some(AbstractType::Variable(variable))) => {
+          Some((variable, TypeSource::Length(name.to_string())))
+        }
+        _ => None,
+      },
+      _ => None,
+    };
+    if let Some((variable, source)) = source {
+      self.scope.type_variables.insert(variable.value.0.clone(), source);
+    }
+  }
+
+  // endregion
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+
+  /// Lowers `program`, which must check and lower without errors.
+  fn lower_program(program: Vec<LocatedDefinition>) -> Program {
+    let definitions = definitions(program);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (program, errors) = lower(&definitions, &typing);
+    assert!(errors.is_empty(), "{:?}", errors);
+    program
+  }
+
+  fn body<'p>(program: &'p Program, name: &str) -> &'p Expr {
+    &program.functions.iter().find(|function| function.name == name).unwrap().body
+  }
+
+  /// `f(x, y) = x & y` for arguments of type `argument`, with `&` overloaded on booleans and bytes.
+  fn and_program(argument: LocatedAbstractType) -> Program {
+    lower_program(vec![
+      extern_val("and_bool", "and_bool", function_type(vec![typ("bool"), typ("bool")], typ("bool"))),
+      extern_val("and_vec", "and_vec", function_type(vec![bits(8), bits(8)], bits(8))),
+      overload("&", &["and_bool", "and_vec"]),
+      val("f", function_type(vec![argument.clone(), argument.clone()], argument)),
+      function("f", vec![pattern("x"), pattern("y")], infix(var("x"), "&", var("y"))),
+    ])
+  }
+
+  #[test]
+  fn bitvector_and_is_a_call() {
+    let program = and_program(bits(8));
+    let arguments = vec![Atom::Local("x".to_string()), Atom::Local("y".to_string())];
+    assert_eq!(body(&program, "f"), &Expr::Operation(Operation::Call("and_vec".to_string(), arguments)));
+  }
+
+  #[test]
+  fn boolean_and_is_a_branch() {
+    let program = and_program(typ("bool"));
+    let expected = Expr::If(
+      Atom::Local("x".to_string()),
+      Box::new(Expr::atom(Atom::Local("y".to_string()))),
+      Box::new(Expr::atom(Atom::Constant(Constant::Bool(false)))),
+    );
+    assert_eq!(body(&program, "f"), &expected);
+  }
+}
@@ -0,0 +1,542 @@
+/*!
+
+The core intermediate representation, a small language every backend targets instead of the surface AST.
+
+Programs are lowered into it once their types are checked (see `lower`). It is in A-normal form, after Sail's own ANF:
+every operand of an operation is an `Atom`, a constant or a variable, and everything else is computed by an `Expr` that
+binds its value to a temporary with `let`. So evaluation order is explicit, and an interpreter or code generator only
+ever handles one operation at a time.
+
+Much of the surface language is gone by this point:
+
+ * overloads are resolved, mappings are replaced by the function for the direction used, and implicit arguments a
+   call left out are passed explicitly,
+ * infix expressions, type annotations, attributes and blocks are gone, and `&` and `|` are `if`s,
+ * reads and writes of registers are explicit, as are writes through nested l-values such as `R[7..4].F = v`, which
+   are read-modify-write sequences,
+ * patterns are only those a backend can match directly; vector concatenations and the like are bound to a variable
+   and taken apart by a guard,
+ * bitfield fields are slices of the underlying bitvector.
+
+Type variables whose values are needed at run time, as by `sizeof('n)`, are bound by `let` like any other variable,
+under their ticked names, which cannot clash with a program's own. Vectors are indexed in the program's default order.
+
+*/
+
+pub mod lower;
+
+use std::fmt::{Display, Formatter};
+
+use crate::abstractions::BigInteger;
+use crate::passes::typecheck::{Constraint, Environment, NumericExpression, Type};
+
+pub use lower::{lower, LocatedLowerError, LowerError};
+
+pub type Name = String;
+
+// region Expressions
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constant {
+  Unit,
+  Bool(bool),
+  Bit(bool),
+  Integer(BigInteger),
+  /// A bitvector, as binary digits with the most significant first
+  Bitvector(String),
+  String(String),
+  Real(String),
+  /// A member of an enum
+  Member(Name),
+}
+
+/// An operand: something that can be used without computing anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Atom {
+  Constant(Constant),
+  /// A variable bound by a function's parameters, a `let`, a `var` or a pattern
+  Local(Name),
+  /// A top-level `let`
+  Global(Name),
+}
+
+/// The operations built into the language rather than provided by the program's externs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Primitive {
+  /// `length(v)`
+  Length,
+  /// `a + b` on integers, used to compute vector indices
+  Add,
+  /// `a - b` on integers, used to compute vector indices
+  Subtract,
+  /// `v[i]`
+  Access,
+  /// `v[i .. j]`
+  Subrange,
+  /// `[v with i = x]`
+  Update,
+  /// `[v with i .. j = x]`
+  UpdateSubrange,
+  /// `v @ w`
+  Append,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+  Atom(Atom),
+  /// A call of a function of the program, which may be an extern
+  Call(Name, Vec<Atom>),
+  Primitive(Primitive, Vec<Atom>),
+  /// A union constructor applied to its payload, which is a tuple if the constructor takes several values
+  Construct(Name, Atom),
+  Tuple(Vec<Atom>),
+  Vector(Vec<Atom>),
+  List(Vec<Atom>),
+  Cons(Atom, Atom),
+  Record(Vec<(Name, Atom)>),
+  Field(Atom, Name),
+  /// A copy of a record with some fields replaced
+  RecordUpdate(Atom, Vec<(Name, Atom)>),
+  ReadRegister(Name),
+  /// `ref R`, a reference to a register
+  Reference(Name),
+  /// Reads the register a reference refers to
+  Dereference(Atom),
+  Undefined(Type),
+  /// The value of a type-level integer, whose variables are bound as locals
+  Sizeof(NumericExpression),
+  /// The value of a type-level boolean, whose variables are bound as locals
+  Constraint(Constraint),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+  /// Tests the condition before each iteration, and runs while it holds
+  While,
+  /// Tests the condition after each iteration, and runs until it holds
+  Until,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+  Operation(Operation),
+  /// `let x : T = e in body`
+  Let(Name, Type, Box<Expr>, Box<Expr>),
+  /// `var x : T = e in body`, where `x` is mutable
+  Var(Name, Type, Box<Expr>, Box<Expr>),
+  /// Assignment to a mutable local
+  Assign(Name, Atom),
+  WriteRegister(Name, Atom),
+  /// Evaluates the first for its effects, and then the second
+  Sequence(Box<Expr>, Box<Expr>),
+  If(Atom, Box<Expr>, Box<Expr>),
+  /// Runs the first arm that matches. Failing to match any is an error.
+  Match(Atom, Vec<Arm>),
+  /// Runs the body, matching anything it throws against the arms, and rethrowing what none matches
+  Try(Box<Expr>, Vec<Arm>),
+  /// A loop whose condition is the value of the first expression
+  Loop(LoopKind, Box<Expr>, Box<Expr>),
+  /// `foreach (variable from start to end by step)`, counting down if not `increasing`
+  For { variable: Name, start: Atom, end: Atom, step: Atom, increasing: bool, body: Box<Expr> },
+  Return(Atom),
+  Throw(Atom),
+  Exit(Atom),
+  /// Fails with the message if the condition is false
+  Assert(Atom, Atom),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arm {
+  pub pattern: Pattern,
+  /// A boolean expression the arm also requires, which may use the variables the pattern binds
+  pub guard  : Option<Expr>,
+  pub body   : Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+  Wildcard,
+  Bind(Name),
+  /// Matches a value equal to the constant
+  Constant(Constant),
+  Tuple(Vec<Pattern>),
+  /// A vector of exactly these elements, the first being the first in the program's default order
+  Vector(Vec<Pattern>),
+  Constructor(Name, Box<Pattern>),
+  Nil,
+  Cons(Box<Pattern>, Box<Pattern>),
+  /// A record whose named fields match
+  Record(Vec<(Name, Pattern)>),
+  /// Matches the pattern, binding the whole value to the name as well
+  As(Box<Pattern>, Name),
+}
+
+impl Expr {
+  pub fn unit() -> Self {
+    Expr::Operation(Operation::Atom(Atom::Constant(Constant::Unit)))
+  }
+
+  pub fn atom(atom: Atom) -> Self {
+    Expr::Operation(Operation::Atom(atom))
+  }
+
+  /// `first; second`, leaving out a unit `first`.
+  pub fn sequence(first: Expr, second: Expr) -> Self {
+    match first == Expr::unit() {
+      true => second,
+      false => Expr::Sequence(Box::new(first), Box::new(second)),
+    }
+  }
+}
+
+// endregion
+
+// region Programs
+
+/// A lowered program. Definitions are in program order, and a backend initialises values before registers.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+  pub functions  : Vec<Function>,
+  pub registers  : Vec<Register>,
+  pub values     : Vec<Value>,
+  pub externs    : Vec<Extern>,
+  /// Everything the program declares, as known to the type checker
+  pub environment: Environment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+  pub name      : Name,
+  /// The parameters, including any leading implicit ones
+  pub parameters: Vec<(Name, Type)>,
+  pub result    : Type,
+  pub body      : Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+  pub name   : Name,
+  pub typ    : Type,
+  pub initial: Option<Expr>,
+}
+
+/// A top-level `let`, which may bind several names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+  pub names: Vec<(Name, Type)>,
+  /// Computes the value of the single name, or a tuple of the values of several
+  pub body : Expr,
+}
+
+/// A function with no definition in the program, implemented by the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extern {
+  pub name    : Name,
+  /// The name of the implementation for each backend, or `_` for all
+  pub bindings: Vec<(String, String)>,
+  pub is_pure : bool,
+}
+
+impl Program {
+  pub fn function(&self, name: &str) -> Option<&Function> {
+    self.functions.iter().find(|function| function.name == name)
+  }
+
+  pub fn extern_function(&self, name: &str) -> Option<&Extern> {
+    self.externs.iter().find(|external| external.name == name)
+  }
+}
+
+impl Extern {
+  /// The implementation for `backend`, falling back to the one for all backends.
+  pub fn binding(&self, backend: &str) -> Option<&str> {
+    self.bindings
+        .iter()
+        .find(|(target, _)| target == backend)
+        .or_else(|| self.bindings.iter().find(|(target, _)| target == "_"))
+        .map(|(_, implementation)| implementation.as_str())
+  }
+}
+
+// endregion
+
+// region Display
+
+impl Display for Constant {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Constant::Unit => write!(f, "()"),
+      Constant::Bool(value) => write!(f, "{}", value),
+      Constant::Bit(true) => write!(f, "bitone"),
+      Constant::Bit(false) => write!(f, "bitzero"),
+      Constant::Integer(value) => write!(f, "{}", value),
+      Constant::Bitvector(bits) => write!(f, "0b{}", bits),
+      Constant::String(value) => write!(f, "{:?}", value),
+      Constant::Real(value) => write!(f, "{}", value),
+      Constant::Member(name) => write!(f, "{}", name),
+    }
+  }
+}
+
+impl Display for Atom {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Atom::Constant(constant) => write!(f, "{}", constant),
+      Atom::Local(name) | Atom::Global(name) => write!(f, "{}", name),
+    }
+  }
+}
+
+impl Display for Primitive {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      Primitive::Length => "%length",
+      Primitive::Add => "%add",
+      Primitive::Subtract => "%subtract",
+      Primitive::Access => "%access",
+      Primitive::Subrange => "%subrange",
+      Primitive::Update => "%update",
+      Primitive::UpdateSubrange => "%update_subrange",
+      Primitive::Append => "%append",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Writes `items` separated by commas.
+fn write_list<T: Display>(f: &mut Formatter<'_>, items: &[T]) -> std::fmt::Result {
+  for (index, item) in items.iter().enumerate() {
+    if index > 0 {
+      write!(f, ", ")?;
+    }
+    write!(f, "{}", item)?;
+  }
+  Ok(())
+}
+
+fn write_fields<T: Display>(f: &mut Formatter<'_>, fields: &[(Name, T)]) -> std::fmt::Result {
+  for (index, (name, value)) in fields.iter().enumerate() {
+    if index > 0 {
+      write!(f, ", ")?;
+    }
+    write!(f, "{} = {}", name, value)?;
+  }
+  Ok(())
+}
+
+impl Display for Operation {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Operation::Atom(atom) => write!(f, "{}", atom),
+      Operation::Call(name, arguments) => {
+        write!(f, "{}(", name)?;
+        write_list(f, arguments)?;
+        write!(f, ")")
+      }
+      Operation::Primitive(primitive, arguments) => {
+        write!(f, "{}(", primitive)?;
+        write_list(f, arguments)?;
+        write!(f, ")")
+      }
+      Operation::Construct(name, payload) => write!(f, "{}({})", name, payload),
+      Operation::Tuple(elements) => {
+        write!(f, "(")?;
+        write_list(f, elements)?;
+        write!(f, ")")
+      }
+      Operation::Vector(elements) => {
+        write!(f, "[")?;
+        write_list(f, elements)?;
+        write!(f, "]")
+      }
+      Operation::List(elements) => {
+        write!(f, "[|")?;
+        write_list(f, elements)?;
+        write!(f, "|]")
+      }
+      Operation::Cons(head, tail) => write!(f, "{} :: {}", head, tail),
+      Operation::Record(fields) => {
+        write!(f, "struct {{ ")?;
+        write_fields(f, fields)?;
+        write!(f, " }}")
+      }
+      Operation::Field(record, field) => write!(f, "{}.{}", record, field),
+      Operation::RecordUpdate(record, fields) => {
+        write!(f, "{{ {} with ", record)?;
+        write_fields(f, fields)?;
+        write!(f, " }}")
+      }
+      Operation::ReadRegister(name) => write!(f, "%read({})", name),
+      Operation::Reference(name) => write!(f, "ref {}", name),
+      Operation::Dereference(reference) => write!(f, "*{}", reference),
+      Operation::Undefined(typ) => write!(f, "undefined : {}", typ),
+      Operation::Sizeof(n) => write!(f, "sizeof({})", n),
+      Operation::Constraint(p) => write!(f, "constraint({})", p),
+    }
+  }
+}
+
+impl Display for Pattern {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Pattern::Wildcard => write!(f, "_"),
+      Pattern::Bind(name) => write!(f, "{}", name),
+      Pattern::Constant(constant) => write!(f, "{}", constant),
+      Pattern::Tuple(elements) => {
+        write!(f, "(")?;
+        write_list(f, elements)?;
+        write!(f, ")")
+      }
+      Pattern::Vector(elements) => {
+        write!(f, "[")?;
+        write_list(f, elements)?;
+        write!(f, "]")
+      }
+      Pattern::Constructor(name, payload) => write!(f, "{}({})", name, payload),
+      Pattern::Nil => write!(f, "[||]"),
+      Pattern::Cons(head, tail) => write!(f, "{} :: {}", head, tail),
+      Pattern::Record(fields) => {
+        write!(f, "struct {{ ")?;
+        write_fields(f, fields)?;
+        write!(f, " }}")
+      }
+      Pattern::As(inner, name) => write!(f, "{} as {}", inner, name),
+    }
+  }
+}
+
+/// Writes `expr` at the given indentation, each `let` and statement on a line of its own.
+fn write_expr(f: &mut Formatter<'_>, expr: &Expr, indent: usize) -> std::fmt::Result {
+  let pad = "  ".repeat(indent);
+  match expr {
+    Expr::Operation(operation) => write!(f, "{}", operation),
+    Expr::Let(name, typ, value, body) | Expr::Var(name, typ, value, body) => {
+      let keyword = match expr {
+        Expr::Var(..) => "var",
+        _ => "let",
+      };
+      write!(f, "{} {} : {} = ", keyword, name, typ)?;
+      write_expr(f, value, indent + 1)?;
+      write!(f, " in\n{}", pad)?;
+      write_expr(f, body, indent)
+    }
+    Expr::Assign(name, value) => write!(f, "{} = {}", name, value),
+    Expr::WriteRegister(name, value) => write!(f, "%write({}, {})", name, value),
+    Expr::Sequence(first, second) => {
+      write_expr(f, first, indent)?;
+      write!(f, ";\n{}", pad)?;
+      write_expr(f, second, indent)
+    }
+    Expr::If(condition, then_expr, else_expr) => {
+      write!(f, "if {} then {{\n{}  ", condition, pad)?;
+      write_expr(f, then_expr, indent + 1)?;
+      write!(f, "\n{}}} else {{\n{}  ", pad, pad)?;
+      write_expr(f, else_expr, indent + 1)?;
+      write!(f, "\n{}}}", pad)
+    }
+    Expr::Match(scrutinee, arms) => {
+      write!(f, "match {} {{", scrutinee)?;
+      write_arms(f, arms, indent)
+    }
+    Expr::Try(body, arms) => {
+      write!(f, "try {{\n{}  ", pad)?;
+      write_expr(f, body, indent + 1)?;
+      write!(f, "\n{}}} catch {{", pad)?;
+      write_arms(f, arms, indent)
+    }
+    Expr::Loop(kind, condition, body) => {
+      let keyword = match kind {
+        LoopKind::While => "while",
+        LoopKind::Until => "until",
+      };
+      write!(f, "{} {{\n{}  ", keyword, pad)?;
+      write_expr(f, condition, indent + 1)?;
+      write!(f, "\n{}}} do {{\n{}  ", pad, pad)?;
+      write_expr(f, body, indent + 1)?;
+      write!(f, "\n{}}}", pad)
+    }
+    Expr::For { variable, start, end, step, increasing, body } => {
+      let order = match increasing {
+        true => "inc",
+        false => "dec",
+      };
+      write!(f, "foreach ({} from {} to {} by {} in {}) {{\n{}  ", variable, start, end, step, order, pad)?;
+      write_expr(f, body, indent + 1)?;
+      write!(f, "\n{}}}", pad)
+    }
+    Expr::Return(value) => write!(f, "return {}", value),
+    Expr::Throw(value) => write!(f, "throw {}", value),
+    Expr::Exit(value) => write!(f, "exit {}", value),
+    Expr::Assert(condition, message) => write!(f, "assert({}, {})", condition, message),
+  }
+}
+
+fn write_arms(f: &mut Formatter<'_>, arms: &[Arm], indent: usize) -> std::fmt::Result {
+  let pad = "  ".repeat(indent);
+  for arm in arms {
+    write!(f, "\n{}  {}", pad, arm.pattern)?;
+    if let Some(guard) = &arm.guard {
+      write!(f, " if ")?;
+      write_expr(f, guard, indent + 2)?;
+    }
+    write!(f, " => ")?;
+    write_expr(f, &arm.body, indent + 2)?;
+    write!(f, ",")?;
+  }
+  write!(f, "\n{}}}", pad)
+}
+
+impl Display for Expr {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write_expr(f, self, 0)
+  }
+}
+
+impl Display for Function {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "function {}(", self.name)?;
+    for (index, (name, typ)) in self.parameters.iter().enumerate() {
+      if index > 0 {
+        write!(f, ", ")?;
+      }
+      write!(f, "{} : {}", name, typ)?;
+    }
+    write!(f, ") -> {} = {{\n  ", self.result)?;
+    write_expr(f, &self.body, 1)?;
+    write!(f, "\n}}")
+  }
+}
+
+impl Display for Program {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    for external in &self.externs {
+      writeln!(f, "extern {}", external.name)?;
+    }
+    for value in &self.values {
+      write!(f, "let (")?;
+      for (index, (name, typ)) in value.names.iter().enumerate() {
+        if index > 0 {
+          write!(f, ", ")?;
+        }
+        write!(f, "{} : {}", name, typ)?;
+      }
+      write!(f, ") = ")?;
+      write_expr(f, &value.body, 1)?;
+      writeln!(f)?;
+    }
+    for register in &self.registers {
+      write!(f, "register {} : {}", register.name, register.typ)?;
+      if let Some(initial) = &register.initial {
+        write!(f, " = ")?;
+        write_expr(f, initial, 1)?;
+      }
+      writeln!(f)?;
+    }
+    for function in &self.functions {
+      writeln!(f, "{}\n", function)?;
+    }
+    Ok(())
+  }
+}
+
+// endregion
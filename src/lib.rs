@@ -1,5 +1,17 @@
-mod parser;
-mod abstractions;
+// `BigInteger` is `Copy` only without the `bigint` feature, and code that builds with either clones it.
+#![cfg_attr(not(feature = "bigint"), allow(clippy::clone_on_copy))]
+
+pub mod parser;
+pub mod abstractions;
+pub mod passes;
+pub mod ir;
+pub mod runtime;
+pub mod interpreter;
+pub mod project;
+pub mod driver;
+pub mod simulator;
+pub mod debugger;
+pub mod repl;
 
 pub fn add(left: usize, right: usize) -> usize {
   left + right
@@ -32,7 +32,7 @@ type Identifier = Text;
 type InfixIdentifier = Text;
 
 /// Enum for kind
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Kind {
   /// Base kind of types
   Type,
@@ -166,9 +166,9 @@ pub type LocatedAbstractType = Located<AbstractType>;
 /// Kind-annotated variable with optional string, list of kind identifiers, and optional kind
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KindedIdentifier {
-  identifiers: Vec<LocatedKindIdentifier>,
-  annotation : Option<String>,
-  kind       : Option<LocatedKind>,
+  pub identifiers: Vec<LocatedKindIdentifier>,
+  pub annotation : Option<String>,
+  pub kind       : Option<LocatedKind>,
 }
 
 /// KindedIdentifier with location
@@ -305,7 +305,7 @@ pub enum Expression {
     if_location: IfLocation,
   },
   /// Loop expression
-  Loop(LoopType, LocatedMeasure, Box<LocatedExpression>, Box<LocatedExpression>),
+  Loop(LoopType, Box<LocatedMeasure>, Box<LocatedExpression>, Box<LocatedExpression>),
   /// For loop expression
   For {
     identifier: LocatedIdentifier,
@@ -512,6 +512,8 @@ pub type LocatedMappingPatternExpression = Located<MappingPatternExpression>;
 
 /// Represents mapping clause (bidirectional pattern-match)
 #[derive(Debug, Clone, PartialEq, Eq)]
+// Big-integer literals make the pattern variants much wider than the boxed ones with the `bigint` feature.
+#[cfg_attr(feature = "bigint", allow(clippy::large_enum_variant))]
 pub enum MappingClause {
   Attribute(String, Option<LocatedAttributeData>, Box<LocatedMappingClause>),
   Documentation(String, Box<LocatedMappingClause>),
@@ -0,0 +1,443 @@
+/*!
+
+Small utilities for inspecting AST nodes, in the spirit of Sail's `Ast_util`.
+
+*/
+
+use std::collections::HashMap;
+
+use codemap::Span;
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::location::{Located, SourceLocation};
+
+/// The namespaces a top-level declaration can introduce a name into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Namespace {
+  /// Functions, values, registers, mappings, union constructors and enum members
+  Value,
+  /// Types of every sort
+  Type,
+}
+
+impl IdentifierType {
+  /// The text of the identifier, without any `operator` marker.
+  pub fn name(&self) -> &str {
+    match self {
+      IdentifierType::Regular(name)
+      | IdentifierType::Operator(name) => name,
+    }
+  }
+}
+
+/// Strips `Attribute` and `Documentation` wrappers from a definition, reporting whether a `Private` wrapper was seen.
+pub fn strip_definition(definition: &LocatedDefinition) -> (&LocatedDefinition, bool) {
+  match &definition.value {
+    Definition::Private(inner) => (strip_definition(inner).0, true),
+    Definition::Attribute(_, _, inner)
+    | Definition::Documentation(_, inner) => strip_definition(inner),
+    _ => (definition, false),
+  }
+}
+
+/// Strips `Private`, `Attribute` and `Documentation` wrappers from a function clause.
+pub fn strip_function_clause(clause: &LocatedFunctionClause) -> (&LocatedIdentifier, &LocatedPatternExpression) {
+  match &clause.value {
+    FunctionClause::Private(inner)
+    | FunctionClause::Attribute(_, _, inner)
+    | FunctionClause::Documentation(_, inner) => strip_function_clause(inner),
+    FunctionClause::Clause(identifier, arm) => (identifier, arm),
+  }
+}
+
+/// Strips `Private`, `Attribute` and `Documentation` wrappers from a union constructor.
+pub fn strip_type_union(union: &LocatedTypeUnion) -> &LocatedTypeUnion {
+  match &union.value {
+    TypeUnion::Private(inner)
+    | TypeUnion::Attribute(_, _, inner)
+    | TypeUnion::Documentation(_, inner) => strip_type_union(inner),
+    _ => union,
+  }
+}
+
+/// The constructor named by a union clause.
+pub fn union_constructor(union: &LocatedTypeUnion) -> &LocatedIdentifier {
+  match &strip_type_union(union).value {
+    TypeUnion::TypeIdentifier(_, constructor)
+    | TypeUnion::AnonymousRecord(_, constructor) => constructor,
+    _ => unreachable!("wrappers are removed by strip_type_union"),
+  }
+}
+
+/// The name of a function, taken from its first clause.
+pub fn function_name(function: &FunctionDefinition) -> Option<&LocatedIdentifier> {
+  let FunctionDefinition::Function(_, _, _, clauses) = function;
+  clauses.first().map(|clause| strip_function_clause(clause).0)
+}
+
+/// The name of the type a type definition introduces.
+pub fn type_definition_name(definition: &TypeDefinition) -> &LocatedIdentifier {
+  match definition {
+    TypeDefinition::Abbreviation(name, ..)
+    | TypeDefinition::Record(name, ..)
+    | TypeDefinition::Variant(name, ..)
+    | TypeDefinition::Enum(name, ..)
+    | TypeDefinition::Abstract(name, ..)
+    | TypeDefinition::Bitfield(name, ..) => name,
+  }
+}
+
+/// Identifiers bound by a pattern. Sail does not distinguish variables from nullary constructors and enum members
+/// syntactically, so a bare identifier is reported whatever it turns out to be.
+pub fn pattern_identifiers(pattern: &LocatedPattern) -> Vec<&LocatedIdentifier> {
+  let mut identifiers = Vec::new();
+  collect_pattern_identifiers(pattern, &mut identifiers);
+  identifiers
+}
+
+fn collect_pattern_identifiers<'a>(pattern: &'a LocatedPattern, identifiers: &mut Vec<&'a LocatedIdentifier>) {
+  match &pattern.value {
+    Pattern::Literal(_)
+    | Pattern::Wildcard => {}
+    Pattern::Identifier(identifier)
+    | Pattern::VectorSubrange(identifier, ..) => identifiers.push(identifier),
+    Pattern::Typed(_, inner)
+    | Pattern::Variable(inner, _)
+    | Pattern::Attribute(_, _, inner) => collect_pattern_identifiers(inner, identifiers),
+    Pattern::Constructor(_, patterns)
+    | Pattern::Vector(patterns)
+    | Pattern::VectorConcat(patterns)
+    | Pattern::Tuple(patterns)
+    | Pattern::List(patterns)
+    | Pattern::StringAppend(patterns) => {
+      for pattern in patterns {
+        collect_pattern_identifiers(pattern, identifiers);
+      }
+    }
+    Pattern::Cons(head, tail) => {
+      collect_pattern_identifiers(head, identifiers);
+      collect_pattern_identifiers(tail, identifiers);
+    }
+    Pattern::Struct(fields) => {
+      for field in fields {
+        if let FieldPattern::Field(_, pattern) = &field.value {
+          collect_pattern_identifiers(pattern, identifiers);
+        }
+      }
+    }
+  }
+}
+
+/// The names a top-level definition declares, with the namespace each is declared in.
+pub fn declared_names(definition: &LocatedDefinition) -> Vec<(Namespace, &LocatedIdentifier)> {
+  let (definition, _) = strip_definition(definition);
+  let mut names = Vec::new();
+
+  match &definition.value {
+    Definition::TypeDefinition(type_definition) => {
+      names.push((Namespace::Type, type_definition_name(type_definition)));
+      match &type_definition.value {
+        TypeDefinition::Variant(_, _, unions) => {
+          names.extend(unions.iter().map(|union| (Namespace::Value, union_constructor(union))));
+        }
+        TypeDefinition::Enum(_, _, members) => {
+          names.extend(members.iter().map(|(member, _)| (Namespace::Value, member)));
+        }
+        _ => {}
+      }
+    }
+    Definition::FunctionDefinition(function) => {
+      names.extend(function_name(function).map(|name| (Namespace::Value, name)));
+    }
+    Definition::MappingDefinition(mapping) => {
+      let MappingDefinition::Mapping(name, ..) = &mapping.value;
+      names.push((Namespace::Value, name));
+    }
+    Definition::ValueDefinition(binding) => {
+      let LetBinding::ValueBinding(pattern, _) = &binding.value;
+      names.extend(pattern_identifiers(pattern).into_iter().map(|name| (Namespace::Value, name)));
+    }
+    Definition::Overload(name, _) => names.push((Namespace::Value, name)),
+    Definition::ValueSpec(specification) => {
+      let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+      names.push((Namespace::Value, name));
+    }
+    Definition::OutcomeSpec(outcome, _) => {
+      let OutcomeSpec::Outcome(name, ..) = &outcome.value;
+      names.push((Namespace::Value, name));
+    }
+    Definition::Register(declaration) => {
+      let DeclarationSpecification::Register(_, name, _) = &declaration.value;
+      names.push((Namespace::Value, name));
+    }
+    Definition::ScatteredDefinition(scattered) => match &scattered.value {
+      ScatteredDefinition::Function(.., name)
+      | ScatteredDefinition::Mapping(name, _) => names.push((Namespace::Value, name)),
+      ScatteredDefinition::Enumeration(name)
+      | ScatteredDefinition::Variant(name, _) => names.push((Namespace::Type, name)),
+      ScatteredDefinition::EnumerationMember(_, member) => names.push((Namespace::Value, member)),
+      ScatteredDefinition::UnionClause(_, union) => names.push((Namespace::Value, union_constructor(union))),
+      ScatteredDefinition::FunctionClause(_)
+      | ScatteredDefinition::MapClause(..)
+      | ScatteredDefinition::End(_) => {}
+    },
+    Definition::InternalMutRec(functions) => {
+      names.extend(functions.iter().filter_map(|function| function_name(function)).map(|name| (Namespace::Value, name)));
+    }
+    _ => {}
+  }
+
+  names
+}
+
+/// Precedence and associativity of the built-in type-level operators, following Sail. Operators not listed bind
+/// tightest and associate to the left.
+fn type_operator_fixity(operator: &str) -> (u8, Associativity) {
+  match operator {
+    "|" => (2, Associativity::Right),
+    "&" => (3, Associativity::Right),
+    "==" | "!=" | ">=" | "<=" | ">" | "<" => (4, Associativity::None),
+    "+" | "-" => (6, Associativity::Left),
+    "*" => (7, Associativity::Left),
+    "^" => (8, Associativity::Right),
+    _ => (9, Associativity::Left),
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+  Left,
+  Right,
+  None,
+}
+
+impl From<&Precedence> for Associativity {
+  fn from(precedence: &Precedence) -> Self {
+    match precedence {
+      Precedence::Infix  => Associativity::None,
+      Precedence::InfixL => Associativity::Left,
+      Precedence::InfixR => Associativity::Right,
+    }
+  }
+}
+
+/// The fixities of the expression-level operators in scope. Starts with Sail's built-in fixities; `infix`, `infixl` and
+/// `infixr` declarations add to or override them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixities(HashMap<String, (u8, Associativity)>);
+
+impl Default for Fixities {
+  fn default() -> Self {
+    let mut fixities = HashMap::new();
+    for (operators, level, associativity) in [
+      (&["|"][..], 2, Associativity::Right),
+      (&["&"][..], 3, Associativity::Right),
+      (&["==", "!=", ">=", "<=", ">", "<", ">=_s", "<=_s", ">_s", "<_s", ">=_u", "<=_u", ">_u", "<_u"][..], 4, Associativity::None),
+      (&["@", "::", "^^"][..], 5, Associativity::Right),
+      (&["+", "-", "+_s", "-_s"][..], 6, Associativity::Left),
+      (&["*", "/", "%", "*_s", "*_u"][..], 7, Associativity::Left),
+      (&["^"][..], 8, Associativity::Right),
+    ] {
+      for operator in operators {
+        fixities.insert(operator.to_string(), (level, associativity));
+      }
+    }
+    Fixities(fixities)
+  }
+}
+
+impl Fixities {
+  /// Records an `infix`, `infixl` or `infixr` declaration.
+  pub fn declare(&mut self, precedence: &Precedence, level: u8, operator: &str) {
+    self.0.insert(operator.to_string(), (level, precedence.into()));
+  }
+
+  /// The precedence and associativity of `operator`. Undeclared operators bind tightest and associate to the left.
+  pub fn get(&self, operator: &str) -> (u8, Associativity) {
+    self.0.get(operator).copied().unwrap_or((9, Associativity::Left))
+  }
+}
+
+/// The location spanning `first` through `last`, when both come from the source text.
+fn merge_locations(first: &SourceLocation, last: &SourceLocation) -> SourceLocation {
+  match (first, last) {
+    (SourceLocation::Span(first), SourceLocation::Span(last)) => SourceLocation::Span(first.merge(*last)),
+    _ => SourceLocation::Unknown,
+  }
+}
+
+fn is_two(literal: &LocatedLiteral) -> bool {
+  match &literal.value {
+    Literal::Number(number) => *number == BigInteger::from_i64(2),
+    _ => false,
+  }
+}
+
+/// Builds the tree for a binary type-level operator. `+`, `-`, `*` and `2 ^ n` have their own `AbstractType` variants;
+/// any other operator becomes an application of the operator to its operands.
+fn type_operator_application(operator: &LocatedIdentifier, left: LocatedAbstractType, right: LocatedAbstractType) -> LocatedAbstractType {
+  let location = merge_locations(&left.location, &right.location);
+  let value = match operator.name() {
+    "+" => AbstractType::Sum(Box::new(left), Box::new(right)),
+    "-" => AbstractType::Minus(Box::new(left), Box::new(right)),
+    "*" => AbstractType::Times(Box::new(left), Box::new(right)),
+    "^" if matches!(&left.value, AbstractType::Literal(literal) if is_two(literal)) => {
+      AbstractType::Exponential(Box::new(right))
+    }
+    _ => AbstractType::TypeConstructorApplication(operator.clone(), vec![left, right]),
+  };
+  Located { location, value }
+}
+
+fn type_prefix_application(operator: &LocatedIdentifier, span: Span, operand: LocatedAbstractType) -> LocatedAbstractType {
+  let location = merge_locations(&SourceLocation::Span(span), &operand.location);
+  let value = match operator.name() {
+    "-" => AbstractType::Negative(Box::new(operand)),
+    _ => AbstractType::TypeConstructorApplication(operator.clone(), vec![operand]),
+  };
+  Located { location, value }
+}
+
+/// Builds the tree for a binary expression-level operator. `@` and `::` have their own `Expression` variants.
+fn expression_operator_application(operator: &LocatedIdentifier, left: LocatedExpression, right: LocatedExpression) -> LocatedExpression {
+  let location = merge_locations(&left.location, &right.location);
+  let value = match operator.name() {
+    "@"  => Expression::VectorAppend(Box::new(left), Box::new(right)),
+    "::" => Expression::Cons(Box::new(left), Box::new(right)),
+    _ => Expression::InfixApplication(Box::new(left), operator.clone(), Box::new(right)),
+  };
+  Located { location, value }
+}
+
+fn expression_prefix_application(operator: &LocatedIdentifier, span: Span, operand: LocatedExpression) -> LocatedExpression {
+  let location = merge_locations(&SourceLocation::Span(span), &operand.location);
+  Located { location, value: Expression::Application(operator.clone(), vec![operand]) }
+}
+
+/// Resolves the operator precedence of an `AbstractType::Infix` token sequence, as the parser leaves it, into a tree.
+/// Prefix `-` becomes `Negative`; other prefix operators are applied to their operand. Returns `None` if the tokens do
+/// not alternate between operands and operators.
+pub fn resolve_type_infix(tokens: &[(InfixToken<LocatedAbstractType>, Span)]) -> Option<LocatedAbstractType> {
+  let resolver = InfixResolver {
+    fixity: &type_operator_fixity,
+    binary: &type_operator_application,
+    prefix: &type_prefix_application,
+  };
+  resolver.resolve(tokens)
+}
+
+/// Resolves the operator precedence of an `Expression::Infix` token sequence into `InfixApplication` nodes, using the
+/// given fixities. Prefix operators become ordinary applications. Returns `None` if the tokens do not alternate between
+/// operands and operators.
+pub fn resolve_expression_infix(tokens: &[(InfixToken<LocatedExpression>, Span)], fixities: &Fixities) -> Option<LocatedExpression> {
+  let fixity = |operator: &str| fixities.get(operator);
+  let resolver = InfixResolver {
+    fixity: &fixity,
+    binary: &expression_operator_application,
+    prefix: &expression_prefix_application,
+  };
+  resolver.resolve(tokens)
+}
+
+type BinaryBuilder<'a, T> = &'a dyn Fn(&LocatedIdentifier, Located<T>, Located<T>) -> Located<T>;
+type PrefixBuilder<'a, T> = &'a dyn Fn(&LocatedIdentifier, Span, Located<T>) -> Located<T>;
+
+/// Precedence climbing over a sequence of infix tokens.
+struct InfixResolver<'a, T> {
+  fixity: &'a dyn Fn(&str) -> (u8, Associativity),
+  binary: BinaryBuilder<'a, T>,
+  prefix: PrefixBuilder<'a, T>,
+}
+
+impl<'a, T: Clone> InfixResolver<'a, T> {
+  fn resolve(&self, tokens: &[(InfixToken<Located<T>>, Span)]) -> Option<Located<T>> {
+    let mut position = 0;
+    let result = self.parse(tokens, &mut position, 0)?;
+    match position == tokens.len() {
+      true  => Some(result),
+      false => None,
+    }
+  }
+
+  fn operand(&self, tokens: &[(InfixToken<Located<T>>, Span)], position: &mut usize) -> Option<Located<T>> {
+    let (token, span) = tokens.get(*position)?;
+    *position += 1;
+    match token {
+      InfixToken::Primary(operand) => Some(operand.clone()),
+      InfixToken::Prefix(operator) => {
+        let operand = self.operand(tokens, position)?;
+        Some((self.prefix)(operator, *span, operand))
+      }
+      InfixToken::Operator(_) => None,
+    }
+  }
+
+  fn parse(&self, tokens: &[(InfixToken<Located<T>>, Span)], position: &mut usize, minimum: u8) -> Option<Located<T>> {
+    let mut left = self.operand(tokens, position)?;
+    // A non-associative operator may not be directly followed by another at the same level.
+    let mut non_associative = None;
+
+    while let Some((InfixToken::Operator(operator), _)) = tokens.get(*position) {
+      let (precedence, associativity) = (self.fixity)(operator.name());
+      if precedence < minimum {
+        break;
+      }
+      if non_associative == Some(precedence) {
+        return None;
+      }
+      *position += 1;
+      let next_minimum = match associativity {
+        Associativity::Right => precedence,
+        Associativity::Left
+        | Associativity::None => precedence + 1,
+      };
+      let right = self.parse(tokens, position, next_minimum)?;
+      left = (self.binary)(operator, left, right);
+      non_associative = match associativity {
+        Associativity::None => Some(precedence),
+        _ => None,
+      };
+    }
+
+    Some(left)
+  }
+}
+
+/// Reads an expression in assignment position as an l-value, as Sail's parser does for the left of `=`. Returns `None`
+/// for expressions that cannot be assigned to. `LValueExpression` has no tuple form, so a tuple of l-values must be
+/// taken apart by the caller.
+pub fn expression_to_lvalue(expression: &LocatedExpression) -> Option<LocatedLValueExpression> {
+  let value = match &expression.value {
+    Expression::Identifier(identifier) => LValueExpression::Identifier(identifier.clone()),
+    Expression::Application(function, arguments) => LValueExpression::Memory(function.clone(), arguments.clone()),
+    Expression::VectorAccess(vector, index) => {
+      LValueExpression::Vector(Box::new(expression_to_lvalue(vector)?), index.clone())
+    }
+    Expression::VectorSubrange(vector, high, low) => {
+      LValueExpression::VectorRange(Box::new(expression_to_lvalue(vector)?), high.clone(), low.clone())
+    }
+    Expression::VectorAppend(..) => {
+      let mut parts = Vec::new();
+      collect_appended_lvalues(expression, &mut parts)?;
+      LValueExpression::VectorConcat(parts)
+    }
+    Expression::Field(record, field) => LValueExpression::Field(Box::new(expression_to_lvalue(record)?), field.clone()),
+    Expression::Attribute(_, _, inner) => return expression_to_lvalue(inner),
+    _ => return None,
+  };
+  Some(Located { location: expression.location.clone(), value })
+}
+
+fn collect_appended_lvalues(expression: &LocatedExpression, parts: &mut Vec<LocatedLValueExpression>) -> Option<()> {
+  match &expression.value {
+    Expression::VectorAppend(left, right) => {
+      collect_appended_lvalues(left, parts)?;
+      collect_appended_lvalues(right, parts)
+    }
+    _ => {
+      parts.push(expression_to_lvalue(expression)?);
+      Some(())
+    }
+  }
+}
@@ -8,6 +8,7 @@ This module defines errors related to scanning and parsing.
 use std::{error::Error, fmt::Display};
 use std::fmt::{Debug, Formatter};
 
+use crate::abstractions::ArithmeticError;
 use crate::parser::location::Located;
 
 pub type LocatedParseError   = Located<ParserError>;
@@ -24,6 +25,11 @@ pub enum ParserError {
   UnmatchedOpenBlock,
   UnmatchedCloseBlock,
   UnknownOperator,
+  /// A number literal (including fixity precedences and vector subrange bounds) is out of range for the active
+  /// `BigInteger` backend or otherwise not a valid number.
+  NumberLiteral(ArithmeticError),
+  /// Sail source was given to a build of rigging without a Sail front end.
+  Unavailable,
   // UnknownError(Box<dyn Error>),
 }
 
@@ -36,11 +42,13 @@ impl ParserError {
       | ParserError::MalformedNumberLiteral(_)
       | ParserError::UnmatchedOpenBlock
       | ParserError::UnmatchedCloseBlock
-      | ParserError::UnknownOperator => false,
+      | ParserError::UnknownOperator
+      | ParserError::NumberLiteral(_) => false,
 
       // | ParserError::UnknownError(_)
       | ParserError::UnrecognizedCharacter(_)
-      | ParserError::UnterminatedStringLiteral => true,
+      | ParserError::UnterminatedStringLiteral
+      | ParserError::Unavailable => true,
 
     }
   }
@@ -79,6 +87,14 @@ impl ParserError {
         write!(f, "unknown operator")
       }
 
+      ParserError::NumberLiteral(error) => {
+        write!(f, "invalid number literal: {}", error)
+      }
+
+      ParserError::Unavailable => {
+        write!(f, "this build of rigging cannot parse Sail source")
+      }
+
       // ParserError::UnknownError(_) => {
       //   write!(f, "unknown error")
       // }
@@ -99,3 +115,9 @@ impl Display for ParserError {
 }
 
 impl Error for ParserError {}
+
+impl From<ArithmeticError> for ParserError {
+  fn from(error: ArithmeticError) -> Self {
+    ParserError::NumberLiteral(error)
+  }
+}
@@ -20,7 +20,7 @@ use std::ops::{Deref, DerefMut};
 
 use codemap::Span;
 
-#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub enum SourceLocation {
   #[default]
   /// The default "empty" location
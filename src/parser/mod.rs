@@ -1,9 +1,13 @@
 use codemap::Spanned;
 
-mod ast;
+pub mod ast;
+pub mod ast_util;
 mod lexer;
-mod errors;
-mod location;
+pub mod errors;
+pub mod location;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod visit;
 
 
 pub type SpannedToken<'input> = Spanned<Token<'input>>;
@@ -0,0 +1,394 @@
+/*!
+
+Builders for syntax trees, standing in for the parser in the unit tests of the passes that follow it.
+
+Every node is given its own `SourceLocation::Unique` location, as the type checker and the passes after it record what
+they learn about an expression by its location. Functions are built with a single clause whose parameters are
+gathered into a tuple pattern, as the parser does for `function f(x, y) = ...`.
+
+*/
+
+// Not every test uses every builder.
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::location::{Located, SourceLocation};
+
+static NEXT_LOCATION: AtomicI32 = AtomicI32::new(0);
+
+/// A location distinct from every other made, on any thread, as a loader may parse on several.
+pub fn location() -> SourceLocation {
+  SourceLocation::Unique(NEXT_LOCATION.fetch_add(1, Ordering::Relaxed), Box::new(SourceLocation::Unknown))
+}
+
+pub fn located<T>(value: T) -> Located<T> {
+  Located { location: location(), value }
+}
+
+pub fn id(name: &str) -> LocatedIdentifier {
+  located(IdentifierType::Regular(name.to_string()))
+}
+
+pub fn operator(name: &str) -> LocatedIdentifier {
+  located(IdentifierType::Operator(name.to_string()))
+}
+
+fn number_literal(n: i64) -> LocatedLiteral {
+  located(Literal::Number(BigInteger::from_i64(n)))
+}
+
+// region Types
+
+pub fn typ(name: &str) -> LocatedAbstractType {
+  located(AbstractType::Identifier(id(name)))
+}
+
+/// A type variable, written with its tick, as in `'n`.
+pub fn type_variable(name: &str) -> LocatedAbstractType {
+  located(AbstractType::Variable(located(KindIdentifier(name.to_string()))))
+}
+
+pub fn type_number(n: i64) -> LocatedAbstractType {
+  located(AbstractType::Literal(number_literal(n)))
+}
+
+pub fn type_application(name: &str, arguments: Vec<LocatedAbstractType>) -> LocatedAbstractType {
+  located(AbstractType::TypeConstructorApplication(id(name), arguments))
+}
+
+/// A binary type-level operator other than `+`, `-` and `*`, such as `>=` or `&` in a constraint.
+pub fn type_operator(left: LocatedAbstractType, name: &str, right: LocatedAbstractType) -> LocatedAbstractType {
+  located(AbstractType::TypeConstructorApplication(operator(name), vec![left, right]))
+}
+
+pub fn type_sum(left: LocatedAbstractType, right: LocatedAbstractType) -> LocatedAbstractType {
+  located(AbstractType::Sum(Box::new(left), Box::new(right)))
+}
+
+pub fn type_minus(left: LocatedAbstractType, right: LocatedAbstractType) -> LocatedAbstractType {
+  located(AbstractType::Minus(Box::new(left), Box::new(right)))
+}
+
+pub fn bits(n: i64) -> LocatedAbstractType {
+  type_application("bits", vec![type_number(n)])
+}
+
+pub fn tuple_type(elements: Vec<LocatedAbstractType>) -> LocatedAbstractType {
+  located(AbstractType::Tuple(elements))
+}
+
+/// `(a, b) -> c`, or `a -> c` for a single argument.
+pub fn function_type(mut arguments: Vec<LocatedAbstractType>, result: LocatedAbstractType) -> LocatedAbstractType {
+  let lhs = match arguments.len() {
+    1 => arguments.remove(0),
+    _ => tuple_type(arguments),
+  };
+  located(AbstractType::Function {
+    lhs   : Box::new(lhs),
+    rhs   : Box::new(result),
+    effect: Box::new(located(AbstractType::EffectSet(Vec::new()))),
+  })
+}
+
+/// `forall 'a 'b, constraint. body`, with the variables' kinds given, or just `body` if there are neither variables nor
+/// a constraint.
+pub fn scheme(variables: &[(&str, Kind)], constraint: Option<LocatedAbstractType>, body: LocatedAbstractType) -> LocatedTypeScheme {
+  let mut items: Vec<LocatedQuantifierItem> = variables
+      .iter()
+      .map(|(name, kind)| {
+        located(QuantifierItem::KindedIdentifier(located(KindedIdentifier {
+          identifiers: vec![located(KindIdentifier(name.to_string()))],
+          annotation : None,
+          kind       : Some(located(*kind)),
+        })))
+      })
+      .collect();
+  items.extend(constraint.map(|constraint| located(QuantifierItem::Constraint(constraint))));
+  let quantifier = match items.is_empty() {
+    true => TypeQuantifier::NoForAll,
+    false => TypeQuantifier::TypeQuantifiers(items),
+  };
+  located(TypeScheme { quantifier: located(quantifier), abstract_type: body })
+}
+
+// endregion
+
+// region Expressions
+
+pub fn var(name: &str) -> LocatedExpression {
+  located(Expression::Identifier(id(name)))
+}
+
+pub fn literal(literal: Literal) -> LocatedExpression {
+  located(Expression::Literal(located(literal)))
+}
+
+pub fn number(n: i64) -> LocatedExpression {
+  located(Expression::Literal(number_literal(n)))
+}
+
+/// A bitvector literal written in hexadecimal, such as `0x0F`.
+pub fn hex(digits: &str) -> LocatedExpression {
+  literal(Literal::Hexadecimal(digits.to_string()))
+}
+
+pub fn boolean(value: bool) -> LocatedExpression {
+  literal(if value { Literal::True } else { Literal::False })
+}
+
+pub fn string(value: &str) -> LocatedExpression {
+  literal(Literal::String(value.to_string()))
+}
+
+pub fn unit() -> LocatedExpression {
+  literal(Literal::Unit)
+}
+
+pub fn call(name: &str, arguments: Vec<LocatedExpression>) -> LocatedExpression {
+  located(Expression::Application(id(name), arguments))
+}
+
+pub fn infix(left: LocatedExpression, name: &str, right: LocatedExpression) -> LocatedExpression {
+  located(Expression::InfixApplication(Box::new(left), operator(name), Box::new(right)))
+}
+
+pub fn block(expressions: Vec<LocatedExpression>) -> LocatedExpression {
+  located(Expression::Block(expressions))
+}
+
+pub fn tuple(elements: Vec<LocatedExpression>) -> LocatedExpression {
+  located(Expression::Tuple(elements))
+}
+
+pub fn typed(typ: LocatedAbstractType, expression: LocatedExpression) -> LocatedExpression {
+  located(Expression::Typed(Box::new(typ), Box::new(expression)))
+}
+
+pub fn if_then_else(condition: LocatedExpression, then_expr: LocatedExpression, else_expr: LocatedExpression) -> LocatedExpression {
+  located(Expression::If {
+    condition  : Box::new(condition),
+    then_expr  : Box::new(then_expr),
+    else_expr  : Box::new(else_expr),
+    if_location: IfLocation { if_loc: location(), then_loc: location(), else_loc: Some(location()) },
+  })
+}
+
+/// `let pattern = value in body`
+pub fn let_in(pattern: LocatedPattern, value: LocatedExpression, body: LocatedExpression) -> LocatedExpression {
+  let binding = located(LetBinding::ValueBinding(Box::new(pattern), Box::new(value)));
+  located(Expression::Let(binding, Box::new(body)))
+}
+
+/// `var target = value in body`, as the parser leaves a `var` declaration in a block.
+pub fn var_in(target: LocatedExpression, value: LocatedExpression, body: LocatedExpression) -> LocatedExpression {
+  located(Expression::Variable(Box::new(target), Box::new(value), Box::new(body)))
+}
+
+pub fn assign(target: LocatedExpression, value: LocatedExpression) -> LocatedExpression {
+  located(Expression::Assign(Box::new(target), Box::new(value)))
+}
+
+pub fn field(expression: LocatedExpression, name: &str) -> LocatedExpression {
+  located(Expression::Field(Box::new(expression), id(name)))
+}
+
+pub fn reference(name: &str) -> LocatedExpression {
+  located(Expression::Reference(id(name)))
+}
+
+pub fn dereference(expression: LocatedExpression) -> LocatedExpression {
+  located(Expression::Dereference(Box::new(expression)))
+}
+
+pub fn while_loop(condition: LocatedExpression, body: LocatedExpression) -> LocatedExpression {
+  located(Expression::Loop(LoopType::While, Box::new(located(None)), Box::new(condition), Box::new(body)))
+}
+
+/// A `while` loop with a termination measure, `while termination_measure { measure } condition do body`.
+pub fn measured_while_loop(measure: LocatedExpression, condition: LocatedExpression, body: LocatedExpression) -> LocatedExpression {
+  located(Expression::Loop(LoopType::While, Box::new(located(Some(measure))), Box::new(condition), Box::new(body)))
+}
+
+/// `foreach (identifier from start to end)`, stepping by one.
+pub fn for_loop(identifier: &str, start: LocatedExpression, end: LocatedExpression, body: LocatedExpression) -> LocatedExpression {
+  located(Expression::For {
+    identifier: id(identifier),
+    start     : Box::new(start),
+    end       : Box::new(end),
+    step      : Box::new(number(1)),
+    typ       : Box::new(located(AbstractType::Increasing)),
+    body      : Box::new(body),
+  })
+}
+
+pub fn matching(scrutinee: LocatedExpression, arms: Vec<LocatedPatternExpression>) -> LocatedExpression {
+  located(Expression::Match(Box::new(scrutinee), arms))
+}
+
+pub fn try_catch(body: LocatedExpression, arms: Vec<LocatedPatternExpression>) -> LocatedExpression {
+  located(Expression::Try(Box::new(body), arms))
+}
+
+pub fn throw(expression: LocatedExpression) -> LocatedExpression {
+  located(Expression::Throw(Box::new(expression)))
+}
+
+pub fn early_return(expression: LocatedExpression) -> LocatedExpression {
+  located(Expression::Return(Box::new(expression)))
+}
+
+pub fn assertion(condition: LocatedExpression, message: &str) -> LocatedExpression {
+  located(Expression::Assert(Box::new(condition), Box::new(string(message))))
+}
+
+pub fn arm(pattern: LocatedPattern, body: LocatedExpression) -> LocatedPatternExpression {
+  located(PatternExpression::Pattern(Box::new(pattern), Box::new(body)))
+}
+
+pub fn guarded_arm(pattern: LocatedPattern, guard: LocatedExpression, body: LocatedExpression) -> LocatedPatternExpression {
+  located(PatternExpression::PatternWhen(Box::new(pattern), Box::new(guard), Box::new(body)))
+}
+
+// endregion
+
+// region Patterns
+
+/// A pattern that is a bare identifier: a variable, or an enum member or nullary constructor.
+pub fn pattern(name: &str) -> LocatedPattern {
+  located(Pattern::Identifier(id(name)))
+}
+
+pub fn wildcard() -> LocatedPattern {
+  located(Pattern::Wildcard)
+}
+
+pub fn literal_pattern(literal: Literal) -> LocatedPattern {
+  located(Pattern::Literal(located(literal)))
+}
+
+pub fn number_pattern(n: i64) -> LocatedPattern {
+  located(Pattern::Literal(number_literal(n)))
+}
+
+pub fn constructor_pattern(name: &str, arguments: Vec<LocatedPattern>) -> LocatedPattern {
+  located(Pattern::Constructor(id(name), arguments))
+}
+
+pub fn tuple_pattern(elements: Vec<LocatedPattern>) -> LocatedPattern {
+  located(Pattern::Tuple(elements))
+}
+
+pub fn typed_pattern(typ: LocatedAbstractType, pattern: LocatedPattern) -> LocatedPattern {
+  located(Pattern::Typed(Box::new(typ), Box::new(pattern)))
+}
+
+/// The pattern for a function's parameters: the one parameter itself, a tuple of several, or `()` for none.
+fn parameters_pattern(mut parameters: Vec<LocatedPattern>) -> LocatedPattern {
+  match parameters.len() {
+    0 => literal_pattern(Literal::Unit),
+    1 => parameters.remove(0),
+    _ => tuple_pattern(parameters),
+  }
+}
+
+// endregion
+
+// region Definitions
+
+pub fn definitions(definitions: Vec<LocatedDefinition>) -> Definitions {
+  Definitions(vec![("test.sail".to_string(), definitions)])
+}
+
+/// `val name : typ`
+pub fn val(name: &str, typ: LocatedAbstractType) -> LocatedDefinition {
+  val_scheme(name, scheme(&[], None, typ))
+}
+
+pub fn val_scheme(name: &str, scheme: LocatedTypeScheme) -> LocatedDefinition {
+  located(Definition::ValueSpec(located(ValueSpecification::ValueSpec(Box::new(scheme), id(name), None))))
+}
+
+/// `val name = "implementation" : typ`, an extern function bound to the same implementation on every backend.
+pub fn extern_val(name: &str, implementation: &str, typ: LocatedAbstractType) -> LocatedDefinition {
+  let bindings = ExternalBindings { is_pure: true, bindings: vec![("_".to_string(), implementation.to_string())] };
+  let specification = ValueSpecification::ValueSpec(Box::new(scheme(&[], None, typ)), id(name), Some(bindings));
+  located(Definition::ValueSpec(located(specification)))
+}
+
+/// `function name(parameters) = body`
+pub fn function(name: &str, parameters: Vec<LocatedPattern>, body: LocatedExpression) -> LocatedDefinition {
+  function_clauses(name, vec![arm(parameters_pattern(parameters), body)])
+}
+
+/// A function of several clauses, each matching its parameters with its own pattern.
+pub fn function_clauses(name: &str, clauses: Vec<LocatedPatternExpression>) -> LocatedDefinition {
+  located(Definition::FunctionDefinition(located(FunctionDefinition::Function(
+    located(None),
+    located(None),
+    located(None),
+    clauses.into_iter().map(|clause| located(FunctionClause::Clause(id(name), Box::new(clause)))).collect(),
+  ))))
+}
+
+/// `function name(parameters) = body` with `termination_measure { parameter => measure }`.
+pub fn measured_function(
+  name      : &str,
+  parameters: Vec<LocatedPattern>,
+  measure   : (LocatedPattern, LocatedExpression),
+  body      : LocatedExpression,
+) -> LocatedDefinition {
+  let clause = located(FunctionClause::Clause(id(name), Box::new(arm(parameters_pattern(parameters), body))));
+  located(Definition::FunctionDefinition(located(FunctionDefinition::Function(
+    located(Some((Box::new(measure.0), Box::new(measure.1)))),
+    located(None),
+    located(None),
+    vec![clause],
+  ))))
+}
+
+pub fn overload(name: &str, targets: &[&str]) -> LocatedDefinition {
+  located(Definition::Overload(operator(name), targets.iter().map(|target| id(target)).collect()))
+}
+
+pub fn register(name: &str, typ: LocatedAbstractType, initial: Option<LocatedExpression>) -> LocatedDefinition {
+  let declaration = DeclarationSpecification::Register(Box::new(typ), id(name), initial.map(Box::new));
+  located(Definition::Register(located(declaration)))
+}
+
+/// `let pattern = value` at the top level.
+pub fn value(pattern: LocatedPattern, value: LocatedExpression) -> LocatedDefinition {
+  located(Definition::ValueDefinition(located(LetBinding::ValueBinding(Box::new(pattern), Box::new(value)))))
+}
+
+pub fn type_synonym(name: &str, body: LocatedAbstractType) -> LocatedDefinition {
+  let definition = TypeDefinition::Abbreviation(id(name), located(TypeQuantifier::NoForAll), located(Kind::Type), Box::new(body));
+  located(Definition::TypeDefinition(located(definition)))
+}
+
+pub fn enumeration(name: &str, members: &[&str]) -> LocatedDefinition {
+  let members = members.iter().map(|member| (id(member), None)).collect();
+  located(Definition::TypeDefinition(located(TypeDefinition::Enum(id(name), Vec::new(), members))))
+}
+
+/// `union name = { constructor : typ, ... }`
+pub fn union(name: &str, constructors: Vec<(&str, LocatedAbstractType)>) -> LocatedDefinition {
+  let constructors = constructors
+      .into_iter()
+      .map(|(constructor, typ)| located(TypeUnion::TypeIdentifier(Box::new(typ), id(constructor))))
+      .collect();
+  located(Definition::TypeDefinition(located(TypeDefinition::Variant(id(name), located(TypeQuantifier::NoForAll), constructors))))
+}
+
+pub fn record(name: &str, fields: Vec<(&str, LocatedAbstractType)>) -> LocatedDefinition {
+  let fields = fields.into_iter().map(|(field, typ)| (typ, id(field))).collect();
+  located(Definition::TypeDefinition(located(TypeDefinition::Record(id(name), located(TypeQuantifier::NoForAll), fields))))
+}
+
+pub fn scattered(definition: ScatteredDefinition) -> LocatedDefinition {
+  located(Definition::ScatteredDefinition(located(definition)))
+}
+
+// endregion
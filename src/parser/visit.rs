@@ -0,0 +1,582 @@
+/*!
+
+A read-only traversal of the AST.
+
+Implement `Visitor`, override the `visit_*` methods for the nodes of interest, and call the matching `walk_*` function
+from an override to continue into the node's children. The default implementation of every `visit_*` method simply
+walks, so a visitor that overrides nothing visits the whole tree and does nothing.
+
+*/
+
+use crate::parser::ast::*;
+
+pub trait Visitor: Sized {
+  fn visit_definition(&mut self, definition: &LocatedDefinition) {
+    walk_definition(self, definition)
+  }
+
+  fn visit_type_definition(&mut self, definition: &LocatedTypeDefinition) {
+    walk_type_definition(self, definition)
+  }
+
+  fn visit_function_definition(&mut self, definition: &LocatedFunctionDefinition) {
+    walk_function_definition(self, definition)
+  }
+
+  fn visit_function_clause(&mut self, clause: &LocatedFunctionClause) {
+    walk_function_clause(self, clause)
+  }
+
+  fn visit_mapping_definition(&mut self, definition: &LocatedMappingDefinition) {
+    walk_mapping_definition(self, definition)
+  }
+
+  fn visit_mapping_clause(&mut self, clause: &LocatedMappingClause) {
+    walk_mapping_clause(self, clause)
+  }
+
+  fn visit_mapping_pattern(&mut self, pattern: &LocatedMappingPattern) {
+    walk_mapping_pattern(self, pattern)
+  }
+
+  fn visit_type_union(&mut self, union: &LocatedTypeUnion) {
+    walk_type_union(self, union)
+  }
+
+  fn visit_type_quantifier(&mut self, quantifier: &LocatedTypeQuantifier) {
+    walk_type_quantifier(self, quantifier)
+  }
+
+  fn visit_abstract_type(&mut self, abstract_type: &LocatedAbstractType) {
+    walk_abstract_type(self, abstract_type)
+  }
+
+  fn visit_expression(&mut self, expression: &LocatedExpression) {
+    walk_expression(self, expression)
+  }
+
+  fn visit_pattern_expression(&mut self, arm: &LocatedPatternExpression) {
+    walk_pattern_expression(self, arm)
+  }
+
+  fn visit_let_binding(&mut self, binding: &LocatedLetBinding) {
+    walk_let_binding(self, binding)
+  }
+
+  fn visit_pattern(&mut self, pattern: &LocatedPattern) {
+    walk_pattern(self, pattern)
+  }
+
+  fn visit_lvalue(&mut self, lvalue: &LocatedLValueExpression) {
+    walk_lvalue(self, lvalue)
+  }
+}
+
+pub fn walk_definitions<V: Visitor>(visitor: &mut V, definitions: &Definitions) {
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      visitor.visit_definition(definition);
+    }
+  }
+}
+
+pub fn walk_definition<V: Visitor>(visitor: &mut V, definition: &LocatedDefinition) {
+  match &definition.value {
+    Definition::TypeDefinition(type_definition) => visitor.visit_type_definition(type_definition),
+    Definition::Constraint(constraint) => visitor.visit_abstract_type(constraint),
+    Definition::FunctionDefinition(function) => visitor.visit_function_definition(function),
+    Definition::MappingDefinition(mapping) => visitor.visit_mapping_definition(mapping),
+    Definition::Implementation(clause) => visitor.visit_function_clause(clause),
+    Definition::ValueDefinition(binding) => visitor.visit_let_binding(binding),
+    Definition::ValueSpec(specification) => {
+      let ValueSpecification::ValueSpec(scheme, _, _) = &specification.value;
+      walk_type_scheme(visitor, scheme);
+    }
+    Definition::OutcomeSpec(outcome, definitions) => {
+      let OutcomeSpec::Outcome(_, scheme, _) = &outcome.value;
+      walk_type_scheme(visitor, scheme);
+      for definition in definitions {
+        visitor.visit_definition(definition);
+      }
+    }
+    Definition::Instantiation(_, substitutions) => {
+      for substitution in substitutions {
+        if let InstantiationSubstitution::TypeSubstitution(_, abstract_type) = &substitution.value {
+          visitor.visit_abstract_type(abstract_type);
+        }
+      }
+    }
+    Definition::DefaultTypingSpec(specification) => {
+      let DefaultTypingSpec::Order(_, order) = &specification.value;
+      visitor.visit_abstract_type(order);
+    }
+    Definition::ScatteredDefinition(scattered) => walk_scattered_definition(visitor, scattered),
+    Definition::Measure(_, pattern, expression) => {
+      visitor.visit_pattern(pattern);
+      visitor.visit_expression(expression);
+    }
+    Definition::LoopMeasures(_, measures) => {
+      for measure in measures {
+        visitor.visit_expression(&measure.expression);
+      }
+    }
+    Definition::Register(declaration) => {
+      let DeclarationSpecification::Register(abstract_type, _, initializer) = &declaration.value;
+      visitor.visit_abstract_type(abstract_type);
+      if let Some(initializer) = initializer {
+        visitor.visit_expression(initializer);
+      }
+    }
+    Definition::Private(inner)
+    | Definition::Attribute(_, _, inner)
+    | Definition::Documentation(_, inner) => visitor.visit_definition(inner),
+    Definition::InternalMutRec(functions) => {
+      for function in functions {
+        visitor.visit_function_definition(function);
+      }
+    }
+    Definition::Overload(..)
+    | Definition::Fixity(..)
+    | Definition::Pragma(..) => {}
+  }
+}
+
+pub fn walk_type_scheme<V: Visitor>(visitor: &mut V, scheme: &LocatedTypeScheme) {
+  visitor.visit_type_quantifier(&scheme.quantifier);
+  visitor.visit_abstract_type(&scheme.abstract_type);
+}
+
+pub fn walk_type_definition<V: Visitor>(visitor: &mut V, definition: &LocatedTypeDefinition) {
+  match &definition.value {
+    TypeDefinition::Abbreviation(_, quantifier, _, abstract_type) => {
+      visitor.visit_type_quantifier(quantifier);
+      visitor.visit_abstract_type(abstract_type);
+    }
+    TypeDefinition::Record(_, quantifier, fields) => {
+      visitor.visit_type_quantifier(quantifier);
+      for (abstract_type, _) in fields {
+        visitor.visit_abstract_type(abstract_type);
+      }
+    }
+    TypeDefinition::Variant(_, quantifier, unions) => {
+      visitor.visit_type_quantifier(quantifier);
+      for union in unions {
+        visitor.visit_type_union(union);
+      }
+    }
+    TypeDefinition::Enum(_, functions, members) => {
+      for (_, abstract_type) in functions {
+        visitor.visit_abstract_type(abstract_type);
+      }
+      for (_, value) in members {
+        if let Some(value) = value {
+          visitor.visit_expression(value);
+        }
+      }
+    }
+    TypeDefinition::Abstract(..) => {}
+    TypeDefinition::Bitfield(_, abstract_type, fields) => {
+      visitor.visit_abstract_type(abstract_type);
+      for (_, range) in fields {
+        walk_index_range(visitor, range);
+      }
+    }
+  }
+}
+
+pub fn walk_index_range<V: Visitor>(visitor: &mut V, range: &LocatedIndexRange) {
+  match &range.value {
+    IndexRange::Single(index) => visitor.visit_abstract_type(index),
+    IndexRange::Range(high, low) => {
+      visitor.visit_abstract_type(high);
+      visitor.visit_abstract_type(low);
+    }
+    IndexRange::Concat(left, right) => {
+      walk_index_range(visitor, left);
+      walk_index_range(visitor, right);
+    }
+  }
+}
+
+pub fn walk_type_union<V: Visitor>(visitor: &mut V, union: &LocatedTypeUnion) {
+  match &union.value {
+    TypeUnion::Private(inner)
+    | TypeUnion::Attribute(_, _, inner)
+    | TypeUnion::Documentation(_, inner) => visitor.visit_type_union(inner),
+    TypeUnion::TypeIdentifier(abstract_type, _) => visitor.visit_abstract_type(abstract_type),
+    TypeUnion::AnonymousRecord(fields, _) => {
+      for (abstract_type, _) in fields {
+        visitor.visit_abstract_type(abstract_type);
+      }
+    }
+  }
+}
+
+pub fn walk_type_quantifier<V: Visitor>(visitor: &mut V, quantifier: &LocatedTypeQuantifier) {
+  if let TypeQuantifier::TypeQuantifiers(items) = &quantifier.value {
+    for item in items {
+      if let QuantifierItem::Constraint(constraint) = &item.value {
+        visitor.visit_abstract_type(constraint);
+      }
+    }
+  }
+}
+
+pub fn walk_function_definition<V: Visitor>(visitor: &mut V, definition: &LocatedFunctionDefinition) {
+  let FunctionDefinition::Function(recursive, annotation, effect, clauses) = &definition.value;
+  walk_recursive_option(visitor, recursive);
+  walk_type_annotation_option(visitor, annotation);
+  if let Some(effect) = &effect.value {
+    visitor.visit_abstract_type(effect);
+  }
+  for clause in clauses {
+    visitor.visit_function_clause(clause);
+  }
+}
+
+pub fn walk_recursive_option<V: Visitor>(visitor: &mut V, recursive: &LocatedRecursiveOption) {
+  if let Some((pattern, measure)) = &recursive.value {
+    visitor.visit_pattern(pattern);
+    visitor.visit_expression(measure);
+  }
+}
+
+pub fn walk_type_annotation_option<V: Visitor>(visitor: &mut V, annotation: &LocatedTypeAnnotationOption) {
+  if let Some((quantifier, abstract_type)) = &annotation.value {
+    visitor.visit_type_quantifier(quantifier);
+    visitor.visit_abstract_type(abstract_type);
+  }
+}
+
+pub fn walk_function_clause<V: Visitor>(visitor: &mut V, clause: &LocatedFunctionClause) {
+  match &clause.value {
+    FunctionClause::Private(inner)
+    | FunctionClause::Attribute(_, _, inner)
+    | FunctionClause::Documentation(_, inner) => visitor.visit_function_clause(inner),
+    FunctionClause::Clause(_, arm) => visitor.visit_pattern_expression(arm),
+  }
+}
+
+pub fn walk_mapping_definition<V: Visitor>(visitor: &mut V, definition: &LocatedMappingDefinition) {
+  let MappingDefinition::Mapping(_, scheme, clauses) = &definition.value;
+  if let Some(scheme) = &scheme.value {
+    walk_type_scheme(visitor, scheme);
+  }
+  for clause in clauses {
+    visitor.visit_mapping_clause(clause);
+  }
+}
+
+pub fn walk_mapping_clause<V: Visitor>(visitor: &mut V, clause: &LocatedMappingClause) {
+  match &clause.value {
+    MappingClause::Attribute(_, _, inner)
+    | MappingClause::Documentation(_, inner) => visitor.visit_mapping_clause(inner),
+    MappingClause::Bidirectional(left, right) => {
+      walk_mapping_pattern_expression(visitor, left);
+      walk_mapping_pattern_expression(visitor, right);
+    }
+    MappingClause::ForwardsDeprecated(left, expression) => {
+      walk_mapping_pattern_expression(visitor, left);
+      visitor.visit_expression(expression);
+    }
+    MappingClause::Forwards(arm)
+    | MappingClause::Backwards(arm) => visitor.visit_pattern_expression(arm),
+  }
+}
+
+pub fn walk_mapping_pattern_expression<V: Visitor>(visitor: &mut V, arm: &LocatedMappingPatternExpression) {
+  match &arm.value {
+    MappingPatternExpression::Pattern(pattern) => visitor.visit_mapping_pattern(pattern),
+    MappingPatternExpression::PatternWhen(pattern, guard) => {
+      visitor.visit_mapping_pattern(pattern);
+      visitor.visit_expression(guard);
+    }
+  }
+}
+
+pub fn walk_mapping_pattern<V: Visitor>(visitor: &mut V, pattern: &LocatedMappingPattern) {
+  match &pattern.value {
+    MappingPattern::Literal(_)
+    | MappingPattern::Identifier(_)
+    | MappingPattern::VectorSubrange(..) => {}
+    MappingPattern::Application(_, patterns)
+    | MappingPattern::Vector(patterns)
+    | MappingPattern::VectorConcat(patterns)
+    | MappingPattern::Tuple(patterns)
+    | MappingPattern::List(patterns)
+    | MappingPattern::StringAppend(patterns) => {
+      for pattern in patterns {
+        visitor.visit_mapping_pattern(pattern);
+      }
+    }
+    MappingPattern::Cons(head, tail) => {
+      visitor.visit_mapping_pattern(head);
+      visitor.visit_mapping_pattern(tail);
+    }
+    MappingPattern::Typed(pattern, abstract_type) => {
+      visitor.visit_mapping_pattern(pattern);
+      visitor.visit_abstract_type(abstract_type);
+    }
+    MappingPattern::As(pattern, _) => visitor.visit_mapping_pattern(pattern),
+    MappingPattern::Struct(fields) => {
+      for (_, pattern) in fields {
+        visitor.visit_mapping_pattern(pattern);
+      }
+    }
+  }
+}
+
+pub fn walk_scattered_definition<V: Visitor>(visitor: &mut V, scattered: &LocatedScatteredDefinition) {
+  match &scattered.value {
+    ScatteredDefinition::Function(recursive, annotation, effect, _) => {
+      walk_recursive_option(visitor, recursive);
+      walk_type_annotation_option(visitor, annotation);
+      if let Some(effect) = &effect.value {
+        visitor.visit_abstract_type(effect);
+      }
+    }
+    ScatteredDefinition::FunctionClause(clause) => visitor.visit_function_clause(clause),
+    ScatteredDefinition::Variant(_, quantifier) => visitor.visit_type_quantifier(quantifier),
+    ScatteredDefinition::UnionClause(_, union) => visitor.visit_type_union(union),
+    ScatteredDefinition::Mapping(_, annotation) => walk_type_annotation_option(visitor, annotation),
+    ScatteredDefinition::MapClause(_, clause) => visitor.visit_mapping_clause(clause),
+    ScatteredDefinition::Enumeration(_)
+    | ScatteredDefinition::EnumerationMember(..)
+    | ScatteredDefinition::End(_) => {}
+  }
+}
+
+pub fn walk_abstract_type<V: Visitor>(visitor: &mut V, abstract_type: &LocatedAbstractType) {
+  match &abstract_type.value {
+    AbstractType::Identifier(_)
+    | AbstractType::Variable(_)
+    | AbstractType::Literal(_)
+    | AbstractType::NumberSet(_)
+    | AbstractType::Increasing
+    | AbstractType::Decreasing
+    | AbstractType::EffectSet(_)
+    | AbstractType::Wildcard => {}
+    AbstractType::In(left, right)
+    | AbstractType::Times(left, right)
+    | AbstractType::Sum(left, right)
+    | AbstractType::Minus(left, right) => {
+      visitor.visit_abstract_type(left);
+      visitor.visit_abstract_type(right);
+    }
+    AbstractType::Exponential(inner)
+    | AbstractType::Negative(inner)
+    | AbstractType::Parenthesized(inner) => visitor.visit_abstract_type(inner),
+    AbstractType::Infix(tokens) => {
+      for (token, _) in tokens {
+        if let InfixToken::Primary(inner) = token {
+          visitor.visit_abstract_type(inner);
+        }
+      }
+    }
+    AbstractType::Function { lhs, rhs, effect }
+    | AbstractType::Bidirectional { lhs, rhs, effect } => {
+      visitor.visit_abstract_type(lhs);
+      visitor.visit_abstract_type(rhs);
+      visitor.visit_abstract_type(effect);
+    }
+    AbstractType::Tuple(types)
+    | AbstractType::TypeConstructorApplication(_, types) => {
+      for inner in types {
+        visitor.visit_abstract_type(inner);
+      }
+    }
+    AbstractType::If { condition, then, elsewise } => {
+      visitor.visit_abstract_type(condition);
+      visitor.visit_abstract_type(then);
+      visitor.visit_abstract_type(elsewise);
+    }
+    AbstractType::Existential(_, constraint, body) => {
+      visitor.visit_abstract_type(constraint);
+      visitor.visit_abstract_type(body);
+    }
+  }
+}
+
+pub fn walk_expression<V: Visitor>(visitor: &mut V, expression: &LocatedExpression) {
+  match &expression.value {
+    Expression::Identifier(_)
+    | Expression::Reference(_)
+    | Expression::Literal(_) => {}
+    Expression::Block(expressions)
+    | Expression::Application(_, expressions)
+    | Expression::Tuple(expressions)
+    | Expression::Vector(expressions)
+    | Expression::List(expressions)
+    | Expression::Struct(expressions) => {
+      for expression in expressions {
+        visitor.visit_expression(expression);
+      }
+    }
+    Expression::Dereference(inner)
+    | Expression::Field(inner, _)
+    | Expression::Exit(inner)
+    | Expression::Throw(inner)
+    | Expression::Return(inner)
+    | Expression::Attribute(_, _, inner)
+    | Expression::InternalReturn(inner) => visitor.visit_expression(inner),
+    Expression::Typed(abstract_type, inner)
+    | Expression::InternalAssume(abstract_type, inner) => {
+      visitor.visit_abstract_type(abstract_type);
+      visitor.visit_expression(inner);
+    }
+    Expression::InfixApplication(left, _, right)
+    | Expression::VectorAccess(left, right)
+    | Expression::VectorAppend(left, right)
+    | Expression::Cons(left, right)
+    | Expression::Assign(left, right)
+    | Expression::Assert(left, right) => {
+      visitor.visit_expression(left);
+      visitor.visit_expression(right);
+    }
+    Expression::Infix(tokens) => {
+      for (token, _) in tokens {
+        if let InfixToken::Primary(inner) = token {
+          visitor.visit_expression(inner);
+        }
+      }
+    }
+    Expression::If { condition, then_expr, else_expr, .. } => {
+      visitor.visit_expression(condition);
+      visitor.visit_expression(then_expr);
+      visitor.visit_expression(else_expr);
+    }
+    Expression::Loop(_, measure, condition, body) => {
+      if let Some(measure) = &measure.value {
+        visitor.visit_expression(measure);
+      }
+      visitor.visit_expression(condition);
+      visitor.visit_expression(body);
+    }
+    Expression::For { start, end, step, typ, body, .. } => {
+      visitor.visit_expression(start);
+      visitor.visit_expression(end);
+      visitor.visit_expression(step);
+      visitor.visit_abstract_type(typ);
+      visitor.visit_expression(body);
+    }
+    Expression::VectorSubrange(first, second, third)
+    | Expression::VectorUpdate(first, second, third)
+    | Expression::Variable(first, second, third) => {
+      visitor.visit_expression(first);
+      visitor.visit_expression(second);
+      visitor.visit_expression(third);
+    }
+    Expression::VectorUpdateSubrange(vector, high, low, value) => {
+      visitor.visit_expression(vector);
+      visitor.visit_expression(high);
+      visitor.visit_expression(low);
+      visitor.visit_expression(value);
+    }
+    Expression::StructUpdate(inner, fields) => {
+      visitor.visit_expression(inner);
+      for field in fields {
+        visitor.visit_expression(field);
+      }
+    }
+    Expression::Match(scrutinee, arms)
+    | Expression::Try(scrutinee, arms) => {
+      visitor.visit_expression(scrutinee);
+      for arm in arms {
+        visitor.visit_pattern_expression(arm);
+      }
+    }
+    Expression::Let(binding, body) => {
+      visitor.visit_let_binding(binding);
+      visitor.visit_expression(body);
+    }
+    Expression::Sizeof(abstract_type)
+    | Expression::Constraint(abstract_type) => visitor.visit_abstract_type(abstract_type),
+    Expression::InternalPlet(pattern, bound, body) => {
+      visitor.visit_pattern(pattern);
+      visitor.visit_expression(bound);
+      visitor.visit_expression(body);
+    }
+  }
+}
+
+pub fn walk_pattern_expression<V: Visitor>(visitor: &mut V, arm: &LocatedPatternExpression) {
+  match &arm.value {
+    PatternExpression::Pattern(pattern, body) => {
+      visitor.visit_pattern(pattern);
+      visitor.visit_expression(body);
+    }
+    PatternExpression::PatternWhen(pattern, guard, body) => {
+      visitor.visit_pattern(pattern);
+      visitor.visit_expression(guard);
+      visitor.visit_expression(body);
+    }
+  }
+}
+
+pub fn walk_let_binding<V: Visitor>(visitor: &mut V, binding: &LocatedLetBinding) {
+  let LetBinding::ValueBinding(pattern, bound) = &binding.value;
+  visitor.visit_pattern(pattern);
+  visitor.visit_expression(bound);
+}
+
+pub fn walk_pattern<V: Visitor>(visitor: &mut V, pattern: &LocatedPattern) {
+  match &pattern.value {
+    Pattern::Literal(_)
+    | Pattern::Wildcard
+    | Pattern::Identifier(_)
+    | Pattern::VectorSubrange(..) => {}
+    Pattern::Typed(abstract_type, inner)
+    | Pattern::Variable(inner, abstract_type) => {
+      visitor.visit_abstract_type(abstract_type);
+      visitor.visit_pattern(inner);
+    }
+    Pattern::Constructor(_, patterns)
+    | Pattern::Vector(patterns)
+    | Pattern::VectorConcat(patterns)
+    | Pattern::Tuple(patterns)
+    | Pattern::List(patterns)
+    | Pattern::StringAppend(patterns) => {
+      for pattern in patterns {
+        visitor.visit_pattern(pattern);
+      }
+    }
+    Pattern::Cons(head, tail) => {
+      visitor.visit_pattern(head);
+      visitor.visit_pattern(tail);
+    }
+    Pattern::Struct(fields) => {
+      for field in fields {
+        if let FieldPattern::Field(_, pattern) = &field.value {
+          visitor.visit_pattern(pattern);
+        }
+      }
+    }
+    Pattern::Attribute(_, _, inner) => visitor.visit_pattern(inner),
+  }
+}
+
+pub fn walk_lvalue<V: Visitor>(visitor: &mut V, lvalue: &LocatedLValueExpression) {
+  match &lvalue.value {
+    LValueExpression::Identifier(_) => {}
+    LValueExpression::Memory(_, arguments) => {
+      for argument in arguments {
+        visitor.visit_expression(argument);
+      }
+    }
+    LValueExpression::Vector(inner, index) => {
+      visitor.visit_lvalue(inner);
+      visitor.visit_expression(index);
+    }
+    LValueExpression::VectorRange(inner, high, low) => {
+      visitor.visit_lvalue(inner);
+      visitor.visit_expression(high);
+      visitor.visit_expression(low);
+    }
+    LValueExpression::VectorConcat(lvalues) => {
+      for lvalue in lvalues {
+        visitor.visit_lvalue(lvalue);
+      }
+    }
+    LValueExpression::Field(inner, _) => visitor.visit_lvalue(inner),
+  }
+}
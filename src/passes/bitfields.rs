@@ -0,0 +1,395 @@
+/*!
+
+The accessor functions Sail provides for every bitfield.
+
+For `bitfield T : bits(n) = { F : h .. l, ... }` this pass adds, as ordinary definitions placed just after it,
+
+ * `Mk_T : bits(n) -> T`, which makes a `T` from its bits,
+ * `_get_T_F : T -> bits(w)`, which reads the field `F`, `w` bits wide,
+ * `_update_T_F : (T, bits(w)) -> T`, which replaces it, and
+ * `_set_T_F : (register(T), bits(w)) -> unit`, which replaces it in a register.
+
+Each has a `val` specification. Names already defined in the program are left to their definitions. The bodies use the
+field syntax the type checker understands directly, `v.F`, `{ v with F = x }`, and the field `bits` for the whole of
+the bitfield. `_set_` writes through the register reference with `__write_register_reference`, which is declared here
+as an extern bound to the `write_register_reference` primitive unless the program declares it.
+
+Whether the fields' ranges lie within the bitfield and overlap is checked by the type checker.
+
+This pass is syntactic and runs before names are resolved, after scattered definitions have been collected.
+
+*/
+
+use std::cell::Cell;
+use std::collections::HashSet;
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::ast_util::{declared_names, strip_definition, Namespace};
+use crate::parser::location::{Located, SourceLocation};
+
+/// The extern `_set_` accessors write registers through.
+pub const WRITE_REGISTER_REFERENCE: &str = "__write_register_reference";
+
+/// The names of the accessors of the field `field` of the bitfield `name`: get, set and update.
+pub fn accessor_names(name: &str, field: &str) -> [String; 3] {
+  [format!("_get_{}_{}", name, field), format!("_set_{}_{}", name, field), format!("_update_{}_{}", name, field)]
+}
+
+/// The name of the function making a bitfield `name` from its bits.
+pub fn constructor_name(name: &str) -> String {
+  format!("Mk_{}", name)
+}
+
+/// Adds the accessors of every bitfield. Definitions should already have had scattered definitions collected.
+pub fn derive_bitfield_accessors(definitions: Definitions) -> Definitions {
+  let mut deriver = Deriver { declared: HashSet::new(), increasing: false };
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      deriver.scan(definition);
+    }
+  }
+
+  let files = definitions
+      .0
+      .into_iter()
+      .map(|(path, file)| {
+        let mut output = Vec::with_capacity(file.len());
+        for definition in file {
+          deriver.definition(definition, &mut output);
+        }
+        (path, output)
+      })
+      .collect();
+  Definitions(files)
+}
+
+struct Deriver {
+  /// Every value name declared in the program, and every accessor derived so far
+  declared  : HashSet<String>,
+  /// The program's default order, which says which index of a range is the more significant
+  increasing: bool,
+}
+
+/// Locations for the nodes generated from one bitfield. Each is distinct, as the types the checker records are keyed by
+/// location.
+struct Locations {
+  bitfield: SourceLocation,
+  next    : Cell<i32>,
+}
+
+impl Locations {
+  fn fresh(&self) -> SourceLocation {
+    self.next.set(self.next.get() + 1);
+    SourceLocation::Unique(self.next.get(), Box::new(self.bitfield.clone()))
+  }
+}
+
+fn located<T>(value: T, location: &Locations) -> Located<T> {
+  Located { location: location.fresh(), value }
+}
+
+fn identifier(name: &str, location: &Locations) -> LocatedIdentifier {
+  located(IdentifierType::Regular(name.to_string()), location)
+}
+
+fn variable(name: &str, location: &Locations) -> LocatedExpression {
+  located(Expression::Identifier(identifier(name, location)), location)
+}
+
+fn named_type(name: &str, location: &Locations) -> LocatedAbstractType {
+  located(AbstractType::Identifier(identifier(name, location)), location)
+}
+
+fn applied_type(constructor: &str, argument: LocatedAbstractType, location: &Locations) -> LocatedAbstractType {
+  located(AbstractType::TypeConstructorApplication(identifier(constructor, location), vec![argument]), location)
+}
+
+fn number(value: i64, location: &Locations) -> LocatedAbstractType {
+  located(AbstractType::Literal(located(Literal::Number(BigInteger::from_i64(value)), location)), location)
+}
+
+/// `{ record with field = value }`.
+fn update(record: LocatedExpression, field: &str, value: LocatedExpression, location: &Locations) -> LocatedExpression {
+  let assignment = located(Expression::Assign(Box::new(variable(field, location)), Box::new(value)), location);
+  located(Expression::StructUpdate(Box::new(record), vec![assignment]), location)
+}
+
+impl Deriver {
+  fn scan(&mut self, definition: &LocatedDefinition) {
+    for (namespace, name) in declared_names(definition) {
+      if namespace == Namespace::Value {
+        self.declared.insert(name.name().to_string());
+      }
+    }
+    let (definition, _) = strip_definition(definition);
+    match &definition.value {
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+        self.declared.insert(name.name().to_string());
+      }
+      Definition::DefaultTypingSpec(specification) => {
+        let DefaultTypingSpec::Order(_, order) = &specification.value;
+        self.increasing = matches!(order.value, AbstractType::Increasing);
+      }
+      _ => {}
+    }
+  }
+
+  fn definition(&mut self, definition: LocatedDefinition, output: &mut Vec<LocatedDefinition>) {
+    let (stripped, private) = strip_definition(&definition);
+    let mut generated = Vec::new();
+    if let Definition::TypeDefinition(type_definition) = &stripped.value {
+      if let TypeDefinition::Bitfield(name, abstract_type, fields) = &type_definition.value {
+        let location = Locations { bitfield: SourceLocation::Generated(Box::new(definition.location.clone())), next: Cell::new(0) };
+        generated = self.accessors(name.name(), abstract_type, fields, &location);
+      }
+    }
+    let wrap = |generated: LocatedDefinition| match private {
+      true => Located { location: generated.location.clone(), value: Definition::Private(Box::new(generated)) },
+      false => generated,
+    };
+    output.push(definition);
+    output.extend(generated.into_iter().map(wrap));
+  }
+
+  /// Whether the function `name` should be generated, rather than left to a definition in the program. Claims the name
+  /// if so.
+  fn derive(&mut self, name: &str) -> bool {
+    self.declared.insert(name.to_string())
+  }
+
+  fn accessors(
+    &mut self,
+    name         : &str,
+    abstract_type: &LocatedAbstractType,
+    fields       : &[(LocatedIdentifier, LocatedIndexRange)],
+    location     : &Locations,
+  ) -> Vec<LocatedDefinition> {
+    let bitfield = named_type(name, location);
+    let mut definitions = Vec::new();
+
+    let constructor = constructor_name(name);
+    if self.derive(&constructor) {
+      // `{ (undefined : T) with bits = v }`
+      let undefined = located(Expression::Literal(located(Literal::Undefined, location)), location);
+      let undefined = located(Expression::Typed(Box::new(bitfield.clone()), Box::new(undefined)), location);
+      let body = update(undefined, "bits", variable("v", location), location);
+      definitions.push(self.specification(&constructor, vec![abstract_type.clone()], bitfield.clone(), location));
+      definitions.push(self.function(&constructor, &["v"], body, location));
+    }
+
+    for (field, range) in fields {
+      let field_type = applied_type("bits", self.width(range, location), location);
+      let [get, set, update_name] = accessor_names(name, field.name());
+
+      if self.derive(&get) {
+        let body = located(Expression::Field(Box::new(variable("v", location)), field.clone()), location);
+        definitions.push(self.specification(&get, vec![bitfield.clone()], field_type.clone(), location));
+        definitions.push(self.function(&get, &["v"], body, location));
+      }
+
+      if self.derive(&update_name) {
+        let body = update(variable("v", location), field.name(), variable("x", location), location);
+        let arguments = vec![bitfield.clone(), field_type.clone()];
+        definitions.push(self.specification(&update_name, arguments, bitfield.clone(), location));
+        definitions.push(self.function(&update_name, &["v", "x"], body, location));
+      }
+
+      if self.derive(&set) {
+        if self.derive(WRITE_REGISTER_REFERENCE) {
+          definitions.push(self.write_register_reference(location));
+        }
+        // `__write_register_reference(r, { *r with F = x })`
+        let current = located(Expression::Dereference(Box::new(variable("r", location))), location);
+        let updated = update(current, field.name(), variable("x", location), location);
+        let body = located(
+          Expression::Application(identifier(WRITE_REGISTER_REFERENCE, location), vec![variable("r", location), updated]),
+          location,
+        );
+        let arguments = vec![applied_type("register", bitfield.clone(), location), field_type.clone()];
+        definitions.push(self.specification(&set, arguments, named_type("unit", location), location));
+        definitions.push(self.function(&set, &["r", "x"], body, location));
+      }
+    }
+    definitions
+  }
+
+  /// The width of a field, the sum of those of its ranges, as a type.
+  fn width(&self, range: &LocatedIndexRange, location: &Locations) -> LocatedAbstractType {
+    let (high, low) = match &range.value {
+      IndexRange::Single(_) => return number(1, location),
+      IndexRange::Range(first, second) => match self.increasing {
+        true => (second, first),
+        false => (first, second),
+      },
+      IndexRange::Concat(first, second) => {
+        let sum = AbstractType::Sum(Box::new(self.width(first, location)), Box::new(self.width(second, location)));
+        return located(sum, location);
+      }
+    };
+    let literal = |index: &LocatedAbstractType| match &index.value {
+      AbstractType::Literal(literal) => match &literal.value {
+        Literal::Number(value) => Some(value.clone()),
+        _ => None,
+      },
+      _ => None,
+    };
+    let constant = literal(high)
+        .zip(literal(low))
+        .and_then(|(high, low)| high.try_sub(&low).and_then(|difference| difference.try_add(&BigInteger::from_i64(1))).ok());
+    if let Some(width) = constant {
+      return located(AbstractType::Literal(located(Literal::Number(width), location)), location);
+    }
+    let difference = located(AbstractType::Minus(high.clone(), low.clone()), location);
+    located(AbstractType::Sum(Box::new(difference), Box::new(number(1, location))), location)
+  }
+
+  fn specification(
+    &self,
+    name     : &str,
+    arguments: Vec<LocatedAbstractType>,
+    result   : LocatedAbstractType,
+    location : &Locations,
+  ) -> LocatedDefinition {
+    let quantifier = located(TypeQuantifier::NoForAll, location);
+    self.specification_with(name, quantifier, arguments, result, None, location)
+  }
+
+  fn specification_with(
+    &self,
+    name      : &str,
+    quantifier: LocatedTypeQuantifier,
+    arguments : Vec<LocatedAbstractType>,
+    result    : LocatedAbstractType,
+    bindings  : Option<ExternalBindings>,
+    location  : &Locations,
+  ) -> LocatedDefinition {
+    let argument = match arguments.len() {
+      1 => arguments.into_iter().next().unwrap_or_else(|| located(AbstractType::Wildcard, location)),
+      _ => located(AbstractType::Tuple(arguments), location),
+    };
+    let abstract_type = located(
+      AbstractType::Function {
+        lhs   : Box::new(argument),
+        rhs   : Box::new(result),
+        effect: Box::new(located(AbstractType::Wildcard, location)),
+      },
+      location,
+    );
+    let scheme = located(TypeScheme { quantifier, abstract_type }, location);
+    let specification = ValueSpecification::ValueSpec(Box::new(scheme), identifier(name, location), bindings);
+    located(Definition::ValueSpec(located(specification, location)), location)
+  }
+
+  /// `val __write_register_reference = {_: "write_register_reference"} : forall ('a : Type). (register('a), 'a) -> unit`
+  fn write_register_reference(&self, location: &Locations) -> LocatedDefinition {
+    let element = located(AbstractType::Variable(located(KindIdentifier("'a".to_string()), location)), location);
+    let kinded = KindedIdentifier {
+      identifiers: vec![located(KindIdentifier("'a".to_string()), location)],
+      annotation : None,
+      kind       : Some(located(Kind::Type, location)),
+    };
+    let quantifier = TypeQuantifier::TypeQuantifiers(vec![located(QuantifierItem::KindedIdentifier(located(kinded, location)), location)]);
+    let bindings = ExternalBindings { is_pure: false, bindings: vec![("_".to_string(), "write_register_reference".to_string())] };
+    self.specification_with(
+      WRITE_REGISTER_REFERENCE,
+      located(quantifier, location),
+      vec![applied_type("register", element.clone(), location), element],
+      named_type("unit", location),
+      Some(bindings),
+      location,
+    )
+  }
+
+  fn function(&self, name: &str, parameters: &[&str], body: LocatedExpression, location: &Locations) -> LocatedDefinition {
+    let mut patterns: Vec<LocatedPattern> =
+        parameters.iter().map(|parameter| located(Pattern::Identifier(identifier(parameter, location)), location)).collect();
+    let pattern = match patterns.len() {
+      1 => patterns.remove(0),
+      _ => located(Pattern::Tuple(patterns), location),
+    };
+    let arm = located(PatternExpression::Pattern(Box::new(pattern), Box::new(body)), location);
+    let clause = located(FunctionClause::Clause(identifier(name, location), Box::new(arm)), location);
+    let function = FunctionDefinition::Function(
+      located(None, location),
+      located(None, location),
+      located(None, location),
+      vec![clause],
+    );
+    located(Definition::FunctionDefinition(located(function, location)), location)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{derive_bitfield_accessors, WRITE_REGISTER_REFERENCE};
+  use crate::parser::ast::*;
+  use crate::parser::ast_util::function_name;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+
+  /// The names of the definitions in a single file, as `kind name`.
+  fn outline(definitions: &Definitions) -> Vec<String> {
+    (definitions.0[0].1.iter())
+        .map(|definition| match &definition.value {
+          Definition::FunctionDefinition(function) => {
+            format!("function {}", function_name(&function.value).map_or("?", |name| name.name()))
+          }
+          Definition::ValueSpec(specification) => {
+            let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+            format!("val {}", name.name())
+          }
+          Definition::TypeDefinition(_) => "type".to_string(),
+          _ => "other".to_string(),
+        })
+        .collect()
+  }
+
+  /// `bitfield name : bits(width) = { field : high .. low, ... }`
+  fn bitfield(name: &str, width: i64, fields: &[(&str, i64, i64)]) -> LocatedDefinition {
+    let fields = (fields.iter())
+        .map(|(field, high, low)| (id(field), located(IndexRange::Range(Box::new(type_number(*high)), Box::new(type_number(*low))))))
+        .collect();
+    located(Definition::TypeDefinition(located(TypeDefinition::Bitfield(id(name), Box::new(bits(width)), fields))))
+  }
+
+  #[test]
+  fn accessors_are_derived_after_the_bitfield() {
+    let definitions = derive_bitfield_accessors(definitions(vec![bitfield("status", 8, &[("mode", 7, 4)])]));
+    assert_eq!(outline(&definitions), vec![
+      "type".to_string(),
+      "val Mk_status".to_string(),
+      "function Mk_status".to_string(),
+      "val _get_status_mode".to_string(),
+      "function _get_status_mode".to_string(),
+      "val _update_status_mode".to_string(),
+      "function _update_status_mode".to_string(),
+      format!("val {}", WRITE_REGISTER_REFERENCE),
+      "val _set_status_mode".to_string(),
+      "function _set_status_mode".to_string(),
+    ]);
+
+    let (_, errors) = check_types(&definitions);
+    assert!(errors.is_empty(), "{:?}", errors);
+  }
+
+  #[test]
+  fn accessors_already_declared_are_not_derived() {
+    let getter = val("_get_status_mode", function_type(vec![typ("status")], bits(4)));
+    let definitions = derive_bitfield_accessors(definitions(vec![getter, bitfield("status", 8, &[("mode", 7, 4)])]));
+    let outline = outline(&definitions);
+    assert_eq!(outline.iter().filter(|line| line.ends_with("_get_status_mode")).count(), 1);
+    assert!(outline.contains(&"function _update_status_mode".to_string()));
+  }
+
+  #[test]
+  fn fields_outside_the_bitfield_are_reported() {
+    let definitions = derive_bitfield_accessors(definitions(vec![bitfield("status", 8, &[("mode", 9, 4)])]));
+    let (_, errors) = check_types(&definitions);
+    let messages: Vec<String> = errors.iter().map(|error| error.value.to_string()).collect();
+    assert_eq!(messages, vec![
+      "field `mode` of the bitfield `status` does not lie within it, most significant index first".to_string(),
+    ]);
+  }
+}
@@ -0,0 +1,919 @@
+/*!
+
+Effect inference.
+
+Every function is given the set of effects evaluating its body may have: reading or writing registers, reading or
+writing memory, throwing an exception, exiting (including by a failed `assert`), using `undefined`, whose value is
+unspecified, and calling out to an external function that is not declared pure. A function's effects are those of its
+own body together with those of every function it calls, so effects are propagated along the call graph to a fixed
+point, which also handles recursion.
+
+Functions without a body take their effects from their declaration. An external function (a `val` specification with
+external bindings) has the effects in its effect annotation, if it has one; memory effects for the usual memory
+primitives, whatever their annotation says; and otherwise `External`, unless it is declared `pure`.
+
+Effects are checked only where they are declared, as an effect set such as `effect {rreg, escape}` on a `val`
+specification, a function's annotation or a function's effect option; modern Sail leaves them out. A function whose
+inferred effects are not all declared is reported, as is an external function declared `pure` that has effects.
+
+Throws within a `try` are caught if one of its arms matches anything; otherwise they may escape.
+
+*/
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::{expression_to_lvalue, pattern_identifiers, strip_definition, strip_function_clause};
+use crate::parser::location::{Located, SourceLocation};
+
+/// External functions that read memory.
+pub const MEMORY_READS: &[&str] = &[
+  "read_mem", "__read_mem", "read_memt", "__read_memt", "read_mem_ifetch", "__read_mem_ifetch", "read_tag", "__read_tag",
+];
+
+/// External functions that write memory.
+pub const MEMORY_WRITES: &[&str] = &[
+  "write_mem", "__write_mem", "write_memt", "__write_memt", "write_mem_ea", "__write_mem_ea", "write_tag", "__write_tag",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Effect {
+  ReadRegister,
+  WriteRegister,
+  ReadMemory,
+  WriteMemory,
+  Throw,
+  Exit,
+  /// Uses `undefined`, whose value is unspecified
+  Undefined,
+  /// Calls an external function not declared pure
+  External,
+}
+
+impl Effect {
+  /// The effects an effect name in an effect set stands for. `escape` covers both ways of leaving a function early.
+  pub fn from_name(name: &str) -> Option<&'static [Effect]> {
+    Some(match name {
+      "rreg" => &[Effect::ReadRegister],
+      "wreg" => &[Effect::WriteRegister],
+      "rmem" | "rmemt" => &[Effect::ReadMemory],
+      "wmem" | "wmv" | "wmvt" | "eamem" => &[Effect::WriteMemory],
+      "escape" => &[Effect::Throw, Effect::Exit],
+      "throw" => &[Effect::Throw],
+      "exit" => &[Effect::Exit],
+      "undef" | "nondet" => &[Effect::Undefined],
+      "extern" => &[Effect::External],
+      _ => return None,
+    })
+  }
+}
+
+impl Display for Effect {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      Effect::ReadRegister  => "rreg",
+      Effect::WriteRegister => "wreg",
+      Effect::ReadMemory    => "rmem",
+      Effect::WriteMemory   => "wmem",
+      Effect::Throw         => "throw",
+      Effect::Exit          => "exit",
+      Effect::Undefined     => "undef",
+      Effect::External      => "extern",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+pub type Effects = BTreeSet<Effect>;
+
+fn effect_list(effects: &[Effect]) -> String {
+  effects.iter().map(|effect| effect.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum EffectError {
+  UnknownEffect(String),
+  /// A function has effects its declaration does not list.
+  Undeclared { name: String, effects: Vec<Effect> },
+  /// An external function declared `pure` is declared or known to have effects.
+  ImpurePure { name: String, effects: Vec<Effect> },
+}
+
+pub type LocatedEffectError = Located<EffectError>;
+
+impl EffectError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      EffectError::UnknownEffect(name) => {
+        write!(f, "unknown effect `{}`", name)
+      }
+
+      EffectError::Undeclared { name, effects } => {
+        write!(f, "`{}` has undeclared effects {{{}}}", name, effect_list(effects))
+      }
+
+      EffectError::ImpurePure { name, effects } => {
+        write!(f, "`{}` is declared pure but has effects {{{}}}", name, effect_list(effects))
+      }
+    }
+  }
+}
+
+impl Debug for EffectError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for EffectError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for EffectError {}
+
+/// The effects of every function, mapping and external function in a program.
+#[derive(Debug, Clone, Default)]
+pub struct EffectSummary {
+  effects  : HashMap<String, Effects>,
+  /// What effect inference over more definitions needs to know about those before
+  registers: HashSet<String>,
+  overloads: HashMap<String, Vec<String>>,
+  declared : HashMap<String, Declared>,
+}
+
+impl EffectSummary {
+  /// The effects of the function `name`, or `None` if it is not a function.
+  pub fn effects(&self, name: &str) -> Option<&Effects> {
+    self.effects.get(name)
+  }
+
+  /// Whether `name` is a function with no effects at all.
+  pub fn is_pure(&self, name: &str) -> bool {
+    self.effects.get(name).is_some_and(|effects| effects.is_empty())
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &Effects)> {
+    self.effects.iter().map(|(name, effects)| (name.as_str(), effects))
+  }
+}
+
+/// What one function's body does directly.
+#[derive(Debug, Default)]
+struct Body {
+  effects: Effects,
+  calls  : BTreeSet<String>,
+}
+
+/// A declared effect set, where effects are to be checked.
+#[derive(Debug, Clone)]
+struct Declared {
+  effects : Effects,
+  location: SourceLocation,
+}
+
+/// Infers the effects of every function in `definitions`, which should already have had scattered definitions
+/// collected.
+pub fn infer_effects(definitions: &Definitions) -> (EffectSummary, Vec<LocatedEffectError>) {
+  let mut summary = EffectSummary::default();
+  let errors = infer_more_effects(&mut summary, definitions);
+  (summary, errors)
+}
+
+/// Infers the effects of the functions in more definitions, which may call those `summary` has the effects of, adding
+/// them to `summary`.
+pub fn infer_more_effects(summary: &mut EffectSummary, definitions: &Definitions) -> Vec<LocatedEffectError> {
+  // Functions already inferred have fixed effects, as those without bodies do, and their declared effects have been
+  // checked unless they have only now been given a body.
+  let earlier: HashSet<String> = summary.declared.keys().cloned().collect();
+  let mut collector = Collector {
+    registers : std::mem::take(&mut summary.registers),
+    overloads : std::mem::take(&mut summary.overloads),
+    primitives: std::mem::take(&mut summary.effects),
+    declared  : std::mem::take(&mut summary.declared),
+    ..Collector::default()
+  };
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      collector.declare(definition);
+    }
+  }
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      collector.definition(definition);
+    }
+  }
+
+  // Effects flow from callees to callers until nothing changes.
+  let mut effects: HashMap<String, Effects> = collector.primitives.clone();
+  for (name, body) in &collector.bodies {
+    effects.insert(name.clone(), body.effects.clone());
+  }
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for (name, body) in &collector.bodies {
+      let mut inherited = Effects::new();
+      for callee in &body.calls {
+        for target in collector.targets(callee) {
+          if let Some(callee_effects) = effects.get(&target) {
+            inherited.extend(callee_effects.iter().copied());
+          }
+        }
+      }
+      let own = effects.get_mut(name).expect("every body has an entry");
+      let before = own.len();
+      own.extend(inherited);
+      changed |= own.len() != before;
+    }
+  }
+
+  let mut errors = collector.errors;
+  let mut declared: Vec<_> = collector.declared.iter().collect();
+  declared.sort_by_key(|(name, _)| *name);
+  for (name, declared) in declared {
+    if earlier.contains(name) && !collector.bodies.contains_key(name) {
+      continue;
+    }
+    let Some(inferred) = effects.get(name) else {
+      continue;
+    };
+    let undeclared: Vec<Effect> = inferred.difference(&declared.effects).copied().collect();
+    if !undeclared.is_empty() {
+      errors.push(Located {
+        location: declared.location.clone(),
+        value   : EffectError::Undeclared { name: name.clone(), effects: undeclared },
+      });
+    }
+  }
+
+  *summary = EffectSummary { effects, registers: collector.registers, overloads: collector.overloads, declared: collector.declared };
+  errors
+}
+
+#[derive(Default)]
+struct Collector {
+  registers : HashSet<String>,
+  overloads : HashMap<String, Vec<String>>,
+  /// The effects of functions without bodies, or whose bodies were inferred before
+  primitives: HashMap<String, Effects>,
+  bodies    : HashMap<String, Body>,
+  declared  : HashMap<String, Declared>,
+  errors    : Vec<LocatedEffectError>,
+  /// The function whose body is being walked
+  current   : Body,
+  scopes    : Vec<HashSet<String>>,
+}
+
+impl Collector {
+  /// The functions a call of `name` may reach: all of an overload's targets, or just `name`.
+  fn targets(&self, name: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut pending = vec![name.to_string()];
+    let mut seen = HashSet::new();
+    while let Some(name) = pending.pop() {
+      if !seen.insert(name.clone()) {
+        continue;
+      }
+      match self.overloads.get(&name) {
+        Some(overloaded) => pending.extend(overloaded.iter().cloned()),
+        None => targets.push(name),
+      }
+    }
+    targets
+  }
+
+  /// The effects in an effect annotation, or `None` if it is not an effect set.
+  fn effect_set(&mut self, effect: &LocatedAbstractType) -> Option<Effects> {
+    match &effect.value {
+      AbstractType::EffectSet(names) => {
+        let mut effects = Effects::new();
+        for name in names {
+          match Effect::from_name(name.name()) {
+            Some(named) => effects.extend(named.iter().copied()),
+            None => self.errors.push(Located {
+              location: name.location.clone(),
+              value   : EffectError::UnknownEffect(name.name().to_string()),
+            }),
+          }
+        }
+        Some(effects)
+      }
+      AbstractType::Parenthesized(inner) => self.effect_set(inner),
+      _ => None,
+    }
+  }
+
+  /// The effect annotation of a function or mapping type.
+  fn type_effects(&mut self, abstract_type: &LocatedAbstractType) -> Option<Effects> {
+    match &abstract_type.value {
+      AbstractType::Function { effect, .. }
+      | AbstractType::Bidirectional { effect, .. } => self.effect_set(effect),
+      AbstractType::Parenthesized(inner) => self.type_effects(inner),
+      _ => None,
+    }
+  }
+
+  fn declare_effects(&mut self, name: &str, effects: Option<Effects>, location: &SourceLocation) {
+    if let Some(effects) = effects {
+      self.declared.insert(name.to_string(), Declared { effects, location: location.clone() });
+    }
+  }
+
+  /// Records registers, overloads and declared effects, which may be used before they appear.
+  fn declare(&mut self, definition: &LocatedDefinition) {
+    let (definition, _) = strip_definition(definition);
+    match &definition.value {
+      Definition::Register(declaration) => {
+        let DeclarationSpecification::Register(_, name, _) = &declaration.value;
+        self.registers.insert(name.name().to_string());
+      }
+
+      Definition::Overload(name, targets) => {
+        self.overloads
+            .entry(name.name().to_string())
+            .or_default()
+            .extend(targets.iter().map(|target| target.name().to_string()));
+      }
+
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(scheme, name, external) = &specification.value;
+        self.value_specification(name, &scheme.abstract_type, external.as_ref(), &specification.location);
+      }
+
+      Definition::OutcomeSpec(outcome, definitions) => {
+        let OutcomeSpec::Outcome(name, scheme, _) = &outcome.value;
+        self.value_specification(name, &scheme.abstract_type, None, &outcome.location);
+        for definition in definitions {
+          self.declare(definition);
+        }
+      }
+
+      _ => {}
+    }
+  }
+
+  fn value_specification(
+    &mut self,
+    name: &LocatedIdentifier,
+    abstract_type: &LocatedAbstractType,
+    external: Option<&ExternalBindings>,
+    location: &SourceLocation,
+  ) {
+    let declared = self.type_effects(abstract_type);
+    let name = name.name();
+
+    // Without a body, a function has the effects it declares.
+    let mut effects = declared.clone().unwrap_or_default();
+    if let Some(external) = external {
+      let names = || std::iter::once(name).chain(external.bindings.iter().map(|(_, binding)| binding.as_str()));
+      if names().any(|name| MEMORY_READS.contains(&name)) {
+        effects.insert(Effect::ReadMemory);
+      }
+      if names().any(|name| MEMORY_WRITES.contains(&name)) {
+        effects.insert(Effect::WriteMemory);
+      }
+      match external.is_pure {
+        true if !effects.is_empty() => self.errors.push(Located {
+          location: location.clone(),
+          value   : EffectError::ImpurePure { name: name.to_string(), effects: effects.iter().copied().collect() },
+        }),
+        true => {}
+        false if declared.is_none() && effects.is_empty() => {
+          effects.insert(Effect::External);
+        }
+        false => {}
+      }
+    }
+    self.primitives.insert(name.to_string(), effects);
+    self.declare_effects(name, declared, location);
+  }
+
+  fn definition(&mut self, definition: &LocatedDefinition) {
+    let (definition, _) = strip_definition(definition);
+    match &definition.value {
+      Definition::FunctionDefinition(function) => self.function(function),
+      Definition::InternalMutRec(functions) => {
+        for function in functions {
+          self.function(function);
+        }
+      }
+      Definition::MappingDefinition(mapping) => self.mapping(mapping),
+      Definition::OutcomeSpec(_, definitions) => {
+        for definition in definitions {
+          self.definition(definition);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Ends the walk of a body, recording what it did under `name`. Clauses of one function accumulate.
+  fn finish_body(&mut self, name: &str) {
+    let body = std::mem::take(&mut self.current);
+    self.primitives.remove(name);
+    let entry = self.bodies.entry(name.to_string()).or_default();
+    entry.effects.extend(body.effects);
+    entry.calls.extend(body.calls);
+  }
+
+  fn function(&mut self, function: &LocatedFunctionDefinition) {
+    let FunctionDefinition::Function(_, annotation, effect, clauses) = &function.value;
+    let Some((name, _)) = clauses.first().map(strip_function_clause) else {
+      return;
+    };
+
+    let declared = match &effect.value {
+      Some(effect) => self.effect_set(effect),
+      None => None,
+    };
+    let declared = declared.or_else(|| {
+      let (_, abstract_type) = annotation.value.as_ref()?;
+      self.type_effects(abstract_type)
+    });
+    self.declare_effects(name.name(), declared, &name.location);
+
+    for clause in clauses {
+      let (_, arm) = strip_function_clause(clause);
+      self.scopes.clear();
+      self.pattern_expression(arm);
+    }
+    self.finish_body(name.name());
+  }
+
+  fn mapping(&mut self, mapping: &LocatedMappingDefinition) {
+    let MappingDefinition::Mapping(name, scheme, clauses) = &mapping.value;
+    if let Some(scheme) = &scheme.value {
+      let declared = self.type_effects(&scheme.abstract_type);
+      self.declare_effects(name.name(), declared, &name.location);
+    }
+    for clause in clauses {
+      self.scopes.clear();
+      self.mapping_clause(clause);
+    }
+    self.finish_body(name.name());
+  }
+
+  fn mapping_clause(&mut self, clause: &LocatedMappingClause) {
+    match &clause.value {
+      MappingClause::Attribute(_, _, inner)
+      | MappingClause::Documentation(_, inner) => self.mapping_clause(inner),
+      MappingClause::Bidirectional(left, right) => {
+        self.mapping_side(left);
+        self.mapping_side(right);
+      }
+      MappingClause::ForwardsDeprecated(left, body) => {
+        self.mapping_side(left);
+        self.expression(body);
+      }
+      MappingClause::Forwards(arm)
+      | MappingClause::Backwards(arm) => self.pattern_expression(arm),
+    }
+  }
+
+  fn mapping_side(&mut self, side: &LocatedMappingPatternExpression) {
+    let pattern = match &side.value {
+      MappingPatternExpression::Pattern(pattern) => pattern,
+      MappingPatternExpression::PatternWhen(pattern, guard) => {
+        self.mapping_pattern(pattern);
+        self.expression(guard);
+        return;
+      }
+    };
+    self.mapping_pattern(pattern);
+  }
+
+  /// Binds the variables of a mapping pattern, and records calls of the mappings it uses.
+  fn mapping_pattern(&mut self, pattern: &LocatedMappingPattern) {
+    match &pattern.value {
+      MappingPattern::Literal(_) => {}
+      MappingPattern::Identifier(identifier)
+      | MappingPattern::VectorSubrange(identifier, ..) => self.bind(identifier),
+      MappingPattern::Application(name, arguments) => {
+        self.current.calls.insert(name.name().to_string());
+        for argument in arguments {
+          self.mapping_pattern(argument);
+        }
+      }
+      MappingPattern::Vector(patterns)
+      | MappingPattern::VectorConcat(patterns)
+      | MappingPattern::Tuple(patterns)
+      | MappingPattern::List(patterns)
+      | MappingPattern::StringAppend(patterns) => {
+        for pattern in patterns {
+          self.mapping_pattern(pattern);
+        }
+      }
+      MappingPattern::Cons(head, tail) => {
+        self.mapping_pattern(head);
+        self.mapping_pattern(tail);
+      }
+      MappingPattern::Typed(inner, _) => self.mapping_pattern(inner),
+      MappingPattern::As(inner, identifier) => {
+        self.mapping_pattern(inner);
+        self.bind(identifier);
+      }
+      MappingPattern::Struct(fields) => {
+        for (_, pattern) in fields {
+          self.mapping_pattern(pattern);
+        }
+      }
+    }
+  }
+
+  // region Scopes
+
+  fn with_scope<F: FnOnce(&mut Self)>(&mut self, f: F) {
+    self.scopes.push(HashSet::new());
+    f(self);
+    self.scopes.pop();
+  }
+
+  fn bind(&mut self, identifier: &LocatedIdentifier) {
+    if self.scopes.is_empty() {
+      self.scopes.push(HashSet::new());
+    }
+    self.scopes.last_mut().unwrap().insert(identifier.name().to_string());
+  }
+
+  fn bind_pattern(&mut self, pattern: &LocatedPattern) {
+    for identifier in pattern_identifiers(pattern) {
+      self.bind(identifier);
+    }
+  }
+
+  fn is_local(&self, name: &str) -> bool {
+    self.scopes.iter().any(|scope| scope.contains(name))
+  }
+
+  /// Whether `name` refers to a register here.
+  fn is_register(&self, name: &str) -> bool {
+    self.registers.contains(name) && !self.is_local(name)
+  }
+
+  // endregion
+
+  fn pattern_expression(&mut self, arm: &LocatedPatternExpression) {
+    self.with_scope(|collector| match &arm.value {
+      PatternExpression::Pattern(pattern, body) => {
+        collector.bind_pattern(pattern);
+        collector.expression(body);
+      }
+      PatternExpression::PatternWhen(pattern, guard, body) => {
+        collector.bind_pattern(pattern);
+        collector.expression(guard);
+        collector.expression(body);
+      }
+    })
+  }
+
+  fn declared_variable(expression: &LocatedExpression) -> Option<&LocatedIdentifier> {
+    match &expression.value {
+      Expression::Identifier(identifier) => Some(identifier),
+      Expression::Typed(_, inner) => Self::declared_variable(inner),
+      _ => None,
+    }
+  }
+
+  /// The effects of assigning to `target`, other than those of evaluating its indices.
+  fn assignment(&mut self, target: &LocatedExpression) {
+    match &target.value {
+      Expression::Tuple(targets) => {
+        for target in targets {
+          self.assignment(target);
+        }
+      }
+      Expression::Typed(_, inner) => self.assignment(inner),
+      Expression::Dereference(inner) => {
+        self.current.effects.insert(Effect::WriteRegister);
+        self.expression(inner);
+      }
+      _ => match expression_to_lvalue(target) {
+        Some(lvalue) => self.lvalue(&lvalue),
+        None => self.expression(target),
+      },
+    }
+  }
+
+  fn lvalue(&mut self, lvalue: &LocatedLValueExpression) {
+    match &lvalue.value {
+      LValueExpression::Identifier(identifier) => {
+        if self.is_register(identifier.name()) {
+          self.current.effects.insert(Effect::WriteRegister);
+        }
+      }
+      LValueExpression::Memory(setter, arguments) => {
+        self.current.calls.insert(setter.name().to_string());
+        for argument in arguments {
+          self.expression(argument);
+        }
+      }
+      LValueExpression::Vector(inner, index) => {
+        self.lvalue(inner);
+        self.expression(index);
+      }
+      LValueExpression::VectorRange(inner, high, low) => {
+        self.lvalue(inner);
+        self.expression(high);
+        self.expression(low);
+      }
+      LValueExpression::VectorConcat(parts) => {
+        for part in parts {
+          self.lvalue(part);
+        }
+      }
+      LValueExpression::Field(inner, _) => self.lvalue(inner),
+    }
+  }
+
+  /// Whether an arm of a `try` catches every exception.
+  fn catches_all(arm: &LocatedPatternExpression) -> bool {
+    let PatternExpression::Pattern(pattern, _) = &arm.value else {
+      return false;
+    };
+    let mut pattern = &**pattern;
+    loop {
+      match &pattern.value {
+        Pattern::Wildcard
+        | Pattern::Identifier(_) => return true,
+        Pattern::Typed(_, inner)
+        | Pattern::Variable(inner, _)
+        | Pattern::Attribute(_, _, inner) => pattern = inner,
+        _ => return false,
+      }
+    }
+  }
+
+  fn expression(&mut self, expression: &LocatedExpression) {
+    match &expression.value {
+      Expression::Block(expressions) => self.with_scope(|collector| {
+        for expression in expressions {
+          // Assigning to an unbound name declares it for the rest of the block.
+          if let Expression::Assign(target, value) = &expression.value {
+            if let Some(identifier) = Self::declared_variable(target) {
+              if !collector.is_local(identifier.name()) && !collector.registers.contains(identifier.name()) {
+                collector.expression(value);
+                collector.bind(identifier);
+                continue;
+              }
+            }
+          }
+          collector.expression(expression);
+        }
+      }),
+      Expression::Identifier(identifier) => {
+        if self.is_register(identifier.name()) {
+          self.current.effects.insert(Effect::ReadRegister);
+        }
+      }
+      Expression::Reference(_) => {}
+      Expression::Dereference(inner) => {
+        self.current.effects.insert(Effect::ReadRegister);
+        self.expression(inner);
+      }
+      Expression::Literal(literal) => {
+        if literal.value == Literal::Undefined {
+          self.current.effects.insert(Effect::Undefined);
+        }
+      }
+      Expression::Application(function, arguments) => {
+        self.current.calls.insert(function.name().to_string());
+        for argument in arguments {
+          self.expression(argument);
+        }
+      }
+      Expression::InfixApplication(left, operator, right) => {
+        self.current.calls.insert(operator.name().to_string());
+        self.expression(left);
+        self.expression(right);
+      }
+      Expression::Infix(tokens) => {
+        for (token, _) in tokens {
+          match token {
+            InfixToken::Primary(inner) => self.expression(inner),
+            InfixToken::Operator(operator)
+            | InfixToken::Prefix(operator) => {
+              self.current.calls.insert(operator.name().to_string());
+            }
+          }
+        }
+      }
+      Expression::Tuple(expressions)
+      | Expression::Vector(expressions)
+      | Expression::List(expressions)
+      | Expression::Struct(expressions) => {
+        for expression in expressions {
+          self.expression(expression);
+        }
+      }
+      Expression::StructUpdate(inner, fields) => {
+        self.expression(inner);
+        for field in fields {
+          self.expression(field);
+        }
+      }
+      Expression::Assign(target, value) => {
+        self.assignment(target);
+        self.expression(value);
+      }
+      Expression::Throw(inner) => {
+        self.current.effects.insert(Effect::Throw);
+        self.expression(inner);
+      }
+      Expression::Exit(inner) => {
+        self.current.effects.insert(Effect::Exit);
+        self.expression(inner);
+      }
+      Expression::Assert(condition, message) => {
+        self.current.effects.insert(Effect::Exit);
+        self.expression(condition);
+        self.expression(message);
+      }
+      Expression::Try(body, arms) => {
+        let outer = std::mem::take(&mut self.current);
+        self.expression(body);
+        let mut inner = std::mem::replace(&mut self.current, outer);
+        if arms.iter().any(Self::catches_all) {
+          inner.effects.remove(&Effect::Throw);
+        }
+        self.current.effects.extend(inner.effects);
+        // Calls in the body are kept, but a throw they make is counted; catching it would need to know the callee's
+        // exceptions, which effects do not track.
+        self.current.calls.extend(inner.calls);
+        for arm in arms {
+          self.pattern_expression(arm);
+        }
+      }
+      Expression::Field(inner, _)
+      | Expression::Return(inner)
+      | Expression::Attribute(_, _, inner)
+      | Expression::InternalReturn(inner)
+      | Expression::Typed(_, inner)
+      | Expression::InternalAssume(_, inner) => self.expression(inner),
+      Expression::VectorAccess(left, right)
+      | Expression::VectorAppend(left, right)
+      | Expression::Cons(left, right) => {
+        self.expression(left);
+        self.expression(right);
+      }
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        self.expression(condition);
+        self.expression(then_expr);
+        self.expression(else_expr);
+      }
+      Expression::Loop(_, measure, condition, body) => {
+        if let Some(measure) = &measure.value {
+          self.expression(measure);
+        }
+        self.expression(condition);
+        self.expression(body);
+      }
+      Expression::For { identifier, start, end, step, body, .. } => {
+        self.expression(start);
+        self.expression(end);
+        self.expression(step);
+        self.with_scope(|collector| {
+          collector.bind(identifier);
+          collector.expression(body);
+        });
+      }
+      Expression::VectorSubrange(first, second, third)
+      | Expression::VectorUpdate(first, second, third) => {
+        self.expression(first);
+        self.expression(second);
+        self.expression(third);
+      }
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        self.expression(vector);
+        self.expression(high);
+        self.expression(low);
+        self.expression(value);
+      }
+      Expression::Match(scrutinee, arms) => {
+        self.expression(scrutinee);
+        for arm in arms {
+          self.pattern_expression(arm);
+        }
+      }
+      Expression::Let(binding, body) => {
+        let LetBinding::ValueBinding(pattern, bound) = &binding.value;
+        self.expression(bound);
+        self.with_scope(|collector| {
+          collector.bind_pattern(pattern);
+          collector.expression(body);
+        });
+      }
+      Expression::Variable(target, initializer, body) => {
+        self.expression(initializer);
+        self.with_scope(|collector| {
+          match Self::declared_variable(target) {
+            Some(identifier) => collector.bind(identifier),
+            None => collector.assignment(target),
+          }
+          collector.expression(body);
+        });
+      }
+      Expression::Sizeof(_)
+      | Expression::Constraint(_) => {}
+      Expression::InternalPlet(pattern, bound, body) => {
+        self.expression(bound);
+        self.with_scope(|collector| {
+          collector.bind_pattern(pattern);
+          collector.expression(body);
+        });
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+
+  fn messages(errors: &[LocatedEffectError]) -> Vec<String> {
+    errors.iter().map(|error| error.value.to_string()).collect()
+  }
+
+  fn effects(summary: &EffectSummary, name: &str) -> Vec<Effect> {
+    summary.effects(name).expect("a function").iter().copied().collect()
+  }
+
+  /// `val name = "name" : unit -> unit`, not declared pure.
+  fn impure_extern(name: &str) -> LocatedDefinition {
+    let bindings = ExternalBindings { is_pure: false, bindings: vec![("_".to_string(), name.to_string())] };
+    // An effect that is not an effect set declares nothing.
+    let effect = Box::new(located(AbstractType::Identifier(id("unit"))));
+    let typ = located(AbstractType::Function { lhs: Box::new(typ("unit")), rhs: Box::new(typ("unit")), effect });
+    let specification = ValueSpecification::ValueSpec(Box::new(scheme(&[], None, typ)), id(name), Some(bindings));
+    located(Definition::ValueSpec(located(specification)))
+  }
+
+  #[test]
+  fn effects_flow_from_callees_to_callers() {
+    let program = vec![
+      register("R", typ("int"), None),
+      function("read", vec![], var("R")),
+      function("caller", vec![], call("read", vec![])),
+      overload("either", &["read", "pure"]),
+      function("pure", vec![], number(1)),
+      function("via", vec![], call("either", vec![])),
+      function("loop", vec![pattern("x")], call("loop", vec![var("x")])),
+      function("fail", vec![], block(vec![assertion(boolean(false), "no"), throw(unit())])),
+    ];
+
+    let (summary, errors) = infer_effects(&definitions(program));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    assert_eq!(effects(&summary, "caller"), vec![Effect::ReadRegister]);
+    assert_eq!(effects(&summary, "via"), vec![Effect::ReadRegister]);
+    assert!(summary.is_pure("pure"));
+    assert!(summary.is_pure("loop"));
+    assert_eq!(effects(&summary, "fail"), vec![Effect::Throw, Effect::Exit]);
+  }
+
+  #[test]
+  fn external_functions_have_their_declared_effects() {
+    let program = vec![impure_extern("outside"), impure_extern("write_mem")];
+
+    let (summary, errors) = infer_effects(&definitions(program));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    assert_eq!(effects(&summary, "outside"), vec![Effect::External]);
+    assert_eq!(effects(&summary, "write_mem"), vec![Effect::WriteMemory]);
+
+    let program = vec![extern_val("outside", "outside", function_type(vec![typ("unit")], typ("unit")))];
+    let (summary, _) = infer_effects(&definitions(program));
+    assert!(summary.is_pure("outside"));
+  }
+
+  #[test]
+  fn effects_beyond_the_declared_ones_are_reported() {
+    let program = vec![
+      register("R", typ("int"), None),
+      // `function_type` declares no effects.
+      val("f", function_type(vec![typ("unit")], typ("int"))),
+      function("f", vec![], var("R")),
+    ];
+
+    let (_, errors) = infer_effects(&definitions(program));
+    assert_eq!(messages(&errors), vec!["`f` has undeclared effects {rreg}".to_string()]);
+  }
+
+  #[test]
+  fn more_definitions_call_earlier_ones() {
+    let (mut summary, _) = infer_effects(&definitions(vec![
+      register("R", typ("int"), None),
+      function("read", vec![], var("R")),
+    ]));
+    let errors = infer_more_effects(&mut summary, &definitions(vec![
+      function("caller", vec![], call("read", vec![])),
+      function("write", vec![], assign(var("R"), number(1))),
+    ]));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    assert_eq!(effects(&summary, "read"), vec![Effect::ReadRegister]);
+    assert_eq!(effects(&summary, "caller"), vec![Effect::ReadRegister]);
+    assert_eq!(effects(&summary, "write"), vec![Effect::WriteRegister]);
+  }
+}
@@ -0,0 +1,749 @@
+/*!
+
+Kind checking.
+
+Every type-level expression has one of four kinds: `Type` for types proper, `Int` for numeric expressions such as vector
+lengths, `Order` for `inc`/`dec`, and `Bool` for constraints. This pass checks that every `AbstractType` in the type
+definitions, value specifications, function annotations, mapping signatures and register declarations of a program is
+well kinded, and infers the kinds of ticked type variables whose kind is not written.
+
+Inference works over one quantifier scope at a time: a type scheme, or the parameters of a type definition. Each
+variable starts with its annotated kind, or an unknown kind that is fixed by the first use that constrains it, and
+variables no use constrains default to `Int`, as in Sail. Type variables that appear free in a type scheme are
+implicitly quantified over that scheme; in type definitions they are an error.
+
+Type definitions are checked in program order, and each one adds the kinds of its parameters to the `TypeSignatures`
+used for later applications of that type. Applications of unknown types are not checked, as the resolver has already
+reported them. Types inside expressions are left to the type checker, which can call `check_scheme` and
+`check_abstract_type` with the signatures this pass returns.
+
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::{resolve_type_infix, strip_definition, strip_type_union, type_definition_name};
+use crate::parser::location::{Located, SourceLocation};
+
+impl Display for Kind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      Kind::Type    => "Type",
+      Kind::Integer => "Int",
+      Kind::Order   => "Order",
+      Kind::Bool    => "Bool",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum KindError {
+  /// A type-level expression has the wrong kind.
+  Mismatch { expected: Kind, found: Kind },
+  /// A type is applied to the wrong number of arguments.
+  Arity { name: String, expected: usize, found: usize },
+  /// A type variable is used outside the scope of any quantifier binding it.
+  UnboundVariable(String),
+  /// A type variable is bound twice by the same quantifier.
+  DuplicateVariable(String),
+  /// A sequence of type-level operators that does not alternate operands and operators.
+  MalformedInfix,
+}
+
+pub type LocatedKindError = Located<KindError>;
+
+impl KindError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      KindError::Mismatch { expected, found } => {
+        write!(f, "expected something of kind {}, but this has kind {}", expected, found)
+      }
+
+      KindError::Arity { name, expected, found } => {
+        write!(f, "type `{}` takes {} argument(s), but {} were given", name, expected, found)
+      }
+
+      KindError::UnboundVariable(name) => {
+        write!(f, "type variable `{}` is not bound by any quantifier", name)
+      }
+
+      KindError::DuplicateVariable(name) => {
+        write!(f, "type variable `{}` is bound more than once", name)
+      }
+
+      KindError::MalformedInfix => {
+        write!(f, "malformed type-level operator expression")
+      }
+    }
+  }
+}
+
+impl Debug for KindError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for KindError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for KindError {}
+
+/// The kinds of a type's parameters and of the type itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeSignature {
+  pub parameters: Vec<Kind>,
+  /// `int` and `bool` may be used bare or applied to an argument
+  pub optional_parameters: bool,
+  pub result: Kind,
+}
+
+impl TypeSignature {
+  pub fn new(parameters: Vec<Kind>, result: Kind) -> Self {
+    TypeSignature { parameters, optional_parameters: false, result }
+  }
+}
+
+/// The signature of every type in scope, by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeSignatures(pub HashMap<String, TypeSignature>);
+
+impl TypeSignatures {
+  /// The builtin types and type-level functions.
+  pub fn builtin() -> Self {
+    use Kind::*;
+
+    let mut signatures = HashMap::new();
+    for name in ["bit", "nat", "real", "string", "unit"] {
+      signatures.insert(name.to_string(), TypeSignature::new(vec![], Type));
+    }
+    signatures.insert("int".to_string(), TypeSignature { parameters: vec![Integer], optional_parameters: true, result: Type });
+    signatures.insert("bool".to_string(), TypeSignature { parameters: vec![Bool], optional_parameters: true, result: Type });
+    for (name, parameters, result) in [
+      ("atom",      vec![Integer],          Type),
+      ("atom_bool", vec![Bool],             Type),
+      ("bits",      vec![Integer],          Type),
+      ("bitvector", vec![Integer],          Type),
+      ("implicit",  vec![Integer],          Type),
+      ("itself",    vec![Integer],          Type),
+      ("list",      vec![Type],             Type),
+      ("range",     vec![Integer, Integer], Type),
+      ("register",  vec![Type],             Type),
+      ("vector",    vec![Integer, Type],    Type),
+      ("abs",       vec![Integer],          Integer),
+      ("div",       vec![Integer, Integer], Integer),
+      ("mod",       vec![Integer, Integer], Integer),
+      ("not",       vec![Bool],             Bool),
+    ] {
+      signatures.insert(name.to_string(), TypeSignature::new(parameters, result));
+    }
+
+    TypeSignatures(signatures)
+  }
+
+  pub fn get(&self, name: &str) -> Option<&TypeSignature> {
+    self.0.get(name)
+  }
+
+  pub fn insert(&mut self, name: &str, signature: TypeSignature) {
+    self.0.insert(name.to_string(), signature);
+  }
+}
+
+/// A type variable bound by a quantifier, or implicitly by its use in a type scheme, with its inferred kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantifiedVariable {
+  pub name    : String,
+  pub location: SourceLocation,
+  pub kind    : Kind,
+}
+
+/// Checks the kinds of every type-level expression in the type-level parts of `definitions`.
+pub fn check_kinds(definitions: &Definitions) -> (TypeSignatures, Vec<LocatedKindError>) {
+  let mut signatures = TypeSignatures::builtin();
+  let mut errors = Vec::new();
+
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      check_definition(&mut signatures, definition, &mut errors);
+    }
+  }
+
+  (signatures, errors)
+}
+
+/// Checks the kinds in more definitions, following those `signatures` came from, adding the signatures of the types
+/// they define.
+pub fn check_more_kinds(signatures: &mut TypeSignatures, definitions: &Definitions) -> Vec<LocatedKindError> {
+  let mut errors = Vec::new();
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      check_definition(signatures, definition, &mut errors);
+    }
+  }
+  errors
+}
+
+/// Checks a type scheme, returning the kinds of the variables it quantifies over, implicitly or explicitly, in order.
+pub fn check_scheme(
+  signatures   : &TypeSignatures,
+  quantifier   : &LocatedTypeQuantifier,
+  abstract_type: &LocatedAbstractType,
+) -> (Vec<QuantifiedVariable>, Vec<LocatedKindError>) {
+  let mut checker = Checker::new(signatures, true);
+  checker.bind_quantifier(quantifier);
+  checker.expect(abstract_type, Kind::Type);
+  let variables = checker.finish();
+  (variables, checker.errors)
+}
+
+/// Checks that `abstract_type` has kind `expected` where the type variables `variables` are in scope.
+pub fn check_abstract_type(
+  signatures   : &TypeSignatures,
+  variables    : &[QuantifiedVariable],
+  abstract_type: &LocatedAbstractType,
+  expected     : Kind,
+) -> Vec<LocatedKindError> {
+  let mut checker = Checker::new(signatures, false);
+  for variable in variables {
+    checker.bind(&variable.name, variable.location.clone(), Some(variable.kind));
+  }
+  checker.expect(abstract_type, expected);
+  checker.errors
+}
+
+fn check_definition(signatures: &mut TypeSignatures, definition: &LocatedDefinition, errors: &mut Vec<LocatedKindError>) {
+  let (definition, _) = strip_definition(definition);
+
+  match &definition.value {
+    Definition::TypeDefinition(type_definition) => {
+      let name = type_definition_name(type_definition).name().to_string();
+      let signature = check_type_definition(signatures, type_definition, errors);
+      signatures.insert(&name, signature);
+    }
+    Definition::ValueSpec(specification) => {
+      let ValueSpecification::ValueSpec(scheme, _, _) = &specification.value;
+      errors.extend(check_scheme(signatures, &scheme.quantifier, &scheme.abstract_type).1);
+    }
+    Definition::OutcomeSpec(outcome, inner) => {
+      let OutcomeSpec::Outcome(_, scheme, _) = &outcome.value;
+      errors.extend(check_scheme(signatures, &scheme.quantifier, &scheme.abstract_type).1);
+      for definition in inner {
+        check_definition(signatures, definition, errors);
+      }
+    }
+    Definition::FunctionDefinition(function) => check_function(signatures, function, errors),
+    Definition::InternalMutRec(functions) => {
+      for function in functions {
+        check_function(signatures, function, errors);
+      }
+    }
+    Definition::MappingDefinition(mapping) => {
+      let MappingDefinition::Mapping(_, scheme, _) = &mapping.value;
+      if let Some(scheme) = &scheme.value {
+        errors.extend(check_scheme(signatures, &scheme.quantifier, &scheme.abstract_type).1);
+      }
+    }
+    Definition::Register(declaration) => {
+      let DeclarationSpecification::Register(abstract_type, _, _) = &declaration.value;
+      errors.extend(check_abstract_type(signatures, &[], abstract_type, Kind::Type));
+    }
+    Definition::Constraint(constraint) => {
+      let mut checker = Checker::new(signatures, true);
+      checker.expect(constraint, Kind::Bool);
+      errors.append(&mut checker.errors);
+    }
+    _ => {}
+  }
+}
+
+fn check_function(signatures: &TypeSignatures, function: &LocatedFunctionDefinition, errors: &mut Vec<LocatedKindError>) {
+  let FunctionDefinition::Function(_, annotation, _, _) = &function.value;
+  if let Some((quantifier, abstract_type)) = &annotation.value {
+    errors.extend(check_scheme(signatures, quantifier, abstract_type).1);
+  }
+}
+
+/// Checks a type definition, returning its signature.
+fn check_type_definition(
+  signatures: &TypeSignatures,
+  definition: &LocatedTypeDefinition,
+  errors    : &mut Vec<LocatedKindError>,
+) -> TypeSignature {
+  let mut checker = Checker::new(signatures, false);
+
+  let result = match &definition.value {
+    TypeDefinition::Abbreviation(_, quantifier, kind, body) => {
+      checker.bind_quantifier(quantifier);
+      checker.expect(body, kind.value);
+      kind.value
+    }
+    TypeDefinition::Record(_, quantifier, fields) => {
+      checker.bind_quantifier(quantifier);
+      for (abstract_type, _) in fields {
+        checker.expect(abstract_type, Kind::Type);
+      }
+      Kind::Type
+    }
+    TypeDefinition::Variant(_, quantifier, unions) => {
+      checker.bind_quantifier(quantifier);
+      for union in unions {
+        match &strip_type_union(union).value {
+          TypeUnion::TypeIdentifier(abstract_type, _) => checker.expect(abstract_type, Kind::Type),
+          TypeUnion::AnonymousRecord(fields, _) => {
+            for (abstract_type, _) in fields {
+              checker.expect(abstract_type, Kind::Type);
+            }
+          }
+          _ => unreachable!("wrappers are removed by strip_type_union"),
+        }
+      }
+      Kind::Type
+    }
+    TypeDefinition::Enum(_, functions, _) => {
+      for (_, abstract_type) in functions {
+        checker.expect(abstract_type, Kind::Type);
+      }
+      Kind::Type
+    }
+    TypeDefinition::Abstract(_, kind) => kind.value,
+    TypeDefinition::Bitfield(_, abstract_type, fields) => {
+      checker.expect(abstract_type, Kind::Type);
+      for (_, range) in fields {
+        checker.index_range(range);
+      }
+      Kind::Type
+    }
+  };
+
+  let parameters = checker.finish().into_iter().map(|variable| variable.kind).collect();
+  errors.append(&mut checker.errors);
+  TypeSignature::new(parameters, result)
+}
+
+/// The kind of a type-level expression while it is being inferred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KindTerm {
+  Known(Kind),
+  /// The kind of a type variable, indexing `Checker::slots`
+  Unknown(usize),
+  /// Compatible with every kind, as for wildcards and applications of unknown types
+  Any,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+  Link(usize),
+  Root(Option<Kind>),
+}
+
+struct Checker<'a> {
+  signatures: &'a TypeSignatures,
+  slots     : Vec<Slot>,
+  /// Innermost last. The first scope holds the variables `finish` reports.
+  scopes    : Vec<HashMap<String, usize>>,
+  /// The variables of the first scope, in the order they were bound
+  bound     : Vec<(String, SourceLocation, usize)>,
+  /// Whether free type variables are bound implicitly rather than reported
+  implicit  : bool,
+  errors    : Vec<LocatedKindError>,
+}
+
+impl<'a> Checker<'a> {
+  fn new(signatures: &'a TypeSignatures, implicit: bool) -> Self {
+    Checker { signatures, slots: Vec::new(), scopes: vec![HashMap::new()], bound: Vec::new(), implicit, errors: Vec::new() }
+  }
+
+  fn error(&mut self, location: &SourceLocation, error: KindError) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  /// Binds a type variable in the innermost scope.
+  fn bind(&mut self, name: &str, location: SourceLocation, kind: Option<Kind>) -> usize {
+    let slot = self.slots.len();
+    self.slots.push(Slot::Root(kind));
+    if self.scopes.len() == 1 {
+      self.bound.push((name.to_string(), location, slot));
+    }
+    self.scopes.last_mut().unwrap().insert(name.to_string(), slot);
+    slot
+  }
+
+  /// Binds the variables of a quantifier in the innermost scope, then checks its constraints.
+  fn bind_quantifier(&mut self, quantifier: &LocatedTypeQuantifier) {
+    let TypeQuantifier::TypeQuantifiers(items) = &quantifier.value else {
+      return;
+    };
+
+    for item in items {
+      if let QuantifierItem::KindedIdentifier(kinded) = &item.value {
+        let kind = kinded.kind.as_ref().map(|kind| kind.value);
+        for identifier in &kinded.identifiers {
+          let KindIdentifier(name) = &identifier.value;
+          if self.scopes.last().unwrap().contains_key(name) {
+            self.error(&identifier.location, KindError::DuplicateVariable(name.clone()));
+            continue;
+          }
+          self.bind(name, identifier.location.clone(), kind);
+        }
+      }
+    }
+
+    for item in items {
+      if let QuantifierItem::Constraint(constraint) = &item.value {
+        self.expect(constraint, Kind::Bool);
+      }
+    }
+  }
+
+  /// Defaults the kinds of unconstrained variables and reports the variables of the outermost scope.
+  fn finish(&mut self) -> Vec<QuantifiedVariable> {
+    let bound = std::mem::take(&mut self.bound);
+    bound
+        .into_iter()
+        .map(|(name, location, slot)| {
+          let kind = self.resolve(KindTerm::Unknown(slot)).unwrap_or(Kind::Integer);
+          QuantifiedVariable { name, location, kind }
+        })
+        .collect()
+  }
+
+  fn root(&self, mut slot: usize) -> usize {
+    while let Slot::Link(next) = self.slots[slot] {
+      slot = next;
+    }
+    slot
+  }
+
+  fn resolve(&self, term: KindTerm) -> Option<Kind> {
+    match term {
+      KindTerm::Known(kind) => Some(kind),
+      KindTerm::Unknown(slot) => match self.slots[self.root(slot)] {
+        Slot::Root(kind) => kind,
+        Slot::Link(_) => unreachable!("root returns a root"),
+      },
+      KindTerm::Any => None,
+    }
+  }
+
+  /// Makes two kinds equal, reporting a mismatch at `location` if they cannot be. `expected` is taken as the kind
+  /// demanded by the context.
+  fn unify(&mut self, expected: KindTerm, found: KindTerm, location: &SourceLocation) -> KindTerm {
+    match (expected, found) {
+      (KindTerm::Any, other)
+      | (other, KindTerm::Any) => other,
+
+      (KindTerm::Known(expected_kind), KindTerm::Known(found_kind)) => {
+        if expected_kind != found_kind {
+          self.error(location, KindError::Mismatch { expected: expected_kind, found: found_kind });
+        }
+        expected
+      }
+
+      (KindTerm::Unknown(slot), KindTerm::Known(kind))
+      | (KindTerm::Known(kind), KindTerm::Unknown(slot)) => {
+        let root = self.root(slot);
+        match self.slots[root] {
+          Slot::Root(Some(existing)) if existing != kind => {
+            // Report relative to the context: a known expectation against the variable's kind, or vice versa.
+            let (expected, found) = match expected {
+              KindTerm::Known(_) => (kind, existing),
+              _ => (existing, kind),
+            };
+            self.error(location, KindError::Mismatch { expected, found });
+          }
+          _ => self.slots[root] = Slot::Root(Some(kind)),
+        }
+        expected
+      }
+
+      (KindTerm::Unknown(first), KindTerm::Unknown(second)) => {
+        let (first, second) = (self.root(first), self.root(second));
+        if first != second {
+          let (Slot::Root(first_kind), Slot::Root(second_kind)) = (self.slots[first], self.slots[second]) else {
+            unreachable!("root returns a root")
+          };
+          match (first_kind, second_kind) {
+            (Some(expected), Some(found)) if expected != found => {
+              self.error(location, KindError::Mismatch { expected, found });
+            }
+            _ => {
+              self.slots[first] = Slot::Root(first_kind.or(second_kind));
+              self.slots[second] = Slot::Link(first);
+            }
+          }
+        }
+        expected
+      }
+    }
+  }
+
+  fn expect(&mut self, abstract_type: &LocatedAbstractType, kind: Kind) {
+    let found = self.infer(abstract_type);
+    self.unify(KindTerm::Known(kind), found, &abstract_type.location);
+  }
+
+  fn variable(&mut self, identifier: &LocatedKindIdentifier) -> KindTerm {
+    let KindIdentifier(name) = &identifier.value;
+    if let Some(&slot) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+      return KindTerm::Unknown(slot);
+    }
+
+    if self.implicit {
+      // Implicit quantification is over the whole scheme, so the variable goes in the outermost scope.
+      let slot = self.slots.len();
+      self.slots.push(Slot::Root(None));
+      self.bound.push((name.clone(), identifier.location.clone(), slot));
+      self.scopes[0].insert(name.clone(), slot);
+      KindTerm::Unknown(slot)
+    } else {
+      self.error(&identifier.location, KindError::UnboundVariable(name.clone()));
+      KindTerm::Any
+    }
+  }
+
+  fn application(&mut self, name: &LocatedIdentifier, arguments: &[LocatedAbstractType], location: &SourceLocation) -> KindTerm {
+    // Binary operators from `resolve_type_infix`
+    if arguments.len() == 2 {
+      let (left, right) = (&arguments[0], &arguments[1]);
+      match name.name() {
+        "==" | "!=" => {
+          let left_kind = self.infer(left);
+          let right_kind = self.infer(right);
+          let kind = self.unify(left_kind, right_kind, &right.location);
+          if let Some(found @ (Kind::Type | Kind::Order)) = self.resolve(kind) {
+            self.error(location, KindError::Mismatch { expected: Kind::Integer, found });
+          }
+          return KindTerm::Known(Kind::Bool);
+        }
+        ">=" | "<=" | ">" | "<" => {
+          self.expect(left, Kind::Integer);
+          self.expect(right, Kind::Integer);
+          return KindTerm::Known(Kind::Bool);
+        }
+        "&" | "|" => {
+          self.expect(left, Kind::Bool);
+          self.expect(right, Kind::Bool);
+          return KindTerm::Known(Kind::Bool);
+        }
+        "^" => {
+          self.expect(left, Kind::Integer);
+          self.expect(right, Kind::Integer);
+          return KindTerm::Known(Kind::Integer);
+        }
+        _ => {}
+      }
+    }
+
+    let Some(signature) = self.signatures.get(name.name()).cloned() else {
+      for argument in arguments {
+        self.infer(argument);
+      }
+      return KindTerm::Any;
+    };
+
+    if arguments.len() != signature.parameters.len() {
+      self.error(location, KindError::Arity {
+        name    : name.name().to_string(),
+        expected: signature.parameters.len(),
+        found   : arguments.len(),
+      });
+      for argument in arguments {
+        self.infer(argument);
+      }
+    } else {
+      for (argument, kind) in arguments.iter().zip(signature.parameters) {
+        self.expect(argument, kind);
+      }
+    }
+
+    KindTerm::Known(signature.result)
+  }
+
+  fn infer(&mut self, abstract_type: &LocatedAbstractType) -> KindTerm {
+    match &abstract_type.value {
+      AbstractType::Identifier(identifier) => match self.signatures.get(identifier.name()) {
+        Some(signature) => {
+          if !signature.parameters.is_empty() && !signature.optional_parameters {
+            let error = KindError::Arity {
+              name    : identifier.name().to_string(),
+              expected: signature.parameters.len(),
+              found   : 0,
+            };
+            self.error(&abstract_type.location, error);
+          }
+          KindTerm::Known(signature.result)
+        }
+        None => KindTerm::Any,
+      },
+
+      AbstractType::Variable(identifier) => self.variable(identifier),
+
+      AbstractType::Literal(literal) => match &literal.value {
+        Literal::Number(_) => KindTerm::Known(Kind::Integer),
+        Literal::True | Literal::False => KindTerm::Known(Kind::Bool),
+        _ => KindTerm::Any,
+      },
+
+      AbstractType::NumberSet(_) => KindTerm::Any,
+
+      AbstractType::In(element, set) => {
+        self.expect(element, Kind::Integer);
+        if !matches!(set.value, AbstractType::NumberSet(_)) {
+          self.infer(set);
+        }
+        KindTerm::Known(Kind::Bool)
+      }
+
+      AbstractType::Times(left, right)
+      | AbstractType::Sum(left, right)
+      | AbstractType::Minus(left, right) => {
+        self.expect(left, Kind::Integer);
+        self.expect(right, Kind::Integer);
+        KindTerm::Known(Kind::Integer)
+      }
+
+      AbstractType::Exponential(inner)
+      | AbstractType::Negative(inner) => {
+        self.expect(inner, Kind::Integer);
+        KindTerm::Known(Kind::Integer)
+      }
+
+      AbstractType::Infix(tokens) => match resolve_type_infix(tokens) {
+        Some(tree) => self.infer(&tree),
+        None => {
+          self.error(&abstract_type.location, KindError::MalformedInfix);
+          KindTerm::Any
+        }
+      },
+
+      AbstractType::Increasing
+      | AbstractType::Decreasing => KindTerm::Known(Kind::Order),
+
+      AbstractType::EffectSet(_)
+      | AbstractType::Wildcard => KindTerm::Any,
+
+      AbstractType::Function { lhs, rhs, .. }
+      | AbstractType::Bidirectional { lhs, rhs, .. } => {
+        self.expect(lhs, Kind::Type);
+        self.expect(rhs, Kind::Type);
+        KindTerm::Known(Kind::Type)
+      }
+
+      AbstractType::Tuple(types) => {
+        for inner in types {
+          self.expect(inner, Kind::Type);
+        }
+        KindTerm::Known(Kind::Type)
+      }
+
+      AbstractType::TypeConstructorApplication(name, arguments) => {
+        self.application(name, arguments, &abstract_type.location)
+      }
+
+      AbstractType::If { condition, then, elsewise } => {
+        self.expect(condition, Kind::Bool);
+        let then_kind = self.infer(then);
+        let else_kind = self.infer(elsewise);
+        self.unify(then_kind, else_kind, &elsewise.location)
+      }
+
+      AbstractType::Existential(variables, constraint, body) => {
+        self.scopes.push(HashMap::new());
+        for identifier in variables {
+          let KindIdentifier(name) = &identifier.value;
+          self.bind(name, identifier.location.clone(), None);
+        }
+        self.expect(constraint, Kind::Bool);
+        self.expect(body, Kind::Type);
+        self.scopes.pop();
+        KindTerm::Known(Kind::Type)
+      }
+
+      AbstractType::Parenthesized(inner) => self.infer(inner),
+    }
+  }
+
+  fn index_range(&mut self, range: &LocatedIndexRange) {
+    match &range.value {
+      IndexRange::Single(index) => self.expect(index, Kind::Integer),
+      IndexRange::Range(high, low) => {
+        self.expect(high, Kind::Integer);
+        self.expect(low, Kind::Integer);
+      }
+      IndexRange::Concat(left, right) => {
+        self.index_range(left);
+        self.index_range(right);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+
+  fn messages(errors: &[LocatedKindError]) -> Vec<String> {
+    errors.iter().map(|error| error.value.to_string()).collect()
+  }
+
+  fn check(program: Vec<LocatedDefinition>) -> (TypeSignatures, Vec<String>) {
+    let (signatures, errors) = check_kinds(&definitions(program));
+    (signatures, messages(&errors))
+  }
+
+  #[test]
+  fn free_variables_take_their_kinds_from_their_uses() {
+    let typ = function_type(
+      vec![type_application("bits", vec![type_variable("'n")]), type_application("list", vec![type_variable("'a")])],
+      typ("unit"),
+    );
+    let (variables, errors) = check_scheme(&TypeSignatures::builtin(), &located(TypeQuantifier::NoForAll), &typ);
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    let kinds: Vec<(&str, Kind)> = variables.iter().map(|variable| (variable.name.as_str(), variable.kind)).collect();
+    assert_eq!(kinds, vec![("'n", Kind::Integer), ("'a", Kind::Type)]);
+  }
+
+  #[test]
+  fn uses_must_agree_with_annotations() {
+    let bits_of_n = type_application("bits", vec![type_variable("'n")]);
+    let (_, errors) = check(vec![
+      val_scheme("f", scheme(&[("'n", Kind::Type)], None, function_type(vec![bits_of_n.clone()], typ("unit")))),
+      val_scheme("g", scheme(&[("'n", Kind::Integer)], Some(type_variable("'n")), function_type(vec![bits_of_n], typ("unit")))),
+    ]);
+    assert_eq!(errors, vec![
+      "expected something of kind Int, but this has kind Type".to_string(),
+      "expected something of kind Bool, but this has kind Int".to_string(),
+    ]);
+  }
+
+  #[test]
+  fn type_definitions_declare_their_signatures() {
+    let (signatures, errors) = check(vec![
+      union("U", vec![("A", typ("unit"))]),
+      register("R", type_application("U", vec![type_number(8)]), None),
+    ]);
+    assert_eq!(signatures.get("U"), Some(&TypeSignature::new(Vec::new(), Kind::Type)));
+    assert_eq!(errors, vec!["type `U` takes 0 argument(s), but 1 were given".to_string()]);
+  }
+
+  #[test]
+  fn type_definitions_cannot_have_free_variables() {
+    let (_, errors) = check(vec![type_synonym("T", type_application("bits", vec![type_variable("'n")]))]);
+    assert_eq!(errors, vec!["type variable `'n` is not bound by any quantifier".to_string()]);
+  }
+
+  #[test]
+  fn more_definitions_use_earlier_signatures() {
+    let (mut signatures, _) = check(vec![union("U", vec![("A", typ("unit"))])]);
+    let errors = check_more_kinds(&mut signatures, &definitions(vec![register("R", typ("U"), None)]));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+  }
+}
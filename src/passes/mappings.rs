@@ -0,0 +1,804 @@
+/*!
+
+Validation and inversion of mappings.
+
+A mapping `M : A <-> B` is a list of clauses `left <-> right`, each both a pattern and an expression on either side, so
+that the same clauses decode and encode. This pass checks that every bidirectional clause can run both ways, meaning
+that each variable bound on one side is used on the other, and then derives the functions Sail provides for every
+mapping, as ordinary definitions placed just after it:
+
+ * `M_forwards : A -> B` and `M_backwards : B -> A`, which fail where the mapping does not match,
+ * `M_forwards_matches : A -> bool` and `M_backwards_matches : B -> bool`, which say whether it does,
+
+with `val` specifications for all four placed after the mapping's own, so that they can be used wherever the mapping
+can. Names already defined in the program are left to their definitions.
+
+A mapping applied within a pattern, as in `ADD(rs) <-> 0b0 @ reg_enc(rs)`, is matched by binding the value to a fresh
+variable and testing it in a guard. Which of the applied mapping's functions applies depends on the type of the value,
+which is only known once types are checked, so the guard uses the overload `M_matches` of both `_matches` functions,
+declared here with the others, and the body applies the mapping itself, which the type checker runs in whichever
+direction fits. An `as` pattern is matched the same way, as plain patterns have no `as`.
+
+This pass is syntactic and runs before names are resolved, after scattered definitions have been collected.
+
+*/
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::{declared_names, strip_definition, Namespace};
+use crate::parser::location::{Located, SourceLocation};
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum MappingError {
+  /// A variable bound on the left of a clause is not used on the right, so the clause cannot run backwards.
+  UnusedOnRight(String),
+  /// A variable bound on the right of a clause is not used on the left, so the clause cannot run forwards.
+  UnusedOnLeft(String),
+  /// A mapping with neither a `val` specification nor a type, whose functions cannot be declared.
+  MissingType(String),
+}
+
+pub type LocatedMappingError = Located<MappingError>;
+
+impl MappingError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MappingError::UnusedOnRight(name) => {
+        write!(f, "`{}` is bound on the left of this mapping clause but not used on the right", name)
+      }
+
+      MappingError::UnusedOnLeft(name) => {
+        write!(f, "`{}` is bound on the right of this mapping clause but not used on the left", name)
+      }
+
+      MappingError::MissingType(name) => {
+        write!(f, "mapping `{}` has no type, so its forwards and backwards functions cannot be derived", name)
+      }
+    }
+  }
+}
+
+impl Debug for MappingError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for MappingError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for MappingError {}
+
+/// The names of the functions derived from the mapping `name`: forwards, backwards, forwards matches, backwards
+/// matches, and the overload of both matches.
+pub fn derived_names(name: &str) -> [String; 5] {
+  [
+    format!("{}_forwards", name),
+    format!("{}_backwards", name),
+    format!("{}_forwards_matches", name),
+    format!("{}_backwards_matches", name),
+    format!("{}_matches", name),
+  ]
+}
+
+/// The declared type of a mapping.
+struct MappingType {
+  quantifier: LocatedTypeQuantifier,
+  left      : LocatedAbstractType,
+  right     : LocatedAbstractType,
+  effect    : LocatedAbstractType,
+}
+
+/// What the pass needs to know about the whole program.
+#[derive(Default)]
+struct Program {
+  types    : HashMap<String, MappingType>,
+  /// Every mapping, defined or only specified
+  mappings : HashSet<String>,
+  /// Mappings with definitions
+  defined  : HashSet<String>,
+  /// Enum members and union constructors, which are not variables in patterns
+  constants: HashSet<String>,
+  /// Every value name declared in the program
+  declared : HashSet<String>,
+}
+
+impl Program {
+  fn new(definitions: &Definitions) -> Self {
+    let mut program = Program::default();
+    for (_, file) in definitions.0.iter() {
+      for definition in file {
+        program.add(definition);
+      }
+    }
+    program
+  }
+
+  fn add(&mut self, definition: &LocatedDefinition) {
+    for (namespace, name) in declared_names(definition) {
+      if namespace == Namespace::Value {
+        self.declared.insert(name.name().to_string());
+      }
+    }
+
+    let (definition, _) = strip_definition(definition);
+    match &definition.value {
+      Definition::TypeDefinition(_) => {
+        for (namespace, name) in declared_names(definition) {
+          if namespace == Namespace::Value {
+            self.constants.insert(name.name().to_string());
+          }
+        }
+      }
+
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(scheme, name, _) = &specification.value;
+        if let Some(mapping_type) = mapping_type(scheme) {
+          self.mappings.insert(name.name().to_string());
+          self.types.insert(name.name().to_string(), mapping_type);
+        }
+      }
+
+      Definition::MappingDefinition(mapping) => {
+        let MappingDefinition::Mapping(name, scheme, _) = &mapping.value;
+        let name = name.name().to_string();
+        self.mappings.insert(name.clone());
+        self.defined.insert(name.clone());
+        if let Some(mapping_type) = scheme.value.as_ref().and_then(mapping_type) {
+          self.types.entry(name).or_insert(mapping_type);
+        }
+      }
+
+      Definition::OutcomeSpec(_, definitions) => {
+        for definition in definitions {
+          self.add(definition);
+        }
+      }
+
+      _ => {}
+    }
+  }
+}
+
+/// The mapping type a type scheme gives, if it is one.
+fn mapping_type(scheme: &LocatedTypeScheme) -> Option<MappingType> {
+  let mut abstract_type = &scheme.abstract_type;
+  while let AbstractType::Parenthesized(inner) = &abstract_type.value {
+    abstract_type = inner;
+  }
+  match &abstract_type.value {
+    AbstractType::Bidirectional { lhs, rhs, effect } => Some(MappingType {
+      quantifier: scheme.quantifier.clone(),
+      left      : (**lhs).clone(),
+      right     : (**rhs).clone(),
+      effect    : (**effect).clone(),
+    }),
+    _ => None,
+  }
+}
+
+/// Checks every mapping and adds the functions derived from it. Definitions should already have had scattered
+/// definitions collected.
+pub fn invert_mappings(definitions: Definitions) -> (Definitions, Vec<LocatedMappingError>) {
+  let program = Program::new(&definitions);
+  let mut inverter = Inverter { program: &program, errors: Vec::new(), specified: HashSet::new(), fresh: 0 };
+
+  let files = definitions
+      .0
+      .into_iter()
+      .map(|(path, file)| {
+        let mut output = Vec::with_capacity(file.len());
+        for definition in file {
+          inverter.definition(definition, &mut output);
+        }
+        (path, output)
+      })
+      .collect();
+  (Definitions(files), inverter.errors)
+}
+
+/// A test a pattern cannot express: the value of `source` must match `pattern`, provided `precondition` holds first.
+struct View {
+  precondition: Option<LocatedExpression>,
+  source      : LocatedExpression,
+  pattern     : LocatedPattern,
+}
+
+struct Inverter<'a> {
+  program  : &'a Program,
+  errors   : Vec<LocatedMappingError>,
+  /// Mappings whose derived functions have been specified
+  specified: HashSet<String>,
+  fresh    : usize,
+}
+
+fn located<T>(value: T, location: &SourceLocation) -> Located<T> {
+  Located { location: location.clone(), value }
+}
+
+fn identifier(name: &str, location: &SourceLocation) -> LocatedIdentifier {
+  located(IdentifierType::Regular(name.to_string()), location)
+}
+
+fn boolean(value: bool, location: &SourceLocation) -> LocatedExpression {
+  let literal = if value { Literal::True } else { Literal::False };
+  located(Expression::Literal(located(literal, location)), location)
+}
+
+/// `left & right`, where either may be absent.
+fn conjunction(left: Option<LocatedExpression>, right: Option<LocatedExpression>) -> Option<LocatedExpression> {
+  match (left, right) {
+    (Some(left), Some(right)) => {
+      let location = left.location.clone();
+      let and = identifier("&", &location);
+      Some(located(Expression::InfixApplication(Box::new(left), and, Box::new(right)), &location))
+    }
+    (left, right) => left.or(right),
+  }
+}
+
+fn let_in(pattern: LocatedPattern, value: LocatedExpression, body: LocatedExpression) -> LocatedExpression {
+  let location = body.location.clone();
+  let binding = located(LetBinding::ValueBinding(Box::new(pattern), Box::new(value)), &location);
+  located(Expression::Let(binding, Box::new(body)), &location)
+}
+
+impl Inverter<'_> {
+  fn definition(&mut self, definition: LocatedDefinition, output: &mut Vec<LocatedDefinition>) {
+    let (stripped, private) = strip_definition(&definition);
+    let wrap = |generated: LocatedDefinition| match private {
+      true => {
+        let location = generated.location.clone();
+        located(Definition::Private(Box::new(generated)), &location)
+      }
+      false => generated,
+    };
+
+    let mut generated = Vec::new();
+    match &stripped.value {
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+        if self.program.defined.contains(name.name()) {
+          generated.extend(self.specifications(name.name(), &definition.location));
+        }
+      }
+
+      Definition::MappingDefinition(mapping) => {
+        let MappingDefinition::Mapping(name, _, clauses) = &mapping.value;
+        if !self.program.types.contains_key(name.name()) {
+          self.errors.push(located(MappingError::MissingType(name.name().to_string()), &name.location));
+        } else {
+          generated.extend(self.specifications(name.name(), &definition.location));
+          generated.extend(self.functions(name, clauses, &definition.location));
+        }
+      }
+
+      _ => {}
+    }
+
+    output.push(definition);
+    output.extend(generated.into_iter().map(wrap));
+  }
+
+  /// Whether a derived function `name` should be generated, rather than left to a definition in the program.
+  fn derive(&self, name: &str) -> bool {
+    !self.program.declared.contains(name)
+  }
+
+  // region Specifications
+
+  fn specifications(&mut self, name: &str, location: &SourceLocation) -> Vec<LocatedDefinition> {
+    let Some(mapping_type) = self.program.types.get(name) else {
+      return Vec::new();
+    };
+    if !self.specified.insert(name.to_string()) {
+      return Vec::new();
+    }
+
+    let location = SourceLocation::Generated(Box::new(location.clone()));
+    let boolean = located(AbstractType::Identifier(identifier("bool", &location)), &location);
+    let [forwards, backwards, forwards_matches, backwards_matches, matches] = derived_names(name);
+    let signatures = [
+      (forwards, &mapping_type.left, &mapping_type.right),
+      (backwards, &mapping_type.right, &mapping_type.left),
+      (forwards_matches.clone(), &mapping_type.left, &boolean),
+      (backwards_matches.clone(), &mapping_type.right, &boolean),
+    ];
+
+    let mut specifications = Vec::new();
+    for (function, argument, result) in signatures {
+      if !self.derive(&function) {
+        continue;
+      }
+      let abstract_type = located(
+        AbstractType::Function {
+          lhs   : Box::new(argument.clone()),
+          rhs   : Box::new(result.clone()),
+          effect: Box::new(mapping_type.effect.clone()),
+        },
+        &location,
+      );
+      let scheme = located(TypeScheme { quantifier: mapping_type.quantifier.clone(), abstract_type }, &location);
+      let specification = ValueSpecification::ValueSpec(Box::new(scheme), identifier(&function, &location), None);
+      specifications.push(located(Definition::ValueSpec(located(specification, &location)), &location));
+    }
+    if self.derive(&matches) {
+      let targets = vec![identifier(&forwards_matches, &location), identifier(&backwards_matches, &location)];
+      specifications.push(located(Definition::Overload(identifier(&matches, &location), targets), &location));
+    }
+    specifications
+  }
+
+  // endregion
+
+  // region Functions
+
+  fn functions(
+    &mut self,
+    name: &LocatedIdentifier,
+    clauses: &[LocatedMappingClause],
+    location: &SourceLocation,
+  ) -> Vec<LocatedDefinition> {
+    let location = SourceLocation::Generated(Box::new(location.clone()));
+    let mut forwards = Vec::new();
+    let mut backwards = Vec::new();
+    for clause in clauses {
+      self.clause(clause, &mut forwards, &mut backwards);
+    }
+
+    let [forwards_name, backwards_name, forwards_matches, backwards_matches, _] = derived_names(name.name());
+    let mut functions = Vec::new();
+    for (function, matches, arms) in [(forwards_name, forwards_matches, forwards), (backwards_name, backwards_matches, backwards)] {
+      if self.derive(&matches) {
+        let tests = arms.iter().map(|arm| self.matches_arm(arm)).collect();
+        functions.extend(self.function(&matches, self.complete(tests, &location), &location));
+      }
+      if self.derive(&function) {
+        functions.extend(self.function(&function, arms, &location));
+      }
+    }
+    functions
+  }
+
+  fn function(&self, name: &str, arms: Vec<LocatedPatternExpression>, location: &SourceLocation) -> Option<LocatedDefinition> {
+    if arms.is_empty() {
+      return None;
+    }
+    let clauses = arms
+        .into_iter()
+        .map(|arm| located(FunctionClause::Clause(identifier(name, location), Box::new(arm)), location))
+        .collect();
+    let function = FunctionDefinition::Function(
+      located(None, location),
+      located(None, location),
+      located(None, location),
+      clauses,
+    );
+    Some(located(Definition::FunctionDefinition(located(function, location)), location))
+  }
+
+  /// An arm answering `true` wherever `arm` matches.
+  fn matches_arm(&self, arm: &LocatedPatternExpression) -> LocatedPatternExpression {
+    let location = &arm.location;
+    let value = match &arm.value {
+      PatternExpression::Pattern(pattern, _) => PatternExpression::Pattern(pattern.clone(), Box::new(boolean(true, location))),
+      PatternExpression::PatternWhen(pattern, guard, _) => {
+        PatternExpression::PatternWhen(pattern.clone(), guard.clone(), Box::new(boolean(true, location)))
+      }
+    };
+    located(value, location)
+  }
+
+  /// `arms`, followed by an arm answering `false` if they might not match everything.
+  fn complete(&self, mut arms: Vec<LocatedPatternExpression>, location: &SourceLocation) -> Vec<LocatedPatternExpression> {
+    let covered = arms.iter().any(|arm| match &arm.value {
+      PatternExpression::Pattern(pattern, _) => self.irrefutable(pattern),
+      PatternExpression::PatternWhen(..) => false,
+    });
+    if !covered && !arms.is_empty() {
+      let wildcard = located(Pattern::Wildcard, location);
+      arms.push(located(PatternExpression::Pattern(Box::new(wildcard), Box::new(boolean(false, location))), location));
+    }
+    arms
+  }
+
+  fn clause(
+    &mut self,
+    clause: &LocatedMappingClause,
+    forwards: &mut Vec<LocatedPatternExpression>,
+    backwards: &mut Vec<LocatedPatternExpression>,
+  ) {
+    match &clause.value {
+      MappingClause::Attribute(_, _, inner)
+      | MappingClause::Documentation(_, inner) => self.clause(inner, forwards, backwards),
+
+      MappingClause::Bidirectional(left, right) => {
+        let left_variables = self.variables(side_pattern(left));
+        let right_variables = self.variables(side_pattern(right));
+        for variable in left_variables.difference(&right_variables) {
+          self.errors.push(located(MappingError::UnusedOnRight(variable.clone()), &clause.location));
+        }
+        for variable in right_variables.difference(&left_variables) {
+          self.errors.push(located(MappingError::UnusedOnLeft(variable.clone()), &clause.location));
+        }
+
+        let right_value = self.expression(side_pattern(right), &left_variables);
+        forwards.push(self.arm(left, right_value));
+        let left_value = self.expression(side_pattern(left), &right_variables);
+        backwards.push(self.arm(right, left_value));
+      }
+
+      MappingClause::ForwardsDeprecated(left, body) => forwards.push(self.arm(left, (**body).clone())),
+      MappingClause::Forwards(arm) => forwards.push(arm.clone()),
+      MappingClause::Backwards(arm) => backwards.push(arm.clone()),
+    }
+  }
+
+  /// A function arm matching one side of a mapping clause, with `body` as its result.
+  fn arm(&mut self, side: &LocatedMappingPatternExpression, body: LocatedExpression) -> LocatedPatternExpression {
+    let (pattern, guard) = match &side.value {
+      MappingPatternExpression::Pattern(pattern) => (pattern, None),
+      MappingPatternExpression::PatternWhen(pattern, guard) => (pattern, Some((**guard).clone())),
+    };
+    let mut views = Vec::new();
+    let pattern = self.pattern(pattern, &mut views);
+
+    // The guard tests each view in turn, outermost first, each in the scope of the variables the last bound.
+    let mut guard = guard;
+    let mut body = body;
+    for view in views.into_iter().rev() {
+      let test = match self.irrefutable(&view.pattern) {
+        true => guard.map(|guard| let_in(view.pattern.clone(), view.source.clone(), guard)),
+        false => {
+          let location = view.source.location.clone();
+          let fail = located(Pattern::Wildcard, &location);
+          let arms = vec![
+            located(
+              PatternExpression::Pattern(Box::new(view.pattern.clone()), Box::new(guard.unwrap_or_else(|| boolean(true, &location)))),
+              &location,
+            ),
+            located(PatternExpression::Pattern(Box::new(fail), Box::new(boolean(false, &location))), &location),
+          ];
+          Some(located(Expression::Match(Box::new(view.source.clone()), arms), &location))
+        }
+      };
+      guard = conjunction(view.precondition, test);
+      body = let_in(view.pattern, view.source, body);
+    }
+
+    let location = side.location.clone();
+    match guard {
+      Some(guard) => located(PatternExpression::PatternWhen(Box::new(pattern), Box::new(guard), Box::new(body)), &location),
+      None => located(PatternExpression::Pattern(Box::new(pattern), Box::new(body)), &location),
+    }
+  }
+
+  /// Whether a pattern matches every value of its type.
+  fn irrefutable(&self, pattern: &LocatedPattern) -> bool {
+    match &pattern.value {
+      Pattern::Wildcard => true,
+      Pattern::Identifier(name) => !self.program.constants.contains(name.name()),
+      Pattern::Typed(_, inner)
+      | Pattern::Variable(inner, _)
+      | Pattern::Attribute(_, _, inner) => self.irrefutable(inner),
+      Pattern::Tuple(elements) => elements.iter().all(|element| self.irrefutable(element)),
+      _ => false,
+    }
+  }
+
+  fn fresh_variable(&mut self) -> String {
+    self.fresh += 1;
+    format!("mapping#{}", self.fresh)
+  }
+
+  /// Translates a mapping pattern into a pattern, collecting the tests it cannot express in `views`.
+  fn pattern(&mut self, pattern: &LocatedMappingPattern, views: &mut Vec<View>) -> LocatedPattern {
+    let location = &pattern.location;
+    let patterns = |this: &mut Self, elements: &[LocatedMappingPattern], views: &mut Vec<View>| -> Vec<LocatedPattern> {
+      elements.iter().map(|element| this.pattern(element, views)).collect()
+    };
+    let value = match &pattern.value {
+      MappingPattern::Literal(literal) => Pattern::Literal(literal.clone()),
+      MappingPattern::Identifier(name) => Pattern::Identifier(name.clone()),
+
+      MappingPattern::Application(name, arguments) if self.program.mappings.contains(name.name()) => {
+        let variable = identifier(&self.fresh_variable(), location);
+        let value = located(Expression::Identifier(variable.clone()), location);
+        let [.., matches] = derived_names(name.name());
+        let precondition = located(Expression::Application(identifier(&matches, location), vec![value.clone()]), location);
+        let source = located(Expression::Application(name.clone(), vec![value]), location);
+        // The view is pushed before the views of its arguments, which can only be tested once it has bound them.
+        let index = views.len();
+        views.push(View { precondition: Some(precondition), source, pattern: located(Pattern::Wildcard, location) });
+        let mut elements = patterns(self, arguments, views);
+        views[index].pattern = match elements.len() {
+          1 => elements.remove(0),
+          _ => located(Pattern::Tuple(elements), location),
+        };
+        Pattern::Identifier(variable)
+      }
+
+      MappingPattern::Application(name, arguments) => Pattern::Constructor(name.clone(), patterns(self, arguments, views)),
+      MappingPattern::Vector(elements) => Pattern::Vector(patterns(self, elements, views)),
+      MappingPattern::VectorConcat(elements) => Pattern::VectorConcat(patterns(self, elements, views)),
+      MappingPattern::VectorSubrange(name, high, low) => Pattern::VectorSubrange(name.clone(), high.clone(), low.clone()),
+      MappingPattern::Tuple(elements) => Pattern::Tuple(patterns(self, elements, views)),
+      MappingPattern::List(elements) => Pattern::List(patterns(self, elements, views)),
+      MappingPattern::Cons(head, tail) => Pattern::Cons(Box::new(self.pattern(head, views)), Box::new(self.pattern(tail, views))),
+      MappingPattern::StringAppend(elements) => Pattern::StringAppend(patterns(self, elements, views)),
+      MappingPattern::Typed(inner, abstract_type) => Pattern::Typed(abstract_type.clone(), Box::new(self.pattern(inner, views))),
+
+      MappingPattern::As(inner, name) => {
+        let source = located(Expression::Identifier(name.clone()), location);
+        let index = views.len();
+        views.push(View { precondition: None, source, pattern: located(Pattern::Wildcard, location) });
+        views[index].pattern = self.pattern(inner, views);
+        Pattern::Identifier(name.clone())
+      }
+
+      MappingPattern::Struct(fields) => Pattern::Struct(
+        fields
+            .iter()
+            .map(|(name, field)| {
+              let field = FieldPattern::Field(name.clone(), Box::new(self.pattern(field, views)));
+              located(field, &name.location)
+            })
+            .collect(),
+      ),
+    };
+    located(value, location)
+  }
+
+  /// Translates a mapping pattern into the expression building the value it matches, from the variables the other
+  /// side of its clause binds.
+  fn expression(&self, pattern: &LocatedMappingPattern, bound: &BTreeSet<String>) -> LocatedExpression {
+    let location = &pattern.location;
+    let expressions = |elements: &[LocatedMappingPattern]| -> Vec<LocatedExpression> {
+      elements.iter().map(|element| self.expression(element, bound)).collect()
+    };
+    let number = |value: &crate::abstractions::BigInteger| {
+      located(Expression::Literal(located(Literal::Number(value.clone()), location)), location)
+    };
+    let value = match &pattern.value {
+      MappingPattern::Literal(literal) => Expression::Literal(literal.clone()),
+      MappingPattern::Identifier(name) => Expression::Identifier(name.clone()),
+      MappingPattern::Application(name, arguments) => Expression::Application(name.clone(), expressions(arguments)),
+      MappingPattern::Vector(elements) => Expression::Vector(expressions(elements)),
+      MappingPattern::VectorConcat(elements) => {
+        let mut parts = expressions(elements).into_iter();
+        let first = parts.next().unwrap_or_else(|| located(Expression::Vector(Vec::new()), location));
+        return parts.fold(first, |left, right| {
+          located(Expression::VectorAppend(Box::new(left), Box::new(right)), location)
+        });
+      }
+      MappingPattern::VectorSubrange(name, high, low) => Expression::VectorSubrange(
+        Box::new(located(Expression::Identifier(name.clone()), location)),
+        Box::new(number(high)),
+        Box::new(number(low)),
+      ),
+      MappingPattern::Tuple(elements) => Expression::Tuple(expressions(elements)),
+      MappingPattern::List(elements) => Expression::List(expressions(elements)),
+      MappingPattern::Cons(head, tail) => {
+        Expression::Cons(Box::new(self.expression(head, bound)), Box::new(self.expression(tail, bound)))
+      }
+      MappingPattern::StringAppend(elements) => {
+        let mut parts = expressions(elements).into_iter();
+        let empty = || located(Expression::Literal(located(Literal::String(String::new()), location)), location);
+        let first = parts.next().unwrap_or_else(empty);
+        return parts.fold(first, |left, right| {
+          located(Expression::Application(identifier("concat_str", location), vec![left, right]), location)
+        });
+      }
+      MappingPattern::Typed(inner, abstract_type) => {
+        Expression::Typed(abstract_type.clone(), Box::new(self.expression(inner, bound)))
+      }
+      MappingPattern::As(_, name) if bound.contains(name.name()) => Expression::Identifier(name.clone()),
+      MappingPattern::As(inner, _) => return self.expression(inner, bound),
+      MappingPattern::Struct(fields) => Expression::Struct(
+        fields
+            .iter()
+            .map(|(name, field)| {
+              let target = located(Expression::Identifier(name.clone()), &name.location);
+              located(Expression::Assign(Box::new(target), Box::new(self.expression(field, bound))), &name.location)
+            })
+            .collect(),
+      ),
+    };
+    located(value, location)
+  }
+
+  /// The variables a mapping pattern binds.
+  fn variables(&self, pattern: &LocatedMappingPattern) -> BTreeSet<String> {
+    let mut variables = BTreeSet::new();
+    self.collect_variables(pattern, &mut variables);
+    variables
+  }
+
+  fn collect_variables(&self, pattern: &LocatedMappingPattern, variables: &mut BTreeSet<String>) {
+    match &pattern.value {
+      MappingPattern::Literal(_) => {}
+      MappingPattern::Identifier(name) => {
+        if !self.program.constants.contains(name.name()) {
+          variables.insert(name.name().to_string());
+        }
+      }
+      MappingPattern::VectorSubrange(name, ..) => {
+        variables.insert(name.name().to_string());
+      }
+      MappingPattern::Application(_, elements)
+      | MappingPattern::Vector(elements)
+      | MappingPattern::VectorConcat(elements)
+      | MappingPattern::Tuple(elements)
+      | MappingPattern::List(elements)
+      | MappingPattern::StringAppend(elements) => {
+        for element in elements {
+          self.collect_variables(element, variables);
+        }
+      }
+      MappingPattern::Cons(head, tail) => {
+        self.collect_variables(head, variables);
+        self.collect_variables(tail, variables);
+      }
+      MappingPattern::Typed(inner, _) => self.collect_variables(inner, variables),
+      MappingPattern::As(inner, name) => {
+        variables.insert(name.name().to_string());
+        self.collect_variables(inner, variables);
+      }
+      MappingPattern::Struct(fields) => {
+        for (_, field) in fields {
+          self.collect_variables(field, variables);
+        }
+      }
+    }
+  }
+
+  // endregion
+}
+
+fn side_pattern(side: &LocatedMappingPatternExpression) -> &LocatedMappingPattern {
+  match &side.value {
+    MappingPatternExpression::Pattern(pattern)
+    | MappingPatternExpression::PatternWhen(pattern, _) => pattern,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{invert_mappings, LocatedMappingError};
+  use crate::abstractions::{BigInteger, Integer};
+  use crate::parser::ast::*;
+  use crate::parser::ast_util::strip_function_clause;
+  use crate::parser::testing::*;
+
+  /// The names of the definitions in a single file, as `kind name`.
+  fn outline(definitions: &Definitions) -> Vec<String> {
+    definitions.0[0].1
+        .iter()
+        .map(|definition| match &definition.value {
+          Definition::FunctionDefinition(function) => {
+            let FunctionDefinition::Function(_, _, _, clauses) = &function.value;
+            format!("function {} ({})", strip_function_clause(&clauses[0]).0.name(), clauses.len())
+          }
+          Definition::ValueSpec(specification) => {
+            let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+            format!("val {}", name.name())
+          }
+          Definition::MappingDefinition(mapping) => {
+            let MappingDefinition::Mapping(name, _, _) = &mapping.value;
+            format!("mapping {}", name.name())
+          }
+          Definition::Overload(name, _) => format!("overload {}", name.name()),
+          _ => "other".to_string(),
+        })
+        .collect()
+  }
+
+  /// `left <-> right`
+  fn mapping_type(left: LocatedAbstractType, right: LocatedAbstractType) -> LocatedAbstractType {
+    located(AbstractType::Bidirectional {
+      lhs   : Box::new(left),
+      rhs   : Box::new(right),
+      effect: Box::new(located(AbstractType::EffectSet(Vec::new()))),
+    })
+  }
+
+  fn side(pattern: MappingPattern) -> LocatedMappingPatternExpression {
+    located(MappingPatternExpression::Pattern(located(pattern)))
+  }
+
+  fn literal(literal: Literal) -> MappingPattern {
+    MappingPattern::Literal(located(literal))
+  }
+
+  /// `mapping name = { left <-> right, ... }`, without a type of its own.
+  fn mapping(name: &str, clauses: Vec<(MappingPattern, MappingPattern)>) -> LocatedDefinition {
+    let clauses = clauses
+        .into_iter()
+        .map(|(left, right)| located(MappingClause::Bidirectional(side(left), side(right))))
+        .collect();
+    located(Definition::MappingDefinition(located(MappingDefinition::Mapping(id(name), located(None), clauses))))
+  }
+
+  fn messages(errors: &[LocatedMappingError]) -> Vec<String> {
+    errors.iter().map(|error| error.value.to_string()).collect()
+  }
+
+  #[test]
+  fn functions_are_derived_after_the_mapping() {
+    let program = vec![
+      val("bit_bool", mapping_type(typ("bit"), typ("bool"))),
+      mapping("bit_bool", vec![
+        (literal(Literal::Zero), literal(Literal::False)),
+        (literal(Literal::One), literal(Literal::True)),
+      ]),
+    ];
+
+    let (definitions, errors) = invert_mappings(definitions(program));
+    assert!(errors.is_empty());
+    assert_eq!(outline(&definitions), vec![
+      "val bit_bool",
+      "val bit_bool_forwards",
+      "val bit_bool_backwards",
+      "val bit_bool_forwards_matches",
+      "val bit_bool_backwards_matches",
+      "overload bit_bool_matches",
+      "mapping bit_bool",
+      "function bit_bool_forwards_matches (3)",
+      "function bit_bool_forwards (2)",
+      "function bit_bool_backwards_matches (3)",
+      "function bit_bool_backwards (2)",
+    ]);
+  }
+
+  #[test]
+  fn functions_already_defined_are_not_derived() {
+    let forwards = function("bit_bool_forwards", vec![pattern("b")], boolean(true));
+    let program = vec![
+      val("bit_bool", mapping_type(typ("bit"), typ("bool"))),
+      val("bit_bool_forwards", function_type(vec![typ("bit")], typ("bool"))),
+      mapping("bit_bool", vec![(MappingPattern::Identifier(id("b")), MappingPattern::Identifier(id("b")))]),
+      forwards,
+    ];
+
+    let (definitions, errors) = invert_mappings(definitions(program));
+    assert!(errors.is_empty());
+    let outline = outline(&definitions);
+    assert_eq!(outline.iter().filter(|line| line.as_str() == "val bit_bool_forwards").count(), 1);
+    assert_eq!(outline.iter().filter(|line| line.starts_with("function bit_bool_forwards ")).count(), 1);
+    assert!(outline.contains(&"function bit_bool_backwards (1)".to_string()));
+  }
+
+  #[test]
+  fn variables_must_be_used_on_both_sides() {
+    let program = vec![
+      val("m", mapping_type(typ("int"), typ("int"))),
+      mapping("m", vec![
+        (MappingPattern::Identifier(id("x")), literal(Literal::Number(BigInteger::from_i64(0)))),
+        (literal(Literal::Number(BigInteger::from_i64(1))), MappingPattern::Identifier(id("y"))),
+      ]),
+    ];
+
+    let (_, errors) = invert_mappings(definitions(program));
+    assert_eq!(messages(&errors), vec![
+      "`x` is bound on the left of this mapping clause but not used on the right".to_string(),
+      "`y` is bound on the right of this mapping clause but not used on the left".to_string(),
+    ]);
+  }
+
+  #[test]
+  fn mappings_without_a_type_are_reported() {
+    let program = vec![mapping("m", vec![(literal(Literal::True), literal(Literal::False))])];
+
+    let (definitions, errors) = invert_mappings(definitions(program));
+    assert_eq!(messages(&errors), vec![
+      "mapping `m` has no type, so its forwards and backwards functions cannot be derived".to_string(),
+    ]);
+    assert_eq!(outline(&definitions), vec!["mapping m"]);
+  }
+}
@@ -0,0 +1,19 @@
+/*!
+
+Whole-program passes over `ast::Definitions`.
+
+Each pass takes the definitions of a loaded project (see `crate::project`) and either rewrites them or computes
+information about them, reporting problems as located diagnostics in the pass's own error type.
+
+*/
+
+pub mod bitfields;
+pub mod effects;
+pub mod kinds;
+pub mod mappings;
+pub mod monomorphise;
+pub mod patterns;
+pub mod resolve;
+pub mod scattered;
+pub mod termination;
+pub mod typecheck;
@@ -0,0 +1,1083 @@
+/*!
+
+Monomorphisation.
+
+Sail functions may be polymorphic in the sizes of the bitvectors they work on, as in
+`val zero_extend : forall 'n 'm, 'm >= 'n. (implicit('m), bits('n)) -> bits('m)`, but theorem-prover and hardware
+backends need every size to be a number. This pass specialises each such function to the sizes it is used at, starting
+from chosen entry points: the instance of `f` at `'n = 8` is a copy `f__8` of its definition and `val` specification
+with `8` for `'n` throughout, whose calls go to instances in turn. The program that results holds the instances, the
+reachable functions that need no specialising, and everything that is not a function. Functions that cannot be reached
+from the entry points, or from the initial values of registers and top-level `let`s, are left out.
+
+Only a function's size variables are specialised: the integer type variables that are the length of a bitvector or
+vector in its type or in the type of something in its body, or that determine a size variable of a function it calls.
+A call instantiates its callee's type variables with expressions over the caller's (see `typecheck::Call`), which in an
+instance of the caller are numbers if they depend only on the caller's sizes.
+
+A size that depends on a value only known at run time can still be resolved if the callee takes that value as an
+argument of type `int('n)` and its type restricts `'n` to a set, as in `'n in {8, 16, 32}`. The call is then split into
+a `match` on the argument, with a call of a different instance in each arm, as Sail's `-mono_rewrites` does. An entry
+point whose size variables are restricted to sets is specialised to every combination of them. Any other size that
+cannot be resolved is reported at the call, along with the chain of calls from an entry point that led there.
+
+External functions and functions without a body are not specialised, as backends implement them for every size.
+
+*/
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::abstractions::BigInteger;
+use crate::parser::ast::*;
+use crate::parser::ast_util::{function_name, resolve_expression_infix, strip_definition, Fixities};
+use crate::parser::location::{Located, SourceLocation};
+use crate::parser::visit::{walk_abstract_type, walk_definition, walk_expression, walk_function_definition, walk_pattern, Visitor};
+use crate::passes::typecheck::{Constraint, FunctionType, NumericExpression, Substitution, Type, TypeArgument, Typing};
+
+/// The most instances the pass creates before giving up, as it would on polymorphic recursion in a size.
+const INSTANCE_LIMIT: usize = 10_000;
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum MonomorphisationError {
+  /// A size of a call that could not be resolved, with the chain of calls from an entry point that led to it.
+  Unresolved { function: String, variable: String, chain: Vec<String> },
+  UnknownEntryPoint(String),
+  /// Specialisation did not finish, as happens with recursion at ever larger sizes.
+  TooManyInstances(String),
+}
+
+pub type LocatedMonomorphisationError = Located<MonomorphisationError>;
+
+impl MonomorphisationError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MonomorphisationError::Unresolved { function, variable, chain } => {
+        write!(f, "cannot resolve the size {} of `{}`", variable, function)?;
+        if !chain.is_empty() {
+          write!(f, ", reached by the calls {}", chain.join(" -> "))?;
+        }
+        Ok(())
+      }
+
+      MonomorphisationError::UnknownEntryPoint(name) => {
+        write!(f, "entry point `{}` is not a function", name)
+      }
+
+      MonomorphisationError::TooManyInstances(name) => {
+        write!(f, "too many instances of `{}`; is it recursive at ever larger sizes?", name)
+      }
+    }
+  }
+}
+
+impl Debug for MonomorphisationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for MonomorphisationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for MonomorphisationError {}
+
+fn located<T>(value: T, location: &SourceLocation) -> Located<T> {
+  Located { location: location.clone(), value }
+}
+
+fn identifier(name: &str, location: &SourceLocation) -> LocatedIdentifier {
+  located(IdentifierType::Regular(name.to_string()), location)
+}
+
+/// The name of the instance of `function` at `sizes`, such as `f__8_16`.
+pub fn instance_name(function: &str, sizes: &[BigInteger]) -> String {
+  if sizes.is_empty() {
+    return function.to_string();
+  }
+  let sizes: Vec<String> = sizes.iter().map(|size| size.to_string().replace('-', "m")).collect();
+  format!("{}__{}", function, sizes.join("_"))
+}
+
+// region Analysis
+
+/// A function and the values of its size variables, in order.
+type Instance = (String, Vec<BigInteger>);
+
+/// How a call in an instance is rewritten.
+#[derive(Clone, Debug)]
+enum Resolution {
+  /// Call the named instance
+  Instance(String),
+  /// Match on the argument at this index, which determines the callee's size, calling the instance for each value,
+  /// with the type the arms are given when their results differ in size
+  Split { argument: usize, cases: Vec<(BigInteger, String)>, result: Option<LocatedAbstractType> },
+}
+
+/// The calls in a function body or top-level definition, with their numbers of arguments, and the locations of all of
+/// its expressions and patterns.
+struct Locations<'a> {
+  fixities : &'a Fixities,
+  calls    : Vec<(SourceLocation, usize)>,
+  locations: Vec<SourceLocation>,
+}
+
+impl Visitor for Locations<'_> {
+  fn visit_expression(&mut self, expression: &LocatedExpression) {
+    self.locations.push(expression.location.clone());
+    match &expression.value {
+      Expression::Application(_, arguments) => self.calls.push((expression.location.clone(), arguments.len())),
+      Expression::InfixApplication(..) => self.calls.push((expression.location.clone(), 2)),
+      Expression::Infix(tokens) => {
+        if let Some(tree) = resolve_expression_infix(tokens, self.fixities) {
+          self.visit_expression(&tree);
+          return;
+        }
+      }
+      _ => {}
+    }
+    walk_expression(self, expression);
+  }
+
+  fn visit_pattern(&mut self, pattern: &LocatedPattern) {
+    self.locations.push(pattern.location.clone());
+    walk_pattern(self, pattern);
+  }
+}
+
+/// The type variables an abstract type mentions.
+#[derive(Default)]
+struct TypeVariables(BTreeSet<String>);
+
+impl Visitor for TypeVariables {
+  fn visit_abstract_type(&mut self, abstract_type: &LocatedAbstractType) {
+    if let AbstractType::Variable(variable) = &abstract_type.value {
+      self.0.insert(variable.value.0.clone());
+    }
+    walk_abstract_type(self, abstract_type);
+  }
+}
+
+/// A function with a body.
+struct Function<'a> {
+  definition: &'a LocatedFunctionDefinition,
+  typ       : FunctionType,
+  /// The result type in the function's `val` specification
+  result    : Option<&'a LocatedAbstractType>,
+  /// The size variables, in the order of the function's quantifier
+  sizes     : Vec<String>,
+  calls     : Vec<(SourceLocation, usize)>,
+}
+
+impl Function<'_> {
+  /// The values a size variable is restricted to by the function's constraint, if it is restricted to a set.
+  fn choices(&self, variable: &str) -> Option<Vec<BigInteger>> {
+    let mut pending = vec![&self.typ.constraint];
+    while let Some(constraint) = pending.pop() {
+      match constraint {
+        Constraint::And(left, right) => pending.extend([&**left, &**right]),
+        Constraint::Set(NumericExpression::Variable(name), values) if name == variable => return Some(values.clone()),
+        Constraint::Equal(NumericExpression::Variable(name), NumericExpression::Constant(value))
+          if name == variable =>
+        {
+          return Some(vec![value.clone()]);
+        }
+        _ => {}
+      }
+    }
+    None
+  }
+
+  /// The index among the arguments of a call with `count` arguments, `implicit` of them left out, of the argument of
+  /// type `int(variable)`.
+  fn argument_of(&self, variable: &str, count: usize, implicit: usize) -> Option<usize> {
+    let parameters = match self.typ.arguments.as_slice() {
+      [Type::Tuple(elements)] if count > 1 => elements.as_slice(),
+      parameters => parameters,
+    };
+    let index = parameters
+        .iter()
+        .position(|parameter| matches!(parameter, Type::Atom(NumericExpression::Variable(name)) if name == variable))?;
+    index.checked_sub(implicit).filter(|index| *index < count)
+  }
+}
+
+/// Adds the variables that are the length of a bitvector or vector in `typ` to `sizes`.
+fn size_variables(typ: &Type, sizes: &mut BTreeSet<String>) {
+  match typ {
+    Type::Bitvector(length) => length.free_variables(sizes),
+    Type::Vector(length, element) => {
+      length.free_variables(sizes);
+      size_variables(element, sizes);
+    }
+    Type::List(element)
+    | Type::Register(element) => size_variables(element, sizes),
+    Type::Tuple(elements) => {
+      for element in elements {
+        size_variables(element, sizes);
+      }
+    }
+    Type::Function(arguments, result) => {
+      for argument in arguments {
+        size_variables(argument, sizes);
+      }
+      size_variables(result, sizes);
+    }
+    Type::Bidirectional(left, right) => {
+      size_variables(left, sizes);
+      size_variables(right, sizes);
+    }
+    // The parameters of a user-defined type may be the sizes of its fields.
+    Type::Application(_, arguments) => {
+      for argument in arguments {
+        match argument {
+          TypeArgument::Type(typ) => size_variables(typ, sizes),
+          TypeArgument::Integer(length) => length.free_variables(sizes),
+          _ => {}
+        }
+      }
+    }
+    Type::Existential(variables, _, body) => {
+      let mut inner = BTreeSet::new();
+      size_variables(body, &mut inner);
+      for (variable, _) in variables {
+        inner.remove(variable);
+      }
+      sizes.extend(inner);
+    }
+    _ => {}
+  }
+}
+
+struct Monomorphiser<'a> {
+  typing     : &'a Typing,
+  functions  : HashMap<String, Function<'a>>,
+  /// The instances found so far, with the chain of calls that first reached each
+  chains     : HashMap<Instance, Vec<String>>,
+  pending    : VecDeque<Instance>,
+  /// How each call that needs rewriting is rewritten, for each instance; the top-level definitions are the instance
+  /// of the empty name
+  resolutions: HashMap<Instance, HashMap<SourceLocation, Resolution>>,
+  errors     : Vec<LocatedMonomorphisationError>,
+}
+
+impl<'a> Monomorphiser<'a> {
+  fn new(definitions: &'a Definitions, typing: &'a Typing) -> Self {
+    let fixities = &typing.environment.fixities;
+    let mut functions = HashMap::new();
+    let mut sizes: HashMap<String, BTreeSet<String>> = HashMap::new();
+    let mut results = HashMap::new();
+    for (_, file) in definitions.0.iter() {
+      for definition in file {
+        if let Definition::ValueSpec(specification) = &strip_definition(definition).0.value {
+          let ValueSpecification::ValueSpec(scheme, name, _) = &specification.value;
+          if let AbstractType::Function { rhs, .. } = &scheme.value.abstract_type.value {
+            results.insert(name.name(), &**rhs);
+          }
+        }
+      }
+    }
+    for (_, file) in definitions.0.iter() {
+      for definition in file {
+        let members = match &strip_definition(definition).0.value {
+          Definition::FunctionDefinition(function) => std::slice::from_ref(function),
+          Definition::InternalMutRec(group) => group.as_slice(),
+          _ => continue,
+        };
+        for definition in members {
+          let Some(name) = function_name(&definition.value) else {
+            continue;
+          };
+          let Some(typ) = typing.environment.functions.get(name.name()).cloned() else {
+            continue;
+          };
+          let mut visitor = Locations { fixities, calls: Vec::new(), locations: Vec::new() };
+          walk_function_definition(&mut visitor, definition);
+
+          let mut found = BTreeSet::new();
+          for typ in typ.arguments.iter().chain([&typ.result]) {
+            size_variables(typ, &mut found);
+          }
+          for location in &visitor.locations {
+            if let Some(typ) = typing.types.get(location) {
+              size_variables(typ, &mut found);
+            }
+          }
+          sizes.insert(name.name().to_string(), found);
+          let result = results.get(name.name()).copied();
+          let function = Function { definition, typ, result, sizes: Vec::new(), calls: visitor.calls };
+          functions.insert(name.name().to_string(), function);
+        }
+      }
+    }
+
+    // A variable that determines a size of a callee is a size too.
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for (name, function) in &functions {
+        let mut found = BTreeSet::new();
+        for (location, _) in &function.calls {
+          let Some(call) = typing.calls.get(location) else {
+            continue;
+          };
+          let Some(callee_sizes) = sizes.get(&call.function) else {
+            continue;
+          };
+          for variable in callee_sizes {
+            if let Some(argument) = call.instantiation.get(variable) {
+              argument.free_variables(&mut found);
+            }
+          }
+        }
+        let own = &sizes[name];
+        if found.iter().any(|variable| !own.contains(variable)) {
+          sizes.get_mut(name).unwrap().extend(found);
+          changed = true;
+        }
+      }
+    }
+
+    for (name, function) in functions.iter_mut() {
+      let found = &sizes[name];
+      function.sizes = (function.typ.variables.iter())
+          .filter(|(variable, kind)| *kind == Kind::Integer && found.contains(variable))
+          .map(|(variable, _)| variable.clone())
+          .collect();
+    }
+
+    Monomorphiser {
+      typing,
+      functions,
+      chains     : HashMap::new(),
+      pending    : VecDeque::new(),
+      resolutions: HashMap::new(),
+      errors     : Vec::new(),
+    }
+  }
+
+  fn error(&mut self, location: &SourceLocation, error: MonomorphisationError) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  /// Records an instance reached by `chain`, to be analysed if it is new.
+  fn reach(&mut self, instance: Instance, chain: Vec<String>, location: &SourceLocation) -> bool {
+    if self.chains.contains_key(&instance) {
+      return true;
+    }
+    if self.chains.len() >= INSTANCE_LIMIT {
+      self.error(location, MonomorphisationError::TooManyInstances(instance.0));
+      return false;
+    }
+    self.chains.insert(instance.clone(), chain);
+    self.pending.push_back(instance);
+    true
+  }
+
+  fn entry_point(&mut self, name: &str) {
+    let Some(function) = self.functions.get(name) else {
+      if !self.typing.environment.functions.contains_key(name) {
+        self.error(&SourceLocation::Unknown, MonomorphisationError::UnknownEntryPoint(name.to_string()));
+      }
+      return;
+    };
+    let location = function_name(&function.definition.value).map_or(SourceLocation::Unknown, |name| name.location.clone());
+
+    // Every combination of the values the sizes are restricted to
+    let mut combinations: Vec<Vec<BigInteger>> = vec![Vec::new()];
+    for variable in function.sizes.clone() {
+      let Some(choices) = self.functions[name].choices(&variable) else {
+        let error = MonomorphisationError::Unresolved { function: name.to_string(), variable, chain: Vec::new() };
+        self.error(&location, error);
+        return;
+      };
+      combinations = combinations
+          .into_iter()
+          .flat_map(|sizes| {
+            choices.iter().map(move |choice| {
+              let mut sizes = sizes.clone();
+              sizes.push(choice.clone());
+              sizes
+            })
+          })
+          .collect();
+    }
+    for sizes in combinations {
+      if !self.reach((name.to_string(), sizes), vec![name.to_string()], &location) {
+        return;
+      }
+    }
+  }
+
+  /// Resolves the calls of an instance, or of the top-level definitions, and reaches the instances they call.
+  fn calls(&mut self, instance: &Instance, calls: &[(SourceLocation, usize)], sizes: &Substitution) {
+    let typing = self.typing;
+    for (location, count) in calls {
+      let Some(call) = typing.calls.get(location) else {
+        continue;
+      };
+      let Some(callee) = self.functions.get(&call.function) else {
+        continue;
+      };
+      let mut chain = self.chains.get(instance).cloned().unwrap_or_default();
+      chain.push(call.function.clone());
+
+      let values: Vec<Option<BigInteger>> = (callee.sizes.iter())
+          .map(|variable| match call.instantiation.get(variable) {
+            Some(TypeArgument::Integer(value)) => value.substitute(sizes).as_constant(),
+            _ => None,
+          })
+          .collect();
+      let unresolved: Vec<usize> = (0..values.len()).filter(|index| values[*index].is_none()).collect();
+
+      if unresolved.is_empty() {
+        let values: Vec<BigInteger> = values.into_iter().flatten().collect();
+        let name = instance_name(&call.function, &values);
+        let specialised = !values.is_empty();
+        if self.reach((call.function.clone(), values), chain, location) && specialised {
+          self.resolve(instance, location, Resolution::Instance(name));
+        }
+        continue;
+      }
+
+      // A single size determined by an argument restricted to a set can be split on.
+      let variable = callee.sizes[unresolved[0]].clone();
+      let split = match unresolved.len() {
+        1 => callee
+            .choices(&variable)
+            .zip(callee.argument_of(&variable, *count, call.implicit.len())),
+        _ => None,
+      };
+      let Some((choices, argument)) = split else {
+        let error = MonomorphisationError::Unresolved { function: call.function.clone(), variable, chain };
+        self.error(location, error);
+        continue;
+      };
+      let result = self.split_result(&call.function, &values, &variable, &choices, location);
+      let mut cases = Vec::new();
+      for choice in choices {
+        let mut values: Vec<BigInteger> = values.iter().map(|value| value.clone().unwrap_or_default()).collect();
+        values[unresolved[0]] = choice.clone();
+        cases.push((choice, instance_name(&call.function, &values)));
+        if !self.reach((call.function.clone(), values), chain.clone(), location) {
+          return;
+        }
+      }
+      self.resolve(instance, location, Resolution::Split { argument, cases, result });
+    }
+  }
+
+  /// The type to give each arm of a call of `function` split on `variable`, if the size of its result depends on
+  /// `variable`: the existential over the choices for `variable` of the result type with the other sizes filled in.
+  fn split_result(
+    &self,
+    function: &str,
+    values  : &[Option<BigInteger>],
+    variable: &str,
+    choices : &[BigInteger],
+    location: &SourceLocation,
+  ) -> Option<LocatedAbstractType> {
+    let callee = &self.functions[function];
+    let specialiser = Specialiser {
+      sizes      : (callee.sizes.iter().cloned().zip(values.iter().cloned()))
+          .filter_map(|(variable, value)| Some((variable, value?)))
+          .collect(),
+      resolutions: None,
+      fixities   : &self.typing.environment.fixities,
+    };
+    let result = specialiser.abstract_type(callee.result?);
+    let mut variables = TypeVariables::default();
+    variables.visit_abstract_type(&result);
+    // A result that mentions type variables other than the one split on cannot be written at the call.
+    if !variables.0.contains(variable) || variables.0.len() > 1 {
+      return None;
+    }
+
+    let generated = SourceLocation::Generated(Box::new(location.clone()));
+    let bound = located(KindIdentifier(variable.to_string()), &generated);
+    let constraint = AbstractType::In(
+      Box::new(located(AbstractType::Variable(bound.clone()), &generated)),
+      Box::new(located(AbstractType::NumberSet(choices.to_vec()), &generated)),
+    );
+    let existential = AbstractType::Existential(vec![bound], Box::new(located(constraint, &generated)), Box::new(result));
+    Some(located(existential, &generated))
+  }
+
+  fn resolve(&mut self, instance: &Instance, location: &SourceLocation, resolution: Resolution) {
+    self.resolutions.entry(instance.clone()).or_default().insert(location.clone(), resolution);
+  }
+
+  fn run(&mut self, definitions: &Definitions, entry_points: &[&str]) {
+    let top: Instance = (String::new(), Vec::new());
+    let mut visitor = Locations { fixities: &self.typing.environment.fixities, calls: Vec::new(), locations: Vec::new() };
+    for (_, file) in definitions.0.iter() {
+      for definition in file {
+        if let Definition::Register(_) | Definition::ValueDefinition(_) = &strip_definition(definition).0.value {
+          walk_definition(&mut visitor, definition);
+        }
+      }
+    }
+    let calls = visitor.calls;
+    self.calls(&top, &calls, &Substitution::new());
+
+    for name in entry_points {
+      self.entry_point(name);
+    }
+
+    while let Some(instance) = self.pending.pop_front() {
+      let function = &self.functions[&instance.0];
+      let sizes: Substitution = (function.sizes.iter())
+          .zip(&instance.1)
+          .map(|(variable, value)| (variable.clone(), TypeArgument::Integer(NumericExpression::Constant(value.clone()))))
+          .collect();
+      let calls = function.calls.clone();
+      self.calls(&instance, &calls, &sizes);
+    }
+  }
+}
+
+// endregion
+
+// region Specialisation
+
+/// Copies syntax with the sizes of an instance substituted for their variables and its calls rewritten.
+struct Specialiser<'a> {
+  sizes      : HashMap<String, BigInteger>,
+  resolutions: Option<&'a HashMap<SourceLocation, Resolution>>,
+  fixities   : &'a Fixities,
+}
+
+impl Specialiser<'_> {
+  fn abstract_type(&self, abstract_type: &LocatedAbstractType) -> LocatedAbstractType {
+    let location = &abstract_type.location;
+    let boxed = |inner: &LocatedAbstractType| Box::new(self.abstract_type(inner));
+    let value = match &abstract_type.value {
+      AbstractType::Variable(variable) => match self.sizes.get(&variable.value.0) {
+        Some(size) => AbstractType::Literal(located(Literal::Number(size.clone()), location)),
+        None => AbstractType::Variable(variable.clone()),
+      },
+      AbstractType::In(left, right) => AbstractType::In(boxed(left), boxed(right)),
+      AbstractType::Times(left, right) => AbstractType::Times(boxed(left), boxed(right)),
+      AbstractType::Sum(left, right) => AbstractType::Sum(boxed(left), boxed(right)),
+      AbstractType::Minus(left, right) => AbstractType::Minus(boxed(left), boxed(right)),
+      AbstractType::Exponential(inner) => AbstractType::Exponential(boxed(inner)),
+      AbstractType::Negative(inner) => AbstractType::Negative(boxed(inner)),
+      AbstractType::Parenthesized(inner) => AbstractType::Parenthesized(boxed(inner)),
+      AbstractType::Infix(tokens) => AbstractType::Infix(
+        tokens
+            .iter()
+            .map(|(token, span)| {
+              let token = match token {
+                InfixToken::Primary(inner) => InfixToken::Primary(self.abstract_type(inner)),
+                InfixToken::Operator(operator) => InfixToken::Operator(operator.clone()),
+                InfixToken::Prefix(operator) => InfixToken::Prefix(operator.clone()),
+              };
+              (token, *span)
+            })
+            .collect(),
+      ),
+      AbstractType::Function { lhs, rhs, effect } => {
+        AbstractType::Function { lhs: boxed(lhs), rhs: boxed(rhs), effect: effect.clone() }
+      }
+      AbstractType::Bidirectional { lhs, rhs, effect } => {
+        AbstractType::Bidirectional { lhs: boxed(lhs), rhs: boxed(rhs), effect: effect.clone() }
+      }
+      AbstractType::Tuple(elements) => AbstractType::Tuple(elements.iter().map(|element| self.abstract_type(element)).collect()),
+      AbstractType::TypeConstructorApplication(name, arguments) => AbstractType::TypeConstructorApplication(
+        name.clone(),
+        arguments.iter().map(|argument| self.abstract_type(argument)).collect(),
+      ),
+      AbstractType::If { condition, then, elsewise } => {
+        AbstractType::If { condition: boxed(condition), then: boxed(then), elsewise: boxed(elsewise) }
+      }
+      AbstractType::Existential(variables, constraint, body) => {
+        AbstractType::Existential(variables.clone(), boxed(constraint), boxed(body))
+      }
+      other => other.clone(),
+    };
+    located(value, location)
+  }
+
+  /// The quantifier of an instance, without the variables it gives sizes to.
+  fn quantifier(&self, quantifier: &LocatedTypeQuantifier) -> LocatedTypeQuantifier {
+    let TypeQuantifier::TypeQuantifiers(items) = &quantifier.value else {
+      return quantifier.clone();
+    };
+    let mut kept = Vec::new();
+    for item in items {
+      match &item.value {
+        QuantifierItem::KindedIdentifier(kinded) => {
+          let mut kinded = kinded.clone();
+          kinded.value.identifiers.retain(|variable| !self.sizes.contains_key(&variable.value.0));
+          if !kinded.value.identifiers.is_empty() {
+            kept.push(located(QuantifierItem::KindedIdentifier(kinded), &item.location));
+          }
+        }
+        QuantifierItem::Constraint(constraint) => {
+          kept.push(located(QuantifierItem::Constraint(self.abstract_type(constraint)), &item.location));
+        }
+      }
+    }
+    match kept.is_empty() {
+      true => located(TypeQuantifier::NoForAll, &quantifier.location),
+      false => located(TypeQuantifier::TypeQuantifiers(kept), &quantifier.location),
+    }
+  }
+
+  fn pattern(&self, pattern: &LocatedPattern) -> LocatedPattern {
+    let boxed = |inner: &LocatedPattern| Box::new(self.pattern(inner));
+    let all = |elements: &[LocatedPattern]| elements.iter().map(|element| self.pattern(element)).collect();
+    let value = match &pattern.value {
+      Pattern::Typed(abstract_type, inner) => Pattern::Typed(Box::new(self.abstract_type(abstract_type)), boxed(inner)),
+      Pattern::Variable(inner, abstract_type) => {
+        Pattern::Variable(boxed(inner), Box::new(self.abstract_type(abstract_type)))
+      }
+      Pattern::Constructor(name, elements) => Pattern::Constructor(name.clone(), all(elements)),
+      Pattern::Vector(elements) => Pattern::Vector(all(elements)),
+      Pattern::VectorConcat(elements) => Pattern::VectorConcat(all(elements)),
+      Pattern::Tuple(elements) => Pattern::Tuple(all(elements)),
+      Pattern::List(elements) => Pattern::List(all(elements)),
+      Pattern::StringAppend(elements) => Pattern::StringAppend(all(elements)),
+      Pattern::Cons(head, tail) => Pattern::Cons(boxed(head), boxed(tail)),
+      Pattern::Struct(fields) => Pattern::Struct(
+        fields
+            .iter()
+            .map(|field| {
+              let value = match &field.value {
+                FieldPattern::Field(name, inner) => FieldPattern::Field(name.clone(), boxed(inner)),
+                FieldPattern::Wildcard => FieldPattern::Wildcard,
+              };
+              located(value, &field.location)
+            })
+            .collect(),
+      ),
+      Pattern::Attribute(name, data, inner) => Pattern::Attribute(name.clone(), data.clone(), boxed(inner)),
+      other => other.clone(),
+    };
+    located(value, &pattern.location)
+  }
+
+  fn arm(&self, arm: &LocatedPatternExpression) -> LocatedPatternExpression {
+    let value = match &arm.value {
+      PatternExpression::Pattern(pattern, body) => {
+        PatternExpression::Pattern(Box::new(self.pattern(pattern)), Box::new(self.expression(body)))
+      }
+      PatternExpression::PatternWhen(pattern, guard, body) => PatternExpression::PatternWhen(
+        Box::new(self.pattern(pattern)),
+        Box::new(self.expression(guard)),
+        Box::new(self.expression(body)),
+      ),
+    };
+    located(value, &arm.location)
+  }
+
+  fn let_binding(&self, binding: &LocatedLetBinding) -> LocatedLetBinding {
+    let LetBinding::ValueBinding(pattern, value) = &binding.value;
+    located(LetBinding::ValueBinding(Box::new(self.pattern(pattern)), Box::new(self.expression(value))), &binding.location)
+  }
+
+  /// A call of `function` with `arguments` at `location`, rewritten as it was resolved.
+  fn call(
+    &self,
+    location : &SourceLocation,
+    function : &LocatedIdentifier,
+    arguments: Vec<LocatedExpression>,
+  ) -> Option<Expression> {
+    match self.resolutions?.get(location)? {
+      Resolution::Instance(name) => Some(Expression::Application(identifier(name, &function.location), arguments)),
+
+      Resolution::Split { argument, cases, result } => {
+        let generated = SourceLocation::Generated(Box::new(location.clone()));
+        let arms = cases
+            .iter()
+            .enumerate()
+            .map(|(index, (size, name))| {
+              // The callee's constraint means the last case is the only one left.
+              let pattern = match index + 1 == cases.len() {
+                true => Pattern::Wildcard,
+                false => Pattern::Literal(located(Literal::Number(size.clone()), &generated)),
+              };
+              let mut arguments = arguments.clone();
+              let size = Expression::Literal(located(Literal::Number(size.clone()), &generated));
+              arguments[*argument] = located(size, &arguments[*argument].location);
+              let mut body = located(Expression::Application(identifier(name, &function.location), arguments), location);
+              if let Some(result) = result {
+                body = located(Expression::Typed(Box::new(result.clone()), Box::new(body)), &generated);
+              }
+              let arm = PatternExpression::Pattern(Box::new(located(pattern, &generated)), Box::new(body));
+              located(arm, &generated)
+            })
+            .collect();
+        Some(Expression::Match(Box::new(arguments[*argument].clone()), arms))
+      }
+    }
+  }
+
+  fn expression(&self, expression: &LocatedExpression) -> LocatedExpression {
+    let location = &expression.location;
+    let boxed = |inner: &LocatedExpression| Box::new(self.expression(inner));
+    let all = |elements: &[LocatedExpression]| -> Vec<LocatedExpression> {
+      elements.iter().map(|element| self.expression(element)).collect()
+    };
+    let value = match &expression.value {
+      Expression::Block(elements) => Expression::Block(all(elements)),
+      Expression::Dereference(inner) => Expression::Dereference(boxed(inner)),
+      Expression::Typed(abstract_type, inner) => {
+        Expression::Typed(Box::new(self.abstract_type(abstract_type)), boxed(inner))
+      }
+      Expression::Application(function, arguments) => {
+        let arguments = all(arguments);
+        match self.call(location, function, arguments.clone()) {
+          Some(call) => call,
+          None => Expression::Application(function.clone(), arguments),
+        }
+      }
+      Expression::InfixApplication(left, operator, right) => {
+        match self.call(location, operator, vec![self.expression(left), self.expression(right)]) {
+          Some(call) => call,
+          None => Expression::InfixApplication(boxed(left), operator.clone(), boxed(right)),
+        }
+      }
+      Expression::Infix(tokens) => match resolve_expression_infix(tokens, self.fixities) {
+        Some(tree) => return self.expression(&tree),
+        None => Expression::Infix(tokens.clone()),
+      },
+      Expression::Tuple(elements) => Expression::Tuple(all(elements)),
+      Expression::If { condition, then_expr, else_expr, if_location } => Expression::If {
+        condition  : boxed(condition),
+        then_expr  : boxed(then_expr),
+        else_expr  : boxed(else_expr),
+        if_location: if_location.clone(),
+      },
+      Expression::Loop(loop_type, measure, condition, body) => {
+        let measure = located(measure.value.as_ref().map(|measure| self.expression(measure)), &measure.location);
+        Expression::Loop(loop_type.clone(), Box::new(measure), boxed(condition), boxed(body))
+      }
+      Expression::For { identifier, start, end, step, typ, body } => Expression::For {
+        identifier: identifier.clone(),
+        start     : boxed(start),
+        end       : boxed(end),
+        step      : boxed(step),
+        typ       : typ.clone(),
+        body      : boxed(body),
+      },
+      Expression::Vector(elements) => Expression::Vector(all(elements)),
+      Expression::VectorAccess(vector, index) => Expression::VectorAccess(boxed(vector), boxed(index)),
+      Expression::VectorSubrange(vector, high, low) => Expression::VectorSubrange(boxed(vector), boxed(high), boxed(low)),
+      Expression::VectorUpdate(vector, index, value) => {
+        Expression::VectorUpdate(boxed(vector), boxed(index), boxed(value))
+      }
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        Expression::VectorUpdateSubrange(boxed(vector), boxed(high), boxed(low), boxed(value))
+      }
+      Expression::VectorAppend(left, right) => Expression::VectorAppend(boxed(left), boxed(right)),
+      Expression::List(elements) => Expression::List(all(elements)),
+      Expression::Cons(head, tail) => Expression::Cons(boxed(head), boxed(tail)),
+      Expression::Struct(fields) => Expression::Struct(all(fields)),
+      Expression::StructUpdate(inner, fields) => Expression::StructUpdate(boxed(inner), all(fields)),
+      Expression::Field(inner, field) => Expression::Field(boxed(inner), field.clone()),
+      Expression::Match(scrutinee, arms) => {
+        Expression::Match(boxed(scrutinee), arms.iter().map(|arm| self.arm(arm)).collect())
+      }
+      Expression::Let(binding, body) => Expression::Let(self.let_binding(binding), boxed(body)),
+      Expression::Assign(target, value) => Expression::Assign(boxed(target), boxed(value)),
+      Expression::Sizeof(abstract_type) => Expression::Sizeof(Box::new(self.abstract_type(abstract_type))),
+      Expression::Constraint(abstract_type) => Expression::Constraint(Box::new(self.abstract_type(abstract_type))),
+      Expression::Exit(inner) => Expression::Exit(boxed(inner)),
+      Expression::Throw(inner) => Expression::Throw(boxed(inner)),
+      Expression::Try(body, arms) => Expression::Try(boxed(body), arms.iter().map(|arm| self.arm(arm)).collect()),
+      Expression::Return(inner) => Expression::Return(boxed(inner)),
+      Expression::Assert(condition, message) => Expression::Assert(boxed(condition), boxed(message)),
+      Expression::Variable(target, value, body) => Expression::Variable(boxed(target), boxed(value), boxed(body)),
+      Expression::Attribute(name, data, inner) => Expression::Attribute(name.clone(), data.clone(), boxed(inner)),
+      Expression::InternalPlet(pattern, value, body) => {
+        Expression::InternalPlet(Box::new(self.pattern(pattern)), boxed(value), boxed(body))
+      }
+      Expression::InternalReturn(inner) => Expression::InternalReturn(boxed(inner)),
+      Expression::InternalAssume(abstract_type, inner) => {
+        Expression::InternalAssume(Box::new(self.abstract_type(abstract_type)), boxed(inner))
+      }
+      Expression::Identifier(_)
+      | Expression::Reference(_)
+      | Expression::Literal(_) => expression.value.clone(),
+    };
+    located(value, location)
+  }
+
+  fn clause(&self, clause: &LocatedFunctionClause, name: &str) -> LocatedFunctionClause {
+    let value = match &clause.value {
+      FunctionClause::Private(inner) => FunctionClause::Private(Box::new(self.clause(inner, name))),
+      FunctionClause::Attribute(attribute, data, inner) => {
+        FunctionClause::Attribute(attribute.clone(), data.clone(), Box::new(self.clause(inner, name)))
+      }
+      FunctionClause::Documentation(text, inner) => {
+        FunctionClause::Documentation(text.clone(), Box::new(self.clause(inner, name)))
+      }
+      FunctionClause::Clause(function, arm) => {
+        FunctionClause::Clause(identifier(name, &function.location), Box::new(self.arm(arm)))
+      }
+    };
+    located(value, &clause.location)
+  }
+
+  fn function(&self, function: &LocatedFunctionDefinition, name: &str) -> LocatedFunctionDefinition {
+    let FunctionDefinition::Function(recursive, annotation, effect, clauses) = &function.value;
+    let recursive = recursive
+        .value
+        .as_ref()
+        .map(|(pattern, measure)| (Box::new(self.pattern(pattern)), Box::new(self.expression(measure))));
+    let annotation = annotation
+        .value
+        .as_ref()
+        .map(|(quantifier, abstract_type)| (self.quantifier(quantifier), Box::new(self.abstract_type(abstract_type))));
+    let definition = FunctionDefinition::Function(
+      located(recursive, &function.location),
+      located(annotation, &function.location),
+      effect.clone(),
+      clauses.iter().map(|clause| self.clause(clause, name)).collect(),
+    );
+    located(definition, &function.location)
+  }
+}
+
+/// `inner` in place of the definition `definition` wraps, keeping its wrappers.
+fn rewrap(definition: &LocatedDefinition, inner: Definition) -> LocatedDefinition {
+  let value = match &definition.value {
+    Definition::Private(wrapped) => Definition::Private(Box::new(rewrap(wrapped, inner))),
+    Definition::Attribute(name, data, wrapped) => {
+      Definition::Attribute(name.clone(), data.clone(), Box::new(rewrap(wrapped, inner)))
+    }
+    Definition::Documentation(text, wrapped) => {
+      Definition::Documentation(text.clone(), Box::new(rewrap(wrapped, inner)))
+    }
+    _ => inner,
+  };
+  located(value, &definition.location)
+}
+
+// endregion
+
+/// Specialises the size-polymorphic functions of `definitions`, which should have been type checked, to the sizes they
+/// are used at from `entry_points`, returning the program with every reachable function replaced by its instances.
+pub fn monomorphise(
+  definitions : &Definitions,
+  typing      : &Typing,
+  entry_points: &[&str],
+) -> (Definitions, Vec<LocatedMonomorphisationError>) {
+  let mut monomorphiser = Monomorphiser::new(definitions, typing);
+  monomorphiser.run(definitions, entry_points);
+
+  // The instances of each function, smallest sizes first
+  let mut instances: HashMap<&str, Vec<&Vec<BigInteger>>> = HashMap::new();
+  for (name, sizes) in monomorphiser.chains.keys() {
+    instances.entry(name.as_str()).or_default().push(sizes);
+  }
+  for sizes in instances.values_mut() {
+    sizes.sort();
+  }
+
+  let functions = &monomorphiser.functions;
+  let specialiser = |name: &str, sizes: &[BigInteger]| {
+    let variables = functions.get(name).map_or(&[][..], |function| function.sizes.as_slice());
+    Specialiser {
+      sizes      : variables.iter().cloned().zip(sizes.iter().cloned()).collect(),
+      resolutions: monomorphiser.resolutions.get(&(name.to_string(), sizes.to_vec())),
+      fixities   : &typing.environment.fixities,
+    }
+  };
+  let specialise = |function: &LocatedFunctionDefinition| -> Vec<LocatedFunctionDefinition> {
+    let Some(name) = function_name(&function.value) else {
+      return vec![function.clone()];
+    };
+    let name = name.name();
+    (instances.get(name).into_iter().flatten())
+        .map(|sizes| specialiser(name, sizes).function(function, &instance_name(name, sizes)))
+        .collect()
+  };
+  let instance_names = |name: &str| -> Vec<String> {
+    match functions.contains_key(name) {
+      true => (instances.get(name).into_iter().flatten()).map(|sizes| instance_name(name, sizes)).collect(),
+      false => vec![name.to_string()],
+    }
+  };
+  let top = specialiser("", &[]);
+
+  let mut files = Vec::new();
+  for (file_name, file) in definitions.0.iter() {
+    let mut result = Vec::new();
+    for definition in file {
+      match &strip_definition(definition).0.value {
+        Definition::FunctionDefinition(function) => {
+          for function in specialise(function) {
+            result.push(rewrap(definition, Definition::FunctionDefinition(function)));
+          }
+        }
+
+        Definition::InternalMutRec(group) => {
+          let group: Vec<LocatedFunctionDefinition> = group.iter().flat_map(specialise).collect();
+          if !group.is_empty() {
+            result.push(rewrap(definition, Definition::InternalMutRec(group)));
+          }
+        }
+
+        Definition::ValueSpec(specification) => {
+          let ValueSpecification::ValueSpec(scheme, name, bindings) = &specification.value;
+          if !functions.contains_key(name.name()) {
+            result.push(definition.clone());
+            continue;
+          }
+          for sizes in instances.get(name.name()).into_iter().flatten() {
+            let specialiser = specialiser(name.name(), sizes);
+            let specialised = TypeScheme {
+              quantifier   : specialiser.quantifier(&scheme.value.quantifier),
+              abstract_type: specialiser.abstract_type(&scheme.value.abstract_type),
+            };
+            let renamed = identifier(&instance_name(name.name(), sizes), &name.location);
+            let specialised = Box::new(located(specialised, &scheme.location));
+            let instance = ValueSpecification::ValueSpec(specialised, renamed, bindings.clone());
+            result.push(rewrap(definition, Definition::ValueSpec(located(instance, &specification.location))));
+          }
+        }
+
+        Definition::Overload(name, targets) => {
+          let targets: Vec<LocatedIdentifier> = (targets.iter())
+              .flat_map(|target| {
+                instance_names(target.name()).into_iter().map(|name| identifier(&name, &target.location))
+              })
+              .collect();
+          if !targets.is_empty() {
+            result.push(rewrap(definition, Definition::Overload(name.clone(), targets)));
+          }
+        }
+
+        Definition::Measure(name, pattern, measure) => {
+          for sizes in instances.get(name.name()).into_iter().flatten() {
+            let specialiser = specialiser(name.name(), sizes);
+            let renamed = identifier(&instance_name(name.name(), sizes), &name.location);
+            let measure =
+                Definition::Measure(renamed, Box::new(specialiser.pattern(pattern)), Box::new(specialiser.expression(measure)));
+            result.push(rewrap(definition, measure));
+          }
+        }
+
+        Definition::LoopMeasures(name, measures) => {
+          for sizes in instances.get(name.name()).into_iter().flatten() {
+            let specialiser = specialiser(name.name(), sizes);
+            let renamed = identifier(&instance_name(name.name(), sizes), &name.location);
+            let measures = (measures.iter())
+                .map(|measure| LoopMeasure {
+                  loop_type : measure.loop_type.clone(),
+                  expression: Box::new(specialiser.expression(&measure.expression)),
+                })
+                .collect();
+            result.push(rewrap(definition, Definition::LoopMeasures(renamed, measures)));
+          }
+        }
+
+        Definition::Register(declaration) => {
+          let DeclarationSpecification::Register(abstract_type, name, initial) = &declaration.value;
+          let initial = initial.as_ref().map(|initial| Box::new(top.expression(initial)));
+          let renamed = DeclarationSpecification::Register(abstract_type.clone(), name.clone(), initial);
+          result.push(rewrap(definition, Definition::Register(located(renamed, &declaration.location))));
+        }
+
+        Definition::ValueDefinition(binding) => {
+          result.push(rewrap(definition, Definition::ValueDefinition(top.let_binding(binding))));
+        }
+
+        _ => result.push(definition.clone()),
+      }
+    }
+    files.push((file_name.clone(), result));
+  }
+  (Definitions(files), monomorphiser.errors)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+
+  /// The functions and `val` specifications of a single file, as `kind name`.
+  fn outline(definitions: &Definitions) -> Vec<String> {
+    (definitions.0[0].1.iter())
+        .filter_map(|definition| match &definition.value {
+          Definition::FunctionDefinition(function) => Some(format!("function {}", function_name(&function.value)?.name())),
+          Definition::ValueSpec(specification) => {
+            let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+            Some(format!("val {}", name.name()))
+          }
+          _ => None,
+        })
+        .collect()
+  }
+
+  fn bits_of(variable: &str) -> LocatedAbstractType {
+    type_application("bits", vec![type_variable(variable)])
+  }
+
+  /// Type checks `program` and monomorphises it from `entry_points`.
+  fn specialise(program: Vec<LocatedDefinition>, entry_points: &[&str]) -> (Vec<String>, Vec<String>) {
+    let definitions = definitions(program);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (definitions, errors) = monomorphise(&definitions, &typing, entry_points);
+    (outline(&definitions), errors.iter().map(|error| error.value.to_string()).collect())
+  }
+
+  #[test]
+  fn functions_are_specialised_to_the_sizes_they_are_used_at() {
+    let (outline, errors) = specialise(
+      vec![
+        val_scheme("same", scheme(&[("'n", Kind::Integer)], None, function_type(vec![bits_of("'n")], bits_of("'n")))),
+        function("same", vec![pattern("x")], var("x")),
+        val("byte", function_type(vec![bits(8)], bits(8))),
+        function("byte", vec![pattern("x")], call("same", vec![var("x")])),
+        val("half", function_type(vec![bits(16)], bits(16))),
+        function("half", vec![pattern("x")], call("same", vec![var("x")])),
+        val("unused", function_type(vec![bits(32)], bits(32))),
+        function("unused", vec![pattern("x")], call("same", vec![var("x")])),
+      ],
+      &["byte", "half"],
+    );
+    assert_eq!(errors, Vec::<String>::new());
+    assert_eq!(outline, vec![
+      "val same__8",
+      "val same__16",
+      "function same__8",
+      "function same__16",
+      "val byte",
+      "function byte",
+      "val half",
+      "function half",
+    ]);
+  }
+
+  #[test]
+  fn sizes_known_only_at_run_time_are_reported() {
+    let zeros_type = || function_type(vec![type_application("int", vec![type_variable("'n")])], bits_of("'n"));
+    let (_, errors) = specialise(
+      vec![
+        extern_val("sail_zeros", "zeros", zeros_type()),
+        val_scheme("zeros", scheme(&[("'n", Kind::Integer)], None, zeros_type())),
+        function("zeros", vec![pattern("n")], call("sail_zeros", vec![var("n")])),
+        val("clear", function_type(vec![typ("int")], typ("unit"))),
+        function("clear", vec![pattern("n")], let_in(wildcard(), call("zeros", vec![var("n")]), unit())),
+      ],
+      &["clear"],
+    );
+    assert_eq!(errors, vec!["cannot resolve the size 'n of `zeros`, reached by the calls clear -> zeros".to_string()]);
+  }
+
+  #[test]
+  fn entry_points_must_be_functions() {
+    let (outline, errors) = specialise(vec![register("R", bits(8), None)], &["R"]);
+    assert_eq!(errors, vec!["entry point `R` is not a function".to_string()]);
+    assert_eq!(outline, Vec::<String>::new());
+  }
+}
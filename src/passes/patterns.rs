@@ -0,0 +1,862 @@
+/*!
+
+Exhaustiveness and redundancy checking of pattern matches.
+
+Every `match`, every function's clauses and each direction of every mapping is checked for arms that can never be
+reached because earlier arms match everything they do, and every `match` and function for values no arm matches. A
+mapping is allowed to be partial, which is what `_forwards_matches` and `_backwards_matches` are for, and an exception
+no arm of a `try` catches is simply thrown on, so those are checked only for redundancy. The functions derived from
+mappings (see `mappings`) are not checked again.
+
+The check is the usefulness algorithm of Maranget's "Warnings for pattern matching". Patterns are first reduced to
+constructors applied to subpatterns, and wildcards. Bitvectors are vectors of bits, one column per bit, so that the
+literals and concatenations of a decoder over 32-bit opcodes are compared bit by bit: `0b0000000 @ rs2 : bits(5) @ ...`
+is seven constant bits followed by five wildcards. Widths come from literals, type annotations and the types the type
+checker recorded, and the width of a single part of a concatenation whose width is otherwise unknown is whatever is left
+of the whole.
+
+Some patterns cannot be reduced, such as string appends, mapping applications within mapping patterns, or
+concatenations whose widths are unknown. These are approximated from the safe side: such an arm is taken to match
+nothing when deciding whether later arms are reachable or the match is exhaustive, and anything when deciding whether
+it is itself reachable. Arms with guards are treated the same way, as the guard may fail. So an unreachable arm
+reported is certainly unreachable, while a match reported as not exhaustive may in fact be exhaustive, just as Sail's
+own check would say. A non-exhaustive match is reported with an example of a value no arm matches.
+
+*/
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::ast_util::strip_function_clause;
+use crate::parser::location::{Located, SourceLocation};
+use crate::parser::visit::{walk_definitions, walk_expression, walk_function_definition, walk_mapping_definition, Visitor};
+use crate::passes::typecheck::{Type, Typing};
+
+/// How many steps checking one match may take before the check is abandoned, as it can take time exponential in the
+/// number of bits of the patterns.
+const STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum PatternError {
+  /// Some value is matched by no arm; `example` is one.
+  NonExhaustive { example: String },
+  /// An arm that can never be reached.
+  Redundant,
+}
+
+pub type LocatedPatternError = Located<PatternError>;
+
+impl PatternError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      PatternError::NonExhaustive { example } => {
+        write!(f, "pattern match is not exhaustive; for example, `{}` is not matched", example)
+      }
+
+      PatternError::Redundant => {
+        write!(f, "this pattern is unreachable, as earlier patterns match everything it does")
+      }
+    }
+  }
+}
+
+impl Debug for PatternError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for PatternError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for PatternError {}
+
+/// Checks every pattern match in `definitions`, which should already have been type checked, giving `typing`.
+pub fn check_patterns(definitions: &Definitions, typing: &Typing) -> Vec<LocatedPatternError> {
+  let mut checker = PatternChecker { typing, errors: Vec::new() };
+  walk_definitions(&mut checker, definitions);
+  checker.errors
+}
+
+// region Reduced patterns
+
+/// The constructor at the head of a reduced pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Head {
+  Unit,
+  Bool(bool),
+  Bit(bool),
+  Tuple(usize),
+  /// A union constructor, always applied to a single payload
+  Constructor(String),
+  /// An enum member
+  Member(String),
+  /// A vector of the given length, including a bitvector
+  Vector(usize),
+  Nil,
+  Cons,
+  /// A struct, with its fields in order of name
+  Struct(Vec<String>),
+  Integer(BigInteger),
+  String(String),
+  Real(String),
+}
+
+impl Head {
+  fn arity(&self) -> usize {
+    match self {
+      Head::Tuple(arity)
+      | Head::Vector(arity) => *arity,
+      Head::Constructor(_) => 1,
+      Head::Cons => 2,
+      Head::Struct(fields) => fields.len(),
+      _ => 0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reduced {
+  Wildcard,
+  Constructor(Head, Vec<Reduced>),
+  /// A pattern that cannot be reduced
+  Opaque,
+}
+
+impl Reduced {
+  fn constant(head: Head) -> Self {
+    Reduced::Constructor(head, Vec::new())
+  }
+
+  fn is_opaque(&self) -> bool {
+    match self {
+      Reduced::Wildcard => false,
+      Reduced::Constructor(_, arguments) => arguments.iter().any(Reduced::is_opaque),
+      Reduced::Opaque => true,
+    }
+  }
+
+  /// The pattern with every irreducible part taken to match anything.
+  fn widen(&self) -> Reduced {
+    match self {
+      Reduced::Constructor(head, arguments) => {
+        Reduced::Constructor(head.clone(), arguments.iter().map(Reduced::widen).collect())
+      }
+      _ => Reduced::Wildcard,
+    }
+  }
+}
+
+impl Display for Reduced {
+  /// Writes the pattern as Sail source, with wildcard bits of a bitvector written as zeros.
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let (head, arguments) = match self {
+      Reduced::Constructor(head, arguments) => (head, arguments),
+      _ => return write!(f, "_"),
+    };
+    let list = |f: &mut Formatter<'_>, arguments: &[Reduced]| -> std::fmt::Result {
+      for (index, argument) in arguments.iter().enumerate() {
+        if index > 0 {
+          write!(f, ", ")?;
+        }
+        write!(f, "{}", argument)?;
+      }
+      Ok(())
+    };
+    match head {
+      Head::Unit => write!(f, "()"),
+      Head::Bool(value) => write!(f, "{}", value),
+      Head::Bit(value) => write!(f, "{}", if *value { "bitone" } else { "bitzero" }),
+      Head::Tuple(_) => {
+        write!(f, "(")?;
+        list(f, arguments)?;
+        write!(f, ")")
+      }
+      Head::Constructor(name) => match &arguments[0] {
+        Reduced::Constructor(Head::Tuple(_), payload) => {
+          write!(f, "{}(", name)?;
+          list(f, payload)?;
+          write!(f, ")")
+        }
+        payload => write!(f, "{}({})", name, payload),
+      },
+      Head::Member(name) => write!(f, "{}", name),
+      Head::Vector(_) if arguments.iter().all(|argument| *argument == Reduced::Wildcard) => write!(f, "_"),
+      Head::Vector(_)
+        if arguments.iter().all(|argument| matches!(argument, Reduced::Wildcard | Reduced::Constructor(Head::Bit(_), _))) =>
+      {
+        write!(f, "0b")?;
+        for argument in arguments {
+          let one = matches!(argument, Reduced::Constructor(Head::Bit(true), _));
+          write!(f, "{}", if one { '1' } else { '0' })?;
+        }
+        Ok(())
+      }
+      Head::Vector(_) => {
+        write!(f, "[")?;
+        list(f, arguments)?;
+        write!(f, "]")
+      }
+      Head::Nil => write!(f, "[||]"),
+      Head::Cons => write!(f, "{} :: {}", arguments[0], arguments[1]),
+      Head::Struct(fields) => {
+        write!(f, "struct {{ ")?;
+        for (index, (field, argument)) in fields.iter().zip(arguments).enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{} = {}", field, argument)?;
+        }
+        write!(f, " }}")
+      }
+      Head::Integer(value) => write!(f, "{}", value),
+      Head::String(value) => write!(f, "{:?}", value),
+      Head::Real(value) => write!(f, "{}", value),
+    }
+  }
+}
+
+/// The bits of a bitvector literal, most significant first.
+fn literal_bits(literal: &Literal) -> Option<Vec<Reduced>> {
+  let (digits, radix, width) = match literal {
+    Literal::Binary(digits) => (digits.trim_start_matches("0b"), 2, 1),
+    Literal::Hexadecimal(digits) => (digits.trim_start_matches("0x"), 16, 4),
+    _ => return None,
+  };
+  let mut bits = Vec::new();
+  for digit in digits.chars().filter(|c| *c != '_') {
+    let value = digit.to_digit(radix)?;
+    for bit in (0..width).rev() {
+      bits.push(Reduced::constant(Head::Bit(value >> bit & 1 == 1)));
+    }
+  }
+  Some(bits)
+}
+
+fn literal(literal: &Literal) -> Reduced {
+  if let Some(bits) = literal_bits(literal) {
+    return Reduced::Constructor(Head::Vector(bits.len()), bits);
+  }
+  match literal {
+    Literal::Unit => Reduced::constant(Head::Unit),
+    Literal::Zero => Reduced::constant(Head::Bit(false)),
+    Literal::One => Reduced::constant(Head::Bit(true)),
+    Literal::True => Reduced::constant(Head::Bool(true)),
+    Literal::False => Reduced::constant(Head::Bool(false)),
+    Literal::Number(value) => Reduced::constant(Head::Integer(value.clone())),
+    Literal::String(value) => Reduced::constant(Head::String(value.clone())),
+    Literal::Real(value) => Reduced::constant(Head::Real(value.clone())),
+    _ => Reduced::Opaque,
+  }
+}
+
+/// The length of a subrange `high..low`, in either order.
+fn subrange_width(high: &BigInteger, low: &BigInteger) -> Option<usize> {
+  let difference = high.try_sub(low).ok()?.try_abs().ok()?;
+  usize::try_from(difference.to_i64()?).ok().map(|width| width + 1)
+}
+
+/// The width `bits(n)` gives, if `n` is a literal.
+fn annotated_width(abstract_type: &LocatedAbstractType) -> Option<usize> {
+  match &abstract_type.value {
+    AbstractType::TypeConstructorApplication(name, arguments) if name.name() == "bits" && arguments.len() == 1 => {
+      match &arguments[0].value {
+        AbstractType::Literal(literal) => match &literal.value {
+          Literal::Number(width) => usize::try_from(width.to_i64()?).ok(),
+          _ => None,
+        },
+        _ => None,
+      }
+    }
+    AbstractType::Parenthesized(inner) => annotated_width(inner),
+    _ => None,
+  }
+}
+
+/// The widths of the parts of a bitvector concatenation, where at most one part of unknown width takes whatever is
+/// left of the `total`.
+fn part_widths(widths: Vec<Option<usize>>, total: Option<usize>) -> Option<Vec<usize>> {
+  let known: usize = widths.iter().flatten().sum();
+  let remainder = match (widths.iter().filter(|width| width.is_none()).count(), total) {
+    (0, _) => 0,
+    (1, Some(total)) if total >= known => total - known,
+    _ => return None,
+  };
+  Some(widths.into_iter().map(|width| width.unwrap_or(remainder)).collect())
+}
+
+/// The concatenation of reduced parts, or `Opaque` if some part is not a bitvector of its width.
+fn concatenate(parts: impl Iterator<Item = (Reduced, usize)>) -> Reduced {
+  let mut bits = Vec::new();
+  for (part, width) in parts {
+    match part_bits(part, width) {
+      Some(part) => bits.extend(part),
+      None => return Reduced::Opaque,
+    }
+  }
+  Reduced::Constructor(Head::Vector(bits.len()), bits)
+}
+
+/// The bits of a reduced part of a bitvector concatenation of the given width.
+fn part_bits(reduced: Reduced, width: usize) -> Option<Vec<Reduced>> {
+  match reduced {
+    Reduced::Wildcard => Some(vec![Reduced::Wildcard; width]),
+    Reduced::Constructor(Head::Vector(length), bits) if length == width => Some(bits),
+    _ => None,
+  }
+}
+
+// endregion
+
+struct PatternChecker<'a> {
+  typing: &'a Typing,
+  errors: Vec<LocatedPatternError>,
+}
+
+/// One arm of a match: its reduced pattern, where it is, and whether it has a guard.
+struct Arm {
+  pattern : Reduced,
+  location: SourceLocation,
+  guarded : bool,
+}
+
+/// The search gave up after `STEP_LIMIT` steps.
+struct GaveUp;
+
+impl PatternChecker<'_> {
+  // region Reduction
+
+  /// The width of a bitvector pattern, if it is known.
+  fn width(&self, location: &SourceLocation) -> Option<usize> {
+    match self.typing.types.get(location)? {
+      Type::Bitvector(length) => usize::try_from(length.as_constant()?.to_i64()?).ok(),
+      _ => None,
+    }
+  }
+
+  /// Whether `name` used as a pattern is an enum member or a union constructor rather than a new variable.
+  fn identifier(&self, name: &str) -> Reduced {
+    let environment = &self.typing.environment;
+    if environment.enum_members.contains_key(name) {
+      Reduced::constant(Head::Member(name.to_string()))
+    } else if environment.constructors.contains_key(name) {
+      Reduced::Constructor(Head::Constructor(name.to_string()), vec![Reduced::Wildcard])
+    } else {
+      Reduced::Wildcard
+    }
+  }
+
+  /// The payload of a constructor pattern with the given arguments.
+  fn payload(arguments: Vec<Reduced>) -> Reduced {
+    match arguments.len() {
+      0 => Reduced::Wildcard,
+      1 => arguments.into_iter().next().unwrap(),
+      arity => Reduced::Constructor(Head::Tuple(arity), arguments),
+    }
+  }
+
+  /// The elements of a list pattern, as conses ending in the empty list.
+  fn list(elements: Vec<Reduced>) -> Reduced {
+    elements.into_iter().rev().fold(Reduced::constant(Head::Nil), |tail, head| {
+      Reduced::Constructor(Head::Cons, vec![head, tail])
+    })
+  }
+
+  /// The fields of a struct pattern in order of name, with those not mentioned matching anything. Fields are those of
+  /// the struct's type, when it is known.
+  fn fields(&self, location: &SourceLocation, mut given: Vec<(String, Reduced)>) -> Reduced {
+    let mut names: Vec<String> = match self.typing.types.get(location) {
+      Some(Type::Application(name, _)) => match self.typing.environment.records.get(name) {
+        Some(record) => record.fields.iter().map(|(field, _)| field.clone()).collect(),
+        None => given.iter().map(|(field, _)| field.clone()).collect(),
+      },
+      _ => given.iter().map(|(field, _)| field.clone()).collect(),
+    };
+    names.sort();
+    names.dedup();
+    let arguments = names
+        .iter()
+        .map(|name| match given.iter().position(|(field, _)| field == name) {
+          Some(index) => given.swap_remove(index).1,
+          None => Reduced::Wildcard,
+        })
+        .collect();
+    Reduced::Constructor(Head::Struct(names), arguments)
+  }
+
+  fn pattern(&self, pattern: &LocatedPattern) -> Reduced {
+    self.pattern_of_width(pattern, self.width(&pattern.location))
+  }
+
+  /// Reduces a pattern, which if it is a bitvector is `width` bits wide.
+  fn pattern_of_width(&self, pattern: &LocatedPattern, width: Option<usize>) -> Reduced {
+    let width = width.or_else(|| self.width(&pattern.location));
+    match &pattern.value {
+      Pattern::Literal(value) => literal(value),
+      Pattern::Wildcard => Reduced::Wildcard,
+      Pattern::Typed(abstract_type, inner) => self.pattern_of_width(inner, width.or_else(|| annotated_width(abstract_type))),
+      Pattern::Identifier(identifier) => self.identifier(identifier.name()),
+      Pattern::Variable(inner, _)
+      | Pattern::Attribute(_, _, inner) => self.pattern_of_width(inner, width),
+      Pattern::Constructor(name, arguments) => {
+        let arguments = arguments.iter().map(|argument| self.pattern(argument)).collect();
+        Reduced::Constructor(Head::Constructor(name.name().to_string()), vec![Self::payload(arguments)])
+      }
+      Pattern::Vector(elements) => {
+        Reduced::Constructor(Head::Vector(elements.len()), elements.iter().map(|element| self.pattern(element)).collect())
+      }
+      Pattern::VectorConcat(parts) => {
+        let widths = parts.iter().map(|part| self.pattern_width(part)).collect();
+        match part_widths(widths, width) {
+          Some(widths) => concatenate(
+            parts.iter().zip(widths).map(|(part, width)| (self.pattern_of_width(part, Some(width)), width)),
+          ),
+          None => Reduced::Opaque,
+        }
+      }
+      Pattern::VectorSubrange(_, high, low) => match subrange_width(high, low) {
+        Some(width) => Reduced::Constructor(Head::Vector(width), vec![Reduced::Wildcard; width]),
+        None => Reduced::Opaque,
+      },
+      Pattern::Tuple(elements) => {
+        Reduced::Constructor(Head::Tuple(elements.len()), elements.iter().map(|element| self.pattern(element)).collect())
+      }
+      Pattern::List(elements) => Self::list(elements.iter().map(|element| self.pattern(element)).collect()),
+      Pattern::Cons(head, tail) => Reduced::Constructor(Head::Cons, vec![self.pattern(head), self.pattern(tail)]),
+      Pattern::StringAppend(_) => Reduced::Opaque,
+      Pattern::Struct(fields) => {
+        let given = fields
+            .iter()
+            .filter_map(|field| match &field.value {
+              FieldPattern::Field(name, pattern) => Some((name.name().to_string(), self.pattern(pattern))),
+              FieldPattern::Wildcard => None,
+            })
+            .collect();
+        self.fields(&pattern.location, given)
+      }
+    }
+  }
+
+  /// The width of a part of a bitvector concatenation, if it can be told from the part alone.
+  fn pattern_width(&self, pattern: &LocatedPattern) -> Option<usize> {
+    if let Some(width) = self.width(&pattern.location) {
+      return Some(width);
+    }
+    match &pattern.value {
+      Pattern::Literal(value) => literal_bits(&value.value).map(|bits| bits.len()),
+      Pattern::Typed(abstract_type, inner) => annotated_width(abstract_type).or_else(|| self.pattern_width(inner)),
+      Pattern::Variable(inner, _)
+      | Pattern::Attribute(_, _, inner) => self.pattern_width(inner),
+      Pattern::Vector(elements) => Some(elements.len()),
+      Pattern::VectorSubrange(_, high, low) => subrange_width(high, low),
+      Pattern::VectorConcat(parts) => parts.iter().map(|part| self.pattern_width(part)).sum(),
+      _ => None,
+    }
+  }
+
+  fn mapping_pattern(&self, pattern: &LocatedMappingPattern) -> Reduced {
+    self.mapping_pattern_of_width(pattern, self.width(&pattern.location))
+  }
+
+  fn mapping_pattern_of_width(&self, pattern: &LocatedMappingPattern, width: Option<usize>) -> Reduced {
+    let width = width.or_else(|| self.width(&pattern.location));
+    match &pattern.value {
+      MappingPattern::Literal(value) => literal(value),
+      MappingPattern::Identifier(identifier) => self.identifier(identifier.name()),
+      MappingPattern::Application(name, arguments) => {
+        // Anything else applied is a mapping, which may match anything or nothing.
+        if !self.typing.environment.constructors.contains_key(name.name()) {
+          return Reduced::Opaque;
+        }
+        let arguments = arguments.iter().map(|argument| self.mapping_pattern(argument)).collect();
+        Reduced::Constructor(Head::Constructor(name.name().to_string()), vec![Self::payload(arguments)])
+      }
+      MappingPattern::Vector(elements) => Reduced::Constructor(
+        Head::Vector(elements.len()),
+        elements.iter().map(|element| self.mapping_pattern(element)).collect(),
+      ),
+      MappingPattern::VectorConcat(parts) => {
+        let widths = parts.iter().map(|part| self.mapping_pattern_width(part)).collect();
+        match part_widths(widths, width) {
+          Some(widths) => concatenate(
+            parts.iter().zip(widths).map(|(part, width)| (self.mapping_pattern_of_width(part, Some(width)), width)),
+          ),
+          None => Reduced::Opaque,
+        }
+      }
+      MappingPattern::VectorSubrange(_, high, low) => match subrange_width(high, low) {
+        Some(width) => Reduced::Constructor(Head::Vector(width), vec![Reduced::Wildcard; width]),
+        None => Reduced::Opaque,
+      },
+      MappingPattern::Tuple(elements) => Reduced::Constructor(
+        Head::Tuple(elements.len()),
+        elements.iter().map(|element| self.mapping_pattern(element)).collect(),
+      ),
+      MappingPattern::List(elements) => Self::list(elements.iter().map(|element| self.mapping_pattern(element)).collect()),
+      MappingPattern::Cons(head, tail) => {
+        Reduced::Constructor(Head::Cons, vec![self.mapping_pattern(head), self.mapping_pattern(tail)])
+      }
+      MappingPattern::StringAppend(_) => Reduced::Opaque,
+      MappingPattern::Typed(inner, abstract_type) => {
+        self.mapping_pattern_of_width(inner, width.or_else(|| annotated_width(abstract_type)))
+      }
+      MappingPattern::As(inner, _) => self.mapping_pattern_of_width(inner, width),
+      MappingPattern::Struct(fields) => {
+        let given = fields.iter().map(|(name, pattern)| (name.name().to_string(), self.mapping_pattern(pattern))).collect();
+        self.fields(&pattern.location, given)
+      }
+    }
+  }
+
+  fn mapping_pattern_width(&self, pattern: &LocatedMappingPattern) -> Option<usize> {
+    if let Some(width) = self.width(&pattern.location) {
+      return Some(width);
+    }
+    match &pattern.value {
+      MappingPattern::Literal(value) => literal_bits(&value.value).map(|bits| bits.len()),
+      MappingPattern::Typed(inner, abstract_type) => {
+        annotated_width(abstract_type).or_else(|| self.mapping_pattern_width(inner))
+      }
+      MappingPattern::As(inner, _) => self.mapping_pattern_width(inner),
+      MappingPattern::Vector(elements) => Some(elements.len()),
+      MappingPattern::VectorSubrange(_, high, low) => subrange_width(high, low),
+      MappingPattern::VectorConcat(parts) => parts.iter().map(|part| self.mapping_pattern_width(part)).sum(),
+      _ => None,
+    }
+  }
+
+  fn arm(&self, arm: &LocatedPatternExpression) -> Arm {
+    let (pattern, guarded) = match &arm.value {
+      PatternExpression::Pattern(pattern, _) => (pattern, false),
+      PatternExpression::PatternWhen(pattern, _, _) => (pattern, true),
+    };
+    Arm { pattern: self.pattern(pattern), location: pattern.location.clone(), guarded }
+  }
+
+  fn mapping_arm(&self, arm: &LocatedMappingPatternExpression) -> Arm {
+    let (pattern, guarded) = match &arm.value {
+      MappingPatternExpression::Pattern(pattern) => (pattern, false),
+      MappingPatternExpression::PatternWhen(pattern, _) => (pattern, true),
+    };
+    Arm { pattern: self.mapping_pattern(pattern), location: pattern.location.clone(), guarded }
+  }
+
+  // endregion
+
+  // region Signatures
+
+  /// Every constructor of the type of the column whose heads are `heads`, if all of them appear.
+  fn complete(&self, heads: &[Head]) -> Option<Vec<Head>> {
+    let environment = &self.typing.environment;
+    let all: Vec<Head> = match heads.first()? {
+      Head::Unit => vec![Head::Unit],
+      Head::Bool(_) => vec![Head::Bool(false), Head::Bool(true)],
+      Head::Bit(_) => vec![Head::Bit(false), Head::Bit(true)],
+      Head::Nil
+      | Head::Cons => vec![Head::Nil, Head::Cons],
+      Head::Tuple(_)
+      | Head::Vector(_)
+      | Head::Struct(_) => heads.to_vec(),
+      Head::Constructor(name) => {
+        let variant = self.variant_constructors(name)?;
+        variant.iter().map(|constructor| Head::Constructor(constructor.clone())).collect()
+      }
+      Head::Member(name) => {
+        let members = environment.enums.get(environment.enum_members.get(name)?)?;
+        members.iter().map(|member| Head::Member(member.clone())).collect()
+      }
+      Head::Integer(_)
+      | Head::String(_)
+      | Head::Real(_) => return None,
+    };
+    match all.iter().all(|head| heads.contains(head)) {
+      true => Some(all),
+      false => None,
+    }
+  }
+
+  /// All the constructors of the union `constructor` belongs to.
+  fn variant_constructors(&self, constructor: &str) -> Option<&Vec<String>> {
+    let environment = &self.typing.environment;
+    match &environment.constructors.get(constructor)?.result {
+      Type::Application(union, _) => Some(&environment.variants.get(union)?.constructors),
+      _ => None,
+    }
+  }
+
+  /// A pattern matching some value of the column's type that none of `heads` does, given that they are not complete.
+  fn missing(&self, heads: &[Head]) -> Reduced {
+    let environment = &self.typing.environment;
+    let absent = |candidates: Vec<Head>| {
+      candidates.into_iter().find(|head| !heads.contains(head)).map(|head| {
+        let arguments = vec![Reduced::Wildcard; head.arity()];
+        Reduced::Constructor(head, arguments)
+      })
+    };
+    let example = match heads.first() {
+      Some(Head::Bool(_)) => absent(vec![Head::Bool(false), Head::Bool(true)]),
+      Some(Head::Bit(_)) => absent(vec![Head::Bit(false), Head::Bit(true)]),
+      Some(Head::Nil | Head::Cons) => absent(vec![Head::Nil, Head::Cons]),
+      Some(Head::Constructor(name)) => self
+          .variant_constructors(name)
+          .and_then(|constructors| absent(constructors.iter().map(|name| Head::Constructor(name.clone())).collect())),
+      Some(Head::Member(name)) => environment
+          .enum_members
+          .get(name)
+          .and_then(|name| environment.enums.get(name))
+          .and_then(|members| absent(members.iter().map(|name| Head::Member(name.clone())).collect())),
+      Some(Head::Integer(_)) => {
+        let used: HashSet<&Head> = heads.iter().collect();
+        (0..).map(|value| Head::Integer(BigInteger::from_i64(value))).find(|head| !used.contains(head)).map(Reduced::constant)
+      }
+      _ => None,
+    };
+    example.unwrap_or(Reduced::Wildcard)
+  }
+
+  // endregion
+
+  // region Usefulness
+
+  /// The rows of `matrix` that can match a value with head `head`, with the head's arguments in place of the first
+  /// column.
+  fn specialize(matrix: &[Vec<Reduced>], head: &Head) -> Vec<Vec<Reduced>> {
+    matrix.iter().filter_map(|row| Self::specialize_row(row, head)).collect()
+  }
+
+  fn specialize_row(row: &[Reduced], head: &Head) -> Option<Vec<Reduced>> {
+    let mut specialized = match &row[0] {
+      Reduced::Constructor(found, arguments) if found == head => arguments.clone(),
+      Reduced::Constructor(..) => return None,
+      _ => vec![Reduced::Wildcard; head.arity()],
+    };
+    specialized.extend_from_slice(&row[1..]);
+    Some(specialized)
+  }
+
+  /// Whether some value matched by `row` is matched by no row of `matrix`, and if so, one such value as a pattern,
+  /// with a wildcard wherever any value will do.
+  fn useful(&self, matrix: &[Vec<Reduced>], row: &[Reduced], steps: &mut usize) -> Result<Option<Vec<Reduced>>, GaveUp> {
+    *steps += 1;
+    if *steps > STEP_LIMIT {
+      return Err(GaveUp);
+    }
+    if row.is_empty() {
+      return Ok(matrix.is_empty().then(Vec::new));
+    }
+
+    let rebuild = |head: &Head, mut witness: Vec<Reduced>| {
+      let rest = witness.split_off(head.arity());
+      let mut rebuilt = vec![Reduced::Constructor(head.clone(), witness)];
+      rebuilt.extend(rest);
+      rebuilt
+    };
+
+    if let Reduced::Constructor(head, _) = &row[0] {
+      let specialized_row = Self::specialize_row(row, head).expect("a row matches its own head");
+      let witness = self.useful(&Self::specialize(matrix, head), &specialized_row, steps)?;
+      return Ok(witness.map(|witness| rebuild(head, witness)));
+    }
+
+    let mut heads: Vec<Head> = Vec::new();
+    for matrix_row in matrix {
+      if let Reduced::Constructor(head, _) = &matrix_row[0] {
+        if !heads.contains(head) {
+          heads.push(head.clone());
+        }
+      }
+    }
+
+    if let Some(all) = self.complete(&heads) {
+      for head in all {
+        let specialized_row = Self::specialize_row(row, &head).expect("a wildcard matches every head");
+        if let Some(witness) = self.useful(&Self::specialize(matrix, &head), &specialized_row, steps)? {
+          return Ok(Some(rebuild(&head, witness)));
+        }
+      }
+      return Ok(None);
+    }
+
+    // Some value of the column's type has no head given, so only the rows matching anything there can match it.
+    let default: Vec<Vec<Reduced>> = matrix
+        .iter()
+        .filter(|matrix_row| !matches!(matrix_row[0], Reduced::Constructor(..)))
+        .map(|matrix_row| matrix_row[1..].to_vec())
+        .collect();
+    let witness = self.useful(&default, &row[1..], steps)?;
+    Ok(witness.map(|witness| {
+      let mut rebuilt = vec![self.missing(&heads)];
+      rebuilt.extend(witness);
+      rebuilt
+    }))
+  }
+
+  /// Reports the unreachable arms of a match, and, if `exhaustive` is given, an example of a value matched by no arm
+  /// at that location.
+  fn check(&mut self, arms: Vec<Arm>, exhaustive: Option<&SourceLocation>) {
+    let mut matrix: Vec<Vec<Reduced>> = Vec::new();
+    let mut steps = 0;
+    let mut errors = Vec::new();
+    for arm in arms {
+      match self.useful(&matrix, &[arm.pattern.widen()], &mut steps) {
+        Ok(Some(_)) => {}
+        Ok(None) => errors.push(Located { location: arm.location, value: PatternError::Redundant }),
+        Err(GaveUp) => return,
+      }
+      if !arm.guarded && !arm.pattern.is_opaque() {
+        matrix.push(vec![arm.pattern]);
+      }
+    }
+
+    if let Some(location) = exhaustive {
+      match self.useful(&matrix, &[Reduced::Wildcard], &mut steps) {
+        Ok(Some(witness)) => errors.push(Located {
+          location: location.clone(),
+          value   : PatternError::NonExhaustive { example: witness[0].to_string() },
+        }),
+        Ok(None) => {}
+        Err(GaveUp) => return,
+      }
+    }
+    self.errors.extend(errors);
+  }
+
+  // endregion
+}
+
+impl Visitor for PatternChecker<'_> {
+  fn visit_function_definition(&mut self, definition: &LocatedFunctionDefinition) {
+    let FunctionDefinition::Function(_, _, _, clauses) = &definition.value;
+    if let Some((name, _)) = clauses.first().map(strip_function_clause) {
+      // Functions derived from a mapping repeat its clauses, which have been checked already.
+      if let SourceLocation::Generated(_) = name.location {
+        return;
+      }
+      let arms = clauses.iter().map(|clause| self.arm(strip_function_clause(clause).1)).collect();
+      self.check(arms, Some(&name.location));
+    }
+    walk_function_definition(self, definition)
+  }
+
+  fn visit_mapping_definition(&mut self, definition: &LocatedMappingDefinition) {
+    let MappingDefinition::Mapping(_, _, clauses) = &definition.value;
+    let mut forwards = Vec::new();
+    let mut backwards = Vec::new();
+    for clause in clauses {
+      let mut clause = clause;
+      while let MappingClause::Attribute(_, _, inner) | MappingClause::Documentation(_, inner) = &clause.value {
+        clause = inner;
+      }
+      match &clause.value {
+        MappingClause::Bidirectional(left, right) => {
+          forwards.push(self.mapping_arm(left));
+          backwards.push(self.mapping_arm(right));
+        }
+        MappingClause::ForwardsDeprecated(left, _) => forwards.push(self.mapping_arm(left)),
+        MappingClause::Forwards(arm) => forwards.push(self.arm(arm)),
+        MappingClause::Backwards(arm) => backwards.push(self.arm(arm)),
+        MappingClause::Attribute(..)
+        | MappingClause::Documentation(..) => unreachable!(),
+      }
+    }
+    self.check(forwards, None);
+    self.check(backwards, None);
+    walk_mapping_definition(self, definition)
+  }
+
+  fn visit_expression(&mut self, expression: &LocatedExpression) {
+    match &expression.value {
+      Expression::Match(_, arms) => {
+        let arms = arms.iter().map(|arm| self.arm(arm)).collect();
+        self.check(arms, Some(&expression.location));
+      }
+      Expression::Try(_, arms) => {
+        let arms = arms.iter().map(|arm| self.arm(arm)).collect();
+        self.check(arms, None);
+      }
+      _ => {}
+    }
+    walk_expression(self, expression)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+
+  /// The errors in `program`, which must type check, as `(message, location)`.
+  fn check(program: Vec<LocatedDefinition>) -> Vec<(String, SourceLocation)> {
+    let definitions = definitions(program);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty(), "{:?}", errors);
+    check_patterns(&definitions, &typing).into_iter().map(|error| (error.value.to_string(), error.location)).collect()
+  }
+
+  fn matcher(argument: LocatedAbstractType, arms: Vec<LocatedPatternExpression>) -> Vec<LocatedDefinition> {
+    vec![
+      val("f", function_type(vec![argument], typ("int"))),
+      function("f", vec![pattern("x")], matching(var("x"), arms)),
+    ]
+  }
+
+  fn messages(errors: Vec<(String, SourceLocation)>) -> Vec<String> {
+    errors.into_iter().map(|(message, _)| message).collect()
+  }
+
+  #[test]
+  fn complete_matches_pass() {
+    let booleans = vec![arm(literal_pattern(Literal::True), number(1)), arm(literal_pattern(Literal::False), number(0))];
+    assert!(check(matcher(typ("bool"), booleans)).is_empty());
+
+    let mut program = vec![union("U", vec![("A", typ("int")), ("B", typ("bool"))])];
+    program.extend(matcher(typ("U"), vec![
+      arm(constructor_pattern("A", vec![pattern("n")]), var("n")),
+      arm(constructor_pattern("B", vec![wildcard()]), number(0)),
+    ]));
+    assert!(check(program).is_empty());
+  }
+
+  #[test]
+  fn missing_values_are_given_as_examples() {
+    let mut program = vec![union("U", vec![("A", typ("int")), ("B", typ("bool"))])];
+    program.extend(matcher(typ("U"), vec![arm(constructor_pattern("A", vec![wildcard()]), number(1))]));
+    assert_eq!(messages(check(program)), vec!["pattern match is not exhaustive; for example, `B(_)` is not matched".to_string()]);
+
+    let guarded = vec![
+      guarded_arm(literal_pattern(Literal::True), boolean(true), number(1)),
+      arm(literal_pattern(Literal::False), number(0)),
+    ];
+    assert_eq!(
+      messages(check(matcher(typ("bool"), guarded))),
+      vec!["pattern match is not exhaustive; for example, `true` is not matched".to_string()],
+    );
+  }
+
+  #[test]
+  fn bitvector_literals_are_compared_bit_by_bit() {
+    let nibbles = (0..16).map(|n| arm(literal_pattern(Literal::Hexadecimal(format!("0x{:X}", n))), number(n))).collect();
+    assert!(check(matcher(bits(4), nibbles)).is_empty());
+
+    let all_but_one = (0..15).map(|n| arm(literal_pattern(Literal::Hexadecimal(format!("0x{:X}", n))), number(n))).collect();
+    assert_eq!(
+      messages(check(matcher(bits(4), all_but_one))),
+      vec!["pattern match is not exhaustive; for example, `0b1111` is not matched".to_string()],
+    );
+  }
+
+  #[test]
+  fn arms_after_a_catch_all_are_unreachable() {
+    let unreachable = number_pattern(0);
+    let location = unreachable.location.clone();
+    let errors = check(matcher(typ("int"), vec![arm(pattern("n"), var("n")), arm(unreachable, number(0))]));
+    assert_eq!(errors, vec![("this pattern is unreachable, as earlier patterns match everything it does".to_string(), location)]);
+  }
+}
@@ -0,0 +1,1119 @@
+/*!
+
+Name resolution.
+
+The resolver builds a `SymbolTable` for a whole program and binds every use of a name to a `DefinitionId`. It runs in
+two phases. First every top-level declaration is entered into the global table, so that definitions may refer to each
+other regardless of order. Then every definition body is walked with a stack of lexical scopes for local variables.
+
+A global symbol collects all of the declarations of one name: a `val` specification and the function implementing it
+are one symbol with two declarations, as are the several `overload` declarations that may extend an overloaded name.
+Any other combination of declarations for the same name is reported as a duplicate, with both locations.
+
+Sail does not distinguish variables from nullary union constructors and enum members syntactically, so a bare
+identifier in a pattern refers to a constructor or enum member if one of that name is in scope and binds a new local
+variable otherwise, exactly as in Sail.
+
+Ticked type variables are not resolved here; they are the kind checker's business. Record and bitfield field names are
+also left alone, since resolving them requires types.
+
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::{strip_definition, strip_function_clause, type_definition_name, union_constructor, Namespace};
+use crate::parser::location::{Located, SourceLocation};
+
+/// Types the type checker provides without any declaration.
+pub const BUILTIN_TYPES: &[&str] = &[
+  "atom", "atom_bool", "bit", "bits", "bitvector", "bool", "implicit", "int", "itself", "list", "nat", "range", "real",
+  "register", "string", "unit", "vector",
+];
+
+/// Uniquely identifies a symbol, global or local, within a `SymbolTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefinitionId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeclarationKind {
+  ValueSpec,
+  Function,
+  /// A top-level `let`
+  Value,
+  Register,
+  Mapping,
+  Overload,
+  Outcome,
+  Constructor,
+  EnumMember,
+  Type,
+  BuiltinType,
+  /// A variable bound by a pattern, `var`, `for` or implicit declaration by assignment
+  Local,
+}
+
+impl Display for DeclarationKind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let description = match self {
+      DeclarationKind::ValueSpec   => "value specification",
+      DeclarationKind::Function    => "function",
+      DeclarationKind::Value       => "value",
+      DeclarationKind::Register    => "register",
+      DeclarationKind::Mapping     => "mapping",
+      DeclarationKind::Overload    => "overload",
+      DeclarationKind::Outcome     => "outcome",
+      DeclarationKind::Constructor => "union constructor",
+      DeclarationKind::EnumMember  => "enum member",
+      DeclarationKind::Type        => "type",
+      DeclarationKind::BuiltinType => "builtin type",
+      DeclarationKind::Local       => "local variable",
+    };
+    write!(f, "{}", description)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+  pub kind    : DeclarationKind,
+  pub location: SourceLocation,
+  pub private : bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+  pub name        : String,
+  pub namespace   : Namespace,
+  pub declarations: Vec<Declaration>,
+}
+
+impl Symbol {
+  /// Where the symbol was first declared.
+  pub fn location(&self) -> &SourceLocation {
+    &self.declarations[0].location
+  }
+
+  pub fn has_kind(&self, kind: DeclarationKind) -> bool {
+    self.declarations.iter().any(|declaration| declaration.kind == kind)
+  }
+
+  pub fn is_local(&self) -> bool {
+    self.has_kind(DeclarationKind::Local)
+  }
+}
+
+/// A use of a name, bound to the symbol it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+  pub name    : String,
+  pub location: SourceLocation,
+  pub id      : DefinitionId,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+  pub symbols   : Vec<Symbol>,
+  /// Every resolved use site, in traversal order
+  pub references: Vec<Reference>,
+  /// The names each overloaded symbol stands for
+  pub overloads : HashMap<DefinitionId, Vec<DefinitionId>>,
+  /// The union or enum type each constructor or enum member belongs to
+  pub parents   : HashMap<DefinitionId, DefinitionId>,
+  globals       : HashMap<(Namespace, String), DefinitionId>,
+  by_location   : HashMap<SourceLocation, DefinitionId>,
+}
+
+impl SymbolTable {
+  pub fn symbol(&self, id: DefinitionId) -> &Symbol {
+    &self.symbols[id.0]
+  }
+
+  /// Looks up a global name.
+  pub fn lookup(&self, namespace: Namespace, name: &str) -> Option<DefinitionId> {
+    self.globals.get(&(namespace, name.to_string())).copied()
+  }
+
+  /// The symbol used or declared at `location`, for go-to-definition. Generated code with unknown locations cannot be
+  /// looked up this way.
+  pub fn resolve_location(&self, location: &SourceLocation) -> Option<DefinitionId> {
+    match location {
+      SourceLocation::Unknown => None,
+      _ => self.by_location.get(location).copied(),
+    }
+  }
+
+  /// Every global symbol with its id.
+  pub fn globals(&self) -> impl Iterator<Item = (DefinitionId, &Symbol)> {
+    self.globals.values().map(move |&id| (id, self.symbol(id)))
+  }
+
+  fn new_symbol(&mut self, name: &str, namespace: Namespace, declaration: Declaration) -> DefinitionId {
+    let id = DefinitionId(self.symbols.len());
+    if declaration.location != SourceLocation::Unknown {
+      self.by_location.insert(declaration.location.clone(), id);
+    }
+    self.symbols.push(Symbol { name: name.to_string(), namespace, declarations: vec![declaration] });
+    id
+  }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum ResolveError {
+  Undefined { name: String, namespace: Namespace },
+  /// A name is declared twice. Carries the kind and location of the earlier declaration.
+  Duplicate { name: String, kind: DeclarationKind, previous_kind: DeclarationKind, previous: SourceLocation },
+}
+
+pub type LocatedResolveError = Located<ResolveError>;
+
+impl ResolveError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ResolveError::Undefined { name, namespace: Namespace::Type } => {
+        write!(f, "undefined type `{}`", name)
+      }
+
+      ResolveError::Undefined { name, namespace: Namespace::Value } => {
+        write!(f, "undefined identifier `{}`", name)
+      }
+
+      ResolveError::Duplicate { name, kind, previous_kind, .. } => {
+        write!(f, "{} `{}` conflicts with an earlier {} of the same name", kind, name, previous_kind)
+      }
+    }
+  }
+}
+
+impl Debug for ResolveError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for ResolveError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for ResolveError {}
+
+/// Resolves every name in `definitions`, which should already have had scattered definitions collected.
+pub fn resolve_names(definitions: &Definitions) -> (SymbolTable, Vec<LocatedResolveError>) {
+  let mut table = SymbolTable::default();
+  for name in BUILTIN_TYPES {
+    let declaration = Declaration { kind: DeclarationKind::BuiltinType, location: SourceLocation::Unknown, private: false };
+    let id = table.new_symbol(name, Namespace::Type, declaration);
+    table.globals.insert((Namespace::Type, name.to_string()), id);
+  }
+
+  let errors = resolve_more_names(&mut table, definitions);
+  (table, errors)
+}
+
+/// Resolves the names in more definitions, which may use those in `table` and are added to it.
+pub fn resolve_more_names(table: &mut SymbolTable, definitions: &Definitions) -> Vec<LocatedResolveError> {
+  let mut resolver = Resolver { table: std::mem::take(table), scopes: Vec::new(), errors: Vec::new() };
+
+  let all = || definitions.0.iter().flat_map(|(_, file)| file.iter());
+  for definition in all() {
+    resolver.declare_definition(definition);
+  }
+  for definition in all() {
+    resolver.definition(definition);
+  }
+
+  *table = resolver.table;
+  resolver.errors
+}
+
+struct Resolver {
+  table : SymbolTable,
+  scopes: Vec<HashMap<String, DefinitionId>>,
+  errors: Vec<LocatedResolveError>,
+}
+
+/// Whether a name may be declared with `new` when it already has a declaration of kind `existing`.
+fn compatible(existing: DeclarationKind, new: DeclarationKind) -> bool {
+  use DeclarationKind::*;
+  matches!(
+    (existing, new),
+    (ValueSpec, Function) | (Function, ValueSpec)
+    | (ValueSpec, Mapping) | (Mapping, ValueSpec)
+    | (Overload, Overload)
+  )
+}
+
+// region Global declarations
+
+impl Resolver {
+  fn declare(&mut self, namespace: Namespace, identifier: &LocatedIdentifier, kind: DeclarationKind, private: bool) -> DefinitionId {
+    let name = identifier.name();
+    let declaration = Declaration { kind, location: identifier.location.clone(), private };
+
+    let Some(&id) = self.table.globals.get(&(namespace, name.to_string())) else {
+      let id = self.table.new_symbol(name, namespace, declaration);
+      self.table.globals.insert((namespace, name.to_string()), id);
+      return id;
+    };
+
+    let symbol = &self.table.symbols[id.0];
+    match symbol.declarations.iter().find(|existing| !compatible(existing.kind, kind) || existing.kind == kind && kind != DeclarationKind::Overload) {
+      Some(existing) => {
+        let error = ResolveError::Duplicate {
+          name         : name.to_string(),
+          kind,
+          previous_kind: existing.kind,
+          previous     : existing.location.clone(),
+        };
+        self.errors.push(Located { location: identifier.location.clone(), value: error });
+      }
+      None => {
+        if identifier.location != SourceLocation::Unknown {
+          self.table.by_location.insert(identifier.location.clone(), id);
+        }
+        self.table.symbols[id.0].declarations.push(declaration);
+      }
+    }
+    id
+  }
+
+  fn declare_definition(&mut self, definition: &LocatedDefinition) {
+    let (definition, private) = strip_definition(definition);
+
+    match &definition.value {
+      Definition::TypeDefinition(type_definition) => {
+        let type_id = self.declare(Namespace::Type, type_definition_name(type_definition), DeclarationKind::Type, private);
+        match &type_definition.value {
+          TypeDefinition::Variant(_, _, unions) => {
+            for union in unions {
+              let constructor_private = private || matches!(union.value, TypeUnion::Private(_));
+              let id = self.declare(Namespace::Value, union_constructor(union), DeclarationKind::Constructor, constructor_private);
+              self.table.parents.insert(id, type_id);
+            }
+          }
+          TypeDefinition::Enum(_, _, members) => {
+            for (member, _) in members {
+              let id = self.declare(Namespace::Value, member, DeclarationKind::EnumMember, private);
+              self.table.parents.insert(id, type_id);
+            }
+          }
+          _ => {}
+        }
+      }
+      Definition::FunctionDefinition(function) => self.declare_function(function, private),
+      Definition::MappingDefinition(mapping) => {
+        let MappingDefinition::Mapping(name, ..) = &mapping.value;
+        self.declare(Namespace::Value, name, DeclarationKind::Mapping, private);
+      }
+      Definition::ValueDefinition(binding) => {
+        let LetBinding::ValueBinding(pattern, _) = &binding.value;
+        for identifier in crate::parser::ast_util::pattern_identifiers(pattern) {
+          self.declare(Namespace::Value, identifier, DeclarationKind::Value, private);
+        }
+      }
+      Definition::Overload(name, _) => {
+        self.declare(Namespace::Value, name, DeclarationKind::Overload, private);
+      }
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+        self.declare(Namespace::Value, name, DeclarationKind::ValueSpec, private);
+      }
+      Definition::OutcomeSpec(outcome, inner) => {
+        let OutcomeSpec::Outcome(name, ..) = &outcome.value;
+        self.declare(Namespace::Value, name, DeclarationKind::Outcome, private);
+        for definition in inner {
+          self.declare_definition(definition);
+        }
+      }
+      Definition::Register(declaration) => {
+        let DeclarationSpecification::Register(_, name, _) = &declaration.value;
+        self.declare(Namespace::Value, name, DeclarationKind::Register, private);
+      }
+      Definition::InternalMutRec(functions) => {
+        for function in functions {
+          self.declare_function(function, private);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn declare_function(&mut self, function: &LocatedFunctionDefinition, private: bool) {
+    if let Some(name) = crate::parser::ast_util::function_name(function) {
+      self.declare(Namespace::Value, name, DeclarationKind::Function, private);
+    }
+  }
+}
+
+// endregion
+
+// region Scopes and references
+
+impl Resolver {
+  fn with_scope<F: FnOnce(&mut Self)>(&mut self, f: F) {
+    self.scopes.push(HashMap::new());
+    f(self);
+    self.scopes.pop();
+  }
+
+  fn bind_local(&mut self, identifier: &LocatedIdentifier) -> DefinitionId {
+    let declaration = Declaration { kind: DeclarationKind::Local, location: identifier.location.clone(), private: false };
+    let id = self.table.new_symbol(identifier.name(), Namespace::Value, declaration);
+    if self.scopes.is_empty() {
+      self.scopes.push(HashMap::new());
+    }
+    self.scopes.last_mut().unwrap().insert(identifier.name().to_string(), id);
+    id
+  }
+
+  fn lookup_local(&self, name: &str) -> Option<DefinitionId> {
+    self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+  }
+
+  fn record(&mut self, identifier: &LocatedIdentifier, id: DefinitionId) {
+    self.table.references.push(Reference {
+      name    : identifier.name().to_string(),
+      location: identifier.location.clone(),
+      id,
+    });
+    if identifier.location != SourceLocation::Unknown {
+      self.table.by_location.insert(identifier.location.clone(), id);
+    }
+  }
+
+  /// Resolves a use of `identifier`, reporting it if it is undefined.
+  fn reference(&mut self, namespace: Namespace, identifier: &LocatedIdentifier) -> Option<DefinitionId> {
+    let id = match namespace {
+      Namespace::Value => self.lookup_local(identifier.name()),
+      Namespace::Type  => None,
+    }
+    .or_else(|| self.table.lookup(namespace, identifier.name()));
+
+    match id {
+      Some(id) => {
+        self.record(identifier, id);
+        Some(id)
+      }
+      None => {
+        let error = ResolveError::Undefined { name: identifier.name().to_string(), namespace };
+        self.errors.push(Located { location: identifier.location.clone(), value: error });
+        None
+      }
+    }
+  }
+
+  /// Resolves a use of `identifier` if possible, without reporting anything if it is undefined.
+  fn optional_reference(&mut self, namespace: Namespace, identifier: &LocatedIdentifier) {
+    if let Some(id) = self.table.lookup(namespace, identifier.name()) {
+      self.record(identifier, id);
+    }
+  }
+
+  /// Whether a bare identifier in a pattern names a constructor or enum member rather than binding a variable.
+  fn is_pattern_constant(&self, name: &str) -> bool {
+    self.lookup_local(name).is_none()
+        && self.table.lookup(Namespace::Value, name).is_some_and(|id| {
+          let symbol = self.table.symbol(id);
+          symbol.has_kind(DeclarationKind::Constructor) || symbol.has_kind(DeclarationKind::EnumMember)
+        })
+  }
+}
+
+// endregion
+
+// region Definitions
+
+impl Resolver {
+  fn definition(&mut self, definition: &LocatedDefinition) {
+    let (definition, _) = strip_definition(definition);
+
+    match &definition.value {
+      Definition::TypeDefinition(type_definition) => self.type_definition(type_definition),
+      Definition::Constraint(constraint) => self.abstract_type(constraint),
+      Definition::FunctionDefinition(function) => self.function(function),
+      Definition::MappingDefinition(mapping) => self.mapping(mapping),
+      Definition::Implementation(clause) => self.function_clause(clause),
+      Definition::ValueDefinition(binding) => {
+        let LetBinding::ValueBinding(pattern, bound) = &binding.value;
+        self.expression(bound);
+        // Outside any scope, the pattern's names refer to the globals declared for them in the first phase.
+        self.pattern(pattern);
+      }
+      Definition::Overload(name, targets) => {
+        let id = self.table.lookup(Namespace::Value, name.name());
+        let targets: Vec<DefinitionId> =
+            targets.iter().filter_map(|target| self.reference(Namespace::Value, target)).collect();
+        if let Some(id) = id {
+          self.table.overloads.entry(id).or_default().extend(targets);
+        }
+      }
+      Definition::Fixity(_, _, operator) => self.optional_reference(Namespace::Value, operator),
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(scheme, _, _) = &specification.value;
+        self.type_scheme(scheme);
+      }
+      Definition::OutcomeSpec(outcome, inner) => {
+        let OutcomeSpec::Outcome(_, scheme, _) = &outcome.value;
+        self.type_scheme(scheme);
+        for definition in inner {
+          self.definition(definition);
+        }
+      }
+      Definition::Instantiation(name, substitutions) => {
+        self.reference(Namespace::Value, name);
+        for substitution in substitutions {
+          match &substitution.value {
+            InstantiationSubstitution::TypeSubstitution(_, abstract_type) => self.abstract_type(abstract_type),
+            InstantiationSubstitution::IdentifierSubstitution(_, replacement) => {
+              self.reference(Namespace::Value, replacement);
+            }
+          }
+        }
+      }
+      Definition::DefaultTypingSpec(_)
+      | Definition::Pragma(..) => {}
+      Definition::ScatteredDefinition(_) => {
+        // Scattered definitions are collected before resolution; any left over have already been reported.
+      }
+      Definition::Measure(name, pattern, measure) => {
+        self.reference(Namespace::Value, name);
+        self.with_scope(|resolver| {
+          resolver.pattern(pattern);
+          resolver.expression(measure);
+        });
+      }
+      Definition::LoopMeasures(name, _) => {
+        // The measures refer to loop variables, which are only in scope inside the function body.
+        self.reference(Namespace::Value, name);
+      }
+      Definition::Register(declaration) => {
+        let DeclarationSpecification::Register(abstract_type, _, initializer) = &declaration.value;
+        self.abstract_type(abstract_type);
+        if let Some(initializer) = initializer {
+          self.expression(initializer);
+        }
+      }
+      Definition::InternalMutRec(functions) => {
+        for function in functions {
+          self.function(function);
+        }
+      }
+      Definition::Private(_)
+      | Definition::Attribute(..)
+      | Definition::Documentation(..) => unreachable!("wrappers are removed by strip_definition"),
+    }
+  }
+
+  fn type_definition(&mut self, definition: &LocatedTypeDefinition) {
+    match &definition.value {
+      TypeDefinition::Abbreviation(_, quantifier, _, abstract_type) => {
+        self.type_quantifier(quantifier);
+        self.abstract_type(abstract_type);
+      }
+      TypeDefinition::Record(_, quantifier, fields) => {
+        self.type_quantifier(quantifier);
+        for (abstract_type, _) in fields {
+          self.abstract_type(abstract_type);
+        }
+      }
+      TypeDefinition::Variant(_, quantifier, unions) => {
+        self.type_quantifier(quantifier);
+        for union in unions {
+          self.type_union(union);
+        }
+      }
+      TypeDefinition::Enum(_, functions, members) => {
+        for (_, abstract_type) in functions {
+          self.abstract_type(abstract_type);
+        }
+        for (_, value) in members {
+          if let Some(value) = value {
+            self.expression(value);
+          }
+        }
+      }
+      TypeDefinition::Abstract(..) => {}
+      TypeDefinition::Bitfield(_, abstract_type, fields) => {
+        self.abstract_type(abstract_type);
+        for (_, range) in fields {
+          self.index_range(range);
+        }
+      }
+    }
+  }
+
+  fn type_union(&mut self, union: &LocatedTypeUnion) {
+    match &union.value {
+      TypeUnion::Private(inner)
+      | TypeUnion::Attribute(_, _, inner)
+      | TypeUnion::Documentation(_, inner) => self.type_union(inner),
+      TypeUnion::TypeIdentifier(abstract_type, _) => self.abstract_type(abstract_type),
+      TypeUnion::AnonymousRecord(fields, _) => {
+        for (abstract_type, _) in fields {
+          self.abstract_type(abstract_type);
+        }
+      }
+    }
+  }
+
+  fn index_range(&mut self, range: &LocatedIndexRange) {
+    match &range.value {
+      IndexRange::Single(index) => self.abstract_type(index),
+      IndexRange::Range(high, low) => {
+        self.abstract_type(high);
+        self.abstract_type(low);
+      }
+      IndexRange::Concat(left, right) => {
+        self.index_range(left);
+        self.index_range(right);
+      }
+    }
+  }
+
+  fn function(&mut self, function: &LocatedFunctionDefinition) {
+    let FunctionDefinition::Function(recursive, annotation, _, clauses) = &function.value;
+    if let Some((quantifier, abstract_type)) = &annotation.value {
+      self.type_quantifier(quantifier);
+      self.abstract_type(abstract_type);
+    }
+    if let Some((pattern, measure)) = &recursive.value {
+      self.with_scope(|resolver| {
+        resolver.pattern(pattern);
+        resolver.expression(measure);
+      });
+    }
+    for clause in clauses {
+      self.function_clause(clause);
+    }
+  }
+
+  fn function_clause(&mut self, clause: &LocatedFunctionClause) {
+    let (name, arm) = strip_function_clause(clause);
+    self.reference(Namespace::Value, name);
+    self.pattern_expression(arm);
+  }
+
+  fn mapping(&mut self, mapping: &LocatedMappingDefinition) {
+    let MappingDefinition::Mapping(_, scheme, clauses) = &mapping.value;
+    if let Some(scheme) = &scheme.value {
+      self.type_scheme(scheme);
+    }
+    for clause in clauses {
+      self.mapping_clause(clause);
+    }
+  }
+
+  fn mapping_clause(&mut self, clause: &LocatedMappingClause) {
+    match &clause.value {
+      MappingClause::Attribute(_, _, inner)
+      | MappingClause::Documentation(_, inner) => self.mapping_clause(inner),
+      // Both sides of a bidirectional clause share one scope: a variable bound on one side is used on the other.
+      MappingClause::Bidirectional(left, right) => self.with_scope(|resolver| {
+        resolver.mapping_pattern_expression(left);
+        resolver.mapping_pattern_expression(right);
+      }),
+      MappingClause::ForwardsDeprecated(left, expression) => self.with_scope(|resolver| {
+        resolver.mapping_pattern_expression(left);
+        resolver.expression(expression);
+      }),
+      MappingClause::Forwards(arm)
+      | MappingClause::Backwards(arm) => self.pattern_expression(arm),
+    }
+  }
+
+  fn mapping_pattern_expression(&mut self, arm: &LocatedMappingPatternExpression) {
+    match &arm.value {
+      MappingPatternExpression::Pattern(pattern) => self.mapping_pattern(pattern),
+      MappingPatternExpression::PatternWhen(pattern, guard) => {
+        self.mapping_pattern(pattern);
+        self.expression(guard);
+      }
+    }
+  }
+
+  fn mapping_pattern(&mut self, pattern: &LocatedMappingPattern) {
+    match &pattern.value {
+      MappingPattern::Literal(_) => {}
+      MappingPattern::Identifier(identifier)
+      | MappingPattern::VectorSubrange(identifier, ..) => self.pattern_variable(identifier),
+      MappingPattern::Application(constructor, patterns) => {
+        self.reference(Namespace::Value, constructor);
+        for pattern in patterns {
+          self.mapping_pattern(pattern);
+        }
+      }
+      MappingPattern::Vector(patterns)
+      | MappingPattern::VectorConcat(patterns)
+      | MappingPattern::Tuple(patterns)
+      | MappingPattern::List(patterns)
+      | MappingPattern::StringAppend(patterns) => {
+        for pattern in patterns {
+          self.mapping_pattern(pattern);
+        }
+      }
+      MappingPattern::Cons(head, tail) => {
+        self.mapping_pattern(head);
+        self.mapping_pattern(tail);
+      }
+      MappingPattern::Typed(pattern, abstract_type) => {
+        self.mapping_pattern(pattern);
+        self.abstract_type(abstract_type);
+      }
+      MappingPattern::As(pattern, identifier) => {
+        self.mapping_pattern(pattern);
+        self.pattern_variable(identifier);
+      }
+      MappingPattern::Struct(fields) => {
+        for (_, pattern) in fields {
+          self.mapping_pattern(pattern);
+        }
+      }
+    }
+  }
+}
+
+// endregion
+
+// region Types
+
+impl Resolver {
+  fn type_scheme(&mut self, scheme: &LocatedTypeScheme) {
+    self.type_quantifier(&scheme.quantifier);
+    self.abstract_type(&scheme.abstract_type);
+  }
+
+  fn type_quantifier(&mut self, quantifier: &LocatedTypeQuantifier) {
+    if let TypeQuantifier::TypeQuantifiers(items) = &quantifier.value {
+      for item in items {
+        if let QuantifierItem::Constraint(constraint) = &item.value {
+          self.abstract_type(constraint);
+        }
+      }
+    }
+  }
+
+  fn abstract_type(&mut self, abstract_type: &LocatedAbstractType) {
+    match &abstract_type.value {
+      AbstractType::Identifier(identifier) => {
+        self.reference(Namespace::Type, identifier);
+      }
+      AbstractType::TypeConstructorApplication(identifier, arguments) => {
+        self.reference(Namespace::Type, identifier);
+        for argument in arguments {
+          self.abstract_type(argument);
+        }
+      }
+      AbstractType::Variable(_)
+      | AbstractType::Literal(_)
+      | AbstractType::NumberSet(_)
+      | AbstractType::Increasing
+      | AbstractType::Decreasing
+      | AbstractType::EffectSet(_)
+      | AbstractType::Wildcard => {}
+      AbstractType::In(left, right)
+      | AbstractType::Times(left, right)
+      | AbstractType::Sum(left, right)
+      | AbstractType::Minus(left, right) => {
+        self.abstract_type(left);
+        self.abstract_type(right);
+      }
+      AbstractType::Exponential(inner)
+      | AbstractType::Negative(inner)
+      | AbstractType::Parenthesized(inner) => self.abstract_type(inner),
+      AbstractType::Infix(tokens) => {
+        for (token, _) in tokens {
+          match token {
+            InfixToken::Primary(inner) => self.abstract_type(inner),
+            // Type-level operators such as `+` and `*` are built in, so there is nothing to resolve.
+            InfixToken::Operator(_)
+            | InfixToken::Prefix(_) => {}
+          }
+        }
+      }
+      AbstractType::Function { lhs, rhs, effect }
+      | AbstractType::Bidirectional { lhs, rhs, effect } => {
+        self.abstract_type(lhs);
+        self.abstract_type(rhs);
+        self.abstract_type(effect);
+      }
+      AbstractType::Tuple(types) => {
+        for inner in types {
+          self.abstract_type(inner);
+        }
+      }
+      AbstractType::If { condition, then, elsewise } => {
+        self.abstract_type(condition);
+        self.abstract_type(then);
+        self.abstract_type(elsewise);
+      }
+      AbstractType::Existential(_, constraint, body) => {
+        self.abstract_type(constraint);
+        self.abstract_type(body);
+      }
+    }
+  }
+}
+
+// endregion
+
+// region Patterns and expressions
+
+impl Resolver {
+  /// Handles a bare identifier in a pattern: a constant reference or a new variable.
+  fn pattern_variable(&mut self, identifier: &LocatedIdentifier) {
+    if self.is_pattern_constant(identifier.name()) {
+      self.reference(Namespace::Value, identifier);
+    } else if self.scopes.is_empty() {
+      // A top-level `let`, whose names were declared globally with the other definitions.
+      if let Some(id) = self.table.lookup(Namespace::Value, identifier.name()) {
+        self.record(identifier, id);
+      }
+    } else if let Some(id) = self.scopes.last().and_then(|scope| scope.get(identifier.name()).copied()) {
+      // The same variable on both sides of a mapping clause, or repeated within one pattern, is a single binding.
+      self.record(identifier, id);
+    } else {
+      self.bind_local(identifier);
+    }
+  }
+
+  /// Binds the variables of `pattern` in the innermost scope, or outside any scope refers them to their globals.
+  fn pattern(&mut self, pattern: &LocatedPattern) {
+    match &pattern.value {
+      Pattern::Literal(_)
+      | Pattern::Wildcard => {}
+      Pattern::Identifier(identifier)
+      | Pattern::VectorSubrange(identifier, ..) => self.pattern_variable(identifier),
+      Pattern::Typed(abstract_type, inner)
+      | Pattern::Variable(inner, abstract_type) => {
+        self.abstract_type(abstract_type);
+        self.pattern(inner);
+      }
+      Pattern::Constructor(constructor, patterns) => {
+        self.reference(Namespace::Value, constructor);
+        for pattern in patterns {
+          self.pattern(pattern);
+        }
+      }
+      Pattern::Vector(patterns)
+      | Pattern::VectorConcat(patterns)
+      | Pattern::Tuple(patterns)
+      | Pattern::List(patterns)
+      | Pattern::StringAppend(patterns) => {
+        for pattern in patterns {
+          self.pattern(pattern);
+        }
+      }
+      Pattern::Cons(head, tail) => {
+        self.pattern(head);
+        self.pattern(tail);
+      }
+      Pattern::Struct(fields) => {
+        for field in fields {
+          if let FieldPattern::Field(_, pattern) = &field.value {
+            self.pattern(pattern);
+          }
+        }
+      }
+      Pattern::Attribute(_, _, inner) => self.pattern(inner),
+    }
+  }
+
+  fn pattern_expression(&mut self, arm: &LocatedPatternExpression) {
+    self.with_scope(|resolver| match &arm.value {
+      PatternExpression::Pattern(pattern, body) => {
+        resolver.pattern(pattern);
+        resolver.expression(body);
+      }
+      PatternExpression::PatternWhen(pattern, guard, body) => {
+        resolver.pattern(pattern);
+        resolver.expression(guard);
+        resolver.expression(body);
+      }
+    })
+  }
+
+  /// The variable a `var` declaration introduces, beneath any type annotation.
+  fn declared_variable(expression: &LocatedExpression) -> Option<&LocatedIdentifier> {
+    match &expression.value {
+      Expression::Identifier(identifier) => Some(identifier),
+      Expression::Typed(_, inner) => Self::declared_variable(inner),
+      _ => None,
+    }
+  }
+
+  /// Resolves a `field = value` item of a struct literal or update, leaving the field name alone.
+  fn struct_field(&mut self, field: &LocatedExpression) {
+    match &field.value {
+      Expression::Assign(name, value) if matches!(name.value, Expression::Identifier(_)) => self.expression(value),
+      _ => self.expression(field),
+    }
+  }
+
+  fn expression(&mut self, expression: &LocatedExpression) {
+    match &expression.value {
+      Expression::Block(expressions) => self.with_scope(|resolver| {
+        for expression in expressions {
+          // Assigning to an unbound name declares it for the rest of the block.
+          if let Expression::Assign(target, value) = &expression.value {
+            if let Some(identifier) = Self::declared_variable(target) {
+              if resolver.lookup_local(identifier.name()).is_none()
+                  && resolver.table.lookup(Namespace::Value, identifier.name()).is_none()
+              {
+                resolver.expression(value);
+                if let Expression::Typed(abstract_type, _) = &target.value {
+                  resolver.abstract_type(abstract_type);
+                }
+                resolver.bind_local(identifier);
+                continue;
+              }
+            }
+          }
+          resolver.expression(expression);
+        }
+      }),
+      Expression::Identifier(identifier)
+      | Expression::Reference(identifier) => {
+        self.reference(Namespace::Value, identifier);
+      }
+      Expression::Literal(_) => {}
+      Expression::Application(function, arguments) => {
+        self.reference(Namespace::Value, function);
+        for argument in arguments {
+          self.expression(argument);
+        }
+      }
+      Expression::InfixApplication(left, operator, right) => {
+        self.expression(left);
+        self.reference(Namespace::Value, operator);
+        self.expression(right);
+      }
+      Expression::Infix(tokens) => {
+        for (token, _) in tokens {
+          match token {
+            InfixToken::Primary(inner) => self.expression(inner),
+            InfixToken::Operator(operator)
+            | InfixToken::Prefix(operator) => {
+              self.reference(Namespace::Value, operator);
+            }
+          }
+        }
+      }
+      Expression::Tuple(expressions)
+      | Expression::Vector(expressions)
+      | Expression::List(expressions) => {
+        for expression in expressions {
+          self.expression(expression);
+        }
+      }
+      Expression::Struct(fields) => {
+        for field in fields {
+          self.struct_field(field);
+        }
+      }
+      Expression::StructUpdate(inner, fields) => {
+        self.expression(inner);
+        for field in fields {
+          self.struct_field(field);
+        }
+      }
+      Expression::Dereference(inner)
+      | Expression::Field(inner, _)
+      | Expression::Exit(inner)
+      | Expression::Throw(inner)
+      | Expression::Return(inner)
+      | Expression::Attribute(_, _, inner)
+      | Expression::InternalReturn(inner) => self.expression(inner),
+      Expression::Typed(abstract_type, inner)
+      | Expression::InternalAssume(abstract_type, inner) => {
+        self.abstract_type(abstract_type);
+        self.expression(inner);
+      }
+      Expression::VectorAccess(left, right)
+      | Expression::VectorAppend(left, right)
+      | Expression::Cons(left, right)
+      | Expression::Assign(left, right)
+      | Expression::Assert(left, right) => {
+        self.expression(left);
+        self.expression(right);
+      }
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        self.expression(condition);
+        self.expression(then_expr);
+        self.expression(else_expr);
+      }
+      Expression::Loop(_, measure, condition, body) => {
+        if let Some(measure) = &measure.value {
+          self.expression(measure);
+        }
+        self.expression(condition);
+        self.expression(body);
+      }
+      Expression::For { identifier, start, end, step, typ, body } => {
+        self.expression(start);
+        self.expression(end);
+        self.expression(step);
+        self.abstract_type(typ);
+        self.with_scope(|resolver| {
+          resolver.bind_local(identifier);
+          resolver.expression(body);
+        });
+      }
+      Expression::VectorSubrange(first, second, third)
+      | Expression::VectorUpdate(first, second, third) => {
+        self.expression(first);
+        self.expression(second);
+        self.expression(third);
+      }
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        self.expression(vector);
+        self.expression(high);
+        self.expression(low);
+        self.expression(value);
+      }
+      Expression::Match(scrutinee, arms)
+      | Expression::Try(scrutinee, arms) => {
+        self.expression(scrutinee);
+        for arm in arms {
+          self.pattern_expression(arm);
+        }
+      }
+      Expression::Let(binding, body) => {
+        let LetBinding::ValueBinding(pattern, bound) = &binding.value;
+        self.expression(bound);
+        self.with_scope(|resolver| {
+          resolver.pattern(pattern);
+          resolver.expression(body);
+        });
+      }
+      Expression::Variable(target, initializer, body) => {
+        self.expression(initializer);
+        self.with_scope(|resolver| {
+          if let Expression::Typed(abstract_type, _) = &target.value {
+            resolver.abstract_type(abstract_type);
+          }
+          match Self::declared_variable(target) {
+            Some(identifier) => {
+              resolver.bind_local(identifier);
+            }
+            None => resolver.expression(target),
+          }
+          resolver.expression(body);
+        });
+      }
+      Expression::Sizeof(abstract_type)
+      | Expression::Constraint(abstract_type) => self.abstract_type(abstract_type),
+      Expression::InternalPlet(pattern, bound, body) => {
+        self.expression(bound);
+        self.with_scope(|resolver| {
+          resolver.pattern(pattern);
+          resolver.expression(body);
+        });
+      }
+    }
+  }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+
+  fn messages(errors: &[LocatedResolveError]) -> Vec<String> {
+    errors.iter().map(|error| error.value.to_string()).collect()
+  }
+
+  /// A call of `name` with no arguments, and the location of the name.
+  fn call_of(name: &str) -> (LocatedExpression, SourceLocation) {
+    let callee = id(name);
+    let location = callee.location.clone();
+    (located(Expression::Application(callee, Vec::new())), location)
+  }
+
+  #[test]
+  fn globals_may_be_used_before_they_are_declared() {
+    let (use_of_g, location) = call_of("g");
+    let program = vec![
+      function("f", vec![], use_of_g),
+      val("g", function_type(vec![typ("unit")], typ("int"))),
+      function("g", vec![], number(1)),
+    ];
+
+    let (table, errors) = resolve_names(&definitions(program));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    let g = table.lookup(Namespace::Value, "g").unwrap();
+    assert_eq!(table.resolve_location(&location), Some(g));
+    assert!(table.symbol(g).has_kind(DeclarationKind::ValueSpec));
+    assert!(table.symbol(g).has_kind(DeclarationKind::Function));
+  }
+
+  #[test]
+  fn bare_pattern_names_are_members_if_declared() {
+    let member = id("A");
+    let member_location = member.location.clone();
+    let variable = id("y");
+    let variable_location = variable.location.clone();
+    let program = vec![
+      enumeration("E", &["A", "B"]),
+      function("f", vec![pattern("x")], matching(var("x"), vec![
+        arm(located(Pattern::Identifier(member)), number(1)),
+        arm(located(Pattern::Identifier(variable)), number(2)),
+      ])),
+    ];
+
+    let (table, errors) = resolve_names(&definitions(program));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    assert_eq!(table.resolve_location(&member_location), table.lookup(Namespace::Value, "A"));
+    let y = table.resolve_location(&variable_location).unwrap();
+    assert!(table.symbol(y).is_local());
+  }
+
+  #[test]
+  fn top_level_let_names_are_global_values() {
+    let name = id("x");
+    let location = name.location.clone();
+    let program = vec![
+      value(located(Pattern::Typed(Box::new(typ("int")), Box::new(located(Pattern::Identifier(name))))), number(1)),
+      function("f", vec![], var("x")),
+    ];
+
+    let (table, errors) = resolve_names(&definitions(program));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    let x = table.lookup(Namespace::Value, "x").unwrap();
+    assert_eq!(table.resolve_location(&location), Some(x));
+    assert!(table.symbol(x).has_kind(DeclarationKind::Value));
+    assert!(!table.symbol(x).is_local());
+  }
+
+  #[test]
+  fn undefined_and_duplicate_names_are_reported() {
+    let program = vec![
+      register("R", typ("int"), None),
+      register("R", typ("int"), None),
+      value(pattern("x"), var("missing")),
+      val("h", function_type(vec![typ("unknown")], typ("unit"))),
+    ];
+
+    let (_, errors) = resolve_names(&definitions(program));
+    assert_eq!(messages(&errors), vec![
+      "register `R` conflicts with an earlier register of the same name".to_string(),
+      "undefined identifier `missing`".to_string(),
+      "undefined type `unknown`".to_string(),
+    ]);
+  }
+
+  #[test]
+  fn more_definitions_see_earlier_ones() {
+    let (mut table, errors) = resolve_names(&definitions(vec![register("R", typ("int"), None)]));
+    assert!(errors.is_empty());
+
+    let errors = resolve_more_names(&mut table, &definitions(vec![value(pattern("x"), var("R"))]));
+    assert_eq!(messages(&errors), Vec::<String>::new());
+    let errors = resolve_more_names(&mut table, &definitions(vec![register("R", typ("int"), None)]));
+    assert_eq!(messages(&errors), vec!["register `R` conflicts with an earlier register of the same name".to_string()]);
+  }
+}
@@ -0,0 +1,498 @@
+/*!
+
+Collection of scattered definitions.
+
+Sail lets functions, unions, enums and mappings be declared `scattered` and then built up clause by clause, possibly
+across many files, until a closing `end`. This pass gathers the clauses of every scattered definition across the whole
+of `Definitions` and replaces them with one ordinary definition:
+
+ * `scattered function` becomes a `FunctionDefinition`,
+ * `scattered union` becomes a `TypeDefinition::Variant`,
+ * `scattered enum` becomes a `TypeDefinition::Enum`,
+ * `scattered mapping` becomes a `MappingDefinition`.
+
+A combined union or enum takes the place of the `scattered` declaration that opened it, as in Sail, where the type is
+declared there and the definitions between its clauses may use it. A combined function or mapping takes the place of its
+last clause instead, because clauses routinely refer to types and functions declared after it was opened. Each clause
+keeps its own location; the combined definition is located at the `scattered` declaration that opened it, and any
+`private`, attribute or documentation wrappers on that declaration are carried over. Wrappers on individual clause
+definitions are moved onto the clauses where the AST allows it.
+
+A clause for a definition that has not been opened, a clause after the `end`, a definition opened twice, a clause of
+the wrong sort and a definition never closed with `end` are all reported.
+
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::strip_function_clause;
+use crate::parser::location::{Located, SourceLocation};
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum ScatteredError {
+  /// A clause or `end` names a scattered definition that has not been opened.
+  NotOpened(String),
+  /// A scattered definition is opened a second time. Carries the location of the first.
+  AlreadyOpened(String, SourceLocation),
+  /// A clause follows the `end` of its definition. Carries the location of the `end`.
+  AlreadyClosed(String, SourceLocation),
+  /// A scattered definition has no `end`.
+  NotClosed(String),
+  /// A clause of one sort is added to a scattered definition of another.
+  WrongKind { name: String, expected: ScatteredKind, found: ScatteredKind },
+}
+
+pub type LocatedScatteredError = Located<ScatteredError>;
+
+impl ScatteredError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ScatteredError::NotOpened(name) => {
+        write!(f, "`{}` has not been declared scattered", name)
+      }
+
+      ScatteredError::AlreadyOpened(name, _) => {
+        write!(f, "scattered definition `{}` is declared more than once", name)
+      }
+
+      ScatteredError::AlreadyClosed(name, _) => {
+        write!(f, "clause for scattered definition `{}` appears after its `end`", name)
+      }
+
+      ScatteredError::NotClosed(name) => {
+        write!(f, "scattered definition `{}` is never closed with `end`", name)
+      }
+
+      ScatteredError::WrongKind { name, expected, found } => {
+        write!(f, "`{}` is a scattered {}, but this is a {} clause", name, expected, found)
+      }
+    }
+  }
+}
+
+impl Debug for ScatteredError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for ScatteredError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for ScatteredError {}
+
+/// The sorts of scattered definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScatteredKind {
+  Function,
+  Union,
+  Enumeration,
+  Mapping,
+}
+
+impl Display for ScatteredKind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ScatteredKind::Function    => write!(f, "function"),
+      ScatteredKind::Union       => write!(f, "union"),
+      ScatteredKind::Enumeration => write!(f, "enum"),
+      ScatteredKind::Mapping     => write!(f, "mapping"),
+    }
+  }
+}
+
+/// A definition-level wrapper found around a scattered declaration or clause.
+#[derive(Clone)]
+enum Wrapper {
+  Private,
+  Attribute(String, Option<LocatedAttributeData>),
+  Documentation(String),
+}
+
+/// The position of a top-level definition: file index, then index within the file.
+type Position = (usize, usize);
+
+/// Everything gathered for one scattered definition.
+struct Scattered {
+  kind    : ScatteredKind,
+  /// The opening declaration, with its wrappers innermost first
+  open    : LocatedScatteredDefinition,
+  wrappers: Vec<(Wrapper, SourceLocation)>,
+  clauses : Vec<Clause>,
+  /// Where the combined definition goes: the opening declaration of a union or enum, the last clause of a function or
+  /// mapping
+  position: Position,
+  end     : Option<SourceLocation>,
+}
+
+enum Clause {
+  Function(LocatedFunctionClause),
+  Union(LocatedTypeUnion),
+  Member(LocatedIdentifier),
+  Mapping(Box<LocatedMappingClause>),
+}
+
+/// Replaces all scattered definitions with ordinary ones, returning the rewritten definitions and any errors.
+pub fn collect_scattered(definitions: Definitions) -> (Definitions, Vec<LocatedScatteredError>) {
+  let mut collector = Collector { scattered: Vec::new(), by_name: HashMap::new(), errors: Vec::new() };
+
+  for (file_index, (_, file_definitions)) in definitions.0.iter().enumerate() {
+    for (index, definition) in file_definitions.iter().enumerate() {
+      let mut wrappers = Vec::new();
+      if let Some(scattered) = unwrap_scattered(definition, &mut wrappers) {
+        collector.add(scattered, wrappers, (file_index, index));
+      }
+    }
+  }
+
+  for scattered in &collector.scattered {
+    if scattered.end.is_none() {
+      let name = scattered_name(&scattered.open).name().to_string();
+      collector.errors.push(Located { location: scattered.open.location.clone(), value: ScatteredError::NotClosed(name) });
+    }
+  }
+
+  // Emit each combined definition in its place and drop every other scattered definition.
+  let mut replacements: HashMap<Position, Scattered> =
+      collector.scattered.into_iter().map(|scattered| (scattered.position, scattered)).collect();
+  let mut files = Vec::with_capacity(definitions.0.len());
+  for (file_index, (file, file_definitions)) in definitions.0.into_iter().enumerate() {
+    let mut rewritten = Vec::with_capacity(file_definitions.len());
+    for (index, definition) in file_definitions.into_iter().enumerate() {
+      if let Some(scattered) = replacements.remove(&(file_index, index)) {
+        rewritten.push(combine(scattered));
+      } else if unwrap_scattered(&definition, &mut Vec::new()).is_none() {
+        rewritten.push(definition);
+      }
+    }
+    files.push((file, rewritten));
+  }
+
+  (Definitions(files), collector.errors)
+}
+
+struct Collector {
+  scattered: Vec<Scattered>,
+  /// Open definitions by name. Functions and mappings share one namespace, unions and enums another, as in Sail.
+  by_name  : HashMap<(bool, String), usize>,
+  errors   : Vec<LocatedScatteredError>,
+}
+
+impl Collector {
+  fn error(&mut self, error: ScatteredError, location: &SourceLocation) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  fn add(
+    &mut self,
+    definition: &LocatedScatteredDefinition,
+    wrappers  : Vec<(Wrapper, SourceLocation)>,
+    position  : Position,
+  ) {
+    let (kind, name, clause) = match &definition.value {
+      ScatteredDefinition::Function(.., name) => return self.open(ScatteredKind::Function, name, definition, wrappers, position),
+      ScatteredDefinition::Variant(name, _)   => return self.open(ScatteredKind::Union, name, definition, wrappers, position),
+      ScatteredDefinition::Enumeration(name)  => return self.open(ScatteredKind::Enumeration, name, definition, wrappers, position),
+      ScatteredDefinition::Mapping(name, _)   => return self.open(ScatteredKind::Mapping, name, definition, wrappers, position),
+      ScatteredDefinition::End(name)          => return self.close(name, &definition.location),
+
+      ScatteredDefinition::FunctionClause(clause) => {
+        let clause = wrap_function_clause(clause.clone(), &wrappers);
+        let name   = strip_function_clause(&clause).0.clone();
+        (ScatteredKind::Function, name, Clause::Function(clause))
+      }
+      ScatteredDefinition::UnionClause(name, union) => {
+        (ScatteredKind::Union, name.clone(), Clause::Union(wrap_type_union(union.clone(), &wrappers)))
+      }
+      ScatteredDefinition::EnumerationMember(name, member) => {
+        (ScatteredKind::Enumeration, name.clone(), Clause::Member(member.clone()))
+      }
+      ScatteredDefinition::MapClause(name, clause) => {
+        (ScatteredKind::Mapping, name.clone(), Clause::Mapping(Box::new(wrap_mapping_clause(clause.clone(), &wrappers))))
+      }
+    };
+
+    let Some(&index) = self.lookup(kind, name.name()) else {
+      return self.error(ScatteredError::NotOpened(name.name().to_string()), &name.location);
+    };
+    let scattered = &self.scattered[index];
+    if scattered.kind != kind {
+      let error = ScatteredError::WrongKind { name: name.name().to_string(), expected: scattered.kind, found: kind };
+      return self.error(error, &definition.location);
+    }
+    if let Some(end) = &scattered.end {
+      let error = ScatteredError::AlreadyClosed(name.name().to_string(), end.clone());
+      return self.error(error, &definition.location);
+    }
+
+    let scattered = &mut self.scattered[index];
+    scattered.clauses.push(clause);
+    if !is_type_kind(kind) {
+      scattered.position = position;
+    }
+  }
+
+  fn lookup(&self, kind: ScatteredKind, name: &str) -> Option<&usize> {
+    // A clause naming a definition of the other namespace is still found, so it can be reported as the wrong kind.
+    self.by_name
+        .get(&(is_type_kind(kind), name.to_string()))
+        .or_else(|| self.by_name.get(&(!is_type_kind(kind), name.to_string())))
+  }
+
+  fn open(
+    &mut self,
+    kind      : ScatteredKind,
+    name      : &LocatedIdentifier,
+    definition: &LocatedScatteredDefinition,
+    wrappers  : Vec<(Wrapper, SourceLocation)>,
+    position  : Position,
+  ) {
+    let key = (is_type_kind(kind), name.name().to_string());
+    if let Some(&previous) = self.by_name.get(&key) {
+      let previous = self.scattered[previous].open.location.clone();
+      return self.error(ScatteredError::AlreadyOpened(key.1, previous), &definition.location);
+    }
+    self.by_name.insert(key, self.scattered.len());
+    self.scattered.push(Scattered {
+      kind,
+      open   : definition.clone(),
+      wrappers,
+      clauses: Vec::new(),
+      position,
+      end    : None,
+    });
+  }
+
+  fn close(&mut self, name: &LocatedIdentifier, location: &SourceLocation) {
+    let index = self.by_name
+        .get(&(false, name.name().to_string()))
+        .filter(|&&index| self.scattered[index].end.is_none())
+        .or_else(|| self.by_name.get(&(true, name.name().to_string())));
+    match index {
+      Some(&index) if self.scattered[index].end.is_none() => self.scattered[index].end = Some(location.clone()),
+      Some(&index) => {
+        let end = self.scattered[index].end.clone().unwrap_or_default();
+        self.error(ScatteredError::AlreadyClosed(name.name().to_string(), end), location)
+      }
+      None => self.error(ScatteredError::NotOpened(name.name().to_string()), &name.location),
+    }
+  }
+}
+
+fn is_type_kind(kind: ScatteredKind) -> bool {
+  matches!(kind, ScatteredKind::Union | ScatteredKind::Enumeration)
+}
+
+fn scattered_name(definition: &LocatedScatteredDefinition) -> &LocatedIdentifier {
+  match &definition.value {
+    ScatteredDefinition::Function(.., name)
+    | ScatteredDefinition::Variant(name, _)
+    | ScatteredDefinition::Enumeration(name)
+    | ScatteredDefinition::Mapping(name, _)
+    | ScatteredDefinition::End(name)
+    | ScatteredDefinition::UnionClause(name, _)
+    | ScatteredDefinition::EnumerationMember(name, _)
+    | ScatteredDefinition::MapClause(name, _) => name,
+    ScatteredDefinition::FunctionClause(clause) => strip_function_clause(clause).0,
+  }
+}
+
+/// Finds a scattered definition beneath any wrappers, recording the wrappers innermost first.
+fn unwrap_scattered<'a>(
+  definition: &'a LocatedDefinition,
+  wrappers  : &mut Vec<(Wrapper, SourceLocation)>,
+) -> Option<&'a LocatedScatteredDefinition> {
+  let wrapper = match &definition.value {
+    Definition::ScatteredDefinition(scattered) => return Some(scattered),
+    Definition::Private(_) => Wrapper::Private,
+    Definition::Attribute(name, data, _) => Wrapper::Attribute(name.clone(), data.clone()),
+    Definition::Documentation(text, _) => Wrapper::Documentation(text.clone()),
+    _ => return None,
+  };
+  let (Definition::Private(inner) | Definition::Attribute(_, _, inner) | Definition::Documentation(_, inner)) =
+      &definition.value else { unreachable!() };
+  let found = unwrap_scattered(inner, wrappers);
+  if found.is_some() {
+    wrappers.push((wrapper, definition.location.clone()));
+  }
+  found
+}
+
+// region Rewrapping
+
+fn wrap_definition(definition: LocatedDefinition, wrappers: &[(Wrapper, SourceLocation)]) -> LocatedDefinition {
+  wrappers.iter().fold(definition, |inner, (wrapper, location)| {
+    let value = match wrapper.clone() {
+      Wrapper::Private => Definition::Private(Box::new(inner)),
+      Wrapper::Attribute(name, data) => Definition::Attribute(name, data, Box::new(inner)),
+      Wrapper::Documentation(text) => Definition::Documentation(text, Box::new(inner)),
+    };
+    Located { location: location.clone(), value }
+  })
+}
+
+fn wrap_function_clause(clause: LocatedFunctionClause, wrappers: &[(Wrapper, SourceLocation)]) -> LocatedFunctionClause {
+  wrappers.iter().fold(clause, |inner, (wrapper, location)| {
+    let value = match wrapper.clone() {
+      Wrapper::Private => FunctionClause::Private(Box::new(inner)),
+      Wrapper::Attribute(name, data) => FunctionClause::Attribute(name, data, Box::new(inner)),
+      Wrapper::Documentation(text) => FunctionClause::Documentation(text, Box::new(inner)),
+    };
+    Located { location: location.clone(), value }
+  })
+}
+
+fn wrap_type_union(union: LocatedTypeUnion, wrappers: &[(Wrapper, SourceLocation)]) -> LocatedTypeUnion {
+  wrappers.iter().fold(union, |inner, (wrapper, location)| {
+    let value = match wrapper.clone() {
+      Wrapper::Private => TypeUnion::Private(Box::new(inner)),
+      Wrapper::Attribute(name, data) => TypeUnion::Attribute(name, data, Box::new(inner)),
+      Wrapper::Documentation(text) => TypeUnion::Documentation(text, Box::new(inner)),
+    };
+    Located { location: location.clone(), value }
+  })
+}
+
+fn wrap_mapping_clause(clause: LocatedMappingClause, wrappers: &[(Wrapper, SourceLocation)]) -> LocatedMappingClause {
+  wrappers.iter().fold(clause, |inner, (wrapper, location)| {
+    let value = match wrapper.clone() {
+      // Mapping clauses cannot be private on their own; the mapping as a whole carries its visibility.
+      Wrapper::Private => return inner,
+      Wrapper::Attribute(name, data) => MappingClause::Attribute(name, data, Box::new(inner)),
+      Wrapper::Documentation(text) => MappingClause::Documentation(text, Box::new(inner)),
+    };
+    Located { location: location.clone(), value }
+  })
+}
+
+// endregion
+
+/// Builds the ordinary definition for a fully collected scattered definition.
+fn combine(scattered: Scattered) -> LocatedDefinition {
+  let location = scattered.open.location.clone();
+
+  let definition = match scattered.open.value {
+    ScatteredDefinition::Function(recursive, annotation, effect, _) => {
+      let clauses = scattered.clauses
+          .into_iter()
+          .filter_map(|clause| match clause { Clause::Function(clause) => Some(clause), _ => None })
+          .collect();
+      Definition::FunctionDefinition(Located::from((FunctionDefinition::Function(recursive, annotation, effect, clauses), location.clone())))
+    }
+    ScatteredDefinition::Variant(name, quantifier) => {
+      let unions = scattered.clauses
+          .into_iter()
+          .filter_map(|clause| match clause { Clause::Union(union) => Some(union), _ => None })
+          .collect();
+      Definition::TypeDefinition(Located::from((TypeDefinition::Variant(name, quantifier, unions), location.clone())))
+    }
+    ScatteredDefinition::Enumeration(name) => {
+      let members = scattered.clauses
+          .into_iter()
+          .filter_map(|clause| match clause { Clause::Member(member) => Some((member, None)), _ => None })
+          .collect();
+      Definition::TypeDefinition(Located::from((TypeDefinition::Enum(name, Vec::new(), members), location.clone())))
+    }
+    ScatteredDefinition::Mapping(name, annotation) => {
+      let clauses = scattered.clauses
+          .into_iter()
+          .filter_map(|clause| match clause { Clause::Mapping(clause) => Some(*clause), _ => None })
+          .collect();
+      let scheme = annotation.map(|annotation| {
+        annotation.map(|(quantifier, abstract_type)| Located {
+          location: abstract_type.location.clone(),
+          value   : TypeScheme { quantifier, abstract_type: *abstract_type },
+        })
+      });
+      Definition::MappingDefinition(Located::from((MappingDefinition::Mapping(name, scheme, clauses), location.clone())))
+    }
+    _ => unreachable!("only opening declarations are recorded as scattered definitions"),
+  };
+
+  wrap_definition(Located::from((definition, location)), &scattered.wrappers)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+
+  /// The names of the definitions in a single file, as `kind name`.
+  fn outline(definitions: &Definitions) -> Vec<String> {
+    definitions.0[0].1
+        .iter()
+        .map(|definition| match &definition.value {
+          Definition::TypeDefinition(typ) => match &typ.value {
+            TypeDefinition::Variant(name, _, unions) => format!("union {} ({})", name.name(), unions.len()),
+            TypeDefinition::Enum(name, _, members) => format!("enum {} ({})", name.name(), members.len()),
+            _ => "type".to_string(),
+          },
+          Definition::FunctionDefinition(function) => {
+            let FunctionDefinition::Function(_, _, _, clauses) = &function.value;
+            format!("function {} ({})", strip_function_clause(&clauses[0]).0.name(), clauses.len())
+          }
+          Definition::ValueSpec(specification) => {
+            let ValueSpecification::ValueSpec(_, name, _) = &specification.value;
+            format!("val {}", name.name())
+          }
+          _ => "other".to_string(),
+        })
+        .collect()
+  }
+
+  fn function_clause(name: &str) -> LocatedDefinition {
+    let clause = located(FunctionClause::Clause(id(name), Box::new(arm(wildcard(), unit()))));
+    scattered(ScatteredDefinition::FunctionClause(clause))
+  }
+
+  #[test]
+  fn types_are_placed_where_they_are_opened() {
+    let union_clause = |constructor: &str| {
+      ScatteredDefinition::UnionClause(id("ast"), located(TypeUnion::TypeIdentifier(Box::new(typ("unit")), id(constructor))))
+    };
+    let program = vec![
+      scattered(ScatteredDefinition::Variant(id("ast"), located(TypeQuantifier::NoForAll))),
+      scattered(ScatteredDefinition::Enumeration(id("kind"))),
+      val("execute", function_type(vec![typ("ast")], typ("unit"))),
+      scattered(union_clause("ADD")),
+      scattered(ScatteredDefinition::EnumerationMember(id("kind"), id("Arithmetic"))),
+      scattered(union_clause("SUB")),
+      scattered(ScatteredDefinition::End(id("ast"))),
+      scattered(ScatteredDefinition::End(id("kind"))),
+    ];
+
+    let (definitions, errors) = collect_scattered(definitions(program));
+    assert!(errors.is_empty());
+    assert_eq!(outline(&definitions), vec!["union ast (2)", "enum kind (1)", "val execute"]);
+  }
+
+  #[test]
+  fn functions_are_placed_at_their_last_clause() {
+    let program = vec![
+      scattered(ScatteredDefinition::Function(located(None), located(None), located(None), id("execute"))),
+      function_clause("execute"),
+      val("helper", function_type(vec![typ("unit")], typ("unit"))),
+      function_clause("execute"),
+      scattered(ScatteredDefinition::End(id("execute"))),
+    ];
+
+    let (definitions, errors) = collect_scattered(definitions(program));
+    assert!(errors.is_empty());
+    assert_eq!(outline(&definitions), vec!["val helper", "function execute (2)"]);
+  }
+
+  #[test]
+  fn unclosed_definitions_are_reported() {
+    let program = vec![scattered(ScatteredDefinition::Enumeration(id("kind")))];
+
+    let (_, errors) = collect_scattered(definitions(program));
+    let messages: Vec<String> = errors.iter().map(|error| error.value.to_string()).collect();
+    assert_eq!(messages, vec!["scattered definition `kind` is never closed with `end`".to_string()]);
+  }
+}
@@ -0,0 +1,1563 @@
+/*!
+
+Termination checking.
+
+Sail lets a recursive function be given a termination measure, an integer expression over its arguments, either in its
+definition or in a separate `termination_measure` definition. Loops can be given one too, either inline or in a
+`termination_measure` definition naming the function they are in, which lists the measures of that function's loops
+in order. A measure must be non-negative and strictly decrease at every recursive call and on every iteration of a loop;
+this pass verifies that it does.
+
+Recursion is found in the call graph. A call is recursive if its callee is in the same strongly connected component as
+its caller, so calls between mutually recursive functions are checked against the callee's measure applied to the
+arguments, which must be less than the caller's measure of its own parameters. `group_recursive` uses the same
+components to put each group of mutually recursive functions into a single `Definition::InternalMutRec`.
+
+Function bodies are evaluated symbolically. Each integer variable's value is tracked as a `NumericExpression`, along
+with what is known on the current path: the function's constraint, what the types of its parameters say about them, the
+conditions of enclosing `if`s and guards, the clauses and arms that did not match, and asserted conditions. Where
+paths join, a variable with different values on each becomes a new variable equal to one or the other. Only integer
+arithmetic and comparisons are interpreted; the value of anything else is taken from the type the checker recorded for
+it, if that is a singleton integer type, and is unknown otherwise. The proof obligations are discharged like the type
+checker's own constraints, by `typecheck::omega` and then the optional external solver.
+
+A `while` loop's measure must be non-negative and decrease over one run of its body, assuming the loop condition held;
+an `until` loop's measure must decrease over one run of its body and be non-negative after it, assuming the loop goes
+round again. Variables the loop assigns to, and all registers, may have any value at the start of an iteration.
+
+Functions and loops without measures are not checked, as Sail only requires them for backends that need to know that
+every function terminates. A recursive call between two functions of which only one has a measure is reported.
+
+*/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::{
+  function_name, pattern_identifiers, resolve_expression_infix, strip_definition, strip_function_clause,
+};
+use crate::parser::location::{Located, SourceLocation};
+use crate::parser::visit::{walk_expression, walk_function_definition, Visitor};
+use crate::passes::typecheck::omega::{satisfiable, Satisfiability};
+use crate::passes::typecheck::{
+  Constraint, ConstraintSolver, NumericExpression, Substitution, Type, TypeArgument, Typing,
+};
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum TerminationError {
+  /// A recursive call at which the callee's measure cannot be shown to be smaller than the caller's.
+  NotDecreasing { caller: String, callee: String },
+  /// A call between mutually recursive functions only one of which has a measure.
+  MissingMeasure { caller: String, callee: String },
+  /// A loop whose measure cannot be shown to decrease over one iteration.
+  LoopNotDecreasing,
+  /// A measure that is not an integer expression the checker can interpret.
+  UnsupportedMeasure,
+  /// A `termination_measure` for a function that is not defined.
+  UnknownFunction(String),
+  /// A function or loop given more than one measure.
+  DuplicateMeasure(String),
+  /// More loop measures for a function than it has loops.
+  ExtraLoopMeasures(String),
+  /// A loop measure given for a different kind of loop than the one it applies to.
+  LoopKindMismatch { expected: LoopType, found: LoopType },
+}
+
+pub type LocatedTerminationError = Located<TerminationError>;
+
+fn loop_keyword(loop_type: &LoopType) -> &'static str {
+  match loop_type {
+    LoopType::While => "while",
+    LoopType::Until => "until",
+  }
+}
+
+impl TerminationError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TerminationError::NotDecreasing { caller, callee } => {
+        write!(f, "cannot prove that the termination measure decreases in this call of `{}` from `{}`", callee, caller)
+      }
+
+      TerminationError::MissingMeasure { caller, callee } => {
+        write!(
+          f,
+          "`{}` and `{}` are mutually recursive, but only one of them has a termination measure",
+          caller, callee
+        )
+      }
+
+      TerminationError::LoopNotDecreasing => {
+        write!(f, "cannot prove that the termination measure of this loop decreases")
+      }
+
+      TerminationError::UnsupportedMeasure => {
+        write!(f, "termination measure is not an integer expression that can be checked")
+      }
+
+      TerminationError::UnknownFunction(name) => {
+        write!(f, "termination measure for undefined function `{}`", name)
+      }
+
+      TerminationError::DuplicateMeasure(name) => {
+        write!(f, "`{}` is given more than one termination measure", name)
+      }
+
+      TerminationError::ExtraLoopMeasures(name) => {
+        write!(f, "more loop termination measures than `{}` has loops", name)
+      }
+
+      TerminationError::LoopKindMismatch { expected, found } => {
+        write!(
+          f,
+          "termination measure is for a `{}` loop, but the loop is a `{}` loop",
+          loop_keyword(expected),
+          loop_keyword(found)
+        )
+      }
+    }
+  }
+}
+
+impl Debug for TerminationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for TerminationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for TerminationError {}
+
+// region Call graph
+
+/// Every function definition in program order, including those inside wrappers and mutually recursive groups.
+fn function_definitions(definitions: &Definitions) -> Vec<&LocatedFunctionDefinition> {
+  let mut functions = Vec::new();
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      match &strip_definition(definition).0.value {
+        Definition::FunctionDefinition(function) => functions.push(function),
+        Definition::InternalMutRec(group) => functions.extend(group),
+        _ => {}
+      }
+    }
+  }
+  functions
+}
+
+/// Collects the names a function body calls, with overloaded names expanded to everything they stand for.
+struct Calls<'a> {
+  overloads: &'a HashMap<String, Vec<String>>,
+  calls    : BTreeSet<String>,
+}
+
+impl Calls<'_> {
+  fn call(&mut self, name: &str) {
+    let mut pending = vec![name.to_string()];
+    while let Some(name) = pending.pop() {
+      if !self.calls.insert(name.clone()) {
+        continue;
+      }
+      if let Some(targets) = self.overloads.get(&name) {
+        pending.extend(targets.iter().cloned());
+      }
+    }
+  }
+}
+
+impl Visitor for Calls<'_> {
+  fn visit_expression(&mut self, expression: &LocatedExpression) {
+    match &expression.value {
+      Expression::Application(function, _) => self.call(function.name()),
+      Expression::InfixApplication(_, operator, _) => self.call(operator.name()),
+      Expression::Infix(tokens) => {
+        for (token, _) in tokens {
+          if let InfixToken::Operator(operator) | InfixToken::Prefix(operator) = token {
+            self.call(operator.name());
+          }
+        }
+      }
+      _ => {}
+    }
+    walk_expression(self, expression);
+  }
+}
+
+/// The call graph between the functions defined in `definitions`, as the names of the functions in program order and
+/// the functions each calls.
+fn call_graph(definitions: &Definitions) -> (Vec<String>, HashMap<String, BTreeSet<String>>) {
+  let mut overloads: HashMap<String, Vec<String>> = HashMap::new();
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      if let Definition::Overload(name, targets) = &strip_definition(definition).0.value {
+        overloads
+            .entry(name.name().to_string())
+            .or_default()
+            .extend(targets.iter().map(|target| target.name().to_string()));
+      }
+    }
+  }
+
+  let functions = function_definitions(definitions);
+  let mut names = Vec::new();
+  let mut calls: HashMap<String, BTreeSet<String>> = HashMap::new();
+  for function in &functions {
+    let Some(name) = function_name(&function.value) else {
+      continue;
+    };
+    let mut visitor = Calls { overloads: &overloads, calls: BTreeSet::new() };
+    walk_function_definition(&mut visitor, function);
+    if !calls.contains_key(name.name()) {
+      names.push(name.name().to_string());
+    }
+    calls.entry(name.name().to_string()).or_default().extend(visitor.calls);
+  }
+  for callees in calls.values_mut() {
+    callees.retain(|callee| names.contains(callee));
+  }
+  (names, calls)
+}
+
+/// Tarjan's algorithm for the strongly connected components of the call graph.
+struct Components<'a> {
+  calls     : &'a HashMap<String, BTreeSet<String>>,
+  index     : HashMap<&'a str, usize>,
+  low       : HashMap<&'a str, usize>,
+  stack     : Vec<&'a str>,
+  on_stack  : HashSet<&'a str>,
+  components: Vec<Vec<String>>,
+}
+
+impl<'a> Components<'a> {
+  fn visit(&mut self, name: &'a str) {
+    let index = self.index.len();
+    self.index.insert(name, index);
+    self.low.insert(name, index);
+    self.stack.push(name);
+    self.on_stack.insert(name);
+
+    let calls = self.calls;
+    for callee in &calls[name] {
+      let callee = callee.as_str();
+      if !self.index.contains_key(callee) {
+        self.visit(callee);
+        let low = self.low[name].min(self.low[callee]);
+        self.low.insert(name, low);
+      } else if self.on_stack.contains(callee) {
+        let low = self.low[name].min(self.index[callee]);
+        self.low.insert(name, low);
+      }
+    }
+
+    if self.low[name] == index {
+      let mut component = Vec::new();
+      while let Some(member) = self.stack.pop() {
+        self.on_stack.remove(member);
+        component.push(member.to_string());
+        if member == name {
+          break;
+        }
+      }
+      self.components.push(component);
+    }
+  }
+}
+
+/// The groups of recursive functions in `definitions`: the strongly connected components of the call graph that are
+/// either a function calling itself or more than one function. Callees come before their callers, and the functions of
+/// each group are in program order.
+pub fn recursive_groups(definitions: &Definitions) -> Vec<Vec<String>> {
+  let (names, calls) = call_graph(definitions);
+  let mut components = Components {
+    calls     : &calls,
+    index     : HashMap::new(),
+    low       : HashMap::new(),
+    stack     : Vec::new(),
+    on_stack  : HashSet::new(),
+    components: Vec::new(),
+  };
+  for name in &names {
+    if !components.index.contains_key(name.as_str()) {
+      components.visit(name);
+    }
+  }
+
+  let order: HashMap<&str, usize> = names.iter().enumerate().map(|(index, name)| (name.as_str(), index)).collect();
+  let mut groups = components.components;
+  groups.retain(|group| group.len() > 1 || calls[&group[0]].contains(&group[0]));
+  for group in groups.iter_mut() {
+    group.sort_by_key(|name| order[name.as_str()]);
+  }
+  groups
+}
+
+/// Replaces the definitions of each group of mutually recursive functions with a single `Definition::InternalMutRec`,
+/// placed where the last of them was defined so that everything the group uses precedes it. Functions that are only
+/// recursive in themselves are left alone, as are the wrappers of functions outside groups; the wrappers of functions
+/// in a group are dropped, as `InternalMutRec` cannot hold them and privacy has already been resolved.
+pub fn group_recursive(definitions: Definitions) -> Definitions {
+  let groups: Vec<Vec<String>> = recursive_groups(&definitions).into_iter().filter(|group| group.len() > 1).collect();
+  let group_of: HashMap<String, usize> = groups
+      .iter()
+      .enumerate()
+      .flat_map(|(index, group)| group.iter().map(move |name| (name.clone(), index)))
+      .collect();
+  let mut members: Vec<BTreeMap<usize, LocatedFunctionDefinition>> = vec![BTreeMap::new(); groups.len()];
+
+  let mut files = Vec::new();
+  for (file_name, file) in definitions.0 {
+    let mut result = Vec::new();
+    for definition in file {
+      let functions = match &strip_definition(&definition).0.value {
+        Definition::FunctionDefinition(function) => vec![function.clone()],
+        Definition::InternalMutRec(group) => group.clone(),
+        _ => {
+          result.push(definition);
+          continue;
+        }
+      };
+      let is_group = matches!(strip_definition(&definition).0.value, Definition::InternalMutRec(_));
+
+      for function in functions {
+        let name = function_name(&function.value).map(|name| name.name().to_string());
+        let Some(&index) = name.as_ref().and_then(|name| group_of.get(name)) else {
+          match is_group {
+            true => {
+              let location = function.location.clone();
+              result.push(Located { location, value: Definition::FunctionDefinition(function) });
+            }
+            false => result.push(definition.clone()),
+          }
+          continue;
+        };
+        let position = groups[index].iter().position(|member| Some(member) == name.as_ref()).unwrap_or_default();
+        members[index].insert(position, function);
+        if members[index].len() == groups[index].len() {
+          let group: Vec<LocatedFunctionDefinition> = std::mem::take(&mut members[index]).into_values().collect();
+          result.push(Located { location: definition.location.clone(), value: Definition::InternalMutRec(group) });
+        }
+      }
+    }
+    files.push((file_name, result));
+  }
+  Definitions(files)
+}
+
+// endregion
+
+// region Symbolic evaluation
+
+/// The value of a pattern or expression: an integer, if it is one whose value is known, or a tuple of values.
+#[derive(Clone, Debug)]
+enum Shape {
+  Value(Option<NumericExpression>),
+  Tuple(Vec<Shape>),
+}
+
+impl Shape {
+  fn value(&self) -> Option<NumericExpression> {
+    match self {
+      Shape::Value(value) => value.clone(),
+      Shape::Tuple(_) => None,
+    }
+  }
+}
+
+/// What is known at one point of a function body.
+#[derive(Clone, Debug, Default)]
+struct State {
+  /// The variables in scope, innermost last, with their values if they are integers
+  locals   : Vec<(String, Option<NumericExpression>)>,
+  /// The values of the integer registers read so far
+  registers: HashMap<String, NumericExpression>,
+  /// What is known to be true on the current path
+  facts    : Vec<Constraint>,
+  /// Whether the current path has returned or thrown
+  dead     : bool,
+}
+
+/// The built-in operations the checker interprets, identified by the external function they are bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operation {
+  Add,
+  Subtract,
+  Multiply,
+  Negate,
+  Less,
+  LessEqual,
+  Greater,
+  GreaterEqual,
+  Equal,
+  NotEqual,
+  And,
+  Or,
+  Not,
+}
+
+impl Operation {
+  /// The operation the function `name` performs. The operators stand for the integer operations in function measures,
+  /// which the type checker does not see; `&` and `|` are left out, as they may equally be bitvector operations.
+  fn from_name(name: &str) -> Option<Operation> {
+    Some(match name {
+      "add_int" | "+" => Operation::Add,
+      "sub_int" | "-" => Operation::Subtract,
+      "mult_int" | "*" => Operation::Multiply,
+      "negate_int" => Operation::Negate,
+      "lt_int" | "<" => Operation::Less,
+      "lteq_int" | "<=" => Operation::LessEqual,
+      "gt_int" | ">" => Operation::Greater,
+      "gteq_int" | ">=" => Operation::GreaterEqual,
+      "eq_int" | "==" => Operation::Equal,
+      "neq_int" | "!=" => Operation::NotEqual,
+      "and_bool" => Operation::And,
+      "or_bool" => Operation::Or,
+      "not_bool" | "not" => Operation::Not,
+      _ => return None,
+    })
+  }
+
+  fn is_arithmetic(self) -> bool {
+    matches!(self, Operation::Add | Operation::Subtract | Operation::Multiply | Operation::Negate)
+  }
+}
+
+/// Collects the variables and registers assigned to in an expression.
+#[derive(Default)]
+struct Assigned(HashSet<String>);
+
+impl Assigned {
+  fn target(&mut self, target: &LocatedExpression) {
+    match &target.value {
+      Expression::Identifier(identifier) => {
+        self.0.insert(identifier.name().to_string());
+      }
+      Expression::Typed(_, inner)
+      | Expression::VectorAccess(inner, _)
+      | Expression::VectorSubrange(inner, _, _)
+      | Expression::Field(inner, _) => self.target(inner),
+      Expression::Tuple(elements) => {
+        for element in elements {
+          self.target(element);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+impl Visitor for Assigned {
+  fn visit_expression(&mut self, expression: &LocatedExpression) {
+    if let Expression::Assign(target, _) = &expression.value {
+      self.target(target);
+    }
+    walk_expression(self, expression);
+  }
+}
+
+fn literal_value(literal: &LocatedLiteral) -> Option<NumericExpression> {
+  match &literal.value {
+    Literal::Number(value) => Some(NumericExpression::Constant(value.clone())),
+    _ => None,
+  }
+}
+
+fn split_arm(arm: &LocatedPatternExpression) -> (&LocatedPattern, Option<&LocatedExpression>, &LocatedExpression) {
+  match &arm.value {
+    PatternExpression::Pattern(pattern, body) => (pattern, None, body),
+    PatternExpression::PatternWhen(pattern, guard, body) => (pattern, Some(guard), body),
+  }
+}
+
+struct Checker<'a, 's> {
+  typing          : &'a Typing,
+  solver          : Option<&'s mut dyn ConstraintSolver>,
+  /// The measure of each function that has one
+  measures        : HashMap<String, (&'a LocatedPattern, &'a LocatedExpression)>,
+  /// The loop measures given separately for each function, with where they were given
+  loop_measures   : HashMap<String, (SourceLocation, Vec<&'a LoopMeasure>)>,
+  /// The recursive group each recursive function belongs to
+  groups          : HashMap<String, usize>,
+  /// The external function each function with external bindings is bound to
+  externs         : HashMap<String, String>,
+  /// The function being checked
+  function        : String,
+  /// The measure of the function being checked, applied to its parameters
+  measure         : Option<NumericExpression>,
+  /// The separately given loop measures of the function being checked that are yet to be used
+  pending_measures: VecDeque<&'a LoopMeasure>,
+  state           : State,
+  fresh           : usize,
+  errors          : Vec<LocatedTerminationError>,
+}
+
+impl<'a, 's> Checker<'a, 's> {
+  fn new(definitions: &'a Definitions, typing: &'a Typing, solver: Option<&'s mut dyn ConstraintSolver>) -> Self {
+    let mut groups = HashMap::new();
+    for (index, group) in recursive_groups(definitions).into_iter().enumerate() {
+      groups.extend(group.into_iter().map(|name| (name, index)));
+    }
+    Checker {
+      typing,
+      solver,
+      measures        : HashMap::new(),
+      loop_measures   : HashMap::new(),
+      groups,
+      externs         : HashMap::new(),
+      function        : String::new(),
+      measure         : None,
+      pending_measures: VecDeque::new(),
+      state           : State::default(),
+      fresh           : 0,
+      errors          : Vec::new(),
+    }
+  }
+
+  fn error(&mut self, location: &SourceLocation, error: TerminationError) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  /// Records the measures and external bindings of every function, reporting measures for functions that do not exist.
+  fn declare(&mut self, definitions: &'a Definitions) {
+    let functions = function_definitions(definitions);
+    let mut defined = HashSet::new();
+    for function in &functions {
+      let FunctionDefinition::Function(recursive, _, _, _) = &function.value;
+      let Some(name) = function_name(&function.value) else {
+        continue;
+      };
+      defined.insert(name.name());
+      if let Some((pattern, expression)) = &recursive.value {
+        self.measures.insert(name.name().to_string(), (&**pattern, &**expression));
+      }
+    }
+
+    for (_, file) in definitions.0.iter() {
+      for definition in file {
+        let definition = strip_definition(definition).0;
+        match &definition.value {
+          Definition::ValueSpec(specification) => {
+            let ValueSpecification::ValueSpec(_, name, Some(bindings)) = &specification.value else {
+              continue;
+            };
+            let binding = bindings
+                .bindings
+                .iter()
+                .find(|(backend, _)| backend == "_")
+                .or(bindings.bindings.first());
+            if let Some((_, external)) = binding {
+              self.externs.insert(name.name().to_string(), external.clone());
+            }
+          }
+
+          Definition::Measure(name, pattern, expression) => {
+            if !defined.contains(name.name()) {
+              self.error(&name.location, TerminationError::UnknownFunction(name.name().to_string()));
+            } else if self.measures.contains_key(name.name()) {
+              self.error(&name.location, TerminationError::DuplicateMeasure(name.name().to_string()));
+            } else {
+              self.measures.insert(name.name().to_string(), (&**pattern, &**expression));
+            }
+          }
+
+          Definition::LoopMeasures(name, measures) => {
+            if !defined.contains(name.name()) {
+              self.error(&name.location, TerminationError::UnknownFunction(name.name().to_string()));
+            } else if self.loop_measures.contains_key(name.name()) {
+              self.error(&name.location, TerminationError::DuplicateMeasure(name.name().to_string()));
+            } else {
+              self.loop_measures
+                  .insert(name.name().to_string(), (name.location.clone(), measures.iter().collect()));
+            }
+          }
+
+          _ => {}
+        }
+      }
+    }
+  }
+
+  // region State
+
+  fn fresh(&mut self) -> NumericExpression {
+    self.fresh += 1;
+    NumericExpression::Variable(format!("'#t{}", self.fresh))
+  }
+
+  fn assume(&mut self, constraint: Constraint) {
+    if constraint != Constraint::True {
+      self.state.facts.push(constraint);
+    }
+  }
+
+  /// Whether `goal` follows from what is known on the current path.
+  fn prove(&mut self, goal: Constraint) -> bool {
+    let mut constraints = self.typing.environment.constraints.clone();
+    constraints.extend(self.state.facts.iter().cloned());
+    constraints.push(Constraint::negate(goal));
+    let mut answer = satisfiable(&constraints);
+    if let (Satisfiability::Unknown, Some(solver)) = (answer, self.solver.as_mut()) {
+      answer = solver.satisfiable(&constraints);
+    }
+    answer == Satisfiability::Unsatisfiable
+  }
+
+  /// Runs `f`, then forgets the variables it bound.
+  fn scoped<T, F: FnOnce(&mut Self) -> T>(&mut self, f: F) -> T {
+    let depth = self.state.locals.len();
+    let result = f(self);
+    self.state.locals.truncate(depth);
+    result
+  }
+
+  /// The value of a value of type `typ`, if it is an integer; existentials are opened, and their constraints assumed.
+  fn integer(&mut self, typ: &Type) -> Option<NumericExpression> {
+    match typ {
+      Type::Atom(value) => Some(value.clone()),
+      Type::Existential(variables, constraint, body) => {
+        let mut renaming = Substitution::new();
+        for (variable, kind) in variables {
+          let NumericExpression::Variable(name) = self.fresh() else {
+            unreachable!()
+          };
+          let argument = match kind {
+            Kind::Bool => TypeArgument::Bool(Constraint::Variable(name)),
+            _ => TypeArgument::Integer(NumericExpression::Variable(name)),
+          };
+          renaming.insert(variable.clone(), argument);
+        }
+        self.assume(constraint.substitute(&renaming));
+        self.integer(&body.substitute(&renaming))
+      }
+      _ => None,
+    }
+  }
+
+  /// The value of the expression or pattern at `location`, from the type the checker recorded for it.
+  fn recorded(&mut self, location: &SourceLocation) -> Option<NumericExpression> {
+    let typ = self.typing.types.get(location)?.clone();
+    self.integer(&typ)
+  }
+
+  /// The values of a value of type `typ`.
+  fn type_shape(&mut self, typ: &Type) -> Shape {
+    match typ {
+      Type::Tuple(elements) => Shape::Tuple(elements.iter().map(|element| self.type_shape(element)).collect()),
+      typ => Shape::Value(self.integer(typ)),
+    }
+  }
+
+  fn lookup(&mut self, name: &str, location: &SourceLocation) -> Option<NumericExpression> {
+    if let Some((_, value)) = self.state.locals.iter().rev().find(|(local, _)| local == name) {
+      return value.clone();
+    }
+    if let Some(value) = self.state.registers.get(name) {
+      return Some(value.clone());
+    }
+    let typ = self.typing.environment.registers.get(name)?.clone();
+    let value = self.integer(&typ).or_else(|| self.recorded(location))?;
+    self.state.registers.insert(name.to_string(), value.clone());
+    Some(value)
+  }
+
+  fn assign(&mut self, target: &LocatedExpression, value: Option<NumericExpression>) {
+    match &target.value {
+      Expression::Identifier(identifier) => {
+        let name = identifier.name();
+        if let Some(local) = self.state.locals.iter_mut().rev().find(|(local, _)| local == name) {
+          local.1 = value;
+        } else if self.typing.environment.registers.contains_key(name) {
+          match value {
+            Some(value) => self.state.registers.insert(name.to_string(), value),
+            None => self.state.registers.remove(name),
+          };
+        } else {
+          // An assignment to an undeclared variable declares it.
+          let value = value.or_else(|| self.recorded(&target.location));
+          self.state.locals.push((name.to_string(), value));
+        }
+      }
+      Expression::Typed(_, inner) => self.assign(inner, value),
+      Expression::Tuple(elements) => {
+        for element in elements {
+          self.assign(element, None);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Forgets the values of the variables assigned in `expressions` and of every register, as at the start of an
+  /// arbitrary iteration of a loop.
+  fn havoc(&mut self, expressions: &[&LocatedExpression]) {
+    let mut assigned = Assigned::default();
+    for expression in expressions {
+      assigned.visit_expression(expression);
+    }
+    let assigned: Vec<usize> = (self.state.locals.iter().enumerate())
+        .filter(|(_, (name, value))| value.is_some() && assigned.0.contains(name))
+        .map(|(index, _)| index)
+        .collect();
+    for index in assigned {
+      self.state.locals[index].1 = Some(self.fresh());
+    }
+    self.state.registers.clear();
+  }
+
+  /// Joins the states at the ends of alternative paths from `base`, and the values of the paths.
+  fn join(&mut self, base: State, paths: Vec<(State, Option<NumericExpression>)>) -> Option<NumericExpression> {
+    let mut live: Vec<(State, Option<NumericExpression>)> =
+        paths.into_iter().filter(|(state, _)| !state.dead).collect();
+    if live.len() <= 1 {
+      let Some((state, value)) = live.pop() else {
+        self.state = State { dead: true, ..base };
+        return None;
+      };
+      self.state = state;
+      return value;
+    }
+
+    let known = base.facts.len();
+    let mut state = base;
+    let mut disjuncts: Vec<Vec<Constraint>> = live.iter().map(|(path, _)| path.facts[known..].to_vec()).collect();
+    for (index, local) in state.locals.iter_mut().enumerate() {
+      let values = live.iter().map(|(path, _)| path.locals.get(index).and_then(|(_, value)| value.clone())).collect();
+      local.1 = self.join_values(values, &mut disjuncts);
+    }
+    state.registers.clear();
+    let registers: BTreeSet<&String> = live.iter().flat_map(|(path, _)| path.registers.keys()).collect();
+    for register in registers {
+      let values = live.iter().map(|(path, _)| path.registers.get(register).cloned()).collect();
+      if let Some(value) = self.join_values(values, &mut disjuncts) {
+        state.registers.insert(register.clone(), value);
+      }
+    }
+    let value = self.join_values(live.iter().map(|(_, value)| value.clone()).collect(), &mut disjuncts);
+
+    let disjunction = disjuncts.into_iter().map(Constraint::all).fold(Constraint::False, Constraint::or);
+    self.state = state;
+    self.assume(disjunction);
+    value
+  }
+
+  /// The join of the values a variable has at the ends of alternative paths: the value itself if it is the same on
+  /// every path, and otherwise a new variable equal to the value on each path.
+  fn join_values(
+    &mut self,
+    values   : Vec<Option<NumericExpression>>,
+    disjuncts: &mut [Vec<Constraint>],
+  ) -> Option<NumericExpression> {
+    let first = values.first()?.clone()?;
+    if values.iter().all(|value| value.as_ref() == Some(&first)) {
+      return Some(first);
+    }
+    if values.iter().any(Option::is_none) {
+      return None;
+    }
+    let joined = self.fresh();
+    for (value, facts) in values.into_iter().zip(disjuncts.iter_mut()) {
+      facts.push(Constraint::Equal(joined.clone(), value?));
+    }
+    Some(joined)
+  }
+
+  // endregion
+
+  // region Patterns
+
+  /// Binds the variables of `pattern` to the parts of `shape`, returning the shape of the value `pattern` describes.
+  fn bind(&mut self, pattern: &LocatedPattern, shape: Shape) -> Shape {
+    match &pattern.value {
+      Pattern::Identifier(identifier) if self.typing.environment.is_constant(identifier.name()) => Shape::Value(None),
+
+      Pattern::Identifier(identifier) => {
+        let value = shape.value().or_else(|| self.recorded(&pattern.location));
+        self.state.locals.push((identifier.name().to_string(), value.clone()));
+        match shape {
+          Shape::Tuple(_) => shape,
+          Shape::Value(_) => Shape::Value(value),
+        }
+      }
+
+      Pattern::Wildcard => shape,
+
+      Pattern::Literal(literal) => Shape::Value(literal_value(literal)),
+
+      Pattern::Typed(_, inner)
+      | Pattern::Variable(inner, _)
+      | Pattern::Attribute(_, _, inner) => self.bind(inner, shape),
+
+      Pattern::Tuple(elements) => {
+        let shapes = match shape {
+          Shape::Tuple(shapes) if shapes.len() == elements.len() => shapes,
+          _ => vec![Shape::Value(None); elements.len()],
+        };
+        Shape::Tuple(elements.iter().zip(shapes).map(|(element, shape)| self.bind(element, shape)).collect())
+      }
+
+      Pattern::Constructor(_, elements)
+      | Pattern::Vector(elements)
+      | Pattern::VectorConcat(elements)
+      | Pattern::List(elements)
+      | Pattern::StringAppend(elements) => {
+        for element in elements {
+          self.bind(element, Shape::Value(None));
+        }
+        Shape::Value(None)
+      }
+
+      Pattern::Cons(head, tail) => {
+        self.bind(head, Shape::Value(None));
+        self.bind(tail, Shape::Value(None));
+        Shape::Value(None)
+      }
+
+      Pattern::VectorSubrange(..)
+      | Pattern::Struct(_) => {
+        for identifier in pattern_identifiers(pattern) {
+          self.state.locals.push((identifier.name().to_string(), None));
+        }
+        Shape::Value(None)
+      }
+    }
+  }
+
+  /// The condition under which `pattern` matches a value of shape `shape`, if it can be expressed.
+  fn matches(&self, pattern: &LocatedPattern, shape: &Shape) -> Option<Constraint> {
+    match &pattern.value {
+      Pattern::Identifier(identifier) if self.typing.environment.is_constant(identifier.name()) => None,
+      Pattern::Identifier(_)
+      | Pattern::Wildcard => Some(Constraint::True),
+      Pattern::Literal(literal) => Some(Constraint::Equal(shape.value()?, literal_value(literal)?)),
+      Pattern::Typed(_, inner)
+      | Pattern::Variable(inner, _)
+      | Pattern::Attribute(_, _, inner) => self.matches(inner, shape),
+      Pattern::Tuple(elements) => {
+        let Shape::Tuple(shapes) = shape else {
+          return None;
+        };
+        let conditions: Option<Vec<Constraint>> =
+            elements.iter().zip(shapes).map(|(element, shape)| self.matches(element, shape)).collect();
+        Some(Constraint::all(conditions?))
+      }
+      _ => None,
+    }
+  }
+
+  /// The shape of `expression`, evaluating it.
+  fn shape(&mut self, expression: &LocatedExpression) -> Shape {
+    match &expression.value {
+      Expression::Tuple(elements) => Shape::Tuple(elements.iter().map(|element| self.shape(element)).collect()),
+      _ => Shape::Value(self.value(expression)),
+    }
+  }
+
+  /// Evaluates the arms of a `match` or `try` on a value of shape `scrutinee`. Each arm may assume that the arms
+  /// before it without guards did not match.
+  fn arms(&mut self, scrutinee: Shape, arms: &[LocatedPatternExpression]) -> Option<NumericExpression> {
+    let base = self.state.clone();
+    let mut excluded: Vec<Constraint> = Vec::new();
+    let mut paths = Vec::new();
+    for arm in arms {
+      let (pattern, guard, body) = split_arm(arm);
+      self.state = base.clone();
+      for condition in &excluded {
+        self.assume(Constraint::negate(condition.clone()));
+      }
+      let condition = self.matches(pattern, &scrutinee);
+      let value = self.scoped(|this| {
+        this.bind(pattern, scrutinee.clone());
+        if let Some(condition) = &condition {
+          this.assume(condition.clone());
+        }
+        if let Some(condition) = guard.and_then(|guard| this.condition(guard)) {
+          this.assume(condition);
+        }
+        this.value(body)
+      });
+      paths.push((std::mem::take(&mut self.state), value));
+      if let (Some(condition), None) = (condition, guard) {
+        excluded.push(condition);
+      }
+    }
+    self.join(base, paths)
+  }
+
+  // endregion
+
+  // region Expressions
+
+  /// Evaluates a condition, returning it as a constraint if it can be expressed as one.
+  fn condition(&mut self, expression: &LocatedExpression) -> Option<Constraint> {
+    let operands = match &expression.value {
+      Expression::Literal(literal) => match literal.value {
+        Literal::True => return Some(Constraint::True),
+        Literal::False => return Some(Constraint::False),
+        _ => None,
+      },
+      Expression::Application(function, arguments) => Some((function, arguments.iter().collect::<Vec<_>>())),
+      Expression::InfixApplication(left, operator, right) => Some((operator, vec![&**left, &**right])),
+      Expression::Infix(tokens) => {
+        let tree = resolve_expression_infix(tokens, &self.typing.environment.fixities)?;
+        return self.condition(&tree);
+      }
+      Expression::Typed(_, inner)
+      | Expression::Attribute(_, _, inner) => return self.condition(inner),
+      _ => None,
+    };
+
+    let operation = operands.as_ref().and_then(|(function, _)| self.operation(&expression.location, function));
+    let (Some(operation), Some((_, arguments))) = (operation, operands) else {
+      self.value(expression);
+      return match self.typing.types.get(&expression.location) {
+        Some(Type::AtomBool(constraint)) => Some(constraint.clone()),
+        _ => None,
+      };
+    };
+
+    match (operation, arguments.as_slice()) {
+      (Operation::Not, [inner]) => self.condition(inner).map(Constraint::negate),
+
+      (Operation::And | Operation::Or, [left, right]) => {
+        // The right operand is only evaluated when the left one leaves the result open.
+        let left = self.condition(left);
+        let known = self.state.facts.len();
+        if let Some(left) = &left {
+          match operation {
+            Operation::And => self.assume(left.clone()),
+            _ => self.assume(Constraint::negate(left.clone())),
+          }
+        }
+        let right = self.condition(right);
+        self.state.facts.truncate(known);
+        let (left, right) = (left?, right?);
+        Some(match operation {
+          Operation::And => Constraint::and(left, right),
+          _ => Constraint::or(left, right),
+        })
+      }
+
+      (_, [left, right]) => {
+        let left = self.value(left);
+        let right = self.value(right);
+        let (left, right) = (left?, right?);
+        match operation {
+          Operation::Less => Some(Constraint::Less(left, right)),
+          Operation::LessEqual => Some(Constraint::LessEqual(left, right)),
+          Operation::Greater => Some(Constraint::Greater(left, right)),
+          Operation::GreaterEqual => Some(Constraint::GreaterEqual(left, right)),
+          Operation::Equal => Some(Constraint::Equal(left, right)),
+          Operation::NotEqual => Some(Constraint::NotEqual(left, right)),
+          _ => None,
+        }
+      }
+
+      _ => {
+        for argument in arguments {
+          self.value(argument);
+        }
+        None
+      }
+    }
+  }
+
+  /// The operation a call of `function` at `location` performs, if it is one the checker interprets.
+  fn operation(&self, location: &SourceLocation, function: &LocatedIdentifier) -> Option<Operation> {
+    match self.typing.calls.get(location) {
+      Some(call) => Operation::from_name(self.externs.get(&call.function).unwrap_or(&call.function)),
+      None => Operation::from_name(self.externs.get(function.name()).map_or(function.name(), String::as_str)),
+    }
+  }
+
+  /// Evaluates `expression`, returning its value if it is an integer whose value can be expressed.
+  fn value(&mut self, expression: &LocatedExpression) -> Option<NumericExpression> {
+    if self.state.dead {
+      return None;
+    }
+    let location = &expression.location;
+    let value = match &expression.value {
+      Expression::Literal(literal) => literal_value(literal),
+
+      Expression::Identifier(identifier) => self.lookup(identifier.name(), location),
+
+      Expression::Typed(_, inner)
+      | Expression::Attribute(_, _, inner)
+      | Expression::InternalAssume(_, inner) => self.value(inner),
+
+      Expression::Block(expressions) => self.scoped(|this| {
+        let mut value = None;
+        for expression in expressions {
+          value = this.value(expression);
+        }
+        value
+      }),
+
+      Expression::Application(function, _)
+      | Expression::InfixApplication(_, function, _)
+        if self.operation(location, function).is_some_and(|operation| !operation.is_arithmetic()) =>
+      {
+        // A comparison or boolean operation, whose value is not an integer
+        self.condition(expression);
+        None
+      }
+
+      Expression::Application(function, arguments) => self.call(location, function, arguments.iter().collect()),
+
+      Expression::InfixApplication(left, operator, right) => self.call(location, operator, vec![&**left, &**right]),
+
+      Expression::Infix(tokens) => match resolve_expression_infix(tokens, &self.typing.environment.fixities) {
+        Some(tree) => self.value(&tree),
+        None => None,
+      },
+
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        let condition = self.condition(condition);
+        let base = self.state.clone();
+        let mut paths = Vec::new();
+        let branches = [(then_expr, condition.clone()), (else_expr, condition.map(Constraint::negate))];
+        for (expression, condition) in branches {
+          self.state = base.clone();
+          if let Some(condition) = condition {
+            self.assume(condition);
+          }
+          let value = self.scoped(|this| this.value(expression));
+          paths.push((std::mem::take(&mut self.state), value));
+        }
+        self.join(base, paths)
+      }
+
+      Expression::Match(scrutinee, arms) => {
+        let scrutinee = self.shape(scrutinee);
+        self.arms(scrutinee, arms)
+      }
+
+      Expression::Try(body, arms) => {
+        let base = self.state.clone();
+        let value = self.value(body);
+        let finished = std::mem::replace(&mut self.state, base.clone());
+        // A handler may run after any part of the body.
+        self.havoc(&[body]);
+        let handled_value = self.arms(Shape::Value(None), arms);
+        let handled = std::mem::take(&mut self.state);
+        self.join(base, vec![(finished, value), (handled, handled_value)])
+      }
+
+      Expression::Let(binding, body) => {
+        let LetBinding::ValueBinding(pattern, bound) = &binding.value;
+        let shape = self.shape(bound);
+        self.scoped(|this| {
+          this.bind(pattern, shape);
+          this.value(body)
+        })
+      }
+
+      Expression::Variable(target, bound, body) => {
+        let value = self.value(bound);
+        self.scoped(|this| {
+          let mut target = &**target;
+          while let Expression::Typed(_, inner) = &target.value {
+            target = inner;
+          }
+          if let Expression::Identifier(identifier) = &target.value {
+            let value = value.or_else(|| this.recorded(&target.location));
+            this.state.locals.push((identifier.name().to_string(), value));
+          }
+          this.value(body)
+        })
+      }
+
+      Expression::InternalPlet(pattern, bound, body) => {
+        let shape = self.shape(bound);
+        self.scoped(|this| {
+          this.bind(pattern, shape);
+          this.value(body)
+        })
+      }
+
+      Expression::Assign(target, value) => {
+        let value = self.value(value);
+        self.assign(target, value);
+        None
+      }
+
+      Expression::Loop(loop_type, measure, condition, body) => {
+        self.loop_iteration(location, loop_type, measure, condition, body);
+        None
+      }
+
+      Expression::For { identifier, start, end, step, typ, body } => {
+        let start = self.value(start);
+        let end = self.value(end);
+        self.value(step);
+        self.havoc(&[body]);
+        let after = self.state.clone();
+        let index = self.fresh();
+        let (low, high) = match typ.value {
+          AbstractType::Decreasing => (end, start),
+          _ => (start, end),
+        };
+        if let Some(low) = low {
+          self.assume(Constraint::LessEqual(low, index.clone()));
+        }
+        if let Some(high) = high {
+          self.assume(Constraint::LessEqual(index.clone(), high));
+        }
+        self.scoped(|this| {
+          this.state.locals.push((identifier.name().to_string(), Some(index)));
+          this.value(body)
+        });
+        self.state = after;
+        None
+      }
+
+      Expression::Return(inner)
+      | Expression::InternalReturn(inner)
+      | Expression::Throw(inner)
+      | Expression::Exit(inner) => {
+        self.value(inner);
+        self.state.dead = true;
+        None
+      }
+
+      Expression::Assert(condition, message) => {
+        let condition = self.condition(condition);
+        self.value(message);
+        if let Some(condition) = condition {
+          self.assume(condition);
+        }
+        None
+      }
+
+      Expression::Tuple(elements)
+      | Expression::Vector(elements)
+      | Expression::List(elements)
+      | Expression::Struct(elements) => {
+        for element in elements {
+          self.value(element);
+        }
+        None
+      }
+
+      Expression::StructUpdate(inner, fields) => {
+        self.value(inner);
+        for field in fields {
+          self.value(field);
+        }
+        None
+      }
+
+      Expression::Dereference(inner)
+      | Expression::Field(inner, _) => {
+        self.value(inner);
+        None
+      }
+
+      Expression::VectorAccess(first, second)
+      | Expression::VectorAppend(first, second)
+      | Expression::Cons(first, second) => {
+        self.value(first);
+        self.value(second);
+        None
+      }
+
+      Expression::VectorSubrange(first, second, third)
+      | Expression::VectorUpdate(first, second, third) => {
+        self.value(first);
+        self.value(second);
+        self.value(third);
+        None
+      }
+
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        self.value(vector);
+        self.value(high);
+        self.value(low);
+        self.value(value);
+        None
+      }
+
+      Expression::Reference(_)
+      | Expression::Sizeof(_)
+      | Expression::Constraint(_) => None,
+    };
+    value.or_else(|| self.recorded(location))
+  }
+
+  /// Evaluates a call, checking it if it is recursive.
+  fn call(
+    &mut self,
+    location : &SourceLocation,
+    function : &LocatedIdentifier,
+    arguments: Vec<&LocatedExpression>,
+  ) -> Option<NumericExpression> {
+    if let Some(operation) = self.operation(location, function) {
+      let values: Vec<Option<NumericExpression>> = arguments.iter().map(|argument| self.value(argument)).collect();
+      return match (operation, values.as_slice()) {
+        (Operation::Negate | Operation::Subtract, [Some(inner)]) => {
+          Some(NumericExpression::Negative(Box::new(inner.clone())))
+        }
+        (Operation::Add, [Some(left), Some(right)]) => Some(NumericExpression::sum(left.clone(), right.clone())),
+        (Operation::Subtract, [Some(left), Some(right)]) => Some(NumericExpression::minus(left.clone(), right.clone())),
+        (Operation::Multiply, [Some(left), Some(right)]) => {
+          Some(NumericExpression::Times(Box::new(left.clone()), Box::new(right.clone())))
+        }
+        _ => None,
+      };
+    }
+
+    let shape = match arguments.as_slice() {
+      [argument] => self.shape(argument),
+      arguments => Shape::Tuple(arguments.iter().map(|argument| self.shape(argument)).collect()),
+    };
+    if self.state.dead {
+      return None;
+    }
+    let callee = match self.typing.calls.get(location) {
+      Some(call) => call.function.clone(),
+      None => function.name().to_string(),
+    };
+    self.recursive_call(location, &callee, shape);
+    // The callee may have written to any register.
+    self.state.registers.clear();
+    None
+  }
+
+  // endregion
+
+  // region Measures
+
+  /// The value of the measure `(pattern, expression)` applied to a value of shape `shape`.
+  fn apply_measure(
+    &mut self,
+    pattern   : &LocatedPattern,
+    expression: &LocatedExpression,
+    shape     : Shape,
+  ) -> Option<NumericExpression> {
+    // Calls in the measure are not recursive calls of the function being checked.
+    let function = std::mem::take(&mut self.function);
+    let value = self.scoped(|this| {
+      this.bind(pattern, shape);
+      this.value(expression)
+    });
+    self.function = function;
+    value
+  }
+
+  /// Checks a call of `callee` with an argument of shape `shape` from the function being checked.
+  fn recursive_call(&mut self, location: &SourceLocation, callee: &str, shape: Shape) {
+    let Some(group) = self.groups.get(&self.function) else {
+      return;
+    };
+    if self.groups.get(callee) != Some(group) {
+      return;
+    }
+    let caller_measured = self.measures.contains_key(&self.function);
+    let Some(&(pattern, expression)) = self.measures.get(callee) else {
+      if caller_measured {
+        let error = TerminationError::MissingMeasure { caller: self.function.clone(), callee: callee.to_string() };
+        self.error(location, error);
+      }
+      return;
+    };
+    if !caller_measured {
+      let error = TerminationError::MissingMeasure { caller: self.function.clone(), callee: callee.to_string() };
+      self.error(location, error);
+      return;
+    }
+    // The caller's measure could not be interpreted, which has been reported already.
+    let Some(measure) = self.measure.clone() else {
+      return;
+    };
+
+    let decreases = match self.apply_measure(pattern, expression, shape) {
+      Some(next) => {
+        let goal = Constraint::and(
+          Constraint::LessEqual(NumericExpression::constant(0), next.clone()),
+          Constraint::Less(next, measure),
+        );
+        self.prove(goal)
+      }
+      None => false,
+    };
+    if !decreases {
+      let error = TerminationError::NotDecreasing { caller: self.function.clone(), callee: callee.to_string() };
+      self.error(location, error);
+    }
+  }
+
+  /// Checks a loop's measure over one iteration, and leaves the state as it is after the loop.
+  fn loop_iteration(
+    &mut self,
+    location : &SourceLocation,
+    loop_type: &LoopType,
+    measure  : &LocatedMeasure,
+    condition: &LocatedExpression,
+    body     : &LocatedExpression,
+  ) {
+    let separate = self.pending_measures.pop_front();
+    let measure = match (&measure.value, separate) {
+      (Some(measure), Some(_)) => {
+        let error = TerminationError::DuplicateMeasure(format!("this `{}` loop", loop_keyword(loop_type)));
+        self.error(&measure.location, error);
+        Some(measure)
+      }
+      (Some(measure), None) => Some(measure),
+      (None, Some(separate)) => {
+        if separate.loop_type != *loop_type {
+          let expected = separate.loop_type.clone();
+          self.error(location, TerminationError::LoopKindMismatch { expected, found: loop_type.clone() });
+        }
+        Some(&*separate.expression)
+      }
+      (None, None) => None,
+    };
+
+    self.havoc(&[condition, body]);
+    let (before, after, exit) = match loop_type {
+      LoopType::While => {
+        let condition = self.condition(condition);
+        let exit = self.state.clone();
+        if let Some(condition) = &condition {
+          self.assume(condition.clone());
+        }
+        let before = measure.and_then(|measure| self.value(measure));
+        self.value(body);
+        let after = measure.and_then(|measure| self.value(measure));
+        let mut exit = exit;
+        if let Some(condition) = condition {
+          exit.facts.push(Constraint::negate(condition));
+        }
+        (before, after, exit)
+      }
+      LoopType::Until => {
+        let before = measure.and_then(|measure| self.value(measure));
+        self.value(body);
+        let condition = self.condition(condition);
+        let mut exit = self.state.clone();
+        if let Some(condition) = condition {
+          exit.facts.push(condition.clone());
+          self.assume(Constraint::negate(condition));
+        }
+        let after = measure.and_then(|measure| self.value(measure));
+        (before, after, exit)
+      }
+    };
+
+    if let Some(measure) = measure {
+      match (before, after) {
+        // The body never finishes, so there is no next iteration.
+        _ if self.state.dead => {}
+        (Some(before), Some(after)) => {
+          let non_negative = match loop_type {
+            LoopType::While => before.clone(),
+            LoopType::Until => after.clone(),
+          };
+          let goal = Constraint::and(
+            Constraint::LessEqual(NumericExpression::constant(0), non_negative),
+            Constraint::Less(after, before),
+          );
+          if !self.prove(goal) {
+            self.error(location, TerminationError::LoopNotDecreasing);
+          }
+        }
+        _ => self.error(&measure.location, TerminationError::UnsupportedMeasure),
+      }
+    }
+    self.state = exit;
+  }
+
+  // endregion
+
+  fn function(&mut self, function: &'a LocatedFunctionDefinition) {
+    let FunctionDefinition::Function(_, _, _, clauses) = &function.value;
+    let Some(name) = function_name(&function.value) else {
+      return;
+    };
+    self.function = name.name().to_string();
+    let separate = self.loop_measures.remove(name.name());
+    self.pending_measures =
+        separate.as_ref().map(|(_, measures)| measures.iter().copied().collect()).unwrap_or_default();
+
+    // What the function's type says about its parameters, shared by all its clauses
+    self.state = State::default();
+    let parameters = match self.typing.environment.functions.get(name.name()).cloned() {
+      Some(function_type) => {
+        self.assume(function_type.constraint.clone());
+        match function_type.arguments.as_slice() {
+          [argument] => self.type_shape(argument),
+          arguments => Shape::Tuple(arguments.iter().map(|argument| self.type_shape(argument)).collect()),
+        }
+      }
+      None => Shape::Value(None),
+    };
+    let base = self.state.clone();
+
+    let measure = self.measures.get(name.name()).copied();
+    let mut excluded: Vec<Constraint> = Vec::new();
+    let mut reported = false;
+    for clause in clauses {
+      let (_, arm) = strip_function_clause(clause);
+      let (pattern, guard, body) = split_arm(arm);
+      self.state = base.clone();
+      for condition in &excluded {
+        self.assume(Constraint::negate(condition.clone()));
+      }
+      let condition = self.matches(pattern, &parameters);
+      let shape = self.bind(pattern, parameters.clone());
+      if let Some(condition) = &condition {
+        self.assume(condition.clone());
+      }
+
+      self.measure = measure.and_then(|(pattern, expression)| self.apply_measure(pattern, expression, shape));
+      if let (Some((_, expression)), None, false) = (measure, &self.measure, reported) {
+        self.error(&expression.location, TerminationError::UnsupportedMeasure);
+        reported = true;
+      }
+
+      if let Some(condition) = guard.and_then(|guard| self.condition(guard)) {
+        self.assume(condition);
+      }
+      self.value(body);
+      if let (Some(condition), None) = (condition, guard) {
+        excluded.push(condition);
+      }
+    }
+
+    if let (Some((location, _)), false) = (separate, self.pending_measures.is_empty()) {
+      self.error(&location, TerminationError::ExtraLoopMeasures(name.name().to_string()));
+    }
+    self.pending_measures.clear();
+    self.measure = None;
+    self.function.clear();
+  }
+}
+
+// endregion
+
+/// Checks the termination measures of the functions and loops in `definitions`, which should have been type checked,
+/// consulting `solver` for constraints the built-in solver cannot decide.
+pub fn check_termination(
+  definitions: &Definitions,
+  typing     : &Typing,
+  solver     : Option<&mut dyn ConstraintSolver>,
+) -> Vec<LocatedTerminationError> {
+  let mut checker = Checker::new(definitions, typing, solver);
+  checker.declare(definitions);
+  for function in function_definitions(definitions) {
+    checker.function(function);
+  }
+  checker.errors
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+
+  /// `left & right`, with the `&` it applies.
+  fn and(left: &str, right: &str) -> (LocatedExpression, LocatedIdentifier) {
+    let and = operator("&");
+    (located(Expression::InfixApplication(Box::new(var(left)), and.clone(), Box::new(var(right)))), and)
+  }
+
+  /// Type checks `program`, with the integer externs the measures use, and checks its termination measures.
+  fn check(program: Vec<LocatedDefinition>) -> Vec<String> {
+    let arithmetic = |name: &str, result: &str| extern_val(name, name, function_type(vec![typ("int"), typ("int")], typ(result)));
+    let mut all = vec![arithmetic("sub_int", "int"), arithmetic("lt_int", "bool"), arithmetic("lteq_int", "bool")];
+    all.extend(program);
+    let definitions = definitions(all);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty(), "{:?}", errors);
+    check_termination(&definitions, &typing, None).iter().map(|error| error.value.to_string()).collect()
+  }
+
+  fn int_to_int(name: &str) -> LocatedDefinition {
+    val(name, function_type(vec![typ("int")], typ("int")))
+  }
+
+  /// `if n <= 0 then 0 else recursion`, measured by `n`.
+  fn countdown(name: &str, recursion: LocatedExpression) -> LocatedDefinition {
+    let body = if_then_else(call("lteq_int", vec![var("n"), number(0)]), number(0), recursion);
+    measured_function(name, vec![pattern("n")], (pattern("n"), var("n")), body)
+  }
+
+  /// `var i : int = n; while termination_measure { i } 0 < i do i = step; 0`
+  fn counting_loop(step: LocatedExpression) -> LocatedDefinition {
+    let body = block(vec![
+      measured_while_loop(var("i"), call("lt_int", vec![number(0), var("i")]), assign(var("i"), step)),
+      number(0),
+    ]);
+    function("f", vec![pattern("n")], var_in(typed(typ("int"), var("i")), var("n"), body))
+  }
+
+  #[test]
+  fn decreasing_measures_are_accepted() {
+    let errors = check(vec![int_to_int("f"), countdown("f", call("f", vec![call("sub_int", vec![var("n"), number(1)])]))]);
+    assert_eq!(errors, Vec::<String>::new());
+  }
+
+  #[test]
+  fn measures_that_do_not_decrease_are_reported() {
+    let errors = check(vec![int_to_int("f"), countdown("f", call("f", vec![var("n")]))]);
+    assert_eq!(errors, vec!["cannot prove that the termination measure decreases in this call of `f` from `f`".to_string()]);
+  }
+
+  #[test]
+  fn measures_must_not_become_negative() {
+    let body = call("f", vec![call("sub_int", vec![var("n"), number(1)])]);
+    let errors = check(vec![int_to_int("f"), measured_function("f", vec![pattern("n")], (pattern("n"), var("n")), body)]);
+    assert_eq!(errors, vec!["cannot prove that the termination measure decreases in this call of `f` from `f`".to_string()]);
+  }
+
+  #[test]
+  fn mutual_recursion_needs_a_measure_on_both_sides() {
+    let errors = check(vec![
+      int_to_int("f"),
+      int_to_int("g"),
+      countdown("f", call("g", vec![call("sub_int", vec![var("n"), number(1)])])),
+      function("g", vec![pattern("n")], call("f", vec![var("n")])),
+    ]);
+    assert_eq!(errors, vec![
+      "`f` and `g` are mutually recursive, but only one of them has a termination measure".to_string(),
+      "`g` and `f` are mutually recursive, but only one of them has a termination measure".to_string(),
+    ]);
+  }
+
+  #[test]
+  fn loop_measures_must_decrease() {
+    let decreasing = check(vec![int_to_int("f"), counting_loop(call("sub_int", vec![var("i"), number(1)]))]);
+    assert_eq!(decreasing, Vec::<String>::new());
+
+    let constant = check(vec![int_to_int("f"), counting_loop(var("i"))]);
+    assert_eq!(constant, vec!["cannot prove that the termination measure of this loop decreases".to_string()]);
+  }
+
+  #[test]
+  fn bitvector_and_is_not_a_conjunction() {
+    let (masked, bitvector_and) = and("x", "y");
+    let (conjunction, boolean_and) = and("a", "b");
+    let (masked_at, conjunction_at) = (masked.location.clone(), conjunction.location.clone());
+    let definitions = definitions(vec![
+      extern_val("and_bool", "and_bool", function_type(vec![typ("bool"), typ("bool")], typ("bool"))),
+      extern_val("and_vec", "and_vec", function_type(vec![bits(8), bits(8)], bits(8))),
+      overload("&", &["and_bool", "and_vec"]),
+      val("f", function_type(vec![bits(8), bits(8)], bits(8))),
+      function("f", vec![pattern("x"), pattern("y")], masked),
+      val("g", function_type(vec![typ("bool"), typ("bool")], typ("bool"))),
+      function("g", vec![pattern("a"), pattern("b")], conjunction),
+    ]);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let checker = Checker::new(&definitions, &typing, None);
+    assert_eq!(checker.operation(&masked_at, &bitvector_and), None);
+    assert_eq!(checker.operation(&conjunction_at, &boolean_and), Some(Operation::And));
+  }
+}
@@ -0,0 +1,2819 @@
+/*!
+
+The bidirectional checker proper: expressions, patterns and definitions.
+
+Type variables introduced while checking are given names that cannot be written in source, such as `'n#3`. They are
+either *rigid*, standing for some fixed but unknown value, as when a value of type `int` is opened into an
+`atom('n#3)`, or *unknowns* of a unification, as when a function's quantifiers are instantiated at a call, which are
+solved by matching the function's argument types against the types of the arguments.
+
+*/
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::ast_util::{expression_to_lvalue, resolve_expression_infix, strip_definition, strip_function_clause, strip_type_union, type_definition_name};
+use crate::parser::location::{Located, SourceLocation};
+use crate::passes::kinds::{self, TypeSignatures};
+use crate::passes::mappings::derived_names;
+use crate::passes::typecheck::environment::{quantifier_constraints, quantifier_variables, Bitfield, Converter, Environment, Record, Synonym, Variant};
+use crate::passes::typecheck::omega::{satisfiable, Satisfiability};
+use crate::passes::typecheck::solver::ConstraintSolver;
+use crate::passes::typecheck::types::{Constraint, FunctionType, NumericExpression, Substitution, Type, TypeArgument};
+use crate::passes::typecheck::{Call, LocatedTypeError, TypeError, Typing};
+
+#[derive(Debug, Clone)]
+struct Local {
+  typ    : Type,
+  mutable: bool,
+}
+
+/// What is in scope at some point within a definition.
+#[derive(Debug, Clone, Default)]
+struct Context {
+  locals        : HashMap<String, Local>,
+  /// Rigid type variables: the quantifiers of the enclosing function and any opened existentials
+  type_variables: HashMap<String, Kind>,
+  /// Facts known to hold, from the function's constraint, opened existentials and enclosing conditions
+  assumptions   : Vec<Constraint>,
+  /// The result type of the enclosing function, for `return`
+  return_type   : Option<Type>,
+}
+
+/// The state of matching a template type, containing unknowns, against an actual type.
+#[derive(Debug, Default)]
+struct Unification {
+  unknowns    : HashMap<String, Kind>,
+  substitution: Substitution,
+  /// Constraints that must hold for the match to succeed, such as the equality of two lengths
+  goals       : Vec<Constraint>,
+}
+
+impl Unification {
+  fn unresolved(&self) -> Vec<(String, Kind)> {
+    let mut unresolved: Vec<(String, Kind)> = self
+        .unknowns
+        .iter()
+        .filter(|(name, _)| !self.substitution.contains_key(*name))
+        .map(|(name, kind)| (name.clone(), *kind))
+        .collect();
+    unresolved.sort_by(|(a, _), (b, _)| a.cmp(b));
+    unresolved
+  }
+}
+
+/// A type variable of the given kind as a type argument.
+fn variable_argument(name: &str, kind: Kind) -> TypeArgument {
+  match kind {
+    Kind::Type => TypeArgument::Type(Type::Variable(name.to_string())),
+    Kind::Integer => TypeArgument::Integer(NumericExpression::variable(name)),
+    Kind::Bool => TypeArgument::Bool(Constraint::Variable(name.to_string())),
+    Kind::Order => TypeArgument::Order(false),
+  }
+}
+
+/// Forgets the exact value of integers and booleans, as is done for mutable variables and list elements.
+fn weaken(typ: Type) -> Type {
+  match typ {
+    Type::Atom(_) => Type::int(),
+    Type::AtomBool(_) => Type::bool(),
+    Type::Tuple(elements) => Type::Tuple(elements.into_iter().map(weaken).collect()),
+    Type::Existential(_, _, body) if body.is_integer() => Type::int(),
+    Type::Existential(_, _, body) if body.is_bool() => Type::bool(),
+    other => other,
+  }
+}
+
+fn conjuncts(constraint: Constraint, into: &mut Vec<Constraint>) {
+  match constraint {
+    Constraint::And(left, right) => {
+      conjuncts(*left, into);
+      conjuncts(*right, into);
+    }
+    Constraint::True => {}
+    other => into.push(other),
+  }
+}
+
+fn mentions(constraint: &Constraint, names: &BTreeSet<String>) -> bool {
+  let mut free = BTreeSet::new();
+  constraint.free_variables(&mut free);
+  free.iter().any(|name| names.contains(name))
+}
+
+/// The number of bits in a bitvector literal.
+fn literal_width(literal: &Literal) -> Option<NumericExpression> {
+  let (digits, bits_per_digit) = match literal {
+    Literal::Binary(digits) => (digits.trim_start_matches("0b"), 1),
+    Literal::Hexadecimal(digits) => (digits.trim_start_matches("0x"), 4),
+    _ => return None,
+  };
+  let count = digits.chars().filter(|c| *c != '_').count() as i64;
+  Some(NumericExpression::constant(count * bits_per_digit))
+}
+
+/// The width of `high..low` or `low..high`.
+fn subrange_width(high: &BigInteger, low: &BigInteger) -> NumericExpression {
+  let width = high
+      .try_sub(low)
+      .and_then(|difference| difference.try_abs())
+      .and_then(|difference| difference.try_add(&BigInteger::from_i64(1)))
+      .unwrap_or(BigInteger::from_i64(0));
+  NumericExpression::Constant(width)
+}
+
+/// The number of indices two lists of inclusive `(low, high)` intervals share, where the intervals of each list are
+/// disjoint.
+fn shared_bits(first: &[(BigInteger, BigInteger)], second: &[(BigInteger, BigInteger)]) -> BigInteger {
+  let mut total = BigInteger::from_i64(0);
+  for (low, high) in first {
+    for (other_low, other_high) in second {
+      let (start, end) = (low.clone().max(other_low.clone()), high.clone().min(other_high.clone()));
+      if start <= end {
+        let count = end.try_sub(&start).and_then(|difference| difference.try_add(&BigInteger::from_i64(1)));
+        total = count.and_then(|count| total.try_add(&count)).unwrap_or(total);
+      }
+    }
+  }
+  total
+}
+
+/// Expressions that cannot be checked without knowing the type they should have.
+fn needs_expected_type(expression: &LocatedExpression) -> bool {
+  match &expression.value {
+    Expression::Literal(literal) => literal.value == Literal::Undefined,
+    Expression::List(elements)
+    | Expression::Vector(elements) => elements.is_empty(),
+    Expression::Struct(_) => true,
+    _ => false,
+  }
+}
+
+pub(super) struct Checker<'a> {
+  environment    : Environment,
+  /// Consulted when the built-in solver gives up
+  solver         : Option<&'a mut dyn ConstraintSolver>,
+  context        : Context,
+  errors         : Vec<LocatedTypeError>,
+  types          : HashMap<SourceLocation, Type>,
+  calls          : HashMap<SourceLocation, Call>,
+  fresh          : usize,
+  /// Set while checking the second side of a bidirectional mapping clause, whose variables are those bound by the first
+  shared_bindings: bool,
+}
+
+impl<'a> Checker<'a> {
+  pub fn new(signatures: TypeSignatures, solver: Option<&'a mut dyn ConstraintSolver>) -> Self {
+    Checker {
+      environment    : Environment::new(signatures),
+      solver,
+      context        : Context::default(),
+      errors         : Vec::new(),
+      types          : HashMap::new(),
+      calls          : HashMap::new(),
+      fresh          : 0,
+      shared_bindings: false,
+    }
+  }
+
+  /// A checker continuing after the program `typing` is the result of checking.
+  pub fn resume(typing: Typing, solver: Option<&'a mut dyn ConstraintSolver>) -> Self {
+    Checker {
+      environment    : typing.environment,
+      solver,
+      context        : Context::default(),
+      errors         : Vec::new(),
+      types          : typing.types,
+      calls          : typing.calls,
+      fresh          : 0,
+      shared_bindings: false,
+    }
+  }
+
+  pub fn finish(self) -> (Typing, Vec<LocatedTypeError>) {
+    (Typing { environment: self.environment, types: self.types, calls: self.calls }, self.errors)
+  }
+
+  // region Utilities
+
+  fn error(&mut self, location: &SourceLocation, error: TypeError) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  fn mismatch(&mut self, location: &SourceLocation, expected: &Type, found: &Type) {
+    self.error(location, TypeError::Mismatch { expected: expected.to_string(), found: found.to_string() });
+  }
+
+  fn record(&mut self, location: &SourceLocation, typ: &Type) {
+    if *location != SourceLocation::Unknown {
+      self.types.insert(location.clone(), typ.clone());
+    }
+  }
+
+  /// Records that the call at `location` runs `function`, such as one direction of a mapping.
+  fn record_call(&mut self, location: &SourceLocation, function: String) {
+    if let Some(call) = self.calls.get_mut(location) {
+      call.function = function;
+    }
+  }
+
+  /// A new type variable name based on `base`, which cannot clash with any written in source.
+  fn fresh_name(&mut self, base: &str) -> String {
+    self.fresh += 1;
+    let base = base.split('#').next().unwrap_or(base);
+    let base = match base {
+      "'" => "'v",
+      base => base,
+    };
+    format!("{}#{}", base, self.fresh)
+  }
+
+  fn assume(&mut self, constraint: Constraint) {
+    if constraint != Constraint::True {
+      self.context.assumptions.push(constraint);
+    }
+  }
+
+  /// Whether `goal` follows from what is known, reporting it if not.
+  fn prove(&mut self, goal: &Constraint, location: &SourceLocation) -> bool {
+    if *goal == Constraint::True {
+      return true;
+    }
+    let mut constraints = self.environment.constraints.clone();
+    constraints.extend(self.context.assumptions.iter().cloned());
+    constraints.push(Constraint::negate(goal.clone()));
+    let mut answer = satisfiable(&constraints);
+    if let (Satisfiability::Unknown, Some(solver)) = (answer, self.solver.as_mut()) {
+      answer = solver.satisfiable(&constraints);
+    }
+    match answer {
+      Satisfiability::Unsatisfiable => true,
+      _ => {
+        self.error(location, TypeError::Unprovable { constraint: goal.to_string() });
+        false
+      }
+    }
+  }
+
+  /// Opens any existentials at the top of `typ` or its tuple elements, binding their variables as rigid variables and
+  /// assuming their constraints.
+  fn unpack(&mut self, typ: Type) -> Type {
+    match typ {
+      Type::Existential(variables, constraint, body) => {
+        let mut renaming = Substitution::new();
+        for (variable, kind) in &variables {
+          let name = self.fresh_name(variable);
+          self.context.type_variables.insert(name.clone(), *kind);
+          renaming.insert(variable.clone(), variable_argument(&name, *kind));
+        }
+        self.assume(constraint.substitute(&renaming));
+        self.unpack(body.substitute(&renaming))
+      }
+      Type::Tuple(elements) => Type::Tuple(elements.into_iter().map(|element| self.unpack(element)).collect()),
+      other => other,
+    }
+  }
+
+  /// Runs `f` in a nested scope. Type variables invented in the scope that escape into the resulting type are
+  /// quantified existentially, along with what was learned about them.
+  fn scoped_type(&mut self, f: impl FnOnce(&mut Self) -> Type) -> Type {
+    let saved = self.context.clone();
+    let result = f(self);
+    let inner = std::mem::replace(&mut self.context, saved);
+
+    let mut free = BTreeSet::new();
+    result.free_variables(&mut free);
+    let local = |name: &String| !self.context.type_variables.contains_key(name) && inner.type_variables.contains_key(name);
+    let mut escaping: BTreeSet<String> = free.into_iter().filter(local).collect();
+    if escaping.is_empty() {
+      return result;
+    }
+
+    // The new assumptions that mention escaping variables, and transitively the variables they mention.
+    let new_assumptions = &inner.assumptions[self.context.assumptions.len().min(inner.assumptions.len())..];
+    let mut relevant = vec![false; new_assumptions.len()];
+    loop {
+      let mut changed = false;
+      for (index, assumption) in new_assumptions.iter().enumerate() {
+        if !relevant[index] && mentions(assumption, &escaping) {
+          relevant[index] = true;
+          changed = true;
+          let mut mentioned = BTreeSet::new();
+          assumption.free_variables(&mut mentioned);
+          escaping.extend(mentioned.into_iter().filter(local));
+        }
+      }
+      if !changed {
+        break;
+      }
+    }
+
+    let constraint = Constraint::all(
+      new_assumptions.iter().zip(relevant).filter(|(_, relevant)| *relevant).map(|(assumption, _)| assumption.clone()),
+    );
+    let variables = escaping.into_iter().map(|name| {
+      let kind = inner.type_variables[&name];
+      (name, kind)
+    });
+    Type::Existential(variables.collect(), constraint, Box::new(result))
+  }
+
+  /// Runs `f` in a nested scope, discarding everything bound in it.
+  fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+    let saved = self.context.clone();
+    let result = f(self);
+    self.context = saved;
+    result
+  }
+
+  /// Converts a type written in source, with the current type variables in scope. With `implicit`, unknown type
+  /// variables are bound rather than reported, and are returned.
+  fn convert(&mut self, abstract_type: &LocatedAbstractType, implicit: bool) -> Option<(Type, Vec<(String, Kind)>)> {
+    let mut converter = Converter::new(&self.environment, self.context.type_variables.clone(), implicit);
+    match converter.convert(abstract_type) {
+      Ok(typ) => Some((typ, converter.new_variables)),
+      Err(error) => {
+        self.errors.push(error);
+        None
+      }
+    }
+  }
+
+  fn convert_constraint(&mut self, abstract_type: &LocatedAbstractType) -> Option<Constraint> {
+    let mut converter = Converter::new(&self.environment, self.context.type_variables.clone(), false);
+    match converter.constraint(abstract_type) {
+      Ok(constraint) => Some(constraint),
+      Err(error) => {
+        self.errors.push(error);
+        None
+      }
+    }
+  }
+
+  fn convert_numeric(&mut self, abstract_type: &LocatedAbstractType) -> Option<NumericExpression> {
+    let mut converter = Converter::new(&self.environment, self.context.type_variables.clone(), false);
+    match converter.numeric(abstract_type) {
+      Ok(n) => Some(n),
+      Err(error) => {
+        self.errors.push(error);
+        None
+      }
+    }
+  }
+
+  /// The type of an annotation on a pattern, if it has one without new type variables.
+  fn pattern_annotation(&self, pattern: &LocatedPattern) -> Option<Type> {
+    match &pattern.value {
+      Pattern::Typed(abstract_type, _)
+      | Pattern::Variable(_, abstract_type) => {
+        let mut converter = Converter::new(&self.environment, self.context.type_variables.clone(), false);
+        converter.convert(abstract_type).ok()
+      }
+      Pattern::Attribute(_, _, inner) => self.pattern_annotation(inner),
+      _ => None,
+    }
+  }
+
+  fn exception_type(&mut self, location: &SourceLocation) -> Type {
+    match self.environment.variants.contains_key("exception") {
+      true => Type::Application("exception".to_string(), Vec::new()),
+      false => {
+        self.error(location, TypeError::UnknownType("exception".to_string()));
+        Type::Any
+      }
+    }
+  }
+
+  // endregion
+
+  // region Subtyping
+
+  /// Matches `template` against `actual`, solving unknowns of `state` and collecting goals. Existentials in the
+  /// template contribute unknowns; existentials in `actual` are opened.
+  fn unify(&mut self, state: &mut Unification, template: &Type, actual: &Type) -> bool {
+    match (template, actual) {
+      (Type::Any, _) | (_, Type::Any) => true,
+
+      (Type::Variable(name), _) if state.unknowns.contains_key(name) => match state.substitution.get(name).cloned() {
+        Some(TypeArgument::Type(bound)) => self.unify(state, &bound, actual),
+        _ => {
+          state.substitution.insert(name.clone(), TypeArgument::Type(actual.clone()));
+          true
+        }
+      },
+
+      (Type::Existential(variables, constraint, body), _) => {
+        let mut renaming = Substitution::new();
+        for (variable, kind) in variables {
+          let name = self.fresh_name(variable);
+          state.unknowns.insert(name.clone(), *kind);
+          renaming.insert(variable.clone(), variable_argument(&name, *kind));
+        }
+        state.goals.push(constraint.substitute(&renaming));
+        self.unify(state, &body.substitute(&renaming), actual)
+      }
+
+      (_, Type::Existential(..)) => {
+        let actual = self.unpack(actual.clone());
+        self.unify(state, template, &actual)
+      }
+
+      (Type::Unit, Type::Unit)
+      | (Type::Bit, Type::Bit)
+      | (Type::String, Type::String)
+      | (Type::Real, Type::Real) => true,
+
+      (Type::Atom(n), Type::Atom(m))
+      | (Type::Bitvector(n), Type::Bitvector(m)) => {
+        Self::unify_numeric(state, n, m);
+        true
+      }
+
+      (Type::AtomBool(p), Type::AtomBool(q)) => {
+        Self::unify_bool(state, p, q);
+        true
+      }
+
+      (Type::Vector(n, element), Type::Vector(m, other)) => {
+        Self::unify_numeric(state, n, m);
+        self.unify(state, element, other)
+      }
+
+      (Type::List(element), Type::List(other))
+      | (Type::Register(element), Type::Register(other)) => self.unify(state, element, other),
+
+      (Type::Tuple(elements), Type::Tuple(others)) if elements.len() == others.len() => {
+        elements.iter().zip(others).all(|(element, other)| self.unify(state, element, other))
+      }
+
+      (Type::Function(arguments, result), Type::Function(others, other_result)) if arguments.len() == others.len() => {
+        arguments.iter().zip(others).all(|(argument, other)| self.unify(state, argument, other))
+            && self.unify(state, result, other_result)
+      }
+
+      (Type::Bidirectional(left, right), Type::Bidirectional(other_left, other_right)) => {
+        self.unify(state, left, other_left) && self.unify(state, right, other_right)
+      }
+
+      (Type::Variable(name), Type::Variable(other)) => name == other,
+
+      (Type::Application(name, arguments), Type::Application(other, others)) if name == other && arguments.len() == others.len() => {
+        arguments.iter().zip(others).all(|(argument, other)| match (argument, other) {
+          (TypeArgument::Type(argument), TypeArgument::Type(other)) => self.unify(state, argument, other),
+          (TypeArgument::Integer(n), TypeArgument::Integer(m)) => {
+            Self::unify_numeric(state, n, m);
+            true
+          }
+          (TypeArgument::Bool(p), TypeArgument::Bool(q)) => {
+            Self::unify_bool(state, p, q);
+            true
+          }
+          (TypeArgument::Order(a), TypeArgument::Order(b)) => a == b,
+          _ => false,
+        })
+      }
+
+      _ => false,
+    }
+  }
+
+  fn unify_numeric(state: &mut Unification, template: &NumericExpression, actual: &NumericExpression) {
+    let template = template.substitute(&state.substitution);
+    if let NumericExpression::Variable(name) = &template {
+      if state.unknowns.contains_key(name) {
+        state.substitution.insert(name.clone(), TypeArgument::Integer(actual.clone()));
+        return;
+      }
+    }
+    state.goals.push(Constraint::Equal(template, actual.clone()));
+  }
+
+  fn unify_bool(state: &mut Unification, template: &Constraint, actual: &Constraint) {
+    let template = template.substitute(&state.substitution);
+    if let Constraint::Variable(name) = &template {
+      if state.unknowns.contains_key(name) {
+        state.substitution.insert(name.clone(), TypeArgument::Bool(actual.clone()));
+        return;
+      }
+    }
+    state.goals.push(Constraint::iff(template, actual.clone()));
+  }
+
+  /// Checks that a value of type `actual` can be used where `expected` is, reporting it if not.
+  fn subtype(&mut self, actual: Type, expected: &Type, location: &SourceLocation) -> bool {
+    let original = actual.clone();
+    let actual = self.unpack(actual);
+    let mut state = Unification::default();
+    if !self.unify(&mut state, expected, &actual) {
+      self.mismatch(location, expected, &original);
+      return false;
+    }
+    let mut proved = true;
+    for goal in std::mem::take(&mut state.goals) {
+      proved &= self.prove(&goal.substitute(&state.substitution), location);
+    }
+    proved
+  }
+
+  /// The type of a value that is one of two branches.
+  fn join(&mut self, first: Type, second: Type, location: &SourceLocation) -> Type {
+    match (&first, &second) {
+      (Type::Any, _) => second,
+      (_, Type::Any) => first,
+      _ if first == second => first,
+      _ if first.is_integer() && second.is_integer() => Type::int(),
+      _ if first.is_bool() && second.is_bool() => Type::bool(),
+      _ => {
+        self.subtype(second, &first, location);
+        first
+      }
+    }
+  }
+
+  // endregion
+
+  // region Expressions
+
+  /// Infers the type of an expression outside any definition.
+  pub fn top_level_expression(&mut self, expression: &LocatedExpression) -> Type {
+    self.context = Context::default();
+    self.infer(expression)
+  }
+
+  fn infer(&mut self, expression: &LocatedExpression) -> Type {
+    let typ = self.infer_expression(expression);
+    self.record(&expression.location, &typ);
+    typ
+  }
+
+  fn check(&mut self, expression: &LocatedExpression, expected: &Type) {
+    self.check_expression(expression, expected);
+    self.record(&expression.location, expected);
+  }
+
+  /// The integer an expression evaluates to, as a numeric expression over the rigid variables.
+  fn integer(&mut self, expression: &LocatedExpression) -> NumericExpression {
+    let typ = self.infer(expression);
+    match self.unpack(typ) {
+      Type::Atom(n) => n,
+      Type::Any => NumericExpression::Variable(self.fresh_name("'n")),
+      other => {
+        self.mismatch(&expression.location, &Type::int(), &other);
+        NumericExpression::Variable(self.fresh_name("'n"))
+      }
+    }
+  }
+
+  /// The constraint that holds exactly when a boolean expression is true.
+  fn condition(&mut self, expression: &LocatedExpression) -> Constraint {
+    let typ = self.infer(expression);
+    match self.unpack(typ) {
+      Type::AtomBool(p) => p,
+      Type::Any => Constraint::True,
+      other => {
+        self.mismatch(&expression.location, &Type::bool(), &other);
+        Constraint::True
+      }
+    }
+  }
+
+  fn check_expression(&mut self, expression: &LocatedExpression, expected: &Type) {
+    let location = &expression.location;
+    match &expression.value {
+      Expression::Block(expressions) => {
+        self.block(expressions, Some(expected));
+      }
+
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        let p = self.condition(condition);
+        self.scoped(|this| {
+          this.assume(p.clone());
+          this.check(then_expr, expected);
+        });
+        self.scoped(|this| {
+          this.assume(Constraint::negate(p));
+          this.check(else_expr, expected);
+        });
+      }
+
+      Expression::Match(scrutinee, arms) => {
+        let typ = self.infer(scrutinee);
+        for arm in arms {
+          self.arm(arm, &typ, Some(expected));
+        }
+      }
+
+      Expression::Try(body, arms) => {
+        self.check(body, expected);
+        let exception = self.exception_type(location);
+        for arm in arms {
+          self.arm(arm, &exception, Some(expected));
+        }
+      }
+
+      Expression::Let(binding, body) => {
+        self.scoped(|this| {
+          this.let_binding(binding);
+          this.check(body, expected);
+        });
+      }
+
+      Expression::InternalPlet(pattern, value, body) => {
+        self.scoped(|this| {
+          this.bind_value(pattern, value);
+          this.check(body, expected);
+        });
+      }
+
+      Expression::Variable(target, value, body) => {
+        self.scoped(|this| {
+          this.declare_variable(target, value);
+          this.check(body, expected);
+        });
+      }
+
+      Expression::Attribute(_, _, inner) => self.check(inner, expected),
+
+      Expression::Literal(literal) if literal.value == Literal::Undefined => {}
+
+      Expression::Application(function, arguments) => {
+        self.application(function, arguments, Some(expected), location);
+      }
+
+      Expression::InfixApplication(left, operator, right) => {
+        let arguments = [(**left).clone(), (**right).clone()];
+        self.application(operator, &arguments, Some(expected), location);
+      }
+
+      Expression::Infix(tokens) => match resolve_expression_infix(tokens, &self.environment.fixities) {
+        Some(tree) => self.check(&tree, expected),
+        None => self.error(location, TypeError::MalformedInfix),
+      },
+
+      Expression::Tuple(elements) => match expected {
+        Type::Tuple(types) if types.len() == elements.len() => {
+          for (element, typ) in elements.iter().zip(types) {
+            self.check(element, typ);
+          }
+        }
+        _ => {
+          let typ = self.infer_expression(expression);
+          self.subtype(typ, expected, location);
+        }
+      },
+
+      Expression::Vector(elements) => match expected {
+        Type::Bitvector(n) => {
+          for element in elements {
+            self.check(element, &Type::Bit);
+          }
+          self.prove(&Constraint::Equal(n.clone(), NumericExpression::constant(elements.len() as i64)), location);
+        }
+        Type::Vector(n, element_type) => {
+          for element in elements {
+            self.check(element, element_type);
+          }
+          self.prove(&Constraint::Equal(n.clone(), NumericExpression::constant(elements.len() as i64)), location);
+        }
+        _ => {
+          let typ = self.infer_expression(expression);
+          self.subtype(typ, expected, location);
+        }
+      },
+
+      Expression::List(elements) => match expected {
+        Type::List(element_type) => {
+          for element in elements {
+            self.check(element, element_type);
+          }
+        }
+        _ => {
+          let typ = self.infer_expression(expression);
+          self.subtype(typ, expected, location);
+        }
+      },
+
+      Expression::Struct(fields) => {
+        let typ = self.struct_literal(fields, Some(expected), location);
+        self.subtype(typ, expected, location);
+      }
+
+      _ => {
+        let typ = self.infer_expression(expression);
+        self.subtype(typ, expected, location);
+      }
+    }
+  }
+
+  fn infer_expression(&mut self, expression: &LocatedExpression) -> Type {
+    let location = &expression.location;
+    match &expression.value {
+      Expression::Block(expressions) => self.block(expressions, None),
+
+      Expression::Identifier(identifier) => self.identifier(identifier),
+
+      Expression::Reference(identifier) => match self.environment.registers.get(identifier.name()) {
+        Some(typ) => Type::Register(Box::new(typ.clone())),
+        None => {
+          self.error(&identifier.location, TypeError::Undefined(identifier.name().to_string()));
+          Type::Any
+        }
+      },
+
+      Expression::Dereference(inner) => {
+        let typ = self.infer(inner);
+        match self.unpack(typ) {
+          Type::Register(element) => *element,
+          Type::Any => Type::Any,
+          other => {
+            self.mismatch(&inner.location, &Type::Register(Box::new(Type::Any)), &other);
+            Type::Any
+          }
+        }
+      }
+
+      Expression::Literal(literal) => self.literal(literal),
+
+      Expression::Typed(abstract_type, inner) => match self.convert(abstract_type, false) {
+        Some((typ, _)) => {
+          self.check(inner, &typ);
+          typ
+        }
+        None => self.infer(inner),
+      },
+
+      Expression::Application(function, arguments) => self.application(function, arguments, None, location),
+
+      Expression::InfixApplication(left, operator, right) => {
+        let arguments = [(**left).clone(), (**right).clone()];
+        self.application(operator, &arguments, None, location)
+      }
+
+      Expression::Infix(tokens) => match resolve_expression_infix(tokens, &self.environment.fixities) {
+        Some(tree) => self.infer(&tree),
+        None => {
+          self.error(location, TypeError::MalformedInfix);
+          Type::Any
+        }
+      },
+
+      Expression::Tuple(elements) => Type::Tuple(elements.iter().map(|element| self.infer(element)).collect()),
+
+      Expression::If { condition, then_expr, else_expr, .. } => {
+        let p = self.condition(condition);
+        let then_type = self.scoped_type(|this| {
+          this.assume(p.clone());
+          this.infer(then_expr)
+        });
+        let else_type = self.scoped_type(|this| {
+          this.assume(Constraint::negate(p));
+          this.infer(else_expr)
+        });
+        self.join(then_type, else_type, &else_expr.location)
+      }
+
+      Expression::Loop(loop_type, measure, condition, body) => {
+        if let Some(measure) = &measure.value {
+          self.integer(measure);
+        }
+        self.scoped(|this| {
+          let p = this.condition(condition);
+          if *loop_type == LoopType::While {
+            this.assume(p);
+          }
+          this.check(body, &Type::Unit);
+        });
+        Type::Unit
+      }
+
+      Expression::For { identifier, start, end, step, typ, body } => {
+        let start = self.integer(start);
+        let end = self.integer(end);
+        self.integer(step);
+        let increasing = !matches!(typ.value, AbstractType::Decreasing);
+        self.scoped(|this| {
+          let index = this.fresh_name("'loop");
+          this.context.type_variables.insert(index.clone(), Kind::Integer);
+          let index = NumericExpression::Variable(index);
+          let (low, high) = match increasing {
+            true => (start, end),
+            false => (end, start),
+          };
+          this.assume(Constraint::LessEqual(low, index.clone()));
+          this.assume(Constraint::LessEqual(index.clone(), high));
+          this.context.locals.insert(identifier.name().to_string(), Local { typ: Type::Atom(index), mutable: false });
+          this.check(body, &Type::Unit);
+        });
+        Type::Unit
+      }
+
+      Expression::Vector(elements) => {
+        let length = NumericExpression::constant(elements.len() as i64);
+        let Some((first, rest)) = elements.split_first() else {
+          return Type::Bitvector(length);
+        };
+        let first_type = self.infer(first);
+        match first_type {
+          Type::Bit => {
+            for element in rest {
+              self.check(element, &Type::Bit);
+            }
+            Type::Bitvector(length)
+          }
+          other => {
+            let element_type = weaken(other);
+            for element in rest {
+              self.check(element, &element_type);
+            }
+            Type::Vector(length, Box::new(element_type))
+          }
+        }
+      }
+
+      Expression::VectorAccess(vector, index) => {
+        let typ = self.infer(vector);
+        match self.unpack(typ) {
+          Type::Bitvector(length) => {
+            self.index_in_bounds(index, &length);
+            Type::Bit
+          }
+          Type::Vector(length, element) => {
+            self.index_in_bounds(index, &length);
+            *element
+          }
+          Type::Any => Type::Any,
+          other => {
+            self.mismatch(&vector.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            Type::Any
+          }
+        }
+      }
+
+      Expression::VectorSubrange(vector, high, low) => {
+        let typ = self.infer(vector);
+        match self.unpack(typ) {
+          Type::Bitvector(length) => Type::Bitvector(self.subrange(high, low, &length, location)),
+          Type::Vector(length, element) => Type::Vector(self.subrange(high, low, &length, location), element),
+          Type::Any => Type::Any,
+          other => {
+            self.mismatch(&vector.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            Type::Any
+          }
+        }
+      }
+
+      Expression::VectorUpdate(vector, index, value) => {
+        let typ = self.infer(vector);
+        match self.unpack(typ) {
+          Type::Bitvector(length) => {
+            self.index_in_bounds(index, &length);
+            self.check(value, &Type::Bit);
+            Type::Bitvector(length)
+          }
+          Type::Vector(length, element) => {
+            self.index_in_bounds(index, &length);
+            self.check(value, &element);
+            Type::Vector(length, element)
+          }
+          Type::Any => Type::Any,
+          other => {
+            self.mismatch(&vector.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            Type::Any
+          }
+        }
+      }
+
+      Expression::VectorUpdateSubrange(vector, high, low, value) => {
+        let typ = self.infer(vector);
+        match self.unpack(typ) {
+          Type::Bitvector(length) => {
+            let width = self.subrange(high, low, &length, location);
+            self.check(value, &Type::Bitvector(width));
+            Type::Bitvector(length)
+          }
+          Type::Vector(length, element) => {
+            let width = self.subrange(high, low, &length, location);
+            self.check(value, &Type::Vector(width, element.clone()));
+            Type::Vector(length, element)
+          }
+          Type::Any => Type::Any,
+          other => {
+            self.mismatch(&vector.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            Type::Any
+          }
+        }
+      }
+
+      Expression::VectorAppend(left, right) => {
+        let left_type = self.infer(left);
+        let right_type = self.infer(right);
+        match (self.unpack(left_type), self.unpack(right_type)) {
+          (Type::Bitvector(n), Type::Bitvector(m)) => Type::Bitvector(NumericExpression::sum(n, m)),
+          (Type::Vector(n, element), Type::Vector(m, other)) => {
+            self.subtype(*other, &element, &right.location);
+            Type::Vector(NumericExpression::sum(n, m), element)
+          }
+          (Type::Any, _) | (_, Type::Any) => Type::Any,
+          (Type::Bitvector(_), other) | (other, _) => {
+            self.mismatch(location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            Type::Any
+          }
+        }
+      }
+
+      Expression::List(elements) => {
+        let Some((first, rest)) = elements.split_first() else {
+          return Type::List(Box::new(Type::Any));
+        };
+        let element_type = weaken(self.infer(first));
+        for element in rest {
+          self.check(element, &element_type);
+        }
+        Type::List(Box::new(element_type))
+      }
+
+      Expression::Cons(head, tail) => {
+        let element_type = weaken(self.infer(head));
+        let list_type = Type::List(Box::new(element_type));
+        self.check(tail, &list_type);
+        list_type
+      }
+
+      Expression::Struct(fields) => self.struct_literal(fields, None, location),
+
+      Expression::StructUpdate(record, fields) => {
+        let typ = self.infer(record);
+        let unpacked = self.unpack(typ.clone());
+        for field in fields {
+          match &field.value {
+            Expression::Assign(name, value) => match &name.value {
+              Expression::Identifier(name) => {
+                let field_type = self.field_type(&unpacked, name);
+                self.check(value, &field_type);
+              }
+              _ => self.error(&field.location, TypeError::NotAssignable),
+            },
+            _ => self.error(&field.location, TypeError::NotAssignable),
+          }
+        }
+        typ
+      }
+
+      Expression::Field(record, field) => {
+        let typ = self.infer(record);
+        self.field_type(&typ, field)
+      }
+
+      Expression::Match(scrutinee, arms) => {
+        let typ = self.infer(scrutinee);
+        let mut result: Option<Type> = None;
+        for arm in arms {
+          let arm_type = self.arm(arm, &typ, None);
+          result = Some(match result {
+            Some(previous) => self.join(previous, arm_type, &arm.location),
+            None => arm_type,
+          });
+        }
+        result.unwrap_or(Type::Any)
+      }
+
+      Expression::Let(binding, body) => self.scoped_type(|this| {
+        this.let_binding(binding);
+        this.infer(body)
+      }),
+
+      Expression::InternalPlet(pattern, value, body) => self.scoped_type(|this| {
+        this.bind_value(pattern, value);
+        this.infer(body)
+      }),
+
+      Expression::Assign(target, value) => {
+        self.assign(target, value, location);
+        Type::Unit
+      }
+
+      Expression::Sizeof(abstract_type) => match self.convert_numeric(abstract_type) {
+        Some(n) => Type::Atom(n),
+        None => Type::int(),
+      },
+
+      Expression::Constraint(abstract_type) => match self.convert_constraint(abstract_type) {
+        Some(p) => Type::AtomBool(p),
+        None => Type::bool(),
+      },
+
+      Expression::Exit(inner) => {
+        self.infer(inner);
+        Type::Any
+      }
+
+      Expression::Throw(inner) => {
+        let exception = self.exception_type(location);
+        self.check(inner, &exception);
+        Type::Any
+      }
+
+      Expression::Try(body, arms) => {
+        let mut result = self.infer(body);
+        let exception = self.exception_type(location);
+        for arm in arms {
+          let arm_type = self.arm(arm, &exception, None);
+          result = self.join(result, arm_type, &arm.location);
+        }
+        result
+      }
+
+      Expression::Return(inner)
+      | Expression::InternalReturn(inner) => {
+        match self.context.return_type.clone() {
+          Some(return_type) => self.check(inner, &return_type),
+          None => {
+            self.error(location, TypeError::ReturnOutsideFunction);
+            self.infer(inner);
+          }
+        }
+        Type::Any
+      }
+
+      Expression::Assert(condition, message) => {
+        self.condition(condition);
+        self.check(message, &Type::String);
+        Type::Unit
+      }
+
+      Expression::Variable(target, value, body) => self.scoped_type(|this| {
+        this.declare_variable(target, value);
+        this.infer(body)
+      }),
+
+      Expression::Attribute(_, _, inner) => self.infer(inner),
+
+      Expression::InternalAssume(abstract_type, inner) => {
+        let assumption = self.convert_constraint(abstract_type);
+        self.scoped_type(|this| {
+          if let Some(assumption) = assumption {
+            this.assume(assumption);
+          }
+          this.infer(inner)
+        })
+      }
+    }
+  }
+
+  fn literal(&mut self, literal: &LocatedLiteral) -> Type {
+    match &literal.value {
+      Literal::Unit => Type::Unit,
+      Literal::Zero
+      | Literal::One => Type::Bit,
+      Literal::True => Type::AtomBool(Constraint::True),
+      Literal::False => Type::AtomBool(Constraint::False),
+      Literal::Number(value) => Type::Atom(NumericExpression::Constant(value.clone())),
+      Literal::Hexadecimal(_)
+      | Literal::Binary(_) => Type::Bitvector(literal_width(&literal.value).unwrap_or(NumericExpression::constant(0))),
+      Literal::String(_) => Type::String,
+      Literal::Real(_) => Type::Real,
+      Literal::Undefined => {
+        self.error(&literal.location, TypeError::CannotInfer("the type of `undefined`".to_string()));
+        Type::Any
+      }
+    }
+  }
+
+  fn identifier(&mut self, identifier: &LocatedIdentifier) -> Type {
+    let name = identifier.name();
+    if let Some(local) = self.context.locals.get(name) {
+      return local.typ.clone();
+    }
+    if let Some(enumeration) = self.environment.enum_members.get(name) {
+      return Type::Application(enumeration.clone(), Vec::new());
+    }
+    if let Some(typ) = self.environment.registers.get(name).or_else(|| self.environment.values.get(name)) {
+      return typ.clone();
+    }
+    match self.environment.functions.contains_key(name) || self.environment.overloads.contains_key(name) {
+      true => self.error(&identifier.location, TypeError::NotAFunction(name.to_string())),
+      false => self.error(&identifier.location, TypeError::Undefined(name.to_string())),
+    }
+    Type::Any
+  }
+
+  /// `&` and `|`, which only evaluate their right operand if they must, so that it may assume the left's outcome.
+  fn lazy_boolean(&mut self, and: bool, left: &LocatedExpression, right: &LocatedExpression) -> Type {
+    let p = self.condition(left);
+    let index = self.context.assumptions.len();
+    self.context.assumptions.push(match and {
+      true => p.clone(),
+      false => Constraint::negate(p.clone()),
+    });
+    let q = self.condition(right);
+    self.context.assumptions.remove(index);
+    match and {
+      true => Type::AtomBool(Constraint::and(p, q)),
+      false => Type::AtomBool(Constraint::or(p, q)),
+    }
+  }
+
+  fn index_in_bounds(&mut self, index: &LocatedExpression, length: &NumericExpression) {
+    let index_value = self.integer(index);
+    let in_bounds = Constraint::and(
+      Constraint::LessEqual(NumericExpression::constant(0), index_value.clone()),
+      Constraint::Less(index_value, length.clone()),
+    );
+    self.prove(&in_bounds, &index.location);
+  }
+
+  /// Checks the indices of `v[first .. second]` against a vector of the given length, returning the subrange's width.
+  fn subrange(&mut self, first: &LocatedExpression, second: &LocatedExpression, length: &NumericExpression, location: &SourceLocation) -> NumericExpression {
+    let first = self.integer(first);
+    let second = self.integer(second);
+    // `v[high .. low]` for decreasing vectors, `v[low .. high]` for increasing ones.
+    let (high, low) = match self.environment.increasing {
+      true => (second, first),
+      false => (first, second),
+    };
+    let in_bounds = Constraint::all([
+      Constraint::LessEqual(NumericExpression::constant(0), low.clone()),
+      Constraint::LessEqual(low.clone(), high.clone()),
+      Constraint::Less(high.clone(), length.clone()),
+    ]);
+    self.prove(&in_bounds, location);
+    NumericExpression::sum(NumericExpression::minus(high, low), NumericExpression::constant(1))
+  }
+
+  fn field_type(&mut self, typ: &Type, field: &LocatedIdentifier) -> Type {
+    let typ = self.unpack(typ.clone());
+    match &typ {
+      Type::Application(name, arguments) => {
+        if let Some(record) = self.environment.records.get(name) {
+          let substitution: Substitution =
+              record.variables.iter().map(|(variable, _)| variable.clone()).zip(arguments.iter().cloned()).collect();
+          if let Some((_, field_type)) = record.fields.iter().find(|(name, _)| name == field.name()) {
+            return field_type.substitute(&substitution);
+          }
+        } else if let Some(bitfield) = self.environment.bitfields.get(name) {
+          if let Some((_, width)) = bitfield.field(field.name()) {
+            return Type::Bitvector(NumericExpression::Constant(width));
+          }
+        }
+        self.error(&field.location, TypeError::UnknownField { type_name: typ.to_string(), field: field.name().to_string() });
+        Type::Any
+      }
+      Type::Any => Type::Any,
+      _ => {
+        self.error(&field.location, TypeError::UnknownField { type_name: typ.to_string(), field: field.name().to_string() });
+        Type::Any
+      }
+    }
+  }
+
+  /// `struct { f = e, ... }`. The record type is the expected one if there is one, or else the record with exactly
+  /// these fields.
+  fn struct_literal(&mut self, fields: &[LocatedExpression], expected: Option<&Type>, location: &SourceLocation) -> Type {
+    let mut values = Vec::new();
+    for field in fields {
+      match &field.value {
+        Expression::Assign(name, value) => match &name.value {
+          Expression::Identifier(name) => values.push((name, &**value)),
+          _ => self.error(&field.location, TypeError::NotAssignable),
+        },
+        _ => self.error(&field.location, TypeError::NotAssignable),
+      }
+    }
+
+    let expected_record = match expected {
+      Some(Type::Application(name, arguments)) if self.environment.records.contains_key(name) => Some((name.clone(), arguments.clone())),
+      _ => None,
+    };
+    let (name, known_arguments) = match expected_record {
+      Some((name, arguments)) => (name, Some(arguments)),
+      None => {
+        let given: BTreeSet<&str> = values.iter().map(|(name, _)| name.name()).collect();
+        let mut candidates = self.environment.records.iter().filter(|(_, record)| {
+          record.fields.len() == given.len() && record.fields.iter().all(|(field, _)| given.contains(field.as_str()))
+        });
+        match (candidates.next(), candidates.next()) {
+          (Some((name, _)), None) => (name.clone(), None),
+          _ => {
+            self.error(location, TypeError::CannotInfer("the record type of this struct".to_string()));
+            for (_, value) in values {
+              self.infer(value);
+            }
+            return Type::Any;
+          }
+        }
+      }
+    };
+    let record = self.environment.records[&name].clone();
+
+    for (field, _) in &record.fields {
+      if !values.iter().any(|(name, _)| name.name() == field) {
+        self.error(location, TypeError::MissingField { type_name: name.clone(), field: field.clone() });
+      }
+    }
+
+    match known_arguments {
+      Some(arguments) => {
+        let substitution: Substitution =
+            record.variables.iter().map(|(variable, _)| variable.clone()).zip(arguments.iter().cloned()).collect();
+        for (field, value) in values {
+          match record.fields.iter().find(|(name, _)| name == field.name()) {
+            Some((_, field_type)) => self.check(value, &field_type.substitute(&substitution)),
+            None => self.error(&field.location, TypeError::UnknownField { type_name: name.clone(), field: field.name().to_string() }),
+          }
+        }
+        Type::Application(name, arguments)
+      }
+      None => {
+        let mut state = Unification::default();
+        let mut renaming = Substitution::new();
+        for (variable, kind) in &record.variables {
+          let fresh = self.fresh_name(variable);
+          state.unknowns.insert(fresh.clone(), *kind);
+          renaming.insert(variable.clone(), variable_argument(&fresh, *kind));
+        }
+        for (field, value) in values {
+          let Some((_, field_type)) = record.fields.iter().find(|(name, _)| name == field.name()) else {
+            self.error(&field.location, TypeError::UnknownField { type_name: name.clone(), field: field.name().to_string() });
+            continue;
+          };
+          let field_type = field_type.substitute(&renaming);
+          let actual = self.infer(value);
+          let actual = self.unpack(actual);
+          if !self.unify(&mut state, &field_type, &actual) {
+            self.mismatch(&value.location, &field_type.substitute(&state.substitution), &actual);
+          }
+        }
+        for goal in std::mem::take(&mut state.goals) {
+          self.prove(&goal.substitute(&state.substitution), location);
+        }
+        if !state.unresolved().is_empty() {
+          self.error(location, TypeError::CannotInfer(format!("the type arguments of {}", name)));
+        }
+        let arguments = record
+            .variables
+            .iter()
+            .map(|(variable, _)| renaming[variable].substitute(&state.substitution))
+            .collect();
+        Type::Application(name, arguments)
+      }
+    }
+  }
+
+  /// Checks the statements of a block. Assignments to undeclared names declare mutable variables for the rest of the
+  /// block, and `assert`s are assumed to hold after them.
+  fn block(&mut self, expressions: &[LocatedExpression], expected: Option<&Type>) -> Type {
+    self.scoped_type(|this| {
+      let mut result = Type::Unit;
+      for (index, expression) in expressions.iter().enumerate() {
+        let last = index + 1 == expressions.len();
+
+        let statement = match &expression.value {
+          Expression::Assign(target, value) => this.declare_implicitly(target, value),
+          Expression::Assert(condition, message) => {
+            let p = this.condition(condition);
+            this.check(message, &Type::String);
+            this.assume(p);
+            true
+          }
+          _ => false,
+        };
+        if statement {
+          this.record(&expression.location, &Type::Unit);
+          result = Type::Unit;
+          if let (true, Some(expected)) = (last, expected) {
+            this.subtype(Type::Unit, expected, &expression.location);
+          }
+          continue;
+        }
+
+        match (last, expected) {
+          (true, Some(expected)) => {
+            this.check(expression, expected);
+            result = expected.clone();
+          }
+          (true, None) => result = this.infer(expression),
+          (false, _) => this.check(expression, &Type::Unit),
+        }
+      }
+      result
+    })
+  }
+
+  /// An assignment to a name that is not yet bound, which declares it. Returns whether `target` was such a name.
+  fn declare_implicitly(&mut self, target: &LocatedExpression, value: &LocatedExpression) -> bool {
+    let name = match &target.value {
+      Expression::Identifier(identifier) => identifier.name(),
+      Expression::Typed(_, inner) => match &inner.value {
+        Expression::Identifier(identifier) => identifier.name(),
+        _ => return false,
+      },
+      _ => return false,
+    };
+    if self.context.locals.contains_key(name)
+        || self.environment.registers.contains_key(name)
+        || self.environment.values.contains_key(name)
+    {
+      return false;
+    }
+    self.declare_variable(target, value);
+    true
+  }
+
+  /// Declares `target`, an identifier with an optional type annotation, as a mutable variable initialised to `value`.
+  fn declare_variable(&mut self, target: &LocatedExpression, value: &LocatedExpression) {
+    let (annotation, identifier) = match &target.value {
+      Expression::Identifier(identifier) => (None, identifier),
+      Expression::Typed(abstract_type, inner) => match &inner.value {
+        Expression::Identifier(identifier) => (Some(abstract_type), identifier),
+        _ => {
+          self.error(&target.location, TypeError::NotAssignable);
+          return;
+        }
+      },
+      _ => {
+        self.error(&target.location, TypeError::NotAssignable);
+        return;
+      }
+    };
+    let typ = match annotation.and_then(|annotation| self.convert(annotation, false)) {
+      Some((typ, _)) => {
+        self.check(value, &typ);
+        typ
+      }
+      None => weaken(self.infer(value)),
+    };
+    self.record(&target.location, &typ);
+    self.context.locals.insert(identifier.name().to_string(), Local { typ, mutable: true });
+  }
+
+  fn assign(&mut self, target: &LocatedExpression, value: &LocatedExpression, location: &SourceLocation) {
+    match &target.value {
+      Expression::Tuple(targets) => {
+        let typ = self.infer(value);
+        match self.unpack(typ) {
+          Type::Tuple(types) if types.len() == targets.len() => {
+            for (target, typ) in targets.iter().zip(types) {
+              self.assign_type(target, typ);
+            }
+          }
+          Type::Any => {
+            for target in targets {
+              self.assign_type(target, Type::Any);
+            }
+          }
+          other => {
+            let expected = Type::Tuple(vec![Type::Any; targets.len()]);
+            self.mismatch(&value.location, &expected, &other);
+          }
+        }
+      }
+
+      Expression::Typed(abstract_type, inner) => {
+        if let Some((typ, _)) = self.convert(abstract_type, false) {
+          self.check(value, &typ);
+          self.assign_type(inner, typ);
+        }
+      }
+
+      _ => {
+        let Some(lvalue) = expression_to_lvalue(target) else {
+          self.error(&target.location, TypeError::NotAssignable);
+          return;
+        };
+        if let LValueExpression::Memory(setter, arguments) = &lvalue.value {
+          let mut arguments = arguments.clone();
+          arguments.push(value.clone());
+          self.application(setter, &arguments, Some(&Type::Unit), location);
+          return;
+        }
+        if let Some(typ) = self.lvalue(&lvalue) {
+          self.check(value, &typ);
+        }
+      }
+    }
+  }
+
+  /// Assigns a value of type `typ` to `target`, declaring it if it is an unbound name.
+  fn assign_type(&mut self, target: &LocatedExpression, typ: Type) {
+    if let Expression::Identifier(identifier) = &target.value {
+      let name = identifier.name();
+      if !self.context.locals.contains_key(name) && !self.environment.registers.contains_key(name) {
+        let typ = weaken(typ);
+        self.record(&target.location, &typ);
+        self.context.locals.insert(name.to_string(), Local { typ, mutable: true });
+        return;
+      }
+    }
+    let Some(lvalue) = expression_to_lvalue(target) else {
+      self.error(&target.location, TypeError::NotAssignable);
+      return;
+    };
+    if let Some(expected) = self.lvalue(&lvalue) {
+      self.subtype(typ, &expected, &target.location);
+    }
+  }
+
+  /// The type of values that may be assigned to `lvalue`.
+  fn lvalue(&mut self, lvalue: &LocatedLValueExpression) -> Option<Type> {
+    match &lvalue.value {
+      LValueExpression::Identifier(identifier) => {
+        let name = identifier.name();
+        if let Some(local) = self.context.locals.get(name) {
+          if local.mutable {
+            return Some(local.typ.clone());
+          }
+          self.error(&identifier.location, TypeError::Immutable(name.to_string()));
+          return None;
+        }
+        if let Some(typ) = self.environment.registers.get(name) {
+          return Some(typ.clone());
+        }
+        match self.environment.values.contains_key(name) {
+          true => self.error(&identifier.location, TypeError::Immutable(name.to_string())),
+          false => self.error(&identifier.location, TypeError::Undefined(name.to_string())),
+        }
+        None
+      }
+
+      LValueExpression::Memory(..) => {
+        self.error(&lvalue.location, TypeError::NotAssignable);
+        None
+      }
+
+      LValueExpression::Vector(vector, index) => {
+        let typ = self.lvalue(vector)?;
+        match self.unpack(typ) {
+          Type::Bitvector(length) => {
+            self.index_in_bounds(index, &length);
+            Some(Type::Bit)
+          }
+          Type::Vector(length, element) => {
+            self.index_in_bounds(index, &length);
+            Some(*element)
+          }
+          Type::Any => Some(Type::Any),
+          other => {
+            self.mismatch(&vector.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            None
+          }
+        }
+      }
+
+      LValueExpression::VectorRange(vector, high, low) => {
+        let typ = self.lvalue(vector)?;
+        match self.unpack(typ) {
+          Type::Bitvector(length) => Some(Type::Bitvector(self.subrange(high, low, &length, &lvalue.location))),
+          Type::Vector(length, element) => Some(Type::Vector(self.subrange(high, low, &length, &lvalue.location), element)),
+          Type::Any => Some(Type::Any),
+          other => {
+            self.mismatch(&vector.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            None
+          }
+        }
+      }
+
+      LValueExpression::VectorConcat(parts) => {
+        let mut width = NumericExpression::constant(0);
+        for part in parts {
+          let typ = self.lvalue(part)?;
+          match self.unpack(typ) {
+            Type::Bitvector(length) => width = NumericExpression::sum(width, length),
+            other => {
+              self.mismatch(&part.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+              return None;
+            }
+          }
+        }
+        Some(Type::Bitvector(width))
+      }
+
+      LValueExpression::Field(record, field) => {
+        let typ = self.lvalue(record)?;
+        Some(self.field_type(&typ, field))
+      }
+    }
+  }
+
+  fn let_binding(&mut self, binding: &LocatedLetBinding) {
+    let LetBinding::ValueBinding(pattern, value) = &binding.value;
+    self.bind_value(pattern, value);
+  }
+
+  /// Binds `pattern` to `value`, checking the value against the pattern's annotation if it has one.
+  fn bind_value(&mut self, pattern: &LocatedPattern, value: &LocatedExpression) {
+    let typ = match self.pattern_annotation(pattern) {
+      Some(annotation) => {
+        self.check(value, &annotation);
+        annotation
+      }
+      None => self.infer(value),
+    };
+    self.bind_pattern(pattern, &typ);
+  }
+
+  fn arm(&mut self, arm: &LocatedPatternExpression, scrutinee: &Type, expected: Option<&Type>) -> Type {
+    let (pattern, guard, body) = match &arm.value {
+      PatternExpression::Pattern(pattern, body) => (pattern, None, body),
+      PatternExpression::PatternWhen(pattern, guard, body) => (pattern, Some(guard), body),
+    };
+    self.scoped_type(|this| {
+      this.bind_pattern(pattern, scrutinee);
+      if let Some(guard) = guard {
+        let p = this.condition(guard);
+        this.assume(p);
+      }
+      match expected {
+        Some(expected) => {
+          this.check(body, expected);
+          expected.clone()
+        }
+        None => this.infer(body),
+      }
+    })
+  }
+
+  // endregion
+
+  // region Applications
+
+  fn application(
+    &mut self,
+    function: &LocatedIdentifier,
+    arguments: &[LocatedExpression],
+    expected: Option<&Type>,
+    location: &SourceLocation,
+  ) -> Type {
+    let name = function.name();
+    if self.context.locals.contains_key(name) {
+      self.error(&function.location, TypeError::NotAFunction(name.to_string()));
+      return Type::Any;
+    }
+
+    let mut candidates = self.environment.overload_candidates(name);
+    // Without an `operator &` or `operator |` overload, `&` and `|` are the boolean operators.
+    if let ("&" | "|", [candidate]) = (name, candidates.as_slice()) {
+      if candidate == name && !self.environment.functions.contains_key(name) {
+        candidates = vec![if name == "&" { "and_bool" } else { "or_bool" }.to_string()];
+      }
+    }
+    if let [candidate] = candidates.as_slice() {
+      let Some(typ) = self.call(candidate, &function.location, arguments, expected, location) else {
+        return Type::Any;
+      };
+      if let Some(expected) = expected {
+        self.subtype(typ.clone(), expected, location);
+      }
+      return typ;
+    }
+
+    // Overloads are tried in order, and the first under which the call checks is taken.
+    for candidate in &candidates {
+      let saved_context = self.context.clone();
+      let saved_errors = self.errors.len();
+      let typ = self.call(candidate, &function.location, arguments, expected, location);
+      if let (Some(typ), Some(expected)) = (&typ, expected) {
+        self.subtype(typ.clone(), expected, location);
+      }
+      if let (Some(typ), true) = (typ, self.errors.len() == saved_errors) {
+        return typ;
+      }
+      self.errors.truncate(saved_errors);
+      self.context = saved_context;
+    }
+    self.error(location, TypeError::NoOverload { name: name.to_string(), candidates });
+    Type::Any
+  }
+
+  /// Checks a call of the function, union constructor or mapping `name`.
+  fn call(
+    &mut self,
+    name: &str,
+    name_location: &SourceLocation,
+    arguments: &[LocatedExpression],
+    expected: Option<&Type>,
+    location: &SourceLocation,
+  ) -> Option<Type> {
+    if let ("and_bool" | "or_bool", [left, right]) = (name, arguments) {
+      if *location != SourceLocation::Unknown {
+        let call = Call { function: name.to_string(), implicit: Vec::new(), instantiation: Substitution::new() };
+        self.calls.insert(location.clone(), call);
+      }
+      return Some(self.lazy_boolean(name == "and_bool", left, right));
+    }
+
+    if let Some(function) = self.environment.functions.get(name).or_else(|| self.environment.constructors.get(name)) {
+      let function = function.clone();
+      let implicit = self.environment.implicit_arguments.get(name).copied().unwrap_or(0);
+      return self.apply(name, &function, implicit, arguments, expected, location);
+    }
+
+    if let Some(mapping) = self.environment.mappings.get(name).cloned() {
+      // A mapping is called in whichever direction its argument fits, forwards first.
+      let saved_context = self.context.clone();
+      let saved_errors = self.errors.len();
+      let [forwards_name, backwards_name, ..] = derived_names(name);
+      if let Some(typ) = self.apply(name, &mapping, 0, arguments, expected, location) {
+        if self.errors.len() == saved_errors {
+          self.record_call(location, forwards_name);
+          return Some(typ);
+        }
+      }
+      self.errors.truncate(saved_errors);
+      self.context = saved_context;
+      let backwards = FunctionType {
+        variables : mapping.variables.clone(),
+        constraint: mapping.constraint.clone(),
+        arguments : vec![mapping.result.clone()],
+        result    : mapping.arguments.first().cloned().unwrap_or(Type::Unit),
+      };
+      let typ = self.apply(name, &backwards, 0, arguments, expected, location);
+      self.record_call(location, backwards_name);
+      return typ;
+    }
+
+    self.error(name_location, TypeError::Undefined(name.to_string()));
+    for argument in arguments {
+      self.infer(argument);
+    }
+    None
+  }
+
+  /// Checks a call of a function of type `function`, returning the type of its result.
+  fn apply(
+    &mut self,
+    name: &str,
+    function: &FunctionType,
+    implicit: usize,
+    arguments: &[LocatedExpression],
+    expected: Option<&Type>,
+    location: &SourceLocation,
+  ) -> Option<Type> {
+    let mut state = Unification::default();
+    let mut renaming = Substitution::new();
+    for (variable, kind) in &function.variables {
+      let fresh = self.fresh_name(variable);
+      state.unknowns.insert(fresh.clone(), *kind);
+      renaming.insert(variable.clone(), variable_argument(&fresh, *kind));
+    }
+    let (constraint, mut parameters, result) = function.instantiate(&renaming);
+
+    // `f(a, b)` may call a function of a single tuple, and `f()` one of a single unit.
+    if let ([Type::Tuple(elements)], true) = (parameters.as_slice(), arguments.len() > 1) {
+      parameters = elements.clone();
+    }
+    if parameters == [Type::Unit] && arguments.is_empty() {
+      parameters.clear();
+    }
+    // Leading implicit arguments may be left out, and are then determined by the expected type.
+    let skipped = match implicit > 0 && arguments.len() + implicit == parameters.len() {
+      true => implicit,
+      false => 0,
+    };
+    if parameters.len() - skipped != arguments.len() {
+      self.error(location, TypeError::Arity { name: name.to_string(), expected: parameters.len() - skipped, found: arguments.len() });
+      for argument in arguments {
+        self.infer(argument);
+      }
+      return None;
+    }
+
+    let mut deferred = Vec::new();
+    for (argument, parameter) in arguments.iter().zip(&parameters[skipped..]) {
+      if needs_expected_type(argument) {
+        deferred.push((argument, parameter));
+        continue;
+      }
+      let actual = self.infer(argument);
+      let actual = self.unpack(actual);
+      if !self.unify(&mut state, parameter, &actual) {
+        self.mismatch(&argument.location, &parameter.substitute(&state.substitution), &actual);
+        return None;
+      }
+    }
+
+    // Unknowns the arguments did not determine may be determined by the type the result should have.
+    if let Some(expected) = expected {
+      if !matches!(expected, Type::Existential(..)) && !state.unresolved().is_empty() {
+        let goals = state.goals.len();
+        self.unify(&mut state, &result, expected);
+        state.goals.truncate(goals);
+      }
+    }
+
+    let unresolved = state.unresolved();
+    let unresolved_names: BTreeSet<String> = unresolved.iter().map(|(name, _)| name.clone()).collect();
+    let mut in_parameters = BTreeSet::new();
+    for parameter in &parameters {
+      parameter.substitute(&state.substitution).free_variables(&mut in_parameters);
+    }
+    if let Some(variable) = in_parameters.iter().find(|variable| unresolved_names.contains(*variable)) {
+      let variable = variable.split('#').next().unwrap_or(variable);
+      self.error(location, TypeError::CannotInfer(format!("the type variable {} in this call of `{}`", variable, name)));
+      return None;
+    }
+
+    // Conjuncts of the constraint about variables nothing determined constrain the result instead.
+    let mut parts = Vec::new();
+    conjuncts(constraint.substitute(&state.substitution), &mut parts);
+    let (about_result, goals): (Vec<Constraint>, Vec<Constraint>) =
+        parts.into_iter().partition(|part| mentions(part, &unresolved_names));
+    for goal in std::mem::take(&mut state.goals).into_iter().chain(goals) {
+      self.prove(&goal.substitute(&state.substitution), location);
+    }
+
+    for (argument, parameter) in deferred {
+      self.check(argument, &parameter.substitute(&state.substitution));
+    }
+
+    let implicit = parameters[..skipped]
+        .iter()
+        .filter_map(|parameter| match parameter.substitute(&state.substitution) {
+          Type::Atom(value) => Some(value),
+          _ => None,
+        })
+        .collect();
+    if *location != SourceLocation::Unknown {
+      let mut instantiation = Substitution::new();
+      for (variable, _) in &function.variables {
+        let argument = renaming[variable].substitute(&state.substitution);
+        let mut free = BTreeSet::new();
+        argument.free_variables(&mut free);
+        if free.is_disjoint(&unresolved_names) {
+          instantiation.insert(variable.clone(), argument);
+        }
+      }
+      self.calls.insert(location.clone(), Call { function: name.to_string(), implicit, instantiation });
+    }
+
+    let result = result.substitute(&state.substitution);
+    match unresolved.is_empty() {
+      true => Some(result),
+      false => Some(Type::Existential(unresolved, Constraint::all(about_result), Box::new(result))),
+    }
+  }
+
+  // endregion
+
+  // region Patterns
+
+  fn bind_identifier(&mut self, identifier: &LocatedIdentifier, typ: &Type) {
+    let name = identifier.name();
+    if self.shared_bindings {
+      if let Some(local) = self.context.locals.get(name) {
+        let existing = local.typ.clone();
+        self.subtype(existing, typ, &identifier.location);
+        return;
+      }
+    }
+    self.context.locals.insert(name.to_string(), Local { typ: typ.clone(), mutable: false });
+  }
+
+  /// A pattern that is a bare enum member.
+  fn bind_enum_member(&mut self, identifier: &LocatedIdentifier, typ: &Type) -> bool {
+    let Some(enumeration) = self.environment.enum_members.get(identifier.name()).cloned() else {
+      return false;
+    };
+    if self.context.locals.contains_key(identifier.name()) {
+      return false;
+    }
+    self.subtype(Type::Application(enumeration, Vec::new()), typ, &identifier.location);
+    true
+  }
+
+  fn bind_literal(&mut self, literal: &LocatedLiteral, typ: &Type) {
+    match (&literal.value, typ) {
+      (Literal::Number(value), Type::Atom(n)) => self.assume(Constraint::Equal(n.clone(), NumericExpression::Constant(value.clone()))),
+      (Literal::True, Type::AtomBool(p)) => self.assume(p.clone()),
+      (Literal::False, Type::AtomBool(p)) => self.assume(Constraint::negate(p.clone())),
+      (_, Type::Any) => {}
+      _ => {
+        let literal_type = self.literal(literal);
+        let mut state = Unification::default();
+        if !self.unify(&mut state, typ, &literal_type) {
+          self.mismatch(&literal.location, typ, &literal_type);
+        }
+        // A literal that cannot match, such as one of the wrong length, is not an error.
+      }
+    }
+  }
+
+  /// Binds the type variables of an annotation on a pattern by matching it against the type of the value.
+  fn bind_annotation(&mut self, abstract_type: &LocatedAbstractType, typ: &Type, location: &SourceLocation) {
+    let Some((annotation, new_variables)) = self.convert(abstract_type, true) else {
+      return;
+    };
+    let mut state = Unification { unknowns: new_variables.iter().cloned().collect(), ..Default::default() };
+    if !self.unify(&mut state, &annotation, typ) {
+      self.mismatch(location, &annotation, typ);
+      return;
+    }
+    for (variable, kind) in new_variables {
+      self.context.type_variables.insert(variable.clone(), kind);
+      match state.substitution.get(&variable) {
+        Some(TypeArgument::Integer(n)) => self.assume(Constraint::Equal(NumericExpression::Variable(variable), n.clone())),
+        Some(TypeArgument::Bool(p)) => self.assume(Constraint::iff(Constraint::Variable(variable), p.clone())),
+        _ => {}
+      }
+    }
+    for goal in std::mem::take(&mut state.goals) {
+      self.prove(&goal.substitute(&state.substitution), location);
+    }
+  }
+
+  /// The element types of a vector or bitvector pattern of `count` elements matched against `typ`.
+  fn vector_elements(&mut self, typ: &Type, count: usize, location: &SourceLocation) -> Type {
+    let length = NumericExpression::constant(count as i64);
+    match typ {
+      Type::Bitvector(n) => {
+        self.prove(&Constraint::Equal(n.clone(), length), location);
+        Type::Bit
+      }
+      Type::Vector(n, element) => {
+        self.prove(&Constraint::Equal(n.clone(), length), location);
+        (**element).clone()
+      }
+      Type::Any => Type::Any,
+      other => {
+        self.mismatch(location, &Type::Bitvector(length), other);
+        Type::Any
+      }
+    }
+  }
+
+  /// The widths of the parts of a concatenation pattern matched against `typ`, at most one of which may be unknown.
+  fn concatenation_widths(&mut self, typ: &Type, widths: Vec<Option<NumericExpression>>, location: &SourceLocation) -> Vec<Type> {
+    let (total, element) = match typ {
+      Type::Bitvector(n) => (n.clone(), None),
+      Type::Vector(n, element) => (n.clone(), Some(element.clone())),
+      Type::Any => return vec![Type::Any; widths.len()],
+      other => {
+        self.mismatch(location, &Type::Bitvector(NumericExpression::variable("'n")), other);
+        return vec![Type::Any; widths.len()];
+      }
+    };
+    let known = widths.iter().flatten().fold(NumericExpression::constant(0), |sum, width| NumericExpression::sum(sum, width.clone()));
+    let widths: Vec<NumericExpression> = match widths.iter().filter(|width| width.is_none()).count() {
+      0 => {
+        self.prove(&Constraint::Equal(known, total), location);
+        widths.into_iter().flatten().collect()
+      }
+      1 => {
+        let rest = NumericExpression::minus(total, known);
+        self.prove(&Constraint::GreaterEqual(rest.clone(), NumericExpression::constant(0)), location);
+        widths.into_iter().map(|width| width.unwrap_or_else(|| rest.clone())).collect()
+      }
+      _ => {
+        self.error(location, TypeError::CannotInfer("the width of each part of this vector concatenation".to_string()));
+        return vec![Type::Any; widths.len()];
+      }
+    };
+    widths
+        .into_iter()
+        .map(|width| match &element {
+          Some(element) => Type::Vector(width, element.clone()),
+          None => Type::Bitvector(width),
+        })
+        .collect()
+  }
+
+  /// The width of a pattern matching part of a bitvector, if it can be told from the pattern alone.
+  fn pattern_width(&self, pattern: &LocatedPattern) -> Option<NumericExpression> {
+    match &pattern.value {
+      Pattern::Literal(literal) => literal_width(&literal.value),
+      Pattern::Typed(..) | Pattern::Variable(..) => match self.pattern_annotation(pattern)? {
+        Type::Bitvector(n) | Type::Vector(n, _) => Some(n),
+        _ => None,
+      },
+      Pattern::Vector(elements) => Some(NumericExpression::constant(elements.len() as i64)),
+      Pattern::VectorSubrange(_, high, low) => Some(subrange_width(high, low)),
+      Pattern::VectorConcat(parts) => parts
+          .iter()
+          .map(|part| self.pattern_width(part))
+          .try_fold(NumericExpression::constant(0), |sum, width| Some(NumericExpression::sum(sum, width?))),
+      Pattern::Attribute(_, _, inner) => self.pattern_width(inner),
+      _ => None,
+    }
+  }
+
+  fn field_types(&mut self, typ: &Type, fields: &[&LocatedIdentifier]) -> Vec<Type> {
+    fields.iter().map(|field| self.field_type(typ, field)).collect()
+  }
+
+  /// Binds the variables of `pattern`, matched against a value of type `typ`.
+  fn bind_pattern(&mut self, pattern: &LocatedPattern, typ: &Type) {
+    let typ = self.unpack(typ.clone());
+    self.record(&pattern.location, &typ);
+    let location = &pattern.location;
+    match &pattern.value {
+      Pattern::Literal(literal) => self.bind_literal(literal, &typ),
+
+      Pattern::Wildcard => {}
+
+      Pattern::Identifier(identifier) => {
+        if !self.bind_enum_member(identifier, &typ) {
+          self.bind_identifier(identifier, &typ);
+        }
+      }
+
+      Pattern::Typed(abstract_type, inner)
+      | Pattern::Variable(inner, abstract_type) => {
+        self.bind_annotation(abstract_type, &typ, location);
+        self.bind_pattern(inner, &typ);
+      }
+
+      Pattern::Constructor(constructor, arguments) => {
+        let payload = self.constructor_payload(constructor, &typ);
+        match (arguments.as_slice(), payload) {
+          ([], _) => {}
+          ([argument], payload) => self.bind_pattern(argument, &payload),
+          (arguments, Type::Tuple(elements)) if elements.len() == arguments.len() => {
+            for (argument, element) in arguments.iter().zip(elements) {
+              self.bind_pattern(argument, &element);
+            }
+          }
+          (arguments, Type::Any) => {
+            for argument in arguments {
+              self.bind_pattern(argument, &Type::Any);
+            }
+          }
+          (arguments, other) => self.mismatch(location, &Type::Tuple(vec![Type::Any; arguments.len()]), &other),
+        }
+      }
+
+      Pattern::Vector(elements) => {
+        let element = self.vector_elements(&typ, elements.len(), location);
+        for pattern in elements {
+          self.bind_pattern(pattern, &element);
+        }
+      }
+
+      Pattern::VectorConcat(parts) => {
+        let widths = parts.iter().map(|part| self.pattern_width(part)).collect();
+        let types = self.concatenation_widths(&typ, widths, location);
+        for (part, typ) in parts.iter().zip(types) {
+          self.bind_pattern(part, &typ);
+        }
+      }
+
+      Pattern::VectorSubrange(identifier, high, low) => {
+        let width = subrange_width(high, low);
+        self.bind_identifier(identifier, &Type::Bitvector(width));
+      }
+
+      Pattern::Tuple(elements) => match &typ {
+        Type::Tuple(types) if types.len() == elements.len() => {
+          for (element, typ) in elements.iter().zip(types) {
+            self.bind_pattern(element, typ);
+          }
+        }
+        _ if elements.len() == 1 => self.bind_pattern(&elements[0], &typ),
+        Type::Any => {
+          for element in elements {
+            self.bind_pattern(element, &Type::Any);
+          }
+        }
+        other => self.mismatch(location, &Type::Tuple(vec![Type::Any; elements.len()]), other),
+      },
+
+      Pattern::List(elements) => {
+        let element = self.list_element(&typ, location);
+        for pattern in elements {
+          self.bind_pattern(pattern, &element);
+        }
+      }
+
+      Pattern::Cons(head, tail) => {
+        let element = self.list_element(&typ, location);
+        self.bind_pattern(head, &element);
+        self.bind_pattern(tail, &typ);
+      }
+
+      Pattern::StringAppend(parts) => {
+        if !matches!(typ, Type::String | Type::Any) {
+          self.mismatch(location, &Type::String, &typ);
+        }
+        for part in parts {
+          self.bind_pattern(part, &Type::String);
+        }
+      }
+
+      Pattern::Struct(fields) => {
+        for field in fields {
+          if let FieldPattern::Field(name, pattern) = &field.value {
+            let field_type = self.field_types(&typ, &[name]).remove(0);
+            self.bind_pattern(pattern, &field_type);
+          }
+        }
+      }
+
+      Pattern::Attribute(_, _, inner) => self.bind_pattern(inner, &typ),
+    }
+  }
+
+  fn list_element(&mut self, typ: &Type, location: &SourceLocation) -> Type {
+    match typ {
+      Type::List(element) => (**element).clone(),
+      Type::Any => Type::Any,
+      other => {
+        self.mismatch(location, &Type::List(Box::new(Type::Any)), other);
+        Type::Any
+      }
+    }
+  }
+
+  /// The payload type of a constructor pattern matched against a value of type `typ`.
+  fn constructor_payload(&mut self, constructor: &LocatedIdentifier, typ: &Type) -> Type {
+    let Some(function) = self.environment.constructors.get(constructor.name()).cloned() else {
+      self.error(&constructor.location, TypeError::Undefined(constructor.name().to_string()));
+      return Type::Any;
+    };
+    let mut state = Unification::default();
+    let mut renaming = Substitution::new();
+    for (variable, kind) in &function.variables {
+      let fresh = self.fresh_name(variable);
+      state.unknowns.insert(fresh.clone(), *kind);
+      renaming.insert(variable.clone(), variable_argument(&fresh, *kind));
+    }
+    let (_, arguments, result) = function.instantiate(&renaming);
+    if !self.unify(&mut state, &result, typ) {
+      self.mismatch(&constructor.location, typ, &result);
+      return Type::Any;
+    }
+    let payload = arguments.into_iter().next().unwrap_or(Type::Unit).substitute(&state.substitution);
+    // Variables of the union that the value's type does not determine are opened.
+    let unresolved = state.unresolved();
+    match unresolved.is_empty() {
+      true => payload,
+      false => Type::Existential(unresolved, Constraint::True, Box::new(payload)),
+    }
+  }
+
+  /// The width of a mapping pattern matching part of a bitvector, if it can be told from the pattern alone.
+  fn mapping_pattern_width(&self, pattern: &LocatedMappingPattern) -> Option<NumericExpression> {
+    match &pattern.value {
+      MappingPattern::Literal(literal) => literal_width(&literal.value),
+      MappingPattern::Typed(_, abstract_type) => {
+        let mut converter = Converter::new(&self.environment, self.context.type_variables.clone(), false);
+        match converter.convert(abstract_type).ok()? {
+          Type::Bitvector(n) | Type::Vector(n, _) => Some(n),
+          _ => None,
+        }
+      }
+      MappingPattern::Vector(elements) => Some(NumericExpression::constant(elements.len() as i64)),
+      MappingPattern::VectorSubrange(_, high, low) => Some(subrange_width(high, low)),
+      MappingPattern::VectorConcat(parts) => parts
+          .iter()
+          .map(|part| self.mapping_pattern_width(part))
+          .try_fold(NumericExpression::constant(0), |sum, width| Some(NumericExpression::sum(sum, width?))),
+      MappingPattern::As(inner, _) => self.mapping_pattern_width(inner),
+      _ => None,
+    }
+  }
+
+  /// Binds the variables of one side of a mapping clause, matched against a value of type `typ`.
+  fn bind_mapping_pattern(&mut self, pattern: &LocatedMappingPattern, typ: &Type) {
+    let typ = self.unpack(typ.clone());
+    self.record(&pattern.location, &typ);
+    let location = &pattern.location;
+    match &pattern.value {
+      MappingPattern::Literal(literal) => self.bind_literal(literal, &typ),
+
+      MappingPattern::Identifier(identifier) => {
+        if !self.bind_enum_member(identifier, &typ) {
+          self.bind_identifier(identifier, &typ);
+        }
+      }
+
+      MappingPattern::Application(name, arguments) => {
+        let payload = match self.environment.mappings.get(name.name()).cloned() {
+          Some(mapping) => self.mapping_payload(name, &mapping, &typ),
+          None => self.constructor_payload(name, &typ),
+        };
+        match (arguments.as_slice(), payload) {
+          ([], _) => {}
+          ([argument], payload) => self.bind_mapping_pattern(argument, &payload),
+          (arguments, Type::Tuple(elements)) if elements.len() == arguments.len() => {
+            for (argument, element) in arguments.iter().zip(elements) {
+              self.bind_mapping_pattern(argument, &element);
+            }
+          }
+          (arguments, Type::Any) => {
+            for argument in arguments {
+              self.bind_mapping_pattern(argument, &Type::Any);
+            }
+          }
+          (arguments, other) => self.mismatch(location, &Type::Tuple(vec![Type::Any; arguments.len()]), &other),
+        }
+      }
+
+      MappingPattern::Vector(elements) => {
+        let element = self.vector_elements(&typ, elements.len(), location);
+        for pattern in elements {
+          self.bind_mapping_pattern(pattern, &element);
+        }
+      }
+
+      MappingPattern::VectorConcat(parts) => {
+        let widths = parts.iter().map(|part| self.mapping_pattern_width(part)).collect();
+        let types = self.concatenation_widths(&typ, widths, location);
+        for (part, typ) in parts.iter().zip(types) {
+          self.bind_mapping_pattern(part, &typ);
+        }
+      }
+
+      MappingPattern::VectorSubrange(identifier, high, low) => {
+        let width = subrange_width(high, low);
+        self.bind_identifier(identifier, &Type::Bitvector(width));
+      }
+
+      MappingPattern::Tuple(elements) => match &typ {
+        Type::Tuple(types) if types.len() == elements.len() => {
+          for (element, typ) in elements.iter().zip(types) {
+            self.bind_mapping_pattern(element, typ);
+          }
+        }
+        _ if elements.len() == 1 => self.bind_mapping_pattern(&elements[0], &typ),
+        Type::Any => {
+          for element in elements {
+            self.bind_mapping_pattern(element, &Type::Any);
+          }
+        }
+        other => self.mismatch(location, &Type::Tuple(vec![Type::Any; elements.len()]), other),
+      },
+
+      MappingPattern::List(elements) => {
+        let element = self.list_element(&typ, location);
+        for pattern in elements {
+          self.bind_mapping_pattern(pattern, &element);
+        }
+      }
+
+      MappingPattern::Cons(head, tail) => {
+        let element = self.list_element(&typ, location);
+        self.bind_mapping_pattern(head, &element);
+        self.bind_mapping_pattern(tail, &typ);
+      }
+
+      MappingPattern::StringAppend(parts) => {
+        if !matches!(typ, Type::String | Type::Any) {
+          self.mismatch(location, &Type::String, &typ);
+        }
+        for part in parts {
+          self.bind_mapping_pattern(part, &Type::String);
+        }
+      }
+
+      MappingPattern::Typed(inner, abstract_type) => {
+        self.bind_annotation(abstract_type, &typ, location);
+        self.bind_mapping_pattern(inner, &typ);
+      }
+
+      MappingPattern::As(inner, identifier) => {
+        self.bind_mapping_pattern(inner, &typ);
+        self.bind_identifier(identifier, &typ);
+      }
+
+      MappingPattern::Struct(fields) => {
+        for (name, pattern) in fields {
+          let field_type = self.field_types(&typ, &[name]).remove(0);
+          self.bind_mapping_pattern(pattern, &field_type);
+        }
+      }
+    }
+  }
+
+  /// The type of the other side of a mapping used as a pattern against a value of type `typ`: a mapping `m : A <-> B`
+  /// in a pattern matching an `A` binds its argument to a `B`, and vice versa.
+  fn mapping_payload(&mut self, name: &LocatedIdentifier, mapping: &FunctionType, typ: &Type) -> Type {
+    let left = mapping.arguments.first().cloned().unwrap_or(Type::Unit);
+    for (this_side, other_side) in [(&left, &mapping.result), (&mapping.result, &left)] {
+      let saved_context = self.context.clone();
+      let saved_errors = self.errors.len();
+      let mut state = Unification::default();
+      let mut renaming = Substitution::new();
+      for (variable, kind) in &mapping.variables {
+        let fresh = self.fresh_name(variable);
+        state.unknowns.insert(fresh.clone(), *kind);
+        renaming.insert(variable.clone(), variable_argument(&fresh, *kind));
+      }
+      if self.unify(&mut state, &this_side.substitute(&renaming), typ) {
+        let goals = std::mem::take(&mut state.goals);
+        if goals.iter().all(|goal| self.prove(&goal.substitute(&state.substitution), &name.location)) {
+          let payload = other_side.substitute(&renaming).substitute(&state.substitution);
+          let unresolved = state.unresolved();
+          return match unresolved.is_empty() {
+            true => payload,
+            false => Type::Existential(unresolved, mapping.constraint.substitute(&renaming), Box::new(payload)),
+          };
+        }
+      }
+      self.errors.truncate(saved_errors);
+      self.context = saved_context;
+    }
+    self.mismatch(&name.location, &Type::Bidirectional(Box::new(left), Box::new(mapping.result.clone())), typ);
+    Type::Any
+  }
+
+  // endregion
+
+  // region Definitions
+
+  pub fn definition(&mut self, definition: &LocatedDefinition) {
+    let (definition, _) = strip_definition(definition);
+    self.context = Context::default();
+    match &definition.value {
+      Definition::TypeDefinition(type_definition) => self.type_definition(type_definition),
+
+      Definition::Constraint(abstract_type) => {
+        if let Some(constraint) = self.convert_constraint(abstract_type) {
+          self.environment.constraints.push(constraint);
+        }
+      }
+
+      Definition::FunctionDefinition(function) => self.function(function),
+
+      Definition::InternalMutRec(functions) => {
+        for function in functions {
+          self.function(function);
+        }
+      }
+
+      Definition::MappingDefinition(mapping) => self.mapping(mapping),
+
+      Definition::ValueDefinition(binding) => {
+        self.let_binding(binding);
+        for (name, local) in std::mem::take(&mut self.context.locals) {
+          let mut free = BTreeSet::new();
+          local.typ.free_variables(&mut free);
+          let typ = match free.is_empty() {
+            true => local.typ,
+            false => weaken(local.typ),
+          };
+          self.environment.values.insert(name, typ);
+        }
+      }
+
+      Definition::Overload(name, targets) => {
+        self.environment
+            .overloads
+            .entry(name.name().to_string())
+            .or_default()
+            .extend(targets.iter().map(|target| target.name().to_string()));
+      }
+
+      Definition::Fixity(precedence, level, operator) => {
+        let level = level.to_i64().and_then(|level| u8::try_from(level).ok()).unwrap_or(9);
+        self.environment.fixities.declare(precedence, level, operator.name());
+      }
+
+      Definition::ValueSpec(specification) => {
+        let ValueSpecification::ValueSpec(scheme, name, _) = &specification.value;
+        self.value_specification(&scheme.quantifier, &scheme.abstract_type, name);
+      }
+
+      Definition::OutcomeSpec(outcome, definitions) => {
+        let OutcomeSpec::Outcome(name, scheme, _) = &outcome.value;
+        self.value_specification(&scheme.quantifier, &scheme.abstract_type, name);
+        for definition in definitions {
+          self.definition(definition);
+        }
+      }
+
+      Definition::DefaultTypingSpec(specification) => {
+        let DefaultTypingSpec::Order(_, order) = &specification.value;
+        self.environment.increasing = matches!(order.value, AbstractType::Increasing);
+      }
+
+      Definition::Register(declaration) => {
+        let DeclarationSpecification::Register(abstract_type, name, initializer) = &declaration.value;
+        let Some((typ, _)) = self.convert(abstract_type, false) else {
+          return;
+        };
+        if let Some(initializer) = initializer {
+          self.check(initializer, &typ);
+        }
+        self.environment.registers.insert(name.name().to_string(), typ);
+      }
+
+      _ => {}
+    }
+  }
+
+  /// The function type of a `val` specification or annotation, and its number of leading `implicit` arguments.
+  fn scheme_type(&mut self, quantifier: &LocatedTypeQuantifier, abstract_type: &LocatedAbstractType) -> Option<(FunctionType, usize)> {
+    let (variables, _) = kinds::check_scheme(&self.environment.signatures, quantifier, abstract_type);
+    let variables: Vec<(String, Kind)> = variables.into_iter().map(|variable| (variable.name, variable.kind)).collect();
+    self.context.type_variables = variables.iter().cloned().collect();
+
+    let mut constraint = Constraint::True;
+    for abstract_constraint in quantifier_constraints(quantifier) {
+      if let Some(part) = self.convert_constraint(abstract_constraint) {
+        constraint = Constraint::and(constraint, part);
+      }
+    }
+    let (typ, _) = self.convert(abstract_type, false)?;
+
+    let (arguments, result) = match typ {
+      Type::Function(arguments, result) => (arguments, *result),
+      Type::Bidirectional(left, right) => (vec![*left], *right),
+      _ => {
+        self.error(&abstract_type.location, TypeError::InvalidType("expected a function or mapping type".to_string()));
+        return None;
+      }
+    };
+
+    let mut lhs = abstract_type;
+    while let AbstractType::Parenthesized(inner) = &lhs.value {
+      lhs = inner;
+    }
+    let implicit = match &lhs.value {
+      AbstractType::Function { lhs, .. } => {
+        let is_implicit = |argument: &LocatedAbstractType| {
+          matches!(&argument.value, AbstractType::TypeConstructorApplication(name, _) if name.name() == "implicit")
+        };
+        match &lhs.value {
+          AbstractType::Tuple(elements) => elements.iter().take_while(|element| is_implicit(element)).count(),
+          _ => is_implicit(lhs) as usize,
+        }
+      }
+      _ => 0,
+    };
+
+    Some((FunctionType { variables, constraint, arguments, result }, implicit))
+  }
+
+  fn value_specification(&mut self, quantifier: &LocatedTypeQuantifier, abstract_type: &LocatedAbstractType, name: &LocatedIdentifier) {
+    let Some((function, implicit)) = self.scheme_type(quantifier, abstract_type) else {
+      return;
+    };
+    let name = name.name().to_string();
+    if implicit > 0 {
+      self.environment.implicit_arguments.insert(name.clone(), implicit);
+    }
+    let mut bare = abstract_type;
+    while let AbstractType::Parenthesized(inner) = &bare.value {
+      bare = inner;
+    }
+    match &bare.value {
+      AbstractType::Bidirectional { .. } => self.environment.mappings.insert(name, function),
+      _ => self.environment.functions.insert(name, function),
+    };
+  }
+
+  fn type_definition(&mut self, definition: &LocatedTypeDefinition) {
+    let name = type_definition_name(&definition.value).name().to_string();
+    let parameters = self.environment.signatures.get(&name).map(|signature| signature.parameters.clone()).unwrap_or_default();
+    let variables_of = |quantifier: &LocatedTypeQuantifier| -> Vec<(String, Kind)> {
+      quantifier_variables(quantifier).into_iter().zip(parameters.iter().copied()).collect()
+    };
+
+    match &definition.value {
+      TypeDefinition::Abbreviation(_, quantifier, kind, body) => {
+        let synonym = Synonym { variables: variables_of(quantifier), kind: kind.value, body: (**body).clone() };
+        self.environment.synonyms.insert(name, synonym);
+      }
+
+      TypeDefinition::Record(_, quantifier, fields) => {
+        let variables = variables_of(quantifier);
+        self.context.type_variables = variables.iter().cloned().collect();
+        let fields = fields
+            .iter()
+            .filter_map(|(field_type, field)| Some((field.name().to_string(), self.convert(field_type, false)?.0)))
+            .collect();
+        self.environment.records.insert(name, Record { variables, fields });
+      }
+
+      TypeDefinition::Variant(_, quantifier, unions) => {
+        let variables = variables_of(quantifier);
+        self.context.type_variables = variables.iter().cloned().collect();
+        let result = Type::Application(
+          name.clone(),
+          variables.iter().map(|(variable, kind)| variable_argument(variable, *kind)).collect(),
+        );
+        let mut constructors = Vec::new();
+        for union in unions {
+          let (payload, constructor) = match &strip_type_union(union).value {
+            TypeUnion::TypeIdentifier(payload, constructor) => match self.convert(payload, false) {
+              Some((payload, _)) => (payload, constructor),
+              None => (Type::Any, constructor),
+            },
+            TypeUnion::AnonymousRecord(fields, constructor) => {
+              let record_name = format!("{}_{}_record", name, constructor.name());
+              let fields = fields
+                  .iter()
+                  .filter_map(|(field_type, field)| Some((field.name().to_string(), self.convert(field_type, false)?.0)))
+                  .collect();
+              self.environment.records.insert(record_name.clone(), Record { variables: variables.clone(), fields });
+              let arguments = variables.iter().map(|(variable, kind)| variable_argument(variable, *kind)).collect();
+              (Type::Application(record_name, arguments), constructor)
+            }
+            _ => unreachable!("wrappers are removed by strip_type_union"),
+          };
+          let function = FunctionType {
+            variables : variables.clone(),
+            constraint: Constraint::True,
+            arguments : vec![payload],
+            result    : result.clone(),
+          };
+          self.environment.constructors.insert(constructor.name().to_string(), function);
+          constructors.push(constructor.name().to_string());
+        }
+        self.environment.variants.insert(name, Variant { variables, constructors });
+      }
+
+      TypeDefinition::Enum(_, _, members) => {
+        let mut names = Vec::new();
+        for (member, value) in members {
+          if let Some(value) = value {
+            self.infer(value);
+          }
+          self.environment.enum_members.insert(member.name().to_string(), name.clone());
+          names.push(member.name().to_string());
+        }
+        self.environment.enums.insert(name, names);
+      }
+
+      TypeDefinition::Abstract(..) => {}
+
+      TypeDefinition::Bitfield(_, abstract_type, fields) => {
+        let width = match self.convert(abstract_type, false) {
+          Some((Type::Bitvector(width), _)) => width,
+          Some((other, _)) => {
+            self.mismatch(&abstract_type.location, &Type::Bitvector(NumericExpression::variable("'n")), &other);
+            return;
+          }
+          None => return,
+        };
+        let mut bitfield = Bitfield { width, fields: Vec::new(), bits: Vec::new() };
+        let length = bitfield.width.as_constant();
+        if let Some(length) = &length {
+          let last = length.try_sub(&BigInteger::from_i64(1)).unwrap_or(BigInteger::from_i64(0));
+          bitfield.bits = match self.environment.increasing {
+            true => vec![(BigInteger::from_i64(0), last)],
+            false => vec![(last, BigInteger::from_i64(0))],
+          };
+        }
+        for (field, range) in fields {
+          let mut ranges = Vec::new();
+          self.index_ranges(range, &mut ranges);
+          if let Some(length) = &length {
+            if !self.field_in_range(&ranges, length) {
+              let error = TypeError::FieldOutOfRange { type_name: name.clone(), field: field.name().to_string() };
+              self.error(&range.location, error);
+            }
+          }
+          bitfield.fields.push((field.name().to_string(), ranges));
+        }
+        self.check_overlaps(&name, fields, &bitfield);
+        self.environment.bitfields.insert(name, bitfield);
+      }
+    }
+  }
+
+  /// Whether every range of a bitfield's field lies within its `length` bits and is written most significant index
+  /// first, which is the higher index in decreasing order and the lower in increasing order.
+  fn field_in_range(&self, ranges: &[(BigInteger, BigInteger)], length: &BigInteger) -> bool {
+    let zero = BigInteger::from_i64(0);
+    ranges.iter().all(|(first, second)| {
+      let ordered = match self.environment.increasing {
+        true => first <= second,
+        false => first >= second,
+      };
+      ordered && [first, second].iter().all(|index| **index >= zero && *index < length)
+    })
+  }
+
+  /// Reports fields of a bitfield that share bits, unless one lies entirely within the other, as a field and a part of
+  /// it given a name of its own do.
+  fn check_overlaps(&mut self, name: &str, fields: &[(LocatedIdentifier, LocatedIndexRange)], bitfield: &Bitfield) {
+    // Each field's bits as inclusive intervals of indices.
+    let intervals: Vec<Vec<(BigInteger, BigInteger)>> = bitfield
+        .fields
+        .iter()
+        .map(|(_, ranges)| ranges.iter().map(|(first, second)| (first.clone().min(second.clone()), first.clone().max(second.clone()))).collect())
+        .collect();
+    let size = |intervals: &[(BigInteger, BigInteger)]| shared_bits(intervals, intervals);
+    for (second, (field, _)) in fields.iter().enumerate() {
+      for first in 0..second {
+        let shared = shared_bits(&intervals[first], &intervals[second]);
+        if shared == BigInteger::from_i64(0) || shared == size(&intervals[first]) || shared == size(&intervals[second]) {
+          continue;
+        }
+        let error = TypeError::OverlappingFields {
+          type_name: name.to_string(),
+          first    : fields[first].0.name().to_string(),
+          second   : field.name().to_string(),
+        };
+        self.error(&field.location, error);
+      }
+    }
+  }
+
+  /// The constant `(high, low)` ranges of a bitfield field.
+  fn index_ranges(&mut self, range: &LocatedIndexRange, ranges: &mut Vec<(BigInteger, BigInteger)>) {
+    let constant = |this: &mut Self, index: &LocatedAbstractType| -> Option<BigInteger> {
+      let value = this.convert_numeric(index)?.as_constant();
+      if value.is_none() {
+        this.error(&index.location, TypeError::InvalidType("bitfield indices must be constant".to_string()));
+      }
+      value
+    };
+    match &range.value {
+      IndexRange::Single(index) => {
+        if let Some(index) = constant(self, index) {
+          ranges.push((index.clone(), index));
+        }
+      }
+      IndexRange::Range(high, low) => {
+        if let (Some(high), Some(low)) = (constant(self, high), constant(self, low)) {
+          ranges.push((high, low));
+        }
+      }
+      IndexRange::Concat(first, second) => {
+        self.index_ranges(first, ranges);
+        self.index_ranges(second, ranges);
+      }
+    }
+  }
+
+  /// The type of a function or mapping from its `val` specification, or failing that its own annotation.
+  fn declared_type(
+    &mut self,
+    name: &LocatedIdentifier,
+    mapping: bool,
+    annotation: Option<(&LocatedTypeQuantifier, &LocatedAbstractType)>,
+  ) -> Option<FunctionType> {
+    let declared = match mapping {
+      true => self.environment.mappings.get(name.name()),
+      false => self.environment.functions.get(name.name()),
+    };
+    if let Some(declared) = declared {
+      return Some(declared.clone());
+    }
+    match annotation {
+      Some((quantifier, abstract_type)) => {
+        let (function, implicit) = self.scheme_type(quantifier, abstract_type)?;
+        let key = name.name().to_string();
+        if implicit > 0 {
+          self.environment.implicit_arguments.insert(key.clone(), implicit);
+        }
+        match mapping {
+          true => self.environment.mappings.insert(key, function.clone()),
+          false => self.environment.functions.insert(key, function.clone()),
+        };
+        Some(function)
+      }
+      None => {
+        self.error(&name.location, TypeError::MissingSpecification(name.name().to_string()));
+        None
+      }
+    }
+  }
+
+  /// Resets the context to the start of a clause of a function or mapping of type `function`.
+  fn enter_clause(&mut self, function: &FunctionType) {
+    self.context = Context {
+      locals        : HashMap::new(),
+      type_variables: function.variables.iter().cloned().collect(),
+      assumptions   : Vec::new(),
+      return_type   : Some(function.result.clone()),
+    };
+    self.assume(function.constraint.clone());
+  }
+
+  fn function(&mut self, function: &LocatedFunctionDefinition) {
+    let FunctionDefinition::Function(_, annotation, _, clauses) = &function.value;
+    let Some((name, _)) = clauses.first().map(strip_function_clause) else {
+      return;
+    };
+    let annotation = annotation.value.as_ref().map(|(quantifier, abstract_type)| (quantifier, &**abstract_type));
+    let Some(function_type) = self.declared_type(name, false, annotation) else {
+      return;
+    };
+
+    let argument = match function_type.arguments.as_slice() {
+      [argument] => argument.clone(),
+      arguments => Type::Tuple(arguments.to_vec()),
+    };
+    for clause in clauses {
+      let (_, arm) = strip_function_clause(clause);
+      self.enter_clause(&function_type);
+      let (pattern, guard, body) = match &arm.value {
+        PatternExpression::Pattern(pattern, body) => (pattern, None, body),
+        PatternExpression::PatternWhen(pattern, guard, body) => (pattern, Some(guard), body),
+      };
+      self.bind_pattern(pattern, &argument);
+      if let Some(guard) = guard {
+        let p = self.condition(guard);
+        self.assume(p);
+      }
+      self.check(body, &function_type.result);
+    }
+    self.context = Context::default();
+  }
+
+  fn mapping_side(&mut self, side: &LocatedMappingPatternExpression, typ: &Type) {
+    match &side.value {
+      MappingPatternExpression::Pattern(pattern) => self.bind_mapping_pattern(pattern, typ),
+      MappingPatternExpression::PatternWhen(pattern, guard) => {
+        self.bind_mapping_pattern(pattern, typ);
+        let p = self.condition(guard);
+        self.assume(p);
+      }
+    }
+  }
+
+  fn mapping_clause(&mut self, mapping: &FunctionType, clause: &LocatedMappingClause) {
+    let left = mapping.arguments.first().cloned().unwrap_or(Type::Unit);
+    let right = mapping.result.clone();
+    self.enter_clause(mapping);
+    match &clause.value {
+      MappingClause::Attribute(_, _, inner)
+      | MappingClause::Documentation(_, inner) => self.mapping_clause(mapping, inner),
+
+      MappingClause::Bidirectional(left_side, right_side) => {
+        self.mapping_side(left_side, &left);
+        self.shared_bindings = true;
+        self.mapping_side(right_side, &right);
+        self.shared_bindings = false;
+      }
+
+      MappingClause::ForwardsDeprecated(left_side, body) => {
+        self.mapping_side(left_side, &left);
+        self.check(body, &right);
+      }
+
+      MappingClause::Forwards(arm) => {
+        self.arm(arm, &left, Some(&right));
+      }
+
+      MappingClause::Backwards(arm) => {
+        self.arm(arm, &right, Some(&left));
+      }
+    }
+  }
+
+  fn mapping(&mut self, mapping: &LocatedMappingDefinition) {
+    let MappingDefinition::Mapping(name, scheme, clauses) = &mapping.value;
+    let annotation = scheme.value.as_ref().map(|scheme| (&scheme.quantifier, &scheme.abstract_type));
+    let Some(mapping_type) = self.declared_type(name, true, annotation) else {
+      return;
+    };
+    for clause in clauses {
+      self.mapping_clause(&mapping_type, clause);
+    }
+    self.context = Context::default();
+  }
+
+  // endregion
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::parser::ast::*;
+  use crate::parser::location::SourceLocation;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::{check_more_types, check_types, Typing};
+
+  /// Checks `definitions`, returning what was learned and the messages of any errors.
+  fn check(program: Vec<LocatedDefinition>) -> (Typing, Vec<String>) {
+    let (typing, errors) = check_types(&definitions(program));
+    (typing, errors.iter().map(|error| error.value.to_string()).collect())
+  }
+
+  fn resolved<'t>(typing: &'t Typing, location: &SourceLocation) -> Option<&'t str> {
+    typing.calls.get(location).map(|call| call.function.as_str())
+  }
+
+  /// `and_bool`, `and_vec` on bytes, and `operator &` overloaded on both, as Sail's prelude declares them.
+  fn and_operators() -> Vec<LocatedDefinition> {
+    vec![
+      extern_val("and_bool", "and_bool", function_type(vec![typ("bool"), typ("bool")], typ("bool"))),
+      extern_val("and_vec", "and_vec", function_type(vec![bits(8), bits(8)], bits(8))),
+      overload("&", &["and_bool", "and_vec"]),
+    ]
+  }
+
+  #[test]
+  fn bitvector_and_resolves_to_the_vector_overload() {
+    let masked = infix(var("x"), "&", hex("0x0F"));
+    let location = masked.location.clone();
+    let mut program = and_operators();
+    program.push(val("f", function_type(vec![bits(8)], bits(8))));
+    program.push(function("f", vec![pattern("x")], masked));
+
+    let (typing, errors) = check(program);
+    assert_eq!(errors, Vec::<String>::new());
+    assert_eq!(resolved(&typing, &location), Some("and_vec"));
+  }
+
+  #[test]
+  fn boolean_and_resolves_to_and_bool() {
+    let conjunction = infix(var("a"), "&", var("b"));
+    let location = conjunction.location.clone();
+    let mut program = and_operators();
+    program.push(val("g", function_type(vec![typ("bool"), typ("bool")], typ("bool"))));
+    program.push(function("g", vec![pattern("a"), pattern("b")], conjunction));
+
+    let (typing, errors) = check(program);
+    assert_eq!(errors, Vec::<String>::new());
+    assert_eq!(resolved(&typing, &location), Some("and_bool"));
+  }
+
+  #[test]
+  fn boolean_operators_need_no_overload() {
+    let disjunction = infix(var("a"), "|", var("b"));
+    let location = disjunction.location.clone();
+    let program = vec![
+      val("g", function_type(vec![typ("bool"), typ("bool")], typ("bool"))),
+      function("g", vec![pattern("a"), pattern("b")], disjunction),
+    ];
+
+    let (typing, errors) = check(program);
+    assert_eq!(errors, Vec::<String>::new());
+    assert_eq!(resolved(&typing, &location), Some("or_bool"));
+  }
+
+  #[test]
+  fn kind_errors_are_reported() {
+    let (_, errors) = check(vec![val("f", function_type(vec![type_application("bits", vec![typ("bool")])], typ("unit")))]);
+    assert_eq!(errors, vec!["expected something of kind Int, but this has kind Type".to_string()]);
+  }
+
+  #[test]
+  fn kind_errors_in_more_definitions_are_reported() {
+    let (mut typing, _) = check(Vec::new());
+    let more = definitions(vec![val("g", function_type(vec![typ("unit")], type_application("bits", vec![typ("bool")])))]);
+    let errors = check_more_types(&mut typing, &more, None);
+    let messages: Vec<String> = errors.iter().map(|error| error.value.to_string()).collect();
+    assert_eq!(messages, vec!["expected something of kind Int, but this has kind Type".to_string()]);
+  }
+
+  #[test]
+  fn bodies_are_checked_against_the_declared_result() {
+    let (_, errors) = check(vec![
+      val("f", function_type(vec![bits(8)], typ("bool"))),
+      function("f", vec![pattern("x")], var("x")),
+    ]);
+    assert_eq!(errors, vec!["expected a value of type {'#p. bool('#p)}, found bits(8)".to_string()]);
+  }
+
+  #[test]
+  fn the_expected_type_reaches_both_branches() {
+    let literal = hex("0x0F");
+    let location = literal.location.clone();
+    let (typing, errors) = check(vec![
+      val("f", function_type(vec![typ("bool")], bits(8))),
+      function("f", vec![pattern("b")], if_then_else(var("b"), literal, hex("0x00"))),
+    ]);
+    assert_eq!(errors, Vec::<String>::new());
+    assert_eq!(typing.types[&location].to_string(), "bits(8)");
+  }
+
+  #[test]
+  fn annotated_expressions_are_checked_against_the_annotation() {
+    let (_, errors) = check(vec![value(pattern("x"), typed(typ("bool"), number(1)))]);
+    assert_eq!(errors, vec!["expected a value of type {'#p. bool('#p)}, found int(1)".to_string()]);
+  }
+
+  #[test]
+  fn overloads_are_chosen_by_argument_type() {
+    let on_int = call("twice", vec![number(1)]);
+    let on_bits = call("twice", vec![hex("0x01")]);
+    let (int_location, bits_location) = (on_int.location.clone(), on_bits.location.clone());
+    let (typing, errors) = check(vec![
+      extern_val("twice_int", "twice_int", function_type(vec![typ("int")], typ("int"))),
+      extern_val("twice_bits", "twice_bits", function_type(vec![bits(8)], bits(8))),
+      overload("twice", &["twice_int", "twice_bits"]),
+      value(pattern("a"), on_int),
+      value(pattern("b"), on_bits),
+    ]);
+    assert_eq!(errors, Vec::<String>::new());
+    assert_eq!(resolved(&typing, &int_location), Some("twice_int"));
+    assert_eq!(resolved(&typing, &bits_location), Some("twice_bits"));
+  }
+
+  #[test]
+  fn overloads_without_a_match_are_reported() {
+    let (_, errors) = check(vec![
+      extern_val("twice_int", "twice_int", function_type(vec![typ("int")], typ("int"))),
+      extern_val("twice_bits", "twice_bits", function_type(vec![bits(8)], bits(8))),
+      overload("twice", &["twice_int", "twice_bits"]),
+      value(pattern("a"), call("twice", vec![boolean(true)])),
+    ]);
+    assert_eq!(errors, vec!["no overload of `twice` accepts these arguments; tried twice_int, twice_bits".to_string()]);
+  }
+
+  #[test]
+  fn calls_must_satisfy_the_callee_constraint() {
+    let positive = type_operator(type_variable("'n"), ">", type_number(0));
+    let signature = scheme(&[("'n", Kind::Integer)], Some(positive), function_type(vec![type_application("atom", vec![type_variable("'n")])], typ("unit")));
+    let program = |argument| vec![val_scheme("f", signature.clone()), value(pattern("x"), call("f", vec![number(argument)]))];
+    let (_, errors) = check(program(1));
+    assert_eq!(errors, Vec::<String>::new());
+    let (_, errors) = check(program(0));
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("could not prove constraint"), "{}", errors[0]);
+  }
+}
@@ -0,0 +1,597 @@
+/*!
+
+The global typing environment, and the translation of `AbstractType`s into `Type`s within it.
+
+*/
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::*;
+use crate::parser::ast_util::{resolve_type_infix, Fixities};
+use crate::parser::location::{Located, SourceLocation};
+use crate::passes::kinds::TypeSignatures;
+use crate::passes::typecheck::types::{Constraint, FunctionType, NumericExpression, Substitution, Type, TypeArgument};
+use crate::passes::typecheck::{LocatedTypeError, TypeError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+  pub variables: Vec<(String, Kind)>,
+  pub fields   : Vec<(String, Type)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+  pub variables   : Vec<(String, Kind)>,
+  pub constructors: Vec<String>,
+}
+
+/// A type abbreviation, kept unconverted so that it can be expanded with its arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Synonym {
+  pub variables: Vec<(String, Kind)>,
+  pub kind     : Kind,
+  pub body     : LocatedAbstractType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitfield {
+  /// The length of the underlying bitvector
+  pub width : NumericExpression,
+  /// Each field's ranges, most significant first, as `(high, low)` bit indices
+  pub fields: Vec<(String, Vec<(BigInteger, BigInteger)>)>,
+  /// The whole of the underlying bitvector as a single range, Sail's `bits` field, or nothing if its width is not
+  /// constant
+  pub bits  : Vec<(BigInteger, BigInteger)>,
+}
+
+/// Where a field of a bitfield lies in the underlying bitvector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPositions {
+  pub name     : String,
+  /// The field's pieces as `(high, low)` bit positions, counting from the least significant bit, most significant
+  /// piece first
+  pub positions: Vec<(usize, usize)>,
+  pub width    : usize,
+}
+
+impl Bitfield {
+  /// The ranges of the field `name`, and their total width. Unless the bitfield has a field of its own called `bits`,
+  /// `bits` is the whole of it.
+  pub fn field(&self, name: &str) -> Option<(&[(BigInteger, BigInteger)], BigInteger)> {
+    let ranges = match self.fields.iter().find(|(field, _)| field == name) {
+      Some((_, ranges)) => ranges,
+      None if name == "bits" && !self.bits.is_empty() => &self.bits,
+      None => return None,
+    };
+    let width = ranges.iter().fold(BigInteger::from_i64(0), |total, (high, low)| {
+      let length = high.try_sub(low).and_then(|difference| difference.try_abs()).unwrap_or(BigInteger::from_i64(0));
+      total.try_add(&length).and_then(|sum| sum.try_add(&BigInteger::from_i64(1))).unwrap_or(total)
+    });
+    Some((ranges, width))
+  }
+
+  /// The bit positions of each field, in the order they are declared, where `increasing` is the program's default
+  /// order. Nothing if the width is not constant.
+  pub fn positions(&self, increasing: bool) -> Option<Vec<FieldPositions>> {
+    let length = self.width.as_constant()?.try_to_usize().ok()?;
+    let position = |index: &BigInteger| -> Option<usize> {
+      let index = index.try_to_usize().ok().filter(|index| *index < length)?;
+      Some(if increasing { length - 1 - index } else { index })
+    };
+    self.fields
+        .iter()
+        .map(|(name, ranges)| {
+          let positions = ranges
+              .iter()
+              .map(|(first, second)| Some((position(first)?, position(second)?)))
+              .collect::<Option<Vec<_>>>()?;
+          let width = positions.iter().map(|(high, low)| high.abs_diff(*low) + 1).sum();
+          Some(FieldPositions { name: name.clone(), positions, width })
+        })
+        .collect()
+  }
+}
+
+/// Everything declared at the top level of a program.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+  pub signatures        : TypeSignatures,
+  pub functions         : HashMap<String, FunctionType>,
+  /// Mappings, as functions from their left type to their right type
+  pub mappings          : HashMap<String, FunctionType>,
+  pub overloads         : HashMap<String, Vec<String>>,
+  pub registers         : HashMap<String, Type>,
+  /// Top-level `let` bindings
+  pub values            : HashMap<String, Type>,
+  /// Union constructors, as functions from their payload to the union type
+  pub constructors      : HashMap<String, FunctionType>,
+  /// The enum each enum member belongs to
+  pub enum_members      : HashMap<String, String>,
+  pub enums             : HashMap<String, Vec<String>>,
+  pub records           : HashMap<String, Record>,
+  pub variants          : HashMap<String, Variant>,
+  pub bitfields         : HashMap<String, Bitfield>,
+  pub synonyms          : HashMap<String, Synonym>,
+  /// The number of leading `implicit` arguments of each function that has any
+  pub implicit_arguments: HashMap<String, usize>,
+  pub fixities          : Fixities,
+  /// Whether `default Order inc` is in effect
+  pub increasing        : bool,
+  /// Top-level `constraint` declarations, assumed everywhere after them
+  pub constraints       : Vec<Constraint>,
+}
+
+impl Default for TypeSignatures {
+  fn default() -> Self {
+    TypeSignatures::builtin()
+  }
+}
+
+impl Environment {
+  pub fn new(signatures: TypeSignatures) -> Self {
+    Environment { signatures, ..Default::default() }
+  }
+
+  /// The bit positions of the fields of the bitfield type `name`, in the order they are declared.
+  pub fn bitfield_positions(&self, name: &str) -> Option<Vec<FieldPositions>> {
+    self.bitfields.get(name)?.positions(self.increasing)
+  }
+
+  /// The functions an overloaded name stands for, with nested overloads expanded, or just `name` if it is not
+  /// overloaded.
+  pub fn overload_candidates(&self, name: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    let mut pending = vec![name.to_string()];
+    let mut seen = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+      if !seen.insert(name.clone()) {
+        continue;
+      }
+      match self.overloads.get(&name) {
+        Some(targets) => pending.extend(targets.iter().rev().cloned()),
+        None => candidates.push(name),
+      }
+    }
+    candidates
+  }
+
+  /// The type of a union constructor or enum member used as a pattern or value, if `name` is one.
+  pub fn is_constant(&self, name: &str) -> bool {
+    self.enum_members.contains_key(name) || self.constructors.contains_key(name)
+  }
+}
+
+/// The names of the variables a quantifier binds, in order.
+pub fn quantifier_variables(quantifier: &LocatedTypeQuantifier) -> Vec<String> {
+  let TypeQuantifier::TypeQuantifiers(items) = &quantifier.value else {
+    return Vec::new();
+  };
+  items
+      .iter()
+      .filter_map(|item| match &item.value {
+        QuantifierItem::KindedIdentifier(kinded) => Some(kinded.identifiers.iter().map(|identifier| identifier.value.0.clone())),
+        QuantifierItem::Constraint(_) => None,
+      })
+      .flatten()
+      .collect()
+}
+
+/// The constraints of a quantifier.
+pub fn quantifier_constraints(quantifier: &LocatedTypeQuantifier) -> Vec<&LocatedAbstractType> {
+  let TypeQuantifier::TypeQuantifiers(items) = &quantifier.value else {
+    return Vec::new();
+  };
+  items
+      .iter()
+      .filter_map(|item| match &item.value {
+        QuantifierItem::Constraint(constraint) => Some(constraint),
+        QuantifierItem::KindedIdentifier(_) => None,
+      })
+      .collect()
+}
+
+fn invalid<T>(location: &SourceLocation, message: &str) -> Result<T, LocatedTypeError> {
+  Err(Located { location: location.clone(), value: TypeError::InvalidType(message.to_string()) })
+}
+
+/// Translates `AbstractType`s into `Type`s.
+pub struct Converter<'a> {
+  environment  : &'a Environment,
+  /// The type variables in scope and their kinds
+  scope        : HashMap<String, Kind>,
+  /// Arguments of a type abbreviation being expanded
+  substitution : Substitution,
+  /// Whether type variables not in scope are bound implicitly, as in pattern annotations, rather than reported
+  implicit     : bool,
+  /// Variables bound implicitly, in order of first appearance
+  pub new_variables: Vec<(String, Kind)>,
+}
+
+impl<'a> Converter<'a> {
+  pub fn new(environment: &'a Environment, scope: HashMap<String, Kind>, implicit: bool) -> Self {
+    Converter { environment, scope, substitution: Substitution::new(), implicit, new_variables: Vec::new() }
+  }
+
+  fn variable(&mut self, identifier: &LocatedKindIdentifier, kind: Kind) -> Result<TypeArgument, LocatedTypeError> {
+    let KindIdentifier(name) = &identifier.value;
+    if let Some(argument) = self.substitution.get(name) {
+      return Ok(argument.clone());
+    }
+
+    let found = match self.scope.get(name) {
+      Some(&found) => found,
+      None if self.implicit => {
+        self.scope.insert(name.clone(), kind);
+        self.new_variables.push((name.clone(), kind));
+        kind
+      }
+      None => return invalid(&identifier.location, &format!("type variable {} is not in scope", name)),
+    };
+
+    match (found, kind) {
+      (Kind::Type, Kind::Type) => Ok(TypeArgument::Type(Type::Variable(name.clone()))),
+      (Kind::Integer, Kind::Integer) => Ok(TypeArgument::Integer(NumericExpression::Variable(name.clone()))),
+      (Kind::Bool, Kind::Bool) => Ok(TypeArgument::Bool(Constraint::Variable(name.clone()))),
+      _ => invalid(&identifier.location, &format!("type variable {} has kind {}, not {}", name, found, kind)),
+    }
+  }
+
+  /// Expands an application of a type abbreviation.
+  fn expand(&mut self, synonym: &Synonym, arguments: &[LocatedAbstractType], location: &SourceLocation) -> Result<TypeArgument, LocatedTypeError> {
+    if arguments.len() != synonym.variables.len() {
+      return invalid(location, &format!("expected {} type argument(s), found {}", synonym.variables.len(), arguments.len()));
+    }
+    let mut substitution = Substitution::new();
+    for ((name, kind), argument) in synonym.variables.iter().zip(arguments) {
+      substitution.insert(name.clone(), self.argument(argument, *kind)?);
+    }
+    let mut inner = Converter::new(self.environment, HashMap::new(), false);
+    inner.substitution = substitution;
+    inner.argument(&synonym.body, synonym.kind)
+  }
+
+  pub fn argument(&mut self, abstract_type: &LocatedAbstractType, kind: Kind) -> Result<TypeArgument, LocatedTypeError> {
+    match kind {
+      Kind::Type => self.convert(abstract_type).map(TypeArgument::Type),
+      Kind::Integer => self.numeric(abstract_type).map(TypeArgument::Integer),
+      Kind::Bool => self.constraint(abstract_type).map(TypeArgument::Bool),
+      Kind::Order => match &abstract_type.value {
+        AbstractType::Increasing => Ok(TypeArgument::Order(true)),
+        AbstractType::Decreasing => Ok(TypeArgument::Order(false)),
+        AbstractType::Parenthesized(inner) => self.argument(inner, kind),
+        _ => invalid(&abstract_type.location, "expected `inc` or `dec`"),
+      },
+    }
+  }
+
+  /// A synonym of the given kind, if `name` is one.
+  fn synonym(&self, name: &str, kind: Kind) -> Option<Synonym> {
+    self.environment.synonyms.get(name).filter(|synonym| synonym.kind == kind).cloned()
+  }
+
+  pub fn convert(&mut self, abstract_type: &LocatedAbstractType) -> Result<Type, LocatedTypeError> {
+    let location = &abstract_type.location;
+    match &abstract_type.value {
+      AbstractType::Identifier(identifier) => match identifier.name() {
+        "unit" => Ok(Type::Unit),
+        "bit" => Ok(Type::Bit),
+        "string" => Ok(Type::String),
+        "real" => Ok(Type::Real),
+        "int" => Ok(Type::int()),
+        "nat" => Ok(Type::nat()),
+        "bool" => Ok(Type::bool()),
+        name => {
+          if let Some(synonym) = self.synonym(name, Kind::Type) {
+            let TypeArgument::Type(expanded) = self.expand(&synonym, &[], location)? else {
+              unreachable!("a Type synonym expands to a type")
+            };
+            return Ok(expanded);
+          }
+          match self.environment.signatures.get(name) {
+            Some(_) => Ok(Type::Application(name.to_string(), Vec::new())),
+            None => Err(Located { location: location.clone(), value: TypeError::UnknownType(name.to_string()) }),
+          }
+        }
+      },
+
+      AbstractType::Variable(identifier) => {
+        let TypeArgument::Type(result) = self.variable(identifier, Kind::Type)? else {
+          unreachable!("variable returns an argument of the requested kind")
+        };
+        Ok(result)
+      }
+
+      AbstractType::TypeConstructorApplication(identifier, arguments) => self.application(identifier, arguments, location),
+
+      AbstractType::Tuple(elements) => {
+        Ok(Type::Tuple(elements.iter().map(|element| self.convert(element)).collect::<Result<_, _>>()?))
+      }
+
+      AbstractType::Function { lhs, rhs, .. } => {
+        let arguments = match &lhs.value {
+          AbstractType::Tuple(elements) => elements.iter().map(|element| self.convert(element)).collect::<Result<_, _>>()?,
+          _ => vec![self.convert(lhs)?],
+        };
+        Ok(Type::Function(arguments, Box::new(self.convert(rhs)?)))
+      }
+
+      AbstractType::Bidirectional { lhs, rhs, .. } => {
+        Ok(Type::Bidirectional(Box::new(self.convert(lhs)?), Box::new(self.convert(rhs)?)))
+      }
+
+      AbstractType::Existential(variables, constraint, body) => {
+        let booleans = bool_variables(constraint);
+        let mut bound = Vec::new();
+        let saved_scope = self.scope.clone();
+        for identifier in variables {
+          let KindIdentifier(name) = &identifier.value;
+          let kind = match booleans.contains(name) || bool_variables(body).contains(name) {
+            true  => Kind::Bool,
+            false => Kind::Integer,
+          };
+          self.scope.insert(name.clone(), kind);
+          self.substitution.remove(name);
+          bound.push((name.clone(), kind));
+        }
+        let result = (|| Ok(Type::Existential(bound, self.constraint(constraint)?, Box::new(self.convert(body)?))))();
+        self.scope = saved_scope;
+        result
+      }
+
+      AbstractType::NumberSet(values) => {
+        let n = NumericExpression::variable("'#n");
+        let constraint = Constraint::Set(n.clone(), values.clone());
+        Ok(Type::Existential(vec![("'#n".to_string(), Kind::Integer)], constraint, Box::new(Type::Atom(n))))
+      }
+
+      AbstractType::Parenthesized(inner) => self.convert(inner),
+
+      AbstractType::Infix(tokens) => match resolve_type_infix(tokens) {
+        Some(tree) => self.convert(&tree),
+        None => Err(Located { location: location.clone(), value: TypeError::MalformedInfix }),
+      },
+
+      AbstractType::Wildcard => Ok(Type::Any),
+
+      _ => invalid(location, "expected a type"),
+    }
+  }
+
+  fn application(&mut self, identifier: &LocatedIdentifier, arguments: &[LocatedAbstractType], location: &SourceLocation) -> Result<Type, LocatedTypeError> {
+    let name = identifier.name();
+    match (name, arguments) {
+      ("atom" | "int" | "implicit" | "itself", [n]) => Ok(Type::Atom(self.numeric(n)?)),
+      ("atom_bool" | "bool", [p]) => Ok(Type::AtomBool(self.constraint(p)?)),
+      ("range", [low, high]) => {
+        let (low, high) = (self.numeric(low)?, self.numeric(high)?);
+        Ok(Type::range(low, high))
+      }
+      // The older two-argument forms also give an order, which is ignored.
+      ("bits" | "bitvector", [n]) | ("bits" | "bitvector", [n, _]) => Ok(Type::Bitvector(self.numeric(n)?)),
+      ("vector", [n, element]) | ("vector", [n, _, element]) => {
+        Ok(Type::Vector(self.numeric(n)?, Box::new(self.convert(element)?)))
+      }
+      ("list", [element]) => Ok(Type::List(Box::new(self.convert(element)?))),
+      ("register", [element]) => Ok(Type::Register(Box::new(self.convert(element)?))),
+      _ => {
+        if let Some(synonym) = self.synonym(name, Kind::Type) {
+          let TypeArgument::Type(expanded) = self.expand(&synonym, arguments, location)? else {
+            unreachable!("a Type synonym expands to a type")
+          };
+          return Ok(expanded);
+        }
+        let Some(signature) = self.environment.signatures.get(name).cloned() else {
+          return Err(Located { location: location.clone(), value: TypeError::UnknownType(name.to_string()) });
+        };
+        if signature.parameters.len() != arguments.len() {
+          return invalid(location, &format!("`{}` takes {} type argument(s)", name, signature.parameters.len()));
+        }
+        let arguments = arguments
+            .iter()
+            .zip(signature.parameters)
+            .map(|(argument, kind)| self.argument(argument, kind))
+            .collect::<Result<_, _>>()?;
+        Ok(Type::Application(name.to_string(), arguments))
+      }
+    }
+  }
+
+  pub fn numeric(&mut self, abstract_type: &LocatedAbstractType) -> Result<NumericExpression, LocatedTypeError> {
+    let location = &abstract_type.location;
+    let binary = |this: &mut Self, left: &LocatedAbstractType, right: &LocatedAbstractType| -> Result<_, LocatedTypeError> {
+      Ok((Box::new(this.numeric(left)?), Box::new(this.numeric(right)?)))
+    };
+
+    match &abstract_type.value {
+      AbstractType::Literal(literal) => match &literal.value {
+        Literal::Number(value) => Ok(NumericExpression::Constant(value.clone())),
+        _ => invalid(location, "expected an integer"),
+      },
+      AbstractType::Variable(identifier) => {
+        let TypeArgument::Integer(result) = self.variable(identifier, Kind::Integer)? else {
+          unreachable!("variable returns an argument of the requested kind")
+        };
+        Ok(result)
+      }
+      AbstractType::Identifier(identifier) => match self.synonym(identifier.name(), Kind::Integer) {
+        Some(synonym) => {
+          let TypeArgument::Integer(expanded) = self.expand(&synonym, &[], location)? else {
+            unreachable!("an Int synonym expands to an integer")
+          };
+          Ok(expanded)
+        }
+        None => invalid(location, &format!("`{}` is not an integer", identifier.name())),
+      },
+      AbstractType::Sum(left, right) => {
+        let (left, right) = binary(self, left, right)?;
+        Ok(NumericExpression::Sum(left, right))
+      }
+      AbstractType::Minus(left, right) => {
+        let (left, right) = binary(self, left, right)?;
+        Ok(NumericExpression::Minus(left, right))
+      }
+      AbstractType::Times(left, right) => {
+        let (left, right) = binary(self, left, right)?;
+        Ok(NumericExpression::Times(left, right))
+      }
+      AbstractType::Exponential(inner) => Ok(NumericExpression::Exponential(Box::new(self.numeric(inner)?))),
+      AbstractType::Negative(inner) => Ok(NumericExpression::Negative(Box::new(self.numeric(inner)?))),
+      AbstractType::Parenthesized(inner) => self.numeric(inner),
+      AbstractType::Infix(tokens) => match resolve_type_infix(tokens) {
+        Some(tree) => self.numeric(&tree),
+        None => Err(Located { location: location.clone(), value: TypeError::MalformedInfix }),
+      },
+      AbstractType::TypeConstructorApplication(identifier, arguments) => match (identifier.name(), arguments.as_slice()) {
+        (name @ ("div" | "mod" | "^"), [_, _])
+        | (name @ "abs", [_]) => Ok(NumericExpression::Application(
+          name.to_string(),
+          arguments.iter().map(|argument| self.numeric(argument)).collect::<Result<_, _>>()?,
+        )),
+        (name, _) => match self.synonym(name, Kind::Integer) {
+          Some(synonym) => {
+            let TypeArgument::Integer(expanded) = self.expand(&synonym, arguments, location)? else {
+              unreachable!("an Int synonym expands to an integer")
+            };
+            Ok(expanded)
+          }
+          None => invalid(location, &format!("`{}` is not an integer-valued type function", name)),
+        },
+      },
+      AbstractType::If { .. } => invalid(location, "conditional integer expressions are not supported"),
+      _ => invalid(location, "expected an integer"),
+    }
+    .map(NumericExpression::simplify)
+  }
+
+  pub fn constraint(&mut self, abstract_type: &LocatedAbstractType) -> Result<Constraint, LocatedTypeError> {
+    let location = &abstract_type.location;
+    match &abstract_type.value {
+      AbstractType::Literal(literal) => match &literal.value {
+        Literal::True => Ok(Constraint::True),
+        Literal::False => Ok(Constraint::False),
+        _ => invalid(location, "expected a constraint"),
+      },
+      AbstractType::Variable(identifier) => {
+        let TypeArgument::Bool(result) = self.variable(identifier, Kind::Bool)? else {
+          unreachable!("variable returns an argument of the requested kind")
+        };
+        Ok(result)
+      }
+      AbstractType::Identifier(identifier) => match self.synonym(identifier.name(), Kind::Bool) {
+        Some(synonym) => {
+          let TypeArgument::Bool(expanded) = self.expand(&synonym, &[], location)? else {
+            unreachable!("a Bool synonym expands to a constraint")
+          };
+          Ok(expanded)
+        }
+        None => invalid(location, &format!("`{}` is not a constraint", identifier.name())),
+      },
+      AbstractType::In(element, set) => match &set.value {
+        AbstractType::NumberSet(values) => Ok(Constraint::Set(self.numeric(element)?, values.clone())),
+        _ => invalid(&set.location, "expected a set of integers"),
+      },
+      AbstractType::Parenthesized(inner) => self.constraint(inner),
+      AbstractType::Infix(tokens) => match resolve_type_infix(tokens) {
+        Some(tree) => self.constraint(&tree),
+        None => Err(Located { location: location.clone(), value: TypeError::MalformedInfix }),
+      },
+      AbstractType::If { condition, then, elsewise } => {
+        let condition = self.constraint(condition)?;
+        Ok(Constraint::or(
+          Constraint::and(condition.clone(), self.constraint(then)?),
+          Constraint::and(Constraint::negate(condition), self.constraint(elsewise)?),
+        ))
+      }
+      AbstractType::TypeConstructorApplication(identifier, arguments) => match (identifier.name(), arguments.as_slice()) {
+        ("&", [left, right]) => Ok(Constraint::and(self.constraint(left)?, self.constraint(right)?)),
+        ("|", [left, right]) => Ok(Constraint::or(self.constraint(left)?, self.constraint(right)?)),
+        ("not", [inner]) => Ok(Constraint::negate(self.constraint(inner)?)),
+        (operator @ ("==" | "!="), [left, right]) if self.is_bool(left) || self.is_bool(right) => {
+          let equal = Constraint::iff(self.constraint(left)?, self.constraint(right)?);
+          match operator {
+            "==" => Ok(equal),
+            _ => Ok(Constraint::negate(equal)),
+          }
+        }
+        (operator @ ("==" | "!=" | "<=" | "<" | ">=" | ">"), [left, right]) => {
+          let (left, right) = (self.numeric(left)?, self.numeric(right)?);
+          Ok(match operator {
+            "==" => Constraint::Equal(left, right),
+            "!=" => Constraint::NotEqual(left, right),
+            "<=" => Constraint::LessEqual(left, right),
+            "<" => Constraint::Less(left, right),
+            ">=" => Constraint::GreaterEqual(left, right),
+            _ => Constraint::Greater(left, right),
+          })
+        }
+        (name, _) => match self.synonym(name, Kind::Bool) {
+          Some(synonym) => {
+            let TypeArgument::Bool(expanded) = self.expand(&synonym, arguments, location)? else {
+              unreachable!("a Bool synonym expands to a constraint")
+            };
+            Ok(expanded)
+          }
+          None => invalid(location, &format!("`{}` is not a constraint", name)),
+        },
+      },
+      _ => invalid(location, "expected a constraint"),
+    }
+  }
+
+  /// Whether `abstract_type` is evidently bool-kinded, for telling boolean from numeric equality.
+  fn is_bool(&self, abstract_type: &LocatedAbstractType) -> bool {
+    match &abstract_type.value {
+      AbstractType::Literal(literal) => matches!(literal.value, Literal::True | Literal::False),
+      AbstractType::Variable(identifier) => match self.substitution.get(&identifier.value.0) {
+        Some(argument) => matches!(argument, TypeArgument::Bool(_)),
+        None => self.scope.get(&identifier.value.0) == Some(&Kind::Bool),
+      },
+      AbstractType::Parenthesized(inner) => self.is_bool(inner),
+      AbstractType::TypeConstructorApplication(identifier, _) => {
+        matches!(identifier.name(), "&" | "|" | "not" | "==" | "!=" | "<=" | "<" | ">=" | ">")
+      }
+      AbstractType::In(..) => true,
+      _ => false,
+    }
+  }
+}
+
+/// Variables used where only a bool-kinded variable can appear: as the argument of `bool` or `atom_bool`, or as an
+/// operand of `&`, `|` or `not`. Existentially bound variables are taken to be integers otherwise.
+fn bool_variables(abstract_type: &LocatedAbstractType) -> BTreeSet<String> {
+  fn collect(abstract_type: &LocatedAbstractType, in_bool_position: bool, variables: &mut BTreeSet<String>) {
+    match &abstract_type.value {
+      AbstractType::Variable(identifier) if in_bool_position => {
+        variables.insert(identifier.value.0.clone());
+      }
+      AbstractType::Parenthesized(inner) => collect(inner, in_bool_position, variables),
+      AbstractType::Infix(tokens) => {
+        if let Some(tree) = resolve_type_infix(tokens) {
+          collect(&tree, in_bool_position, variables);
+        }
+      }
+      AbstractType::TypeConstructorApplication(identifier, arguments) => {
+        let bool_arguments = matches!(identifier.name(), "bool" | "atom_bool" | "&" | "|" | "not");
+        for argument in arguments {
+          collect(argument, bool_arguments, variables);
+        }
+      }
+      AbstractType::Tuple(elements) => {
+        for element in elements {
+          collect(element, false, variables);
+        }
+      }
+      AbstractType::Function { lhs, rhs, .. }
+      | AbstractType::Bidirectional { lhs, rhs, .. } => {
+        collect(lhs, false, variables);
+        collect(rhs, false, variables);
+      }
+      _ => {}
+    }
+  }
+
+  let mut variables = BTreeSet::new();
+  collect(abstract_type, false, &mut variables);
+  variables
+}
@@ -0,0 +1,261 @@
+/*!
+
+The type checker.
+
+Sail's types are dependent on integers and booleans: `bits('n)` is a bitvector of length `'n`, `atom('n)` the integer
+`'n` and nothing else, and a function type such as `forall 'n, 'n > 0. bits('n) -> bits(2 * 'n)` carries a constraint
+that every call must satisfy. The checker is bidirectional, in the style of Sail's own: an expression is either checked
+against a type the context expects or has its type inferred, and the checker switches between the two where it must.
+
+Wherever one type must be a subtype of another, the checker generates constraints, such as that two lengths are equal
+or that an index is within bounds, and discharges them with the linear arithmetic decision procedure in `omega`,
+assuming the constraints of the enclosing function's type and any facts learned on the way, such as the condition of an
+enclosing `if`. A constraint that cannot be proved is reported at the expression that gave rise to it. Constraints
+beyond `omega`, which are those with nonlinear terms, can be passed on to an external solver (see `solver`).
+
+Definitions are checked in program order, as Sail requires types and value specifications to be declared before they
+are used. The pass runs the kind checker (see `kinds`) to learn the signatures of types, and reports its errors with its
+own. It assumes that names have been resolved (see `resolve`) and does not report those errors again; where it meets an
+unknown name it reports it only if nothing else would have.
+
+*/
+
+mod checker;
+mod environment;
+pub mod omega;
+pub mod solver;
+pub mod types;
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::parser::ast::*;
+use crate::parser::location::{Located, SourceLocation};
+use crate::passes::kinds::{self, KindError, LocatedKindError};
+
+pub use environment::{Bitfield, Environment, FieldPositions, Record, Synonym, Variant};
+pub use solver::{ConstraintSolver, SmtSolver};
+pub use types::{Constraint, FunctionType, NumericExpression, Substitution, Type, TypeArgument};
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum TypeError {
+  /// A value of one type is used where another is expected.
+  Mismatch { expected: String, found: String },
+  /// A constraint could not be proved from what is known at this point.
+  Unprovable { constraint: String },
+  Undefined(String),
+  UnknownType(String),
+  /// A type written in the source cannot be interpreted.
+  InvalidType(String),
+  NotAFunction(String),
+  Arity { name: String, expected: usize, found: usize },
+  /// None of the functions an overloaded name stands for accepts these arguments.
+  NoOverload { name: String, candidates: Vec<String> },
+  /// The type of an expression cannot be inferred and must be given by an annotation.
+  CannotInfer(String),
+  UnknownField { type_name: String, field: String },
+  MissingField { type_name: String, field: String },
+  /// The left of an assignment is not something that can be assigned to.
+  NotAssignable,
+  /// Assignment to a variable bound by `let` or a pattern.
+  Immutable(String),
+  /// A function with neither a `val` specification nor a type annotation.
+  MissingSpecification(String),
+  /// `return` outside a function.
+  ReturnOutsideFunction,
+  MalformedInfix,
+  /// A field of a bitfield with bits outside the underlying bitvector, or a range written the wrong way round.
+  FieldOutOfRange { type_name: String, field: String },
+  /// Two fields of a bitfield that share bits without either lying within the other.
+  OverlappingFields { type_name: String, first: String, second: String },
+  /// A type-level expression that is not well kinded.
+  Kind(KindError),
+}
+
+pub type LocatedTypeError = Located<TypeError>;
+
+impl TypeError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TypeError::Mismatch { expected, found } => {
+        write!(f, "expected a value of type {}, found {}", expected, found)
+      }
+
+      TypeError::Unprovable { constraint } => {
+        write!(f, "could not prove constraint {}", constraint)
+      }
+
+      TypeError::Undefined(name) => {
+        write!(f, "undefined identifier `{}`", name)
+      }
+
+      TypeError::UnknownType(name) => {
+        write!(f, "unknown type `{}`", name)
+      }
+
+      TypeError::InvalidType(message) => {
+        write!(f, "invalid type: {}", message)
+      }
+
+      TypeError::NotAFunction(name) => {
+        write!(f, "`{}` is not a function", name)
+      }
+
+      TypeError::Arity { name, expected, found } => {
+        write!(f, "`{}` takes {} argument(s), but {} were given", name, expected, found)
+      }
+
+      TypeError::NoOverload { name, candidates } => {
+        write!(f, "no overload of `{}` accepts these arguments; tried {}", name, candidates.join(", "))
+      }
+
+      TypeError::CannotInfer(message) => {
+        write!(f, "cannot infer {}; add a type annotation", message)
+      }
+
+      TypeError::UnknownField { type_name, field } => {
+        write!(f, "type {} has no field `{}`", type_name, field)
+      }
+
+      TypeError::MissingField { type_name, field } => {
+        write!(f, "missing field `{}` of type {}", field, type_name)
+      }
+
+      TypeError::NotAssignable => {
+        write!(f, "this expression cannot be assigned to")
+      }
+
+      TypeError::Immutable(name) => {
+        write!(f, "cannot assign to immutable variable `{}`", name)
+      }
+
+      TypeError::MissingSpecification(name) => {
+        write!(f, "function `{}` has no `val` specification or type annotation", name)
+      }
+
+      TypeError::ReturnOutsideFunction => {
+        write!(f, "`return` outside of a function")
+      }
+
+      TypeError::MalformedInfix => {
+        write!(f, "malformed operator expression")
+      }
+
+      TypeError::FieldOutOfRange { type_name, field } => {
+        write!(f, "field `{}` of the bitfield `{}` does not lie within it, most significant index first", field, type_name)
+      }
+
+      TypeError::OverlappingFields { type_name, first, second } => {
+        write!(f, "fields `{}` and `{}` of the bitfield `{}` partly overlap", first, second, type_name)
+      }
+
+      TypeError::Kind(error) => error.msg(f),
+    }
+  }
+}
+
+impl Debug for TypeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for TypeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for TypeError {}
+
+/// What the type checker learns about a program.
+#[derive(Debug, Clone, Default)]
+pub struct Typing {
+  /// The global environment after the last definition
+  pub environment: Environment,
+  /// The type of every expression with a source location, as checked or inferred. Existentials are not opened, so an
+  /// expression of type `int` is recorded as `int` rather than as some `atom('n#3)`.
+  pub types      : HashMap<SourceLocation, Type>,
+  /// The function each call with a source location resolved to, keyed by the location of the call
+  pub calls      : HashMap<SourceLocation, Call>,
+}
+
+/// What a call resolved to once overloading and mapping directions are settled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+  /// The function called, which for a mapping is the derived function for the direction used
+  pub function: String,
+  /// The values of leading implicit arguments the call left out
+  pub implicit: Vec<NumericExpression>,
+  /// What the call instantiates the function's type variables with, for those it determines, in terms of the type
+  /// variables in scope at the call
+  pub instantiation: Substitution,
+}
+
+/// Type checks a whole program, which should already have had scattered definitions collected.
+pub fn check_types(definitions: &Definitions) -> (Typing, Vec<LocatedTypeError>) {
+  check_types_with_solver(definitions, None)
+}
+
+/// Type checks a whole program, consulting `solver` for constraints the built-in solver cannot decide.
+pub fn check_types_with_solver(
+  definitions: &Definitions,
+  solver     : Option<&mut dyn ConstraintSolver>,
+) -> (Typing, Vec<LocatedTypeError>) {
+  let (signatures, kind_errors) = kinds::check_kinds(definitions);
+  let mut checker = checker::Checker::new(signatures, solver);
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      checker.definition(definition);
+    }
+  }
+  let (typing, errors) = checker.finish();
+  (typing, with_kind_errors(kind_errors, errors))
+}
+
+/// Type checks more definitions, following the program `typing` is the result of checking, adding what it learns to
+/// `typing`, and consulting `solver` as `check_types_with_solver` does.
+pub fn check_more_types(
+  typing     : &mut Typing,
+  definitions: &Definitions,
+  solver     : Option<&mut dyn ConstraintSolver>,
+) -> Vec<LocatedTypeError> {
+  let mut signatures = std::mem::take(&mut typing.environment.signatures);
+  let kind_errors = kinds::check_more_kinds(&mut signatures, definitions);
+  typing.environment.signatures = signatures;
+
+  let mut checker = checker::Checker::resume(std::mem::take(typing), solver);
+  for (_, file) in definitions.0.iter() {
+    for definition in file {
+      checker.definition(definition);
+    }
+  }
+  let (result, errors) = checker.finish();
+  *typing = result;
+  with_kind_errors(kind_errors, errors)
+}
+
+/// Kind errors followed by type errors, leaving out those the checker reports again for an ill-kinded type.
+fn with_kind_errors(kind_errors: Vec<LocatedKindError>, errors: Vec<LocatedTypeError>) -> Vec<LocatedTypeError> {
+  let ill_kinded: HashSet<SourceLocation> = kind_errors.iter().map(|error| error.location.clone()).collect();
+  kind_errors
+      .into_iter()
+      .map(|error| error.map(TypeError::Kind))
+      .chain(errors.into_iter().filter(|error| !ill_kinded.contains(&error.location)))
+      .collect()
+}
+
+/// Infers the type of an expression in the global environment of the program `typing` is the result of checking,
+/// adding the types of its parts to `typing`, and consulting `solver` as `check_types_with_solver` does.
+pub fn check_expression(
+  typing    : &mut Typing,
+  expression: &LocatedExpression,
+  solver    : Option<&mut dyn ConstraintSolver>,
+) -> (Type, Vec<LocatedTypeError>) {
+  let mut checker = checker::Checker::resume(std::mem::take(typing), solver);
+  let typ = checker.top_level_expression(expression);
+  let (result, errors) = checker.finish();
+  *typing = result;
+  (typ, errors)
+}
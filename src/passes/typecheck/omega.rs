@@ -0,0 +1,626 @@
+/*!
+
+A decision procedure for the constraints the type checker generates.
+
+Constraints are quantifier-free formulas over integer and boolean type variables. The type checker proves a goal by
+showing that its assumptions together with the negation of the goal are unsatisfiable. Satisfiability is decided in two
+layers:
+
+ * The formula is split lazily into conjunctions of linear equalities and inequalities, one disjunct at a time, so that
+   a satisfiable disjunct found early ends the search.
+ * Each conjunction is decided by the Omega test of Pugh (1991): equalities are eliminated exactly, using the
+   "symmetric modulo" trick when no variable has a unit coefficient, and inequalities by Fourier–Motzkin elimination,
+   which is exact over the integers when one side of every pair has a unit coefficient. Otherwise the real shadow, the
+   dark shadow and, if need be, the splinters between them decide the problem exactly.
+
+Nonlinear terms, such as `'n * 'm` or `2 ^ 'n` for unknown `'n`, are treated as opaque variables, with syntactically
+equal terms sharing a variable and `2 ^ 'n` known to be positive. This is sound for proving unsatisfiability, and so
+for proving goals, but some true goals involving such terms cannot be proved; the answer is then `Unknown`. `Unknown`
+is also the answer when coefficients overflow or a problem is too large to finish within the search budget.
+
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::abstractions::Integer;
+use crate::passes::typecheck::types::{Constraint, NumericExpression};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Satisfiability {
+  Satisfiable,
+  Unsatisfiable,
+  /// The procedure gave up. See the module documentation.
+  Unknown,
+}
+
+/// The number of Omega problems, including splinters, one call to `satisfiable` may solve before giving up.
+const OMEGA_BUDGET: usize = 10_000;
+
+/// The number of disjuncts one call to `satisfiable` may examine before giving up.
+const DISJUNCT_BUDGET: usize = 4_096;
+
+/// Decides whether the conjunction of `constraints` has a solution over the integers and booleans.
+pub fn satisfiable(constraints: &[Constraint]) -> Satisfiability {
+  let mut search = Search {
+    variables      : HashMap::new(),
+    side_conditions: Vec::new(),
+    omega_budget   : OMEGA_BUDGET,
+    disjunct_budget: DISJUNCT_BUDGET,
+  };
+  let pending = constraints.iter().map(|constraint| (constraint.clone(), true)).collect();
+  search.search(Conjunct::default(), pending)
+}
+
+// region Linear expressions
+
+/// `constant + Σ coefficient · variable`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Linear {
+  constant: i128,
+  terms   : BTreeMap<usize, i128>,
+}
+
+impl Linear {
+  fn constant(value: i128) -> Self {
+    Linear { constant: value, terms: BTreeMap::new() }
+  }
+
+  fn variable(variable: usize) -> Self {
+    Linear { constant: 0, terms: BTreeMap::from([(variable, 1)]) }
+  }
+
+  fn is_constant(&self) -> bool {
+    self.terms.is_empty()
+  }
+
+  fn coefficient(&self, variable: usize) -> i128 {
+    self.terms.get(&variable).copied().unwrap_or(0)
+  }
+
+  /// `self + factor · other`
+  fn add_scaled(&self, other: &Linear, factor: i128) -> Option<Linear> {
+    let mut result = self.clone();
+    result.constant = result.constant.checked_add(other.constant.checked_mul(factor)?)?;
+    for (&variable, &coefficient) in &other.terms {
+      let sum = result.coefficient(variable).checked_add(coefficient.checked_mul(factor)?)?;
+      match sum {
+        0 => result.terms.remove(&variable),
+        _ => result.terms.insert(variable, sum),
+      };
+    }
+    Some(result)
+  }
+
+  fn scale(&self, factor: i128) -> Option<Linear> {
+    Linear::default().add_scaled(self, factor)
+  }
+
+  fn sub(&self, other: &Linear) -> Option<Linear> {
+    self.add_scaled(other, -1)
+  }
+
+  fn terms_gcd(&self) -> i128 {
+    self.terms.values().fold(0, |acc, &coefficient| gcd(acc, coefficient))
+  }
+
+  /// Replaces `variable` using the equality `equality = 0`, in which it has coefficient ±1.
+  fn eliminate(&self, variable: usize, equality: &Linear) -> Option<Linear> {
+    let coefficient = self.coefficient(variable);
+    match coefficient {
+      0 => Some(self.clone()),
+      _ => self.add_scaled(equality, -coefficient.checked_mul(equality.coefficient(variable))?),
+    }
+  }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+  let (mut a, mut b) = (a.abs(), b.abs());
+  while b != 0 {
+    (a, b) = (b, a % b);
+  }
+  a
+}
+
+/// `a mod^ m`, the remainder of `a` divided by `m` taken in `(-m/2, m/2]`.
+fn symmetric_mod(a: i128, m: i128) -> Option<i128> {
+  let quotient = (a.checked_mul(2)?.checked_add(m)?).div_euclid(m.checked_mul(2)?);
+  a.checked_sub(m.checked_mul(quotient)?)
+}
+
+// endregion
+
+// region Disjunct search
+
+/// One disjunct: a conjunction of linear constraints and boolean literals.
+#[derive(Debug, Clone, Default)]
+struct Conjunct {
+  /// Each is `= 0`
+  equalities  : Vec<Linear>,
+  /// Each is `>= 0`
+  inequalities: Vec<Linear>,
+  booleans    : BTreeMap<String, bool>,
+}
+
+struct Search {
+  /// Type variables and opaque nonlinear terms, by name
+  variables      : HashMap<String, usize>,
+  /// Facts about opaque terms, such as `2 ^ 'n >= 1`, each `>= 0`
+  side_conditions: Vec<Linear>,
+  omega_budget   : usize,
+  disjunct_budget: usize,
+}
+
+impl Search {
+  fn variable(&mut self, name: String) -> usize {
+    let next = self.variables.len();
+    *self.variables.entry(name).or_insert(next)
+  }
+
+  /// Linearises `expression`, treating nonlinear subterms as opaque variables.
+  fn linear(&mut self, expression: &NumericExpression) -> Option<Linear> {
+    if let Some(value) = expression.as_constant() {
+      return Some(Linear::constant(value.to_i64()? as i128));
+    }
+
+    match expression {
+      NumericExpression::Constant(value) => Some(Linear::constant(value.to_i64()? as i128)),
+      NumericExpression::Variable(name) => Some(Linear::variable(self.variable(name.clone()))),
+      NumericExpression::Sum(left, right) => self.linear(left)?.add_scaled(&self.linear(right)?, 1),
+      NumericExpression::Minus(left, right) => self.linear(left)?.sub(&self.linear(right)?),
+      NumericExpression::Negative(inner) => self.linear(inner)?.scale(-1),
+      NumericExpression::Times(left, right) => {
+        let (left_linear, right_linear) = (self.linear(left)?, self.linear(right)?);
+        match (left_linear.is_constant(), right_linear.is_constant()) {
+          (true, _) => right_linear.scale(left_linear.constant),
+          (_, true) => left_linear.scale(right_linear.constant),
+          _ => Some(self.opaque(expression)),
+        }
+      }
+      NumericExpression::Exponential(_) => {
+        let term = self.opaque(expression);
+        let positive = term.add_scaled(&Linear::constant(1), -1)?;
+        self.side_conditions.push(positive);
+        Some(term)
+      }
+      NumericExpression::Application(..) => Some(self.opaque(expression)),
+    }
+  }
+
+  fn opaque(&mut self, expression: &NumericExpression) -> Linear {
+    Linear::variable(self.variable(expression.to_string()))
+  }
+
+  /// Searches the disjuncts of `conjunct` together with the `pending` constraints, each with its polarity.
+  fn search(&mut self, mut conjunct: Conjunct, mut pending: Vec<(Constraint, bool)>) -> Satisfiability {
+    use Constraint::*;
+
+    while let Some((constraint, positive)) = pending.pop() {
+      let (left, right) = match &constraint {
+        Equal(left, right)
+        | NotEqual(left, right)
+        | LessEqual(left, right)
+        | Less(left, right)
+        | GreaterEqual(left, right)
+        | Greater(left, right) => {
+          let (Some(left), Some(right)) = (self.linear(left), self.linear(right)) else {
+            return Satisfiability::Unknown;
+          };
+          (left, right)
+        }
+        _ => (Linear::default(), Linear::default()),
+      };
+      // `left - right` and `right - left`, for the comparisons
+      let (Some(difference), Some(reverse)) = (left.sub(&right), right.sub(&left)) else {
+        return Satisfiability::Unknown;
+      };
+      let (Some(strict), Some(reverse_strict)) = (difference.add_scaled(&Linear::constant(-1), 1), reverse.add_scaled(&Linear::constant(-1), 1)) else {
+        return Satisfiability::Unknown;
+      };
+
+      match (constraint, positive) {
+        (True, true) | (False, false) => {}
+        (True, false) | (False, true) => return Satisfiability::Unsatisfiable,
+
+        (Variable(name), polarity) => {
+          if *conjunct.booleans.entry(name).or_insert(polarity) != polarity {
+            return Satisfiability::Unsatisfiable;
+          }
+        }
+
+        (Not(inner), polarity) => pending.push((*inner, !polarity)),
+
+        (And(left, right), true)
+        | (Or(left, right), false) => {
+          pending.push((*left, positive));
+          pending.push((*right, positive));
+        }
+
+        (Or(left, right), true)
+        | (And(left, right), false) => {
+          return self.branch(&conjunct, &pending, vec![(*left, positive), (*right, positive)]);
+        }
+
+        (Equal(..), true)
+        | (NotEqual(..), false) => conjunct.equalities.push(difference),
+
+        (Equal(a, b), false)
+        | (NotEqual(a, b), true) => {
+          return self.branch(&conjunct, &pending, vec![(Less(a.clone(), b.clone()), true), (Greater(a, b), true)]);
+        }
+
+        // a <= b  ⟺  b - a >= 0
+        (LessEqual(..), true) | (Greater(..), false) => conjunct.inequalities.push(reverse),
+        // a < b  ⟺  b - a - 1 >= 0
+        (Less(..), true) | (GreaterEqual(..), false) => conjunct.inequalities.push(reverse_strict),
+        // a >= b  ⟺  a - b >= 0
+        (GreaterEqual(..), true) | (Less(..), false) => conjunct.inequalities.push(difference),
+        // a > b  ⟺  a - b - 1 >= 0
+        (Greater(..), true) | (LessEqual(..), false) => conjunct.inequalities.push(strict),
+
+        (Set(element, values), true) => {
+          let alternatives = values
+              .into_iter()
+              .map(|value| (Equal(element.clone(), NumericExpression::Constant(value)), true))
+              .collect();
+          return self.branch(&conjunct, &pending, alternatives);
+        }
+        (Set(element, values), false) => {
+          for value in values {
+            pending.push((Equal(element.clone(), NumericExpression::Constant(value)), false));
+          }
+        }
+      }
+    }
+
+    self.decide(conjunct)
+  }
+
+  /// Tries each alternative in turn, stopping at the first satisfiable one.
+  fn branch(&mut self, conjunct: &Conjunct, pending: &[(Constraint, bool)], alternatives: Vec<(Constraint, bool)>) -> Satisfiability {
+    let mut result = Satisfiability::Unsatisfiable;
+    for alternative in alternatives {
+      let mut pending = pending.to_vec();
+      pending.push(alternative);
+      match self.search(conjunct.clone(), pending) {
+        Satisfiability::Satisfiable => return Satisfiability::Satisfiable,
+        Satisfiability::Unknown => result = Satisfiability::Unknown,
+        Satisfiability::Unsatisfiable => {}
+      }
+    }
+    result
+  }
+
+  fn decide(&mut self, mut conjunct: Conjunct) -> Satisfiability {
+    if self.disjunct_budget == 0 {
+      return Satisfiability::Unknown;
+    }
+    self.disjunct_budget -= 1;
+
+    conjunct.inequalities.extend(self.side_conditions.iter().cloned());
+    let mut next_variable = self.variables.len();
+    omega(conjunct.equalities, conjunct.inequalities, &mut next_variable, &mut self.omega_budget)
+  }
+}
+
+// endregion
+
+// region The Omega test
+
+/// Divides an equality through by the gcd of its coefficients. `None` if it has no integer solution.
+fn normalize_equality(equality: Linear) -> Option<Option<Linear>> {
+  let divisor = equality.terms_gcd();
+  if divisor == 0 {
+    // No variables: trivially true or false
+    return match equality.constant {
+      0 => Some(None),
+      _ => None,
+    };
+  }
+  if equality.constant % divisor != 0 {
+    return None;
+  }
+  let terms = equality.terms.into_iter().map(|(variable, coefficient)| (variable, coefficient / divisor)).collect();
+  Some(Some(Linear { constant: equality.constant / divisor, terms }))
+}
+
+/// Divides an inequality through by the gcd of its coefficients, rounding the constant down, which tightens it over
+/// the integers. `None` if it is false.
+fn normalize_inequality(inequality: Linear) -> Option<Option<Linear>> {
+  let divisor = inequality.terms_gcd();
+  if divisor == 0 {
+    return match inequality.constant >= 0 {
+      true  => Some(None),
+      false => None,
+    };
+  }
+  let terms = inequality.terms.into_iter().map(|(variable, coefficient)| (variable, coefficient / divisor)).collect();
+  Some(Some(Linear { constant: inequality.constant.div_euclid(divisor), terms }))
+}
+
+fn omega(equalities: Vec<Linear>, inequalities: Vec<Linear>, next_variable: &mut usize, budget: &mut usize) -> Satisfiability {
+  if *budget == 0 {
+    return Satisfiability::Unknown;
+  }
+  *budget -= 1;
+
+  let mut normalized_equalities = Vec::new();
+  for equality in equalities {
+    match normalize_equality(equality) {
+      None => return Satisfiability::Unsatisfiable,
+      Some(Some(equality)) => normalized_equalities.push(equality),
+      Some(None) => {}
+    }
+  }
+  let mut normalized_inequalities = Vec::new();
+  for inequality in inequalities {
+    match normalize_inequality(inequality) {
+      None => return Satisfiability::Unsatisfiable,
+      Some(Some(inequality)) => normalized_inequalities.push(inequality),
+      Some(None) => {}
+    }
+  }
+
+  if let Some(equality) = normalized_equalities.pop() {
+    return eliminate_equality(equality, normalized_equalities, normalized_inequalities, next_variable, budget);
+  }
+
+  // Keep only the tightest of parallel inequalities, and spot pairs that pin an expression to one value.
+  let mut tightest: BTreeMap<Vec<(usize, i128)>, i128> = BTreeMap::new();
+  for inequality in normalized_inequalities {
+    let key: Vec<(usize, i128)> = inequality.terms.into_iter().collect();
+    let constant = tightest.entry(key).or_insert(inequality.constant);
+    *constant = (*constant).min(inequality.constant);
+  }
+  let mut inequalities = Vec::new();
+  let mut equalities = Vec::new();
+  for (terms, &constant) in &tightest {
+    let negated: Vec<(usize, i128)> = terms.iter().map(|&(variable, coefficient)| (variable, -coefficient)).collect();
+    if let Some(&opposite) = tightest.get(&negated) {
+      // terms + c >= 0 and -terms + d >= 0, so -c <= terms <= d
+      let Some(sum) = constant.checked_add(opposite) else {
+        return Satisfiability::Unknown;
+      };
+      if sum < 0 {
+        return Satisfiability::Unsatisfiable;
+      }
+      if sum == 0 && terms < &negated {
+        equalities.push(Linear { constant, terms: terms.iter().copied().collect() });
+      }
+    }
+    inequalities.push(Linear { constant, terms: terms.iter().copied().collect() });
+  }
+  if !equalities.is_empty() {
+    return omega(equalities, inequalities, next_variable, budget);
+  }
+
+  eliminate_inequalities(inequalities, next_variable, budget)
+}
+
+fn eliminate_equality(
+  equality     : Linear,
+  equalities   : Vec<Linear>,
+  inequalities : Vec<Linear>,
+  next_variable: &mut usize,
+  budget       : &mut usize,
+) -> Satisfiability {
+  // With a unit coefficient, the variable can be solved for and substituted away exactly.
+  if let Some((&variable, _)) = equality.terms.iter().find(|(_, coefficient)| coefficient.abs() == 1) {
+    let substitute = |constraints: Vec<Linear>| -> Option<Vec<Linear>> {
+      constraints.into_iter().map(|constraint| constraint.eliminate(variable, &equality)).collect()
+    };
+    let (Some(equalities), Some(inequalities)) = (substitute(equalities), substitute(inequalities)) else {
+      return Satisfiability::Unknown;
+    };
+    return omega(equalities, inequalities, next_variable, budget);
+  }
+
+  // Otherwise introduce σ with m·σ = Σ (aᵢ mod^ m)·xᵢ + (c mod^ m), where m = |aₖ| + 1 for the smallest coefficient
+  // aₖ. In this equation xₖ has coefficient ∓1, so it can be eliminated, which shrinks the coefficients of the
+  // original equality.
+  let (&smallest, &coefficient) = equality.terms.iter().min_by_key(|(_, coefficient)| coefficient.abs()).unwrap();
+  let Some(modulus) = coefficient.abs().checked_add(1) else {
+    return Satisfiability::Unknown;
+  };
+  let sigma = *next_variable;
+  *next_variable += 1;
+
+  let mut auxiliary = Linear::variable(sigma).scale(-modulus).unwrap_or_default();
+  let Some(constant) = symmetric_mod(equality.constant, modulus) else {
+    return Satisfiability::Unknown;
+  };
+  auxiliary.constant = constant;
+  for (&variable, &coefficient) in &equality.terms {
+    let Some(remainder) = symmetric_mod(coefficient, modulus) else {
+      return Satisfiability::Unknown;
+    };
+    if remainder != 0 {
+      auxiliary.terms.insert(variable, remainder);
+    }
+  }
+  debug_assert_eq!(auxiliary.coefficient(smallest).abs(), 1);
+
+  let mut equalities = equalities;
+  equalities.push(equality);
+  let substitute = |constraints: Vec<Linear>| -> Option<Vec<Linear>> {
+    constraints.into_iter().map(|constraint| constraint.eliminate(smallest, &auxiliary)).collect()
+  };
+  let (Some(equalities), Some(inequalities)) = (substitute(equalities), substitute(inequalities)) else {
+    return Satisfiability::Unknown;
+  };
+  omega(equalities, inequalities, next_variable, budget)
+}
+
+fn eliminate_inequalities(inequalities: Vec<Linear>, next_variable: &mut usize, budget: &mut usize) -> Satisfiability {
+  if inequalities.is_empty() {
+    return Satisfiability::Satisfiable;
+  }
+
+  // Count the lower bounds (positive coefficient) and upper bounds (negative coefficient) of each variable.
+  let mut bounds: BTreeMap<usize, (usize, usize, bool, bool)> = BTreeMap::new();
+  for inequality in &inequalities {
+    for (&variable, &coefficient) in &inequality.terms {
+      let entry = bounds.entry(variable).or_insert((0, 0, true, true));
+      if coefficient > 0 {
+        entry.0 += 1;
+        entry.2 &= coefficient == 1;
+      } else {
+        entry.1 += 1;
+        entry.3 &= coefficient == -1;
+      }
+    }
+  }
+
+  // A variable bounded on one side only can always be chosen to satisfy every constraint it appears in.
+  if let Some((&unbounded, _)) = bounds.iter().find(|(_, (lower, upper, _, _))| *lower == 0 || *upper == 0) {
+    let remaining = inequalities.into_iter().filter(|inequality| inequality.coefficient(unbounded) == 0).collect();
+    return eliminate_inequalities(remaining, next_variable, budget);
+  }
+
+  // Prefer a variable whose elimination is exact, then the one producing fewest new constraints.
+  let (&variable, &(_, _, unit_lower, unit_upper)) = bounds
+      .iter()
+      .min_by_key(|(_, (lower, upper, unit_lower, unit_upper))| (!(*unit_lower || *unit_upper), lower * upper))
+      .unwrap();
+  let exact = unit_lower || unit_upper;
+
+  let mut unrelated = Vec::new();
+  let mut lowers = Vec::new();
+  let mut uppers = Vec::new();
+  for inequality in &inequalities {
+    match inequality.coefficient(variable) {
+      0 => unrelated.push(inequality.clone()),
+      coefficient if coefficient > 0 => lowers.push(inequality.clone()),
+      _ => uppers.push(inequality.clone()),
+    }
+  }
+
+  // For b·x + L >= 0 and -a·x + U >= 0: the real shadow is a·L + b·U >= 0, the dark shadow a·L + b·U >= (a-1)(b-1).
+  let shadow = |dark: bool| -> Option<Vec<Linear>> {
+    let mut result = unrelated.clone();
+    for lower in &lowers {
+      let b = lower.coefficient(variable);
+      for upper in &uppers {
+        let a = -upper.coefficient(variable);
+        let mut combined = lower.scale(a)?.add_scaled(upper, b)?;
+        if dark {
+          combined.constant = combined.constant.checked_sub((a - 1).checked_mul(b - 1)?)?;
+        }
+        result.push(combined);
+      }
+    }
+    Some(result)
+  };
+
+  let Some(real) = shadow(false) else {
+    return Satisfiability::Unknown;
+  };
+  if exact {
+    return omega(Vec::new(), real, next_variable, budget);
+  }
+
+  let real_result = omega(Vec::new(), real, next_variable, budget);
+  if real_result == Satisfiability::Unsatisfiable {
+    return Satisfiability::Unsatisfiable;
+  }
+  let Some(dark) = shadow(true) else {
+    return Satisfiability::Unknown;
+  };
+  let mut result = omega(Vec::new(), dark, next_variable, budget);
+  if result == Satisfiability::Satisfiable {
+    return Satisfiability::Satisfiable;
+  }
+
+  // Any integer solution outside the dark shadow lies close to some lower bound: b·x = -L + i for a small i.
+  let largest_upper = uppers.iter().map(|upper| -upper.coefficient(variable)).max().unwrap();
+  for lower in &lowers {
+    let b = lower.coefficient(variable);
+    let Some(limit) = largest_upper.checked_mul(b).and_then(|product| product.checked_sub(largest_upper + b)) else {
+      return Satisfiability::Unknown;
+    };
+    for i in 0..=limit.div_euclid(largest_upper) {
+      let splinter = lower.add_scaled(&Linear::constant(-i), 1).unwrap_or_default();
+      match omega(vec![splinter], inequalities.clone(), next_variable, budget) {
+        Satisfiability::Satisfiable => return Satisfiability::Satisfiable,
+        Satisfiability::Unknown => result = Satisfiability::Unknown,
+        Satisfiability::Unsatisfiable => {}
+      }
+    }
+  }
+  result
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::passes::typecheck::types::NumericExpression as N;
+
+  fn var(name: &str) -> N {
+    N::variable(name)
+  }
+
+  fn num(value: i64) -> N {
+    N::constant(value)
+  }
+
+  fn times(left: N, right: N) -> N {
+    N::Times(Box::new(left), Box::new(right))
+  }
+
+  #[test]
+  fn linear_bounds() {
+    // 0 <= n < 4 and n >= 4
+    let constraints = [
+      Constraint::LessEqual(num(0), var("'n")),
+      Constraint::Less(var("'n"), num(4)),
+      Constraint::GreaterEqual(var("'n"), num(4)),
+    ];
+    assert_eq!(satisfiable(&constraints), Satisfiability::Unsatisfiable);
+    assert_eq!(satisfiable(&constraints[..2]), Satisfiability::Satisfiable);
+  }
+
+  #[test]
+  fn integer_gaps() {
+    // 2x = 2y + 1 has no integer solution
+    let parity = [Constraint::Equal(times(num(2), var("'x")), N::sum(times(num(2), var("'y")), num(1)))];
+    assert_eq!(satisfiable(&parity), Satisfiability::Unsatisfiable);
+
+    // 27 <= 11x + 13y <= 45 and -10 <= 7x - 9y <= 4: real solutions but no integer ones (Pugh's example)
+    let sum = N::sum(times(num(11), var("'x")), times(num(13), var("'y")));
+    let difference = N::minus(times(num(7), var("'x")), times(num(9), var("'y")));
+    let pugh = [
+      Constraint::LessEqual(num(27), sum.clone()),
+      Constraint::LessEqual(sum, num(45)),
+      Constraint::LessEqual(num(-10), difference.clone()),
+      Constraint::LessEqual(difference, num(4)),
+    ];
+    assert_eq!(satisfiable(&pugh), Satisfiability::Unsatisfiable);
+
+    // 3x + 5y = 7 does have one
+    let bezout = [Constraint::Equal(N::sum(times(num(3), var("'x")), times(num(5), var("'y"))), num(7))];
+    assert_eq!(satisfiable(&bezout), Satisfiability::Satisfiable);
+  }
+
+  #[test]
+  fn disjunctions_and_booleans() {
+    let set = Constraint::Set(var("'n"), vec![8.into(), 16.into(), 32.into()]);
+    let constraints = [set.clone(), Constraint::Greater(var("'n"), num(16)), Constraint::NotEqual(var("'n"), num(32))];
+    assert_eq!(satisfiable(&constraints), Satisfiability::Unsatisfiable);
+    assert_eq!(satisfiable(&[set, Constraint::Greater(var("'n"), num(16))]), Satisfiability::Satisfiable);
+
+    let p = Constraint::Variable("'p".to_string());
+    assert_eq!(satisfiable(&[p.clone(), Constraint::negate(p)]), Satisfiability::Unsatisfiable);
+  }
+
+  #[test]
+  fn opaque_terms() {
+    // n * m == n * m + 1 is false whatever n * m is
+    let product = times(var("'n"), var("'m"));
+    let constraints = [Constraint::Equal(product.clone(), N::sum(product, num(1)))];
+    assert_eq!(satisfiable(&constraints), Satisfiability::Unsatisfiable);
+
+    let power = N::Exponential(Box::new(var("'n")));
+    assert_eq!(satisfiable(&[Constraint::LessEqual(power, num(0))]), Satisfiability::Unsatisfiable);
+  }
+}
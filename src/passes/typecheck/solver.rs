@@ -0,0 +1,362 @@
+/*!
+
+Pluggable constraint solvers for the type checker.
+
+The built-in Omega test in `omega` decides linear constraints exactly, but treats nonlinear terms such as `'n * 'm`
+or `2 ^ 'n` as opaque, and answers `Unknown` when that matters. A `ConstraintSolver` given to the type checker is
+consulted for exactly those constraints. `SmtSolver` is such a solver: it writes the constraints as an SMT-LIB2 script
+to the standard input of any locally installed solver binary, such as `z3 -in` or `cvc5 --lang smt2`, and reads
+`sat`, `unsat` or `unknown` from its standard output.
+
+A solver that has not answered within its timeout (`SmtSolver::with_timeout`) is killed, and the answer is `Unknown`.
+
+Results are cached, keyed by the script itself. Constraints are converted from `AbstractType`s, so they carry no
+source locations, and variable names are renamed canonically before serialising, so the script is the same for the
+same constraint wherever it arises, whatever fresh names the checker happened to give its variables.
+
+*/
+
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::fmt::Write as FmtWrite;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::passes::typecheck::omega::Satisfiability;
+use crate::passes::typecheck::types::{Constraint, NumericExpression};
+
+/// Decides whether a conjunction of constraints is satisfiable.
+pub trait ConstraintSolver {
+  fn satisfiable(&mut self, constraints: &[Constraint]) -> Satisfiability;
+}
+
+/// A solver run as a separate process speaking SMT-LIB2.
+pub struct SmtSolver {
+  command  : OsString,
+  arguments: Vec<OsString>,
+  timeout  : Duration,
+  cache    : HashMap<String, Satisfiability>,
+}
+
+/// How often a running solver is checked for having finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+impl SmtSolver {
+  /// A solver run as `command arguments...`, which must read a script from its standard input.
+  pub fn new<S, I, A>(command: S, arguments: I) -> Self
+  where
+      S: Into<OsString>,
+      I: IntoIterator<Item = A>,
+      A: Into<OsString>,
+  {
+    SmtSolver {
+      command  : command.into(),
+      arguments: arguments.into_iter().map(Into::into).collect(),
+      timeout  : Duration::from_secs(10),
+      cache    : HashMap::new(),
+    }
+  }
+
+  /// Sets how long the solver may take over one problem before it is killed. The default is ten seconds.
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// The number of distinct problems answered so far.
+  pub fn cached(&self) -> usize {
+    self.cache.len()
+  }
+
+  /// Runs the solver on `script`. A solver that cannot be run gives an error; one that gives no recognisable answer,
+  /// or none within the timeout, gives `Unknown`.
+  fn run(&self, script: &str) -> Result<Satisfiability, std::io::Error> {
+    let mut child = Command::new(&self.command)
+        .args(&self.arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Written and read on other threads, so that a solver that stops reading or never answers can still be killed.
+    let stdin = child.stdin.take().map(|mut stdin| {
+      let script = script.to_string();
+      thread::spawn(move || stdin.write_all(script.as_bytes()))
+    });
+    let stdout = child.stdout.take().map(|mut stdout| {
+      thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+      })
+    });
+
+    let deadline = Instant::now() + self.timeout;
+    while child.try_wait()?.is_none() {
+      if Instant::now() >= deadline {
+        let _ = child.kill();
+        child.wait()?;
+        return Ok(Satisfiability::Unknown);
+      }
+      thread::sleep(POLL_INTERVAL);
+    }
+    if let Some(writer) = stdin {
+      // A solver may answer without reading all of a script it rejects, closing its input early.
+      let _ = writer.join();
+    }
+    let answer = match stdout {
+      Some(reader) => reader.join().unwrap_or_else(|_| Ok(Vec::new()))?,
+      None => Vec::new(),
+    };
+    let answer = String::from_utf8_lossy(&answer);
+    Ok(match answer.lines().map(str::trim).find(|line| !line.is_empty()) {
+      Some("sat") => Satisfiability::Satisfiable,
+      Some("unsat") => Satisfiability::Unsatisfiable,
+      _ => Satisfiability::Unknown,
+    })
+  }
+}
+
+impl ConstraintSolver for SmtSolver {
+  fn satisfiable(&mut self, constraints: &[Constraint]) -> Satisfiability {
+    let script = to_smtlib(constraints);
+    if let Some(&answer) = self.cache.get(&script) {
+      return answer;
+    }
+    match self.run(&script) {
+      Ok(answer) => {
+        self.cache.insert(script, answer);
+        answer
+      }
+      // Not cached, in case the failure is transient.
+      Err(_) => Satisfiability::Unknown,
+    }
+  }
+}
+
+// region Serialisation
+
+/// Canonical names for the variables of a problem, in order of first appearance.
+#[derive(Default)]
+struct Names {
+  integers: Vec<String>,
+  booleans: Vec<String>,
+  names   : HashMap<String, String>,
+  /// Whether `pow2` or `pow` is used, each needing a declaration
+  pow2    : bool,
+  pow     : bool,
+  /// Terms `2 ^ x`, each known to be positive
+  powers  : BTreeSet<String>,
+}
+
+impl Names {
+  fn integer(&mut self, name: &str) -> String {
+    if let Some(canonical) = self.names.get(name) {
+      return canonical.clone();
+    }
+    let canonical = format!("n{}", self.integers.len() + self.booleans.len());
+    self.integers.push(canonical.clone());
+    self.names.insert(name.to_string(), canonical.clone());
+    canonical
+  }
+
+  fn boolean(&mut self, name: &str) -> String {
+    if let Some(canonical) = self.names.get(name) {
+      return canonical.clone();
+    }
+    let canonical = format!("p{}", self.integers.len() + self.booleans.len());
+    self.booleans.push(canonical.clone());
+    self.names.insert(name.to_string(), canonical.clone());
+    canonical
+  }
+}
+
+fn number(value: &BigInteger) -> String {
+  match *value < BigInteger::from_i64(0) {
+    true => format!("(- {})", value.to_string().trim_start_matches('-')),
+    false => value.to_string(),
+  }
+}
+
+fn numeric(expression: &NumericExpression, names: &mut Names) -> String {
+  let binary = |operator: &str, left: &NumericExpression, right: &NumericExpression, names: &mut Names| {
+    format!("({} {} {})", operator, numeric(left, names), numeric(right, names))
+  };
+  match expression {
+    NumericExpression::Constant(value) => number(value),
+    NumericExpression::Variable(name) => names.integer(name),
+    NumericExpression::Sum(left, right) => binary("+", left, right, names),
+    NumericExpression::Minus(left, right) => binary("-", left, right, names),
+    NumericExpression::Times(left, right) => binary("*", left, right, names),
+    NumericExpression::Negative(inner) => format!("(- {})", numeric(inner, names)),
+    NumericExpression::Exponential(inner) => match expression.as_constant() {
+      Some(value) => number(&value),
+      None => {
+        names.pow2 = true;
+        let term = format!("(pow2 {})", numeric(inner, names));
+        names.powers.insert(term.clone());
+        term
+      }
+    },
+    NumericExpression::Application(function, arguments) => {
+      let function = match function.as_str() {
+        "^" => {
+          names.pow = true;
+          "pow"
+        }
+        other => other,
+      };
+      let arguments: Vec<String> = arguments.iter().map(|argument| numeric(argument, names)).collect();
+      format!("({} {})", function, arguments.join(" "))
+    }
+  }
+}
+
+fn formula(constraint: &Constraint, names: &mut Names) -> String {
+  let comparison = |operator: &str, left: &NumericExpression, right: &NumericExpression, names: &mut Names| {
+    format!("({} {} {})", operator, numeric(left, names), numeric(right, names))
+  };
+  match constraint {
+    Constraint::True => "true".to_string(),
+    Constraint::False => "false".to_string(),
+    Constraint::Variable(name) => names.boolean(name),
+    Constraint::Equal(left, right) => comparison("=", left, right, names),
+    Constraint::NotEqual(left, right) => comparison("distinct", left, right, names),
+    Constraint::LessEqual(left, right) => comparison("<=", left, right, names),
+    Constraint::Less(left, right) => comparison("<", left, right, names),
+    Constraint::GreaterEqual(left, right) => comparison(">=", left, right, names),
+    Constraint::Greater(left, right) => comparison(">", left, right, names),
+    Constraint::Set(element, values) => {
+      let element = numeric(element, names);
+      match values.as_slice() {
+        [] => "false".to_string(),
+        [value] => format!("(= {} {})", element, number(value)),
+        values => {
+          let cases: Vec<String> = values.iter().map(|value| format!("(= {} {})", element, number(value))).collect();
+          format!("(or {})", cases.join(" "))
+        }
+      }
+    }
+    Constraint::And(left, right) => format!("(and {} {})", formula(left, names), formula(right, names)),
+    Constraint::Or(left, right) => format!("(or {} {})", formula(left, names), formula(right, names)),
+    Constraint::Not(inner) => format!("(not {})", formula(inner, names)),
+  }
+}
+
+/// An SMT-LIB2 script asking whether all of `constraints` can hold at once.
+pub fn to_smtlib(constraints: &[Constraint]) -> String {
+  let mut names = Names::default();
+  let assertions: Vec<String> = constraints.iter().map(|constraint| formula(constraint, &mut names)).collect();
+
+  let mut script = String::from("(set-logic ALL)\n");
+  for name in &names.integers {
+    let _ = writeln!(script, "(declare-const {} Int)", name);
+  }
+  for name in &names.booleans {
+    let _ = writeln!(script, "(declare-const {} Bool)", name);
+  }
+  if names.pow2 {
+    script.push_str("(declare-fun pow2 (Int) Int)\n");
+  }
+  if names.pow {
+    script.push_str("(declare-fun pow (Int Int) Int)\n");
+  }
+  for power in &names.powers {
+    let _ = writeln!(script, "(assert (> {} 0))", power);
+  }
+  for assertion in assertions {
+    let _ = writeln!(script, "(assert {})", assertion);
+  }
+  script.push_str("(check-sat)\n(exit)\n");
+  script
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn n(name: &str) -> NumericExpression {
+    NumericExpression::variable(name)
+  }
+
+  #[test]
+  fn serialisation() {
+    let problem = [
+      Constraint::Greater(n("'n#3"), NumericExpression::constant(-1)),
+      Constraint::Or(
+        Box::new(Constraint::Variable("'p#4".to_string())),
+        Box::new(Constraint::Equal(NumericExpression::Exponential(Box::new(n("'n#3"))), n("'m#9"))),
+      ),
+    ];
+    assert_eq!(
+      to_smtlib(&problem),
+      "(set-logic ALL)\n\
+       (declare-const n0 Int)\n\
+       (declare-const n2 Int)\n\
+       (declare-const p1 Bool)\n\
+       (declare-fun pow2 (Int) Int)\n\
+       (assert (> (pow2 n0) 0))\n\
+       (assert (> n0 (- 1)))\n\
+       (assert (or p1 (= (pow2 n0) n2)))\n\
+       (check-sat)\n(exit)\n"
+    );
+
+    // The same problem over differently named variables is the same script.
+    let renamed = [Constraint::Greater(n("'n#17"), NumericExpression::constant(-1))];
+    let original = [Constraint::Greater(n("'n#3"), NumericExpression::constant(-1))];
+    assert_eq!(to_smtlib(&renamed), to_smtlib(&original));
+  }
+
+  /// A stand-in solver that answers `unsat` to any script containing `distinct` and `sat` otherwise, appending a line
+  /// to the file named by its argument each time it runs.
+  #[cfg(unix)]
+  #[test]
+  fn external_solver() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let directory = std::env::temp_dir().join(format!("rigging-solver-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let script = directory.join("solver.sh");
+    let log = directory.join("calls");
+    std::fs::write(&script, "#!/bin/sh\necho call >> \"$1\"\nif grep -q distinct; then echo unsat; else echo sat; fi\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut solver = SmtSolver::new(&script, [&log]);
+    let unsatisfiable = [Constraint::NotEqual(n("'a#1"), n("'a#1"))];
+    let satisfiable = [Constraint::Less(n("'a#1"), n("'b#2"))];
+    assert_eq!(solver.satisfiable(&unsatisfiable), Satisfiability::Unsatisfiable);
+    assert_eq!(solver.satisfiable(&satisfiable), Satisfiability::Satisfiable);
+    // Answered from the cache, even with different variable names.
+    assert_eq!(solver.satisfiable(&[Constraint::NotEqual(n("'c#5"), n("'c#5"))]), Satisfiability::Unsatisfiable);
+    assert_eq!(solver.cached(), 2);
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 2);
+
+    let mut missing = SmtSolver::new(directory.join("no-such-solver"), Vec::<OsString>::new());
+    assert_eq!(missing.satisfiable(&satisfiable), Satisfiability::Unknown);
+    assert_eq!(missing.cached(), 0);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn slow_solvers_are_killed() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let directory = std::env::temp_dir().join(format!("rigging-slow-solver-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let script = directory.join("solver.sh");
+    std::fs::write(&script, "#!/bin/sh\nexec sleep 30\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut solver = SmtSolver::new(&script, Vec::<OsString>::new()).with_timeout(Duration::from_millis(100));
+    let started = Instant::now();
+    assert_eq!(solver.satisfiable(&[Constraint::Less(n("'a#1"), n("'b#2"))]), Satisfiability::Unknown);
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    std::fs::remove_dir_all(&directory).unwrap();
+  }
+}
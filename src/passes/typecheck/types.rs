@@ -0,0 +1,581 @@
+/*!
+
+The type checker's internal representation of types.
+
+`AbstractType` mixes the four kinds together and keeps every piece of surface syntax. The checker instead works with
+`Type`, whose integer-kinded parts are `NumericExpression`s and whose bool-kinded parts are `Constraint`s. The surface
+types `int`, `nat`, `range` and `bool` are sugar for existentials over `atom` and `atom_bool`, and are expanded as such,
+so that the checker only has one way of describing "an integer between 0 and 31".
+
+Type variables keep their tick, as in `'n`. Variables the checker invents, when it opens an existential or
+instantiates a function's quantifiers, are named like `'n#3`, and the variables bound by the existentials that `int`,
+`nat`, `range` and `bool` expand to are `'#n` and `'#p`; none of these can clash with anything a user can write.
+
+*/
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::parser::ast::Kind;
+
+/// An integer-kinded type-level expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NumericExpression {
+  Constant(BigInteger),
+  Variable(String),
+  Sum(Box<NumericExpression>, Box<NumericExpression>),
+  Minus(Box<NumericExpression>, Box<NumericExpression>),
+  Times(Box<NumericExpression>, Box<NumericExpression>),
+  /// `2 ^ n`
+  Exponential(Box<NumericExpression>),
+  Negative(Box<NumericExpression>),
+  /// A type-level function: `div`, `mod` or `abs`
+  Application(String, Vec<NumericExpression>),
+}
+
+/// A bool-kinded type-level expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Constraint {
+  True,
+  False,
+  Variable(String),
+  Equal(NumericExpression, NumericExpression),
+  NotEqual(NumericExpression, NumericExpression),
+  LessEqual(NumericExpression, NumericExpression),
+  Less(NumericExpression, NumericExpression),
+  GreaterEqual(NumericExpression, NumericExpression),
+  Greater(NumericExpression, NumericExpression),
+  /// `n in {1, 2, 4}`
+  Set(NumericExpression, Vec<BigInteger>),
+  And(Box<Constraint>, Box<Constraint>),
+  Or(Box<Constraint>, Box<Constraint>),
+  Not(Box<Constraint>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+  Unit,
+  Bit,
+  String,
+  Real,
+  /// The singleton type of the integer `n`, written `int('n)` or `atom('n)`
+  Atom(NumericExpression),
+  /// The singleton type of the boolean `p`, written `bool('p)` or `atom_bool('p)`
+  AtomBool(Constraint),
+  Bitvector(NumericExpression),
+  Vector(NumericExpression, Box<Type>),
+  List(Box<Type>),
+  Register(Box<Type>),
+  Tuple(Vec<Type>),
+  Function(Vec<Type>, Box<Type>),
+  Bidirectional(Box<Type>, Box<Type>),
+  /// A `Type`-kinded type variable
+  Variable(String),
+  /// A user-defined record, union, enum, bitfield or abstract type
+  Application(String, Vec<TypeArgument>),
+  Existential(Vec<(String, Kind)>, Constraint, Box<Type>),
+  /// Compatible with every type. The type of expressions that never return, such as `throw`, and of expressions whose
+  /// type could not be determined because of an error that has already been reported.
+  Any,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeArgument {
+  Type(Type),
+  Integer(NumericExpression),
+  /// `true` for `inc`
+  Order(bool),
+  Bool(Constraint),
+}
+
+/// The type of a function, with its quantifiers and their constraint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+  pub variables : Vec<(String, Kind)>,
+  pub constraint: Constraint,
+  pub arguments : Vec<Type>,
+  pub result    : Type,
+}
+
+/// A substitution for type variables of any kind.
+pub type Substitution = HashMap<String, TypeArgument>;
+
+// region Constructors
+
+impl NumericExpression {
+  pub fn constant(value: i64) -> Self {
+    NumericExpression::Constant(BigInteger::from_i64(value))
+  }
+
+  pub fn variable(name: &str) -> Self {
+    NumericExpression::Variable(name.to_string())
+  }
+
+  pub fn sum(left: NumericExpression, right: NumericExpression) -> Self {
+    NumericExpression::Sum(Box::new(left), Box::new(right)).simplify()
+  }
+
+  pub fn minus(left: NumericExpression, right: NumericExpression) -> Self {
+    NumericExpression::Minus(Box::new(left), Box::new(right)).simplify()
+  }
+
+  /// The constant value of the expression, if it has no variables.
+  pub fn as_constant(&self) -> Option<BigInteger> {
+    match self {
+      NumericExpression::Constant(value) => Some(value.clone()),
+      NumericExpression::Variable(_) => None,
+      NumericExpression::Sum(left, right) => left.as_constant()?.try_add(&right.as_constant()?).ok(),
+      NumericExpression::Minus(left, right) => left.as_constant()?.try_sub(&right.as_constant()?).ok(),
+      NumericExpression::Times(left, right) => left.as_constant()?.try_mul(&right.as_constant()?).ok(),
+      NumericExpression::Exponential(exponent) => BigInteger::try_pow2(exponent.as_constant()?.try_to_u32().ok()?).ok(),
+      NumericExpression::Negative(inner) => inner.as_constant()?.try_neg().ok(),
+      NumericExpression::Application(name, arguments) => {
+        let values = arguments.iter().map(|argument| argument.as_constant()).collect::<Option<Vec<_>>>()?;
+        match (name.as_str(), values.as_slice()) {
+          ("div", [left, right]) => left.try_ediv(right).ok(),
+          ("mod", [left, right]) => left.try_emod(right).ok(),
+          ("abs", [value]) => value.try_abs().ok(),
+          _ => None,
+        }
+      }
+    }
+  }
+
+  /// Folds constant subexpressions.
+  pub fn simplify(self) -> Self {
+    if let Some(value) = self.as_constant() {
+      return NumericExpression::Constant(value);
+    }
+    let zero = BigInteger::from_i64(0);
+    match self {
+      NumericExpression::Sum(left, right) => {
+        let (left, right) = (left.simplify(), right.simplify());
+        match (left.as_constant(), right.as_constant()) {
+          (Some(value), _) if value == zero => right,
+          (_, Some(value)) if value == zero => left,
+          _ => NumericExpression::Sum(Box::new(left), Box::new(right)),
+        }
+      }
+      NumericExpression::Minus(left, right) => {
+        let (left, right) = (left.simplify(), right.simplify());
+        match right.as_constant() {
+          Some(value) if value == zero => left,
+          _ => NumericExpression::Minus(Box::new(left), Box::new(right)),
+        }
+      }
+      NumericExpression::Times(left, right) => {
+        NumericExpression::Times(Box::new(left.simplify()), Box::new(right.simplify()))
+      }
+      NumericExpression::Exponential(exponent) => NumericExpression::Exponential(Box::new(exponent.simplify())),
+      NumericExpression::Negative(inner) => NumericExpression::Negative(Box::new(inner.simplify())),
+      NumericExpression::Application(name, arguments) => {
+        NumericExpression::Application(name, arguments.into_iter().map(NumericExpression::simplify).collect())
+      }
+      other => other,
+    }
+  }
+}
+
+impl Constraint {
+  pub fn and(left: Constraint, right: Constraint) -> Self {
+    match (left, right) {
+      (Constraint::True, other)
+      | (other, Constraint::True) => other,
+      (left, right) => Constraint::And(Box::new(left), Box::new(right)),
+    }
+  }
+
+  pub fn or(left: Constraint, right: Constraint) -> Self {
+    match (left, right) {
+      (Constraint::False, other)
+      | (other, Constraint::False) => other,
+      (left, right) => Constraint::Or(Box::new(left), Box::new(right)),
+    }
+  }
+
+  pub fn negate(constraint: Constraint) -> Self {
+    match constraint {
+      Constraint::True => Constraint::False,
+      Constraint::False => Constraint::True,
+      Constraint::Not(inner) => *inner,
+      other => Constraint::Not(Box::new(other)),
+    }
+  }
+
+  /// The conjunction of all of `constraints`.
+  pub fn all<I: IntoIterator<Item = Constraint>>(constraints: I) -> Self {
+    constraints.into_iter().fold(Constraint::True, Constraint::and)
+  }
+
+  /// `left` and `right` are both true or both false.
+  pub fn iff(left: Constraint, right: Constraint) -> Self {
+    Constraint::or(
+      Constraint::and(left.clone(), right.clone()),
+      Constraint::and(Constraint::negate(left), Constraint::negate(right)),
+    )
+  }
+}
+
+impl Type {
+  /// `int`, an integer of unknown value.
+  pub fn int() -> Self {
+    Type::Existential(vec![("'#n".to_string(), Kind::Integer)], Constraint::True, Box::new(Type::Atom(NumericExpression::variable("'#n"))))
+  }
+
+  /// `nat`
+  pub fn nat() -> Self {
+    let n = NumericExpression::variable("'#n");
+    let constraint = Constraint::GreaterEqual(n.clone(), NumericExpression::constant(0));
+    Type::Existential(vec![("'#n".to_string(), Kind::Integer)], constraint, Box::new(Type::Atom(n)))
+  }
+
+  /// `range(low, high)`
+  pub fn range(low: NumericExpression, high: NumericExpression) -> Self {
+    let n = NumericExpression::variable("'#n");
+    let constraint = Constraint::and(Constraint::LessEqual(low, n.clone()), Constraint::LessEqual(n.clone(), high));
+    Type::Existential(vec![("'#n".to_string(), Kind::Integer)], constraint, Box::new(Type::Atom(n)))
+  }
+
+  /// `bool`, a boolean of unknown value.
+  pub fn bool() -> Self {
+    Type::Existential(vec![("'#p".to_string(), Kind::Bool)], Constraint::True, Box::new(Type::AtomBool(Constraint::Variable("'#p".to_string()))))
+  }
+
+  /// Whether the type is an integer of some sort, ignoring any existential.
+  pub fn is_integer(&self) -> bool {
+    match self {
+      Type::Atom(_) => true,
+      Type::Existential(_, _, body) => body.is_integer(),
+      _ => false,
+    }
+  }
+
+  /// Whether the type is a boolean of some sort, ignoring any existential.
+  pub fn is_bool(&self) -> bool {
+    match self {
+      Type::AtomBool(_) => true,
+      Type::Existential(_, _, body) => body.is_bool(),
+      _ => false,
+    }
+  }
+}
+
+// endregion
+
+// region Substitution and free variables
+
+impl NumericExpression {
+  pub fn substitute(&self, substitution: &Substitution) -> NumericExpression {
+    match self {
+      NumericExpression::Variable(name) => match substitution.get(name) {
+        Some(TypeArgument::Integer(replacement)) => replacement.clone(),
+        _ => self.clone(),
+      },
+      NumericExpression::Constant(_) => self.clone(),
+      NumericExpression::Sum(left, right) => {
+        NumericExpression::Sum(Box::new(left.substitute(substitution)), Box::new(right.substitute(substitution)))
+      }
+      NumericExpression::Minus(left, right) => {
+        NumericExpression::Minus(Box::new(left.substitute(substitution)), Box::new(right.substitute(substitution)))
+      }
+      NumericExpression::Times(left, right) => {
+        NumericExpression::Times(Box::new(left.substitute(substitution)), Box::new(right.substitute(substitution)))
+      }
+      NumericExpression::Exponential(inner) => NumericExpression::Exponential(Box::new(inner.substitute(substitution))),
+      NumericExpression::Negative(inner) => NumericExpression::Negative(Box::new(inner.substitute(substitution))),
+      NumericExpression::Application(name, arguments) => NumericExpression::Application(
+        name.clone(),
+        arguments.iter().map(|argument| argument.substitute(substitution)).collect(),
+      ),
+    }
+    .simplify()
+  }
+
+  pub fn free_variables(&self, variables: &mut BTreeSet<String>) {
+    match self {
+      NumericExpression::Variable(name) => {
+        variables.insert(name.clone());
+      }
+      NumericExpression::Constant(_) => {}
+      NumericExpression::Sum(left, right)
+      | NumericExpression::Minus(left, right)
+      | NumericExpression::Times(left, right) => {
+        left.free_variables(variables);
+        right.free_variables(variables);
+      }
+      NumericExpression::Exponential(inner)
+      | NumericExpression::Negative(inner) => inner.free_variables(variables),
+      NumericExpression::Application(_, arguments) => {
+        for argument in arguments {
+          argument.free_variables(variables);
+        }
+      }
+    }
+  }
+}
+
+impl Constraint {
+  pub fn substitute(&self, substitution: &Substitution) -> Constraint {
+    let numeric = |expression: &NumericExpression| expression.substitute(substitution);
+    match self {
+      Constraint::True
+      | Constraint::False => self.clone(),
+      Constraint::Variable(name) => match substitution.get(name) {
+        Some(TypeArgument::Bool(replacement)) => replacement.clone(),
+        _ => self.clone(),
+      },
+      Constraint::Equal(left, right) => Constraint::Equal(numeric(left), numeric(right)),
+      Constraint::NotEqual(left, right) => Constraint::NotEqual(numeric(left), numeric(right)),
+      Constraint::LessEqual(left, right) => Constraint::LessEqual(numeric(left), numeric(right)),
+      Constraint::Less(left, right) => Constraint::Less(numeric(left), numeric(right)),
+      Constraint::GreaterEqual(left, right) => Constraint::GreaterEqual(numeric(left), numeric(right)),
+      Constraint::Greater(left, right) => Constraint::Greater(numeric(left), numeric(right)),
+      Constraint::Set(element, values) => Constraint::Set(numeric(element), values.clone()),
+      Constraint::And(left, right) => Constraint::and(left.substitute(substitution), right.substitute(substitution)),
+      Constraint::Or(left, right) => Constraint::or(left.substitute(substitution), right.substitute(substitution)),
+      Constraint::Not(inner) => Constraint::negate(inner.substitute(substitution)),
+    }
+  }
+
+  pub fn free_variables(&self, variables: &mut BTreeSet<String>) {
+    match self {
+      Constraint::True
+      | Constraint::False => {}
+      Constraint::Variable(name) => {
+        variables.insert(name.clone());
+      }
+      Constraint::Equal(left, right)
+      | Constraint::NotEqual(left, right)
+      | Constraint::LessEqual(left, right)
+      | Constraint::Less(left, right)
+      | Constraint::GreaterEqual(left, right)
+      | Constraint::Greater(left, right) => {
+        left.free_variables(variables);
+        right.free_variables(variables);
+      }
+      Constraint::Set(element, _) => element.free_variables(variables),
+      Constraint::And(left, right)
+      | Constraint::Or(left, right) => {
+        left.free_variables(variables);
+        right.free_variables(variables);
+      }
+      Constraint::Not(inner) => inner.free_variables(variables),
+    }
+  }
+}
+
+impl TypeArgument {
+  pub fn substitute(&self, substitution: &Substitution) -> TypeArgument {
+    match self {
+      TypeArgument::Type(inner) => TypeArgument::Type(inner.substitute(substitution)),
+      TypeArgument::Integer(inner) => TypeArgument::Integer(inner.substitute(substitution)),
+      TypeArgument::Order(_) => self.clone(),
+      TypeArgument::Bool(inner) => TypeArgument::Bool(inner.substitute(substitution)),
+    }
+  }
+
+  pub fn free_variables(&self, variables: &mut BTreeSet<String>) {
+    match self {
+      TypeArgument::Type(inner) => inner.free_variables(variables),
+      TypeArgument::Integer(inner) => inner.free_variables(variables),
+      TypeArgument::Order(_) => {}
+      TypeArgument::Bool(inner) => inner.free_variables(variables),
+    }
+  }
+}
+
+impl Type {
+  /// Applies `substitution`. Variables bound by an existential inside the type shadow the substitution.
+  pub fn substitute(&self, substitution: &Substitution) -> Type {
+    match self {
+      Type::Unit
+      | Type::Bit
+      | Type::String
+      | Type::Real
+      | Type::Any => self.clone(),
+      Type::Atom(n) => Type::Atom(n.substitute(substitution)),
+      Type::AtomBool(p) => Type::AtomBool(p.substitute(substitution)),
+      Type::Bitvector(n) => Type::Bitvector(n.substitute(substitution)),
+      Type::Vector(n, element) => Type::Vector(n.substitute(substitution), Box::new(element.substitute(substitution))),
+      Type::List(element) => Type::List(Box::new(element.substitute(substitution))),
+      Type::Register(element) => Type::Register(Box::new(element.substitute(substitution))),
+      Type::Tuple(elements) => Type::Tuple(elements.iter().map(|element| element.substitute(substitution)).collect()),
+      Type::Function(arguments, result) => Type::Function(
+        arguments.iter().map(|argument| argument.substitute(substitution)).collect(),
+        Box::new(result.substitute(substitution)),
+      ),
+      Type::Bidirectional(left, right) => {
+        Type::Bidirectional(Box::new(left.substitute(substitution)), Box::new(right.substitute(substitution)))
+      }
+      Type::Variable(name) => match substitution.get(name) {
+        Some(TypeArgument::Type(replacement)) => replacement.clone(),
+        _ => self.clone(),
+      },
+      Type::Application(name, arguments) => {
+        Type::Application(name.clone(), arguments.iter().map(|argument| argument.substitute(substitution)).collect())
+      }
+      Type::Existential(variables, constraint, body) => {
+        let mut inner = substitution.clone();
+        for (variable, _) in variables {
+          inner.remove(variable);
+        }
+        Type::Existential(variables.clone(), constraint.substitute(&inner), Box::new(body.substitute(&inner)))
+      }
+    }
+  }
+
+  pub fn free_variables(&self, variables: &mut BTreeSet<String>) {
+    match self {
+      Type::Unit
+      | Type::Bit
+      | Type::String
+      | Type::Real
+      | Type::Any => {}
+      Type::Atom(n)
+      | Type::Bitvector(n) => n.free_variables(variables),
+      Type::AtomBool(p) => p.free_variables(variables),
+      Type::Vector(n, element) => {
+        n.free_variables(variables);
+        element.free_variables(variables);
+      }
+      Type::List(element)
+      | Type::Register(element) => element.free_variables(variables),
+      Type::Tuple(elements) => {
+        for element in elements {
+          element.free_variables(variables);
+        }
+      }
+      Type::Function(arguments, result) => {
+        for argument in arguments {
+          argument.free_variables(variables);
+        }
+        result.free_variables(variables);
+      }
+      Type::Bidirectional(left, right) => {
+        left.free_variables(variables);
+        right.free_variables(variables);
+      }
+      Type::Variable(name) => {
+        variables.insert(name.clone());
+      }
+      Type::Application(_, arguments) => {
+        for argument in arguments {
+          argument.free_variables(variables);
+        }
+      }
+      Type::Existential(bound, constraint, body) => {
+        let mut inner = BTreeSet::new();
+        constraint.free_variables(&mut inner);
+        body.free_variables(&mut inner);
+        for (variable, _) in bound {
+          inner.remove(variable);
+        }
+        variables.extend(inner);
+      }
+    }
+  }
+}
+
+impl FunctionType {
+  /// The function's type with its quantified variables replaced according to `substitution`.
+  pub fn instantiate(&self, substitution: &Substitution) -> (Constraint, Vec<Type>, Type) {
+    (
+      self.constraint.substitute(substitution),
+      self.arguments.iter().map(|argument| argument.substitute(substitution)).collect(),
+      self.result.substitute(substitution),
+    )
+  }
+}
+
+// endregion
+
+// region Display
+
+impl Display for NumericExpression {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      NumericExpression::Constant(value) => write!(f, "{}", value),
+      NumericExpression::Variable(name) => write!(f, "{}", name),
+      NumericExpression::Sum(left, right) => write!(f, "({} + {})", left, right),
+      NumericExpression::Minus(left, right) => write!(f, "({} - {})", left, right),
+      NumericExpression::Times(left, right) => write!(f, "({} * {})", left, right),
+      NumericExpression::Exponential(exponent) => write!(f, "2 ^ {}", exponent),
+      NumericExpression::Negative(inner) => write!(f, "-{}", inner),
+      NumericExpression::Application(name, arguments) => {
+        write!(f, "{}({})", name, arguments.iter().map(|argument| argument.to_string()).collect::<Vec<_>>().join(", "))
+      }
+    }
+  }
+}
+
+impl Display for Constraint {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Constraint::True => write!(f, "true"),
+      Constraint::False => write!(f, "false"),
+      Constraint::Variable(name) => write!(f, "{}", name),
+      Constraint::Equal(left, right) => write!(f, "{} == {}", left, right),
+      Constraint::NotEqual(left, right) => write!(f, "{} != {}", left, right),
+      Constraint::LessEqual(left, right) => write!(f, "{} <= {}", left, right),
+      Constraint::Less(left, right) => write!(f, "{} < {}", left, right),
+      Constraint::GreaterEqual(left, right) => write!(f, "{} >= {}", left, right),
+      Constraint::Greater(left, right) => write!(f, "{} > {}", left, right),
+      Constraint::Set(element, values) => {
+        write!(f, "{} in {{{}}}", element, values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", "))
+      }
+      Constraint::And(left, right) => write!(f, "({} & {})", left, right),
+      Constraint::Or(left, right) => write!(f, "({} | {})", left, right),
+      Constraint::Not(inner) => write!(f, "not({})", inner),
+    }
+  }
+}
+
+impl Display for TypeArgument {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TypeArgument::Type(inner) => write!(f, "{}", inner),
+      TypeArgument::Integer(inner) => write!(f, "{}", inner),
+      TypeArgument::Order(true) => write!(f, "inc"),
+      TypeArgument::Order(false) => write!(f, "dec"),
+      TypeArgument::Bool(inner) => write!(f, "{}", inner),
+    }
+  }
+}
+
+fn join<T: Display>(items: &[T]) -> String {
+  items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl Display for Type {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Type::Unit => write!(f, "unit"),
+      Type::Bit => write!(f, "bit"),
+      Type::String => write!(f, "string"),
+      Type::Real => write!(f, "real"),
+      Type::Atom(n) => write!(f, "int({})", n),
+      Type::AtomBool(p) => write!(f, "bool({})", p),
+      Type::Bitvector(n) => write!(f, "bits({})", n),
+      Type::Vector(n, element) => write!(f, "vector({}, {})", n, element),
+      Type::List(element) => write!(f, "list({})", element),
+      Type::Register(element) => write!(f, "register({})", element),
+      Type::Tuple(elements) => write!(f, "({})", join(elements)),
+      Type::Function(arguments, result) => write!(f, "({}) -> {}", join(arguments), result),
+      Type::Bidirectional(left, right) => write!(f, "{} <-> {}", left, right),
+      Type::Variable(name) => write!(f, "{}", name),
+      Type::Application(name, arguments) if arguments.is_empty() => write!(f, "{}", name),
+      Type::Application(name, arguments) => write!(f, "{}({})", name, join(arguments)),
+      Type::Existential(variables, constraint, body) => {
+        let names = variables.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(" ");
+        match constraint {
+          Constraint::True => write!(f, "{{{}. {}}}", names, body),
+          _ => write!(f, "{{{}, {}. {}}}", names, constraint, body),
+        }
+      }
+      Type::Any => write!(f, "_"),
+    }
+  }
+}
+
+// endregion
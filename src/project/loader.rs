@@ -0,0 +1,464 @@
+/*!
+
+The project loader.
+
+Files are added to one `CodeMap` sequentially, because `CodeMap::add_file` assigns each file a contiguous range of
+positions and needs exclusive access. Parsing does not: once every file has its span, the files are parsed in parallel
+on a scoped thread pool and the results are reassembled in the order the files were given, which is the dependency
+order of the project. A file that fails to read or parse contributes diagnostics but does not stop the others from
+loading, and keeps its place in the load order with no definitions. A file that cannot be read is added to the
+`CodeMap` empty, so that its diagnostic has the file as its location.
+
+Projects using Sail's module system are loaded with `Loader::load_project`, which resolves the module load order
+first and checks module visibility once everything is parsed.
+
+The loader does not hard-code a parser. Anything implementing `SourceParser`, including a plain closure, can be plugged
+in.
+
+*/
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use codemap::{CodeMap, File};
+
+use crate::parser::ast::{Definitions, LocatedDefinition};
+use crate::parser::errors::{LocatedParseError, ParserError};
+use crate::parser::location::{Located, SourceLocation};
+use crate::project::modules::{check_visibility, resolve_modules, ModuleGraph, ModuleSelection, ProjectError};
+use crate::project::sail_project::parse_project;
+
+/// Parses the complete contents of one file into its top-level definitions.
+pub trait SourceParser: Sync {
+  fn parse(&self, file: &File) -> Result<Vec<LocatedDefinition>, Vec<LocatedParseError>>;
+}
+
+impl<F> SourceParser for F
+where
+    F: Fn(&File) -> Result<Vec<LocatedDefinition>, Vec<LocatedParseError>> + Sync,
+{
+  fn parse(&self, file: &File) -> Result<Vec<LocatedDefinition>, Vec<LocatedParseError>> {
+    self(file)
+  }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum LoadError {
+  /// A file could not be read.
+  Io(PathBuf, String),
+  Parse(ParserError),
+  /// An error in the project file or the module structure it describes
+  Project(ProjectError),
+}
+
+pub type LocatedLoadError = Located<LoadError>;
+
+impl LoadError {
+  pub fn is_fatal(&self) -> bool {
+    match self {
+      LoadError::Io(..) => true,
+
+      LoadError::Parse(error) => error.is_fatal(),
+      LoadError::Project(error) => error.is_fatal(),
+    }
+  }
+
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LoadError::Io(path, error) => {
+        write!(f, "could not read {}: {}", path.display(), error)
+      }
+
+      LoadError::Parse(error) => {
+        error.msg(f)
+      }
+
+      LoadError::Project(error) => {
+        error.msg(f)
+      }
+    }
+  }
+}
+
+impl Debug for LoadError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for LoadError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for LoadError {}
+
+/// The result of loading a project: every file's definitions plus every diagnostic encountered along the way.
+pub struct LoadedProject {
+  pub code_map   : CodeMap,
+  pub definitions: Definitions,
+  pub diagnostics: Vec<LocatedLoadError>,
+  /// The module structure, when loaded from a `.sail_project` file
+  pub modules    : Option<ModuleGraph>,
+}
+
+impl LoadedProject {
+  pub fn has_fatal_errors(&self) -> bool {
+    self.diagnostics.iter().any(|diagnostic| diagnostic.is_fatal())
+  }
+}
+
+pub struct Loader<P: SourceParser> {
+  parser : P,
+  threads: usize,
+}
+
+impl<P: SourceParser> Loader<P> {
+  pub fn new(parser: P) -> Self {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    Loader { parser, threads }
+  }
+
+  pub fn parser(&self) -> &P {
+    &self.parser
+  }
+
+  /// Sets the number of parser threads. One thread parses on the calling thread.
+  pub fn with_threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    self
+  }
+
+  /// Loads the given files, in order.
+  pub fn load_files<Q: AsRef<Path>>(&self, paths: &[Q]) -> LoadedProject {
+    self.load_files_into(CodeMap::new(), paths)
+  }
+
+  fn load_files_into<Q: AsRef<Path>>(&self, code_map: CodeMap, paths: &[Q]) -> LoadedProject {
+    let sources = paths
+        .iter()
+        .map(|path| {
+          let path = path.as_ref();
+          let source = std::fs::read_to_string(path);
+          (path.display().to_string(), source.map_err(|error| LoadError::Io(path.to_path_buf(), error.to_string())))
+        })
+        .collect();
+    self.load_into(code_map, sources)
+  }
+
+  /// Loads a project described by a `.sail_project` file. The selected modules are loaded in dependency order, and
+  /// uses of definitions the using module cannot see are reported as diagnostics.
+  pub fn load_project<Q: AsRef<Path>>(&self, project_file: Q, selection: &ModuleSelection) -> LoadedProject {
+    let project_file = project_file.as_ref();
+    let mut code_map = CodeMap::new();
+    let failed = |code_map, diagnostics| LoadedProject {
+      code_map,
+      definitions: Definitions(Vec::new()),
+      diagnostics,
+      modules    : None,
+    };
+
+    let source = match std::fs::read_to_string(project_file) {
+      Ok(source) => source,
+      Err(error) => {
+        let error = LoadError::Io(project_file.to_path_buf(), error.to_string());
+        let file = code_map.add_file(project_file.display().to_string(), String::new());
+        return failed(code_map, vec![at_file(&file, error)]);
+      }
+    };
+    let file = code_map.add_file(project_file.display().to_string(), source);
+    let base = project_file.parent().unwrap_or_else(|| Path::new(""));
+
+    let graph = parse_project(&file)
+        .map_err(|error| vec![error])
+        .and_then(|description| resolve_modules(&description, selection, base));
+    let mut graph = match graph {
+      Ok(graph) => graph,
+      Err(errors) => {
+        let diagnostics = errors.into_iter().map(|error| error.map(LoadError::Project)).collect();
+        return failed(code_map, diagnostics);
+      }
+    };
+
+    let paths = graph.files();
+    let mut project = self.load_files_into(code_map, &paths);
+    for path in &paths {
+      graph.register_file(path.display().to_string(), path);
+    }
+    project.diagnostics.extend(
+      check_visibility(&project.definitions, &graph)
+          .into_iter()
+          .map(|error| error.map(LoadError::Project))
+    );
+    project.modules = Some(graph);
+    project
+  }
+
+  /// Loads the files named by a manifest: one path per line, relative to the manifest's directory, with blank lines
+  /// and `#` comments ignored. Files are loaded in the order listed.
+  pub fn load_manifest<Q: AsRef<Path>>(&self, manifest: Q) -> LoadedProject {
+    let manifest = manifest.as_ref();
+    match read_manifest(manifest) {
+      Ok(paths) => self.load_files(&paths),
+      Err(error) => {
+        let mut code_map = CodeMap::new();
+        let file = code_map.add_file(manifest.display().to_string(), String::new());
+        let diagnostics = vec![at_file(&file, error)];
+        LoadedProject { code_map, definitions: Definitions(Vec::new()), diagnostics, modules: None }
+      }
+    }
+  }
+
+  /// Loads in-memory `(name, source)` pairs, in order.
+  pub fn load_sources(&self, sources: Vec<(String, String)>) -> LoadedProject {
+    self.load_sources_into(CodeMap::new(), sources)
+  }
+
+  fn load_sources_into(&self, code_map: CodeMap, sources: Vec<(String, String)>) -> LoadedProject {
+    self.load_into(code_map, sources.into_iter().map(|(name, source)| (name, Ok(source))).collect())
+  }
+
+  /// Loads `(name, source)` pairs, in order, where reading a source may have failed.
+  fn load_into(&self, mut code_map: CodeMap, sources: Vec<(String, Result<String, LoadError>)>) -> LoadedProject {
+    let mut diagnostics = Vec::new();
+    let files: Vec<(Arc<File>, bool)> = sources
+        .into_iter()
+        .map(|(name, source)| match source {
+          Ok(source) => (code_map.add_file(name, source), true),
+          Err(error) => {
+            // Added empty, as the location of its diagnostic.
+            let file = code_map.add_file(name, String::new());
+            diagnostics.push(at_file(&file, error));
+            (file, false)
+          }
+        })
+        .collect();
+
+    let readable: Vec<Arc<File>> = files.iter().filter(|(_, read)| *read).map(|(file, _)| file.clone()).collect();
+    let mut results = self.parse_all(&readable).into_iter();
+
+    let mut definitions = Vec::with_capacity(files.len());
+    for (file, read) in &files {
+      // A file that could not be read or parsed keeps an entry, so its position in the load order is still visible to
+      // later passes.
+      let result = if *read { results.next().expect("every readable file is parsed") } else { Ok(Vec::new()) };
+      match result {
+        Ok(parsed) => definitions.push((file.name().to_string(), parsed)),
+        Err(errors) => {
+          definitions.push((file.name().to_string(), Vec::new()));
+          diagnostics.extend(errors.into_iter().map(|error| error.map(LoadError::Parse)));
+        }
+      }
+    }
+
+    LoadedProject {
+      code_map,
+      definitions: Definitions(definitions),
+      diagnostics,
+      modules    : None,
+    }
+  }
+
+  /// Parses every file, returning results in the same order as `files`.
+  fn parse_all(&self, files: &[Arc<File>]) -> Vec<Result<Vec<LocatedDefinition>, Vec<LocatedParseError>>> {
+    let threads = self.threads.min(files.len());
+    if threads <= 1 {
+      return files.iter().map(|file| self.parser.parse(file)).collect();
+    }
+
+    // Workers pull the next unclaimed file index, so one large file does not hold up a fixed partition of the rest.
+    let next    = AtomicUsize::new(0);
+    let results = Mutex::new((0..files.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+      for _ in 0..threads {
+        scope.spawn(|| loop {
+          let index = next.fetch_add(1, Ordering::Relaxed);
+          let Some(file) = files.get(index) else { break };
+          let result = self.parser.parse(file);
+          results.lock().unwrap()[index] = Some(result);
+        });
+      }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every file index is claimed by exactly one worker"))
+        .collect()
+  }
+}
+
+/// `error`, located at the whole of `file`.
+fn at_file(file: &File, error: LoadError) -> LocatedLoadError {
+  Located { location: SourceLocation::Span(file.span), value: error }
+}
+
+fn read_manifest(manifest: &Path) -> Result<Vec<PathBuf>, LoadError> {
+  let text = std::fs::read_to_string(manifest)
+      .map_err(|error| LoadError::Io(manifest.to_path_buf(), error.to_string()))?;
+  let base = manifest.parent().unwrap_or_else(|| Path::new(""));
+
+  Ok(
+    text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| base.join(line))
+        .collect()
+  )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+  use crate::parser::ast::Definition;
+  use crate::parser::ast_util::declared_names;
+  use crate::parser::testing::*;
+
+  /// Parses a toy language standing in for Sail, one definition a line: `fn f` defines `f`, `fn f = g` defines `f` to
+  /// call `g`, and either may follow `private`. Anything else is a parse error at the file.
+  pub(crate) fn toy(file: &File) -> Result<Vec<LocatedDefinition>, Vec<LocatedParseError>> {
+    let mut definitions = Vec::new();
+    for line in file.source().lines().map(str::trim).filter(|line| !line.is_empty()) {
+      let (private, line) = match line.strip_prefix("private ") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+      };
+      let definition = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["fn", name] => function(name, vec![], number(0)),
+        ["fn", name, "=", callee] => function(name, vec![], call(callee, vec![])),
+        _ => {
+          let error = ParserError::UnrecognizedCharacter(line.chars().next().unwrap_or(' '));
+          return Err(vec![Located { location: SourceLocation::Span(file.span), value: error }]);
+        }
+      };
+      definitions.push(if private { located(Definition::Private(Box::new(definition))) } else { definition });
+    }
+    Ok(definitions)
+  }
+
+  /// A fresh directory holding `files`, as `(path, contents)` pairs.
+  pub(crate) fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("rigging-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    for (path, contents) in files {
+      let path = directory.join(path);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, contents).unwrap();
+    }
+    directory
+  }
+
+  /// Each file loaded, with the names it defines.
+  fn names(project: &LoadedProject) -> Vec<(String, Vec<String>)> {
+    project
+        .definitions
+        .0
+        .iter()
+        .map(|(file, definitions)| {
+          let names = definitions.iter().flat_map(declared_names).map(|(_, name)| name.name().to_string()).collect();
+          (file.clone(), names)
+        })
+        .collect()
+  }
+
+  /// The diagnostics, each with the name of the file it is located in.
+  fn diagnostics(project: &LoadedProject) -> Vec<(Option<String>, String)> {
+    project
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+          let file = match &diagnostic.location {
+            SourceLocation::Span(span) => Some(project.code_map.find_file(span.low()).name().to_string()),
+            _ => None,
+          };
+          (file, diagnostic.value.to_string())
+        })
+        .collect()
+  }
+
+  #[test]
+  fn parallel_parsing_keeps_the_load_order() {
+    // Earlier files take longer to parse, so that they finish last.
+    let slow = |file: &File| {
+      let index: u64 = file.name().trim_end_matches(".sail").parse().unwrap();
+      thread::sleep(std::time::Duration::from_millis(20 - index));
+      toy(file)
+    };
+    let sources = (0..16).map(|index| (format!("{}.sail", index), format!("fn f{}", index))).collect();
+    let project = Loader::new(slow).with_threads(4).load_sources(sources);
+
+    let expected: Vec<(String, Vec<String>)> =
+      (0..16).map(|index| (format!("{}.sail", index), vec![format!("f{}", index)])).collect();
+    assert_eq!(names(&project), expected);
+    assert!(project.diagnostics.is_empty());
+  }
+
+  #[test]
+  fn manifests_list_files_relative_to_themselves() {
+    let directory = directory("manifest", &[
+      ("model.manifest", "# The prelude comes first.\nprelude.sail\n\n  core/insts.sail  # then the instructions\n"),
+      ("prelude.sail", "fn prelude"),
+      ("core/insts.sail", "fn execute = prelude"),
+    ]);
+    let project = Loader::new(toy).load_manifest(directory.join("model.manifest"));
+    let path = |name: &str| directory.join(name).display().to_string();
+    assert_eq!(names(&project), vec![
+      (path("prelude.sail"), vec!["prelude".to_string()]),
+      (path("core/insts.sail"), vec!["execute".to_string()]),
+    ]);
+    assert!(project.diagnostics.is_empty());
+
+    let missing = Loader::new(toy).load_manifest(directory.join("missing.manifest"));
+    assert_eq!(diagnostics(&missing).len(), 1);
+    assert_eq!(diagnostics(&missing)[0].0, Some(path("missing.manifest")));
+    assert!(missing.has_fatal_errors());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn unreadable_files_keep_their_place() {
+    let directory = directory("unreadable", &[("a.sail", "fn a"), ("c.sail", "fn c")]);
+    let paths: Vec<PathBuf> = ["a.sail", "b.sail", "c.sail"].iter().map(|name| directory.join(name)).collect();
+    let project = Loader::new(toy).with_threads(2).load_files(&paths);
+    let path = |name: &str| directory.join(name).display().to_string();
+
+    assert_eq!(names(&project), vec![
+      (path("a.sail"), vec!["a".to_string()]),
+      (path("b.sail"), vec![]),
+      (path("c.sail"), vec!["c".to_string()]),
+    ]);
+    let diagnostics = diagnostics(&project);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].0, Some(path("b.sail")));
+    assert!(diagnostics[0].1.starts_with(&format!("could not read {}: ", path("b.sail"))), "{}", diagnostics[0].1);
+    assert!(project.has_fatal_errors());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn files_that_fail_to_parse_keep_their_place() {
+    let sources = vec![
+      ("a.sail".to_string(), "fn a".to_string()),
+      ("b.sail".to_string(), "fn b\n!".to_string()),
+      ("c.sail".to_string(), "fn c = a".to_string()),
+    ];
+    let project = Loader::new(toy).with_threads(3).load_sources(sources);
+
+    assert_eq!(names(&project), vec![
+      ("a.sail".to_string(), vec!["a".to_string()]),
+      ("b.sail".to_string(), vec![]),
+      ("c.sail".to_string(), vec!["c".to_string()]),
+    ]);
+    assert_eq!(diagnostics(&project), vec![(Some("b.sail".to_string()), "unrecognized character !".to_string())]);
+    assert!(project.has_fatal_errors());
+  }
+}
@@ -0,0 +1,19 @@
+/*!
+
+Loading multi-file Sail projects.
+
+A Sail model is rarely a single file. This module turns a list of files, or a manifest naming them, into one
+`ast::Definitions` whose spans all live in a single shared `codemap::CodeMap`, so diagnostics from any later pass can
+be reported against the right file.
+
+Projects organised with Sail's module system are described by a `.sail_project` file, parsed by `sail_project` and
+resolved into a load order by `modules`.
+
+*/
+
+mod loader;
+pub mod modules;
+pub mod sail_project;
+
+pub use loader::{LoadError, LoadedProject, LocatedLoadError, Loader, SourceParser};
+pub use modules::{ModuleGraph, ModuleSelection};
@@ -0,0 +1,788 @@
+/*!
+
+Module resolution and visibility for Sail projects.
+
+Given a parsed `ProjectDescription` and a `ModuleSelection` (variable overrides plus the modules wanted), the resolver
+evaluates every conditional, works out which modules are loaded, and orders them so that each module comes after
+everything it `requires` or is declared `after`. A cycle in those edges is reported with the full cycle.
+
+Semantics:
+
+ * Selecting or requiring a module that contains nested modules selects or requires all of them as well.
+ * `requires` is transitive for visibility: a module sees its own definitions and everything visible to the modules it
+   requires. `after` affects only the load order.
+ * A `private` definition is visible only inside the module that declares it.
+ * A module declared in a conditional branch that is not taken behaves as if excluded from the selection.
+
+`check_visibility` enforces the last two rules over the loaded `Definitions`. It only inspects references that cannot
+be shadowed by local variables (function applications, type names and constructor patterns); complete name
+resolution happens later, in the resolver, which can consult the same `ModuleGraph`.
+
+*/
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use crate::parser::ast::*;
+use crate::parser::ast_util::{declared_names, strip_definition, Namespace};
+use crate::parser::location::{Located, SourceLocation};
+use crate::parser::visit::{self, Visitor};
+use crate::project::sail_project::{Condition, FileEntry, ModuleDeclaration, ModuleItem, ProjectDescription, ProjectValue};
+
+pub type ModuleId = usize;
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum ProjectError {
+  UnexpectedCharacter(char),
+  UnterminatedString,
+  UnterminatedComment,
+  UnexpectedToken { expected: String, found: String },
+  DuplicateModule(String),
+  UnknownModule(String),
+  UndefinedVariable(String),
+  /// A selected module requires a module the selection excludes.
+  ExcludedRequirement { module: String, required: String },
+  DependencyCycle(Vec<String>),
+  /// An `error("...")` file entry was selected.
+  ErrorDirective(String),
+  /// A definition is used from a module that does not require the module declaring it.
+  NotVisible { name: String, module: String, defined_in: String },
+  /// A private definition is used outside the module declaring it.
+  PrivateAccess { name: String, module: String, defined_in: String },
+}
+
+pub type LocatedProjectError = Located<ProjectError>;
+
+impl ProjectError {
+  pub fn is_fatal(&self) -> bool {
+    !matches!(self, ProjectError::NotVisible { .. } | ProjectError::PrivateAccess { .. })
+  }
+
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ProjectError::UnexpectedCharacter(c) => {
+        write!(f, "unexpected character {}", c)
+      }
+
+      ProjectError::UnterminatedString => {
+        write!(f, "unterminated string")
+      }
+
+      ProjectError::UnterminatedComment => {
+        write!(f, "unterminated comment")
+      }
+
+      ProjectError::UnexpectedToken { expected, found } => {
+        write!(f, "expected {} but found {}", expected, found)
+      }
+
+      ProjectError::DuplicateModule(name) => {
+        write!(f, "module `{}` is declared more than once", name)
+      }
+
+      ProjectError::UnknownModule(name) => {
+        write!(f, "no module named `{}`", name)
+      }
+
+      ProjectError::UndefinedVariable(name) => {
+        write!(f, "undefined project variable `${}`", name)
+      }
+
+      ProjectError::ExcludedRequirement { module, required } => {
+        write!(f, "module `{}` requires `{}`, which is excluded", module, required)
+      }
+
+      ProjectError::DependencyCycle(cycle) => {
+        write!(f, "module dependency cycle: {}", cycle.join(" -> "))
+      }
+
+      ProjectError::ErrorDirective(message) => {
+        write!(f, "{}", message)
+      }
+
+      ProjectError::NotVisible { name, module, defined_in } => {
+        write!(
+          f,
+          "`{}` is defined in module `{}`, which module `{}` does not require",
+          name, defined_in, module
+        )
+      }
+
+      ProjectError::PrivateAccess { name, module, defined_in } => {
+        write!(f, "`{}` is private to module `{}` and cannot be used in module `{}`", name, defined_in, module)
+      }
+    }
+  }
+}
+
+impl Debug for ProjectError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for ProjectError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for ProjectError {}
+
+/// Which modules to load, and the values of project variables.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleSelection {
+  /// Overrides for the project's `variable` declarations
+  pub variables: Vec<(String, String)>,
+  /// Modules to load, along with everything they require. Empty means every module.
+  pub modules  : Vec<String>,
+  /// Modules that must not be loaded
+  pub excluded : Vec<String>,
+}
+
+impl ModuleSelection {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_variable<S: Into<String>, T: Into<String>>(mut self, name: S, value: T) -> Self {
+    self.variables.push((name.into(), value.into()));
+    self
+  }
+
+  pub fn with_module<S: Into<String>>(mut self, name: S) -> Self {
+    self.modules.push(name.into());
+    self
+  }
+
+  pub fn without_module<S: Into<String>>(mut self, name: S) -> Self {
+    self.excluded.push(name.into());
+    self
+  }
+}
+
+/// A module after conditionals have been evaluated.
+#[derive(Debug, Clone)]
+pub struct Module {
+  pub name    : String,
+  pub location: SourceLocation,
+  pub parent  : Option<ModuleId>,
+  pub children: Vec<ModuleId>,
+  pub requires: Vec<ModuleId>,
+  pub after   : Vec<ModuleId>,
+  pub files   : Vec<PathBuf>,
+}
+
+/// The resolved module structure of a project.
+#[derive(Debug, Clone)]
+pub struct ModuleGraph {
+  /// Every declared module, selected or not, in declaration order
+  pub modules   : Vec<Module>,
+  /// The selected modules, in load order
+  pub load_order: Vec<ModuleId>,
+  file_modules  : HashMap<String, ModuleId>,
+  visible       : Vec<HashSet<ModuleId>>,
+}
+
+impl ModuleGraph {
+  pub fn module_id(&self, name: &str) -> Option<ModuleId> {
+    self.modules.iter().position(|module| module.name == name)
+  }
+
+  /// The module a loaded file belongs to, by the file name recorded in `Definitions`.
+  pub fn module_of_file(&self, file: &str) -> Option<ModuleId> {
+    self.file_modules.get(file).copied()
+  }
+
+  /// Whether public definitions of `target` are visible from `from`.
+  pub fn can_see(&self, from: ModuleId, target: ModuleId) -> bool {
+    self.visible[from].contains(&target)
+  }
+
+  /// Every file to load, in load order.
+  pub fn files(&self) -> Vec<PathBuf> {
+    self.load_order
+        .iter()
+        .flat_map(|&id| self.modules[id].files.iter().cloned())
+        .collect()
+  }
+
+  /// Records the name under which `path` appears in `Definitions`.
+  pub(crate) fn register_file(&mut self, name: String, path: &Path) {
+    if let Some(id) = self.load_order.iter().copied().find(|&id| self.modules[id].files.iter().any(|f| f == path)) {
+      self.file_modules.insert(name, id);
+    }
+  }
+}
+
+// region Resolution
+
+/// Resolves the modules of `project`. File paths are taken relative to `base`.
+pub fn resolve_modules(
+  project  : &ProjectDescription,
+  selection: &ModuleSelection,
+  base     : &Path,
+) -> Result<ModuleGraph, Vec<LocatedProjectError>> {
+  let mut resolver = Resolver {
+    modules    : Vec::new(),
+    names      : HashMap::new(),
+    variables  : HashMap::new(),
+    directives : Vec::new(),
+    disabled   : HashSet::new(),
+    errors     : Vec::new(),
+  };
+
+  for declaration in &project.modules {
+    resolver.declare(declaration, None);
+  }
+  for (name, value) in &project.variables {
+    let value = resolver.value(&value.value, &value.location).unwrap_or_default();
+    resolver.variables.insert(name.value.clone(), value);
+  }
+  for (name, value) in &selection.variables {
+    resolver.variables.insert(name.clone(), value.clone());
+  }
+
+  let mut id = 0;
+  for declaration in &project.modules {
+    resolver.evaluate_module(declaration, &mut id, base, true);
+  }
+  if !resolver.errors.is_empty() {
+    return Err(resolver.errors);
+  }
+
+  let selected = resolver.select(selection)?;
+  // Errors in conditional file lists matter only for modules that are actually loaded.
+  let directive_errors: Vec<_> = resolver.directives
+      .drain(..)
+      .filter(|(id, _)| selected.contains(id))
+      .map(|(_, error)| error)
+      .collect();
+  if !directive_errors.is_empty() {
+    return Err(directive_errors);
+  }
+
+  let load_order = resolver.order(&selected)?;
+  let visible    = resolver.visibility();
+  Ok(ModuleGraph {
+    modules     : resolver.modules,
+    load_order,
+    file_modules: HashMap::new(),
+    visible,
+  })
+}
+
+struct Resolver {
+  modules   : Vec<Module>,
+  names     : HashMap<String, ModuleId>,
+  variables : HashMap<String, String>,
+  /// `error(...)` entries reached while evaluating each module
+  directives: Vec<(ModuleId, LocatedProjectError)>,
+  /// Modules declared inside conditional branches that were not taken
+  disabled  : HashSet<ModuleId>,
+  errors    : Vec<LocatedProjectError>,
+}
+
+impl Resolver {
+  fn error(&mut self, error: ProjectError, location: &SourceLocation) {
+    self.errors.push(Located { location: location.clone(), value: error });
+  }
+
+  /// Assigns ids to `declaration` and its nested modules, depth first in declaration order.
+  fn declare(&mut self, declaration: &ModuleDeclaration, parent: Option<ModuleId>) {
+    let id = self.modules.len();
+    if self.names.insert(declaration.name.value.clone(), id).is_some() {
+      self.error(ProjectError::DuplicateModule(declaration.name.value.clone()), &declaration.name.location);
+    }
+    self.modules.push(Module {
+      name    : declaration.name.value.clone(),
+      location: declaration.name.location.clone(),
+      parent,
+      children: Vec::new(),
+      requires: Vec::new(),
+      after   : Vec::new(),
+      files   : Vec::new(),
+    });
+    if let Some(parent) = parent {
+      self.modules[parent].children.push(id);
+    }
+    self.declare_nested(&declaration.items, id);
+  }
+
+  fn declare_nested(&mut self, items: &[Located<ModuleItem>], parent: ModuleId) {
+    for item in items {
+      match &item.value {
+        ModuleItem::Module(child) => self.declare(child, Some(parent)),
+        ModuleItem::If(_, then_items, else_items) => {
+          self.declare_nested(then_items, parent);
+          self.declare_nested(else_items, parent);
+        }
+        _ => {}
+      }
+    }
+  }
+
+  /// Evaluates the items of `declaration`, visiting modules in the same order as `declare`. A module declared in a
+  /// branch not taken is disabled, and so are its nested modules.
+  fn evaluate_module(&mut self, declaration: &ModuleDeclaration, next_id: &mut ModuleId, base: &Path, active: bool) {
+    let id = *next_id;
+    *next_id += 1;
+    if !active {
+      self.disabled.insert(id);
+    }
+    self.evaluate_items(id, &declaration.items, next_id, base, active);
+  }
+
+  fn evaluate_items(
+    &mut self,
+    id      : ModuleId,
+    items   : &[Located<ModuleItem>],
+    next_id : &mut ModuleId,
+    base    : &Path,
+    active  : bool,
+  ) {
+    for item in items {
+      match &item.value {
+        ModuleItem::Requires(names) if active => {
+          let ids = self.module_ids(names);
+          self.modules[id].requires.extend(ids);
+        }
+        ModuleItem::After(names) if active => {
+          let ids = self.module_ids(names);
+          self.modules[id].after.extend(ids);
+        }
+        ModuleItem::Files(entries) if active => {
+          for entry in entries {
+            if let Some(path) = self.file_entry(id, entry) {
+              self.modules[id].files.push(base.join(path));
+            }
+          }
+        }
+        ModuleItem::Requires(_)
+        | ModuleItem::After(_)
+        | ModuleItem::Files(_) => {}
+        // Nested modules are declared unconditionally, so they are always visited to keep ids in step.
+        ModuleItem::Module(child) => self.evaluate_module(child, next_id, base, active),
+        ModuleItem::If(condition, then_items, else_items) => {
+          let holds = active && self.condition(condition).unwrap_or(false);
+          self.evaluate_items(id, then_items, next_id, base, active && holds);
+          self.evaluate_items(id, else_items, next_id, base, active && !holds);
+        }
+      }
+    }
+  }
+
+  fn module_ids(&mut self, names: &[Located<String>]) -> Vec<ModuleId> {
+    let mut ids = Vec::new();
+    for name in names {
+      match self.names.get(&name.value) {
+        Some(&id) => ids.push(id),
+        None => self.error(ProjectError::UnknownModule(name.value.clone()), &name.location),
+      }
+    }
+    ids
+  }
+
+  fn file_entry(&mut self, id: ModuleId, entry: &Located<FileEntry>) -> Option<String> {
+    match &entry.value {
+      FileEntry::Path(path) => Some(path.clone()),
+      FileEntry::Error(message) => {
+        let error = Located { location: entry.location.clone(), value: ProjectError::ErrorDirective(message.clone()) };
+        self.directives.push((id, error));
+        None
+      }
+      FileEntry::If(condition, then_entry, else_entry) => {
+        match self.condition(condition)? {
+          true  => self.file_entry(id, then_entry),
+          false => else_entry.as_ref().and_then(|entry| self.file_entry(id, entry)),
+        }
+      }
+    }
+  }
+
+  fn condition(&mut self, condition: &Located<Condition>) -> Option<bool> {
+    let location = &condition.location;
+    Some(match &condition.value {
+      Condition::Value(value) => self.value(value, location)? == "true",
+      Condition::Equal(left, right) => self.value(left, location)? == self.value(right, location)?,
+      Condition::NotEqual(left, right) => self.value(left, location)? != self.value(right, location)?,
+      Condition::Not(inner) => !self.condition(inner)?,
+      Condition::And(left, right) => self.condition(left)? && self.condition(right)?,
+      Condition::Or(left, right) => self.condition(left)? || self.condition(right)?,
+    })
+  }
+
+  fn value(&mut self, value: &ProjectValue, location: &SourceLocation) -> Option<String> {
+    match value {
+      ProjectValue::String(string) => Some(string.clone()),
+      ProjectValue::Bool(boolean) => Some(boolean.to_string()),
+      ProjectValue::Variable(name) => match self.variables.get(name) {
+        Some(value) => Some(value.clone()),
+        None => {
+          self.error(ProjectError::UndefinedVariable(name.clone()), location);
+          None
+        }
+      },
+    }
+  }
+
+  /// `id` and all of its nested modules.
+  fn with_descendants(&self, id: ModuleId) -> Vec<ModuleId> {
+    let mut ids = vec![id];
+    let mut index = 0;
+    while index < ids.len() {
+      ids.extend(self.modules[ids[index]].children.iter().copied());
+      index += 1;
+    }
+    ids
+  }
+
+  /// The modules `id` depends on through `requires`, with groups expanded to their nested modules.
+  fn required(&self, id: ModuleId) -> Vec<ModuleId> {
+    self.modules[id].requires.iter().flat_map(|&required| self.with_descendants(required)).collect()
+  }
+
+  fn select(&self, selection: &ModuleSelection) -> Result<BTreeSet<ModuleId>, Vec<LocatedProjectError>> {
+    let mut errors = Vec::new();
+    let lookup = |name: &String, errors: &mut Vec<LocatedProjectError>| match self.names.get(name) {
+      Some(&id) => Some(id),
+      None => {
+        errors.push(ProjectError::UnknownModule(name.clone()).into());
+        None
+      }
+    };
+
+    let excluded: HashSet<ModuleId> = selection.excluded
+        .iter()
+        .filter_map(|name| lookup(name, &mut errors))
+        .flat_map(|id| self.with_descendants(id))
+        .chain(self.disabled.iter().copied())
+        .collect();
+    let roots: Vec<ModuleId> = match selection.modules.is_empty() {
+      true  => (0..self.modules.len()).collect(),
+      false => selection.modules.iter().filter_map(|name| lookup(name, &mut errors)).collect(),
+    };
+
+    let mut selected = BTreeSet::new();
+    let mut stack: Vec<ModuleId> = roots
+        .into_iter()
+        .flat_map(|id| self.with_descendants(id))
+        .filter(|id| !excluded.contains(id))
+        .collect();
+    while let Some(id) = stack.pop() {
+      if !selected.insert(id) {
+        continue;
+      }
+      for required in self.required(id) {
+        if excluded.contains(&required) {
+          errors.push(Located {
+            location: self.modules[id].location.clone(),
+            value   : ProjectError::ExcludedRequirement {
+              module  : self.modules[id].name.clone(),
+              required: self.modules[required].name.clone(),
+            },
+          });
+        } else {
+          stack.push(required);
+        }
+      }
+    }
+
+    match errors.is_empty() {
+      true  => Ok(selected),
+      false => Err(errors),
+    }
+  }
+
+  /// Orders the selected modules so that every module follows its prerequisites, preferring declaration order among
+  /// modules that are free to go next.
+  fn order(&self, selected: &BTreeSet<ModuleId>) -> Result<Vec<ModuleId>, Vec<LocatedProjectError>> {
+    let prerequisites = |id: ModuleId| -> BTreeSet<ModuleId> {
+      self.required(id)
+          .into_iter()
+          .chain(self.modules[id].after.iter().flat_map(|&after| self.with_descendants(after)))
+          .filter(|other| *other != id && selected.contains(other))
+          .collect()
+    };
+    let mut waiting: HashMap<ModuleId, BTreeSet<ModuleId>> =
+        selected.iter().map(|&id| (id, prerequisites(id))).collect();
+
+    let mut order = Vec::with_capacity(selected.len());
+    while let Some(&next) = selected.iter().find(|id| waiting.get(id).is_some_and(|pending| pending.is_empty())) {
+      waiting.remove(&next);
+      for pending in waiting.values_mut() {
+        pending.remove(&next);
+      }
+      order.push(next);
+    }
+
+    if waiting.is_empty() {
+      return Ok(order);
+    }
+
+    // Every remaining module waits on another remaining module, so following first prerequisites must revisit one.
+    let mut path = vec![*waiting.keys().min().unwrap()];
+    loop {
+      let current = *path.last().unwrap();
+      let next = *waiting[&current].iter().next().unwrap();
+      if let Some(start) = path.iter().position(|&id| id == next) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|&id| self.modules[id].name.clone()).collect();
+        cycle.push(self.modules[next].name.clone());
+        return Err(vec![Located {
+          location: self.modules[next].location.clone(),
+          value   : ProjectError::DependencyCycle(cycle),
+        }]);
+      }
+      path.push(next);
+    }
+  }
+
+  fn visibility(&self) -> Vec<HashSet<ModuleId>> {
+    (0..self.modules.len())
+        .map(|id| {
+          let mut visible = HashSet::from([id]);
+          let mut stack = self.required(id);
+          while let Some(next) = stack.pop() {
+            if visible.insert(next) {
+              stack.extend(self.required(next));
+            }
+          }
+          visible
+        })
+        .collect()
+  }
+}
+
+// endregion
+
+// region Visibility checking
+
+struct Declaration {
+  module : ModuleId,
+  private: bool,
+}
+
+/// Reports uses of definitions that are private to, or not required by, the module using them.
+pub fn check_visibility(definitions: &Definitions, graph: &ModuleGraph) -> Vec<LocatedProjectError> {
+  let mut declarations: HashMap<(Namespace, String), Vec<Declaration>> = HashMap::new();
+  for (file, file_definitions) in &definitions.0 {
+    let Some(module) = graph.module_of_file(file) else { continue };
+    for definition in file_definitions {
+      let (_, private) = strip_definition(definition);
+      for (namespace, name) in declared_names(definition) {
+        declarations
+            .entry((namespace, name.name().to_string()))
+            .or_default()
+            .push(Declaration { module, private });
+      }
+    }
+  }
+
+  let mut errors = Vec::new();
+  for (file, file_definitions) in &definitions.0 {
+    let Some(module) = graph.module_of_file(file) else { continue };
+    let mut collector = ReferenceCollector { references: Vec::new() };
+    for definition in file_definitions {
+      collector.visit_definition(definition);
+    }
+
+    for (namespace, name, location) in collector.references {
+      let Some(candidates) = declarations.get(&(namespace, name.clone())) else { continue };
+      if candidates.iter().any(|d| d.module == module || (!d.private && graph.can_see(module, d.module))) {
+        continue;
+      }
+
+      let private = candidates.iter().find(|d| d.private && graph.can_see(module, d.module));
+      let error = match private {
+        Some(declaration) => ProjectError::PrivateAccess {
+          name      : name.clone(),
+          module    : graph.modules[module].name.clone(),
+          defined_in: graph.modules[declaration.module].name.clone(),
+        },
+        None => ProjectError::NotVisible {
+          name      : name.clone(),
+          module    : graph.modules[module].name.clone(),
+          defined_in: graph.modules[candidates[0].module].name.clone(),
+        },
+      };
+      errors.push(Located { location, value: error });
+    }
+  }
+
+  errors
+}
+
+/// Collects references that cannot be to local variables.
+struct ReferenceCollector {
+  references: Vec<(Namespace, String, SourceLocation)>,
+}
+
+impl ReferenceCollector {
+  fn reference(&mut self, namespace: Namespace, identifier: &LocatedIdentifier) {
+    self.references.push((namespace, identifier.name().to_string(), identifier.location.clone()));
+  }
+}
+
+impl Visitor for ReferenceCollector {
+  fn visit_abstract_type(&mut self, abstract_type: &LocatedAbstractType) {
+    match &abstract_type.value {
+      AbstractType::Identifier(identifier)
+      | AbstractType::TypeConstructorApplication(identifier, _) => {
+        self.reference(Namespace::Type, identifier);
+      }
+      _ => {}
+    }
+    visit::walk_abstract_type(self, abstract_type)
+  }
+
+  fn visit_expression(&mut self, expression: &LocatedExpression) {
+    match &expression.value {
+      Expression::Application(identifier, _)
+      | Expression::InfixApplication(_, identifier, _) => {
+        self.reference(Namespace::Value, identifier);
+      }
+      _ => {}
+    }
+    visit::walk_expression(self, expression)
+  }
+
+  fn visit_pattern(&mut self, pattern: &LocatedPattern) {
+    if let Pattern::Constructor(identifier, _) = &pattern.value {
+      self.reference(Namespace::Value, identifier);
+    }
+    visit::walk_pattern(self, pattern)
+  }
+
+  fn visit_mapping_pattern(&mut self, pattern: &LocatedMappingPattern) {
+    if let MappingPattern::Application(identifier, _) = &pattern.value {
+      self.reference(Namespace::Value, identifier);
+    }
+    visit::walk_mapping_pattern(self, pattern)
+  }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use codemap::CodeMap;
+
+  use crate::project::loader::tests::{directory, toy};
+  use crate::project::sail_project::parse_project;
+  use crate::project::{LoadError, Loader};
+
+  fn resolve(text: &str, selection: &ModuleSelection) -> Result<ModuleGraph, Vec<String>> {
+    let file = CodeMap::new().add_file("test.sail_project".to_string(), text.to_string());
+    let project = parse_project(&file).expect("the project parses");
+    resolve_modules(&project, selection, Path::new(""))
+        .map_err(|errors| errors.iter().map(|error| error.value.to_string()).collect())
+  }
+
+  fn files(graph: &ModuleGraph) -> Vec<String> {
+    graph.files().iter().map(|path| path.display().to_string()).collect()
+  }
+
+  const LAYERED: &str = "c { requires b files c.sail } b { after a files b.sail } a { files a.sail }";
+
+  #[test]
+  fn modules_load_after_what_they_require_or_follow() {
+    let graph = resolve(LAYERED, &ModuleSelection::new()).unwrap();
+    assert_eq!(files(&graph), vec!["a.sail", "b.sail", "c.sail"]);
+
+    let (a, b, c) = (graph.module_id("a").unwrap(), graph.module_id("b").unwrap(), graph.module_id("c").unwrap());
+    assert!(graph.can_see(c, b));
+    assert!(!graph.can_see(b, c));
+    // `after` orders without making anything visible.
+    assert!(!graph.can_see(b, a));
+  }
+
+  #[test]
+  fn cycles_are_reported() {
+    let errors = resolve("a { requires b } b { after c } c { requires a }", &ModuleSelection::new()).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("module dependency cycle: "), "{}", errors[0]);
+    for module in ["a", "b", "c"] {
+      assert!(errors[0].contains(&format!("{} ->", module)), "{}", errors[0]);
+    }
+  }
+
+  #[test]
+  fn selections_bring_in_requirements() {
+    let graph = resolve(LAYERED, &ModuleSelection::new().with_module("c")).unwrap();
+    assert_eq!(files(&graph), vec!["b.sail", "c.sail"]);
+
+    let errors = resolve(LAYERED, &ModuleSelection::new().with_module("c").without_module("b")).unwrap_err();
+    assert_eq!(errors, vec!["module `c` requires `b`, which is excluded".to_string()]);
+
+    let errors = resolve(LAYERED, &ModuleSelection::new().with_module("d")).unwrap_err();
+    assert_eq!(errors, vec!["no module named `d`".to_string()]);
+  }
+
+  #[test]
+  fn variables_choose_files_and_modules() {
+    let project = "variable ARCH = RV64\n\
+                   top {\n\
+                     core { files if $ARCH == RV32 then xlen32.sail else xlen64.sail }\n\
+                     if $ARCH == RV32 { } else { wide { requires core files wide.sail } }\n\
+                   }";
+
+    let graph = resolve(project, &ModuleSelection::new()).unwrap();
+    assert_eq!(files(&graph), vec!["xlen64.sail", "wide.sail"]);
+
+    let graph = resolve(project, &ModuleSelection::new().with_variable("ARCH", "RV32")).unwrap();
+    assert_eq!(files(&graph), vec!["xlen32.sail"]);
+  }
+
+  /// The errors from loading the project `text`, whose modules `a`, `b` and `c` are in files of the same names, in
+  /// the toy language of the loader's tests.
+  fn visibility_errors(name: &str, text: &str, files: [&str; 3]) -> Vec<ProjectError> {
+    let directory = directory(name, &[
+      ("model.sail_project", text),
+      ("a.sail", files[0]),
+      ("b.sail", files[1]),
+      ("c.sail", files[2]),
+    ]);
+    let project = Loader::new(toy).load_project(directory.join("model.sail_project"), &ModuleSelection::new());
+    std::fs::remove_dir_all(&directory).unwrap();
+    project
+        .diagnostics
+        .into_iter()
+        .map(|diagnostic| match diagnostic.value {
+          LoadError::Project(error) => error,
+          error => panic!("expected a project error, found {}", error),
+        })
+        .collect()
+  }
+
+  const VISIBILITY: &str = "a { files a.sail } b { requires a files b.sail } c { after a files c.sail }";
+
+  #[test]
+  fn required_modules_public_names_are_visible() {
+    let errors = visibility_errors("visible", VISIBILITY, ["fn f\nprivate fn g = f", "fn h = f", "fn c"]);
+    assert_eq!(errors, vec![]);
+  }
+
+  #[test]
+  fn private_names_are_not_visible_in_other_modules() {
+    let errors = visibility_errors("private", VISIBILITY, ["private fn secret", "fn h = secret", "fn c"]);
+    assert_eq!(errors, vec![ProjectError::PrivateAccess {
+      name      : "secret".to_string(),
+      module    : "b".to_string(),
+      defined_in: "a".to_string(),
+    }]);
+  }
+
+  #[test]
+  fn names_are_not_visible_in_modules_that_do_not_require_theirs() {
+    // `after` orders `c` after `a` without requiring it.
+    let errors = visibility_errors("not-visible", VISIBILITY, ["fn f", "fn b", "fn h = f"]);
+    assert_eq!(errors, vec![ProjectError::NotVisible {
+      name      : "f".to_string(),
+      module    : "c".to_string(),
+      defined_in: "a".to_string(),
+    }]);
+  }
+}
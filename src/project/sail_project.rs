@@ -0,0 +1,576 @@
+/*!
+
+Parser for Sail `.sail_project` files.
+
+A project file declares variables and a set of modules:
+
+```text
+variable ARCH = RV64
+
+prelude {
+  files prelude.sail
+}
+
+core {
+  requires prelude
+  files
+    types.sail,
+    if $ARCH == RV32 then xlen32.sail else xlen64.sail,
+    regs.sail
+}
+
+extensions {
+  vector {
+    requires core
+    after core
+    files vext.sail
+  }
+}
+```
+
+`requires` makes the named modules' definitions visible and forces them to load first; `after` only constrains load
+order. Module bodies may contain `if <condition> { ... } else { ... }` blocks, and file lists may contain
+`if <condition> then <file> else <file>` entries and `error("message")` entries, so that variables select what is
+loaded. Conditions compare `$VARIABLE`s, bare words and strings with `==` and `!=`, combined with `!`, `&&`, `||` and
+parentheses. Comments are written `//` and `/* */`.
+
+*/
+
+use codemap::{File, Span};
+
+use crate::parser::location::{Located, SourceLocation};
+use crate::project::modules::{LocatedProjectError, ProjectError};
+
+/// A complete project file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProjectDescription {
+  pub variables: Vec<(Located<String>, Located<ProjectValue>)>,
+  pub modules  : Vec<ModuleDeclaration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDeclaration {
+  pub name : Located<String>,
+  pub items: Vec<Located<ModuleItem>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleItem {
+  Requires(Vec<Located<String>>),
+  After(Vec<Located<String>>),
+  Files(Vec<Located<FileEntry>>),
+  /// A nested module
+  Module(ModuleDeclaration),
+  /// Conditional inclusion of module items
+  If(Located<Condition>, Vec<Located<ModuleItem>>, Vec<Located<ModuleItem>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileEntry {
+  Path(String),
+  If(Located<Condition>, Box<Located<FileEntry>>, Option<Box<Located<FileEntry>>>),
+  /// Selecting this entry is an error with the given message
+  Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+  Value(ProjectValue),
+  Equal(ProjectValue, ProjectValue),
+  NotEqual(ProjectValue, ProjectValue),
+  Not(Box<Located<Condition>>),
+  And(Box<Located<Condition>>, Box<Located<Condition>>),
+  Or(Box<Located<Condition>>, Box<Located<Condition>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectValue {
+  /// `$NAME`
+  Variable(String),
+  /// A quoted string or bare word
+  String(String),
+  Bool(bool),
+}
+
+// region Lexer
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+  Word(String),
+  String(String),
+  Dollar,
+  OpenBrace,
+  CloseBrace,
+  OpenParen,
+  CloseParen,
+  Comma,
+  Assign,
+  Equal,
+  NotEqual,
+  And,
+  Or,
+  Not,
+  End,
+}
+
+impl Token {
+  fn describe(&self) -> String {
+    match self {
+      Token::Word(word)     => format!("`{}`", word),
+      Token::String(string) => format!("\"{}\"", string),
+      Token::Dollar         => "`$`".to_string(),
+      Token::OpenBrace      => "`{`".to_string(),
+      Token::CloseBrace     => "`}`".to_string(),
+      Token::OpenParen      => "`(`".to_string(),
+      Token::CloseParen     => "`)`".to_string(),
+      Token::Comma          => "`,`".to_string(),
+      Token::Assign         => "`=`".to_string(),
+      Token::Equal          => "`==`".to_string(),
+      Token::NotEqual       => "`!=`".to_string(),
+      Token::And            => "`&&`".to_string(),
+      Token::Or             => "`||`".to_string(),
+      Token::Not            => "`!`".to_string(),
+      Token::End            => "end of file".to_string(),
+    }
+  }
+}
+
+fn is_word_byte(byte: u8) -> bool {
+  byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'.' | b'/' | b'-' | b'\'')
+}
+
+fn tokenize(file: &File) -> Result<Vec<(Token, Span)>, LocatedProjectError> {
+  let source = file.source().as_bytes();
+  let span   = |start: usize, end: usize| file.span.subspan(start as u64, end as u64);
+  let error  = |error: ProjectError, start: usize, end: usize| Located {
+    location: SourceLocation::Span(span(start, end)),
+    value   : error,
+  };
+
+  let mut tokens   = Vec::new();
+  let mut position = 0;
+  while position < source.len() {
+    let start = position;
+    let byte  = source[position];
+    let next  = source.get(position + 1).copied();
+
+    let token = match (byte, next) {
+      _ if byte.is_ascii_whitespace() => {
+        position += 1;
+        continue;
+      }
+      (b'/', Some(b'/')) => {
+        while position < source.len() && source[position] != b'\n' {
+          position += 1;
+        }
+        continue;
+      }
+      (b'/', Some(b'*')) => {
+        position += 2;
+        while position < source.len() && !source[position..].starts_with(b"*/") {
+          position += 1;
+        }
+        if position >= source.len() {
+          return Err(error(ProjectError::UnterminatedComment, start, position));
+        }
+        position += 2;
+        continue;
+      }
+      (b'"', _) => {
+        position += 1;
+        while position < source.len() && source[position] != b'"' {
+          position += 1;
+        }
+        if position >= source.len() {
+          return Err(error(ProjectError::UnterminatedString, start, position));
+        }
+        position += 1;
+        Token::String(file.source()[start + 1..position - 1].to_string())
+      }
+      (b'=', Some(b'=')) => { position += 2; Token::Equal }
+      (b'!', Some(b'=')) => { position += 2; Token::NotEqual }
+      (b'&', Some(b'&')) => { position += 2; Token::And }
+      (b'|', Some(b'|')) => { position += 2; Token::Or }
+      (b'=', _) => { position += 1; Token::Assign }
+      (b'!', _) => { position += 1; Token::Not }
+      (b'$', _) => { position += 1; Token::Dollar }
+      (b'{', _) => { position += 1; Token::OpenBrace }
+      (b'}', _) => { position += 1; Token::CloseBrace }
+      (b'(', _) => { position += 1; Token::OpenParen }
+      (b')', _) => { position += 1; Token::CloseParen }
+      (b',', _) => { position += 1; Token::Comma }
+      _ if is_word_byte(byte) => {
+        while position < source.len() && is_word_byte(source[position]) {
+          position += 1;
+        }
+        Token::Word(file.source()[start..position].to_string())
+      }
+      _ => {
+        let character = file.source()[start..].chars().next().unwrap_or('?');
+        return Err(error(ProjectError::UnexpectedCharacter(character), start, start + character.len_utf8()));
+      }
+    };
+    tokens.push((token, span(start, position)));
+  }
+
+  tokens.push((Token::End, span(source.len(), source.len())));
+  Ok(tokens)
+}
+
+// endregion
+
+// region Parser
+
+/// Parses the contents of a project file.
+pub fn parse_project(file: &File) -> Result<ProjectDescription, LocatedProjectError> {
+  let tokens = tokenize(file)?;
+  let mut parser = Parser { tokens, position: 0 };
+  parser.project()
+}
+
+struct Parser {
+  tokens  : Vec<(Token, Span)>,
+  position: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> &Token {
+    &self.tokens[self.position].0
+  }
+
+  fn span(&self) -> Span {
+    self.tokens[self.position].1
+  }
+
+  fn advance(&mut self) -> (Token, Span) {
+    let token = self.tokens[self.position].clone();
+    if self.position + 1 < self.tokens.len() {
+      self.position += 1;
+    }
+    token
+  }
+
+  fn peek_word(&self, keyword: &str) -> bool {
+    matches!(self.peek(), Token::Word(word) if word == keyword)
+  }
+
+  fn unexpected<T>(&self, expected: &str) -> Result<T, LocatedProjectError> {
+    Err(Located {
+      location: SourceLocation::Span(self.span()),
+      value   : ProjectError::UnexpectedToken {
+        expected: expected.to_string(),
+        found   : self.peek().describe(),
+      },
+    })
+  }
+
+  fn expect(&mut self, token: Token) -> Result<Span, LocatedProjectError> {
+    if *self.peek() == token {
+      Ok(self.advance().1)
+    } else {
+      self.unexpected(&token.describe())
+    }
+  }
+
+  fn word(&mut self, expected: &str) -> Result<Located<String>, LocatedProjectError> {
+    match self.peek().clone() {
+      Token::Word(word) => {
+        let (_, span) = self.advance();
+        Ok(Located { location: SourceLocation::Span(span), value: word })
+      }
+      _ => self.unexpected(expected),
+    }
+  }
+
+  fn project(&mut self) -> Result<ProjectDescription, LocatedProjectError> {
+    let mut project = ProjectDescription::default();
+    loop {
+      match self.peek() {
+        Token::End => return Ok(project),
+        Token::Word(word) if word == "variable" => {
+          self.advance();
+          let name = self.word("a variable name")?;
+          self.expect(Token::Assign)?;
+          let value = self.located_value()?;
+          project.variables.push((name, value));
+        }
+        Token::Word(_) => project.modules.push(self.module()?),
+        _ => return self.unexpected("`variable` or a module name"),
+      }
+    }
+  }
+
+  fn module(&mut self) -> Result<ModuleDeclaration, LocatedProjectError> {
+    let name = self.word("a module name")?;
+    let items = self.module_body()?;
+    Ok(ModuleDeclaration { name, items })
+  }
+
+  fn module_body(&mut self) -> Result<Vec<Located<ModuleItem>>, LocatedProjectError> {
+    self.expect(Token::OpenBrace)?;
+    let mut items = Vec::new();
+    while *self.peek() != Token::CloseBrace {
+      items.push(self.module_item()?);
+    }
+    self.expect(Token::CloseBrace)?;
+    Ok(items)
+  }
+
+  fn module_item(&mut self) -> Result<Located<ModuleItem>, LocatedProjectError> {
+    let start = self.span();
+    let item = match self.peek().clone() {
+      Token::Word(word) if word == "requires" => {
+        self.advance();
+        ModuleItem::Requires(self.word_list("a module name")?)
+      }
+      Token::Word(word) if word == "after" => {
+        self.advance();
+        ModuleItem::After(self.word_list("a module name")?)
+      }
+      Token::Word(word) if word == "files" => {
+        self.advance();
+        let mut entries = vec![self.file_entry()?];
+        while *self.peek() == Token::Comma {
+          self.advance();
+          entries.push(self.file_entry()?);
+        }
+        ModuleItem::Files(entries)
+      }
+      Token::Word(word) if word == "if" => {
+        self.advance();
+        let condition = self.condition()?;
+        let then_items = self.module_body()?;
+        let else_items = match self.peek_word("else") {
+          true => {
+            self.advance();
+            match self.peek_word("if") {
+              // `else if` chains nest as a single conditional item in the else branch.
+              true  => vec![self.module_item()?],
+              false => self.module_body()?,
+            }
+          }
+          false => Vec::new(),
+        };
+        ModuleItem::If(condition, then_items, else_items)
+      }
+      Token::Word(_) => ModuleItem::Module(self.module()?),
+      _ => return self.unexpected("`requires`, `after`, `files`, `if` or a module name"),
+    };
+    Ok(self.located(item, start))
+  }
+
+  fn word_list(&mut self, expected: &str) -> Result<Vec<Located<String>>, LocatedProjectError> {
+    let mut words = vec![self.word(expected)?];
+    while *self.peek() == Token::Comma {
+      self.advance();
+      words.push(self.word(expected)?);
+    }
+    Ok(words)
+  }
+
+  fn file_entry(&mut self) -> Result<Located<FileEntry>, LocatedProjectError> {
+    let start = self.span();
+    let entry = match self.peek().clone() {
+      Token::Word(word) if word == "if" => {
+        self.advance();
+        let condition = self.condition()?;
+        match self.peek_word("then") {
+          true  => { self.advance(); }
+          false => return self.unexpected("`then`"),
+        }
+        let then_entry = Box::new(self.file_entry()?);
+        let else_entry = match self.peek_word("else") {
+          true => {
+            self.advance();
+            Some(Box::new(self.file_entry()?))
+          }
+          false => None,
+        };
+        FileEntry::If(condition, then_entry, else_entry)
+      }
+      Token::Word(word) if word == "error" => {
+        self.advance();
+        self.expect(Token::OpenParen)?;
+        let message = match self.peek().clone() {
+          Token::String(message) => {
+            self.advance();
+            message
+          }
+          _ => return self.unexpected("an error message string"),
+        };
+        self.expect(Token::CloseParen)?;
+        FileEntry::Error(message)
+      }
+      Token::Word(path) | Token::String(path) => {
+        self.advance();
+        FileEntry::Path(path)
+      }
+      _ => return self.unexpected("a file name"),
+    };
+    Ok(self.located(entry, start))
+  }
+
+  fn condition(&mut self) -> Result<Located<Condition>, LocatedProjectError> {
+    let mut left = self.conjunction()?;
+    while *self.peek() == Token::Or {
+      self.advance();
+      let right = self.conjunction()?;
+      left = self.join(left, right, Condition::Or);
+    }
+    Ok(left)
+  }
+
+  fn conjunction(&mut self) -> Result<Located<Condition>, LocatedProjectError> {
+    let mut left = self.negation()?;
+    while *self.peek() == Token::And {
+      self.advance();
+      let right = self.negation()?;
+      left = self.join(left, right, Condition::And);
+    }
+    Ok(left)
+  }
+
+  fn negation(&mut self) -> Result<Located<Condition>, LocatedProjectError> {
+    let start = self.span();
+    match self.peek() {
+      Token::Not => {
+        self.advance();
+        let inner = self.negation()?;
+        Ok(self.located(Condition::Not(Box::new(inner)), start))
+      }
+      Token::OpenParen => {
+        self.advance();
+        let inner = self.condition()?;
+        self.expect(Token::CloseParen)?;
+        Ok(inner)
+      }
+      _ => {
+        let left = self.value()?;
+        let condition = match self.peek() {
+          Token::Equal => {
+            self.advance();
+            Condition::Equal(left, self.value()?)
+          }
+          Token::NotEqual => {
+            self.advance();
+            Condition::NotEqual(left, self.value()?)
+          }
+          _ => Condition::Value(left),
+        };
+        Ok(self.located(condition, start))
+      }
+    }
+  }
+
+  fn located_value(&mut self) -> Result<Located<ProjectValue>, LocatedProjectError> {
+    let start = self.span();
+    let value = self.value()?;
+    Ok(self.located(value, start))
+  }
+
+  fn value(&mut self) -> Result<ProjectValue, LocatedProjectError> {
+    match self.peek().clone() {
+      Token::Dollar => {
+        self.advance();
+        Ok(ProjectValue::Variable(self.word("a variable name")?.value))
+      }
+      Token::Word(word) if word == "true"  => { self.advance(); Ok(ProjectValue::Bool(true)) }
+      Token::Word(word) if word == "false" => { self.advance(); Ok(ProjectValue::Bool(false)) }
+      Token::Word(word) | Token::String(word) => {
+        self.advance();
+        Ok(ProjectValue::String(word))
+      }
+      _ => self.unexpected("a value"),
+    }
+  }
+
+  fn join<F>(&self, left: Located<Condition>, right: Located<Condition>, constructor: F) -> Located<Condition>
+  where
+      F: FnOnce(Box<Located<Condition>>, Box<Located<Condition>>) -> Condition,
+  {
+    let location = match (&left.location, &right.location) {
+      (SourceLocation::Span(first), SourceLocation::Span(last)) => SourceLocation::Span(first.merge(*last)),
+      _ => left.location.clone(),
+    };
+    Located { location, value: constructor(Box::new(left), Box::new(right)) }
+  }
+
+  /// Locates `value` from `start` through the most recently consumed token.
+  fn located<T>(&self, value: T, start: Span) -> Located<T> {
+    let end = self.tokens[self.position.saturating_sub(1)].1;
+    Located { location: SourceLocation::Span(start.merge(end)), value }
+  }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use codemap::CodeMap;
+
+  fn parse(text: &str) -> Result<ProjectDescription, String> {
+    let file = CodeMap::new().add_file("test.sail_project".to_string(), text.to_string());
+    parse_project(&file).map_err(|error| error.value.to_string())
+  }
+
+  fn names(modules: &[ModuleDeclaration]) -> Vec<&str> {
+    modules.iter().map(|module| module.name.value.as_str()).collect()
+  }
+
+  #[test]
+  fn variables_modules_and_items() {
+    let project = parse(
+      "variable ARCH = RV64\n\
+       // The prelude comes first.\n\
+       prelude { files prelude.sail }\n\
+       core {\n\
+         requires prelude\n\
+         /* Chosen by $ARCH */\n\
+         files types.sail, if $ARCH == RV32 then xlen32.sail else xlen64.sail\n\
+       }\n\
+       extensions { vector { requires core after core files vext.sail } }",
+    )
+    .unwrap();
+
+    assert_eq!(project.variables.len(), 1);
+    assert_eq!(project.variables[0].0.value, "ARCH");
+    assert_eq!(project.variables[0].1.value, ProjectValue::String("RV64".to_string()));
+    assert_eq!(names(&project.modules), vec!["prelude", "core", "extensions"]);
+
+    let core: Vec<&ModuleItem> = project.modules[1].items.iter().map(|item| &item.value).collect();
+    assert!(matches!(core[0], ModuleItem::Requires(required) if required[0].value == "prelude"));
+    let ModuleItem::Files(files) = core[1] else { panic!("expected files, found {:?}", core[1]) };
+    assert_eq!(files[0].value, FileEntry::Path("types.sail".to_string()));
+    let FileEntry::If(condition, _, Some(_)) = &files[1].value else { panic!("expected a conditional file") };
+    assert_eq!(
+      condition.value,
+      Condition::Equal(ProjectValue::Variable("ARCH".to_string()), ProjectValue::String("RV32".to_string())),
+    );
+
+    let ModuleItem::Module(vector) = &project.modules[2].items[0].value else { panic!("expected a nested module") };
+    assert_eq!(vector.name.value, "vector");
+    assert_eq!(vector.items.len(), 3);
+  }
+
+  #[test]
+  fn conditions_combine() {
+    let project = parse("core { if !($A == x) && ($B != \"y\" || $C) { files a.sail } else { files b.sail } }").unwrap();
+    let ModuleItem::If(condition, then_items, else_items) = &project.modules[0].items[0].value else {
+      panic!("expected a conditional")
+    };
+    assert!(matches!(&condition.value, Condition::And(left, right)
+      if matches!(left.value, Condition::Not(_)) && matches!(right.value, Condition::Or(..))));
+    assert_eq!((then_items.len(), else_items.len()), (1, 1));
+  }
+
+  #[test]
+  fn malformed_projects_are_errors() {
+    assert_eq!(
+      parse("core { files a.sail").unwrap_err(),
+      "expected `requires`, `after`, `files`, `if` or a module name but found end of file",
+    );
+    assert_eq!(parse("core { files \"a.sail }").unwrap_err(), "unterminated string");
+    assert_eq!(parse("core { /* files a.sail }").unwrap_err(), "unterminated comment");
+  }
+}
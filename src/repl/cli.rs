@@ -0,0 +1,89 @@
+/*!
+
+The command line of `rigging repl`.
+
+```text
+rigging repl [<model>...]
+```
+
+The model is a list of Sail files, loaded in order, or a single `.sail_project` file, and may be left out to start from
+nothing. Inputs are read from standard input a line at a time, continuing onto the next line while brackets are open
+or the line ends with something that needs more, such as `=`. Results go to standard output and errors to standard
+error. `:quit` or the end of the input leaves.
+
+*/
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::project::SourceParser;
+use crate::repl::{incomplete, Repl};
+
+pub const USAGE: &str = "\
+usage: rigging repl [<model>...]
+
+Starts an interactive session with the Sail files <model>, or the project in a .sail_project file. Type :help in the
+session for the commands it takes.";
+
+/// Runs the session the command line `arguments`, without the program name and subcommand, asks for, parsing with
+/// `parser`.
+pub fn main<P: SourceParser>(parser: P, arguments: impl IntoIterator<Item = String>) -> ExitCode {
+  let mut model = Vec::new();
+  for argument in arguments {
+    match argument.as_str() {
+      "--help" | "-h" => {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+      }
+      option if option.starts_with('-') => {
+        eprintln!("rigging repl: unknown option {}\n{}", option, USAGE);
+        return ExitCode::FAILURE;
+      }
+      _ => model.push(PathBuf::from(argument)),
+    }
+  }
+
+  let mut repl = Repl::new(parser, model);
+  match repl.reload() {
+    Ok(message) => eprintln!("{}", message),
+    Err(message) => {
+      eprintln!("{}", message);
+      return ExitCode::FAILURE;
+    }
+  }
+
+  let stdin = std::io::stdin();
+  let mut lines = stdin.lock().lines();
+  loop {
+    let mut input = String::new();
+    let mut prompt = "sail> ";
+    loop {
+      print!("{}", prompt);
+      let _ = std::io::stdout().flush();
+      match lines.next() {
+        Some(Ok(line)) => input.push_str(&line),
+        Some(Err(error)) => {
+          eprintln!("rigging repl: {}", error);
+          return ExitCode::FAILURE;
+        }
+        None => return ExitCode::SUCCESS,
+      }
+      input.push('\n');
+      // Commands are a single line.
+      if input.trim_start().starts_with(':') || !incomplete(&input) {
+        break;
+      }
+      prompt = "  ... ";
+    }
+
+    match repl.input(&input) {
+      Ok(output) if output.is_empty() => {}
+      Ok(output) => println!("{}", output),
+      Err(message) => eprintln!("{}", message),
+    }
+    if repl.has_quit() {
+      return ExitCode::SUCCESS;
+    }
+  }
+}
@@ -0,0 +1,483 @@
+/*!
+
+An interactive Sail session, like the interactive mode of the OCaml Sail.
+
+A `Repl` holds a model, loaded and checked as the simulator loads one, an interpreter for it and the machine state it
+runs against. Each input is one of
+
+ * a top-level definition, or several, which are checked as if they followed the model and then added to it, so that
+   later inputs may use them,
+ * an expression, which is checked in the model's global environment and evaluated, and whose value is shown with its
+   type, or
+ * a command, starting with a colon:
+
+```text
+:type <expression>   show the type of <expression> without evaluating it
+:load <file>         add the definitions in <file>
+:reload              load the model again, then everything added since it was loaded
+:registers           show the registers, with their types and values
+:help                show this message
+:quit                leave
+```
+
+Inputs are named `<repl:n>` in locations. The crate parses only whole files, so an expression is parsed as the
+right-hand side of a top-level `let`. Definitions are told from expressions by the keyword they start with, except that
+an input starting with `let` is an expression if it parses as one, such as `let x = 1 in x + 1`.
+
+Registers live in a `SimpleEnvironment`, so that definitions may declare more of them, and memory reads as zero until
+written.
+
+*/
+
+pub mod cli;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use codemap::CodeMap;
+
+use crate::driver::{check_model, check_more, describe_location, Diagnostic};
+use crate::passes::effects::EffectSummary;
+use crate::passes::resolve::SymbolTable;
+use crate::interpreter::Interpreter;
+use crate::parser::ast::{Definition, Definitions, LetBinding, LocatedExpression};
+use crate::parser::ast_util::{declared_names, Namespace};
+use crate::parser::location::SourceLocation;
+use crate::passes::typecheck::{check_expression, Type, Typing};
+use crate::project::{Loader, ModuleSelection, SourceParser};
+use crate::runtime::{LocatedRuntimeError, SimpleEnvironment};
+
+pub const HELP: &str = "\
+<definition>         add a definition to the model
+<expression>         evaluate an expression, showing its value and type
+:type <expression>   show the type of <expression> without evaluating it
+:load <file>         add the definitions in <file>
+:reload              load the model again, then everything added since it was loaded
+:registers           show the registers, with their types and values
+:help                show this message
+:quit                leave";
+
+/// The keywords a top-level definition may start with.
+const DEFINITION_KEYWORDS: &[&str] = &[
+  "function", "val", "register", "type", "struct", "union", "enum", "bitfield", "newtype", "let", "overload", "mapping",
+  "default", "infix", "infixl", "infixr", "scattered", "end", "constraint", "termination_measure", "outcome",
+  "instantiation", "private",
+];
+
+pub struct Repl<P: SourceParser> {
+  loader     : Loader<P>,
+  /// The model's files, or its `.sail_project` file
+  model      : Vec<PathBuf>,
+  code_map   : CodeMap,
+  /// What checking the model learned beyond its types, which are the interpreter's
+  symbols    : SymbolTable,
+  effects    : EffectSummary,
+  interpreter: Interpreter,
+  environment: SimpleEnvironment,
+  /// The number of inputs so far, to name the next
+  inputs     : usize,
+  /// What has been added to the model, as `(name, source)`, in order
+  added      : Vec<(String, String)>,
+  quit       : bool,
+}
+
+impl<P: SourceParser> Repl<P> {
+  /// A session for the model in `model`, parsed with `parser`, which has yet to be loaded (see `reload`).
+  pub fn new(parser: P, model: Vec<PathBuf>) -> Self {
+    Repl {
+      loader     : Loader::new(parser),
+      model,
+      code_map   : CodeMap::new(),
+      symbols    : SymbolTable::default(),
+      effects    : EffectSummary::default(),
+      interpreter: Interpreter::new(&Definitions(Vec::new()), Typing::default()),
+      environment: SimpleEnvironment::default(),
+      inputs     : 0,
+      added      : Vec::new(),
+      quit       : false,
+    }
+  }
+
+  /// Runs one input, returning what to show, or what went wrong.
+  pub fn input(&mut self, text: &str) -> Result<String, String> {
+    let text = text.trim();
+    match text.strip_prefix(':') {
+      Some(command) => self.command(command),
+      None if text.is_empty() => Ok(String::new()),
+      None if is_definition(text) => {
+        let name = self.next_name();
+        if text.starts_with("let") {
+          if let Ok(expression) = self.parse_expression(&name, text) {
+            let typ = self.check_parsed(&expression)?;
+            return self.run(&expression, typ);
+          }
+        }
+        let result = self.define(&name, text);
+        if result.is_ok() {
+          self.added.push((name, text.to_string()));
+        }
+        result
+      }
+      None => self.evaluate(text),
+    }
+  }
+
+  fn command(&mut self, command: &str) -> Result<String, String> {
+    let (name, argument) = command.split_once(char::is_whitespace).map(|(name, rest)| (name, rest.trim())).unwrap_or((command, ""));
+    match name {
+      "type" | "t" => {
+        let (_, typ) = self.check(argument)?;
+        Ok(format!("{} : {}", argument, typ))
+      }
+      "load" | "l" => {
+        let source = std::fs::read_to_string(argument).map_err(|error| format!("{}: {}", argument, error))?;
+        let result = self.define(argument, &source);
+        if result.is_ok() {
+          self.added.push((argument.to_string(), source));
+        }
+        result
+      }
+      "reload" | "r" => self.reload(),
+      "registers" => Ok(self.registers().join("\n")),
+      "help" | "h" | "?" => Ok(HELP.to_string()),
+      "quit" | "q" => {
+        self.quit = true;
+        Ok(String::new())
+      }
+      _ => Err(format!("unknown command :{}; try :help", name)),
+    }
+  }
+
+  /// Whether `:quit` has been input.
+  pub fn has_quit(&self) -> bool {
+    self.quit
+  }
+
+  /// Loads the model, replacing everything but what has been added since, which is then added again.
+  pub fn reload(&mut self) -> Result<String, String> {
+    let project = match self.model.as_slice() {
+      [project] if project.extension().is_some_and(|extension| extension == "sail_project") => {
+        self.loader.load_project(project, &ModuleSelection::new())
+      }
+      model => self.loader.load_files(model),
+    };
+    let (model, diagnostics) = check_model(project);
+    let mut messages: Vec<String> = diagnostics.iter().map(|diagnostic| diagnostic.describe(&model.code_map)).collect();
+    if diagnostics.iter().any(|diagnostic| diagnostic.fatal) {
+      return Err(messages.join("\n"));
+    }
+
+    let mut interpreter = Interpreter::new(&model.definitions, model.typing);
+    let mut environment = SimpleEnvironment::default();
+    if let Err(error) = interpreter.initialise(&mut environment) {
+      return Err(describe_error(&model.code_map, &error));
+    }
+    self.code_map = model.code_map;
+    self.symbols = model.symbols;
+    self.effects = model.effects;
+    self.interpreter = interpreter;
+    self.environment = environment;
+
+    let files = model.definitions.0.len();
+    for (name, source) in std::mem::take(&mut self.added) {
+      match self.define(&name, &source) {
+        Ok(_) => self.added.push((name, source)),
+        Err(message) => messages.push(message),
+      }
+    }
+    messages.push(format!("loaded {} files and {} additions", files, self.added.len()));
+    Ok(messages.join("\n"))
+  }
+
+  /// Adds the definitions in `source`, describing what they declare.
+  fn define(&mut self, name: &str, source: &str) -> Result<String, String> {
+    let file = self.code_map.add_file(name.to_string(), source.to_string());
+    let parsed = self.loader.parser().parse(&file).map_err(|errors| {
+      let diagnostics = errors.into_iter().map(|error| Diagnostic::from_error(error, true)).collect::<Vec<_>>();
+      self.describe(&diagnostics)
+    })?;
+
+    // A definition that fails to check or to initialise leaves the model as it was. The interpreter keeps its
+    // functions, but nothing that checks can call them.
+    let saved = (self.symbols.clone(), self.interpreter.typing().clone(), self.effects.clone());
+    let (definitions, diagnostics) = check_more(
+      &mut self.symbols,
+      self.interpreter.typing_mut(),
+      &mut self.effects,
+      Definitions(vec![(name.to_string(), parsed)]),
+    );
+    let extended = match diagnostics.iter().any(|diagnostic| diagnostic.fatal) {
+      true => Err(self.describe(&diagnostics)),
+      false => self.interpreter
+          .extend(&definitions, &mut self.environment)
+          .map_err(|error| describe_error(&self.code_map, &error)),
+    };
+    if let Err(message) = extended {
+      let (symbols, typing, effects) = saved;
+      self.symbols = symbols;
+      *self.interpreter.typing_mut() = typing;
+      self.effects = effects;
+      return Err(message);
+    }
+
+    let mut lines: Vec<String> = diagnostics.iter().map(|diagnostic| diagnostic.describe(&self.code_map)).collect();
+    let mut seen = HashSet::new();
+    for definition in definitions.0.iter().flat_map(|(_, file)| file) {
+      for (namespace, name) in declared_names(definition) {
+        if seen.insert((namespace, name.name().to_string())) {
+          lines.push(self.describe_name(namespace, name.name()));
+        }
+      }
+    }
+    Ok(lines.join("\n"))
+  }
+
+  /// What a definition declared under `name`, as it would be shown.
+  fn describe_name(&mut self, namespace: Namespace, name: &str) -> String {
+    let environment = &self.interpreter.typing().environment;
+    if namespace == Namespace::Type {
+      return format!("type {}", name);
+    }
+    if let Some(function) = environment.functions.get(name) {
+      let typ = Type::Function(function.arguments.clone(), Box::new(function.result.clone()));
+      return format!("{} : {}", name, typ);
+    }
+    if let Some(typ) = environment.registers.get(name).cloned() {
+      return match self.environment.registers.get(name) {
+        Some(value) => format!("register {} : {} = {}", name, typ, value),
+        None => format!("register {} : {}", name, typ),
+      };
+    }
+    match (environment.values.get(name), self.interpreter.global(name)) {
+      (Some(typ), Some(value)) => format!("{} : {} = {}", name, typ, value),
+      (Some(typ), None) => format!("{} : {}", name, typ),
+      _ => name.to_string(),
+    }
+  }
+
+  fn evaluate(&mut self, text: &str) -> Result<String, String> {
+    let (expression, typ) = self.check(text)?;
+    self.run(&expression, typ)
+  }
+
+  /// Evaluates a checked expression of type `typ`.
+  fn run(&mut self, expression: &LocatedExpression, typ: Type) -> Result<String, String> {
+    match self.interpreter.evaluate(expression, Vec::new(), &mut self.environment) {
+      Ok(value) => Ok(format!("{} : {}", value, typ)),
+      Err(error) => Err(describe_error(&self.code_map, &error)),
+    }
+  }
+
+  /// Parses and checks an expression.
+  fn check(&mut self, text: &str) -> Result<(LocatedExpression, Type), String> {
+    if text.is_empty() {
+      return Err("no expression given".to_string());
+    }
+    let name = self.next_name();
+    let expression = self.parse_expression(&name, text)?;
+    let typ = self.check_parsed(&expression)?;
+    Ok((expression, typ))
+  }
+
+  /// Parses an expression, as the input `name`.
+  fn parse_expression(&mut self, name: &str, text: &str) -> Result<LocatedExpression, String> {
+    let file = self.code_map.add_file(name.to_string(), format!("let _ = {}", text));
+    let parsed = self.loader.parser().parse(&file).map_err(|errors| {
+      let diagnostics = errors.into_iter().map(|error| Diagnostic::from_error(error, true)).collect::<Vec<_>>();
+      self.describe(&diagnostics)
+    })?;
+    match parsed.as_slice() {
+      [definition] => match &definition.value {
+        Definition::ValueDefinition(binding) => {
+          let LetBinding::ValueBinding(_, expression) = &binding.value;
+          Ok((**expression).clone())
+        }
+        _ => Err(format!("`{}` is not an expression", text)),
+      },
+      _ => Err(format!("`{}` is not an expression", text)),
+    }
+  }
+
+  /// Checks a parsed expression in the model's global environment, returning its type.
+  fn check_parsed(&mut self, expression: &LocatedExpression) -> Result<Type, String> {
+    let (typ, errors) = check_expression(self.interpreter.typing_mut(), expression, None);
+    if !errors.is_empty() {
+      let diagnostics = errors.into_iter().map(|error| Diagnostic::from_error(error, true)).collect::<Vec<_>>();
+      return Err(self.describe(&diagnostics));
+    }
+    Ok(typ)
+  }
+
+  /// The registers, by name, with their types and values.
+  pub fn registers(&self) -> Vec<String> {
+    let mut registers: Vec<(&String, &Type)> = self.interpreter.typing().environment.registers.iter().collect();
+    registers.sort_by_key(|(name, _)| *name);
+    registers
+        .into_iter()
+        .map(|(name, typ)| match self.environment.registers.get(name) {
+          Some(value) => format!("{} : {} = {}", name, typ, value),
+          None => format!("{} : {}", name, typ),
+        })
+        .collect()
+  }
+
+  fn next_name(&mut self) -> String {
+    self.inputs += 1;
+    format!("<repl:{}>", self.inputs)
+  }
+
+  fn describe(&self, diagnostics: &[Diagnostic]) -> String {
+    diagnostics.iter().map(|diagnostic| diagnostic.describe(&self.code_map)).collect::<Vec<_>>().join("\n")
+  }
+
+  pub fn interpreter(&self) -> &Interpreter {
+    &self.interpreter
+  }
+
+  pub fn environment_mut(&mut self) -> &mut SimpleEnvironment {
+    &mut self.environment
+  }
+}
+
+fn describe_error(code_map: &CodeMap, error: &LocatedRuntimeError) -> String {
+  match (&error.location, describe_location(code_map, &error.location)) {
+    (SourceLocation::Unknown, _) | (_, None) => error.value.to_string(),
+    (_, Some(location)) => format!("{}: {}", location, error.value),
+  }
+}
+
+/// Whether an input is a top-level definition rather than an expression.
+pub fn is_definition(text: &str) -> bool {
+  let word: String = text.chars().take_while(|character| character.is_alphanumeric() || *character == '_').collect();
+  DEFINITION_KEYWORDS.contains(&word.as_str()) || text.starts_with('$') || text.starts_with("/*!")
+}
+
+/// Whether an input needs more lines: it has unclosed brackets, or its last line ends with something that must be
+/// followed by more.
+pub fn incomplete(text: &str) -> bool {
+  let mut depth = 0i64;
+  let mut in_string = false;
+  let mut escaped = false;
+  for character in text.chars() {
+    match character {
+      _ if escaped => escaped = false,
+      '\\' if in_string => escaped = true,
+      '"' => in_string = !in_string,
+      '(' | '[' | '{' if !in_string => depth += 1,
+      ')' | ']' | '}' if !in_string => depth -= 1,
+      _ => {}
+    }
+  }
+  let last = text.trim_end();
+  depth > 0 || in_string || ["=", "->", "=>", ",", "then", "else", "match", "|"].iter().any(|end| last.ends_with(end))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use codemap::File;
+
+  use crate::parser::ast::LocatedDefinition;
+  use crate::parser::errors::{LocatedParseError, ParserError};
+  use crate::parser::location::Located;
+  use crate::parser::testing::*;
+
+  /// Stands in for the parser, knowing only the sources the tests input.
+  fn parse(file: &File) -> Result<Vec<LocatedDefinition>, Vec<LocatedParseError>> {
+    let definition = match file.source() {
+      "register R : int = 1" => register("R", typ("int"), Some(number(1))),
+      "register S : int = { assert(false, \"S\"); 1 }" => {
+        register("S", typ("int"), Some(block(vec![assertion(boolean(false), "S"), number(1)])))
+      }
+      "val f : int -> int" => val("f", function_type(vec![typ("int")], typ("int"))),
+      "function f(x) = x" => function("f", vec![pattern("x")], var("x")),
+      "function g(x) = h(x)" => function("g", vec![pattern("x")], call("h", vec![var("x")])),
+      "let y = 2" => value(pattern("y"), number(2)),
+      "let _ = R" => value(wildcard(), var("R")),
+      "let _ = S" => value(wildcard(), var("S")),
+      "let _ = y" => value(wildcard(), var("y")),
+      "let _ = f(R)" => value(wildcard(), call("f", vec![var("R")])),
+      "let _ = g(1)" => value(wildcard(), call("g", vec![number(1)])),
+      "let _ = let x = 3 in x" => value(wildcard(), let_in(pattern("x"), number(3), var("x"))),
+      _ => return Err(vec![Located { location: SourceLocation::Unknown, value: ParserError::UnknownOperator }]),
+    };
+    Ok(vec![definition])
+  }
+
+  type Parser = fn(&File) -> Result<Vec<LocatedDefinition>, Vec<LocatedParseError>>;
+
+  fn repl() -> Repl<Parser> {
+    let mut repl = Repl::new(parse as Parser, Vec::new());
+    repl.reload().expect("an empty model loads");
+    repl
+  }
+
+  #[test]
+  fn definitions_are_added_to_the_model() {
+    let mut repl = repl();
+    assert_eq!(repl.input("register R : int = 1"), Ok("register R : {'#n. int('#n)} = 1".to_string()));
+    assert_eq!(repl.input("val f : int -> int"), Ok("f : ({'#n. int('#n)}) -> {'#n. int('#n)}".to_string()));
+    repl.input("function f(x) = x").unwrap();
+    assert_eq!(repl.input("f(R)"), Ok("1 : {'#n. int('#n)}".to_string()));
+    assert_eq!(repl.input(":type f(R)"), Ok("f(R) : {'#n. int('#n)}".to_string()));
+  }
+
+  #[test]
+  fn let_starts_an_expression_or_a_definition() {
+    let mut repl = repl();
+    assert_eq!(repl.input("let x = 3 in x"), Ok("3 : int(3)".to_string()));
+    repl.input("let y = 2").unwrap();
+    assert_eq!(repl.input("y"), Ok("2 : int(2)".to_string()));
+  }
+
+  #[test]
+  fn failed_definitions_leave_the_model_as_it_was() {
+    let mut repl = repl();
+    assert!(repl.input("function g(x) = h(x)").is_err());
+    assert!(repl.input("g(1)").is_err());
+    assert!(repl.input("register S : int = { assert(false, \"S\"); 1 }").is_err());
+    assert!(repl.input("S").is_err());
+    assert!(repl.registers().is_empty());
+  }
+
+  #[test]
+  fn reloading_adds_additions_again() {
+    let mut repl = repl();
+    repl.input("register R : int = 1").unwrap();
+    assert!(repl.input("function g(x) = h(x)").is_err());
+    assert_eq!(repl.input(":reload"), Ok("loaded 0 files and 1 additions".to_string()));
+    assert_eq!(repl.input("R"), Ok("1 : {'#n. int('#n)}".to_string()));
+  }
+
+  #[test]
+  fn quitting() {
+    let mut repl = repl();
+    assert_eq!(repl.input(":quit"), Ok(String::new()));
+    assert!(repl.has_quit());
+  }
+
+  #[test]
+  fn help_lists_every_command() {
+    assert_eq!(repl().input(":help"), Ok(HELP.to_string()));
+    for line in HELP.lines().filter(|line| line.starts_with(':')) {
+      let command = line.split_whitespace().next().unwrap();
+      let result = repl().input(command);
+      assert!(!matches!(&result, Err(message) if message.starts_with("unknown command")), "{}", command);
+    }
+  }
+
+  #[test]
+  fn classifies_inputs() {
+    assert!(is_definition("function f(x) = x + 1"));
+    assert!(is_definition("register R : bits(8) = 0x00"));
+    assert!(is_definition("$include <prelude.sail>"));
+    assert!(!is_definition("f(3)"));
+    assert!(!is_definition("letter"));
+
+    assert!(incomplete("function f(x) = {"));
+    assert!(incomplete("let x ="));
+    assert!(incomplete("\"a (string"));
+    assert!(!incomplete("f(\"(\")"));
+    assert!(!incomplete("{ let x = 1; x }"));
+  }
+}
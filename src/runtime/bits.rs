@@ -0,0 +1,342 @@
+/*!
+
+Bitvectors of any length.
+
+A `Bits` is a sequence of bits numbered by position, position 0 being the least significant, and is stored as 64-bit
+words, least significant first. Positions are independent of the program's default order: with `dec`, as in almost every
+specification, index `i` of a vector is position `i`, and with `inc` it is position `length - 1 - i`. Callers translate.
+
+Bits of the last word beyond the length are always zero, so that two bitvectors of the same length and bits compare equal.
+
+*/
+
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::abstractions::{ArithmeticError, BigInteger, Integer};
+
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct Bits {
+  length: usize,
+  words : Vec<u64>,
+}
+
+/// The number of words holding `length` bits.
+fn word_count(length: usize) -> usize {
+  length.div_ceil(64)
+}
+
+impl Bits {
+  pub fn zeros(length: usize) -> Bits {
+    Bits { length, words: vec![0; word_count(length)] }
+  }
+
+  pub fn ones(length: usize) -> Bits {
+    let mut bits = Bits { length, words: vec![u64::MAX; word_count(length)] };
+    bits.normalise();
+    bits
+  }
+
+  /// The low `length` bits of `value`.
+  pub fn from_u64(value: u64, length: usize) -> Bits {
+    let mut bits = Bits::zeros(length);
+    if let Some(word) = bits.words.first_mut() {
+      *word = value;
+    }
+    bits.normalise();
+    bits
+  }
+
+  /// The low `length` bits of the two's complement representation of `value`, as Sail's `to_bits` and
+  /// `get_slice_int` take them.
+  pub fn from_integer(value: &BigInteger, length: usize) -> Result<Bits, ArithmeticError> {
+    let base = BigInteger::try_pow2(32)?;
+    let mut bits = Bits::zeros(length);
+    let mut rest = value.clone();
+    for index in 0..word_count(length) * 2 {
+      // Euclidean division by a positive base rounds toward negative infinity, so negative values sign extend.
+      let chunk = rest.try_emod(&base)?.to_i64().unwrap_or(0) as u64;
+      bits.words[index / 2] |= chunk << (32 * (index % 2));
+      rest = rest.try_ediv(&base)?;
+    }
+    bits.normalise();
+    Ok(bits)
+  }
+
+  /// Reads binary digits, most significant first, ignoring any `0b` prefix and `_` separators.
+  pub fn parse_binary(text: &str) -> Option<Bits> {
+    let digits: Vec<char> = text.trim_start_matches("0b").chars().filter(|c| *c != '_').collect();
+    let mut bits = Bits::zeros(digits.len());
+    for (position, digit) in digits.iter().rev().enumerate() {
+      match digit {
+        '0' => {}
+        '1' => bits.set(position, true),
+        _ => return None,
+      }
+    }
+    Some(bits)
+  }
+
+  /// Reads hexadecimal digits, most significant first, ignoring any `0x` prefix and `_` separators. Each digit is four
+  /// bits.
+  pub fn parse_hexadecimal(text: &str) -> Option<Bits> {
+    let digits: Vec<char> = text.trim_start_matches("0x").chars().filter(|c| *c != '_').collect();
+    let mut bits = Bits::zeros(digits.len() * 4);
+    for (index, digit) in digits.iter().rev().enumerate() {
+      let value = digit.to_digit(16)?;
+      for offset in 0..4 {
+        bits.set(index * 4 + offset, value >> offset & 1 == 1);
+      }
+    }
+    Some(bits)
+  }
+
+  /// Bits from bytes in little-endian order, the first byte the least significant.
+  pub fn from_bytes_le(bytes: &[u8]) -> Bits {
+    let mut bits = Bits::zeros(bytes.len() * 8);
+    for (index, byte) in bytes.iter().enumerate() {
+      bits.words[index / 8] |= (*byte as u64) << (8 * (index % 8));
+    }
+    bits
+  }
+
+  /// The bytes of a bitvector whose length is a multiple of eight, least significant first.
+  pub fn to_bytes_le(&self) -> Vec<u8> {
+    (0..self.length / 8).map(|index| (self.words[index / 8] >> (8 * (index % 8))) as u8).collect()
+  }
+
+  pub fn len(&self) -> usize {
+    self.length
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.length == 0
+  }
+
+  /// The words holding the bits, least significant first.
+  pub fn words(&self) -> &[u64] {
+    &self.words
+  }
+
+  /// Clears the bits of the last word beyond the length.
+  fn normalise(&mut self) {
+    let used = self.length % 64;
+    if used != 0 {
+      if let Some(last) = self.words.last_mut() {
+        *last &= (1u64 << used) - 1;
+      }
+    }
+  }
+
+  pub fn get(&self, position: usize) -> bool {
+    position < self.length && self.words[position / 64] >> (position % 64) & 1 == 1
+  }
+
+  /// Sets the bit at `position`, which must be within the length.
+  pub fn set(&mut self, position: usize, value: bool) {
+    let mask = 1u64 << (position % 64);
+    match value {
+      true => self.words[position / 64] |= mask,
+      false => self.words[position / 64] &= !mask,
+    }
+  }
+
+  /// The `width` bits from position `low` up.
+  pub fn slice(&self, low: usize, width: usize) -> Bits {
+    let mut result = Bits::zeros(width);
+    for offset in 0..width {
+      if self.get(low + offset) {
+        result.set(offset, true);
+      }
+    }
+    result
+  }
+
+  /// A copy with the bits from position `low` up replaced by `value`.
+  pub fn with_slice(&self, low: usize, value: &Bits) -> Bits {
+    let mut result = self.clone();
+    for offset in 0..value.length.min(self.length.saturating_sub(low)) {
+      result.set(low + offset, value.get(offset));
+    }
+    result
+  }
+
+  /// `self @ low`: the bits of `self` above those of `low`.
+  pub fn append(&self, low: &Bits) -> Bits {
+    let mut result = low.clone();
+    result.length += self.length;
+    result.words.resize(word_count(result.length), 0);
+    for position in 0..self.length {
+      if self.get(position) {
+        result.set(low.length + position, true);
+      }
+    }
+    result
+  }
+
+  /// `self` extended to `length` bits with copies of its most significant bit if `signed`, or else with zeros.
+  pub fn extend(&self, length: usize, signed: bool) -> Bits {
+    let mut result = self.slice(0, length);
+    if signed && !self.is_empty() && self.get(self.length - 1) {
+      for position in self.length..length {
+        result.set(position, true);
+      }
+    }
+    result
+  }
+
+  /// `self + other` modulo two to the length, for bitvectors of the same length.
+  pub fn wrapping_add(&self, other: &Bits) -> Bits {
+    let mut carry = false;
+    let words = self
+        .words
+        .iter()
+        .zip(&other.words)
+        .map(|(a, b)| {
+          let (sum, first) = a.overflowing_add(*b);
+          let (sum, second) = sum.overflowing_add(carry as u64);
+          carry = first || second;
+          sum
+        })
+        .collect();
+    let mut result = Bits { length: self.length.min(other.length), words };
+    result.normalise();
+    result
+  }
+
+  /// `self - other` modulo two to the length, for bitvectors of the same length.
+  pub fn wrapping_sub(&self, other: &Bits) -> Bits {
+    self.wrapping_add(&other.not()).wrapping_add(&Bits::from_u64(1, self.length))
+  }
+
+  pub fn shift_left(&self, amount: usize) -> Bits {
+    let mut result = Bits::zeros(self.length);
+    for position in amount..self.length {
+      result.set(position, self.get(position - amount));
+    }
+    result
+  }
+
+  /// Shifts right, filling with copies of the most significant bit if `arithmetic`, or else with zeros.
+  pub fn shift_right(&self, amount: usize, arithmetic: bool) -> Bits {
+    let fill = arithmetic && !self.is_empty() && self.get(self.length - 1);
+    let mut result = Bits::zeros(self.length);
+    for position in 0..self.length {
+      let bit = match position.checked_add(amount) {
+        Some(source) if source < self.length => self.get(source),
+        _ => fill,
+      };
+      result.set(position, bit);
+    }
+    result
+  }
+
+  /// The number of zeros above the most significant one.
+  pub fn leading_zeros(&self) -> usize {
+    (0..self.length).rev().take_while(|position| !self.get(*position)).count()
+  }
+
+  /// The value of the bits as an unsigned integer, if it fits.
+  pub fn to_u64(&self) -> Option<u64> {
+    match self.words.iter().skip(1).all(|word| *word == 0) {
+      true => Some(self.words.first().copied().unwrap_or(0)),
+      false => None,
+    }
+  }
+
+  /// Sail's `unsigned`.
+  pub fn to_unsigned(&self) -> Result<BigInteger, ArithmeticError> {
+    let mut value = BigInteger::from_i64(0);
+    for word in self.words.iter().rev() {
+      for half in [word >> 32, word & 0xffff_ffff] {
+        value = value.try_shl(32)?.try_add(&BigInteger::from_i64(half as i64))?;
+      }
+    }
+    Ok(value)
+  }
+
+  /// Sail's `signed`, reading the most significant bit as the sign.
+  pub fn to_signed(&self) -> Result<BigInteger, ArithmeticError> {
+    match self.length > 0 && self.get(self.length - 1) {
+      // -x - 1 is the complement of x.
+      true => self.not().to_unsigned()?.try_neg()?.try_sub(&BigInteger::from_i64(1)),
+      false => self.to_unsigned(),
+    }
+  }
+
+  pub fn not(&self) -> Bits {
+    let mut result = Bits { length: self.length, words: self.words.iter().map(|word| !word).collect() };
+    result.normalise();
+    result
+  }
+
+  /// Combines two bitvectors of the same length bit by bit.
+  pub fn zip_with(&self, other: &Bits, f: impl Fn(u64, u64) -> u64) -> Bits {
+    let words = self.words.iter().zip(&other.words).map(|(a, b)| f(*a, *b)).collect();
+    let mut result = Bits { length: self.length.min(other.length), words };
+    result.normalise();
+    result
+  }
+}
+
+impl Display for Bits {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    if self.length > 0 && self.length.is_multiple_of(4) {
+      write!(f, "0x")?;
+      for digit in (0..self.length / 4).rev() {
+        let value = (0..4).fold(0, |value, offset| value | (self.get(digit * 4 + offset) as u32) << offset);
+        write!(f, "{}", char::from_digit(value, 16).unwrap_or('?').to_ascii_uppercase())?;
+      }
+      return Ok(());
+    }
+    write!(f, "0b")?;
+    for position in (0..self.length).rev() {
+      write!(f, "{}", self.get(position) as u8)?;
+    }
+    Ok(())
+  }
+}
+
+impl Debug for Bits {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    Display::fmt(self, f)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn int(value: i64) -> BigInteger {
+    BigInteger::from_i64(value)
+  }
+
+  #[test]
+  fn integers_round_trip() {
+    let bits = Bits::from_integer(&int(-3), 70).unwrap();
+    assert_eq!(bits.len(), 70);
+    assert_eq!(bits.to_signed(), Ok(int(-3)));
+    assert!(bits.get(69));
+    assert_eq!(Bits::from_integer(&int(0x1ff), 8).unwrap().to_unsigned(), Ok(int(0xff)));
+  }
+
+  #[test]
+  fn slices_and_appends() {
+    let bits = Bits::parse_hexadecimal("0xA5").unwrap();
+    assert_eq!(bits.slice(4, 4).to_string(), "0xA");
+    assert_eq!(bits.slice(0, 3).to_string(), "0b101");
+    assert_eq!(Bits::parse_binary("0b11").unwrap().append(&bits).to_string(), "0b1110100101");
+    assert_eq!(bits.with_slice(0, &Bits::zeros(4)).to_string(), "0xA0");
+    assert_eq!(bits.not().to_string(), "0x5A");
+  }
+
+  #[test]
+  fn arithmetic_and_shifts() {
+    let a = Bits::from_u64(u64::MAX, 70);
+    assert_eq!(a.wrapping_add(&Bits::from_u64(1, 70)).to_unsigned(), BigInteger::try_pow2(64));
+    assert_eq!(Bits::zeros(8).wrapping_sub(&Bits::from_u64(1, 8)).to_string(), "0xFF");
+    assert_eq!(Bits::from_u64(0x81, 8).shift_right(1, true).to_string(), "0xC0");
+    assert_eq!(Bits::from_u64(0x81, 8).shift_left(1).to_string(), "0x02");
+    assert_eq!(Bits::from_u64(0x8, 4).extend(8, true).to_string(), "0xF8");
+    assert_eq!(Bits::from_u64(0x10, 8).leading_zeros(), 3);
+  }
+}
@@ -0,0 +1,300 @@
+/*!
+
+Loading ELF executables into memory.
+
+The loader copies each loadable segment to its physical address, zeroing what the segment occupies beyond its bytes in
+the file, and reads the symbol table, so that a harness can find symbols such as `tohost`. It reads 32- and 64-bit files
+of either byte order, and does no relocation.
+
+*/
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::runtime::{Endianness, Memory, MemoryError};
+
+const LOADABLE: u32 = 1;
+const SYMBOL_TABLE: u32 = 2;
+
+/// The most memory a segment may occupy.
+const MAX_SEGMENT: u64 = 1 << 32;
+/// How much of a segment's zeroed memory is written at once.
+const PAGE: usize = 4096;
+
+/// What the loader learned about an executable it loaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElfImage {
+  pub entry     : u64,
+  /// Whether the file is a 64-bit one
+  pub wide      : bool,
+  pub endianness: Endianness,
+  /// The address and size in memory of each loaded segment
+  pub segments  : Vec<(u64, u64)>,
+  pub symbols   : HashMap<String, u64>,
+}
+
+impl ElfImage {
+  pub fn symbol(&self, name: &str) -> Option<u64> {
+    self.symbols.get(name).copied()
+  }
+}
+
+/// Loads the ELF file at `path` into `memory`.
+pub fn load_elf(path: impl AsRef<Path>, memory: &mut dyn Memory) -> Result<ElfImage, MemoryError> {
+  let path = path.as_ref();
+  let bytes = std::fs::read(path).map_err(|error| MemoryError::Io(format!("cannot read {}: {}", path.display(), error)))?;
+  load_elf_bytes(&bytes, memory)
+}
+
+/// Loads the ELF file held in `bytes` into `memory`.
+pub fn load_elf_bytes(bytes: &[u8], memory: &mut dyn Memory) -> Result<ElfImage, MemoryError> {
+  let file = ElfFile::new(bytes)?;
+  let mut image = ElfImage { wide: file.wide, endianness: file.endianness, ..ElfImage::default() };
+  image.entry = file.word(24)?;
+
+  let (program_headers, program_header_size, program_header_count) = match file.wide {
+    true => (file.word(32)?, file.half(54)?, file.half(56)?),
+    false => (file.word(28)?, file.half(42)?, file.half(44)?),
+  };
+  for index in 0..program_header_count as u64 {
+    let header = file.entry(program_headers, index, program_header_size as u64)?;
+    if file.u32(header)? != LOADABLE {
+      continue;
+    }
+    let (offset, address, file_size, memory_size) = match file.wide {
+      true => (file.word(header + 8)?, file.word(header + 24)?, file.word(header + 32)?, file.word(header + 40)?),
+      false => (file.word(header + 4)?, file.word(header + 12)?, file.word(header + 16)?, file.word(header + 20)?),
+    };
+    if file_size > memory_size {
+      return Err(MemoryError::InvalidElf("a segment is larger in the file than in memory".to_string()));
+    }
+    let end = match address.checked_add(memory_size) {
+      Some(end) if memory_size <= MAX_SEGMENT => end,
+      _ => return Err(MemoryError::InvalidElf("a segment does not fit in memory".to_string())),
+    };
+    memory.write_bytes(address, file.bytes(offset, file_size)?)?;
+    let zeros = [0; PAGE];
+    let mut next = address + file_size;
+    while next < end {
+      let length = (end - next).min(PAGE as u64);
+      memory.write_bytes(next, &zeros[..length as usize])?;
+      next += length;
+    }
+    image.segments.push((address, memory_size));
+  }
+
+  image.symbols = file.symbols()?;
+  Ok(image)
+}
+
+/// The bytes of an ELF file, and how to read the fields of its headers.
+struct ElfFile<'a> {
+  bytes     : &'a [u8],
+  wide      : bool,
+  endianness: Endianness,
+}
+
+impl<'a> ElfFile<'a> {
+  fn new(bytes: &'a [u8]) -> Result<Self, MemoryError> {
+    if bytes.len() < 16 || bytes[..4] != [0x7f, b'E', b'L', b'F'] {
+      return Err(MemoryError::InvalidElf("not an ELF file".to_string()));
+    }
+    let wide = match bytes[4] {
+      1 => false,
+      2 => true,
+      _ => return Err(MemoryError::InvalidElf("unknown class".to_string())),
+    };
+    let endianness = match bytes[5] {
+      1 => Endianness::Little,
+      2 => Endianness::Big,
+      _ => return Err(MemoryError::InvalidElf("unknown byte order".to_string())),
+    };
+    Ok(ElfFile { bytes, wide, endianness })
+  }
+
+  fn bytes(&self, offset: u64, length: u64) -> Result<&'a [u8], MemoryError> {
+    offset
+      .checked_add(length)
+      .and_then(|end| self.bytes.get(offset as usize..end as usize))
+      .ok_or_else(|| MemoryError::InvalidElf("truncated file".to_string()))
+  }
+
+  fn unsigned(&self, offset: u64, length: u64) -> Result<u64, MemoryError> {
+    let bytes = self.bytes(offset, length)?;
+    let fold = |value: u64, byte: &u8| value << 8 | *byte as u64;
+    Ok(match self.endianness {
+      Endianness::Little => bytes.iter().rev().fold(0, fold),
+      Endianness::Big => bytes.iter().fold(0, fold),
+    })
+  }
+
+  /// The offset of entry `index` of the table at `offset` whose entries are `size` bytes, which must be in the file.
+  fn entry(&self, offset: u64, index: u64, size: u64) -> Result<u64, MemoryError> {
+    index
+      .checked_mul(size)
+      .and_then(|start| start.checked_add(offset))
+      .filter(|start| start.checked_add(size).is_some_and(|end| end <= self.bytes.len() as u64))
+      .ok_or_else(|| MemoryError::InvalidElf("a header table extends beyond the file".to_string()))
+  }
+
+  fn half(&self, offset: u64) -> Result<u16, MemoryError> {
+    Ok(self.unsigned(offset, 2)? as u16)
+  }
+
+  fn u32(&self, offset: u64) -> Result<u32, MemoryError> {
+    Ok(self.unsigned(offset, 4)? as u32)
+  }
+
+  /// An address or offset, whose size depends on the class of the file.
+  fn word(&self, offset: u64) -> Result<u64, MemoryError> {
+    self.unsigned(offset, if self.wide { 8 } else { 4 })
+  }
+
+  /// The null-terminated string at `offset` in the string table at `table`.
+  fn string(&self, table: u64, offset: u64) -> Result<String, MemoryError> {
+    let rest = table
+      .checked_add(offset)
+      .and_then(|start| self.bytes.get(start as usize..))
+      .ok_or_else(|| MemoryError::InvalidElf("truncated file".to_string()))?;
+    let length = rest.iter().position(|byte| *byte == 0).unwrap_or(rest.len());
+    Ok(String::from_utf8_lossy(&rest[..length]).into_owned())
+  }
+
+  /// The section headers, as offsets into the file.
+  fn sections(&self) -> Result<Vec<u64>, MemoryError> {
+    let (offset, size, count) = match self.wide {
+      true => (self.word(40)?, self.half(58)?, self.half(60)?),
+      false => (self.word(32)?, self.half(46)?, self.half(48)?),
+    };
+    (0..count as u64).map(|index| self.entry(offset, index, size as u64)).collect()
+  }
+
+  /// The offset and size of a section.
+  fn section_contents(&self, header: u64) -> Result<(u64, u64), MemoryError> {
+    match self.wide {
+      true => Ok((self.word(header + 24)?, self.word(header + 32)?)),
+      false => Ok((self.word(header + 16)?, self.word(header + 20)?)),
+    }
+  }
+
+  /// The named symbols of every symbol table.
+  fn symbols(&self) -> Result<HashMap<String, u64>, MemoryError> {
+    let sections = self.sections()?;
+    let mut symbols = HashMap::new();
+    for &header in &sections {
+      if self.u32(header + 4)? != SYMBOL_TABLE {
+        continue;
+      }
+      let (offset, size) = self.section_contents(header)?;
+      let link = self.u32(header + if self.wide { 40 } else { 24 })?;
+      let strings = match sections.get(link as usize) {
+        Some(&strings) => self.section_contents(strings)?.0,
+        None => return Err(MemoryError::InvalidElf("symbol table without a string table".to_string())),
+      };
+      let entry_size = if self.wide { 24 } else { 16 };
+      for index in 0..size / entry_size {
+        let symbol = self.entry(offset, index, entry_size)?;
+        let name = self.string(strings, self.u32(symbol)? as u64)?;
+        let value = self.word(symbol + if self.wide { 8 } else { 4 })?;
+        if !name.is_empty() {
+          symbols.insert(name, value);
+        }
+      }
+    }
+    Ok(symbols)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::runtime::{MemoryConfig, SparseMemory};
+
+  /// A little-endian 64-bit executable with one segment and a symbol table holding `tohost`.
+  fn executable() -> Vec<u8> {
+    let mut file = vec![0u8; 0x200];
+    let mut put = |offset: usize, value: u64, size: usize| {
+      file[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    };
+    put(0, 0x464c_457f, 4);
+    // 64-bit, little endian, version 1.
+    put(4, 0x0001_0102, 3);
+    put(24, 0x8000_0000, 8);
+    // One program header at 0x40, three section headers at 0x100.
+    put(32, 0x40, 8);
+    put(40, 0x100, 8);
+    put(54, 56, 2);
+    put(56, 1, 2);
+    put(58, 64, 2);
+    put(60, 3, 2);
+    // The segment: four bytes from 0x1c0, eight in memory.
+    put(0x40, LOADABLE as u64, 4);
+    put(0x48, 0x1c0, 8);
+    put(0x58, 0x8000_0000, 8);
+    put(0x60, 4, 8);
+    put(0x68, 8, 8);
+    put(0x1c0, 0xdead_beef, 4);
+    // The symbol table at 0x1d0, whose second entry names `tohost`, and its strings at 0x1a0.
+    put(0x144, SYMBOL_TABLE as u64, 4);
+    put(0x158, 0x1d0, 8);
+    put(0x160, 48, 8);
+    put(0x168, 2, 4);
+    put(0x198, 0x1a0, 8);
+    put(0x1d0 + 24, 1, 4);
+    put(0x1d0 + 32, 0x8000_1000, 8);
+    file[0x1a1..0x1a7].copy_from_slice(b"tohost");
+    file
+  }
+
+  #[test]
+  fn loads_segments_and_symbols() {
+    let mut memory = SparseMemory::new(MemoryConfig::default());
+    memory.write_bytes(0x8000_0004, &[0xff; 4]).unwrap();
+    let image = load_elf_bytes(&executable(), &mut memory).unwrap();
+    assert_eq!(image.entry, 0x8000_0000);
+    assert_eq!(image.segments, vec![(0x8000_0000, 8)]);
+    assert_eq!(image.symbol("tohost"), Some(0x8000_1000));
+    assert_eq!(memory.read(0x8000_0000, 8).unwrap().to_u64(), Some(0xdead_beef));
+
+    assert_eq!(load_elf_bytes(b"not an executable", &mut memory), Err(MemoryError::InvalidElf("not an ELF file".to_string())));
+  }
+
+  #[test]
+  fn zeroes_memory_a_page_at_a_time() {
+    let mut file = executable();
+    file[0x68..0x70].copy_from_slice(&0x2800u64.to_le_bytes());
+    let mut memory = SparseMemory::new(MemoryConfig::default());
+    memory.write_bytes(0x8000_1ffe, &[0xff; 4]).unwrap();
+    memory.write_bytes(0x8000_27fe, &[0xff; 4]).unwrap();
+    let image = load_elf_bytes(&file, &mut memory).unwrap();
+    assert_eq!(image.segments, vec![(0x8000_0000, 0x2800)]);
+    assert_eq!(memory.read(0x8000_1ffc, 8).unwrap().to_u64(), Some(0));
+    // Memory beyond the segment is left as it was.
+    assert_eq!(memory.read(0x8000_27fc, 8).unwrap().to_u64(), Some(0x0000_ffff_0000_0000));
+  }
+
+  #[test]
+  fn rejects_headers_and_segments_out_of_range() {
+    let invalid = |patch: &dyn Fn(&mut Vec<u8>)| {
+      let mut file = executable();
+      patch(&mut file);
+      let mut memory = SparseMemory::new(MemoryConfig::default());
+      match load_elf_bytes(&file, &mut memory) {
+        Err(MemoryError::InvalidElf(message)) => message,
+        other => panic!("expected an invalid ELF file, found {:?}", other),
+      }
+    };
+    let put = |file: &mut Vec<u8>, offset: usize, value: u64| {
+      file[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    };
+
+    // Program headers whose offsets overflow, or lie beyond the file.
+    assert_eq!(invalid(&|file| put(file, 32, u64::MAX - 8)), "a header table extends beyond the file");
+    assert_eq!(invalid(&|file| put(file, 32, 0x1f0)), "a header table extends beyond the file");
+    // Section headers likewise.
+    assert_eq!(invalid(&|file| put(file, 40, u64::MAX)), "a header table extends beyond the file");
+    // A segment too large, or reaching past the end of the address space.
+    assert_eq!(invalid(&|file| put(file, 0x68, 1 << 40)), "a segment does not fit in memory");
+    assert_eq!(invalid(&|file| put(file, 0x58, u64::MAX - 4)), "a segment does not fit in memory");
+  }
+}
@@ -0,0 +1,159 @@
+/*!
+
+The machine state a program runs against.
+
+*/
+
+use std::collections::HashMap;
+
+use crate::runtime::{Bits, Memory, RegisterFile, RuntimeError, SparseMemory, Value};
+
+/// Registers and memory, which a program reads and writes through the interpreter and through primitives.
+pub trait Environment {
+  fn read_register(&mut self, name: &str) -> Result<Value, RuntimeError>;
+
+  fn write_register(&mut self, name: &str, value: Value) -> Result<(), RuntimeError>;
+
+  /// Reads `bytes` bytes from `address` up, in the memory's byte order, which is little endian unless it says otherwise.
+  fn read_memory(&mut self, _address: u64, _bytes: usize) -> Result<Bits, RuntimeError> {
+    Err(RuntimeError::Unsupported("memory".to_string()))
+  }
+
+  /// Writes `data`, whose length is a multiple of eight, from `address` up, in the memory's byte order.
+  fn write_memory(&mut self, _address: u64, _data: &Bits) -> Result<(), RuntimeError> {
+    Err(RuntimeError::Unsupported("memory".to_string()))
+  }
+
+  /// The tag of the granule of memory holding `address`, for specifications with tagged memory, such as CHERI's.
+  fn read_tag(&mut self, _address: u64) -> Result<bool, RuntimeError> {
+    Err(RuntimeError::Unsupported("tagged memory".to_string()))
+  }
+
+  fn write_tag(&mut self, _address: u64, _tag: bool) -> Result<(), RuntimeError> {
+    Err(RuntimeError::Unsupported("tagged memory".to_string()))
+  }
+}
+
+/// Registers in a map, and memory as a map from addresses to bytes, unwritten bytes reading as zero.
+#[derive(Clone, Debug, Default)]
+pub struct SimpleEnvironment {
+  pub registers: HashMap<String, Value>,
+  pub memory   : HashMap<u64, u8>,
+}
+
+impl Environment for SimpleEnvironment {
+  fn read_register(&mut self, name: &str) -> Result<Value, RuntimeError> {
+    self.registers.get(name).cloned().ok_or_else(|| RuntimeError::UnknownRegister(name.to_string()))
+  }
+
+  fn write_register(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+    self.registers.insert(name.to_string(), value);
+    Ok(())
+  }
+
+  fn read_memory(&mut self, address: u64, bytes: usize) -> Result<Bits, RuntimeError> {
+    let data: Vec<u8> =
+      (0..bytes as u64).map(|offset| self.memory.get(&address.wrapping_add(offset)).copied().unwrap_or(0)).collect();
+    Ok(Bits::from_bytes_le(&data))
+  }
+
+  fn write_memory(&mut self, address: u64, data: &Bits) -> Result<(), RuntimeError> {
+    for (offset, byte) in data.to_bytes_le().into_iter().enumerate() {
+      self.memory.insert(address.wrapping_add(offset as u64), byte);
+    }
+    Ok(())
+  }
+}
+
+/// A register file and a memory, the state of a whole machine.
+#[derive(Clone, Debug)]
+pub struct Machine<M: Memory = SparseMemory> {
+  pub registers: RegisterFile,
+  pub memory   : M,
+}
+
+impl<M: Memory> Machine<M> {
+  pub fn new(registers: RegisterFile, memory: M) -> Self {
+    Machine { registers, memory }
+  }
+}
+
+impl<M: Memory> Environment for Machine<M> {
+  fn read_register(&mut self, name: &str) -> Result<Value, RuntimeError> {
+    self.registers.read_register(name)
+  }
+
+  fn write_register(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+    self.registers.write_register(name, value)
+  }
+
+  fn read_memory(&mut self, address: u64, bytes: usize) -> Result<Bits, RuntimeError> {
+    Ok(self.memory.read(address, bytes)?)
+  }
+
+  fn write_memory(&mut self, address: u64, data: &Bits) -> Result<(), RuntimeError> {
+    Ok(self.memory.write(address, data)?)
+  }
+
+  fn read_tag(&mut self, address: u64) -> Result<bool, RuntimeError> {
+    Ok(self.memory.read_tag(address)?)
+  }
+
+  fn write_tag(&mut self, address: u64, tag: bool) -> Result<(), RuntimeError> {
+    Ok(self.memory.write_tag(address, tag)?)
+  }
+}
+
+/// An access to memory, as a `Recorder` saw it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+  pub write  : bool,
+  pub address: u64,
+  /// The data read or written
+  pub data   : Bits,
+}
+
+/// An environment passing everything on to another, keeping a log of the registers written and the memory read and
+/// written, in the order they were.
+pub struct Recorder<'e> {
+  environment  : &'e mut dyn Environment,
+  pub registers: Vec<(String, Value)>,
+  pub memory   : Vec<MemoryAccess>,
+}
+
+impl<'e> Recorder<'e> {
+  pub fn new(environment: &'e mut dyn Environment) -> Self {
+    Recorder { environment, registers: Vec::new(), memory: Vec::new() }
+  }
+}
+
+impl Environment for Recorder<'_> {
+  fn read_register(&mut self, name: &str) -> Result<Value, RuntimeError> {
+    self.environment.read_register(name)
+  }
+
+  fn write_register(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+    self.registers.push((name.to_string(), value.clone()));
+    self.environment.write_register(name, value)
+  }
+
+  fn read_memory(&mut self, address: u64, bytes: usize) -> Result<Bits, RuntimeError> {
+    let data = self.environment.read_memory(address, bytes)?;
+    self.memory.push(MemoryAccess { write: false, address, data: data.clone() });
+    Ok(data)
+  }
+
+  fn write_memory(&mut self, address: u64, data: &Bits) -> Result<(), RuntimeError> {
+    self.environment.write_memory(address, data)?;
+    self.memory.push(MemoryAccess { write: true, address, data: data.clone() });
+    Ok(())
+  }
+
+  fn read_tag(&mut self, address: u64) -> Result<bool, RuntimeError> {
+    self.environment.read_tag(address)
+  }
+
+  fn write_tag(&mut self, address: u64, tag: bool) -> Result<(), RuntimeError> {
+    self.environment.write_tag(address, tag)
+  }
+}
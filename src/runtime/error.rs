@@ -0,0 +1,145 @@
+/*!
+
+Errors that stop a running program.
+
+*/
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::abstractions::{ArithmeticError, BigInteger};
+use crate::parser::location::Located;
+use crate::runtime::{MemoryError, Value};
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum RuntimeError {
+  /// The program called `exit`.
+  Exit,
+  AssertionFailed(String),
+  /// An exception was thrown and not caught.
+  UncaughtException(Value),
+  /// No arm of a `match` or clause of a function matched, or a `let` pattern did not.
+  MatchFailure,
+  UnknownFunction(String),
+  /// An extern function with no implementation registered for any of its bindings.
+  MissingExtern(String),
+  UnboundVariable(String),
+  UnknownRegister(String),
+  /// A register and a field it does not have.
+  UnknownField(String, String),
+  /// The value of a type variable the program needs is not known at run time.
+  UnknownTypeVariable(String),
+  Arithmetic(ArithmeticError),
+  OutOfBounds { index: BigInteger, length: usize },
+  /// A value of the wrong shape for the operation, which a checked program never has.
+  Malformed(String),
+  Unsupported(String),
+  /// Calls nested more deeply than the interpreter allows.
+  TooDeep,
+  /// A failure reported by an extern function or the environment.
+  Failure(String),
+  /// A fault from the machine's memory, such as a misaligned access.
+  Memory(MemoryError),
+}
+
+pub type LocatedRuntimeError = Located<RuntimeError>;
+
+impl RuntimeError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RuntimeError::Exit => {
+        write!(f, "the program exited")
+      }
+
+      RuntimeError::AssertionFailed(message) => {
+        write!(f, "assertion failed: {}", message)
+      }
+
+      RuntimeError::UncaughtException(value) => {
+        write!(f, "uncaught exception {}", value)
+      }
+
+      RuntimeError::MatchFailure => {
+        write!(f, "no pattern matched")
+      }
+
+      RuntimeError::UnknownFunction(name) => {
+        write!(f, "unknown function `{}`", name)
+      }
+
+      RuntimeError::MissingExtern(name) => {
+        write!(f, "no implementation of the external function `{}`", name)
+      }
+
+      RuntimeError::UnboundVariable(name) => {
+        write!(f, "unbound variable `{}`", name)
+      }
+
+      RuntimeError::UnknownRegister(name) => {
+        write!(f, "unknown register `{}`", name)
+      }
+
+      RuntimeError::UnknownField(register, field) => {
+        write!(f, "the register `{}` has no field `{}`", register, field)
+      }
+
+      RuntimeError::UnknownTypeVariable(name) => {
+        write!(f, "the value of the type variable {} is not known at run time", name)
+      }
+
+      RuntimeError::Arithmetic(error) => {
+        write!(f, "{}", error)
+      }
+
+      RuntimeError::OutOfBounds { index, length } => {
+        write!(f, "index {} out of bounds for a vector of length {}", index, length)
+      }
+
+      RuntimeError::Malformed(what) => {
+        write!(f, "expected {}", what)
+      }
+
+      RuntimeError::Unsupported(what) => {
+        write!(f, "{} is not supported", what)
+      }
+
+      RuntimeError::TooDeep => {
+        write!(f, "calls nested too deeply")
+      }
+
+      RuntimeError::Failure(message) => {
+        write!(f, "{}", message)
+      }
+
+      RuntimeError::Memory(error) => {
+        write!(f, "{}", error)
+      }
+    }
+  }
+}
+
+impl Debug for RuntimeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for RuntimeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for RuntimeError {}
+
+impl From<ArithmeticError> for RuntimeError {
+  fn from(error: ArithmeticError) -> Self {
+    RuntimeError::Arithmetic(error)
+  }
+}
+
+impl From<MemoryError> for RuntimeError {
+  fn from(error: MemoryError) -> Self {
+    RuntimeError::Memory(error)
+  }
+}
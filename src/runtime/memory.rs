@@ -0,0 +1,283 @@
+/*!
+
+Memory, as the primitives behind Sail's `read_mem` and `write_mem` effects see it.
+
+A `Memory` is byte addressed. Implementations provide reading and writing runs of bytes, and, for specifications such as
+CHERI's that keep a tag with every capability-sized granule, reading and writing tags. The trait builds sized accesses
+on those, checked against a `MemoryConfig`: the byte order values are stored in, the sizes an access may have, and
+whether an access must be aligned to its size.
+
+`SparseMemory` keeps the pages that have been written in a map, so that a program can use a few scattered regions of a
+large address space. Bytes that have never been written read as zero.
+
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::runtime::Bits;
+
+/// The size of the pages `SparseMemory` allocates.
+pub const PAGE_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endianness {
+  #[default]
+  Little,
+  Big,
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum MemoryError {
+  /// An access not aligned to its size, in a memory that requires alignment.
+  Misaligned { address: u64, size: usize },
+  /// An access of a size, in bits, the memory does not allow.
+  AccessSize(usize),
+  /// An access running past the end of the address space.
+  OutOfRange(u64),
+  /// A tag read or written in a memory without tags.
+  Untagged,
+  Io(String),
+  /// A file the ELF loader cannot load.
+  InvalidElf(String),
+}
+
+impl MemoryError {
+  pub fn msg(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MemoryError::Misaligned { address, size } => {
+        write!(f, "misaligned access of {} bytes at {:#x}", size, address)
+      }
+
+      MemoryError::AccessSize(bits) => {
+        write!(f, "accesses of {} bits are not supported", bits)
+      }
+
+      MemoryError::OutOfRange(address) => {
+        write!(f, "access at {:#x} runs past the end of memory", address)
+      }
+
+      MemoryError::Untagged => {
+        write!(f, "memory has no tags")
+      }
+
+      MemoryError::Io(message) => {
+        write!(f, "{}", message)
+      }
+
+      MemoryError::InvalidElf(message) => {
+        write!(f, "invalid ELF file: {}", message)
+      }
+    }
+  }
+}
+
+impl Debug for MemoryError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Display for MemoryError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    self.msg(f)
+  }
+}
+
+impl Error for MemoryError {}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryConfig {
+  /// The order of the bytes of values wider than a byte
+  pub endianness  : Endianness,
+  /// Whether an access whose size is a power of two must be aligned to it
+  pub aligned     : bool,
+  /// The sizes in bytes an access may have, or any if there are none
+  pub access_sizes: Vec<usize>,
+  /// The number of bytes each tag covers, in a memory with tags
+  pub tag_granule : Option<usize>,
+}
+
+impl MemoryConfig {
+  /// Checks an access of `size` bytes at `address`.
+  pub fn check(&self, address: u64, size: usize) -> Result<(), MemoryError> {
+    if !self.access_sizes.is_empty() && !self.access_sizes.contains(&size) {
+      return Err(MemoryError::AccessSize(size * 8));
+    }
+    if self.aligned && size.is_power_of_two() && !address.is_multiple_of(size as u64) {
+      return Err(MemoryError::Misaligned { address, size });
+    }
+    end(address, size)?;
+    Ok(())
+  }
+}
+
+/// The address just past an access of `size` bytes at `address`, which may be the end of the address space.
+fn end(address: u64, size: usize) -> Result<u128, MemoryError> {
+  let end = address as u128 + size as u128;
+  match end <= 1 << 64 {
+    true => Ok(end),
+    false => Err(MemoryError::OutOfRange(address)),
+  }
+}
+
+pub trait Memory {
+  fn config(&self) -> &MemoryConfig;
+
+  /// Fills `data` with the bytes from `address` up, without the checks sized accesses have.
+  fn read_bytes(&mut self, address: u64, data: &mut [u8]) -> Result<(), MemoryError>;
+
+  /// Writes `data` from `address` up, without the checks sized accesses have.
+  fn write_bytes(&mut self, address: u64, data: &[u8]) -> Result<(), MemoryError>;
+
+  /// The tag of the granule holding `address`.
+  fn read_tag(&mut self, _address: u64) -> Result<bool, MemoryError> {
+    Err(MemoryError::Untagged)
+  }
+
+  fn write_tag(&mut self, _address: u64, _tag: bool) -> Result<(), MemoryError> {
+    Err(MemoryError::Untagged)
+  }
+
+  /// Reads a value of `size` bytes at `address`.
+  fn read(&mut self, address: u64, size: usize) -> Result<Bits, MemoryError> {
+    self.config().check(address, size)?;
+    let mut data = vec![0; size];
+    self.read_bytes(address, &mut data)?;
+    if self.config().endianness == Endianness::Big {
+      data.reverse();
+    }
+    Ok(Bits::from_bytes_le(&data))
+  }
+
+  /// Writes `value`, whose length is a whole number of bytes, at `address`.
+  fn write(&mut self, address: u64, value: &Bits) -> Result<(), MemoryError> {
+    if !value.len().is_multiple_of(8) {
+      return Err(MemoryError::AccessSize(value.len()));
+    }
+    self.config().check(address, value.len() / 8)?;
+    let mut data = value.to_bytes_le();
+    if self.config().endianness == Endianness::Big {
+      data.reverse();
+    }
+    self.write_bytes(address, &data)
+  }
+}
+
+/// Memory allocated a page at a time as it is written.
+#[derive(Clone, Debug, Default)]
+pub struct SparseMemory {
+  config: MemoryConfig,
+  pages : HashMap<u64, Box<[u8]>>,
+  /// The granules whose tags are set
+  tags  : HashSet<u64>,
+}
+
+impl SparseMemory {
+  pub fn new(config: MemoryConfig) -> Self {
+    SparseMemory { config, ..SparseMemory::default() }
+  }
+
+  /// The addresses of the pages that have been written, in no particular order.
+  pub fn pages(&self) -> impl Iterator<Item = u64> + '_ {
+    self.pages.keys().map(|page| page * PAGE_SIZE as u64)
+  }
+
+  /// Splits an access into the parts within each page: the page, the offset into it, and the offset into the access.
+  fn chunks(address: u64, length: usize) -> Result<Vec<(u64, usize, std::ops::Range<usize>)>, MemoryError> {
+    end(address, length)?;
+    let mut chunks = Vec::new();
+    let mut done = 0;
+    while done < length {
+      let current = address + done as u64;
+      let offset = (current % PAGE_SIZE as u64) as usize;
+      let size = (PAGE_SIZE - offset).min(length - done);
+      chunks.push((current / PAGE_SIZE as u64, offset, done..done + size));
+      done += size;
+    }
+    Ok(chunks)
+  }
+
+  fn granule(&self, address: u64) -> Result<u64, MemoryError> {
+    match self.config.tag_granule {
+      Some(size) if size > 0 => Ok(address / size as u64),
+      _ => Err(MemoryError::Untagged),
+    }
+  }
+}
+
+impl Memory for SparseMemory {
+  fn config(&self) -> &MemoryConfig {
+    &self.config
+  }
+
+  fn read_bytes(&mut self, address: u64, data: &mut [u8]) -> Result<(), MemoryError> {
+    for (page, offset, range) in SparseMemory::chunks(address, data.len())? {
+      match self.pages.get(&page) {
+        Some(bytes) => data[range.clone()].copy_from_slice(&bytes[offset..offset + range.len()]),
+        None => data[range].fill(0),
+      }
+    }
+    Ok(())
+  }
+
+  fn write_bytes(&mut self, address: u64, data: &[u8]) -> Result<(), MemoryError> {
+    for (page, offset, range) in SparseMemory::chunks(address, data.len())? {
+      let bytes = self.pages.entry(page).or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+      bytes[offset..offset + range.len()].copy_from_slice(&data[range]);
+    }
+    Ok(())
+  }
+
+  fn read_tag(&mut self, address: u64) -> Result<bool, MemoryError> {
+    Ok(self.tags.contains(&self.granule(address)?))
+  }
+
+  fn write_tag(&mut self, address: u64, tag: bool) -> Result<(), MemoryError> {
+    let granule = self.granule(address)?;
+    match tag {
+      true => self.tags.insert(granule),
+      false => self.tags.remove(&granule),
+    };
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn byte_order_and_page_boundaries() {
+    let mut memory = SparseMemory::new(MemoryConfig::default());
+    let address = PAGE_SIZE as u64 - 2;
+    memory.write(address, &Bits::from_u64(0x1122_3344, 32)).unwrap();
+    let mut bytes = [0; 4];
+    memory.read_bytes(address, &mut bytes).unwrap();
+    assert_eq!(bytes, [0x44, 0x33, 0x22, 0x11]);
+    assert_eq!(memory.read(address, 4).unwrap().to_u64(), Some(0x1122_3344));
+    assert_eq!(memory.read(0x10_0000, 8).unwrap().to_u64(), Some(0));
+
+    let mut memory = SparseMemory::new(MemoryConfig { endianness: Endianness::Big, ..MemoryConfig::default() });
+    memory.write(0, &Bits::from_u64(0x1122, 16)).unwrap();
+    memory.read_bytes(0, &mut bytes[..2]).unwrap();
+    assert_eq!(bytes[..2], [0x11, 0x22]);
+    assert_eq!(memory.read(0, 2).unwrap().to_u64(), Some(0x1122));
+  }
+
+  #[test]
+  fn faults_and_tags() {
+    let config = MemoryConfig { aligned: true, access_sizes: vec![1, 2, 4, 8], tag_granule: Some(16), ..MemoryConfig::default() };
+    let mut memory = SparseMemory::new(config);
+    assert_eq!(memory.read(2, 4), Err(MemoryError::Misaligned { address: 2, size: 4 }));
+    assert_eq!(memory.read(0, 3), Err(MemoryError::AccessSize(24)));
+    assert_eq!(memory.read(u64::MAX - 3, 8), Err(MemoryError::Misaligned { address: u64::MAX - 3, size: 8 }));
+    assert!(memory.read(u64::MAX - 7, 8).is_ok());
+
+    memory.write_tag(0x20, true).unwrap();
+    assert!(memory.read_tag(0x2f).unwrap());
+    assert!(!memory.read_tag(0x30).unwrap());
+    assert_eq!(SparseMemory::default().read_tag(0), Err(MemoryError::Untagged));
+  }
+}
@@ -0,0 +1,23 @@
+/*!
+
+The run-time representation of Sail values, shared by the interpreter and anything else that runs Sail programs in Rust.
+
+*/
+
+mod bits;
+mod elf;
+mod environment;
+mod error;
+mod memory;
+mod primitives;
+mod registers;
+mod value;
+
+pub use bits::Bits;
+pub use elf::{load_elf, load_elf_bytes, ElfImage};
+pub use environment::{Environment, Machine, MemoryAccess, Recorder, SimpleEnvironment};
+pub use error::{LocatedRuntimeError, RuntimeError};
+pub use memory::{Endianness, Memory, MemoryConfig, MemoryError, SparseMemory, PAGE_SIZE};
+pub use primitives::{Primitive, Primitives};
+pub use registers::{RegisterChange, RegisterDeclaration, RegisterFile, Snapshot};
+pub use value::Value;
@@ -0,0 +1,506 @@
+/*!
+
+Sail's standard primitives, the Rust implementations of the extern functions its library declares.
+
+A `val` specification such as `val add_bits = pure {c: "add_bits", _: "add_bits"} : ...` says nothing about what
+`add_bits` does; a backend must supply it. `Primitives` is a registry of implementations keyed by the names in those
+bindings. `Primitives::standard` holds Sail's integer, bitvector, string, real, list and vector operations, each under
+the names the library's interpreter, OCaml and C bindings use for it, and users add their own, or replace standard
+ones, with `define`.
+
+Vector primitives taking indices use the `dec` order, as Sail's library does; those suffixed `_inc` use `inc`. The
+`print` family writes to standard output and the `prerr` family to standard error. Memory primitives read and write
+through the `Environment`, so a `Machine` gives them its `Memory`.
+
+*/
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::abstractions::{ArithmeticError, BigInteger, Integer, Rational};
+use crate::runtime::{Bits, Environment, RuntimeError, Value};
+
+/// The implementation of an extern function, given the values of its arguments, including any implicit ones.
+pub type Primitive = Rc<dyn Fn(&[Value], &mut dyn Environment) -> Result<Value, RuntimeError>>;
+
+#[derive(Clone, Default)]
+pub struct Primitives {
+  functions: HashMap<String, Primitive>,
+}
+
+impl Primitives {
+  /// An empty registry.
+  pub fn new() -> Self {
+    Primitives::default()
+  }
+
+  /// A registry of Sail's standard primitives.
+  pub fn standard() -> Self {
+    let mut primitives = Primitives::new();
+    primitives.define_booleans();
+    primitives.define_integers();
+    primitives.define_bitvectors();
+    primitives.define_vectors();
+    primitives.define_strings();
+    primitives.define_reals();
+    primitives.define_memory();
+    primitives.define_undefined();
+    primitives
+  }
+
+  /// Registers `implementation` under `name`, replacing any primitive of that name.
+  pub fn define(
+    &mut self,
+    name          : &str,
+    implementation: impl Fn(&[Value], &mut dyn Environment) -> Result<Value, RuntimeError> + 'static,
+  ) {
+    self.functions.insert(name.to_string(), Rc::new(implementation));
+  }
+
+  /// Registers a primitive that does not touch the machine state.
+  pub fn define_pure(&mut self, name: &str, implementation: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static) {
+    self.define(name, move |arguments, _| implementation(arguments));
+  }
+
+  /// Registers a pure primitive under each of `names`.
+  fn define_all(&mut self, names: &[&str], implementation: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static) {
+    let implementation: Primitive = Rc::new(move |arguments, _| implementation(arguments));
+    for name in names {
+      self.functions.insert(name.to_string(), implementation.clone());
+    }
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Primitive> {
+    self.functions.get(name)
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.functions.contains_key(name)
+  }
+
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.functions.keys().map(|name| name.as_str())
+  }
+
+  /// Adds the primitives of `other`, replacing any of the same names.
+  pub fn extend(&mut self, other: &Primitives) {
+    for (name, implementation) in &other.functions {
+      self.functions.insert(name.clone(), implementation.clone());
+    }
+  }
+
+  // region Standard primitives
+
+  fn define_booleans(&mut self) {
+    self.define_all(&["not", "not_bool"], |a| Ok(Value::Bool(!boolean(a, 0)?)));
+    // Strict versions, for when they are called as functions rather than written as `&` and `|`.
+    self.define_all(&["and_bool"], |a| Ok(Value::Bool(boolean(a, 0)? && boolean(a, 1)?)));
+    self.define_all(&["or_bool"], |a| Ok(Value::Bool(boolean(a, 0)? || boolean(a, 1)?)));
+    self.define_all(
+      &["eq_anything", "eq_unit", "eq_bool", "eq_bit", "eq_int", "eq_string", "eq_list", "eq_bits", "eq_vec", "eq_real"],
+      |a| Ok(Value::Bool(argument(a, 0)? == argument(a, 1)?)),
+    );
+    self.define_all(&["neq_anything", "neq_bool", "neq_bit", "neq_int", "neq_string", "neq_bits", "neq_vec"], |a| {
+      Ok(Value::Bool(argument(a, 0)? != argument(a, 1)?))
+    });
+  }
+
+  fn define_integers(&mut self) {
+    self.define_all(&["add_int", "add_atom"], |a| Ok(Value::Integer(integer(a, 0)?.try_add(integer(a, 1)?)?)));
+    self.define_all(&["sub_int", "sub_atom"], |a| Ok(Value::Integer(integer(a, 0)?.try_sub(integer(a, 1)?)?)));
+    self.define_all(&["sub_nat"], |a| {
+      let difference = integer(a, 0)?.try_sub(integer(a, 1)?)?;
+      Ok(Value::Integer(difference.max(BigInteger::from_i64(0))))
+    });
+    self.define_all(&["mult", "mult_int", "mult_atom"], |a| Ok(Value::Integer(integer(a, 0)?.try_mul(integer(a, 1)?)?)));
+    self.define_all(&["negate", "neg_int", "negate_atom"], |a| Ok(Value::Integer(integer(a, 0)?.try_neg()?)));
+    self.define_all(&["abs_int", "abs_atom"], |a| Ok(Value::Integer(integer(a, 0)?.try_abs()?)));
+    self.define_all(&["max_int"], |a| Ok(Value::Integer(integer(a, 0)?.clone().max(integer(a, 1)?.clone()))));
+    self.define_all(&["min_int"], |a| Ok(Value::Integer(integer(a, 0)?.clone().min(integer(a, 1)?.clone()))));
+    self.define_all(&["tdiv_int", "quotient"], |a| Ok(Value::Integer(integer(a, 0)?.try_tdiv(integer(a, 1)?)?)));
+    self.define_all(&["tmod_int", "modulus"], |a| Ok(Value::Integer(integer(a, 0)?.try_tmod(integer(a, 1)?)?)));
+    self.define_all(&["ediv_int", "div_int"], |a| Ok(Value::Integer(integer(a, 0)?.try_ediv(integer(a, 1)?)?)));
+    self.define_all(&["emod_int", "mod_int"], |a| Ok(Value::Integer(integer(a, 0)?.try_emod(integer(a, 1)?)?)));
+    self.define_all(&["pow2"], |a| Ok(Value::Integer(BigInteger::try_pow2(integer(a, 0)?.try_to_u32()?)?)));
+    self.define_all(&["pow", "pow_int"], |a| Ok(Value::Integer(integer(a, 0)?.try_pow(integer(a, 1)?.try_to_u32()?)?)));
+    self.define_all(&["shl_int"], |a| Ok(Value::Integer(integer(a, 0)?.try_shl(integer(a, 1)?.try_to_u32()?)?)));
+    self.define_all(&["shr_int"], |a| Ok(Value::Integer(integer(a, 0)?.try_shr(integer(a, 1)?.try_to_u32()?)?)));
+    self.define_all(&["lt", "lt_int"], |a| Ok(Value::Bool(integer(a, 0)? < integer(a, 1)?)));
+    self.define_all(&["lteq", "lteq_int"], |a| Ok(Value::Bool(integer(a, 0)? <= integer(a, 1)?)));
+    self.define_all(&["gt", "gt_int"], |a| Ok(Value::Bool(integer(a, 0)? > integer(a, 1)?)));
+    self.define_all(&["gteq", "gteq_int"], |a| Ok(Value::Bool(integer(a, 0)? >= integer(a, 1)?)));
+  }
+
+  fn define_bitvectors(&mut self) {
+    self.define_all(&["length", "bitvector_length", "vector_length", "vec_length"], |a| match argument(a, 0)? {
+      Value::List(elements) => Ok(Value::Integer(BigInteger::from_i64(elements.len() as i64))),
+      value => Ok(Value::Integer(BigInteger::from_i64(value.length().ok_or_else(not_a_vector)? as i64))),
+    });
+    self.define_all(&["not_vec", "not_bits"], |a| Ok(Value::Bitvector(bits(a, 0)?.not())));
+    self.define_all(&["and_vec", "and_bits"], |a| Ok(Value::Bitvector(bits(a, 0)?.zip_with(bits(a, 1)?, |x, y| x & y))));
+    self.define_all(&["or_vec", "or_bits"], |a| Ok(Value::Bitvector(bits(a, 0)?.zip_with(bits(a, 1)?, |x, y| x | y))));
+    self.define_all(&["xor_vec", "xor_bits"], |a| Ok(Value::Bitvector(bits(a, 0)?.zip_with(bits(a, 1)?, |x, y| x ^ y))));
+    self.define_all(&["add_vec", "add_bits"], |a| Ok(Value::Bitvector(bits(a, 0)?.wrapping_add(bits(a, 1)?))));
+    self.define_all(&["sub_vec", "sub_bits"], |a| Ok(Value::Bitvector(bits(a, 0)?.wrapping_sub(bits(a, 1)?))));
+    self.define_all(&["add_vec_int", "add_bits_int"], |a| {
+      let value = bits(a, 0)?;
+      Ok(Value::Bitvector(Bits::from_integer(&value.to_unsigned()?.try_add(integer(a, 1)?)?, value.len())?))
+    });
+    self.define_all(&["sub_vec_int", "sub_bits_int"], |a| {
+      let value = bits(a, 0)?;
+      Ok(Value::Bitvector(Bits::from_integer(&value.to_unsigned()?.try_sub(integer(a, 1)?)?, value.len())?))
+    });
+    // The product of two n-bit vectors has 2n bits.
+    self.define_all(&["mult_vec", "mult_bits"], |a| {
+      let (left, right) = (bits(a, 0)?, bits(a, 1)?);
+      let product = left.to_unsigned()?.try_mul(&right.to_unsigned()?)?;
+      Ok(Value::Bitvector(Bits::from_integer(&product, left.len() * 2)?))
+    });
+    self.define_all(&["mults_vec", "mults_bits"], |a| {
+      let (left, right) = (bits(a, 0)?, bits(a, 1)?);
+      let product = left.to_signed()?.try_mul(&right.to_signed()?)?;
+      Ok(Value::Bitvector(Bits::from_integer(&product, left.len() * 2)?))
+    });
+    self.define_all(&["uint", "unsigned", "sail_unsigned"], |a| Ok(Value::Integer(bits(a, 0)?.to_unsigned()?)));
+    self.define_all(&["sint", "signed", "sail_signed"], |a| Ok(Value::Integer(bits(a, 0)?.to_signed()?)));
+    self.define_all(&["zeros", "sail_zeros"], |a| Ok(Value::Bitvector(Bits::zeros(natural(a, 0)?))));
+    self.define_all(&["ones", "sail_ones"], |a| Ok(Value::Bitvector(Bits::ones(natural(a, 0)?))));
+    self.define_all(&["zero_extend", "sail_zero_extend"], |a| extend(a, false));
+    self.define_all(&["sign_extend", "sail_sign_extend"], |a| extend(a, true));
+    self.define_all(&["sail_truncate", "truncate"], |a| {
+      let value = bits(a, 0)?;
+      let length = natural(a, 1)?;
+      in_range(length, value.len())?;
+      Ok(Value::Bitvector(value.slice(0, length)))
+    });
+    self.define_all(&["sail_truncateLSB", "truncateLSB"], |a| {
+      let value = bits(a, 0)?;
+      let length = natural(a, 1)?;
+      in_range(length, value.len())?;
+      Ok(Value::Bitvector(value.slice(value.len() - length, length)))
+    });
+    self.define_all(&["shiftl", "sail_shiftleft"], |a| Ok(Value::Bitvector(bits(a, 0)?.shift_left(natural(a, 1)?))));
+    self.define_all(&["shiftr", "sail_shiftright"], |a| Ok(Value::Bitvector(bits(a, 0)?.shift_right(natural(a, 1)?, false))));
+    self.define_all(&["arith_shiftr", "sail_arith_shiftright"], |a| {
+      Ok(Value::Bitvector(bits(a, 0)?.shift_right(natural(a, 1)?, true)))
+    });
+    // Shifts by an amount given as a bitvector.
+    self.define_all(&["shift_bits_left"], |a| Ok(Value::Bitvector(bits(a, 0)?.shift_left(shift_amount(bits(a, 1)?)))));
+    self.define_all(&["shift_bits_right"], |a| Ok(Value::Bitvector(bits(a, 0)?.shift_right(shift_amount(bits(a, 1)?), false))));
+    self.define_all(&["shift_bits_right_arith"], |a| {
+      Ok(Value::Bitvector(bits(a, 0)?.shift_right(shift_amount(bits(a, 1)?), true)))
+    });
+    self.define_all(&["replicate_bits"], |a| {
+      let value = bits(a, 0)?;
+      let result = (0..natural(a, 1)?).fold(Bits::zeros(0), |result, _| result.append(value));
+      Ok(Value::Bitvector(result))
+    });
+    // `slice(xs, start, n)` is the `n` bits of `xs` from position `start` up.
+    self.define_all(&["slice", "sail_slice"], |a| {
+      let value = bits(a, 0)?;
+      let (start, length) = (natural(a, 1)?, natural(a, 2)?);
+      in_range(start.saturating_add(length), value.len())?;
+      Ok(Value::Bitvector(value.slice(start, length)))
+    });
+    // `get_slice_int(n, m, start)` is the `n` bits of the integer `m` from bit `start` up.
+    self.define_all(&["get_slice_int"], |a| {
+      let value = integer(a, 1)?.try_shr(integer(a, 2)?.try_to_u32()?)?;
+      Ok(Value::Bitvector(Bits::from_integer(&value, natural(a, 0)?)?))
+    });
+    // `set_slice_int(n, m, start, v)` is `m` with the `n` bits from bit `start` up replaced by `v`.
+    self.define_all(&["set_slice_int"], |a| {
+      let (length, value, start, slice) = (integer(a, 0)?.try_to_u32()?, integer(a, 1)?, integer(a, 2)?.try_to_u32()?, bits(a, 3)?);
+      let low = value.try_emod(&BigInteger::try_pow2(start)?)?;
+      let high = value.try_shr(start + length)?.try_shl(start + length)?;
+      let middle = slice.slice(0, length as usize).to_unsigned()?.try_shl(start)?;
+      Ok(Value::Integer(high.try_add(&middle)?.try_add(&low)?))
+    });
+    self.define_all(&["count_leading_zeros"], |a| Ok(Value::Integer(BigInteger::from_i64(bits(a, 0)?.leading_zeros() as i64))));
+  }
+
+  fn define_vectors(&mut self) {
+    // Sail's library names the `dec` versions both with and without a suffix.
+    for (suffix, increasing) in [("", false), ("_dec", false), ("_inc", true)] {
+      let names = |names: &[&str]| names.iter().map(|name| format!("{}{}", name, suffix)).collect::<Vec<_>>();
+      for name in names(&["vector_access", "bitvector_access", "access"]) {
+        self.define_pure(&name, move |a| argument(a, 0)?.access(integer(a, 1)?, increasing));
+      }
+      for name in names(&["vector_subrange", "bitvector_subrange", "subrange", "subrange_bits"]) {
+        self.define_pure(&name, move |a| argument(a, 0)?.subrange(integer(a, 1)?, integer(a, 2)?, increasing));
+      }
+      for name in names(&["vector_update", "bitvector_update", "update"]) {
+        self.define_pure(&name, move |a| argument(a, 0)?.clone().update(integer(a, 1)?, argument(a, 2)?.clone(), increasing));
+      }
+      for name in names(&["vector_update_subrange", "bitvector_update_subrange", "update_subrange", "update_subrange_bits"]) {
+        self.define_pure(&name, move |a| {
+          argument(a, 0)?.clone().update_subrange(integer(a, 1)?, integer(a, 2)?, argument(a, 3)?.clone(), increasing)
+        });
+      }
+    }
+    self.define_all(&["append", "vector_concat", "append_64", "bitvector_concat"], |a| argument(a, 0)?.append(argument(a, 1)?));
+    self.define_all(&["vector_init"], |a| Ok(Value::Vector(vec![argument(a, 1)?.clone(); natural(a, 0)?])));
+    self.define_all(&["cons"], |a| match argument(a, 1)? {
+      Value::List(tail) => Ok(Value::List(std::iter::once(argument(a, 0)?.clone()).chain(tail.iter().cloned()).collect())),
+      _ => Err(RuntimeError::Malformed("a list".to_string())),
+    });
+    // Register references, as `register(T)` values, which `bitfields` uses for its `_set_` accessors.
+    self.define("reg_deref", |a, environment| match argument(a, 0)? {
+      Value::Reference(name) => environment.read_register(name),
+      _ => Err(RuntimeError::Malformed("a register reference".to_string())),
+    });
+    self.define("write_register_reference", |a, environment| match argument(a, 0)? {
+      Value::Reference(name) => environment.write_register(name, argument(a, 1)?.clone()).map(|_| Value::Unit),
+      _ => Err(RuntimeError::Malformed("a register reference".to_string())),
+    });
+    self.define_all(&["internal_pick"], |a| match argument(a, 0)? {
+      Value::List(elements) => elements.first().cloned().ok_or_else(|| RuntimeError::Failure("`internal_pick` of an empty list".to_string())),
+      _ => Err(RuntimeError::Malformed("a list".to_string())),
+    });
+  }
+
+  fn define_strings(&mut self) {
+    self.define_all(&["concat_str", "append_str"], |a| Ok(Value::String(format!("{}{}", string(a, 0)?, string(a, 1)?))));
+    self.define_all(&["string_length"], |a| Ok(Value::Integer(BigInteger::from_i64(string(a, 0)?.chars().count() as i64))));
+    self.define_all(&["string_startswith"], |a| Ok(Value::Bool(string(a, 0)?.starts_with(string(a, 1)?))));
+    self.define_all(&["string_drop"], |a| Ok(Value::String(string(a, 0)?.chars().skip(natural(a, 1)?).collect())));
+    self.define_all(&["string_take"], |a| Ok(Value::String(string(a, 0)?.chars().take(natural(a, 1)?).collect())));
+    self.define_all(&["dec_str", "string_of_int"], |a| Ok(Value::String(integer(a, 0)?.to_string())));
+    self.define_all(&["hex_str"], |a| Ok(Value::String(hexadecimal(integer(a, 0)?)?)));
+    self.define_all(&["hex_str_upper"], |a| Ok(Value::String(hexadecimal(integer(a, 0)?)?.to_uppercase().replacen("0X", "0x", 1))));
+    self.define_all(&["string_of_bits", "bits_str"], |a| Ok(Value::String(bits(a, 0)?.to_string())));
+    self.define_all(&["string_of_real"], |a| Ok(Value::String(real(a, 0)?.to_string())));
+
+    self.define_all(&["print", "print_string"], |a| print(false, false, string(a, 0)?));
+    self.define_all(&["print_endline"], |a| print(false, true, string(a, 0)?));
+    self.define_all(&["prerr", "prerr_string"], |a| print(true, false, string(a, 0)?));
+    self.define_all(&["prerr_endline"], |a| print(true, true, string(a, 0)?));
+    self.define_all(&["print_int"], |a| print(false, true, &format!("{}{}", string(a, 0)?, integer(a, 1)?)));
+    self.define_all(&["prerr_int"], |a| print(true, true, &format!("{}{}", string(a, 0)?, integer(a, 1)?)));
+    self.define_all(&["print_bits"], |a| print(false, true, &format!("{}{}", string(a, 0)?, bits(a, 1)?)));
+    self.define_all(&["prerr_bits"], |a| print(true, true, &format!("{}{}", string(a, 0)?, bits(a, 1)?)));
+    self.define_all(&["print_real"], |a| print(false, true, &format!("{}{}", string(a, 0)?, real(a, 1)?)));
+    self.define_all(&["prerr_real"], |a| print(true, true, &format!("{}{}", string(a, 0)?, real(a, 1)?)));
+    self.define_all(&["putchar"], |a| {
+      let character = char::from_u32(integer(a, 0)?.try_to_u32()?).unwrap_or(char::REPLACEMENT_CHARACTER);
+      print(false, false, &character.to_string())
+    });
+  }
+
+  fn define_reals(&mut self) {
+    self.define_all(&["to_real"], |a| Ok(Value::Real(Rational::from_integer(integer(a, 0)?.clone()))));
+    self.define_all(&["add_real"], |a| Ok(Value::Real(real(a, 0)?.checked_add(real(a, 1)?)?)));
+    self.define_all(&["sub_real"], |a| Ok(Value::Real(real(a, 0)?.checked_sub(real(a, 1)?)?)));
+    self.define_all(&["mult_real"], |a| Ok(Value::Real(real(a, 0)?.checked_mul(real(a, 1)?)?)));
+    self.define_all(&["div_real", "quotient_real"], |a| Ok(Value::Real(real(a, 0)?.checked_div(real(a, 1)?)?)));
+    self.define_all(&["neg_real", "negate_real"], |a| Ok(Value::Real(real(a, 0)?.checked_neg()?)));
+    self.define_all(&["abs_real"], |a| {
+      let value = real(a, 0)?;
+      Ok(Value::Real(if value.is_negative() { value.checked_neg()? } else { value.clone() }))
+    });
+    self.define_all(&["sqrt_real"], |a| Ok(Value::Real(real(a, 0)?.sqrt_real()?)));
+    self.define_all(&["real_power"], |a| {
+      let (base, exponent) = (real(a, 0)?, integer(a, 1)?);
+      let mut result = Rational::from_integer(BigInteger::from_i64(1));
+      for _ in 0..exponent.try_abs()?.try_to_usize()? {
+        result = result.checked_mul(base)?;
+      }
+      match exponent.is_negative() {
+        true => Ok(Value::Real(Rational::from_integer(BigInteger::from_i64(1)).checked_div(&result)?)),
+        false => Ok(Value::Real(result)),
+      }
+    });
+    self.define_all(&["round_down", "floor"], |a| Ok(Value::Integer(real(a, 0)?.floor())));
+    self.define_all(&["round_up", "ceil"], |a| Ok(Value::Integer(real(a, 0)?.ceil())));
+    self.define_all(&["lt_real"], |a| Ok(Value::Bool(real(a, 0)? < real(a, 1)?)));
+    self.define_all(&["lteq_real"], |a| Ok(Value::Bool(real(a, 0)? <= real(a, 1)?)));
+    self.define_all(&["gt_real"], |a| Ok(Value::Bool(real(a, 0)? > real(a, 1)?)));
+    self.define_all(&["gteq_real"], |a| Ok(Value::Bool(real(a, 0)? >= real(a, 1)?)));
+  }
+
+  /// Memory and its tags, through the environment. The sizes and addresses before the ones used are those of the
+  /// library's declarations, which pass the width of the address and, for the `_ram` forms, the start of RAM.
+  fn define_memory(&mut self) {
+    self.define("read_ram", |a, environment| Ok(Value::Bitvector(environment.read_memory(address(a, 3)?, natural(a, 1)?)?)));
+    self.define("read_mem", |a, environment| Ok(Value::Bitvector(environment.read_memory(address(a, 2)?, natural(a, 3)?)?)));
+    self.define("write_ram", |a, environment| write_memory(environment, address(a, 3)?, natural(a, 1)?, bits(a, 4)?));
+    self.define("write_mem", |a, environment| write_memory(environment, address(a, 2)?, natural(a, 3)?, bits(a, 4)?));
+    self.define("read_tag_bool", |a, environment| Ok(Value::Bool(environment.read_tag(address(a, 0)?)?)));
+    self.define("write_tag_bool", |a, environment| environment.write_tag(address(a, 0)?, boolean(a, 1)?).map(|_| Value::Unit));
+  }
+
+  /// `undefined` of each type, which like the interpreter's is zero, `false` or empty.
+  fn define_undefined(&mut self) {
+    self.define_all(&["undefined_unit"], |_| Ok(Value::Unit));
+    self.define_all(&["undefined_bool"], |_| Ok(Value::Bool(false)));
+    self.define_all(&["undefined_bit"], |_| Ok(Value::Bit(false)));
+    self.define_all(&["undefined_int", "undefined_nat"], |_| Ok(Value::Integer(BigInteger::from_i64(0))));
+    self.define_all(&["undefined_range"], |a| Ok(Value::Integer(integer(a, 0)?.clone())));
+    self.define_all(&["undefined_atom"], |a| Ok(Value::Integer(integer(a, 0)?.clone())));
+    self.define_all(&["undefined_string"], |_| Ok(Value::String(String::new())));
+    self.define_all(&["undefined_real"], |_| Ok(Value::Real(Rational::zero())));
+    self.define_all(&["undefined_list"], |_| Ok(Value::List(Vec::new())));
+    self.define_all(&["undefined_bitvector", "undefined_bits"], |a| Ok(Value::Bitvector(Bits::zeros(natural(a, 0)?))));
+    self.define_all(&["undefined_vector"], |a| Ok(Value::Vector(vec![argument(a, 1)?.clone(); natural(a, 0)?])));
+  }
+
+  // endregion
+}
+
+/// Writes `data`, which must be `bytes` bytes long, returning the `true` the library's write primitives return.
+fn write_memory(environment: &mut dyn Environment, address: u64, bytes: usize, data: &Bits) -> Result<Value, RuntimeError> {
+  if data.len() != bytes * 8 {
+    return Err(RuntimeError::Malformed(format!("{} bytes of data", bytes)));
+  }
+  environment.write_memory(address, data)?;
+  Ok(Value::Bool(true))
+}
+
+// region Arguments
+
+fn argument(arguments: &[Value], index: usize) -> Result<&Value, RuntimeError> {
+  arguments.get(index).ok_or_else(|| RuntimeError::Malformed(format!("at least {} arguments", index + 1)))
+}
+
+fn boolean(arguments: &[Value], index: usize) -> Result<bool, RuntimeError> {
+  argument(arguments, index)?.as_bool().ok_or_else(|| RuntimeError::Malformed("a boolean".to_string()))
+}
+
+fn integer(arguments: &[Value], index: usize) -> Result<&BigInteger, RuntimeError> {
+  argument(arguments, index)?.as_integer().ok_or_else(|| RuntimeError::Malformed("an integer".to_string()))
+}
+
+/// An integer argument used as a length or an index.
+fn natural(arguments: &[Value], index: usize) -> Result<usize, RuntimeError> {
+  Ok(integer(arguments, index)?.try_to_usize()?)
+}
+
+fn bits(arguments: &[Value], index: usize) -> Result<&Bits, RuntimeError> {
+  argument(arguments, index)?.as_bits().ok_or_else(|| RuntimeError::Malformed("a bitvector".to_string()))
+}
+
+/// An address, as a bitvector or an integer.
+fn address(arguments: &[Value], index: usize) -> Result<u64, RuntimeError> {
+  let address = match argument(arguments, index)? {
+    Value::Bitvector(bits) => bits.to_u64(),
+    Value::Integer(value) => value.to_i64().and_then(|value| u64::try_from(value).ok()),
+    _ => return Err(RuntimeError::Malformed("an address".to_string())),
+  };
+  address.ok_or_else(|| RuntimeError::Failure("address out of range".to_string()))
+}
+
+fn string(arguments: &[Value], index: usize) -> Result<&str, RuntimeError> {
+  argument(arguments, index)?.as_string().ok_or_else(|| RuntimeError::Malformed("a string".to_string()))
+}
+
+fn real(arguments: &[Value], index: usize) -> Result<&Rational, RuntimeError> {
+  match argument(arguments, index)? {
+    Value::Real(value) => Ok(value),
+    _ => Err(RuntimeError::Malformed("a real".to_string())),
+  }
+}
+
+fn not_a_vector() -> RuntimeError {
+  RuntimeError::Malformed("a vector".to_string())
+}
+
+// endregion
+
+/// Fails unless `length` is at most `limit`.
+fn in_range(length: usize, limit: usize) -> Result<(), RuntimeError> {
+  match length <= limit {
+    true => Ok(()),
+    false => Err(RuntimeError::OutOfBounds { index: BigInteger::from_i64(length as i64), length: limit }),
+  }
+}
+
+/// `zero_extend(xs, m)` or `sign_extend(xs, m)`, which may not shorten `xs`.
+fn extend(arguments: &[Value], signed: bool) -> Result<Value, RuntimeError> {
+  let value = bits(arguments, 0)?;
+  let length = natural(arguments, 1)?;
+  in_range(value.len(), length)?;
+  Ok(Value::Bitvector(value.extend(length, signed)))
+}
+
+/// A shift amount given as a bitvector, which shifts everything out if it is too large to represent.
+fn shift_amount(amount: &Bits) -> usize {
+  amount.to_u64().and_then(|amount| usize::try_from(amount).ok()).unwrap_or(usize::MAX)
+}
+
+/// Sail's `hex_str`, which writes negative numbers with a minus sign.
+fn hexadecimal(value: &BigInteger) -> Result<String, ArithmeticError> {
+  let magnitude = value.try_abs()?;
+  let mut digits = String::new();
+  let mut rest = magnitude;
+  let sixteen = BigInteger::from_i64(16);
+  loop {
+    let digit = rest.try_emod(&sixteen)?.to_i64().unwrap_or(0) as u32;
+    digits.insert(0, char::from_digit(digit, 16).unwrap_or('?'));
+    rest = rest.try_ediv(&sixteen)?;
+    if rest.is_zero() {
+      break;
+    }
+  }
+  Ok(format!("{}0x{}", if value.is_negative() { "-" } else { "" }, digits))
+}
+
+fn print(error: bool, newline: bool, text: &str) -> Result<Value, RuntimeError> {
+  match (error, newline) {
+    (false, false) => print!("{}", text),
+    (false, true) => println!("{}", text),
+    (true, false) => eprint!("{}", text),
+    (true, true) => eprintln!("{}", text),
+  }
+  Ok(Value::Unit)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::runtime::{Machine, Memory, RegisterFile, SimpleEnvironment, SparseMemory};
+
+  fn call(primitives: &Primitives, name: &str, arguments: &[Value]) -> Value {
+    let primitive = primitives.get(name).unwrap();
+    primitive(arguments, &mut SimpleEnvironment::default()).unwrap()
+  }
+
+  fn int(value: i64) -> Value {
+    Value::Integer(BigInteger::from_i64(value))
+  }
+
+  fn bits(value: i64, length: usize) -> Value {
+    Value::Bitvector(Bits::from_integer(&BigInteger::from_i64(value), length).unwrap())
+  }
+
+  #[test]
+  fn standard_primitives() {
+    let primitives = Primitives::standard();
+    assert_eq!(call(&primitives, "ediv_int", &[int(-7), int(2)]), int(-4));
+    assert_eq!(call(&primitives, "add_bits", &[bits(0xff, 8), bits(1, 8)]), bits(0, 8));
+    assert_eq!(call(&primitives, "sign_extend", &[bits(0x8, 4), int(8)]), bits(0xf8, 8));
+    assert_eq!(call(&primitives, "vector_subrange", &[bits(0xab, 8), int(7), int(4)]), bits(0xa, 4));
+    assert_eq!(call(&primitives, "vector_access_inc", &[bits(0x2, 4), int(2)]), Value::Bit(true));
+    assert_eq!(call(&primitives, "set_slice_int", &[int(4), int(0xab), int(4), bits(0x1, 4)]), int(0x1b));
+    assert_eq!(call(&primitives, "hex_str", &[int(255)]), Value::String("0xff".to_string()));
+  }
+
+  #[test]
+  fn memory_primitives_use_the_environment() {
+    let primitives = Primitives::standard();
+    let mut machine = Machine::new(RegisterFile::new(false), SparseMemory::default());
+    let write = primitives.get("write_mem").unwrap();
+    let data = bits(0x1234, 16);
+    assert_eq!(write(&[Value::Unit, int(64), bits(0x100, 64), int(2), data.clone()], &mut machine).unwrap(), Value::Bool(true));
+    let read = primitives.get("read_ram").unwrap();
+    assert_eq!(read(&[int(64), int(2), bits(0, 64), bits(0x100, 64)], &mut machine).unwrap(), data);
+    assert_eq!(machine.memory.read(0x100, 1).unwrap().to_u64(), Some(0x34));
+  }
+
+  #[test]
+  fn user_primitives_replace_standard_ones() {
+    let mut primitives = Primitives::standard();
+    let mut replacements = Primitives::new();
+    replacements.define_pure("add_int", |_| Ok(Value::Unit));
+    primitives.extend(&replacements);
+    assert_eq!(call(&primitives, "add_int", &[int(2), int(3)]), Value::Unit);
+  }
+}
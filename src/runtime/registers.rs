@@ -0,0 +1,347 @@
+/*!
+
+A register file, the architectural state a program's `register` declarations describe.
+
+A `RegisterFile` holds a value for each declared register, in declaration order, and is an `Environment` without
+memory, so a program can run against it directly. `Interpreter::register_file` makes one from a program, with the
+values of the registers' initialisers; a compiled backend declares its registers with `declare`. Beyond reading and
+writing whole registers, a test harness can
+
+ * read and write registers as bitvectors, integers and booleans, checked against their declared widths,
+ * read and write the fields of registers whose type is a bitfield,
+ * take a `Snapshot` of every register, restore it later, and list what changed since, and
+ * `reset` the registers to their initial values.
+
+*/
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::runtime::{Bits, Environment, RuntimeError, Value};
+
+/// What a register file knows of a register's type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegisterDeclaration {
+  pub name  : String,
+  /// The length of a register holding a bitvector or a bitfield
+  pub width : Option<usize>,
+  /// The bitfield type of the register, if it has one
+  pub bitfield: Option<String>,
+  /// A bitfield's fields, with their ranges most significant first, as `(high, low)` indices
+  pub fields: Vec<(String, Vec<(BigInteger, BigInteger)>)>,
+}
+
+impl RegisterDeclaration {
+  /// The ranges of the field `name`.
+  pub fn field(&self, name: &str) -> Option<&[(BigInteger, BigInteger)]> {
+    self.fields.iter().find(|(field, _)| field == name).map(|(_, ranges)| ranges.as_slice())
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RegisterFile {
+  /// Whether vectors are indexed from the most significant end, the program's default order
+  increasing  : bool,
+  declarations: Vec<RegisterDeclaration>,
+  /// Values in declaration order
+  values      : Vec<Value>,
+  index       : HashMap<String, usize>,
+  initial     : Snapshot,
+}
+
+impl RegisterFile {
+  /// An empty register file, whose bitfields' ranges are in increasing order if `increasing` is set.
+  pub fn new(increasing: bool) -> Self {
+    RegisterFile { increasing, ..RegisterFile::default() }
+  }
+
+  /// Declares a register, replacing any of the same name, with `value` as its current value.
+  pub fn declare(&mut self, declaration: RegisterDeclaration, value: Value) {
+    match self.index.get(&declaration.name) {
+      Some(&index) => {
+        self.declarations[index] = declaration;
+        self.values[index] = value;
+      }
+      None => {
+        self.index.insert(declaration.name.clone(), self.declarations.len());
+        self.declarations.push(declaration);
+        self.values.push(value);
+      }
+    }
+  }
+
+  /// The declarations of the registers, in declaration order.
+  pub fn declarations(&self) -> &[RegisterDeclaration] {
+    &self.declarations
+  }
+
+  pub fn declaration(&self, name: &str) -> Option<&RegisterDeclaration> {
+    self.index.get(name).map(|&index| &self.declarations[index])
+  }
+
+  /// The registers and their values, in declaration order.
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+    self.declarations.iter().map(|declaration| declaration.name.as_str()).zip(&self.values)
+  }
+
+  fn position(&self, name: &str) -> Result<usize, RuntimeError> {
+    self.index.get(name).copied().ok_or_else(|| RuntimeError::UnknownRegister(name.to_string()))
+  }
+
+  // region Values
+
+  pub fn get(&self, name: &str) -> Result<&Value, RuntimeError> {
+    Ok(&self.values[self.position(name)?])
+  }
+
+  /// Writes `value` to the register `name`, which must be a bitvector of the declared width if the register has one.
+  pub fn set(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+    let index = self.position(name)?;
+    if let Some(width) = self.declarations[index].width {
+      if value.as_bits().map(|bits| bits.len()) != Some(width) {
+        return Err(RuntimeError::Malformed(format!("a bitvector of length {} for the register `{}`", width, name)));
+      }
+    }
+    self.values[index] = value;
+    Ok(())
+  }
+
+  pub fn bits(&self, name: &str) -> Result<&Bits, RuntimeError> {
+    self.get(name)?.as_bits().ok_or_else(|| RuntimeError::Malformed(format!("the register `{}` to be a bitvector", name)))
+  }
+
+  /// A bitvector register as a number, if it fits in 64 bits.
+  pub fn u64(&self, name: &str) -> Result<u64, RuntimeError> {
+    self.bits(name)?.to_u64().ok_or_else(|| RuntimeError::Malformed(format!("the register `{}` to fit in 64 bits", name)))
+  }
+
+  pub fn integer(&self, name: &str) -> Result<&BigInteger, RuntimeError> {
+    self.get(name)?.as_integer().ok_or_else(|| RuntimeError::Malformed(format!("the register `{}` to be an integer", name)))
+  }
+
+  pub fn boolean(&self, name: &str) -> Result<bool, RuntimeError> {
+    self.get(name)?.as_bool().ok_or_else(|| RuntimeError::Malformed(format!("the register `{}` to be a boolean", name)))
+  }
+
+  pub fn set_bits(&mut self, name: &str, bits: Bits) -> Result<(), RuntimeError> {
+    self.set(name, Value::Bitvector(bits))
+  }
+
+  /// Writes `value` to a bitvector register, truncating it to the register's width.
+  pub fn set_u64(&mut self, name: &str, value: u64) -> Result<(), RuntimeError> {
+    let width = self.width(name)?;
+    self.set_bits(name, Bits::from_u64(value, width))
+  }
+
+  pub fn set_integer(&mut self, name: &str, value: BigInteger) -> Result<(), RuntimeError> {
+    self.set(name, Value::Integer(value))
+  }
+
+  pub fn set_boolean(&mut self, name: &str, value: bool) -> Result<(), RuntimeError> {
+    self.set(name, Value::Bool(value))
+  }
+
+  fn width(&self, name: &str) -> Result<usize, RuntimeError> {
+    self.declarations[self.position(name)?]
+        .width
+        .ok_or_else(|| RuntimeError::Malformed(format!("the register `{}` to be a bitvector", name)))
+  }
+
+  // endregion
+
+  // region Bitfields
+
+  fn ranges(&self, register: &str, field: &str) -> Result<&[(BigInteger, BigInteger)], RuntimeError> {
+    self.declarations[self.position(register)?]
+        .field(field)
+        .ok_or_else(|| RuntimeError::UnknownField(register.to_string(), field.to_string()))
+  }
+
+  /// The field `field` of a bitfield register, the concatenation of its ranges.
+  pub fn field(&self, register: &str, field: &str) -> Result<Bits, RuntimeError> {
+    self.read_field(self.get(register)?, self.ranges(register, field)?)
+  }
+
+  fn read_field(&self, value: &Value, ranges: &[(BigInteger, BigInteger)]) -> Result<Bits, RuntimeError> {
+    let mut result = Value::Bitvector(Bits::zeros(0));
+    for (high, low) in ranges {
+      result = result.append(&value.subrange(high, low, self.increasing)?)?;
+    }
+    result.as_bits().cloned().ok_or_else(|| RuntimeError::Malformed("a bitfield".to_string()))
+  }
+
+  /// Writes `bits`, which must be as long as the field, to the field `field` of a bitfield register.
+  pub fn set_field(&mut self, register: &str, field: &str, bits: Bits) -> Result<(), RuntimeError> {
+    let ranges = self.ranges(register, field)?.to_vec();
+    let mut value = self.get(register)?.clone();
+    let mut rest = bits.len();
+    for (high, low) in &ranges {
+      let width = high.try_sub(low)?.try_abs()?.try_to_usize()? + 1;
+      let Some(remaining) = rest.checked_sub(width) else {
+        return Err(RuntimeError::Malformed(format!("a value as long as the field `{}` of `{}`", field, register)));
+      };
+      value = value.update_subrange(high, low, Value::Bitvector(bits.slice(remaining, width)), self.increasing)?;
+      rest = remaining;
+    }
+    if rest != 0 {
+      return Err(RuntimeError::Malformed(format!("a value as long as the field `{}` of `{}`", field, register)));
+    }
+    self.set(register, value)
+  }
+
+  // endregion
+
+  // region Snapshots
+
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot { values: self.iter().map(|(name, value)| (name.to_string(), value.clone())).collect() }
+  }
+
+  /// Writes back the values of the registers in `snapshot`, leaving any it does not have.
+  pub fn restore(&mut self, snapshot: &Snapshot) {
+    for (name, value) in &snapshot.values {
+      if let Some(&index) = self.index.get(name) {
+        self.values[index] = value.clone();
+      }
+    }
+  }
+
+  /// The registers whose values differ from those in `snapshot`, in declaration order.
+  pub fn diff(&self, snapshot: &Snapshot) -> Vec<RegisterChange> {
+    self.iter()
+        .filter_map(|(name, after)| {
+          let before = snapshot.get(name)?;
+          (before != after).then(|| self.change(name, before, after))
+        })
+        .collect()
+  }
+
+  fn change(&self, name: &str, before: &Value, after: &Value) -> RegisterChange {
+    let mut fields = Vec::new();
+    for (field, ranges) in self.declaration(name).map(|declaration| declaration.fields.as_slice()).unwrap_or_default() {
+      if let (Ok(old), Ok(new)) = (self.read_field(before, ranges), self.read_field(after, ranges)) {
+        if old != new {
+          fields.push((field.clone(), old, new));
+        }
+      }
+    }
+    RegisterChange { name: name.to_string(), before: before.clone(), after: after.clone(), fields }
+  }
+
+  /// Takes the current values as the initial ones, which `reset` restores.
+  pub fn record_initial_values(&mut self) {
+    self.initial = self.snapshot();
+  }
+
+  pub fn reset(&mut self) {
+    let initial = std::mem::take(&mut self.initial);
+    self.restore(&initial);
+    self.initial = initial;
+  }
+
+  // endregion
+}
+
+impl Environment for RegisterFile {
+  fn read_register(&mut self, name: &str) -> Result<Value, RuntimeError> {
+    self.get(name).cloned()
+  }
+
+  fn write_register(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+    self.set(name, value)
+  }
+}
+
+/// The values of a register file's registers at some point.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+  values: Vec<(String, Value)>,
+}
+
+impl Snapshot {
+  pub fn get(&self, name: &str) -> Option<&Value> {
+    self.values.iter().find(|(register, _)| register == name).map(|(_, value)| value)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+    self.values.iter().map(|(name, value)| (name.as_str(), value))
+  }
+}
+
+/// A register whose value changed, with the fields that changed if it is a bitfield.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisterChange {
+  pub name  : String,
+  pub before: Value,
+  pub after : Value,
+  pub fields: Vec<(String, Bits, Bits)>,
+}
+
+impl Display for RegisterChange {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: {} -> {}", self.name, self.before, self.after)?;
+    for (field, before, after) in &self.fields {
+      write!(f, "\n  {}: {} -> {}", field, before, after)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bits(value: i64, length: usize) -> Bits {
+    Bits::from_integer(&BigInteger::from_i64(value), length).unwrap()
+  }
+
+  fn register_file() -> RegisterFile {
+    let mut registers = RegisterFile::new(false);
+    let status = RegisterDeclaration {
+      name    : "STATUS".to_string(),
+      width   : Some(8),
+      bitfield: Some("Status".to_string()),
+      fields  : vec![
+        ("MODE".to_string(), vec![(BigInteger::from_i64(7), BigInteger::from_i64(6))]),
+        ("SPLIT".to_string(), vec![(BigInteger::from_i64(5), BigInteger::from_i64(4)), (BigInteger::from_i64(1), BigInteger::from_i64(0))]),
+      ],
+    };
+    registers.declare(status, Value::Bitvector(bits(0, 8)));
+    registers.declare(RegisterDeclaration { name: "PC".to_string(), width: Some(16), ..RegisterDeclaration::default() }, Value::Bitvector(bits(0, 16)));
+    registers.record_initial_values();
+    registers
+  }
+
+  #[test]
+  fn typed_access_and_fields() {
+    let mut registers = register_file();
+    registers.set_u64("PC", 0x1234).unwrap();
+    assert_eq!(registers.u64("PC").unwrap(), 0x1234);
+    assert!(registers.set_bits("PC", bits(0, 8)).is_err());
+    assert_eq!(registers.set_u64("NOPE", 0), Err(RuntimeError::UnknownRegister("NOPE".to_string())));
+
+    registers.set_field("STATUS", "SPLIT", bits(0b1001, 4)).unwrap();
+    assert_eq!(registers.u64("STATUS").unwrap(), 0b0010_0001);
+    assert_eq!(registers.field("STATUS", "SPLIT").unwrap(), bits(0b1001, 4));
+    assert_eq!(registers.field("STATUS", "MODE").unwrap(), bits(0, 2));
+    assert!(registers.set_field("STATUS", "MODE", bits(0, 3)).is_err());
+  }
+
+  #[test]
+  fn snapshots_and_diffs() {
+    let mut registers = register_file();
+    let before = registers.snapshot();
+    registers.set_field("STATUS", "MODE", bits(0b11, 2)).unwrap();
+    let changes = registers.diff(&before);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].name, "STATUS");
+    assert_eq!(changes[0].fields, vec![("MODE".to_string(), bits(0, 2), bits(0b11, 2))]);
+
+    registers.restore(&before);
+    assert!(registers.diff(&before).is_empty());
+    registers.set_u64("PC", 4).unwrap();
+    registers.reset();
+    assert_eq!(registers.u64("PC").unwrap(), 0);
+  }
+}
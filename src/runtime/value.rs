@@ -0,0 +1,232 @@
+/*!
+
+The values Sail programs compute with.
+
+*/
+
+use std::fmt::{Display, Formatter};
+
+use crate::abstractions::{BigInteger, Integer, Rational};
+use crate::runtime::{Bits, RuntimeError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+  Unit,
+  Bool(bool),
+  Bit(bool),
+  Integer(BigInteger),
+  Real(Rational),
+  String(String),
+  Bitvector(Bits),
+  /// A vector of anything but bits, its elements by position, least significant first (see `Bits`)
+  Vector(Vec<Value>),
+  List(Vec<Value>),
+  Tuple(Vec<Value>),
+  /// A struct, its fields in the order its type declares them
+  Record(Vec<(String, Value)>),
+  /// A member of an enum
+  Member(String),
+  /// A union value, the payload of a constructor without one being `Unit`
+  Constructor(String, Box<Value>),
+  /// A reference to a register, made by `ref R`
+  Reference(String),
+}
+
+impl Value {
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Value::Bool(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_integer(&self) -> Option<&BigInteger> {
+    match self {
+      Value::Integer(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_bits(&self) -> Option<&Bits> {
+    match self {
+      Value::Bitvector(bits) => Some(bits),
+      _ => None,
+    }
+  }
+
+  pub fn as_string(&self) -> Option<&str> {
+    match self {
+      Value::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  /// The value of the field `name` of a struct.
+  pub fn field(&self, name: &str) -> Option<&Value> {
+    match self {
+      Value::Record(fields) => fields.iter().find(|(field, _)| field == name).map(|(_, value)| value),
+      _ => None,
+    }
+  }
+}
+
+// region Vectors
+
+/// The position of `index` in a vector of `length` elements, `increasing` if the order is `inc` (see `Bits`).
+pub fn position(index: &BigInteger, length: usize, increasing: bool) -> Result<usize, RuntimeError> {
+  let index_value = match index.try_to_usize() {
+    Ok(index) if index < length => index,
+    _ => return Err(RuntimeError::OutOfBounds { index: index.clone(), length }),
+  };
+  match increasing {
+    true => Ok(length - 1 - index_value),
+    false => Ok(index_value),
+  }
+}
+
+/// The lowest position and the width of `high..low`.
+fn span(high: &BigInteger, low: &BigInteger, length: usize, increasing: bool) -> Result<(usize, usize), RuntimeError> {
+  let high = position(high, length, increasing)?;
+  let low = position(low, length, increasing)?;
+  Ok((high.min(low), high.abs_diff(low) + 1))
+}
+
+fn not_a_vector() -> RuntimeError {
+  RuntimeError::Malformed("a vector".to_string())
+}
+
+/// Vector operations, on bitvectors and on vectors of anything else alike, indexed in the order given.
+impl Value {
+  /// The number of bits or elements of a vector.
+  pub fn length(&self) -> Option<usize> {
+    match self {
+      Value::Bitvector(bits) => Some(bits.len()),
+      Value::Vector(elements) => Some(elements.len()),
+      _ => None,
+    }
+  }
+
+  pub fn access(&self, index: &BigInteger, increasing: bool) -> Result<Value, RuntimeError> {
+    match self {
+      Value::Bitvector(bits) => Ok(Value::Bit(bits.get(position(index, bits.len(), increasing)?))),
+      Value::Vector(elements) => Ok(elements[position(index, elements.len(), increasing)?].clone()),
+      _ => Err(not_a_vector()),
+    }
+  }
+
+  pub fn subrange(&self, high: &BigInteger, low: &BigInteger, increasing: bool) -> Result<Value, RuntimeError> {
+    match self {
+      Value::Bitvector(bits) => {
+        let (low, width) = span(high, low, bits.len(), increasing)?;
+        Ok(Value::Bitvector(bits.slice(low, width)))
+      }
+      Value::Vector(elements) => {
+        let (low, width) = span(high, low, elements.len(), increasing)?;
+        Ok(Value::Vector(elements[low..low + width].to_vec()))
+      }
+      _ => Err(not_a_vector()),
+    }
+  }
+
+  pub fn update(self, index: &BigInteger, value: Value, increasing: bool) -> Result<Value, RuntimeError> {
+    match (self, value) {
+      (Value::Bitvector(mut bits), Value::Bit(bit)) => {
+        bits.set(position(index, bits.len(), increasing)?, bit);
+        Ok(Value::Bitvector(bits))
+      }
+      (Value::Vector(mut elements), value) => {
+        let position = position(index, elements.len(), increasing)?;
+        elements[position] = value;
+        Ok(Value::Vector(elements))
+      }
+      _ => Err(RuntimeError::Malformed("a vector and an element".to_string())),
+    }
+  }
+
+  pub fn update_subrange(self, high: &BigInteger, low: &BigInteger, value: Value, increasing: bool) -> Result<Value, RuntimeError> {
+    match (self, value) {
+      (Value::Bitvector(bits), Value::Bitvector(value)) => {
+        let (low, _) = span(high, low, bits.len(), increasing)?;
+        Ok(Value::Bitvector(bits.with_slice(low, &value)))
+      }
+      (Value::Vector(mut elements), Value::Vector(value)) => {
+        let (low, width) = span(high, low, elements.len(), increasing)?;
+        for (offset, element) in value.into_iter().take(width).enumerate() {
+          elements[low + offset] = element;
+        }
+        Ok(Value::Vector(elements))
+      }
+      _ => Err(RuntimeError::Malformed("two vectors".to_string())),
+    }
+  }
+
+  /// `self @ low`, in which `low` has the low positions.
+  pub fn append(&self, low: &Value) -> Result<Value, RuntimeError> {
+    match (self, low) {
+      (Value::Bitvector(high), Value::Bitvector(low)) => Ok(Value::Bitvector(high.append(low))),
+      (Value::Vector(high), Value::Vector(low)) => Ok(Value::Vector(low.iter().chain(high).cloned().collect())),
+      _ => Err(RuntimeError::Malformed("two vectors".to_string())),
+    }
+  }
+}
+
+// endregion
+
+/// Writes `values` separated by commas.
+fn comma_separated<'a>(f: &mut Formatter<'_>, values: impl Iterator<Item = &'a Value>) -> std::fmt::Result {
+  for (index, value) in values.enumerate() {
+    if index > 0 {
+      write!(f, ", ")?;
+    }
+    write!(f, "{}", value)?;
+  }
+  Ok(())
+}
+
+/// Values are written as Sail expressions.
+impl Display for Value {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Value::Unit => write!(f, "()"),
+      Value::Bool(value) => write!(f, "{}", value),
+      Value::Bit(false) => write!(f, "bitzero"),
+      Value::Bit(true) => write!(f, "bitone"),
+      Value::Integer(value) => write!(f, "{}", value),
+      Value::Real(value) => write!(f, "{}", value),
+      Value::String(value) => write!(f, "{:?}", value),
+      Value::Bitvector(bits) => write!(f, "{}", bits),
+      Value::Vector(elements) => {
+        write!(f, "[")?;
+        comma_separated(f, elements.iter().rev())?;
+        write!(f, "]")
+      }
+      Value::List(elements) => {
+        write!(f, "[|")?;
+        comma_separated(f, elements.iter())?;
+        write!(f, "|]")
+      }
+      Value::Tuple(elements) => {
+        write!(f, "(")?;
+        comma_separated(f, elements.iter())?;
+        write!(f, ")")
+      }
+      Value::Record(fields) => {
+        write!(f, "struct {{ ")?;
+        for (index, (name, value)) in fields.iter().enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{} = {}", name, value)?;
+        }
+        write!(f, " }}")
+      }
+      Value::Member(name) => write!(f, "{}", name),
+      Value::Constructor(name, payload) => match &**payload {
+        Value::Unit => write!(f, "{}()", name),
+        Value::Tuple(_) => write!(f, "{}{}", name, payload),
+        payload => write!(f, "{}({})", name, payload),
+      },
+      Value::Reference(name) => write!(f, "ref {}", name),
+    }
+  }
+}
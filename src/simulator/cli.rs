@@ -0,0 +1,411 @@
+/*!
+
+The command line of `rigging-sim`.
+
+```text
+rigging-sim [options] <model>... [--elf <program>]
+```
+
+The model is a list of Sail files, loaded in order, or a single `.sail_project` file. The crate has no Sail front end
+of its own, so the binary is built around `main`, which takes the parser to load the model with.
+
+With `--gdb` or `--gdb-stdio`, the simulator waits for GDB to connect instead of running the program itself, and
+serves it until it detaches (see `gdb`). With `--debug`, the program runs under the source-level debugger, taking
+commands on standard input (see `debugger::console`); with `--dap`, the debugger speaks the Debug Adapter Protocol on
+standard input and output, and everything the program prints goes to standard error.
+
+With `--trace-file`, a record of each step is written to a file, or to standard output if it is `-`, as text or as
+JSON lines (see `trace`). JSON lines are the default for a file ending `.jsonl`.
+
+The exit status is the exit code the program gave through `tohost`, zero if the model called `exit`, and one if the
+model could not be loaded, a step failed, or the step limit was reached.
+
+*/
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::abstractions::Integer;
+use crate::debugger::console::Console;
+use crate::debugger::dap::DebugAdapter;
+use crate::debugger::{SourceDebugger, Sources};
+use crate::driver::{check_model, describe_location};
+use crate::interpreter::Interpreter;
+use crate::project::{Loader, ModuleSelection, SourceParser};
+use crate::parser::location::Located;
+use crate::runtime::{Endianness, LocatedRuntimeError, MemoryConfig, RuntimeError, SparseMemory, Value};
+use crate::simulator::gdb::{self, GdbServer, TargetDescription};
+use crate::simulator::trace::{TraceFormat, TraceWriter};
+use crate::simulator::{Simulator, SimulatorOptions, Stop};
+
+pub const USAGE: &str = "\
+usage: rigging-sim [options] <model>... [--elf <program>]
+
+options:
+  --elf <file>        load the ELF executable <file> into memory
+  --step <name>       the function running one step (default: step)
+  --limit <n>         stop after <n> steps
+  --pc <register>     the register to set to the program's entry point (default: PC)
+  --tohost <address>  the address of tohost, if the program has no such symbol
+  --big-endian        store values in memory most significant byte first
+  --aligned           fault on accesses not aligned to their size
+  --trace             print the registers each step changes
+  --trace-file <file> write a record of each step to <file>, or to standard output if it is -
+  --trace-format <f>  write the records as text or jsonl (default: jsonl for a .jsonl file, otherwise text)
+  --execute <name>    the function whose argument is the decoded instruction (default: execute)
+  --gdb <port>        serve GDB on the local TCP port <port> instead of running
+  --gdb-stdio         serve GDB on standard input and output instead of running
+  --gdb-target <file> describe the registers to GDB as <file> gives them
+  --debug             run under the source-level debugger, reading commands from standard input
+  --dap               run under the source-level debugger, speaking the Debug Adapter Protocol on standard input
+                      and output
+  --help              print this message";
+
+/// Where to serve GDB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GdbTransport {
+  /// A TCP port on the local host
+  Tcp(u16),
+  Stdio,
+}
+
+/// How to debug the model's source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugFrontend {
+  Console,
+  /// The Debug Adapter Protocol on standard input and output
+  Adapter,
+}
+
+/// What the command line asks for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Arguments {
+  pub model       : Vec<PathBuf>,
+  pub elf         : Option<PathBuf>,
+  pub memory      : MemoryConfig,
+  pub options     : SimulatorOptions,
+  pub gdb         : Option<GdbTransport>,
+  /// A GDB target description file
+  pub gdb_target  : Option<PathBuf>,
+  pub debug       : Option<DebugFrontend>,
+  pub trace_file  : Option<PathBuf>,
+  pub trace_format: Option<TraceFormat>,
+  pub help        : bool,
+}
+
+pub fn parse_arguments(arguments: impl IntoIterator<Item = String>) -> Result<Arguments, String> {
+  let mut parsed = Arguments::default();
+  let mut arguments = arguments.into_iter();
+  while let Some(argument) = arguments.next() {
+    let mut value = |option: &str| arguments.next().ok_or_else(|| format!("{} needs a value", option));
+    match argument.as_str() {
+      "--elf" => parsed.elf = Some(PathBuf::from(value("--elf")?)),
+      "--step" => parsed.options.step = value("--step")?,
+      "--limit" => parsed.options.limit = Some(parse_number(&value("--limit")?)?),
+      "--pc" => parsed.options.pc = value("--pc")?,
+      "--tohost" => parsed.options.tohost = Some(parse_number(&value("--tohost")?)?),
+      "--big-endian" => parsed.memory.endianness = Endianness::Big,
+      "--aligned" => parsed.memory.aligned = true,
+      "--trace" => parsed.options.trace = true,
+      "--trace-file" => {
+        parsed.trace_file = Some(PathBuf::from(value("--trace-file")?));
+        parsed.options.record = true;
+      }
+      "--trace-format" => parsed.trace_format = Some(TraceFormat::parse(&value("--trace-format")?)?),
+      "--execute" => parsed.options.execute = value("--execute")?,
+      "--gdb" => {
+        let port = value("--gdb")?;
+        let port = port.parse().map_err(|_| format!("{} is not a port", port))?;
+        parsed.gdb = Some(GdbTransport::Tcp(port));
+      }
+      "--gdb-stdio" => parsed.gdb = Some(GdbTransport::Stdio),
+      "--gdb-target" => parsed.gdb_target = Some(PathBuf::from(value("--gdb-target")?)),
+      "--debug" => parsed.debug = Some(DebugFrontend::Console),
+      "--dap" => parsed.debug = Some(DebugFrontend::Adapter),
+      "--help" | "-h" => parsed.help = true,
+      option if option.starts_with('-') => return Err(format!("unknown option {}", option)),
+      _ => parsed.model.push(PathBuf::from(argument)),
+    }
+  }
+  if parsed.model.is_empty() && !parsed.help {
+    return Err("no model given".to_string());
+  }
+  if parsed.gdb.is_some() && parsed.debug.is_some() {
+    return Err("GDB and the source-level debugger cannot be used together".to_string());
+  }
+  Ok(parsed)
+}
+
+/// A decimal number, or a hexadecimal one prefixed with `0x`.
+pub fn parse_number(text: &str) -> Result<u64, String> {
+  let result = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+    Some(digits) => u64::from_str_radix(&digits.replace('_', ""), 16),
+    None => text.replace('_', "").parse(),
+  };
+  result.map_err(|_| format!("{} is not a number", text))
+}
+
+/// Runs the simulator as the command line `arguments`, without the program name, asks, loading the model with `parser`.
+pub fn main<P: SourceParser>(parser: P, arguments: impl IntoIterator<Item = String>) -> ExitCode {
+  let arguments = match parse_arguments(arguments) {
+    Ok(arguments) => arguments,
+    Err(message) => {
+      eprintln!("rigging-sim: {}\n{}", message, USAGE);
+      return ExitCode::FAILURE;
+    }
+  };
+  if arguments.help {
+    println!("{}", USAGE);
+    return ExitCode::SUCCESS;
+  }
+
+  let loader = Loader::new(parser);
+  let project = match arguments.model.as_slice() {
+    [project] if project.extension().is_some_and(|extension| extension == "sail_project") => {
+      loader.load_project(project, &ModuleSelection::new())
+    }
+    model => loader.load_files(model),
+  };
+  let (model, diagnostics) = check_model(project);
+  for diagnostic in &diagnostics {
+    eprintln!("{}", diagnostic.describe(&model.code_map));
+  }
+  if diagnostics.iter().any(|diagnostic| diagnostic.fatal) {
+    return ExitCode::FAILURE;
+  }
+
+  let report = |error: LocatedRuntimeError| {
+    match describe_location(&model.code_map, &error.location) {
+      Some(location) => eprintln!("{}: {}", location, error.value),
+      None => eprintln!("rigging-sim: {}", error.value),
+    }
+    ExitCode::FAILURE
+  };
+
+  let mut interpreter = Interpreter::new(&model.definitions, model.typing.clone());
+  let sources = Sources::of(&model.code_map, &model.definitions);
+  match arguments.debug {
+    Some(DebugFrontend::Console) => {
+      interpreter.set_debugger(Some(Box::new(SourceDebugger::new(sources, Console::stdio()))));
+    }
+    Some(DebugFrontend::Adapter) => {
+      print_to_stderr(&mut interpreter);
+      interpreter.set_debugger(Some(Box::new(SourceDebugger::new(sources, DebugAdapter::stdio()))));
+    }
+    None => {}
+  }
+  let mut simulator = match Simulator::new(interpreter, SparseMemory::new(arguments.memory), arguments.options) {
+    Ok(simulator) => simulator,
+    Err(error) => return report(error),
+  };
+  if let Some(elf) = &arguments.elf {
+    if let Err(error) = simulator.load_elf(elf) {
+      return report(error);
+    }
+  }
+
+  if let Some(transport) = arguments.gdb {
+    return serve_gdb(&mut simulator, transport, arguments.gdb_target.as_deref(), report);
+  }
+
+  // Standard output carries the debug adapter's messages.
+  let standard_output = || -> Box<dyn Write> {
+    match arguments.debug {
+      Some(DebugFrontend::Adapter) => Box::new(std::io::stderr()),
+      _ => Box::new(std::io::stdout()),
+    }
+  };
+  let mut trace = match &arguments.trace_file {
+    Some(path) => {
+      let format = arguments.trace_format.unwrap_or(match path.extension().is_some_and(|extension| extension == "jsonl") {
+        true => TraceFormat::Jsonl,
+        false => TraceFormat::Text,
+      });
+      let output: Box<dyn Write> = match path.to_str() {
+        Some("-") => standard_output(),
+        _ => match std::fs::File::create(path) {
+          Ok(file) => Box::new(std::io::BufWriter::new(file)),
+          Err(error) => {
+            eprintln!("rigging-sim: {}: {}", path.display(), error);
+            return ExitCode::FAILURE;
+          }
+        },
+      };
+      Some(TraceWriter::new(output, format))
+    }
+    None => None,
+  };
+
+  let result = run(&mut simulator, &mut *standard_output(), trace.as_mut());
+  if let Some(mut debugger) = simulator.interpreter_mut().set_debugger(None) {
+    debugger.finished(match result {
+      Ok(Stop::Exit(code)) => code as i64,
+      Ok(Stop::Halted) => 0,
+      _ => 1,
+    });
+  }
+  match result {
+    Ok(stop) => {
+      match stop {
+        Stop::Exit(code) => eprintln!("rigging-sim: exited with code {} after {} steps", code, simulator.steps()),
+        Stop::Halted => {}
+        Stop::Limit => eprintln!("rigging-sim: stopped after {} steps", simulator.steps()),
+      }
+      exit_status(stop)
+    }
+    Err(error) => report(error),
+  }
+}
+
+/// Runs `simulator` until it stops, writing the registers each step changes and what the program prints to `output`,
+/// and a record of each step to `trace`, if there is one. A trace that cannot be written is reported and given up.
+fn run(
+  simulator: &mut Simulator,
+  output   : &mut dyn Write,
+  mut trace: Option<&mut TraceWriter<Box<dyn Write>>>,
+) -> Result<Stop, LocatedRuntimeError> {
+  let result = simulator.run(|step, simulator| {
+    for change in &step.changes {
+      let _ = writeln!(output, "[{}] {}", step.number, change);
+    }
+    if let Some(record) = &step.record {
+      if let Some(Err(error)) = trace.as_mut().map(|writer| writer.write(record)) {
+        eprintln!("rigging-sim: writing the trace: {}", error);
+        trace = None;
+      }
+    }
+    let console = simulator.take_console();
+    if !console.is_empty() {
+      let _ = output.write_all(&console);
+      let _ = output.flush();
+    }
+  });
+  if let Some(writer) = trace {
+    if let Err(error) = writer.flush() {
+      eprintln!("rigging-sim: writing the trace: {}", error);
+    }
+  }
+  result
+}
+
+/// The exit status for a simulation that stopped for `stop`.
+fn exit_status(stop: Stop) -> ExitCode {
+  match stop {
+    Stop::Exit(code) => ExitCode::from(code.min(255) as u8),
+    Stop::Halted => ExitCode::SUCCESS,
+    Stop::Limit => ExitCode::FAILURE,
+  }
+}
+
+/// Makes Sail's printing primitives write to standard error.
+fn print_to_stderr(interpreter: &mut Interpreter) {
+  let pairs = [
+    ("print", "prerr"),
+    ("print_string", "prerr_string"),
+    ("print_endline", "prerr_endline"),
+    ("print_int", "prerr_int"),
+    ("print_bits", "prerr_bits"),
+    ("print_real", "prerr_real"),
+  ];
+  for (print, prerr) in pairs {
+    if let Some(prerr) = interpreter.primitives().get(prerr).cloned() {
+      interpreter.define_extern(print, move |arguments, environment| prerr(arguments, environment));
+    }
+  }
+  interpreter.define_extern("putchar", |arguments, _| {
+    if let [Value::Integer(code), ..] = arguments {
+      let character = code.try_to_u32().ok().and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+      eprint!("{}", character);
+    }
+    Ok(Value::Unit)
+  });
+}
+
+fn serve_gdb(
+  simulator : &mut Simulator,
+  transport : GdbTransport,
+  target    : Option<&Path>,
+  report    : impl Fn(LocatedRuntimeError) -> ExitCode,
+) -> ExitCode {
+  let description = match target {
+    Some(path) => match std::fs::read_to_string(path).map_err(|error| error.to_string()).and_then(|text| TargetDescription::parse(&text)) {
+      Ok(description) => description,
+      Err(error) => {
+        eprintln!("rigging-sim: {}: {}", path.display(), error);
+        return ExitCode::FAILURE;
+      }
+    },
+    None => TargetDescription::from_registers(&simulator.machine().registers),
+  };
+
+  let mut server = GdbServer::new(simulator, description);
+  let result = match transport {
+    GdbTransport::Tcp(port) => {
+      eprintln!("rigging-sim: waiting for GDB on port {}", port);
+      gdb::accept(&format!("127.0.0.1:{}", port)).and_then(|mut stream| server.serve(&mut stream))
+    }
+    GdbTransport::Stdio => server.serve(&mut gdb::Stdio::new()),
+  };
+  match result {
+    Ok(Some(Stop::Exit(code))) => ExitCode::from(code.min(255) as u8),
+    Ok(_) => ExitCode::SUCCESS,
+    Err(error) => report(Located::from(RuntimeError::Failure(format!("GDB connection: {}", error)))),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::simulator::tests::{model, Action};
+
+  fn parse(arguments: &[&str]) -> Result<Arguments, String> {
+    parse_arguments(arguments.iter().map(|argument| argument.to_string()))
+  }
+
+  #[test]
+  fn parses_options() {
+    let arguments = parse(&["a.sail", "--limit", "1_000", "b.sail", "--tohost", "0x8000_1000", "--trace", "--elf", "x"]).unwrap();
+    assert_eq!(arguments.model, vec![PathBuf::from("a.sail"), PathBuf::from("b.sail")]);
+    assert_eq!(arguments.elf, Some(PathBuf::from("x")));
+    assert_eq!(arguments.options.limit, Some(1_000));
+    assert_eq!(arguments.options.tohost, Some(0x8000_1000));
+    assert!(arguments.options.trace);
+    assert_eq!(arguments.options.step, "step");
+
+    assert!(parse(&["--limit"]).is_err());
+    assert!(parse(&["a.sail", "--limit", "ten"]).is_err());
+    assert!(parse(&["a.sail", "--unknown"]).is_err());
+    assert_eq!(parse(&["a.sail", "--gdb", "1234"]).unwrap().gdb, Some(GdbTransport::Tcp(1234)));
+    assert!(parse(&["a.sail", "--gdb", "port"]).is_err());
+    assert_eq!(parse(&["a.sail", "--dap"]).unwrap().debug, Some(DebugFrontend::Adapter));
+    assert!(parse(&["a.sail", "--debug", "--gdb-stdio"]).is_err());
+    let traced = parse(&["a.sail", "--trace-file", "-", "--trace-format", "jsonl"]).unwrap();
+    assert!(traced.options.record);
+    assert_eq!(traced.trace_format, Some(TraceFormat::Jsonl));
+    assert!(parse(&["a.sail", "--trace-format", "binary"]).is_err());
+    assert!(parse(&[]).is_err());
+  }
+
+  #[test]
+  fn runs_print_register_changes_and_console_output() {
+    let options = SimulatorOptions { trace: true, ..SimulatorOptions::default() };
+    let (mut simulator, _) = model(false, vec![Action::Print(b'o'), Action::Print(b'k'), Action::Exit(7)], options);
+    let mut output = Vec::new();
+    assert_eq!(run(&mut simulator, &mut output, None), Ok(Stop::Exit(7)));
+    // What a step prints follows the registers it changed.
+
+    assert_eq!(String::from_utf8(output).unwrap(), "\
+      [0] PC: 0x0000000000000000 -> 0x0000000000000004\n\
+      o[1] PC: 0x0000000000000004 -> 0x0000000000000008\n\
+      k[2] PC: 0x0000000000000008 -> 0x000000000000000C\n");
+  }
+
+  #[test]
+  fn exit_status_follows_the_stop() {
+    assert_eq!(exit_status(Stop::Exit(3)), ExitCode::from(3));
+    assert_eq!(exit_status(Stop::Exit(1000)), ExitCode::from(255));
+    assert_eq!(exit_status(Stop::Halted), ExitCode::SUCCESS);
+    assert_eq!(exit_status(Stop::Limit), ExitCode::FAILURE);
+  }
+}
@@ -0,0 +1,845 @@
+/*!
+
+A stub for GDB's remote serial protocol, so that GDB can debug a program running on the simulator.
+
+GDB talks to the stub in packets, `$data#checksum`, over a TCP socket (`target remote :1234`) or the stub's standard
+input and output (`target remote | rigging-sim ...`). The stub serves
+
+ * reading and writing registers, `g`, `G`, `p` and `P`,
+ * reading and writing memory, `m`, `M` and `X`, through the memory model without its access checks,
+ * software and hardware breakpoints, `Z0`, `Z1` and their removal, both stopping before the step run at the address,
+ * stepping, `s`, and continuing, `c`, until a breakpoint, an interrupt, or the end of the program, which is reported
+   as the program's exit code, and
+ * the target description, through `qXfer:features:read`.
+
+GDB numbers registers, and a `TargetDescription` says which of the model's registers each number is. By default every
+bitvector register of the model is described, in declaration order, under its own name. GDB's support for an
+architecture expects particular registers in a particular order, which a description file gives:
+
+```text
+# Comments run to the end of the line.
+architecture riscv:rv64
+feature org.gnu.gdb.riscv.cpu
+register zero 64 -
+register ra 64 x1
+register pc 64 PC code_ptr
+```
+
+Each `register` line gives the name GDB knows the register by, its size in bits, the model's register holding it, `-`
+for one that reads as zero and ignores writes, or `R[n]` for the element at position `n` of a vector register, and
+optionally the register's GDB type. Register values go to GDB in the memory's byte order.
+
+Characters the program writes to the console are forwarded to GDB while it runs.
+
+*/
+
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::runtime::{Bits, Endianness, Memory, RegisterFile, RuntimeError, Value};
+use crate::simulator::{Simulator, Stop};
+
+/// The signal reported when the target stops after a step or at a breakpoint: `SIGTRAP`.
+const SIGTRAP: u8 = 5;
+/// The signal reported when GDB interrupts the target: `SIGINT`.
+const SIGINT: u8 = 2;
+/// The signal reported when a step fails: `SIGABRT`.
+const SIGABRT: u8 = 6;
+/// How many steps run between checks for an interrupt from GDB.
+const INTERRUPT_INTERVAL: u64 = 1024;
+/// The largest packet the stub accepts.
+const PACKET_SIZE: usize = 0x4000;
+
+/// A register as GDB sees it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetRegister {
+  pub name  : String,
+  /// The size in bits, a multiple of eight
+  pub bits  : usize,
+  pub source: RegisterSource,
+  /// GDB's type for the register, such as `code_ptr`, if not an integer
+  pub typ   : Option<String>,
+}
+
+/// Where the value of a register GDB sees is kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegisterSource {
+  /// A register of the model
+  Register(String),
+  /// The element at a position of a vector register of the model
+  Element(String, usize),
+  /// Nothing: the register reads as zero and ignores writes.
+  Zero,
+}
+
+/// The registers GDB sees, in the order it numbers them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TargetDescription {
+  /// GDB's name for the architecture, such as `riscv:rv64`
+  pub architecture: Option<String>,
+  /// The name of the feature holding the registers
+  pub feature     : String,
+  pub registers   : Vec<TargetRegister>,
+}
+
+impl TargetDescription {
+  /// Describes every register of `registers` with a width, under its own name, in declaration order.
+  pub fn from_registers(registers: &RegisterFile) -> Self {
+    let registers = registers
+        .declarations()
+        .iter()
+        .filter_map(|declaration| {
+          Some(TargetRegister {
+            name  : declaration.name.clone(),
+            bits  : declaration.width?.div_ceil(8) * 8,
+            source: RegisterSource::Register(declaration.name.clone()),
+            typ   : None,
+          })
+        })
+        .collect();
+    TargetDescription { architecture: None, feature: "org.rigging.sail".to_string(), registers }
+  }
+
+  /// Parses a description file, in the format the module describes.
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut description = TargetDescription { feature: "org.rigging.sail".to_string(), ..TargetDescription::default() };
+    for (number, line) in text.lines().enumerate() {
+      let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+      let error = |message: &str| format!("line {}: {}", number + 1, message);
+      match words.as_slice() {
+        [] => {}
+        ["architecture", architecture] => description.architecture = Some(architecture.to_string()),
+        ["feature", feature] => description.feature = feature.to_string(),
+        ["register", name, bits, rest @ ..] if rest.len() <= 2 => {
+          let bits: usize = bits.parse().map_err(|_| error("a register's size must be a number"))?;
+          if bits == 0 || !bits.is_multiple_of(8) {
+            return Err(error("a register's size must be a multiple of eight"));
+          }
+          let source = match rest.first() {
+            None | Some(&"-") => RegisterSource::Zero,
+            Some(source) => parse_source(source).ok_or_else(|| error("malformed register"))?,
+          };
+          let typ = rest.get(1).map(|typ| typ.to_string());
+          description.registers.push(TargetRegister { name: name.to_string(), bits, source, typ });
+        }
+        _ => return Err(error("expected `architecture`, `feature` or `register`")),
+      }
+    }
+    Ok(description)
+  }
+
+  /// The description in GDB's XML format.
+  pub fn to_xml(&self) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n");
+    if let Some(architecture) = &self.architecture {
+      xml.push_str(&format!("  <architecture>{}</architecture>\n", architecture));
+    }
+    xml.push_str(&format!("  <feature name=\"{}\">\n", self.feature));
+    for (number, register) in self.registers.iter().enumerate() {
+      xml.push_str(&format!("    <reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\"", register.name, register.bits, number));
+      if let Some(typ) = &register.typ {
+        xml.push_str(&format!(" type=\"{}\"", typ));
+      }
+      xml.push_str("/>\n");
+    }
+    xml.push_str("  </feature>\n</target>\n");
+    xml
+  }
+}
+
+/// `R` or `R[n]`.
+fn parse_source(text: &str) -> Option<RegisterSource> {
+  match text.strip_suffix(']').and_then(|text| text.split_once('[')) {
+    Some((register, position)) => Some(RegisterSource::Element(register.to_string(), position.parse().ok()?)),
+    None if !text.contains(['[', ']']) => Some(RegisterSource::Register(text.to_string())),
+    None => None,
+  }
+}
+
+/// A connection to GDB.
+pub trait Connection: Read + Write {
+  /// Whether GDB has sent an interrupt, which the connection consumes, without waiting for one.
+  fn interrupted(&mut self) -> bool {
+    false
+  }
+}
+
+impl Connection for TcpStream {
+  fn interrupted(&mut self) -> bool {
+    let mut byte = [0];
+    if self.set_nonblocking(true).is_err() {
+      return false;
+    }
+    let interrupted = matches!(self.peek(&mut byte), Ok(1) if byte[0] == 0x03);
+    let _ = self.set_nonblocking(false);
+    if interrupted {
+      let _ = self.read_exact(&mut byte);
+    }
+    interrupted
+  }
+}
+
+/// The process's standard input and output, for GDB to run the stub as a pipe.
+///
+/// Standard input cannot be peeked without blocking, so a thread reads it and hands over what arrives; the stub
+/// looks at what is waiting to notice an interrupt while the target runs.
+pub struct Stdio {
+  input  : Receiver<io::Result<Vec<u8>>>,
+  pending: VecDeque<u8>,
+}
+
+impl Stdio {
+  pub fn new() -> Stdio {
+    let (sender, input) = mpsc::channel();
+    thread::spawn(move || {
+      let mut buffer = [0; 4096];
+      loop {
+        let read = io::stdin().read(&mut buffer).map(|count| buffer[..count].to_vec());
+        let finished = !matches!(&read, Ok(bytes) if !bytes.is_empty());
+        if sender.send(read).is_err() || finished {
+          break;
+        }
+      }
+    });
+    Stdio { input, pending: VecDeque::new() }
+  }
+}
+
+impl Default for Stdio {
+  fn default() -> Stdio {
+    Stdio::new()
+  }
+}
+
+impl Read for Stdio {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    if self.pending.is_empty() {
+      match self.input.recv() {
+        Ok(read) => self.pending.extend(read?),
+        Err(_) => return Ok(0),
+      }
+    }
+    let count = buffer.len().min(self.pending.len());
+    for (slot, byte) in buffer.iter_mut().zip(self.pending.drain(..count)) {
+      *slot = byte;
+    }
+    Ok(count)
+  }
+}
+
+impl Write for Stdio {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    io::stdout().write(buffer)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    io::stdout().flush()
+  }
+}
+
+impl Connection for Stdio {
+  fn interrupted(&mut self) -> bool {
+    while let Ok(Ok(bytes)) = self.input.try_recv() {
+      self.pending.extend(bytes);
+    }
+    let interrupted = self.pending.front() == Some(&0x03);
+    if interrupted {
+      self.pending.pop_front();
+    }
+    interrupted
+  }
+}
+
+/// Waits on `address`, such as `127.0.0.1:1234`, for GDB to connect.
+pub fn accept(address: &str) -> io::Result<TcpStream> {
+  let listener = TcpListener::bind(address)?;
+  let (stream, _) = listener.accept()?;
+  stream.set_nodelay(true)?;
+  Ok(stream)
+}
+
+/// What a packet from GDB asks the stub to do besides reply.
+enum Action {
+  Reply(Vec<u8>),
+  /// Run, then report why the target stopped.
+  Resume { step: bool },
+  /// Reply, then end the session.
+  Detach(Vec<u8>),
+  Kill,
+}
+
+pub struct GdbServer<'s, M: Memory> {
+  simulator  : &'s mut Simulator<M>,
+  description: TargetDescription,
+  breakpoints: HashSet<u64>,
+  /// Whether packets are acknowledged, until GDB asks for them not to be
+  acknowledge: bool,
+}
+
+impl<'s, M: Memory> GdbServer<'s, M> {
+  pub fn new(simulator: &'s mut Simulator<M>, description: TargetDescription) -> Self {
+    GdbServer { simulator, description, breakpoints: HashSet::new(), acknowledge: true }
+  }
+
+  /// Serves GDB until it detaches, kills the target, or the program ends, returning how it ended if it did.
+  pub fn serve(&mut self, connection: &mut dyn Connection) -> io::Result<Option<Stop>> {
+    loop {
+      let packet = match self.read_packet(connection) {
+        Ok(Some(packet)) => packet,
+        // An interrupt while the target is stopped
+        Ok(None) => {
+          self.write_packet(connection, format!("S{:02x}", SIGINT).as_bytes())?;
+          continue;
+        }
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+      };
+      match self.handle(&packet) {
+        Action::Reply(reply) => self.write_packet(connection, &reply)?,
+        Action::Resume { step } => {
+          let (reply, stop) = self.resume(step, connection)?;
+          self.write_packet(connection, &reply)?;
+          if stop.is_some() {
+            return Ok(stop);
+          }
+        }
+        Action::Detach(reply) => {
+          self.write_packet(connection, &reply)?;
+          return Ok(None);
+        }
+        Action::Kill => return Ok(None),
+      }
+    }
+  }
+
+  fn handle(&mut self, packet: &[u8]) -> Action {
+    let text = String::from_utf8_lossy(packet);
+    let reply = |text: &str| Action::Reply(text.as_bytes().to_vec());
+    let (command, arguments) = text.split_at(text.chars().next().map_or(0, char::len_utf8));
+    match command {
+      "?" => reply(&format!("S{:02x}", SIGTRAP)),
+      "g" => reply(&self.read_registers()),
+      "G" => reply(&respond(self.write_registers(arguments))),
+      "p" => match usize::from_str_radix(arguments, 16).ok().and_then(|number| self.read_register(number)) {
+        Some(value) => reply(&value),
+        None => reply("E01"),
+      },
+      "P" => reply(&respond(self.write_register(arguments))),
+      "m" => match parse_range(arguments).and_then(|(address, length)| self.read_memory(address, length)) {
+        Some(data) => reply(&data),
+        None => reply("E01"),
+      },
+      "M" => reply(&respond(self.write_memory_hex(arguments))),
+      "X" => reply(&respond(self.write_memory_binary(packet))),
+      "Z" | "z" => match self.breakpoint(command == "Z", arguments) {
+        Some(result) => reply(&respond(result)),
+        None => reply(""),
+      },
+      "c" | "s" => {
+        if let Ok(address) = u64::from_str_radix(arguments, 16) {
+          if self.simulator.set_pc(address).is_err() {
+            return reply("E01");
+          }
+        }
+        Action::Resume { step: command == "s" }
+      }
+      "H" | "T" => reply("OK"),
+      "D" => Action::Detach(b"OK".to_vec()),
+      "k" => Action::Kill,
+      "q" | "Q" => self.query(&text),
+      _ => reply(""),
+    }
+  }
+
+  fn query(&mut self, text: &str) -> Action {
+    let reply = |text: &str| Action::Reply(text.as_bytes().to_vec());
+    if text.starts_with("qSupported") {
+      return reply(&format!("PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+", PACKET_SIZE));
+    }
+    if let Some(request) = text.strip_prefix("qXfer:features:read:target.xml:") {
+      return match parse_range(request) {
+        Some((offset, length)) => Action::Reply(transfer(self.description.to_xml().as_bytes(), offset, length)),
+        None => reply("E01"),
+      };
+    }
+    match text {
+      "QStartNoAckMode" => {
+        self.acknowledge = false;
+        reply("OK")
+      }
+      "qAttached" => reply("1"),
+      "qC" => reply("QC1"),
+      "qfThreadInfo" => reply("m1"),
+      "qsThreadInfo" => reply("l"),
+      "qSymbol::" => reply("OK"),
+      _ => reply(""),
+    }
+  }
+
+  // region Running
+
+  /// Steps once, or until a breakpoint or an interrupt, returning the stop reply and how the program ended if it did.
+  fn resume(&mut self, step: bool, connection: &mut dyn Connection) -> io::Result<(Vec<u8>, Option<Stop>)> {
+    let mut first = true;
+    loop {
+      if !first {
+        if self.simulator.pc().is_ok_and(|pc| self.breakpoints.contains(&pc)) {
+          return Ok((format!("T{:02x}swbreak:;", SIGTRAP).into_bytes(), None));
+        }
+        if self.simulator.steps().is_multiple_of(INTERRUPT_INTERVAL) && connection.interrupted() {
+          return Ok((format!("S{:02x}", SIGINT).into_bytes(), None));
+        }
+      }
+      first = false;
+
+      let result = self.simulator.advance(&mut |_, _| {});
+      let console = self.simulator.take_console();
+      if !console.is_empty() {
+        self.write_packet(connection, format!("O{}", hex(&console)).as_bytes())?;
+      }
+      match result {
+        Ok(None) if step => return Ok((format!("S{:02x}", SIGTRAP).into_bytes(), None)),
+        Ok(None) => {}
+        Ok(Some(Stop::Limit)) => return Ok((format!("S{:02x}", SIGTRAP).into_bytes(), None)),
+        Ok(Some(Stop::Exit(code))) => return Ok((format!("W{:02x}", code.min(0xff)).into_bytes(), Some(Stop::Exit(code)))),
+        Ok(Some(Stop::Halted)) => return Ok((b"W00".to_vec(), Some(Stop::Halted))),
+        Err(error) => {
+          let message = format!("{}\n", error.value);
+          self.write_packet(connection, format!("O{}", hex(message.as_bytes())).as_bytes())?;
+          return Ok((format!("S{:02x}", SIGABRT).into_bytes(), None));
+        }
+      }
+    }
+  }
+
+  /// Inserts or removes a breakpoint, or returns `None` for kinds of breakpoint the stub does not support.
+  fn breakpoint(&mut self, insert: bool, arguments: &str) -> Option<Result<(), ()>> {
+    let mut parts = arguments.split(',');
+    let kind = parts.next()?;
+    if kind != "0" && kind != "1" {
+      return None;
+    }
+    let Some(Ok(address)) = parts.next().map(|address| u64::from_str_radix(address, 16)) else {
+      return Some(Err(()));
+    };
+    match insert {
+      true => self.breakpoints.insert(address),
+      false => self.breakpoints.remove(&address),
+    };
+    Some(Ok(()))
+  }
+
+  // endregion
+
+  // region Registers
+
+  fn read_registers(&self) -> String {
+    (0..self.description.registers.len()).filter_map(|number| self.read_register(number)).collect()
+  }
+
+  /// The register GDB numbers `number`, as hexadecimal bytes in target order, or `x`s if it cannot be read.
+  fn read_register(&self, number: usize) -> Option<String> {
+    let register = self.description.registers.get(number)?;
+    let bytes = register.bits / 8;
+    let value = match &register.source {
+      RegisterSource::Zero => Some(Bits::zeros(register.bits)),
+      RegisterSource::Register(name) => self.simulator.machine().registers.get(name).ok().and_then(register_bits),
+      RegisterSource::Element(name, position) => match self.simulator.machine().registers.get(name) {
+        Ok(Value::Vector(elements)) => elements.get(*position).and_then(register_bits),
+        _ => None,
+      },
+    };
+    let Some(value) = value.filter(|value| value.len() <= register.bits) else {
+      return Some("xx".repeat(bytes));
+    };
+    let mut data = value.extend(register.bits, false).to_bytes_le();
+    if self.endianness() == Endianness::Big {
+      data.reverse();
+    }
+    Some(hex(&data))
+  }
+
+  fn write_registers(&mut self, data: &str) -> Result<(), ()> {
+    let mut rest = data;
+    for number in 0..self.description.registers.len() {
+      let length = self.description.registers[number].bits / 4;
+      if rest.len() < length {
+        return Err(());
+      }
+      let (value, remaining) = rest.split_at(length);
+      // Registers GDB does not know are sent as `x`s, and left as they are.
+      if !value.contains('x') {
+        self.set_register(number, value)?;
+      }
+      rest = remaining;
+    }
+    Ok(())
+  }
+
+  /// `n=value`
+  fn write_register(&mut self, arguments: &str) -> Result<(), ()> {
+    let (number, value) = arguments.split_once('=').ok_or(())?;
+    let number = usize::from_str_radix(number, 16).map_err(|_| ())?;
+    self.set_register(number, value)
+  }
+
+  fn set_register(&mut self, number: usize, value: &str) -> Result<(), ()> {
+    let register = self.description.registers.get(number).ok_or(())?.clone();
+    let mut data = unhex(value).ok_or(())?;
+    if data.len() != register.bits / 8 {
+      return Err(());
+    }
+    if self.endianness() == Endianness::Big {
+      data.reverse();
+    }
+    let bits = Bits::from_bytes_le(&data);
+    let registers = &mut self.simulator.machine_mut().registers;
+    let fit = |current: &Value, bits: Bits| -> Result<Value, RuntimeError> {
+      match current {
+        Value::Bitvector(old) => Ok(Value::Bitvector(bits.slice(0, old.len()))),
+        Value::Bit(_) => Ok(Value::Bit(bits.get(0))),
+        Value::Bool(_) => Ok(Value::Bool(bits.get(0))),
+        _ => Err(RuntimeError::Unsupported("writing a register that is not a bitvector".to_string())),
+      }
+    };
+    let result = match &register.source {
+      RegisterSource::Zero => Ok(()),
+      RegisterSource::Register(name) => registers.get(name).and_then(|current| fit(current, bits)).and_then(|value| registers.set(name, value)),
+      RegisterSource::Element(name, position) => match registers.get(name).cloned() {
+        Ok(Value::Vector(mut elements)) if *position < elements.len() => {
+          fit(&elements[*position], bits).and_then(|value| {
+            elements[*position] = value;
+            registers.set(name, Value::Vector(elements))
+          })
+        }
+        _ => Err(RuntimeError::UnknownRegister(name.clone())),
+      },
+    };
+    result.map_err(|_| ())
+  }
+
+  // endregion
+
+  // region Memory
+
+  fn read_memory(&mut self, address: u64, length: usize) -> Option<String> {
+    let mut data = vec![0; length.min(PACKET_SIZE / 2)];
+    self.simulator.machine_mut().memory.read_bytes(address, &mut data).ok()?;
+    Some(hex(&data))
+  }
+
+  /// `address,length:hex`
+  fn write_memory_hex(&mut self, arguments: &str) -> Result<(), ()> {
+    let (range, data) = arguments.split_once(':').ok_or(())?;
+    let (address, length) = parse_range(range).ok_or(())?;
+    let data = unhex(data).ok_or(())?;
+    if data.len() != length {
+      return Err(());
+    }
+    self.simulator.machine_mut().memory.write_bytes(address, &data).map_err(|_| ())
+  }
+
+  /// `Xaddress,length:binary`, whose data is not text.
+  fn write_memory_binary(&mut self, packet: &[u8]) -> Result<(), ()> {
+    let colon = packet.iter().position(|&byte| byte == b':').ok_or(())?;
+    let range = std::str::from_utf8(&packet[1..colon]).map_err(|_| ())?;
+    let (address, length) = parse_range(range).ok_or(())?;
+    let data = &packet[colon + 1..];
+    if data.len() != length {
+      return Err(());
+    }
+    self.simulator.machine_mut().memory.write_bytes(address, data).map_err(|_| ())
+  }
+
+  fn endianness(&self) -> Endianness {
+    self.simulator.machine().memory.config().endianness
+  }
+
+  // endregion
+
+  // region Packets
+
+  /// Reads the next packet's data, acknowledging it, or `None` for an interrupt.
+  fn read_packet(&mut self, connection: &mut dyn Connection) -> io::Result<Option<Vec<u8>>> {
+    loop {
+      match read_byte(connection)? {
+        b'$' => {}
+        0x03 => return Ok(None),
+        // Acknowledgements, and anything else between packets
+        _ => continue,
+      }
+      let mut data = Vec::new();
+      let mut checksum: u8 = 0;
+      loop {
+        let byte = read_byte(connection)?;
+        if byte == b'#' {
+          break;
+        }
+        checksum = checksum.wrapping_add(byte);
+        match byte {
+          b'}' => {
+            let escaped = read_byte(connection)?;
+            checksum = checksum.wrapping_add(escaped);
+            data.push(escaped ^ 0x20);
+          }
+          _ => data.push(byte),
+        }
+        if data.len() > PACKET_SIZE {
+          return Err(io::Error::new(ErrorKind::InvalidData, "packet too long"));
+        }
+      }
+      let sent = [read_byte(connection)?, read_byte(connection)?];
+      let valid = std::str::from_utf8(&sent).ok().and_then(|sent| u8::from_str_radix(sent, 16).ok()) == Some(checksum);
+      if self.acknowledge {
+        connection.write_all(if valid { b"+" } else { b"-" })?;
+        connection.flush()?;
+      }
+      if valid {
+        return Ok(Some(data));
+      }
+    }
+  }
+
+  fn write_packet(&mut self, connection: &mut dyn Connection, data: &[u8]) -> io::Result<()> {
+    let packet = frame(data);
+    loop {
+      connection.write_all(&packet)?;
+      connection.flush()?;
+      if !self.acknowledge {
+        return Ok(());
+      }
+      loop {
+        match read_byte(connection)? {
+          b'+' => return Ok(()),
+          b'-' => break,
+          _ => continue,
+        }
+      }
+    }
+  }
+
+  // endregion
+}
+
+/// `$data#checksum`, with the characters the protocol reserves escaped.
+fn frame(data: &[u8]) -> Vec<u8> {
+  let mut packet = vec![b'$'];
+  for &byte in data {
+    match byte {
+      b'$' | b'#' | b'}' | b'*' => packet.extend([b'}', byte ^ 0x20]),
+      _ => packet.push(byte),
+    }
+  }
+  let checksum = packet[1..].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+  packet.extend(format!("#{:02x}", checksum).into_bytes());
+  packet
+}
+
+fn read_byte(connection: &mut dyn Connection) -> io::Result<u8> {
+  let mut byte = [0];
+  connection.read_exact(&mut byte)?;
+  Ok(byte[0])
+}
+
+/// A register's value as bits, if it is a bitvector or a bit.
+fn register_bits(value: &Value) -> Option<Bits> {
+  match value {
+    Value::Bitvector(bits) => Some(bits.clone()),
+    Value::Bit(bit) | Value::Bool(bit) => Some(Bits::from_u64(*bit as u64, 1)),
+    _ => None,
+  }
+}
+
+/// `address,length`, both hexadecimal.
+fn parse_range(text: &str) -> Option<(u64, usize)> {
+  let (address, length) = text.split_once(',')?;
+  Some((u64::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+}
+
+/// The part of `data` from `offset` of at most `length` bytes, marked `l` if it is the last and `m` if not.
+fn transfer(data: &[u8], offset: u64, length: usize) -> Vec<u8> {
+  let start = (offset as usize).min(data.len());
+  let end = start.saturating_add(length).min(data.len());
+  let mut reply = vec![if end == data.len() { b'l' } else { b'm' }];
+  reply.extend_from_slice(&data[start..end]);
+  reply
+}
+
+fn respond(result: Result<(), ()>) -> String {
+  match result {
+    Ok(()) => "OK".to_string(),
+    Err(()) => "E01".to_string(),
+  }
+}
+
+fn hex(data: &[u8]) -> String {
+  data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+  if !text.len().is_multiple_of(2) {
+    return None;
+  }
+  (0..text.len()).step_by(2).map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+  use crate::interpreter::Interpreter;
+  use crate::parser::ast::Definitions;
+  use crate::passes::typecheck::{Constraint, FunctionType, Type, Typing};
+  use crate::runtime::{MemoryConfig, RegisterDeclaration, SparseMemory};
+  use crate::simulator::SimulatorOptions;
+
+  /// GDB's side of a session, sent all at once, and the stub's.
+  struct Script {
+    input : Cursor<Vec<u8>>,
+    output: Vec<u8>,
+  }
+
+  impl Read for Script {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+      self.input.read(buffer)
+    }
+  }
+
+  impl Write for Script {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+      self.output.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl Connection for Script {}
+
+  /// A machine whose step adds four to `PC`, and which exits through `tohost` at `0x100`.
+  fn simulator() -> Simulator {
+    let mut typing = Typing::default();
+    let step = FunctionType { variables: vec![], constraint: Constraint::True, arguments: vec![Type::Unit], result: Type::Unit };
+    typing.environment.functions.insert("step".to_string(), step);
+    let mut interpreter = Interpreter::new(&Definitions(Vec::new()), typing);
+    interpreter.define_extern("step", |_, environment| {
+      let pc = environment.read_register("PC")?.as_bits().cloned().unwrap();
+      environment.write_register("PC", Value::Bitvector(pc.wrapping_add(&Bits::from_u64(4, 64))))?;
+      Ok(Value::Unit)
+    });
+    let options = SimulatorOptions { tohost: Some(0x1000), ..SimulatorOptions::default() };
+    let mut simulator = Simulator::new(interpreter, SparseMemory::new(MemoryConfig::default()), options).unwrap();
+    let pc = RegisterDeclaration { name: "PC".to_string(), width: Some(64), ..RegisterDeclaration::default() };
+    simulator.machine_mut().registers.declare(pc, Value::Bitvector(Bits::zeros(64)));
+    simulator
+  }
+
+  /// The packets the stub sent, without acknowledgements.
+  fn replies(output: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(output);
+    text.split('$').skip(1).map(|packet| packet.split('#').next().unwrap().to_string()).collect()
+  }
+
+  fn session(simulator: &mut Simulator, packets: &[&str]) -> (Vec<String>, Option<Stop>) {
+    let description = TargetDescription::from_registers(&simulator.machine().registers);
+    described_session(simulator, description, packets)
+  }
+
+  fn described_session(
+    simulator  : &mut Simulator,
+    description: TargetDescription,
+    packets    : &[&str],
+  ) -> (Vec<String>, Option<Stop>) {
+    let mut input = Vec::new();
+    for packet in packets {
+      input.extend(frame(packet.as_bytes()));
+      input.extend(b"+");
+    }
+    let mut script = Script { input: Cursor::new(input), output: Vec::new() };
+    let stop = GdbServer::new(simulator, description).serve(&mut script).unwrap();
+    (replies(&script.output), stop)
+  }
+
+  #[test]
+  fn packets() {
+    assert_eq!(frame(b"OK"), b"$OK#9a");
+    assert_eq!(frame(b"a#b"), b"$a}\x03b#43");
+    assert_eq!(unhex("0aff"), Some(vec![0x0a, 0xff]));
+    assert_eq!(unhex("0a0"), None);
+    assert_eq!(transfer(b"abcdef", 2, 2), b"mcd");
+    assert_eq!(transfer(b"abcdef", 4, 8), b"lef");
+  }
+
+  #[test]
+  fn parses_descriptions() {
+    let description = TargetDescription::parse("architecture riscv:rv64 # RV64\nregister zero 64 -\nregister x5 64 X[5]\nregister pc 64 PC code_ptr\n").unwrap();
+    assert_eq!(description.architecture.as_deref(), Some("riscv:rv64"));
+    assert_eq!(description.registers[0].source, RegisterSource::Zero);
+    assert_eq!(description.registers[1].source, RegisterSource::Element("X".to_string(), 5));
+    assert_eq!(description.registers[2].typ.as_deref(), Some("code_ptr"));
+    assert!(description.to_xml().contains("<reg name=\"pc\" bitsize=\"64\" regnum=\"2\" type=\"code_ptr\"/>"));
+    assert!(TargetDescription::parse("register pc 12 PC").is_err());
+    assert!(TargetDescription::parse("registers").is_err());
+  }
+
+  #[test]
+  fn serves_a_session() {
+    let mut simulator = simulator();
+    let (replies, stop) = session(&mut simulator, &[
+      "?",
+      "g",
+      "P0=0001000000000000",
+      "p0",
+      "M200,2:abcd",
+      "m1ff,4",
+      "Z0,110,4",
+      "c",
+      "p0",
+      "s",
+      "z0,110,4",
+      "Z2,0,4",
+      "D",
+    ]);
+    assert_eq!(replies, vec![
+      "S05",
+      "0000000000000000",
+      "OK",
+      "0001000000000000",
+      "OK",
+      "00abcd00",
+      "OK",
+      "T05swbreak:;",
+      "1001000000000000",
+      "S05",
+      "OK",
+      "",
+      "OK",
+    ]);
+    assert_eq!(stop, None);
+    assert_eq!(simulator.pc(), Ok(0x114));
+
+    simulator.machine_mut().memory.write_bytes(0x1000, &[3, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    let (replies, stop) = session(&mut simulator, &["c"]);
+    assert_eq!(replies, vec!["W01"]);
+    assert_eq!(stop, Some(Stop::Exit(1)));
+  }
+
+  #[test]
+  fn writes_bit_and_boolean_registers() {
+    let mut simulator = simulator();
+    for (name, value) in [("F", Value::Bit(false)), ("B", Value::Bool(false))] {
+      let register = RegisterDeclaration { name: name.to_string(), ..RegisterDeclaration::default() };
+      simulator.machine_mut().registers.declare(register, value);
+    }
+    let description = TargetDescription::parse("register pc 64 PC\nregister f 8 F\nregister b 8 B\n").unwrap();
+    let (replies, _) = described_session(&mut simulator, description, &["P1=01", "P2=01", "p1", "p2", "D"]);
+    assert_eq!(replies, vec!["OK", "OK", "01", "01", "OK"]);
+    assert_eq!(simulator.machine().registers.get("F"), Ok(&Value::Bit(true)));
+    assert_eq!(simulator.machine().registers.get("B"), Ok(&Value::Bool(true)));
+  }
+}
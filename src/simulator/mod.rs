@@ -0,0 +1,464 @@
+/*!
+
+An instruction-level simulator, running a Sail model of an architecture on the interpreter.
+
+A model describes one step of the machine, fetching, decoding and executing an instruction, as a function, by
+convention `step`. The `Simulator` holds an `Interpreter` for the model and the `Machine` it runs against, loads a
+program into the machine's memory from an ELF file, and calls the step function until something stops it:
+
+ * the program reports an exit code through HTIF, the host-target interface of RISC-V's test environments, by writing
+   `code << 1 | 1` to the word at the symbol `tohost`,
+ * the model calls Sail's `exit`, or
+ * the number of steps reaches the configured limit.
+
+HTIF requests to write a character to the console, which `riscv-tests` and `pk` use for output, are collected for the
+caller to print, and acknowledged through `fromhost` if the program has one. Other requests are acknowledged and
+otherwise ignored.
+
+A step function taking an argument, like `step(step_number : int)` in `sail-riscv`, is passed the number of steps
+taken so far. With tracing on, each `Step` lists the registers the step changed, and with recording on it carries a
+`TraceRecord` of the step: the program counter, the instruction passed to the model's `execute` function, the registers
+written and the memory read and written (see `trace`).
+
+`gdb` serves GDB's remote serial protocol, so that GDB can debug the program running on the simulator, and `cli` is the
+command line front end of the `rigging-sim` binary.
+
+*/
+
+pub mod cli;
+pub mod gdb;
+pub mod trace;
+
+use std::path::Path;
+
+use crate::abstractions::{BigInteger, Integer};
+use crate::interpreter::Interpreter;
+use crate::parser::location::Located;
+use crate::passes::typecheck::Type;
+use crate::simulator::trace::TraceRecord;
+use crate::runtime::{
+  load_elf,
+  ElfImage,
+  Endianness,
+  LocatedRuntimeError,
+  Machine,
+  Memory,
+  MemoryError,
+  Recorder,
+  RegisterChange,
+  RuntimeError,
+  SparseMemory,
+  Value,
+};
+
+/// HTIF's device and command for writing a character to the console.
+const CONSOLE: (u64, u64) = (1, 1);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatorOptions {
+  /// The function running one step of the machine
+  pub step      : String,
+  /// The number of steps after which to stop, if any
+  pub limit     : Option<u64>,
+  /// The register set to the entry point of a loaded program, if the model declares it
+  pub pc        : String,
+  /// The address of `tohost`, which otherwise comes from the symbols of a loaded program
+  pub tohost    : Option<u64>,
+  /// Whether to list the registers each step changes
+  pub trace     : bool,
+  /// Whether to make a `TraceRecord` of each step
+  pub record    : bool,
+  /// The function executing a decoded instruction, whose argument a `TraceRecord` names
+  pub execute   : String,
+}
+
+impl Default for SimulatorOptions {
+  fn default() -> Self {
+    SimulatorOptions {
+      step   : "step".to_string(),
+      limit  : None,
+      pc     : "PC".to_string(),
+      tohost : None,
+      trace  : false,
+      record : false,
+      execute: "execute".to_string(),
+    }
+  }
+}
+
+/// Why the simulator stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+  /// The program wrote an exit code to `tohost`.
+  Exit(u64),
+  /// The model called `exit`.
+  Halted,
+  /// The number of steps reached the limit.
+  Limit,
+}
+
+/// What one step did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+  /// The number of steps before this one
+  pub number : u64,
+  /// The registers changed, when tracing
+  pub changes: Vec<RegisterChange>,
+  /// What the step did, when recording
+  pub record : Option<TraceRecord>,
+}
+
+pub struct Simulator<M: Memory = SparseMemory> {
+  interpreter : Interpreter,
+  machine     : Machine<M>,
+  options     : SimulatorOptions,
+  /// Whether the step function takes the step number
+  numbered    : bool,
+  steps       : u64,
+  tohost      : Option<u64>,
+  fromhost    : Option<u64>,
+  console     : Vec<u8>,
+}
+
+impl<M: Memory> Simulator<M> {
+  /// A simulator running the model `interpreter` holds, with its registers at their initial values and `memory`.
+  pub fn new(mut interpreter: Interpreter, memory: M, options: SimulatorOptions) -> Result<Self, LocatedRuntimeError> {
+    let numbered = match interpreter.typing().environment.functions.get(&options.step) {
+      Some(function) => !matches!(function.arguments.as_slice(), [] | [Type::Unit]),
+      None => return Err(Located::from(RuntimeError::UnknownFunction(options.step.clone()))),
+    };
+    if options.record {
+      interpreter.record_calls([options.execute.clone()]);
+    }
+    let registers = interpreter.register_file()?;
+    Ok(Simulator {
+      interpreter,
+      machine : Machine::new(registers, memory),
+      tohost  : options.tohost,
+      options,
+      numbered,
+      steps   : 0,
+      fromhost: None,
+      console : Vec::new(),
+    })
+  }
+
+  /// Loads the ELF file at `path` into memory, setting the program counter to its entry point and finding `tohost`
+  /// and `fromhost` among its symbols.
+  pub fn load_elf(&mut self, path: impl AsRef<Path>) -> Result<ElfImage, LocatedRuntimeError> {
+    let image = load_elf(path, &mut self.machine.memory).map_err(|error| Located::from(RuntimeError::from(error)))?;
+    if self.machine.registers.declaration(&self.options.pc).is_some() {
+      self.set_pc(image.entry).map_err(Located::from)?;
+    }
+    self.tohost = self.options.tohost.or_else(|| image.symbol("tohost"));
+    self.fromhost = image.symbol("fromhost");
+    Ok(image)
+  }
+
+  /// Runs one step.
+  pub fn step(&mut self) -> Result<Step, LocatedRuntimeError> {
+    let before = self.options.trace.then(|| self.machine.registers.snapshot());
+    let arguments = match self.numbered {
+      true => vec![Value::Integer(BigInteger::from_i64(self.steps as i64))],
+      false => Vec::new(),
+    };
+    let record = match self.options.record {
+      true => Some(self.record(arguments)?),
+      false => {
+        self.interpreter.call(&self.options.step, arguments, &mut self.machine)?;
+        None
+      }
+    };
+    let changes = before.map(|before| self.machine.registers.diff(&before)).unwrap_or_default();
+    let step = Step { number: self.steps, changes, record };
+    self.steps += 1;
+    Ok(step)
+  }
+
+  /// Runs the step function with `arguments`, recording what it does.
+  fn record(&mut self, arguments: Vec<Value>) -> Result<TraceRecord, LocatedRuntimeError> {
+    let pc = self.pc().ok();
+    self.interpreter.take_recorded_calls();
+    let mut recorder = Recorder::new(&mut self.machine);
+    self.interpreter.call(&self.options.step, arguments, &mut recorder)?;
+    let instruction = self.interpreter.take_recorded_calls().into_iter().find_map(|(_, arguments)| match arguments.first() {
+      Some(Value::Constructor(constructor, _)) => Some(constructor.clone()),
+      _ => None,
+    });
+    Ok(TraceRecord {
+      step     : self.steps,
+      pc,
+      instruction,
+      registers: recorder.registers.into_iter().map(|(name, value)| (name, value.to_string())).collect(),
+      memory   : recorder.memory,
+    })
+  }
+
+  /// Runs steps until the simulation stops, passing each to `observe`.
+  pub fn run(&mut self, mut observe: impl FnMut(&Step, &mut Self)) -> Result<Stop, LocatedRuntimeError> {
+    loop {
+      if let Some(stop) = self.advance(&mut observe)? {
+        return Ok(stop);
+      }
+    }
+  }
+
+  /// Runs one step, unless the limit has been reached, serves any request the program made of the host and passes the
+  /// step to `observe`, so that it may take what the step printed, returning why the simulation stopped if it did.
+  pub fn advance(&mut self, observe: &mut impl FnMut(&Step, &mut Self)) -> Result<Option<Stop>, LocatedRuntimeError> {
+    if self.options.limit.is_some_and(|limit| self.steps >= limit) {
+      return Ok(Some(Stop::Limit));
+    }
+    let step = match self.step() {
+      Ok(step) => step,
+      Err(Located { value: RuntimeError::Exit, .. }) => return Ok(Some(Stop::Halted)),
+      Err(error) => return Err(error),
+    };
+    let exit = self.poll_host().map_err(|error| Located::from(RuntimeError::from(error)))?;
+    observe(&step, self);
+    Ok(exit.map(Stop::Exit))
+  }
+
+  /// The value of the program counter, if the model declares it as a bitvector register.
+  pub fn pc(&self) -> Result<u64, RuntimeError> {
+    self.machine.registers.u64(&self.options.pc)
+  }
+
+  pub fn set_pc(&mut self, address: u64) -> Result<(), RuntimeError> {
+    self.machine.registers.set_u64(&self.options.pc, address)
+  }
+
+  /// Serves a request the program left in `tohost`, returning the exit code if it asks to exit.
+  fn poll_host(&mut self) -> Result<Option<u64>, MemoryError> {
+    let Some(tohost) = self.tohost else { return Ok(None) };
+    let request = self.read_word(tohost)?;
+    if request == 0 {
+      return Ok(None);
+    }
+    let (device, command, payload) = (request >> 56, (request >> 48) & 0xff, request & 0xffff_ffff_ffff);
+    if device == 0 && payload & 1 == 1 {
+      return Ok(Some(payload >> 1));
+    }
+    if (device, command) == CONSOLE {
+      self.console.push(payload as u8);
+    }
+    self.write_word(tohost, 0)?;
+    if let Some(fromhost) = self.fromhost {
+      self.write_word(fromhost, device << 56 | command << 48)?;
+    }
+    Ok(None)
+  }
+
+  fn read_word(&mut self, address: u64) -> Result<u64, MemoryError> {
+    let mut bytes = [0; 8];
+    self.machine.memory.read_bytes(address, &mut bytes)?;
+    Ok(match self.machine.memory.config().endianness {
+      Endianness::Little => u64::from_le_bytes(bytes),
+      Endianness::Big => u64::from_be_bytes(bytes),
+    })
+  }
+
+  fn write_word(&mut self, address: u64, value: u64) -> Result<(), MemoryError> {
+    let bytes = match self.machine.memory.config().endianness {
+      Endianness::Little => value.to_le_bytes(),
+      Endianness::Big => value.to_be_bytes(),
+    };
+    self.machine.memory.write_bytes(address, &bytes)
+  }
+
+  /// Takes the characters the program has written to the console since this was last called.
+  pub fn take_console(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.console)
+  }
+
+  /// The number of steps run.
+  pub fn steps(&self) -> u64 {
+    self.steps
+  }
+
+  pub fn options(&self) -> &SimulatorOptions {
+    &self.options
+  }
+
+  pub fn machine(&self) -> &Machine<M> {
+    &self.machine
+  }
+
+  pub fn machine_mut(&mut self) -> &mut Machine<M> {
+    &mut self.machine
+  }
+
+  pub fn interpreter(&self) -> &Interpreter {
+    &self.interpreter
+  }
+
+  pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+    &mut self.interpreter
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::parser::testing::*;
+  use crate::passes::typecheck::check_types;
+  use crate::runtime::{Bits, MemoryAccess, MemoryConfig};
+
+  pub(super) const TOHOST: u64 = 0x1000;
+  const FROMHOST: u64 = 0x1008;
+
+  /// What `execute` does after advancing `PC`.
+  #[derive(Clone, Copy)]
+  pub(super) enum Action {
+    Nothing,
+    /// Asks the host to print a character.
+    Print(u8),
+    /// Asks the host to exit with a code.
+    Exit(u64),
+    /// Calls Sail's `exit`.
+    Halt,
+  }
+
+  /// A simulator for a model whose `step` passes `INSN(n)` to an external `execute`, `n` being the step number if the
+  /// step function is `numbered` and zero otherwise, and the numbers `execute` was passed. `execute` adds four to `PC`
+  /// and then does the next of `actions`, or nothing once they run out.
+  pub(super) fn model(
+    numbered: bool,
+    actions : Vec<Action>,
+    options : SimulatorOptions,
+  ) -> (Simulator, Rc<RefCell<Vec<Value>>>) {
+    let step = match numbered {
+      true => vec![
+        val("step", function_type(vec![typ("int")], typ("unit"))),
+        function("step", vec![pattern("n")], call("execute", vec![call("INSN", vec![var("n")])])),
+      ],
+      false => vec![
+        val("step", function_type(vec![typ("unit")], typ("unit"))),
+        function("step", vec![], call("execute", vec![call("INSN", vec![number(0)])])),
+      ],
+    };
+    let mut program = vec![
+      union("ast", vec![("INSN", typ("int"))]),
+      register("PC", bits(64), Some(hex("0x0000000000000000"))),
+      extern_val("execute", "execute", function_type(vec![typ("ast")], typ("unit"))),
+    ];
+    program.extend(step);
+    let definitions = definitions(program);
+    let (typing, errors) = check_types(&definitions);
+    assert!(errors.is_empty());
+    let mut interpreter = Interpreter::new(&definitions, typing);
+
+    let executed = Rc::new(RefCell::new(Vec::new()));
+    let seen = executed.clone();
+    let actions = RefCell::new(actions.into_iter());
+    interpreter.define_extern("execute", move |arguments, environment| {
+      let Value::Constructor(_, number) = &arguments[0] else { panic!("expected an instruction") };
+      seen.borrow_mut().push((**number).clone());
+      let pc = environment.read_register("PC")?.as_bits().cloned().unwrap();
+      environment.write_register("PC", Value::Bitvector(pc.wrapping_add(&Bits::from_u64(4, 64))))?;
+      let request = match actions.borrow_mut().next().unwrap_or(Action::Nothing) {
+        Action::Nothing => return Ok(Value::Unit),
+        Action::Print(character) => 1 << 56 | 1 << 48 | character as u64,
+        Action::Exit(code) => code << 1 | 1,
+        Action::Halt => return Err(RuntimeError::Exit),
+      };
+      environment.write_memory(TOHOST, &Bits::from_u64(request, 64))?;
+      Ok(Value::Unit)
+    });
+
+    let options = SimulatorOptions { tohost: Some(TOHOST), ..options };
+    let simulator = Simulator::new(interpreter, SparseMemory::new(MemoryConfig::default()), options).unwrap();
+    (simulator, executed)
+  }
+
+  fn word(simulator: &mut Simulator, address: u64) -> u64 {
+    simulator.read_word(address).unwrap()
+  }
+
+  #[test]
+  fn programs_exit_through_tohost() {
+    let (mut simulator, _) = model(false, vec![Action::Nothing, Action::Exit(3)], SimulatorOptions::default());
+    let mut observed = Vec::new();
+    assert_eq!(simulator.run(|step, _| observed.push(step.number)), Ok(Stop::Exit(3)));
+    assert_eq!(observed, vec![0, 1]);
+    assert_eq!(simulator.steps(), 2);
+    assert_eq!(simulator.pc(), Ok(8));
+  }
+
+  #[test]
+  fn models_halt_by_calling_exit() {
+    let (mut simulator, _) = model(false, vec![Action::Nothing, Action::Halt], SimulatorOptions::default());
+    assert_eq!(simulator.run(|_, _| {}), Ok(Stop::Halted));
+    assert_eq!(simulator.steps(), 1);
+  }
+
+  #[test]
+  fn runs_stop_at_the_step_limit() {
+    let options = SimulatorOptions { limit: Some(3), ..SimulatorOptions::default() };
+    let (mut simulator, _) = model(false, vec![], options);
+    assert_eq!(simulator.run(|_, _| {}), Ok(Stop::Limit));
+    assert_eq!(simulator.steps(), 3);
+    assert_eq!(simulator.pc(), Ok(12));
+  }
+
+  #[test]
+  fn console_output_is_collected_and_acknowledged() {
+    let actions = vec![Action::Print(b'h'), Action::Print(b'i'), Action::Exit(0)];
+    let (mut simulator, _) = model(false, actions, SimulatorOptions::default());
+    simulator.fromhost = Some(FROMHOST);
+
+    assert_eq!(simulator.advance(&mut |_, _| {}), Ok(None));
+    // The request is taken from `tohost` and acknowledged in `fromhost`.
+    assert_eq!(word(&mut simulator, TOHOST), 0);
+    assert_eq!(word(&mut simulator, FROMHOST), 1 << 56 | 1 << 48);
+    assert_eq!(simulator.take_console(), b"h".to_vec());
+
+    assert_eq!(simulator.run(|_, _| {}), Ok(Stop::Exit(0)));
+    assert_eq!(simulator.take_console(), b"i".to_vec());
+    assert_eq!(simulator.take_console(), Vec::<u8>::new());
+  }
+
+  #[test]
+  fn numbered_step_functions_are_passed_the_step_number() {
+    let options = SimulatorOptions { limit: Some(3), ..SimulatorOptions::default() };
+    let integers =
+      |values: &[i64]| values.iter().map(|value| Value::Integer(BigInteger::from_i64(*value))).collect::<Vec<_>>();
+
+    let (mut simulator, executed) = model(true, vec![], options.clone());
+    assert_eq!(simulator.run(|_, _| {}), Ok(Stop::Limit));
+    assert_eq!(*executed.borrow(), integers(&[0, 1, 2]));
+
+    let (mut simulator, executed) = model(false, vec![], options);
+    assert_eq!(simulator.run(|_, _| {}), Ok(Stop::Limit));
+    assert_eq!(*executed.borrow(), integers(&[0, 0, 0]));
+  }
+
+  #[test]
+  fn steps_are_traced_and_recorded() {
+    let options = SimulatorOptions { trace: true, record: true, ..SimulatorOptions::default() };
+    let (mut simulator, _) = model(false, vec![Action::Print(b'x')], options);
+    let step = simulator.step().unwrap();
+
+    let changes: Vec<(String, Value, Value)> =
+      step.changes.iter().map(|change| (change.name.clone(), change.before.clone(), change.after.clone())).collect();
+    assert_eq!(changes, vec![(
+      "PC".to_string(),
+      Value::Bitvector(Bits::from_u64(0, 64)),
+      Value::Bitvector(Bits::from_u64(4, 64)),
+    )]);
+    assert_eq!(step.record, Some(TraceRecord {
+      step       : 0,
+      pc         : Some(0),
+      instruction: Some("INSN".to_string()),
+      registers  : vec![("PC".to_string(), "0x0000000000000004".to_string())],
+      memory     : vec![MemoryAccess {
+        write  : true,
+        address: TOHOST,
+        data   : Bits::from_u64(1 << 56 | 1 << 48 | b'x' as u64, 64),
+      }],
+    }));
+  }
+}
@@ -0,0 +1,481 @@
+/*!
+
+Traces of a simulation, one `TraceRecord` for each step, and comparing them.
+
+A record holds the program counter before the step, the constructor of the `ast` value the step passed to `execute`,
+which is the instruction it decoded, the registers the step wrote, with the values written, and the memory it read and
+wrote, in the order it did. Traces are written in one of two forms:
+
+ * text, for reading:
+
+   ```text
+   #12 pc 0x80000004 ADDI
+     x1 <- 0x0000000000000005
+     mem[0x80001000] <- 0x12345678
+     mem[0x80001008] -> 0x0000
+   ```
+
+ * JSON lines, one object for each step, for tools:
+
+   ```text
+   {"step":12,"pc":"0x80000004","instruction":"ADDI","registers":[["x1","0x0000000000000005"]],"memory":[["w","0x80001000","0x12345678"]]}
+   ```
+
+   Numbers that may not fit a JSON integer, the program counter and addresses, are written as hexadecimal strings, as
+   are values. A trace from another source, such as an RTL simulation, need have only the members it knows.
+
+`first_divergence` compares two traces in JSON lines a step at a time, and `diff_main` is the `rigging trace-diff`
+command built on it. Steps are compared on
+
+ * the program counter and the instruction, where both traces have them,
+ * the last value each register was given, as the order of writes within a step is the model's business, bit vectors
+   being the same whatever the case of their digits and however many leading zeros they are written with, and
+ * the memory written, and unless told otherwise the memory read, in order.
+
+*/
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+
+use crate::abstractions::Json;
+use crate::runtime::{Bits, MemoryAccess};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+  /// The number of steps before this one
+  pub step       : u64,
+  /// The program counter before the step
+  pub pc         : Option<u64>,
+  /// The constructor of the instruction the step executed
+  pub instruction: Option<String>,
+  /// The registers written, with the values written, in order
+  pub registers  : Vec<(String, String)>,
+  pub memory     : Vec<MemoryAccess>,
+}
+
+impl Display for TraceRecord {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "#{}", self.step)?;
+    if let Some(pc) = self.pc {
+      write!(f, " pc {:#x}", pc)?;
+    }
+    if let Some(instruction) = &self.instruction {
+      write!(f, " {}", instruction)?;
+    }
+    for (name, value) in &self.registers {
+      write!(f, "\n  {} <- {}", name, value)?;
+    }
+    for access in &self.memory {
+      let arrow = if access.write { "<-" } else { "->" };
+      write!(f, "\n  mem[{:#x}] {} {}", access.address, arrow, access.data)?;
+    }
+    Ok(())
+  }
+}
+
+impl TraceRecord {
+  pub fn to_json(&self) -> Json {
+    let mut json = Json::object([("step", Json::Integer(self.step as i64))]);
+    if let Some(pc) = self.pc {
+      json.insert("pc", Json::from(format!("{:#x}", pc)));
+    }
+    if let Some(instruction) = &self.instruction {
+      json.insert("instruction", Json::from(instruction.as_str()));
+    }
+    let registers = self.registers.iter().map(|(name, value)| Json::from(vec![name.as_str(), value.as_str()])).collect();
+    json.insert("registers", Json::Array(registers));
+    let memory = self
+        .memory
+        .iter()
+        .map(|access| {
+          let kind = if access.write { "w" } else { "r" };
+          Json::from(vec![kind.to_string(), format!("{:#x}", access.address), access.data.to_string()])
+        })
+        .collect();
+    json.insert("memory", Json::Array(memory));
+    json
+  }
+
+  pub fn from_json(json: &Json) -> Result<TraceRecord, String> {
+    let step = json.get("step").and_then(Json::as_i64).ok_or("a record needs a step number")?;
+    let pc = match json.get("pc") {
+      Some(pc) => Some(hexadecimal(pc).ok_or("malformed pc")?),
+      None => None,
+    };
+    let instruction = json.get("instruction").and_then(Json::as_str).map(str::to_string);
+
+    let mut registers = Vec::new();
+    for register in json.get("registers").and_then(Json::as_array).unwrap_or_default() {
+      match register.as_array() {
+        Some([name, value]) => match (name.as_str(), value.as_str()) {
+          (Some(name), Some(value)) => {
+            let value = register_bits(value).map_or_else(|| value.to_string(), |bits| bits.to_string());
+            registers.push((name.to_string(), value))
+          }
+          _ => return Err("a register write is a name and a value".to_string()),
+        },
+        _ => return Err("a register write is a name and a value".to_string()),
+      }
+    }
+
+    let mut memory = Vec::new();
+    for access in json.get("memory").and_then(Json::as_array).unwrap_or_default() {
+      let malformed = || "a memory access is \"r\" or \"w\", an address and data".to_string();
+      let [kind, address, data] = access.as_array().ok_or_else(malformed)? else { return Err(malformed()) };
+      let write = match kind.as_str() {
+        Some("w") => true,
+        Some("r") => false,
+        _ => return Err(malformed()),
+      };
+      let address = hexadecimal(address).ok_or_else(malformed)?;
+      let data = data.as_str().and_then(parse_bits).ok_or_else(malformed)?;
+      memory.push(MemoryAccess { write, address, data });
+    }
+
+    Ok(TraceRecord { step: step as u64, pc, instruction, registers, memory })
+  }
+}
+
+/// A number written as a hexadecimal string, or as a JSON integer.
+fn hexadecimal(json: &Json) -> Option<u64> {
+  match json {
+    Json::Integer(value) => u64::try_from(*value).ok(),
+    Json::String(text) => {
+      let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))?;
+      u64::from_str_radix(&digits.replace('_', ""), 16).ok()
+    }
+    _ => None,
+  }
+}
+
+fn parse_bits(text: &str) -> Option<Bits> {
+  match text.starts_with("0b") {
+    true => Bits::parse_binary(text),
+    false => Bits::parse_hexadecimal(&text.to_ascii_lowercase()),
+  }
+}
+
+/// A register value that is a bit vector, written in hexadecimal or binary with its prefix. Other values, such as
+/// integers and enum members, are compared as they are written.
+fn register_bits(text: &str) -> Option<Bits> {
+  match text.starts_with("0x") || text.starts_with("0X") || text.starts_with("0b") {
+    true => parse_bits(text),
+    false => None,
+  }
+}
+
+// region Writing and reading
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+  Text,
+  /// JSON lines
+  Jsonl,
+}
+
+impl TraceFormat {
+  pub fn parse(text: &str) -> Result<TraceFormat, String> {
+    match text {
+      "text" => Ok(TraceFormat::Text),
+      "jsonl" => Ok(TraceFormat::Jsonl),
+      _ => Err(format!("{} is not a trace format; use text or jsonl", text)),
+    }
+  }
+}
+
+pub struct TraceWriter<W: Write> {
+  output: W,
+  format: TraceFormat,
+}
+
+impl<W: Write> TraceWriter<W> {
+  pub fn new(output: W, format: TraceFormat) -> Self {
+    TraceWriter { output, format }
+  }
+
+  pub fn write(&mut self, record: &TraceRecord) -> std::io::Result<()> {
+    match self.format {
+      TraceFormat::Text => writeln!(self.output, "{}", record),
+      TraceFormat::Jsonl => writeln!(self.output, "{}", record.to_json()),
+    }
+  }
+
+  pub fn flush(&mut self) -> std::io::Result<()> {
+    self.output.flush()
+  }
+}
+
+/// The records of a trace in JSON lines, skipping blank lines.
+pub struct TraceReader<R: BufRead> {
+  input: R,
+  line : usize,
+}
+
+impl<R: BufRead> TraceReader<R> {
+  pub fn new(input: R) -> Self {
+    TraceReader { input, line: 0 }
+  }
+
+  /// The number of the line last read, counting from one.
+  pub fn line(&self) -> usize {
+    self.line
+  }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+  type Item = Result<TraceRecord, String>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let mut text = String::new();
+      self.line += 1;
+      match self.input.read_line(&mut text) {
+        Ok(0) => return None,
+        Ok(_) if text.trim().is_empty() => continue,
+        Ok(_) => {
+          let record = Json::parse(text.trim()).and_then(|json| TraceRecord::from_json(&json));
+          return Some(record.map_err(|error| format!("line {}: {}", self.line, error)));
+        }
+        Err(error) => return Some(Err(format!("line {}: {}", self.line, error))),
+      }
+    }
+  }
+}
+
+// endregion
+
+// region Comparing
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+  /// Registers whose values are not compared, such as counters
+  pub ignore      : HashSet<String>,
+  /// Whether to leave out memory reads
+  pub ignore_reads: bool,
+}
+
+/// Where two traces first differ: the position of the step in each, counting from zero, what differs, and how it reads
+/// in each trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+  pub index: usize,
+  pub what : String,
+  pub left : String,
+  pub right: String,
+}
+
+impl Display for Divergence {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "traces diverge at step {}, {}:\n  < {}\n  > {}", self.index, self.what, self.left, self.right)
+  }
+}
+
+/// Where two traces first differ, or `None` if they agree. Reading either trace may fail.
+pub fn first_divergence(
+  left   : impl IntoIterator<Item = Result<TraceRecord, String>>,
+  right  : impl IntoIterator<Item = Result<TraceRecord, String>>,
+  options: &DiffOptions,
+) -> Result<Option<Divergence>, String> {
+  let mut left = left.into_iter();
+  let mut right = right.into_iter();
+  for index in 0.. {
+    let (a, b) = match (left.next().transpose()?, right.next().transpose()?) {
+      (None, None) => return Ok(None),
+      (Some(a), Some(b)) => (a, b),
+      (a, b) => {
+        let describe = |record: Option<TraceRecord>| match record {
+          Some(record) => format!("#{} continues", record.step),
+          None => "ends".to_string(),
+        };
+        return Ok(Some(Divergence { index, what: "one trace ends".to_string(), left: describe(a), right: describe(b) }));
+      }
+    };
+    if let Some((what, left, right)) = compare(&a, &b, options) {
+      return Ok(Some(Divergence { index, what, left, right }));
+    }
+  }
+  Ok(None)
+}
+
+/// What differs between two records of the same step, and how it reads in each.
+fn compare(a: &TraceRecord, b: &TraceRecord, options: &DiffOptions) -> Option<(String, String, String)> {
+  if let (Some(x), Some(y)) = (a.pc, b.pc) {
+    if x != y {
+      return Some(("pc".to_string(), format!("{:#x}", x), format!("{:#x}", y)));
+    }
+  }
+  if let (Some(x), Some(y)) = (&a.instruction, &b.instruction) {
+    if x != y {
+      return Some(("instruction".to_string(), x.clone(), y.clone()));
+    }
+  }
+
+  let last_values = |record: &TraceRecord| -> BTreeMap<String, String> {
+    record.registers.iter().filter(|(name, _)| !options.ignore.contains(name)).cloned().collect()
+  };
+  let (x, y) = (last_values(a), last_values(b));
+  for name in x.keys().chain(y.keys()) {
+    let (u, v) = (x.get(name), y.get(name));
+    let same = match (u, v) {
+      (Some(u), Some(v)) => same_value(u, v),
+      (u, v) => u == v,
+    };
+    if !same {
+      let show = |value: Option<&String>| value.cloned().unwrap_or_else(|| "not written".to_string());
+      return Some((format!("register {}", name), show(u), show(v)));
+    }
+  }
+
+  let accesses = |record: &TraceRecord| -> Vec<String> {
+    record
+        .memory
+        .iter()
+        .filter(|access| access.write || !options.ignore_reads)
+        .map(|access| format!("mem[{:#x}] {} {}", access.address, if access.write { "<-" } else { "->" }, access.data))
+        .collect()
+  };
+  let (x, y) = (accesses(a), accesses(b));
+  if x != y {
+    let position = x.iter().zip(&y).take_while(|(u, v)| u == v).count();
+    let show = |list: &[String]| list.get(position).cloned().unwrap_or_else(|| "no access".to_string());
+    return Some((format!("memory access {}", position), show(&x), show(&y)));
+  }
+  None
+}
+
+/// Whether two register values are the same, bit vectors being compared zero-extended to the wider of the two.
+fn same_value(x: &str, y: &str) -> bool {
+  match (register_bits(x), register_bits(y)) {
+    (Some(x), Some(y)) => {
+      let width = x.len().max(y.len());
+      x.extend(width, false) == y.extend(width, false)
+    }
+    _ => x == y,
+  }
+}
+
+pub const DIFF_USAGE: &str = "\
+usage: rigging trace-diff [options] <trace> <trace>
+
+Compares two traces in JSON lines, as rigging-sim writes them with --trace-format jsonl, and reports the first step at
+which they differ. The exit status is zero if they agree, one if they differ and two if either cannot be read.
+
+options:
+  --ignore <register>  do not compare <register>; may be given more than once
+  --ignore-reads       do not compare memory reads
+  --help               print this message";
+
+/// Runs `rigging trace-diff` with `arguments`, without the program name and subcommand.
+pub fn diff_main(arguments: impl IntoIterator<Item = String>) -> ExitCode {
+  let mut options = DiffOptions::default();
+  let mut paths = Vec::new();
+  let mut arguments = arguments.into_iter();
+  while let Some(argument) = arguments.next() {
+    match argument.as_str() {
+      "--ignore" => match arguments.next() {
+        Some(register) => {
+          options.ignore.insert(register);
+        }
+        None => {
+          eprintln!("rigging trace-diff: --ignore needs a value\n{}", DIFF_USAGE);
+          return ExitCode::from(2);
+        }
+      },
+      "--ignore-reads" => options.ignore_reads = true,
+      "--help" | "-h" => {
+        println!("{}", DIFF_USAGE);
+        return ExitCode::SUCCESS;
+      }
+      option if option.starts_with('-') && option != "-" => {
+        eprintln!("rigging trace-diff: unknown option {}\n{}", option, DIFF_USAGE);
+        return ExitCode::from(2);
+      }
+      _ => paths.push(argument),
+    }
+  }
+  let [left, right] = paths.as_slice() else {
+    eprintln!("rigging trace-diff: two traces are needed\n{}", DIFF_USAGE);
+    return ExitCode::from(2);
+  };
+
+  let open = |path: &str| -> Result<TraceReader<Box<dyn BufRead>>, String> {
+    let input: Box<dyn BufRead> = match path {
+      "-" => Box::new(std::io::stdin().lock()),
+      path => Box::new(std::io::BufReader::new(std::fs::File::open(path).map_err(|error| format!("{}: {}", path, error))?)),
+    };
+    Ok(TraceReader::new(input))
+  };
+  let result = open(left).and_then(|a| open(right).map(|b| (a, b))).and_then(|(a, b)| {
+    let a = a.map(|record| record.map_err(|error| format!("{}: {}", left, error)));
+    let b = b.map(|record| record.map_err(|error| format!("{}: {}", right, error)));
+    first_divergence(a, b, &options)
+  });
+  match result {
+    Ok(None) => ExitCode::SUCCESS,
+    Ok(Some(divergence)) => {
+      println!("{}", divergence);
+      ExitCode::FAILURE
+    }
+    Err(message) => {
+      eprintln!("rigging trace-diff: {}", message);
+      ExitCode::from(2)
+    }
+  }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(step: u64, pc: u64, registers: &[(&str, &str)]) -> TraceRecord {
+    TraceRecord {
+      step,
+      pc         : Some(pc),
+      instruction: Some("ADDI".to_string()),
+      registers  : registers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+      memory     : vec![MemoryAccess { write: true, address: 0x8000_1000, data: Bits::from_u64(0x1234, 16) }],
+    }
+  }
+
+  #[test]
+  fn round_trips_json_lines() {
+    let original = record(3, 0x8000_0004, &[("x1", "0x05"), ("PC", "0x80000008")]);
+    let text = format!("{}\n\n{}\n", original.to_json(), original.to_json());
+    let records: Vec<TraceRecord> = TraceReader::new(text.as_bytes()).collect::<Result<_, _>>().unwrap();
+    assert_eq!(records, vec![original.clone(), original.clone()]);
+    assert_eq!(original.to_string(), "#3 pc 0x80000004 ADDI\n  x1 <- 0x05\n  PC <- 0x80000008\n  mem[0x80001000] <- 0x1234");
+    assert!(TraceReader::new(&b"{\"pc\":\"0x4\"}"[..]).next().unwrap().is_err());
+  }
+
+  #[test]
+  fn finds_the_first_divergence() {
+    let ok = |records: Vec<TraceRecord>| records.into_iter().map(Ok).collect::<Vec<_>>();
+    let left = vec![record(0, 0, &[("x1", "0x1"), ("x1", "0x2")]), record(1, 4, &[("x2", "0x3")])];
+    let same = vec![record(0, 0, &[("x1", "0x2")]), record(1, 4, &[("x2", "0x3")])];
+    let options = DiffOptions::default();
+    assert_eq!(first_divergence(ok(left.clone()), ok(same), &options), Ok(None));
+
+    let other = vec![record(0, 0, &[("x1", "0x2")]), record(1, 4, &[("x2", "0x4")])];
+    let divergence = first_divergence(ok(left.clone()), ok(other.clone()), &options).unwrap().unwrap();
+    assert_eq!((divergence.index, divergence.what.as_str()), (1, "register x2"));
+    let ignoring = DiffOptions { ignore: HashSet::from(["x2".to_string()]), ..DiffOptions::default() };
+    assert_eq!(first_divergence(ok(left.clone()), ok(other), &ignoring), Ok(None));
+
+    // Bit vectors compare by value, whatever their case and width; other values as written.
+    let model = vec![record(0, 0, &[("x1", "0x00000000000000AB"), ("mode", "Machine")])];
+    let line = r#"{"step":0,"pc":"0x0","instruction":"ADDI","registers":[["x1","0xab"],["mode","Machine"]],"memory":[["w","0x80001000","0x1234"]]}"#;
+    let rtl = TraceRecord::from_json(&Json::parse(line).unwrap()).unwrap();
+    assert_eq!(rtl.registers[0].1, "0xAB");
+    assert_eq!(first_divergence(ok(model.clone()), ok(vec![rtl]), &options), Ok(None));
+    let user = vec![record(0, 0, &[("x1", "0xab"), ("mode", "User")])];
+    let divergence = first_divergence(ok(model), ok(user), &options).unwrap().unwrap();
+    assert_eq!((divergence.what.as_str(), divergence.right.as_str()), ("register mode", "User"));
+
+    let shorter = first_divergence(ok(left.clone()), ok(left[..1].to_vec()), &options).unwrap().unwrap();
+    assert_eq!((shorter.index, shorter.right.as_str()), (1, "ends"));
+  }
+}